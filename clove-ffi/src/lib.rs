@@ -0,0 +1,10 @@
+//! clove-lang as a C library: `libclove.so` (`.dylib`, `.dll`) and
+//! `libclove.a`, with the functions of [`clove_lang::ffi`] declared in
+//! `include/clove.h`.
+//!
+//! ```text
+//! cargo build --release -p clove-ffi
+//! cc app.c -Iinclude -Ltarget/release -lclove
+//! ```
+
+pub use clove_lang::ffi::*;
@@ -0,0 +1,23 @@
+#![no_main]
+
+use arbitrary::Arbitrary;
+use clove_lang::{EnvPolicy, Evaluator, Lexer, Parser, Value};
+use libfuzzer_sys::fuzz_target;
+
+#[derive(Debug, Arbitrary)]
+struct Input<'a> {
+    query: &'a str,
+    document: Value,
+}
+
+fuzz_target!(|input: Input<'_>| {
+    let Ok(mut parser) = Parser::new(Lexer::new(input.query)) else {
+        return;
+    };
+    let Ok(query) = parser.parse_query() else {
+        return;
+    };
+    let _ = Evaluator::new()
+        .with_env_policy(EnvPolicy::DenyAll)
+        .eval_query(&query, input.document);
+});
@@ -0,0 +1,14 @@
+#![no_main]
+
+use clove_lang::{Lexer, Parser, Query};
+use libfuzzer_sys::fuzz_target;
+
+// A printed query parses back to the same tree
+fuzz_target!(|query: Query| {
+    let text = query.to_string();
+    let reparsed = Parser::new(Lexer::new(&text)).and_then(|mut parser| parser.parse_query());
+    match reparsed {
+        Ok(reparsed) => assert_eq!(reparsed, query, "printed as {}", text),
+        Err(e) => panic!("{} does not parse: {}", text, e),
+    }
+});
@@ -0,0 +1,13 @@
+#![no_main]
+
+use clove_lang::{Lexer, Token};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|source: &str| {
+    let mut lexer = Lexer::new(source);
+    while let Ok(token) = lexer.next_token() {
+        if token == Token::Eof {
+            break;
+        }
+    }
+});
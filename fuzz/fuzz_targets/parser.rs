@@ -0,0 +1,10 @@
+#![no_main]
+
+use clove_lang::{Lexer, Parser};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|source: &str| {
+    if let Ok(mut parser) = Parser::new(Lexer::new(source)) {
+        let _ = parser.parse_query();
+    }
+});
@@ -0,0 +1,36 @@
+/*
+ * clove.h - C interface to clove-lang
+ *
+ * Build the library with `cargo build --release -p clove-ffi` and link
+ * against the resulting libclove (shared) or libclove.a (static).
+ *
+ * Ownership:
+ *   - clove_compile() returns a handle; release it with clove_query_free().
+ *   - clove_eval_json() returns a string; release it with clove_free_result().
+ *   - clove_last_error() returns a borrowed string, valid until the next
+ *     clove_* call on the same thread. Do not free it.
+ *
+ * All input strings must be NUL-terminated UTF-8. On failure, functions
+ * returning pointers return NULL and record a per-thread error message.
+ */
+
+#ifndef CLOVE_H
+#define CLOVE_H
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct CloveQuery CloveQuery;
+
+CloveQuery *clove_compile(const char *query);
+char *clove_eval_json(const CloveQuery *query, const char *json);
+void clove_free_result(char *result);
+void clove_query_free(CloveQuery *query);
+const char *clove_last_error(void);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* CLOVE_H */
@@ -14,6 +14,11 @@
 //! - **[statements]** - Pipeline statements (filter, transform, scope definition)
 //! - **[query]** - Complete query structure with UDFs and output
 //! - **[udf]** - User-defined function definitions
+//! - **[patterns]** - Patterns of `match` expressions
+//! - **[builder]** - Building queries in code with [`QueryBuilder`]
+//! - **[format](mod@format)** - Printing queries back as source, through `Display`
+//! - **arbitrary** - Random queries the parser accepts (`arbitrary` feature)
+//! - **serialize** - Versioned JSON serialization of queries (`serde` feature)
 //!
 //! ## Quick Start
 //!
@@ -89,13 +94,24 @@ pub mod operators;
 pub mod statements;
 pub mod query;
 pub mod udf;
+pub mod patterns;
+pub mod builder;
+pub mod format;
+#[cfg(feature = "arbitrary")]
+mod arbitrary;
+#[cfg(feature = "serde")]
+pub mod serialize;
 
-pub use tokens::Token;
-pub use expressions::Expr;
+pub use tokens::{Token, TokenClass};
+pub use expressions::{EnvVarType, Expr};
 pub use operators::{BinOp};
 pub use statements::Statement;
 pub use query::Query;
 pub use udf::UDF;
+pub use patterns::{MatchArm, Pattern};
+pub use builder::QueryBuilder;
+#[cfg(feature = "serde")]
+pub use serialize::{AstDecodeError, AST_FORMAT_VERSION};
 
 // #[derive(Debug, Clone)]
 // pub enum Expr2 {
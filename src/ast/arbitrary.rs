@@ -0,0 +1,314 @@
+//! `arbitrary::Arbitrary` for queries (feature `arbitrary`).
+//!
+//! Deriving it would mostly make trees no source parses to: a field name
+//! outside an access, a scope named like a lambda's item, a pattern
+//! binding a name twice. The trees made here are ones the parser could
+//! have produced, so they can drive property tests such as printing a
+//! query and parsing it back (see [`crate::ast::format`]). Names come from
+//! small fixed sets, kept apart so that `@name` means the same thing
+//! wherever it is printed, and nesting is kept well inside
+//! [`MAX_NESTING_DEPTH`](crate::parser::MAX_NESTING_DEPTH).
+
+use arbitrary::{Arbitrary, Result, Unstructured};
+
+use crate::ast::{BinOp, EnvVarType, Expr, MatchArm, Pattern, Query, Statement, UDF};
+
+/// Levels of nested expressions below a statement
+const DEPTH: u32 = 4;
+
+/// Names of scopes
+const SCOPES: &[&str] = &["items", "user", "total"];
+
+/// Names of lambda items, foreach elements and pattern bindings
+const BINDERS: &[&str] = &["x", "item", "row"];
+
+/// Field names, including ones that need quoting
+const KEYS: &[&str] = &["name", "price", "_id", "größe", "unit price", "null", "0", ""];
+
+/// Method names, including keywords, which are names after a `.`
+const METHODS: &[&str] = &["map", "sum", "or", "and", "len"];
+
+/// Names of UDFs and host functions
+const FUNCTIONS: &[&str] = &["double", "lookup", "f"];
+
+/// Names of environment variables (`meta` would be `$meta`)
+const ENV_VARS: &[&str] = &["HOME", "PORT", "_x"];
+
+const OPERATORS: &[BinOp] = &[
+    BinOp::Equal,
+    BinOp::NotEqual,
+    BinOp::ApproxEqual,
+    BinOp::LessThan,
+    BinOp::GreaterThan,
+    BinOp::LessEqual,
+    BinOp::GreaterEqual,
+    BinOp::Add,
+    BinOp::Subtract,
+    BinOp::Multiply,
+    BinOp::Divide,
+    BinOp::Modulo,
+    BinOp::And,
+    BinOp::Or,
+    BinOp::NullCoalesce,
+];
+
+const ENV_VAR_TYPES: &[EnvVarType] =
+    &[EnvVarType::Int, EnvVarType::Float, EnvVarType::Bool, EnvVarType::String];
+
+impl<'a> Arbitrary<'a> for Query {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        let mut generator = Generator { u, binders: Vec::new() };
+        let udfs = generator.list(2, |g| g.udf())?;
+        let statements = generator.list(4, |g| g.statement(DEPTH))?;
+        let output = match generator.u.arbitrary()? {
+            true => Some(generator.expr(DEPTH)?),
+            false => None,
+        };
+        Ok(Query { udfs, statements, output })
+    }
+}
+
+impl<'a> Arbitrary<'a> for Statement {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Generator { u, binders: Vec::new() }.statement(DEPTH)
+    }
+}
+
+impl<'a> Arbitrary<'a> for Expr {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Generator { u, binders: Vec::new() }.expr(DEPTH)
+    }
+}
+
+struct Generator<'u, 'a> {
+    u: &'u mut Unstructured<'a>,
+    /// Names `@name` refers to as a lambda's item, a foreach element or a
+    /// pattern's binding, innermost last
+    binders: Vec<String>,
+}
+
+impl Generator<'_, '_> {
+    /// Up to `max` items made by `item`
+    fn list<T>(&mut self, max: usize, mut item: impl FnMut(&mut Self) -> Result<T>) -> Result<Vec<T>> {
+        let len = self.u.int_in_range(0..=max)?;
+        (0..len).map(|_| item(self)).collect()
+    }
+
+    fn name(&mut self, names: &[&str]) -> Result<String> {
+        Ok(self.u.choose(names)?.to_string())
+    }
+
+    /// Run `make` with `names` bound for `@name`
+    fn binding<T>(&mut self, names: Vec<String>, make: impl FnOnce(&mut Self) -> Result<T>) -> Result<T> {
+        let outer = self.binders.len();
+        self.binders.extend(names);
+        let made = make(self);
+        self.binders.truncate(outer);
+        made
+    }
+
+    /// `&name:arity := body`, or with named parameters when some have
+    /// defaults or extra arguments are collected
+    fn udf(&mut self) -> Result<UDF> {
+        let name = self.name(FUNCTIONS)?;
+        let arity = self.u.int_in_range(0..=3)?;
+        let defaults = self.u.int_in_range(0..=arity)?;
+        let defaults = (0..defaults).map(|_| self.expr(1)).collect::<Result<_>>()?;
+        let variadic = self.u.arbitrary()?;
+        let body = self.statement(DEPTH)?;
+        Ok(UDF { name, arity, defaults, variadic, body })
+    }
+
+    fn statement(&mut self, depth: u32) -> Result<Statement> {
+        let choices = if depth == 0 { 6 } else { 8 };
+        Ok(match self.u.choose_index(choices)? {
+            0 => Statement::Filter(self.expr(depth)?),
+            1 => {
+                let target = self.path(depth, false)?;
+                let value = match self.u.arbitrary()? {
+                    true => Expr::Filter(Box::new(self.expr(depth)?)),
+                    false => self.expr(depth)?,
+                };
+                Statement::Transform { target, value }
+            }
+            2 => Statement::ScopeDefinition { name: self.name(SCOPES)?, path: self.expr(depth)? },
+            3 => Statement::LiveScopeDefinition { name: self.name(SCOPES)?, path: self.expr(depth)? },
+            4 => Statement::Delete(self.path(depth, true)?),
+            5 => Statement::Access(self.expr(depth)?),
+            6 => {
+                let condition = self.expr(depth - 1)?;
+                Statement::When { condition, statements: self.block(depth - 1)? }
+            }
+            _ => {
+                // The path must start with `$` or `@name`
+                let mut path = match self.u.arbitrary()? {
+                    true => Expr::Root,
+                    false => Expr::ScopeRef(self.name(SCOPES)?),
+                };
+                for _ in 0..self.u.int_in_range(0..=2)? {
+                    let key = Expr::Key(self.name(KEYS)?);
+                    path = Expr::Access { object: Box::new(path), key: Box::new(key) };
+                }
+                let name = self.name(BINDERS)?;
+                let statements = self.binding(vec![name.clone()], |g| g.block(depth - 1))?;
+                Statement::ForEach { path, name, statements }
+            }
+        })
+    }
+
+    /// The statements of a `when` or `foreach`, of which there is at least one
+    fn block(&mut self, depth: u32) -> Result<Vec<Statement>> {
+        let mut statements = vec![self.statement(depth)?];
+        statements.extend(self.list(2, |g| g.statement(depth))?);
+        Ok(statements)
+    }
+
+    /// The target of a transform, or of a delete if `wildcards`
+    fn path(&mut self, depth: u32, wildcards: bool) -> Result<Expr> {
+        let mut path = match self.u.choose_index(4)? {
+            0 => Expr::ScopeRef(self.name(SCOPES)?),
+            1 => Expr::LambdaParam,
+            2 if !self.binders.is_empty() => Expr::LambdaVar(self.u.choose(&self.binders)?.clone()),
+            _ => Expr::Root,
+        };
+        for _ in 0..self.u.int_in_range(0..=3)? {
+            let key = match self.u.choose_index(4)? {
+                0 => Expr::Integer(self.u.int_in_range(-3..=3)?),
+                1 if wildcards => Expr::Wildcard,
+                2 if depth > 0 => self.expr(depth - 1)?,
+                _ => Expr::Key(self.name(KEYS)?),
+            };
+            path = Expr::Access { object: Box::new(path), key: Box::new(key) };
+        }
+        Ok(path)
+    }
+
+    fn expr(&mut self, depth: u32) -> Result<Expr> {
+        if depth == 0 {
+            return self.leaf();
+        }
+        let depth = depth - 1;
+        let boxed = |g: &mut Self| g.expr(depth).map(Box::new);
+        Ok(match self.u.choose_index(12)? {
+            0 => Expr::Access { object: boxed(self)?, key: Box::new(self.key(depth)?) },
+            1 => Expr::ExistenceCheck(boxed(self)?),
+            2 => Expr::MethodCall {
+                object: boxed(self)?,
+                method: self.name(METHODS)?,
+                args: self.list(3, |g| g.expr(depth))?,
+            },
+            3 => Expr::UDFCall { name: self.name(FUNCTIONS)?, args: self.list(3, |g| g.expr(depth))? },
+            4 => Expr::HostCall { name: self.name(FUNCTIONS)?, args: self.list(3, |g| g.expr(depth))? },
+            5 | 6 => {
+                let op = *self.u.choose(OPERATORS)?;
+                Expr::BinaryOp { op, left: boxed(self)?, right: boxed(self)? }
+            }
+            7 => {
+                let param = self.name(BINDERS)?;
+                let body = self.binding(vec![param.clone()], boxed)?;
+                Expr::Lambda { param, body }
+            }
+            8 => Expr::Try { body: boxed(self)?, fallback: boxed(self)? },
+            9 => {
+                let subject = boxed(self)?;
+                let mut arms = vec![self.arm(depth)?];
+                arms.extend(self.list(2, |g| g.arm(depth))?);
+                Expr::Match { subject, arms }
+            }
+            10 => Expr::Object(self.list(3, |g| Ok((g.object_key()?, g.expr(depth)?)))?),
+            _ => Expr::Array(self.list(3, |g| g.expr(depth))?),
+        })
+    }
+
+    fn leaf(&mut self) -> Result<Expr> {
+        Ok(match self.u.choose_index(15)? {
+            0 => self.literal()?,
+            1 => Expr::Root,
+            2 => Expr::OriginalRoot,
+            3 => Expr::ScopeRef(self.name(SCOPES)?),
+            4 => Expr::LambdaParam,
+            5 => Expr::OuterLambdaParam(self.u.int_in_range(1..=2)?),
+            6 => Expr::ArgRef(self.u.int_in_range(1..=3)?),
+            7 if !self.binders.is_empty() => Expr::LambdaVar(self.u.choose(&self.binders)?.clone()),
+            8 => Expr::EnvVar(self.name(ENV_VARS)?),
+            9 => Expr::TypedEnvVar { name: self.name(ENV_VARS)?, ty: *self.u.choose(ENV_VAR_TYPES)? },
+            10 => Expr::Meta,
+            _ => self.literal()?,
+        })
+    }
+
+    /// A literal the parser folds to one node: not NaN, which is never
+    /// equal to itself, nor the smallest integer, which is written as a
+    /// subtraction
+    fn literal(&mut self) -> Result<Expr> {
+        Ok(match self.u.choose_index(5)? {
+            0 => Expr::Integer(self.u.int_in_range(i64::MIN + 1..=i64::MAX)?),
+            1 => match f64::arbitrary(self.u)? {
+                n if n.is_nan() => Expr::Float(0.5),
+                n => Expr::Float(n),
+            },
+            2 => Expr::String(self.u.arbitrary()?),
+            3 => Expr::Boolean(self.u.arbitrary()?),
+            _ => Expr::Null,
+        })
+    }
+
+    /// The key of an access: a field name, or an expression computing one
+    fn key(&mut self, depth: u32) -> Result<Expr> {
+        Ok(match self.u.choose_index(3)? {
+            0 => Expr::Key(self.name(KEYS)?),
+            1 => Expr::Key(self.u.arbitrary()?),
+            _ => self.expr(depth)?,
+        })
+    }
+
+    fn object_key(&mut self) -> Result<String> {
+        match self.u.arbitrary()? {
+            true => self.name(KEYS),
+            false => self.u.arbitrary(),
+        }
+    }
+
+    fn arm(&mut self, depth: u32) -> Result<MatchArm> {
+        let mut names = Vec::new();
+        let pattern = self.pattern(depth, &mut names)?;
+        self.binding(names, |g| {
+            let guard = match g.u.arbitrary()? {
+                true => Some(g.expr(depth)?),
+                false => None,
+            };
+            Ok(MatchArm { pattern, guard, body: g.expr(depth)? })
+        })
+    }
+
+    /// A pattern, adding the names it binds to `names`; no name is bound
+    /// twice in one pattern
+    fn pattern(&mut self, depth: u32, names: &mut Vec<String>) -> Result<Pattern> {
+        let choices = if depth == 0 { 3 } else { 5 };
+        Ok(match self.u.choose_index(choices)? {
+            0 => Pattern::Wildcard,
+            1 => {
+                let name = self.name(BINDERS)?;
+                if names.contains(&name) {
+                    Pattern::Wildcard
+                } else {
+                    names.push(name.clone());
+                    Pattern::Bind(name)
+                }
+            }
+            2 => Pattern::Literal(self.literal()?),
+            3 => {
+                let len = self.u.int_in_range(0..=2)?;
+                let entries = (0..len)
+                    .map(|_| Ok((self.object_key()?, self.pattern(depth - 1, names)?)))
+                    .collect::<Result<_>>()?;
+                Pattern::Object(entries)
+            }
+            _ => {
+                let len = self.u.int_in_range(0..=2)?;
+                let items = (0..len).map(|_| self.pattern(depth - 1, names)).collect::<Result<_>>()?;
+                Pattern::Array { items, rest: self.u.arbitrary()? }
+            }
+        })
+    }
+}
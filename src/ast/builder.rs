@@ -0,0 +1,380 @@
+//! Building queries in code instead of formatting query strings.
+//!
+//! [`QueryBuilder`] assembles a [`Query`] statement by statement from
+//! expressions made with the constructors on [`Expr`]. Rust values become
+//! literals through `Expr::from`, so text from outside the program is always
+//! a string value and can never be read as query syntax. For code that does
+//! produce query text, [`quote_string`] and [`quote_key`] escape it.
+//!
+//! # Examples
+//!
+//! ```
+//! use clove_lang::{clove_value, Evaluator, Expr, QueryBuilder};
+//!
+//! // Untrusted input stays a plain string
+//! let status = r#"active") | !($[secret]"#;
+//!
+//! let query = QueryBuilder::new()
+//!     .filter(Expr::root().get("status").equals(status))
+//!     .transform(Expr::root().get("items"), Expr::item().get("price") * 2)
+//!     .output(Expr::root().get("items").method("sum", []))
+//!     .build();
+//!
+//! let doc = clove_value!({"status": "active", "items": [{"price": 5}, {"price": 7}]});
+//! assert_eq!(Evaluator::new().eval_query(&query, doc).unwrap(), clove_value!(null));
+//!
+//! let doc = clove_value!({"status": status, "items": [{"price": 5}, {"price": 7}]});
+//! assert_eq!(Evaluator::new().eval_query(&query, doc).unwrap(), clove_value!(24));
+//! ```
+
+use std::ops::{Add, Div, Mul, Rem, Sub};
+
+use crate::ast::{BinOp, Expr, MatchArm, Query, Statement, UDF};
+use crate::value::Value;
+
+/// Builds a [`Query`] one pipeline stage at a time.
+///
+/// Stages run in the order they are added, as in `$ | stage | stage | ...`.
+/// Targets of [`transform`](Self::transform) and [`delete`](Self::delete)
+/// must be literal paths such as `Expr::root().get("items").index(0)`;
+/// other targets fail when the query is evaluated, as they would if parsed.
+#[derive(Debug, Clone, Default)]
+pub struct QueryBuilder {
+    udfs: Vec<UDF>,
+    statements: Vec<Statement>,
+    output: Option<Expr>,
+}
+
+impl QueryBuilder {
+    /// An empty pipeline, which returns the document unchanged
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Define a UDF callable with [`Expr::call`] (`&name:arity := body`)
+    pub fn udf(mut self, name: impl Into<String>, arity: usize, body: Statement) -> Self {
+        self.udfs.push(UDF { name: name.into(), arity, defaults: Vec::new(), variadic: false, body });
+        self
+    }
+
+    /// Keep the document only if `condition` holds (`?(condition)`)
+    pub fn filter(self, condition: impl Into<Expr>) -> Self {
+        self.statement(Statement::Filter(condition.into()))
+    }
+
+    /// Set `target` to `value` (`~(target := value)`). A `value` using
+    /// [`Expr::item`] maps the array at `target`, and one made with
+    /// [`Expr::filter`] filters it.
+    pub fn transform(self, target: Expr, value: impl Into<Expr>) -> Self {
+        self.statement(Statement::Transform { target, value: value.into() })
+    }
+
+    /// Remove `target` from the document (`-(target)`)
+    pub fn delete(self, target: Expr) -> Self {
+        self.statement(Statement::Delete(target))
+    }
+
+    /// Bind `value` to the scope `@name` for later stages (`@name := value`)
+    pub fn scope(self, name: impl Into<String>, value: impl Into<Expr>) -> Self {
+        self.statement(Statement::ScopeDefinition { name: name.into(), path: value.into() })
+    }
+
+    /// Run the stages of `then` only if `condition` holds
+    /// (`when (condition) { ... }`)
+    pub fn when(self, condition: impl Into<Expr>, then: QueryBuilder) -> Self {
+        self.statement(Statement::When { condition: condition.into(), statements: then.statements })
+    }
+
+    /// Run the stages of `body` on each element of the array at `path`,
+    /// which they refer to as [`Expr::var`] (`foreach path as @name { ... }`)
+    pub fn foreach(self, path: Expr, name: impl Into<String>, body: QueryBuilder) -> Self {
+        self.statement(Statement::ForEach { path, name: name.into(), statements: body.statements })
+    }
+
+    /// Append any statement
+    pub fn statement(mut self, statement: Statement) -> Self {
+        self.statements.push(statement);
+        self
+    }
+
+    /// Return `expr` instead of the document (`!(expr)`)
+    pub fn output(mut self, expr: impl Into<Expr>) -> Self {
+        self.output = Some(expr.into());
+        self
+    }
+
+    /// The assembled query
+    pub fn build(self) -> Query {
+        Query {
+            udfs: self.udfs,
+            statements: self.statements,
+            output: self.output,
+        }
+    }
+}
+
+impl Expr {
+    /// The document (`$`)
+    pub fn root() -> Expr {
+        Expr::Root
+    }
+
+    /// The current element of a lambda or per-item transform (`@`)
+    pub fn item() -> Expr {
+        Expr::LambdaParam
+    }
+
+    /// The item of the lambda `level` lambdas out from the current one
+    /// (`@@` is `Expr::outer_item(1)`)
+    pub fn outer_item(level: usize) -> Expr {
+        Expr::OuterLambdaParam(level)
+    }
+
+    /// A scope reference (`@name`)
+    pub fn scope(name: impl Into<String>) -> Expr {
+        Expr::ScopeRef(name.into())
+    }
+
+    /// A UDF argument (`@1` is `Expr::arg(1)`)
+    pub fn arg(position: usize) -> Expr {
+        Expr::ArgRef(position)
+    }
+
+    /// A lambda naming its item `param` (`param -> body`), which `body`
+    /// refers to as [`Expr::var`]
+    pub fn lambda(param: impl Into<String>, body: impl Into<Expr>) -> Expr {
+        Expr::Lambda { param: param.into(), body: Box::new(body.into()) }
+    }
+
+    /// The item of the enclosing lambda or foreach named `name` (`@name`)
+    pub fn var(name: impl Into<String>) -> Expr {
+        Expr::LambdaVar(name.into())
+    }
+
+    /// A match expression: the body of the first of `arms` whose pattern
+    /// matches `subject`, or null
+    pub fn match_on(subject: impl Into<Expr>, arms: impl IntoIterator<Item = MatchArm>) -> Expr {
+        Expr::Match { subject: Box::new(subject.into()), arms: arms.into_iter().collect() }
+    }
+
+    /// A call of the host function `name` (`ext::name(args)`)
+    pub fn host_call(name: impl Into<String>, args: impl IntoIterator<Item = Expr>) -> Expr {
+        Expr::HostCall { name: name.into(), args: args.into_iter().collect() }
+    }
+
+    /// `body`, or `fallback` if evaluating `body` fails (`try body catch fallback`)
+    pub fn try_or(body: impl Into<Expr>, fallback: impl Into<Expr>) -> Expr {
+        Expr::Try { body: Box::new(body.into()), fallback: Box::new(fallback.into()) }
+    }
+
+    /// An environment variable (`$NAME`)
+    pub fn env(name: impl Into<String>) -> Expr {
+        Expr::EnvVar(name.into())
+    }
+
+    /// An array literal
+    pub fn array(items: impl IntoIterator<Item = Expr>) -> Expr {
+        Expr::Array(items.into_iter().collect())
+    }
+
+    /// An object literal
+    pub fn object<K: Into<String>>(entries: impl IntoIterator<Item = (K, Expr)>) -> Expr {
+        Expr::Object(entries.into_iter().map(|(key, value)| (key.into(), value)).collect())
+    }
+
+    /// An array filter for [`QueryBuilder::transform`] (`?(condition)`)
+    pub fn filter(condition: impl Into<Expr>) -> Expr {
+        Expr::Filter(Box::new(condition.into()))
+    }
+
+    /// A UDF call (`&name[args...]`)
+    pub fn call(name: impl Into<String>, args: impl IntoIterator<Item = Expr>) -> Expr {
+        Expr::UDFCall { name: name.into(), args: args.into_iter().collect() }
+    }
+
+    /// The field `key` of this value (`[key]`)
+    pub fn get(self, key: impl Into<String>) -> Expr {
+        self.at(Expr::Key(key.into()))
+    }
+
+    /// The element at `index` of this array, negative counting from the end
+    /// (`[index]`)
+    pub fn index(self, index: i64) -> Expr {
+        self.at(Expr::Integer(index))
+    }
+
+    /// The entry named by a computed key (`[key]`)
+    pub fn at(self, key: Expr) -> Expr {
+        Expr::Access { object: Box::new(self), key: Box::new(key) }
+    }
+
+    /// Whether this value exists and is non-empty (`[?]`)
+    pub fn exists(self) -> Expr {
+        Expr::ExistenceCheck(Box::new(self))
+    }
+
+    /// A method call on this value (`.method(args...)`)
+    pub fn method(self, method: impl Into<String>, args: impl IntoIterator<Item = Expr>) -> Expr {
+        Expr::MethodCall {
+            object: Box::new(self),
+            method: method.into(),
+            args: args.into_iter().collect(),
+        }
+    }
+
+    /// `self op right`
+    pub fn binary(self, op: BinOp, right: impl Into<Expr>) -> Expr {
+        Expr::BinaryOp { op, left: Box::new(self), right: Box::new(right.into()) }
+    }
+
+    /// `self == right`
+    pub fn equals(self, right: impl Into<Expr>) -> Expr {
+        self.binary(BinOp::Equal, right)
+    }
+
+    /// `self != right`
+    pub fn not_equals(self, right: impl Into<Expr>) -> Expr {
+        self.binary(BinOp::NotEqual, right)
+    }
+
+    /// `self ~= right`
+    pub fn approx_equals(self, right: impl Into<Expr>) -> Expr {
+        self.binary(BinOp::ApproxEqual, right)
+    }
+
+    /// `self < right`
+    pub fn lt(self, right: impl Into<Expr>) -> Expr {
+        self.binary(BinOp::LessThan, right)
+    }
+
+    /// `self <= right`
+    pub fn le(self, right: impl Into<Expr>) -> Expr {
+        self.binary(BinOp::LessEqual, right)
+    }
+
+    /// `self > right`
+    pub fn gt(self, right: impl Into<Expr>) -> Expr {
+        self.binary(BinOp::GreaterThan, right)
+    }
+
+    /// `self >= right`
+    pub fn ge(self, right: impl Into<Expr>) -> Expr {
+        self.binary(BinOp::GreaterEqual, right)
+    }
+
+    /// `self and right`
+    pub fn and(self, right: impl Into<Expr>) -> Expr {
+        self.binary(BinOp::And, right)
+    }
+
+    /// `self or right`
+    pub fn or(self, right: impl Into<Expr>) -> Expr {
+        self.binary(BinOp::Or, right)
+    }
+
+    /// `self ?? right`
+    pub fn coalesce(self, right: impl Into<Expr>) -> Expr {
+        self.binary(BinOp::NullCoalesce, right)
+    }
+}
+
+/// The literal expression that evaluates to the value
+impl<T: Into<Value>> From<T> for Expr {
+    fn from(value: T) -> Self {
+        match value.into() {
+            Value::Null => Expr::Null,
+            Value::Boolean(b) => Expr::Boolean(b),
+            Value::Integer(n) => Expr::Integer(n),
+            Value::Float(n) => Expr::Float(n),
+            Value::String(s) => Expr::String(s),
+            // There is no bytes literal, so decode one from base64
+            Value::Bytes(bytes) => Expr::MethodCall {
+                object: Box::new(Expr::String(crate::value::encode_base64(&bytes))),
+                method: "base64_decode".to_string(),
+                args: Vec::new(),
+            },
+            Value::Array(items) => Expr::Array(items.into_iter().map(Expr::from).collect()),
+            Value::Object(map) => {
+                let mut entries: Vec<(String, Expr)> =
+                    map.into_iter().map(|(key, value)| (key, Expr::from(value))).collect();
+                entries.sort_by(|a, b| a.0.cmp(&b.0));
+                Expr::Object(entries)
+            }
+        }
+    }
+}
+
+macro_rules! arithmetic_ops {
+    ($($trait:ident $method:ident $op:ident),*) => {$(
+        impl<T: Into<Expr>> $trait<T> for Expr {
+            type Output = Expr;
+
+            fn $method(self, right: T) -> Expr {
+                self.binary(BinOp::$op, right)
+            }
+        }
+    )*};
+}
+
+arithmetic_ops!(
+    Add add Add,
+    Sub sub Subtract,
+    Mul mul Multiply,
+    Div div Divide,
+    Rem rem Modulo
+);
+
+/// `s` as a Clove string literal, with quotes and backslashes escaped
+///
+/// # Examples
+///
+/// ```
+/// use clove_lang::ast::builder::quote_string;
+///
+/// assert_eq!(quote_string(r#"say "hi"\n"#), r#""say \"hi\"\\n""#);
+/// ```
+pub fn quote_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// An accessor for the field `name`: `[name]` when it reads as an
+/// identifier, `["quoted name"]` otherwise
+///
+/// # Examples
+///
+/// ```
+/// use clove_lang::ast::builder::quote_key;
+///
+/// assert_eq!(quote_key("price"), "[price]");
+/// assert_eq!(quote_key("unit price"), r#"["unit price"]"#);
+/// assert_eq!(quote_key("null"), r#"["null"]"#);
+/// ```
+pub fn quote_key(name: &str) -> String {
+    if is_identifier(name) {
+        format!("[{}]", name)
+    } else {
+        format!("[{}]", quote_string(name))
+    }
+}
+
+/// Whether `name` lexes as an identifier rather than a keyword or literal,
+/// so it can be written bare as a key
+pub(crate) fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(|c| c.is_alphabetic() || c == '_')
+        && chars.all(|c| c.is_alphanumeric() || c == '_')
+        && !matches!(name, "true" | "false" | "null" | "and" | "or")
+}
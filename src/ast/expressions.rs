@@ -1,10 +1,11 @@
-use crate::ast::BinOp;
+use crate::ast::{BinOp, MatchArm};
 
 /// Abstract Syntax Tree node representing a parsed expression.
 ///
 /// The AST is the internal representation of a query after parsing.
 /// It captures the structure and meaning of the query for evaluation.
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Expr {
     // Literals
     /// Literal floating point number
@@ -40,6 +41,10 @@ pub enum Expr {
     // References
     /// Root document reference (`$`)
     Root,
+
+    /// The document the query started from (`$$`), unchanged by earlier
+    /// stages
+    OriginalRoot,
     
     /// Scope reference (`@name`)
     ///
@@ -51,9 +56,22 @@ pub enum Expr {
     /// Refers to the current item in a lambda or transform context.
     LambdaParam,
     
+    /// Item of an enclosing lambda (`@@`, `@@@`, etc.)
+    ///
+    /// The number is how many lambdas out: `@@` is `OuterLambdaParam(1)`,
+    /// the item of the lambda around the one `@` belongs to.
+    OuterLambdaParam(usize),
+
     /// UDF argument reference (`@1`, `@2`, etc.)
     ArgRef(usize),
 
+    /// Named lambda parameter (`@item` inside `item -> ...`), or a name
+    /// bound by a `match` pattern
+    ///
+    /// Unlike `@`, it still refers to its own lambda's item inside nested
+    /// lambdas.
+    LambdaVar(String),
+
 
     /// Environment variable reference
     ///
@@ -65,11 +83,42 @@ pub enum Expr {
     /// ```
     EnvVar(String),
 
+    /// Environment variable reference converted to a type (`$NAME:type`)
+    ///
+    /// # Examples
+    /// ```text
+    /// $PORT:int       // TypedEnvVar { name: "PORT", ty: Int }
+    /// $RATIO:float
+    /// $DEBUG:bool
+    /// ```
+    TypedEnvVar {
+        name: String,
+        ty: EnvVarType,
+    },
+
+    /// Metadata about the current record, supplied by the host (`$meta`)
+    ///
+    /// # Examples
+    /// ```text
+    /// $meta[line]     // record number when reading NDJSON
+    /// $meta[file]     // source file name
+    /// ```
+    Meta,
+
     // Keys - field/property names in access expressions
     /// Field or property name used in access expressions.
     /// Transformed from Token::Identifier during parsing.
     /// Only appears as the `key` in `Expr::Access`.
     Key(String),
+
+    /// Every element of an array or value of an object (`[*]`).
+    /// Only appears as the `key` in `Expr::Access`, within a delete target.
+    ///
+    /// # Example
+    /// ```text
+    /// -($[users][*][password])
+    /// ```
+    Wildcard,
     
     // Access
     /// Field or index access
@@ -96,6 +145,55 @@ pub enum Expr {
     /// Used in transforms: ~($[items] := ?(@[price] > 100))
     Filter(Box<Expr>),
 
+    /// Lambda with a named parameter
+    ///
+    /// Binds the current item (`@`) to `param` while evaluating `body`.
+    ///
+    /// # Example
+    /// ```text
+    /// $[orders].map(order -> @order[items].map(@[qty] * @order[rate]))
+    /// ```
+    Lambda {
+        param: String,
+        body: Box<Expr>,
+    },
+
+    /// Structural pattern matching: the body of the first arm whose
+    /// pattern matches `subject` (and whose guard holds), or null if none
+    /// does
+    ///
+    /// # Example
+    /// ```text
+    /// match $[event] { {"type": "click", "x": x} => x, _ => null }
+    /// ```
+    Match {
+        subject: Box<Expr>,
+        arms: Vec<MatchArm>,
+    },
+
+    /// Call of a function supplied by the host (`ext::name(args)`), which
+    /// may have side effects
+    ///
+    /// # Example
+    /// ```text
+    /// ext::lookup_user($[id])
+    /// ```
+    HostCall {
+        name: String,
+        args: Vec<Expr>,
+    },
+
+    /// `body`, or `fallback` if evaluating `body` fails
+    ///
+    /// # Example
+    /// ```text
+    /// $[rows].map(try @[price] * 2 catch null)
+    /// ```
+    Try {
+        body: Box<Expr>,
+        fallback: Box<Expr>,
+    },
+
     // Operations
     /// Binary operation (arithmetic, comparison, logical)
     BinaryOp {
@@ -145,3 +243,40 @@ pub enum Expr {
     /// ```
     Array(Vec<Expr>),
 }
+
+/// Target type of a typed environment variable lookup (`$NAME:type`).
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum EnvVarType {
+    /// `:int` - parsed as a 64-bit integer
+    Int,
+    /// `:float` - parsed as a float
+    Float,
+    /// `:bool` - `true`/`1`/`yes`/`on` or `false`/`0`/`no`/`off` (case-insensitive)
+    Bool,
+    /// `:string` - the raw value (same as an untyped lookup)
+    String,
+}
+
+impl EnvVarType {
+    /// Parse a type name as written after `$NAME:`
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "int" => Some(EnvVarType::Int),
+            "float" => Some(EnvVarType::Float),
+            "bool" => Some(EnvVarType::Bool),
+            "string" => Some(EnvVarType::String),
+            _ => None,
+        }
+    }
+
+    /// The type name as written in queries
+    pub fn name(&self) -> &'static str {
+        match self {
+            EnvVarType::Int => "int",
+            EnvVarType::Float => "float",
+            EnvVarType::Bool => "bool",
+            EnvVarType::String => "string",
+        }
+    }
+}
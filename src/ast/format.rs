@@ -0,0 +1,476 @@
+//! Printing queries back as Clove source.
+//!
+//! [`Query`], [`Statement`], [`Expr`], [`Pattern`] and [`UDF`] implement
+//! `Display`, writing source that parses back to the same tree, so tools
+//! can rewrite a parsed or built query and hand the result on as text.
+//! Parentheses are added only where the grammar needs them: `(1 + 2) * 3`
+//! keeps its parentheses, `1 + 2 * 3` gets none.
+//!
+//! # Examples
+//!
+//! ```
+//! use clove_lang::{Lexer, Parser};
+//!
+//! let source = "$|?($.n>1 and ($.m+1)*2<9)|!({'total': $.n})";
+//! let query = Parser::new(Lexer::new(source)).unwrap().parse_query().unwrap();
+//! let text = query.to_string();
+//! assert_eq!(text, "$ | ?($[n] > 1 and ($[m] + 1) * 2 < 9) | !({total: $[n]})");
+//! assert_eq!(Parser::new(Lexer::new(&text)).unwrap().parse_query().unwrap(), query);
+//! ```
+//!
+//! Parsing drops layout, and spelling that means the same as another
+//! (`$.name` and `$[name]`, `"a"` and `'a'`, `!(a: 1)` and `!({a: 1})`), so
+//! those come back in one form. Trees only [`Query::optimize`] or code can
+//! make have no source of their own, and print as near as the syntax
+//! allows: a hoisted scope as an ordinary scope definition, NaN as
+//! `(1e999 - 1e999)` and the smallest integer as
+//! `(-9223372036854775807 - 1)`.
+
+use std::fmt;
+
+use crate::ast::builder::{is_identifier, quote_key, quote_string};
+use crate::ast::{BinOp, Expr, MatchArm, Pattern, Query, Statement, UDF};
+
+// How tightly each kind of expression binds, loosest first. Lambdas and
+// `try` take everything to their right, so they bind loosest of all.
+const OPEN: u8 = 0;
+const COALESCE: u8 = 1;
+const OR: u8 = 2;
+const AND: u8 = 3;
+const COMPARISON: u8 = 4;
+const ADDITIVE: u8 = 5;
+const MULTIPLICATIVE: u8 = 6;
+const POSTFIX: u8 = 7;
+
+impl fmt::Display for Query {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for udf in &self.udfs {
+            writeln!(f, "{}", udf)?;
+        }
+        f.write_str("$")?;
+        for stmt in &self.statements {
+            write!(f, " | {}", stmt)?;
+        }
+        if let Some(output) = &self.output {
+            write!(f, " | !({})", output)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for UDF {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let body = self.body.to_string();
+        if self.defaults.is_empty() && !self.variadic {
+            return write!(f, "&{}:{} := {}", self.name, self.arity, body);
+        }
+
+        // Parameters have no names in the tree, as the body refers to them
+        // by number; pick ones the body and defaults do not use for scopes
+        let defaults: Vec<String> = self.defaults.iter().map(Expr::to_string).collect();
+        let taken = |name: &str| {
+            name == "rest" || std::iter::once(&body).chain(&defaults).any(|text| mentions(text, name))
+        };
+        let required = self.arity.saturating_sub(defaults.len());
+        let mut params = Vec::new();
+        for index in 1..=self.arity {
+            let mut name = format!("p{}", index);
+            while taken(&name) {
+                name.push('_');
+            }
+            match index.checked_sub(required + 1).and_then(|default| defaults.get(default)) {
+                Some(default) => params.push(format!("{} = {}", name, default)),
+                None => params.push(name),
+            }
+        }
+        if self.variadic {
+            params.push("...".to_string());
+        }
+        write!(f, "&{}({}) := {}", self.name, params.join(", "), body)
+    }
+}
+
+/// Whether `text` refers to `@name`, and not just to a longer name
+/// starting with it
+fn mentions(text: &str, name: &str) -> bool {
+    let reference = format!("@{}", name);
+    text.match_indices(&reference)
+        .any(|(at, _)| !text[at + reference.len()..].starts_with(|c: char| c.is_alphanumeric() || c == '_'))
+}
+
+impl fmt::Display for Statement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut out = String::new();
+        write_statement(&mut out, self);
+        f.write_str(&out)
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut out = String::new();
+        write_expr(&mut out, self, OPEN);
+        f.write_str(&out)
+    }
+}
+
+impl fmt::Display for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut out = String::new();
+        write_pattern(&mut out, self);
+        f.write_str(&out)
+    }
+}
+
+fn write_statement(out: &mut String, stmt: &Statement) {
+    match stmt {
+        Statement::ScopeDefinition { name, path } | Statement::HoistedScope { name, path } => {
+            out.push_str(&format!("@{} := ", name));
+            write_expr(out, path, OPEN);
+        }
+        Statement::LiveScopeDefinition { name, path } => {
+            out.push_str(&format!("@{} ::= ", name));
+            write_expr(out, path, OPEN);
+        }
+        Statement::ExistenceCheck(expr) => {
+            let mut text = String::new();
+            write_postfix_base(&mut text, expr);
+            text.push_str("[?]");
+            write_statement_expr(out, text);
+        }
+        Statement::Filter(condition) => {
+            out.push_str("?(");
+            write_expr(out, condition, OPEN);
+            out.push(')');
+        }
+        Statement::When { condition, statements } => {
+            out.push_str("when (");
+            write_expr(out, condition, OPEN);
+            out.push_str(") ");
+            write_block(out, statements);
+        }
+        Statement::ForEach { path, name, statements } => {
+            out.push_str("foreach ");
+            write_expr(out, path, POSTFIX);
+            out.push_str(&format!(" as @{} ", name));
+            write_block(out, statements);
+        }
+        Statement::Transform { target, value } => {
+            out.push_str("~(");
+            write_expr(out, target, POSTFIX);
+            out.push_str(" := ");
+            write_expr(out, value, OPEN);
+            out.push(')');
+        }
+        Statement::Delete(target) => {
+            out.push_str("-(");
+            write_expr(out, target, POSTFIX);
+            out.push(')');
+        }
+        Statement::Access(expr) => write_statement_expr(out, expr.to_string()),
+    }
+}
+
+/// An expression standing as a statement. A statement starting with `@name`
+/// is read as a scope definition or a plain path, and one starting with `-`
+/// as a deletion or a negative number, so other expressions starting with
+/// those are parenthesized; `@1` is an argument either way.
+fn write_statement_expr(out: &mut String, text: String) {
+    let mut chars = text.chars();
+    let ambiguous = match chars.next() {
+        Some('-') => true,
+        Some('@') => !chars.next().is_some_and(|c| c.is_ascii_digit()),
+        _ => false,
+    };
+    if ambiguous {
+        out.push('(');
+        out.push_str(&text);
+        out.push(')');
+    } else {
+        out.push_str(&text);
+    }
+}
+
+/// `{ statement | statement ... }`, the body of a `when` or `foreach`
+fn write_block(out: &mut String, statements: &[Statement]) {
+    out.push_str("{ ");
+    for (index, stmt) in statements.iter().enumerate() {
+        if index > 0 {
+            out.push_str(" | ");
+        }
+        write_statement(out, stmt);
+    }
+    out.push_str(" }");
+}
+
+fn precedence(expr: &Expr) -> u8 {
+    match expr {
+        Expr::Lambda { .. } | Expr::Try { .. } => OPEN,
+        Expr::BinaryOp { op, .. } => match op {
+            BinOp::NullCoalesce => COALESCE,
+            BinOp::Or => OR,
+            BinOp::And => AND,
+            BinOp::Equal
+            | BinOp::NotEqual
+            | BinOp::ApproxEqual
+            | BinOp::LessThan
+            | BinOp::GreaterThan
+            | BinOp::LessEqual
+            | BinOp::GreaterEqual => COMPARISON,
+            BinOp::Add | BinOp::Subtract => ADDITIVE,
+            BinOp::Multiply | BinOp::Divide | BinOp::Modulo => MULTIPLICATIVE,
+        },
+        _ => POSTFIX,
+    }
+}
+
+fn operator(op: BinOp) -> &'static str {
+    match op {
+        BinOp::Equal => "==",
+        BinOp::NotEqual => "!=",
+        BinOp::ApproxEqual => "~=",
+        BinOp::LessThan => "<",
+        BinOp::GreaterThan => ">",
+        BinOp::LessEqual => "<=",
+        BinOp::GreaterEqual => ">=",
+        BinOp::Add => "+",
+        BinOp::Subtract => "-",
+        BinOp::Multiply => "*",
+        BinOp::Divide => "/",
+        BinOp::Modulo => "%",
+        BinOp::And => "and",
+        BinOp::Or => "or",
+        BinOp::NullCoalesce => "??",
+    }
+}
+
+/// Write `expr`, parenthesized if it binds more loosely than `min`
+fn write_expr(out: &mut String, expr: &Expr, min: u8) {
+    if precedence(expr) < min {
+        out.push('(');
+        write_bare(out, expr);
+        out.push(')');
+    } else {
+        write_bare(out, expr);
+    }
+}
+
+/// Write `expr` so that `[key]`, `.method()` or `[?]` can follow it.
+/// Nothing follows `[?]`, and a negative number is parenthesized although
+/// it would read the same without, as `-1.abs()` looks like `-(1.abs())`.
+fn write_postfix_base(out: &mut String, expr: &Expr) {
+    let parenthesize = match expr {
+        Expr::ExistenceCheck(_) => true,
+        Expr::Integer(n) => *n < 0,
+        Expr::Float(n) => n.is_sign_negative(),
+        _ => precedence(expr) < POSTFIX,
+    };
+    if parenthesize {
+        out.push('(');
+        write_bare(out, expr);
+        out.push(')');
+    } else {
+        write_bare(out, expr);
+    }
+}
+
+fn write_bare(out: &mut String, expr: &Expr) {
+    match expr {
+        Expr::Float(n) => write_float(out, *n),
+        // The literal 9223372036854775808 is out of range before negation
+        Expr::Integer(i64::MIN) => out.push_str("(-9223372036854775807 - 1)"),
+        Expr::Integer(n) => out.push_str(&n.to_string()),
+        Expr::String(s) => out.push_str(&quote_string(s)),
+        Expr::Boolean(b) => out.push_str(if *b { "true" } else { "false" }),
+        Expr::Null => out.push_str("null"),
+        Expr::Root => out.push('$'),
+        Expr::OriginalRoot => out.push_str("$$"),
+        Expr::ScopeRef(name) | Expr::LambdaVar(name) => {
+            out.push('@');
+            out.push_str(name);
+        }
+        Expr::LambdaParam => out.push('@'),
+        Expr::OuterLambdaParam(level) => out.push_str(&"@".repeat(level + 1)),
+        Expr::ArgRef(index) => out.push_str(&format!("@{}", index)),
+        Expr::EnvVar(name) => out.push_str(&format!("${}", name)),
+        Expr::TypedEnvVar { name, ty } => out.push_str(&format!("${}:{}", name, ty.name())),
+        Expr::Meta => out.push_str("$meta"),
+        Expr::Key(name) => out.push_str(name),
+        Expr::Wildcard => out.push('*'),
+        Expr::Access { object, key } => {
+            write_postfix_base(out, object);
+            write_key(out, key);
+        }
+        Expr::ExistenceCheck(object) => {
+            write_postfix_base(out, object);
+            out.push_str("[?]");
+        }
+        Expr::Filter(condition) => {
+            out.push_str("?(");
+            write_expr(out, condition, OPEN);
+            out.push(')');
+        }
+        Expr::Lambda { param, body } => {
+            out.push_str(param);
+            out.push_str(" -> ");
+            write_expr(out, body, OPEN);
+        }
+        Expr::Match { subject, arms } => {
+            out.push_str("match ");
+            write_expr(out, subject, OPEN);
+            out.push_str(" { ");
+            for (index, arm) in arms.iter().enumerate() {
+                if index > 0 {
+                    out.push_str(", ");
+                }
+                write_arm(out, arm);
+            }
+            out.push_str(" }");
+        }
+        Expr::HostCall { name, args } => {
+            out.push_str("ext::");
+            out.push_str(name);
+            write_list(out, '(', args, ')');
+        }
+        Expr::Try { body, fallback } => {
+            out.push_str("try ");
+            write_expr(out, body, OPEN);
+            out.push_str(" catch ");
+            write_expr(out, fallback, OPEN);
+        }
+        Expr::BinaryOp { op, left, right } => {
+            let level = precedence(expr);
+            // Comparisons do not chain, so neither side may be one
+            let left_min = if level == COMPARISON { level + 1 } else { level };
+            write_expr(out, left, left_min);
+            out.push(' ');
+            out.push_str(operator(*op));
+            out.push(' ');
+            write_expr(out, right, level + 1);
+        }
+        Expr::MethodCall { object, method, args } => {
+            write_postfix_base(out, object);
+            out.push('.');
+            out.push_str(method);
+            write_list(out, '(', args, ')');
+        }
+        Expr::UDFCall { name, args } => {
+            out.push('&');
+            out.push_str(name);
+            write_list(out, '[', args, ']');
+        }
+        Expr::Object(entries) => {
+            out.push('{');
+            for (index, (key, value)) in entries.iter().enumerate() {
+                if index > 0 {
+                    out.push_str(", ");
+                }
+                write_object_key(out, key);
+                out.push_str(": ");
+                write_expr(out, value, OPEN);
+            }
+            out.push('}');
+        }
+        Expr::Array(items) => write_list(out, '[', items, ']'),
+    }
+}
+
+/// A float literal that lexes back as a float, infinities as `1e999`, and
+/// NaN, which has no literal, as an expression evaluating to it
+fn write_float(out: &mut String, n: f64) {
+    if n.is_nan() {
+        out.push_str("(1e999 - 1e999)");
+    } else if n.is_infinite() {
+        out.push_str(if n > 0.0 { "1e999" } else { "-1e999" });
+    } else {
+        // `{:?}` always has a `.` or an exponent, and round-trips
+        out.push_str(&format!("{:?}", n));
+    }
+}
+
+/// `[key]` after an access. A key written as a name or a string is a
+/// field name, so a computed key starting with either is parenthesized.
+fn write_key(out: &mut String, key: &Expr) {
+    match key {
+        Expr::Key(name) => out.push_str(&quote_key(name)),
+        Expr::Wildcard => out.push_str("[*]"),
+        key => {
+            let mut text = String::new();
+            write_expr(&mut text, key, OPEN);
+            let word: String = text.chars().take_while(|c| c.is_alphanumeric() || *c == '_').collect();
+            let reads_as_name = text.starts_with('"')
+                || (word.starts_with(|c: char| !c.is_ascii_digit())
+                    && !matches!(word.as_str(), "true" | "false" | "null"));
+            if reads_as_name {
+                out.push_str(&format!("[({})]", text));
+            } else {
+                out.push_str(&format!("[{}]", text));
+            }
+        }
+    }
+}
+
+fn write_object_key(out: &mut String, key: &str) {
+    if is_identifier(key) {
+        out.push_str(key);
+    } else {
+        out.push_str(&quote_string(key));
+    }
+}
+
+/// Comma-separated expressions between `open` and `close`
+fn write_list(out: &mut String, open: char, items: &[Expr], close: char) {
+    out.push(open);
+    for (index, item) in items.iter().enumerate() {
+        if index > 0 {
+            out.push_str(", ");
+        }
+        write_expr(out, item, OPEN);
+    }
+    out.push(close);
+}
+
+fn write_arm(out: &mut String, arm: &MatchArm) {
+    write_pattern(out, &arm.pattern);
+    if let Some(guard) = &arm.guard {
+        out.push_str(" if ");
+        write_expr(out, guard, OPEN);
+    }
+    out.push_str(" => ");
+    write_expr(out, &arm.body, OPEN);
+}
+
+fn write_pattern(out: &mut String, pattern: &Pattern) {
+    match pattern {
+        Pattern::Wildcard => out.push('_'),
+        Pattern::Bind(name) => out.push_str(name),
+        Pattern::Literal(expr) => write_expr(out, expr, POSTFIX),
+        Pattern::Object(entries) => {
+            out.push('{');
+            for (index, (key, pattern)) in entries.iter().enumerate() {
+                if index > 0 {
+                    out.push_str(", ");
+                }
+                write_object_key(out, key);
+                out.push_str(": ");
+                write_pattern(out, pattern);
+            }
+            out.push('}');
+        }
+        Pattern::Array { items, rest } => {
+            out.push('[');
+            for (index, item) in items.iter().enumerate() {
+                if index > 0 {
+                    out.push_str(", ");
+                }
+                write_pattern(out, item);
+            }
+            match (rest, items.is_empty()) {
+                (true, true) => out.push_str("..."),
+                (true, false) => out.push_str(", ..."),
+                _ => {}
+            }
+            out.push(']');
+        }
+    }
+}
@@ -1,11 +1,14 @@
 /// Binary operators.
 #[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum BinOp {
     // Comparison
     /// Equal (`==`)
     Equal,
     /// Not equal (`!=`)
     NotEqual,
+    /// Approximately equal (`~=`): numbers within a tolerance, strings ignoring case
+    ApproxEqual,
     /// Less than (`<`)
     LessThan,
     /// Greater than (`>`)
@@ -0,0 +1,48 @@
+use crate::ast::Expr;
+
+/// One arm of a `match` expression: `pattern [if guard] => body`
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MatchArm {
+    /// What the value must look like
+    pub pattern: Pattern,
+
+    /// Condition checked after the pattern matches, with its names bound
+    pub guard: Option<Expr>,
+
+    /// The result when the arm is taken
+    pub body: Expr,
+}
+
+/// Shape a value is matched against in a `match` arm.
+///
+/// # Examples
+/// ```text
+/// {"type": "click", "x": x}     // object with these keys, binding x
+/// [first, _, ...]               // array of two or more elements
+/// "done"                        // equal to a literal
+/// _                             // anything
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Pattern {
+    /// Matches anything (`_`)
+    Wildcard,
+
+    /// Matches anything and binds it to the name
+    Bind(String),
+
+    /// Matches a value equal (as with `==`) to a literal expression
+    Literal(Expr),
+
+    /// Matches an object that has each of the keys, with a value matching
+    /// its pattern; other keys are ignored
+    Object(Vec<(String, Pattern)>),
+
+    /// Matches an array whose elements match `items` in order; with `rest`
+    /// (a trailing `...`), the array may have more elements
+    Array {
+        items: Vec<Pattern>,
+        rest: bool,
+    },
+}
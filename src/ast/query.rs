@@ -1,9 +1,12 @@
+use sha2::{Digest, Sha256};
+
 use crate::ast::{Expr, Statement, UDF};
 
 /// Complete query pipeline.
 ///
 /// Represents a full query from UDF definitions to final output.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Query {
     /// User-defined functions
     pub udfs: Vec<UDF>,
@@ -14,3 +17,46 @@ pub struct Query {
     /// Optional output expression (defaults to root if None)
     pub output: Option<Expr>,
 }
+
+impl Query {
+    /// The same query, rewritten to evaluate faster (see [`crate::optimize`])
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use clove_lang::{Expr, Lexer, Parser};
+    ///
+    /// let query = Parser::new(Lexer::new("$ | ?(1 < 2) | !(60 * 60 * 24)")).unwrap().parse_query().unwrap();
+    /// let query = query.optimize();
+    /// assert!(query.statements.is_empty());
+    /// assert_eq!(query.output, Some(Expr::Integer(86400)));
+    /// ```
+    pub fn optimize(self) -> Query {
+        crate::optimize::optimize_query(self)
+    }
+
+    /// A stable content hash of the query, for keying caches: the first 16
+    /// hex digits of the SHA-256 of its source as [`Display`](std::fmt::Display)
+    /// prints it.
+    ///
+    /// Queries that parse to the same tree have the same fingerprint, however
+    /// they are laid out or spelled (`$.a` and `$['a']`, `0x10` and `16`,
+    /// renamed UDF parameters). The query is hashed as written, not as
+    /// [`optimize`](Self::optimize) would rewrite it.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use clove_lang::{Lexer, Parser};
+    ///
+    /// let parse = |source| Parser::new(Lexer::new(source)).unwrap().parse_query().unwrap();
+    /// let query = parse("$ | ?($.age >= 18) | !($.name)");
+    /// assert_eq!(query.fingerprint().len(), 16);
+    /// assert_eq!(query.fingerprint(), parse("$|?($[age]>=18)\n  |!($['name'])").fingerprint());
+    /// assert_ne!(query.fingerprint(), parse("$ | ?($.age > 18) | !($.name)").fingerprint());
+    /// ```
+    pub fn fingerprint(&self) -> String {
+        let digest = Sha256::digest(self.to_string().as_bytes());
+        digest[..8].iter().map(|byte| format!("{:02x}", byte)).collect()
+    }
+}
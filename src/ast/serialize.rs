@@ -0,0 +1,104 @@
+//! JSON serialization of parsed queries (requires the `serde` feature).
+//!
+//! Serialized queries are wrapped in a versioned envelope:
+//!
+//! ```text
+//! {"version": 2, "query": { ...AST... }}
+//! ```
+//!
+//! [`AST_FORMAT_VERSION`] is bumped whenever the shape of the AST changes, so
+//! a query serialized by an older release is rejected with
+//! [`AstDecodeError::UnsupportedVersion`] instead of being misread.
+
+use crate::ast::Query;
+
+/// Version of the serialized AST format produced by [`Query::to_json`].
+pub const AST_FORMAT_VERSION: u32 = 2;
+
+/// Errors that can occur when loading a serialized query.
+#[derive(Debug)]
+pub enum AstDecodeError {
+    /// Not valid JSON, or not the expected AST shape
+    Json(serde_json::Error),
+    /// Serialized by an incompatible version of the format
+    UnsupportedVersion { found: u64, expected: u32 },
+    /// The envelope has no (numeric) `version` field
+    MissingVersion,
+}
+
+impl std::fmt::Display for AstDecodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AstDecodeError::Json(e) => write!(f, "Invalid serialized query: {}", e),
+            AstDecodeError::UnsupportedVersion { found, expected } => write!(
+                f,
+                "Unsupported serialized query version {} (expected {})",
+                found, expected
+            ),
+            AstDecodeError::MissingVersion => write!(f, "Serialized query has no version tag"),
+        }
+    }
+}
+
+impl std::error::Error for AstDecodeError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            AstDecodeError::Json(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<serde_json::Error> for AstDecodeError {
+    fn from(e: serde_json::Error) -> Self {
+        AstDecodeError::Json(e)
+    }
+}
+
+#[derive(serde::Serialize)]
+struct EnvelopeRef<'a> {
+    version: u32,
+    query: &'a Query,
+}
+
+impl Query {
+    /// Serialize this query to JSON, tagged with [`AST_FORMAT_VERSION`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use clove_lang::{Lexer, Parser, Query};
+    ///
+    /// let query = Parser::new(Lexer::new("$ | ?($[n] > 1)")).unwrap().parse_query().unwrap();
+    /// let json = query.to_json().unwrap();
+    /// assert_eq!(Query::from_json(&json).unwrap(), query);
+    /// ```
+    pub fn to_json(&self) -> Result<String, serde_json::Error> {
+        serde_json::to_string(&EnvelopeRef {
+            version: AST_FORMAT_VERSION,
+            query: self,
+        })
+    }
+
+    /// Load a query serialized by [`Query::to_json`] without re-parsing it.
+    pub fn from_json(json: &str) -> Result<Query, AstDecodeError> {
+        let mut envelope: serde_json::Value = serde_json::from_str(json)?;
+
+        let version = envelope
+            .get("version")
+            .and_then(serde_json::Value::as_u64)
+            .ok_or(AstDecodeError::MissingVersion)?;
+        if version != u64::from(AST_FORMAT_VERSION) {
+            return Err(AstDecodeError::UnsupportedVersion {
+                found: version,
+                expected: AST_FORMAT_VERSION,
+            });
+        }
+
+        let query = envelope
+            .get_mut("query")
+            .map(serde_json::Value::take)
+            .unwrap_or(serde_json::Value::Null);
+        Ok(serde_json::from_value(query)?)
+    }
+}
@@ -3,11 +3,13 @@ use crate::ast::Expr;
 /// Pipeline statement.
 ///
 /// A pipeline consists of a sequence of statements that process data.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Statement {
     /// Scope definition
     ///
-    /// Creates a shorthand reference for a path.
+    /// Creates a shorthand reference for a path. The value is captured when
+    /// the statement runs, so later transforms do not change it.
     ///
     /// # Example
     /// ```text
@@ -17,6 +19,31 @@ pub enum Statement {
         name: String,
         path: Expr,
     },
+
+    /// Live scope definition
+    ///
+    /// Like [`Statement::ScopeDefinition`], but the expression is evaluated
+    /// each time the scope is referenced, against the document at that point.
+    ///
+    /// # Example
+    /// ```text
+    /// @items ::= $[items]
+    /// ```
+    LiveScopeDefinition {
+        name: String,
+        path: Expr,
+    },
+
+    /// Scope definition inserted by [`Query::optimize`](crate::Query::optimize)
+    /// for an expression hoisted out of a lambda
+    ///
+    /// Evaluated once, like [`Statement::ScopeDefinition`], but an error is
+    /// only reported if the scope is referenced. Its name (`%0`, `%1`, ...)
+    /// cannot be written in a query, so it never shadows a user's scope.
+    HoistedScope {
+        name: String,
+        path: Expr,
+    },
     
     /// Existence check
     ///
@@ -35,6 +62,39 @@ pub enum Statement {
     /// ?($[status] == "active")
     /// ```
     Filter(Expr),
+
+    /// Conditional stages
+    ///
+    /// Runs `statements` in order if `condition` holds for the document, and
+    /// otherwise passes the document on unchanged. A filter among them that
+    /// rejects the document ends the whole pipeline.
+    ///
+    /// # Example
+    /// ```text
+    /// when ($[legacy][?]) { ~($[legacy_id] := $[legacy][id]) | -($[legacy]) }
+    /// ```
+    When {
+        condition: Expr,
+        statements: Vec<Statement>,
+    },
+
+    /// Loop over an array
+    ///
+    /// Runs `statements` once per element of the array at `path`, with the
+    /// element bound to `@name`, and puts the results back in the array. In
+    /// the body, transforms and deletions change `@name`, a filter that fails
+    /// drops the element, and an expression replaces it; `$` is still the
+    /// document.
+    ///
+    /// # Example
+    /// ```text
+    /// foreach $[items] as @item { ~(@item[total] := @item[price] * $[rate]) | -(@item[draft]) }
+    /// ```
+    ForEach {
+        path: Expr,
+        name: String,
+        statements: Vec<Statement>,
+    },
     
     /// Transform operation
     ///
@@ -77,6 +77,18 @@ pub enum Token {
     /// $[field]
     /// ```
     Dollar,
+
+    /// Original document reference
+    ///
+    /// Always refers to the document the query started from, whatever the
+    /// stages before have made of `$`.
+    ///
+    /// # Examples
+    /// ```text
+    /// $$
+    /// $$[field]
+    /// ```
+    DoubleDollar,
     
     /// At-sign prefix for scope references, lambda params, or UDF args
     ///
@@ -157,6 +169,22 @@ pub enum Token {
     /// @items := $[items]
     /// ```
     ColonEqual,
+
+    /// Match arm separator (`=>`)
+    ///
+    /// # Examples
+    /// ```text
+    /// match $[kind] { "a" => 1, _ => 0 }
+    /// ```
+    FatArrow,
+
+    /// Live scope definition operator
+    ///
+    /// # Examples
+    /// ```text
+    /// @items ::= $[items]
+    /// ```
+    ColonColonEqual,
     
     /// Pipeline operator
     ///
@@ -175,6 +203,9 @@ pub enum Token {
     /// Inequality operator
     NotEq,
     
+    /// Approximate equality operator (`~=`)
+    TildeEq,
+    
     /// Less than
     Lt,
     
@@ -250,4 +281,80 @@ pub enum Token {
     
     /// End of file
     Eof,
+
+    /// Text that does not lex, with the error message. Only
+    /// [`tokenize_with_spans`](crate::lexer::tokenize_with_spans) produces
+    /// these; [`Lexer::next_token`](crate::lexer::Lexer::next_token) returns
+    /// the error instead.
+    Error(String),
+}
+
+/// What kind of syntax a [`Token`] is, for syntax highlighting
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TokenClass {
+    /// `and` and `or`, also written `&&` and `||`
+    Keyword,
+    /// Comparison, arithmetic, pipeline (`|`, `?`, `~`, `!`) and assignment
+    /// operators
+    Operator,
+    /// Numbers, strings, `true`, `false` and `null`
+    Literal,
+    /// `$`, `@`, `&`, `$NAME` and names of fields, methods, scopes and
+    /// functions
+    Reference,
+    /// Brackets, parentheses, braces, `.`, `,` and `:`
+    Punctuation,
+    /// Text that does not lex
+    Error,
+}
+
+impl Token {
+    /// How to highlight the token
+    pub fn class(&self) -> TokenClass {
+        match self {
+            Token::And | Token::Or => TokenClass::Keyword,
+            Token::Question
+            | Token::DoubleQuestion
+            | Token::Tilde
+            | Token::Exclamation
+            | Token::ColonEqual
+            | Token::ColonColonEqual
+            | Token::FatArrow
+            | Token::Pipe
+            | Token::EqEq
+            | Token::NotEq
+            | Token::TildeEq
+            | Token::Lt
+            | Token::Gt
+            | Token::LtEq
+            | Token::GtEq
+            | Token::Plus
+            | Token::Minus
+            | Token::Star
+            | Token::Slash
+            | Token::Percent => TokenClass::Operator,
+            Token::Float(_)
+            | Token::Integer(_)
+            | Token::String(_)
+            | Token::Boolean(_)
+            | Token::Null => TokenClass::Literal,
+            Token::EnvVar(_)
+            | Token::Identifier(_)
+            | Token::Dollar
+            | Token::DoubleDollar
+            | Token::At
+            | Token::Ampersand => TokenClass::Reference,
+            Token::LBracket
+            | Token::RBracket
+            | Token::LParen
+            | Token::RParen
+            | Token::LBrace
+            | Token::RBrace
+            | Token::Dot
+            | Token::Comma
+            | Token::Colon
+            | Token::Eof => TokenClass::Punctuation,
+            Token::Error(_) => TokenClass::Error,
+        }
+    }
 }
@@ -1,9 +1,10 @@
-use crate::ast::Statement;
+use crate::ast::{Expr, Statement};
 
 /// User-defined function.
 ///
 /// Functions can encapsulate filters, transforms, or computed values.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct UDF {
     /// Function name
     pub name: String,
@@ -11,6 +12,25 @@ pub struct UDF {
     /// Number of arguments
     pub arity: usize,
     
+    /// Default values of the last `defaults.len()` parameters, used when a
+    /// call leaves them out
+    pub defaults: Vec<Expr>,
+    
+    /// Whether arguments past `arity` are collected into `@rest`
+    pub variadic: bool,
+    
     /// Function body (pre-parsed AST)
     pub body: Statement,
 }
+
+impl UDF {
+    /// Fewest arguments a call may pass
+    pub fn min_arity(&self) -> usize {
+        self.arity - self.defaults.len()
+    }
+
+    /// Most arguments a call may pass, or `None` if it is variadic
+    pub fn max_arity(&self) -> Option<usize> {
+        (!self.variadic).then_some(self.arity)
+    }
+}
@@ -0,0 +1,234 @@
+//! `clove-lsp`: a language server for Clove queries.
+//!
+//! Speaks the Language Server Protocol over stdin and stdout, publishing
+//! diagnostics as documents change and answering hover and completion
+//! requests with the analyses in [`clove_lang::ide`]. Field names complete
+//! from a sample document named in the client's initialization options:
+//!
+//! ```json
+//! {"sample": "/path/to/sample.json"}
+//! ```
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs::File;
+use std::io::BufReader;
+
+use clove_lang::ide::{self, CompletionKind, Severity};
+use clove_lang::{Position, Value};
+use lsp_server::{Connection, Message, Notification, Request, RequestId, Response};
+use lsp_types::notification::{
+    DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument, Notification as _,
+    PublishDiagnostics,
+};
+use lsp_types::request::{Completion, HoverRequest, Request as _};
+use lsp_types::{
+    CompletionItem, CompletionItemKind, CompletionOptions, CompletionParams, CompletionResponse,
+    Diagnostic, DiagnosticSeverity, DidChangeTextDocumentParams, DidCloseTextDocumentParams,
+    DidOpenTextDocumentParams, Hover, HoverContents, HoverParams, HoverProviderCapability,
+    InitializeParams, MarkupContent, MarkupKind, PublishDiagnosticsParams, Range,
+    ServerCapabilities, TextDocumentSyncCapability, TextDocumentSyncKind, Url,
+};
+
+type BoxError = Box<dyn Error + Send + Sync>;
+
+struct Server {
+    connection: Connection,
+    documents: HashMap<Url, String>,
+    sample: Option<Value>,
+}
+
+fn main() -> Result<(), BoxError> {
+    let (connection, io_threads) = Connection::stdio();
+
+    let capabilities = ServerCapabilities {
+        text_document_sync: Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::FULL)),
+        hover_provider: Some(HoverProviderCapability::Simple(true)),
+        completion_provider: Some(CompletionOptions {
+            trigger_characters: Some(vec![".".into(), "&".into(), "[".into()]),
+            ..Default::default()
+        }),
+        ..Default::default()
+    };
+    let params: InitializeParams =
+        serde_json::from_value(connection.initialize(serde_json::to_value(capabilities)?)?)?;
+    let sample = match params
+        .initialization_options
+        .as_ref()
+        .and_then(|options| options.get("sample"))
+        .and_then(|sample| sample.as_str())
+    {
+        Some(path) => Some(Value::from_reader(BufReader::new(File::open(path)?))?),
+        None => None,
+    };
+
+    let mut server = Server {
+        connection,
+        documents: HashMap::new(),
+        sample,
+    };
+    server.run()?;
+    drop(server);
+    io_threads.join()?;
+    Ok(())
+}
+
+impl Server {
+    fn run(&mut self) -> Result<(), BoxError> {
+        while let Ok(message) = self.connection.receiver.recv() {
+            match message {
+                Message::Request(request) => {
+                    if self.connection.handle_shutdown(&request)? {
+                        return Ok(());
+                    }
+                    self.request(request)?;
+                }
+                Message::Notification(notification) => self.notification(notification)?,
+                Message::Response(_) => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn request(&self, request: Request) -> Result<(), BoxError> {
+        let result = match request.method.as_str() {
+            HoverRequest::METHOD => {
+                let params: HoverParams = serde_json::from_value(request.params)?;
+                let at = params.text_document_position_params;
+                serde_json::to_value(self.hover(&at.text_document.uri, at.position))?
+            }
+            Completion::METHOD => {
+                let params: CompletionParams = serde_json::from_value(request.params)?;
+                let at = params.text_document_position;
+                serde_json::to_value(self.completion(&at.text_document.uri, at.position))?
+            }
+            _ => {
+                let response = Response::new_err(
+                    request.id,
+                    lsp_server::ErrorCode::MethodNotFound as i32,
+                    format!("Unsupported request: {}", request.method),
+                );
+                return self.send(response.into());
+            }
+        };
+        self.respond(request.id, result)
+    }
+
+    fn notification(&mut self, notification: Notification) -> Result<(), BoxError> {
+        match notification.method.as_str() {
+            DidOpenTextDocument::METHOD => {
+                let params: DidOpenTextDocumentParams = serde_json::from_value(notification.params)?;
+                let document = params.text_document;
+                self.documents.insert(document.uri.clone(), document.text);
+                self.publish(document.uri, Some(document.version))
+            }
+            DidChangeTextDocument::METHOD => {
+                let params: DidChangeTextDocumentParams = serde_json::from_value(notification.params)?;
+                let document = params.text_document;
+                // Full sync: the last change holds the whole text
+                if let Some(change) = params.content_changes.into_iter().last() {
+                    self.documents.insert(document.uri.clone(), change.text);
+                }
+                self.publish(document.uri, Some(document.version))
+            }
+            DidCloseTextDocument::METHOD => {
+                let params: DidCloseTextDocumentParams = serde_json::from_value(notification.params)?;
+                let uri = params.text_document.uri;
+                self.documents.remove(&uri);
+                self.publish(uri, None)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Send the diagnostics for `uri`, none once it is closed
+    fn publish(&self, uri: Url, version: Option<i32>) -> Result<(), BoxError> {
+        let text = self.documents.get(&uri).map(String::as_str);
+        let diagnostics = text
+            .map(|text| {
+                ide::diagnostics(text)
+                    .into_iter()
+                    .map(|diagnostic| Diagnostic {
+                        range: range(text, diagnostic.start, diagnostic.end),
+                        severity: Some(match diagnostic.severity {
+                            Severity::Error => DiagnosticSeverity::ERROR,
+                            Severity::Warning => DiagnosticSeverity::WARNING,
+                        }),
+                        source: Some("clove".into()),
+                        message: diagnostic.message,
+                        ..Default::default()
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        let params = PublishDiagnosticsParams { uri, diagnostics, version };
+        self.send(Notification::new(PublishDiagnostics::METHOD.into(), params).into())
+    }
+
+    fn hover(&self, uri: &Url, position: lsp_types::Position) -> Option<Hover> {
+        let text = self.documents.get(uri)?;
+        let (line, column) = from_lsp(text, position);
+        let hover = ide::hover(text, line, column)?;
+        Some(Hover {
+            contents: HoverContents::Markup(MarkupContent {
+                kind: MarkupKind::PlainText,
+                value: hover.contents,
+            }),
+            range: Some(range(text, hover.start, hover.end)),
+        })
+    }
+
+    fn completion(&self, uri: &Url, position: lsp_types::Position) -> Option<CompletionResponse> {
+        let text = self.documents.get(uri)?;
+        let (line, column) = from_lsp(text, position);
+        let items = ide::completions(text, line, column, self.sample.as_ref())
+            .into_iter()
+            .map(|completion| CompletionItem {
+                label: completion.label,
+                kind: Some(match completion.kind {
+                    CompletionKind::Method => CompletionItemKind::METHOD,
+                    CompletionKind::Function => CompletionItemKind::FUNCTION,
+                    CompletionKind::Field => CompletionItemKind::FIELD,
+                }),
+                detail: completion.detail,
+                ..Default::default()
+            })
+            .collect();
+        Some(CompletionResponse::Array(items))
+    }
+
+    fn respond(&self, id: RequestId, result: serde_json::Value) -> Result<(), BoxError> {
+        self.send(Response { id, result: Some(result), error: None }.into())
+    }
+
+    fn send(&self, message: Message) -> Result<(), BoxError> {
+        self.connection.sender.send(message)?;
+        Ok(())
+    }
+}
+
+/// A 1-based line and character column from an LSP position, which counts
+/// from 0 in UTF-16 code units
+fn from_lsp(text: &str, position: lsp_types::Position) -> (usize, usize) {
+    let line = text.lines().nth(position.line as usize).unwrap_or("");
+    let mut units = 0;
+    let column = line
+        .chars()
+        .take_while(|c| {
+            units += c.len_utf16() as u32;
+            units <= position.character
+        })
+        .count();
+    (position.line as usize + 1, column + 1)
+}
+
+/// The LSP position of a 1-based line and character column
+fn to_lsp(text: &str, position: Position) -> lsp_types::Position {
+    let line = text.lines().nth(position.line - 1).unwrap_or("");
+    let character = line.chars().take(position.column - 1).map(char::len_utf16).sum::<usize>();
+    lsp_types::Position::new(position.line as u32 - 1, character as u32)
+}
+
+fn range(text: &str, start: Position, end: Position) -> Range {
+    Range::new(to_lsp(text, start), to_lsp(text, end))
+}
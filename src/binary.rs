@@ -0,0 +1,353 @@
+//! MessagePack and CBOR documents.
+//!
+//! Decoding maps both formats onto [`Value`] the way a JSON reader would,
+//! with a few coercions for what JSON has no notation for:
+//!
+//! - Binary data (MessagePack `bin`, CBOR byte strings) becomes
+//!   [`Value::Bytes`], which encodes back to binary data. JSON output writes
+//!   it as a base64 string.
+//! - Map keys must be strings; integer and boolean keys are converted to
+//!   their text (`1` becomes `"1"`), and any other key is an error.
+//! - Unsigned integers above `i64::MAX` become floats, as in JSON input.
+//! - Text that is not valid UTF-8 is converted lossily (invalid bytes become
+//!   U+FFFD).
+//! - CBOR tags are dropped, keeping the tagged value; MessagePack
+//!   extension types are an error.
+//!
+//! Encoding writes objects with their keys sorted, so equal values encode
+//! to equal bytes. MessagePack support needs the `msgpack` feature and CBOR
+//! the `cbor` feature.
+//!
+//! # Examples
+//!
+//! ```
+//! # #[cfg(feature = "msgpack")]
+//! # {
+//! use clove_lang::binary::{value_from_msgpack, value_to_msgpack};
+//! use clove_lang::clove_value;
+//!
+//! let doc = clove_value!({"id": 7, "tags": ["a", "b"]});
+//! let bytes = value_to_msgpack(&doc);
+//! assert_eq!(value_from_msgpack(&bytes).unwrap(), doc);
+//! # }
+//! ```
+
+use std::fmt;
+use std::str::FromStr;
+
+#[cfg(any(feature = "msgpack", feature = "cbor"))]
+use std::collections::HashMap;
+
+use crate::value::Value;
+
+/// Document formats the CLI reads with `--input-format` and writes with
+/// `--output-format`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DataFormat {
+    /// JSON text
+    #[default]
+    Json,
+    /// MessagePack (needs the `msgpack` feature)
+    Msgpack,
+    /// CBOR, RFC 8949 (needs the `cbor` feature)
+    Cbor,
+}
+
+impl DataFormat {
+    /// Decode one document of this binary format; trailing bytes are an
+    /// error. JSON is read by [`Value::from_reader`] instead, and fails here.
+    pub fn decode(self, bytes: &[u8]) -> Result<Value, FormatError> {
+        let mut values = self.decode_stream(bytes, Some(1))?;
+        Ok(values.pop().unwrap_or(Value::Null))
+    }
+
+    /// Decode every document of this binary format written back to back,
+    /// such as a stream of MessagePack records
+    pub fn decode_all(self, bytes: &[u8]) -> Result<Vec<Value>, FormatError> {
+        self.decode_stream(bytes, None)
+    }
+
+    /// Encode `value` in this binary format. JSON is written by
+    /// [`JsonWriter`](crate::output::JsonWriter) instead, and fails here.
+    pub fn encode(self, value: &Value) -> Result<Vec<u8>, FormatError> {
+        match self {
+            DataFormat::Json => Err(FormatError::NotBinary),
+            #[cfg(feature = "msgpack")]
+            DataFormat::Msgpack => Ok(value_to_msgpack(value)),
+            #[cfg(feature = "cbor")]
+            DataFormat::Cbor => Ok(value_to_cbor(value)),
+            #[allow(unreachable_patterns)]
+            format => {
+                let _ = value;
+                Err(FormatError::Disabled(format))
+            }
+        }
+    }
+
+    /// Documents decoded until `bytes` runs out; with `expected`, exactly
+    /// that many
+    fn decode_stream(self, bytes: &[u8], expected: Option<usize>) -> Result<Vec<Value>, FormatError> {
+        let mut rest = bytes;
+        let mut values = Vec::new();
+        while !rest.is_empty() || (values.is_empty() && expected.is_some()) {
+            if expected == Some(values.len()) {
+                return Err(FormatError::Decode {
+                    format: self,
+                    message: format!("{} trailing byte(s) after the document", rest.len()),
+                });
+            }
+            values.push(self.read(&mut rest)?);
+        }
+        Ok(values)
+    }
+
+    /// Decode the document at the start of `bytes`, advancing past it
+    fn read(self, bytes: &mut &[u8]) -> Result<Value, FormatError> {
+        match self {
+            DataFormat::Json => Err(FormatError::NotBinary),
+            #[cfg(feature = "msgpack")]
+            DataFormat::Msgpack => msgpack::read(bytes),
+            #[cfg(feature = "cbor")]
+            DataFormat::Cbor => cbor::read(bytes),
+            #[allow(unreachable_patterns)]
+            format => {
+                let _ = bytes;
+                Err(FormatError::Disabled(format))
+            }
+        }
+    }
+}
+
+impl FromStr for DataFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "json" => Ok(DataFormat::Json),
+            "msgpack" => Ok(DataFormat::Msgpack),
+            "cbor" => Ok(DataFormat::Cbor),
+            other => Err(format!(
+                "unknown data format '{}' (expected json, msgpack or cbor)",
+                other
+            )),
+        }
+    }
+}
+
+impl fmt::Display for DataFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            DataFormat::Json => "json",
+            DataFormat::Msgpack => "msgpack",
+            DataFormat::Cbor => "cbor",
+        })
+    }
+}
+
+/// Errors from decoding or encoding a binary document
+#[derive(Debug, Clone, PartialEq)]
+pub enum FormatError {
+    /// The bytes are not a valid document, or hold something a [`Value`]
+    /// cannot (see the [module documentation](self))
+    Decode { format: DataFormat, message: String },
+    /// The format's feature was not enabled when clove was built
+    Disabled(DataFormat),
+    /// JSON was asked for where only binary formats are handled
+    NotBinary,
+}
+
+impl fmt::Display for FormatError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FormatError::Decode { format, message } => write!(f, "Invalid {}: {}", format, message),
+            FormatError::Disabled(format) => {
+                write!(f, "{} support is not enabled (build with --features {})", format, format)
+            }
+            FormatError::NotBinary => write!(f, "JSON is not a binary format"),
+        }
+    }
+}
+
+impl std::error::Error for FormatError {}
+
+/// Parse a MessagePack document.
+///
+/// See the [module documentation](self) for how MessagePack types map onto
+/// [`Value`].
+#[cfg(feature = "msgpack")]
+pub fn value_from_msgpack(bytes: &[u8]) -> Result<Value, FormatError> {
+    DataFormat::Msgpack.decode(bytes)
+}
+
+/// Write a value as MessagePack
+#[cfg(feature = "msgpack")]
+pub fn value_to_msgpack(value: &Value) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    rmpv::encode::write_value(&mut bytes, &msgpack::from_value(value))
+        .expect("writing to a Vec cannot fail");
+    bytes
+}
+
+/// Parse a CBOR document.
+///
+/// See the [module documentation](self) for how CBOR types map onto
+/// [`Value`].
+#[cfg(feature = "cbor")]
+pub fn value_from_cbor(bytes: &[u8]) -> Result<Value, FormatError> {
+    DataFormat::Cbor.decode(bytes)
+}
+
+/// Write a value as CBOR
+#[cfg(feature = "cbor")]
+pub fn value_to_cbor(value: &Value) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    ciborium::into_writer(&cbor::from_value(value), &mut bytes).expect("writing to a Vec cannot fail");
+    bytes
+}
+
+/// An object from decoded entries, with keys converted by `key`
+#[cfg(any(feature = "msgpack", feature = "cbor"))]
+fn object<K>(
+    entries: Vec<(K, K)>,
+    mut key: impl FnMut(K) -> Result<String, FormatError>,
+    mut value: impl FnMut(K) -> Result<Value, FormatError>,
+) -> Result<Value, FormatError> {
+    let mut map = HashMap::with_capacity(entries.len());
+    for (k, v) in entries {
+        map.insert(key(k)?, value(v)?);
+    }
+    Ok(Value::Object(map))
+}
+
+/// Entries of an object sorted by key
+#[cfg(any(feature = "msgpack", feature = "cbor"))]
+fn sorted_entries(map: &HashMap<String, Value>) -> Vec<(&String, &Value)> {
+    let mut entries: Vec<_> = map.iter().collect();
+    entries.sort_by(|a, b| a.0.cmp(b.0));
+    entries
+}
+
+#[cfg(feature = "msgpack")]
+mod msgpack {
+    use rmpv::Value as Msgpack;
+
+    use super::{DataFormat, FormatError, object, sorted_entries};
+    use crate::value::Value;
+
+    fn error(message: String) -> FormatError {
+        FormatError::Decode { format: DataFormat::Msgpack, message }
+    }
+
+    pub(super) fn read(bytes: &mut &[u8]) -> Result<Value, FormatError> {
+        let value = rmpv::decode::read_value(bytes).map_err(|e| error(e.to_string()))?;
+        to_value(value)
+    }
+
+    fn to_value(value: Msgpack) -> Result<Value, FormatError> {
+        Ok(match value {
+            Msgpack::Nil => Value::Null,
+            Msgpack::Boolean(b) => Value::Boolean(b),
+            Msgpack::Integer(n) => match n.as_i64() {
+                Some(n) => Value::Integer(n),
+                None => Value::Float(n.as_f64().unwrap_or(f64::NAN)),
+            },
+            Msgpack::F32(n) => Value::Float(n as f64),
+            Msgpack::F64(n) => Value::Float(n),
+            Msgpack::String(s) => Value::String(String::from_utf8_lossy(s.as_bytes()).into_owned()),
+            Msgpack::Binary(bytes) => Value::Bytes(bytes),
+            Msgpack::Array(items) => Value::Array(items.into_iter().map(to_value).collect::<Result<_, _>>()?),
+            Msgpack::Map(entries) => object(entries, key, to_value)?,
+            Msgpack::Ext(ty, _) => return Err(error(format!("unsupported extension type {}", ty))),
+        })
+    }
+
+    fn key(key: Msgpack) -> Result<String, FormatError> {
+        match key {
+            Msgpack::String(s) => Ok(String::from_utf8_lossy(s.as_bytes()).into_owned()),
+            Msgpack::Integer(n) => Ok(n.to_string()),
+            Msgpack::Boolean(b) => Ok(b.to_string()),
+            other => Err(error(format!("map keys must be strings, got {}", other))),
+        }
+    }
+
+    pub(super) fn from_value(value: &Value) -> Msgpack {
+        match value {
+            Value::Null => Msgpack::Nil,
+            Value::Boolean(b) => Msgpack::Boolean(*b),
+            Value::Integer(n) => Msgpack::from(*n),
+            Value::Float(n) => Msgpack::F64(*n),
+            Value::String(s) => Msgpack::from(s.as_str()),
+            Value::Bytes(b) => Msgpack::Binary(b.clone()),
+            Value::Array(items) => Msgpack::Array(items.iter().map(from_value).collect()),
+            Value::Object(map) => Msgpack::Map(
+                sorted_entries(map)
+                    .into_iter()
+                    .map(|(k, v)| (Msgpack::from(k.as_str()), from_value(v)))
+                    .collect(),
+            ),
+        }
+    }
+}
+
+#[cfg(feature = "cbor")]
+mod cbor {
+    use ciborium::Value as Cbor;
+
+    use super::{DataFormat, FormatError, object, sorted_entries};
+    use crate::value::Value;
+
+    fn error(message: String) -> FormatError {
+        FormatError::Decode { format: DataFormat::Cbor, message }
+    }
+
+    pub(super) fn read(bytes: &mut &[u8]) -> Result<Value, FormatError> {
+        let value: Cbor = ciborium::from_reader(bytes).map_err(|e| error(e.to_string()))?;
+        to_value(value)
+    }
+
+    fn to_value(value: Cbor) -> Result<Value, FormatError> {
+        Ok(match value {
+            Cbor::Null => Value::Null,
+            Cbor::Bool(b) => Value::Boolean(b),
+            Cbor::Integer(n) => {
+                let n = i128::from(n);
+                i64::try_from(n).map_or(Value::Float(n as f64), Value::Integer)
+            }
+            Cbor::Float(n) => Value::Float(n),
+            Cbor::Text(s) => Value::String(s),
+            Cbor::Bytes(bytes) => Value::Bytes(bytes),
+            Cbor::Tag(_, value) => to_value(*value)?,
+            Cbor::Array(items) => Value::Array(items.into_iter().map(to_value).collect::<Result<_, _>>()?),
+            Cbor::Map(entries) => object(entries, key, to_value)?,
+            other => return Err(error(format!("unsupported value {:?}", other))),
+        })
+    }
+
+    fn key(key: Cbor) -> Result<String, FormatError> {
+        match key {
+            Cbor::Text(s) => Ok(s),
+            Cbor::Integer(n) => Ok(i128::from(n).to_string()),
+            Cbor::Bool(b) => Ok(b.to_string()),
+            Cbor::Tag(_, key) => self::key(*key),
+            other => Err(error(format!("map keys must be strings, got {:?}", other))),
+        }
+    }
+
+    pub(super) fn from_value(value: &Value) -> Cbor {
+        match value {
+            Value::Null => Cbor::Null,
+            Value::Boolean(b) => Cbor::Bool(*b),
+            Value::Integer(n) => Cbor::Integer((*n).into()),
+            Value::Float(n) => Cbor::Float(*n),
+            Value::String(s) => Cbor::Text(s.clone()),
+            Value::Bytes(b) => Cbor::Bytes(b.clone()),
+            Value::Array(items) => Cbor::Array(items.iter().map(from_value).collect()),
+            Value::Object(map) => Cbor::Map(
+                sorted_entries(map)
+                    .into_iter()
+                    .map(|(k, v)| (Cbor::Text(k.clone()), from_value(v)))
+                    .collect(),
+            ),
+        }
+    }
+}
@@ -0,0 +1,133 @@
+//! Case conversions behind `.title()`, `.snake_case()`, `.camel_case()` and
+//! `.kebab_case()`.
+//!
+//! Words are runs of letters, digits and combining marks, so accented and
+//! non-Latin text stays whole whether it is composed or not. Inside a run,
+//! a new word starts at a lowercase letter or digit followed by an
+//! uppercase letter (`fooBar`), and before the last of several uppercase
+//! letters followed by a lowercase one (`HTTPServer`).
+
+use unicode_normalization::char::is_combining_mark;
+
+/// Uppercase the first letter of every word and lowercase the rest,
+/// keeping everything between words as it is
+pub(crate) fn title(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(start) = rest.find(is_word_char) {
+        out.push_str(&rest[..start]);
+        let word = &rest[start..];
+        let mut end = word.len();
+        let mut prev_is_word = false;
+        for (i, c) in word.char_indices() {
+            let in_word = is_word_char(c) || (prev_is_word && is_apostrophe(c));
+            if !in_word {
+                end = i;
+                break;
+            }
+            prev_is_word = is_word_char(c);
+        }
+        push_word(&mut out, &word[..end], true);
+        rest = &word[end..];
+    }
+    out.push_str(rest);
+    out
+}
+
+/// `some_words`
+pub(crate) fn snake_case(s: &str) -> String {
+    join_lowercase(s, "_")
+}
+
+/// `some-words`
+pub(crate) fn kebab_case(s: &str) -> String {
+    join_lowercase(s, "-")
+}
+
+/// `someWords`
+pub(crate) fn camel_case(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for (i, word) in words(s).iter().enumerate() {
+        push_word(&mut out, word, i > 0);
+    }
+    out
+}
+
+/// Push `word` lowercased, with its first letter in titlecase if `capitalize`.
+/// Lowercasing the word as a whole gets context right, such as a final `ς`.
+fn push_word(out: &mut String, word: &str, capitalize: bool) {
+    let mut chars = word.chars();
+    if capitalize && let Some(first) = chars.next() {
+        push_titlecase(out, first);
+    }
+    out.push_str(&chars.as_str().to_lowercase());
+}
+
+fn join_lowercase(s: &str, separator: &str) -> String {
+    words(s).iter().map(|word| word.to_lowercase()).collect::<Vec<_>>().join(separator)
+}
+
+/// The words of `s` (see the [module docs](self))
+fn words(s: &str) -> Vec<String> {
+    let chars: Vec<char> = s.chars().collect();
+    let mut words = Vec::new();
+    let mut word = String::new();
+    // The last letter or digit of `word`, skipping combining marks
+    let mut prev: Option<char> = None;
+    for (i, &c) in chars.iter().enumerate() {
+        if !is_word_char(c) {
+            if !word.is_empty() {
+                words.push(std::mem::take(&mut word));
+            }
+            prev = None;
+            continue;
+        }
+        if let Some(p) = prev
+            && c.is_uppercase()
+        {
+            let next_is_lower = chars[i + 1..]
+                .iter()
+                .find(|next| !is_combining_mark(**next))
+                .is_some_and(|next| next.is_lowercase());
+            if p.is_lowercase() || p.is_numeric() || (p.is_uppercase() && next_is_lower) {
+                words.push(std::mem::take(&mut word));
+            }
+        }
+        word.push(c);
+        if !is_combining_mark(c) {
+            prev = Some(c);
+        }
+    }
+    if !word.is_empty() {
+        words.push(word);
+    }
+    words
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || is_combining_mark(c)
+}
+
+fn is_apostrophe(c: char) -> bool {
+    matches!(c, '\'' | '\u{2019}')
+}
+
+/// Push the titlecase form of `c`: uppercase, except for the digraphs that
+/// have a distinct titlecase letter (`ǆ` becomes `ǅ`, not `Ǆ`), and for
+/// letters that uppercase to several (`ß` becomes `Ss`, not `SS`)
+fn push_titlecase(out: &mut String, c: char) {
+    let digraph = match c {
+        'Ǆ' | 'ǅ' | 'ǆ' => Some('ǅ'),
+        'Ǉ' | 'ǈ' | 'ǉ' => Some('ǈ'),
+        'Ǌ' | 'ǋ' | 'ǌ' => Some('ǋ'),
+        'Ǳ' | 'ǲ' | 'ǳ' => Some('ǲ'),
+        _ => None,
+    };
+    if let Some(titlecase) = digraph {
+        out.push(titlecase);
+        return;
+    }
+    let mut upper = c.to_uppercase();
+    out.extend(upper.next());
+    out.extend(upper.flat_map(char::to_lowercase));
+}
@@ -0,0 +1,67 @@
+//! Exit-status assertions over JSON input (`clove assert`)
+
+use std::io::Read;
+
+use super::{CheckOptions, CheckResult, CliError, execute_check, execute_check_reader, clove_to_json};
+
+/// Exit code when the assertion holds
+pub const EXIT_PASSED: i32 = 0;
+/// Exit code when the query result is falsy
+pub const EXIT_FAILED: i32 = 1;
+/// Exit code when the query or input could not be processed
+pub const EXIT_ERROR: i32 = 2;
+
+/// Outcome of evaluating an assertion
+#[derive(Debug)]
+pub struct AssertOutcome {
+    /// Whether the query result was truthy
+    pub passed: bool,
+    /// The query result
+    pub value: serde_json::Value,
+}
+
+impl AssertOutcome {
+    /// Process exit code for this outcome, mirroring `jq -e`
+    pub fn exit_code(&self) -> i32 {
+        if self.passed { EXIT_PASSED } else { EXIT_FAILED }
+    }
+}
+
+/// Evaluate `options.query` against `options.input` and apply truthiness.
+///
+/// `null`, `false`, `0`, `""`, `[]` and `{}` fail the assertion, as does an
+/// input rejected by a filter; everything else passes.
+pub fn execute_assert(options: &CheckOptions) -> Result<AssertOutcome, CliError> {
+    outcome(execute_check(&CheckOptions {
+        syntax_only: false,
+        ..options.clone()
+    })?)
+}
+
+/// Like [`execute_assert`], streaming the JSON input from `reader`.
+pub fn execute_assert_reader<R: Read>(
+    options: &CheckOptions,
+    reader: R,
+) -> Result<AssertOutcome, CliError> {
+    outcome(execute_check_reader(
+        &CheckOptions {
+            syntax_only: false,
+            ..options.clone()
+        },
+        reader,
+    )?)
+}
+
+fn outcome(result: CheckResult) -> Result<AssertOutcome, CliError> {
+    match result {
+        CheckResult::Success { value, .. } => Ok(AssertOutcome {
+            passed: value.is_truthy(),
+            value: clove_to_json(value),
+        }),
+        CheckResult::FilteredOut => Ok(AssertOutcome {
+            passed: false,
+            value: serde_json::Value::Null,
+        }),
+        CheckResult::SyntaxValid => Err(CliError::NoResult),
+    }
+}
@@ -0,0 +1,128 @@
+//! Benchmark queries against a fixed input
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use serde_json::json;
+
+use crate::Value;
+use super::check::compile;
+use super::{CheckOptions, CliError};
+
+/// Options for the bench command
+#[derive(Debug, Clone)]
+pub struct BenchOptions {
+    /// Measured runs per query (at least one run is always measured)
+    pub iterations: usize,
+    /// Unmeasured runs before measuring
+    pub warmup: usize,
+    /// Deny all environment variable access from the queries
+    pub no_env: bool,
+    /// Cache repeated pure subexpressions (see [`Evaluator::with_memoization`](crate::Evaluator::with_memoization))
+    pub memoize: bool,
+    /// Run [`Query::optimize`](crate::Query::optimize) on the queries
+    pub optimize: bool,
+}
+
+impl Default for BenchOptions {
+    fn default() -> Self {
+        BenchOptions {
+            iterations: 100,
+            warmup: 10,
+            no_env: false,
+            memoize: true,
+            optimize: true,
+        }
+    }
+}
+
+/// Timings for one query
+#[derive(Debug, Clone)]
+pub struct BenchReport {
+    pub query: String,
+    pub iterations: usize,
+    pub min: Duration,
+    pub mean: Duration,
+    pub p95: Duration,
+    pub max: Duration,
+}
+
+impl BenchReport {
+    /// JSON form for CI tracking (durations in nanoseconds)
+    pub fn to_json(&self) -> serde_json::Value {
+        json!({
+            "query": self.query,
+            "iterations": self.iterations,
+            "min_ns": self.min.as_nanos() as u64,
+            "mean_ns": self.mean.as_nanos() as u64,
+            "p95_ns": self.p95.as_nanos() as u64,
+            "max_ns": self.max.as_nanos() as u64,
+        })
+    }
+}
+
+impl std::fmt::Display for BenchReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}\n  {} runs: min {:?}  mean {:?}  p95 {:?}  max {:?}",
+            self.query, self.iterations, self.min, self.mean, self.p95, self.max
+        )
+    }
+}
+
+/// Time each query against `input`.
+///
+/// Queries are parsed once; each run evaluates against a fresh copy of the
+/// input, and only evaluation is timed. A query that fails to parse or
+/// evaluate aborts the benchmark. Allocations are not measured, since that
+/// would need a counting global allocator in the host binary.
+pub fn execute_bench(
+    queries: &[String],
+    input: &Value,
+    options: &BenchOptions,
+) -> Result<Vec<BenchReport>, CliError> {
+    queries
+        .iter()
+        .map(|query| {
+            let check = CheckOptions {
+                query: query.clone(),
+                no_env: options.no_env,
+                no_optimize: !options.optimize,
+                ..Default::default()
+            };
+            let compiled = compile(&check)?;
+            let mut evaluator = check.evaluator().with_memoization(options.memoize);
+
+            for _ in 0..options.warmup {
+                compiled
+                    .eval(&mut evaluator, input.clone(), HashMap::new())
+                    .map_err(CliError::Eval)?;
+            }
+
+            let iterations = options.iterations.max(1);
+            let mut samples = Vec::with_capacity(iterations);
+            for _ in 0..iterations {
+                let input = input.clone();
+                let start = Instant::now();
+                let result = compiled.eval(&mut evaluator, input, HashMap::new());
+                samples.push(start.elapsed());
+                // Drop the result outside the timed region
+                drop(result.map_err(CliError::Eval)?);
+            }
+
+            samples.sort();
+            let total: Duration = samples.iter().sum();
+            // Nearest-rank 95th percentile
+            let p95_rank = (samples.len() * 95).div_ceil(100).max(1);
+            Ok(BenchReport {
+                query: query.clone(),
+                iterations: samples.len(),
+                min: samples[0],
+                mean: total / samples.len() as u32,
+                p95: samples[p95_rank - 1],
+                max: samples[samples.len() - 1],
+            })
+        })
+        .collect()
+}
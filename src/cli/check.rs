@@ -1,7 +1,18 @@
 //! Execute clove queries against JSON input
 
-use crate::{Evaluator, Lexer, Parser};
-use super::{CliError, json_to_clove, clove_to_json};
+use std::collections::HashMap;
+use std::io::{BufRead, Read};
+use std::path::PathBuf;
+
+use crate::binary::DataFormat;
+use crate::jsonpath::JsonPath;
+use crate::loader::{QueryLoader, has_directives};
+use crate::optimize::optimize_expr;
+use crate::transform::{Path, extract_path};
+use crate::{
+    DuplicateKeys, EnvPolicy, EvalError, Evaluator, NonFinitePolicy, Lexer, LimitedReader, Parser, PathSelector, QueryOutcome, Value,
+};
+use super::CliError;
 
 /// Options for the check command
 #[derive(Debug, Clone, Default)]
@@ -10,10 +21,124 @@ pub struct CheckOptions {
     pub query: String,
     /// JSON input string
     pub input: Option<String>,
+    /// Run the query on `null` instead of reading any input, to build a
+    /// document from literals and environment variables (like `jq -n`)
+    pub null_input: bool,
     /// Pretty-print the output
     pub pretty: bool,
     /// Only validate syntax, don't execute
     pub syntax_only: bool,
+    /// Deny all environment variable access from the query
+    pub no_env: bool,
+    /// Reject inputs larger than this many bytes
+    pub max_input_size: Option<u64>,
+    /// Fail when a query stage would receive a document of more than this
+    /// many bytes in memory (see [`Evaluator::with_max_document_bytes`])
+    pub max_document_size: Option<u64>,
+    /// Fail when UDF calls nest more than this deep (see
+    /// [`Evaluator::with_max_call_depth`])
+    pub max_call_depth: Option<usize>,
+    /// Print the size of the document each query stage receives to stderr
+    pub explain: bool,
+    /// Interpret `query` as a JSONPath selector instead of a Clove query
+    pub jsonpath: bool,
+    /// Extra directories searched for `%include`d files
+    pub include_paths: Vec<PathBuf>,
+    /// Directory relative `%include` paths resolve against first (the query
+    /// file's directory; the current directory if unset)
+    pub base_dir: Option<PathBuf>,
+    /// Metadata the query can read as `$meta` (e.g. `file`)
+    pub meta: HashMap<String, Value>,
+    /// Parameters the query reads as `@name` (see [`Evaluator::with_params`])
+    pub params: HashMap<String, Value>,
+    /// Run the query as written, without [`Query::optimize`](crate::Query::optimize)
+    pub no_optimize: bool,
+    /// Seed for `.sample()`, `.shuffle()` and `&random[]` (see [`Evaluator::with_seed`])
+    pub seed: Option<u64>,
+    /// Fail instead of ordering values of different types (see
+    /// [`Evaluator::with_strict_ordering`])
+    pub strict_ordering: bool,
+    /// What arithmetic does with NaN and infinite results; under the
+    /// default they are written as null, with a warning on stderr
+    pub non_finite: NonFinitePolicy,
+    /// Combine every JSON document in the input into one array and run the
+    /// query once on it (see [`Value::from_reader_all`])
+    pub slurp: bool,
+    /// Format of the input document; MessagePack and CBOR input is read
+    /// whole rather than pruned while parsing (see [`crate::binary`])
+    pub input_format: DataFormat,
+    /// What to do with an object that repeats a key, in the JSON input and
+    /// in the query's object literals
+    pub duplicate_keys: DuplicateKeys,
+    /// Named queries to run instead of `query`, all against one parse of
+    /// the input; the result is an object of their results by name, with
+    /// null for a query whose filter rejects the input
+    pub selects: Vec<(String, String)>,
+    /// Paths in the result, as written in a delete such as
+    /// `$[users][*][password]`, whose values are replaced by
+    /// [`REDACTED`] before output
+    pub redact: Vec<String>,
+}
+
+/// What `--redact` writes in place of a value
+pub const REDACTED: &str = "[REDACTED]";
+
+impl CheckOptions {
+    /// An evaluator configured by these options
+    pub(crate) fn evaluator(&self) -> Evaluator {
+        let mut evaluator = Evaluator::new().with_params(self.params.clone());
+        if let Some(seed) = self.seed {
+            evaluator = evaluator.with_seed(seed);
+        }
+        if self.strict_ordering {
+            evaluator = evaluator.with_strict_ordering(true);
+        }
+        evaluator = evaluator.with_non_finite_policy(self.non_finite);
+        if let Some(limit) = self.max_document_size {
+            evaluator = evaluator.with_max_document_bytes(usize::try_from(limit).unwrap_or(usize::MAX));
+        }
+        if let Some(limit) = self.max_call_depth {
+            evaluator = evaluator.with_max_call_depth(limit);
+        }
+        if self.explain {
+            evaluator = evaluator.with_eval_stats(true);
+        }
+        if self.no_env {
+            evaluator.with_env_policy(EnvPolicy::DenyAll)
+        } else {
+            evaluator
+        }
+    }
+
+    /// A parser for `query` with these options
+    fn parser(&self, query: &str) -> Result<Parser, CliError> {
+        let parser = Parser::new(Lexer::new(query)).map_err(CliError::Parse)?;
+        Ok(parser.with_duplicate_keys(self.duplicate_keys))
+    }
+
+    /// The paths of `redact`
+    fn redactions(&self) -> Result<Vec<Path>, CliError> {
+        self.redact
+            .iter()
+            .map(|target| {
+                let path = self
+                    .parser(target)
+                    .and_then(|mut parser| parser.parse_target().map_err(CliError::Parse))
+                    .and_then(|expr| extract_path(&expr).map_err(CliError::Eval));
+                path.map_err(|error| CliError::Redact { target: target.clone(), error: Box::new(error) })
+            })
+            .collect()
+    }
+
+    /// Parse a query that starts with `%include` directives
+    fn load_query(&self, query: &str) -> Result<crate::Query, CliError> {
+        let loader = self
+            .include_paths
+            .iter()
+            .fold(QueryLoader::new(), |loader, dir| loader.with_search_path(dir))
+            .with_duplicate_keys(self.duplicate_keys);
+        Ok(loader.load_str(query, self.base_dir.as_deref())?)
+    }
 }
 
 /// Result of a check operation
@@ -21,54 +146,435 @@ pub struct CheckOptions {
 pub enum CheckResult {
     /// Syntax validation passed
     SyntaxValid,
-    /// Query executed successfully with JSON output
-    Success(serde_json::Value),
+    /// Query executed successfully, with its result
+    Success {
+        value: Value,
+        /// Whether NaN or infinite numbers in the result, which JSON cannot
+        /// represent, were replaced by null
+        non_finite: bool,
+    },
+    /// A filter rejected the input, so there is no output
+    FilteredOut,
 }
 
 /// Detect whether a query string is a pipeline query or simple expression
-fn is_pipeline_query(query: &str) -> bool {
+pub(crate) fn is_pipeline_query(query: &str) -> bool {
     // A query uses single | for piping, but not || for logical OR
     query.contains(" | ") || (query.contains('|') && !query.contains("||"))
 }
 
+/// Parse a byte size such as `1048576`, `512K`, `10M` or `2G` (binary units)
+pub fn parse_byte_size(s: &str) -> Result<u64, String> {
+    let s = s.trim();
+    let (digits, multiplier) = match s.char_indices().last() {
+        Some((i, 'k' | 'K')) => (&s[..i], 1u64 << 10),
+        Some((i, 'm' | 'M')) => (&s[..i], 1 << 20),
+        Some((i, 'g' | 'G')) => (&s[..i], 1 << 30),
+        _ => (s, 1),
+    };
+    digits
+        .parse::<u64>()
+        .ok()
+        .and_then(|n| n.checked_mul(multiplier))
+        .ok_or_else(|| format!("invalid size '{}' (expected e.g. 1048576, 512K, 10M, 2G)", s))
+}
+
+/// Parse a `--select` argument, `NAME=QUERY`
+pub fn parse_select(s: &str) -> Result<(String, String), String> {
+    match s.split_once('=') {
+        Some((name, query))
+            if !name.is_empty()
+                && name.chars().all(|c| c.is_alphanumeric() || c == '_' || c == '-') =>
+        {
+            Ok((name.to_string(), query.to_string()))
+        }
+        _ => Err(format!("invalid select '{}' (expected NAME=QUERY, e.g. version=$[meta][version])", s)),
+    }
+}
+
 /// Execute a clove check operation
 pub fn execute_check(options: &CheckOptions) -> Result<CheckResult, CliError> {
-    let query = &options.query;
-    let is_query = is_pipeline_query(query);
+    if options.syntax_only {
+        return check_syntax(options);
+    }
 
-    let lexer = Lexer::new(query);
-    let mut parser = Parser::new(lexer).map_err(CliError::Parse)?;
+    if options.null_input {
+        return execute_check_reader(options, std::io::empty());
+    }
+    let json_str = options.input.as_ref().ok_or(CliError::NoInput)?;
+    execute_check_reader(options, json_str.as_bytes())
+}
 
+/// Execute a clove check operation, streaming the JSON input from `reader`.
+///
+/// `options.input` is ignored, and so is `reader` with `options.null_input`.
+/// With `options.jsonpath` the result is the
+/// array of nodes the selector matches. With `options.slurp` the input may
+/// hold any number of documents, and the query sees them as one array.
+/// Otherwise, when the query only reads constant
+/// paths, such as `$[users][0][name]` or `$ | ?($[ok]) | !({"v": $[meta][v]})`,
+/// only the subtrees it reads are kept while parsing (see [`PathSelector`]).
+pub fn execute_check_reader<R: Read>(
+    options: &CheckOptions,
+    reader: R,
+) -> Result<CheckResult, CliError> {
     if options.syntax_only {
-        let result = if is_query {
-            parser.parse_query().map(|_| ())
-        } else {
-            parser.parse().map(|_| ())
+        return check_syntax(options);
+    }
+
+    if options.jsonpath {
+        let path = JsonPath::parse(&options.query)?;
+        let input_value = read_document(reader, &PathSelector::default(), options)?;
+        let nodes = Value::Array(path.evaluate(&input_value));
+        let (value, non_finite) = to_output(nodes, &options.redactions()?);
+        return Ok(CheckResult::Success { value, non_finite });
+    }
+
+    if !options.selects.is_empty() {
+        let selects = compile_selects(options)?;
+        let selector = selects
+            .iter()
+            .map(|(_, compiled)| compiled.selector())
+            .reduce(PathSelector::union)
+            .unwrap_or_default();
+        let input_value = read_document(reader, &selector, options)?;
+        let result = eval_selects(&selects, &mut options.evaluator(), input_value, &options.meta)?;
+        let (value, non_finite) = to_output(result, &options.redactions()?);
+        return Ok(CheckResult::Success { value, non_finite });
+    }
+
+    let compiled = compile(options)?;
+    let redactions = options.redactions()?;
+    let input_value = read_document(reader, &compiled.selector(), options)?;
+
+    let mut evaluator = options.evaluator();
+    let outcome = compiled.eval(&mut evaluator, input_value, options.meta.clone());
+    if options.explain {
+        eprint!("{}", explain_stages(&evaluator));
+    }
+
+    Ok(match outcome.map_err(CliError::Eval)? {
+        QueryOutcome::Value(result) => {
+            let (value, non_finite) = to_output(result, &redactions);
+            CheckResult::Success { value, non_finite }
+        }
+        QueryOutcome::FilteredOut => CheckResult::FilteredOut,
+    })
+}
+
+/// Execute a clove check against newline-delimited JSON, one record per line.
+///
+/// `emit` receives each record's result in order, and whether NaN or
+/// infinite numbers in it were replaced by null. Blank lines and records
+/// rejected by a filter are skipped.
+/// Queries can read the 1-based line number as `$meta[line]`, alongside
+/// `options.meta`. The first failing record stops the run with
+/// [`CliError::Record`].
+pub fn execute_check_ndjson<R: BufRead>(
+    options: &CheckOptions,
+    reader: R,
+    mut emit: impl FnMut(Value, bool) -> Result<(), CliError>,
+) -> Result<(), CliError> {
+    if options.syntax_only {
+        check_syntax(options)?;
+        return Ok(());
+    }
+
+    let compiled = compile(options)?;
+    let redactions = options.redactions()?;
+    let selector = compiled.selector();
+    let mut evaluator = options.evaluator();
+
+    for (index, line) in reader.lines().enumerate() {
+        let line_number = index + 1;
+        let at_line = |error| CliError::Record {
+            line: line_number,
+            error: Box::new(error),
         };
 
-        return match result {
-            Ok(()) => Ok(CheckResult::SyntaxValid),
-            Err(e) => Err(CliError::Parse(e)),
+        let line = line.map_err(|e| at_line(CliError::Io(e)))?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let record = read_input(line.as_bytes(), options.max_input_size, |reader| {
+            Value::from_reader_selecting_with(reader, &selector, options.duplicate_keys)
+        })
+        .map_err(at_line)?;
+
+        let mut meta = options.meta.clone();
+        meta.insert("line".to_string(), Value::Integer(line_number as i64));
+        let outcome = compiled.eval(&mut evaluator, record, meta);
+        if options.explain {
+            eprint!("Line {}:\n{}", line_number, explain_stages(&evaluator));
+        }
+        let outcome = outcome.map_err(|e| at_line(CliError::Eval(e)))?;
+        if let QueryOutcome::Value(result) = outcome {
+            let (value, non_finite) = to_output(result, &redactions);
+            emit(value, non_finite)?;
+        }
+    }
+    Ok(())
+}
+
+/// `result` with the values at `redactions` replaced, and whether it held
+/// NaN or infinite numbers, which JSON cannot represent and which become
+/// null
+fn to_output(mut result: Value, redactions: &[Path]) -> (Value, bool) {
+    let redacted = Value::String(REDACTED.to_string());
+    for path in redactions {
+        result.replace_matching(path, &redacted);
+    }
+    fn replace_non_finite(value: &mut Value) -> bool {
+        match value {
+            Value::Float(n) if !n.is_finite() => {
+                *value = Value::Null;
+                true
+            }
+            // Every element is visited, not just up to the first found
+            Value::Array(items) => items.iter_mut().map(replace_non_finite).fold(false, |a, b| a | b),
+            Value::Object(fields) => fields.values_mut().map(replace_non_finite).fold(false, |a, b| a | b),
+            _ => false,
+        }
+    }
+    let non_finite = replace_non_finite(&mut result);
+    (result, non_finite)
+}
+
+/// One line per stage: its number, what it is, the approximate size of the
+/// document it received and how long it took; then the size of the result
+/// and what evaluation did overall
+fn explain_stages(evaluator: &Evaluator) -> String {
+    let stats = evaluator.eval_stats().unwrap_or_default();
+    let mut report: String = stats
+        .statements
+        .iter()
+        .enumerate()
+        .map(|(i, timing)| {
+            format!(
+                "  {:>3}  {:<20} {:>12}{:>10.3} ms\n",
+                i + 1,
+                timing.stage,
+                format_bytes(timing.bytes),
+                timing.elapsed.as_secs_f64() * 1000.0
+            )
+        })
+        .collect();
+    report.push_str(&format!("       {:<20} {:>12}\n", "result", format_bytes(stats.result_bytes)));
+    report.push_str(&format!(
+        "  {} expressions, max depth {}, about {} allocated\n",
+        stats.expressions,
+        stats.max_depth,
+        format_bytes(stats.allocated_bytes)
+    ));
+    if !stats.method_calls.is_empty() {
+        let calls: Vec<_> = stats.method_calls.iter().map(|(name, n)| format!("{} {}", name, n)).collect();
+        report.push_str(&format!("  method calls: {}\n", calls.join(", ")));
+    }
+    report
+}
+
+/// A byte count in binary units, e.g. `812 B` or `3.4 MiB`
+fn format_bytes(bytes: usize) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
+/// A parsed query: a full pipeline or a standalone expression
+pub(crate) enum CompiledQuery {
+    Pipeline(crate::Query),
+    Expression(crate::Expr),
+}
+
+impl CompiledQuery {
+    pub(crate) fn eval(
+        &self,
+        evaluator: &mut Evaluator,
+        input: Value,
+        meta: HashMap<String, Value>,
+    ) -> Result<QueryOutcome, EvalError> {
+        match self {
+            CompiledQuery::Pipeline(query) => evaluator.eval_query_outcome(query, input, meta),
+            CompiledQuery::Expression(expr) => evaluator
+                .eval_expression_with_meta(expr, input, meta)
+                .map(QueryOutcome::Value),
+        }
+    }
+
+    /// The parts of the input this query reads
+    pub(crate) fn selector(&self) -> PathSelector {
+        match self {
+            CompiledQuery::Pipeline(query) => PathSelector::from_query(query),
+            CompiledQuery::Expression(expr) => PathSelector::from_expr(expr),
+        }
+        .unwrap_or_default()
+    }
+}
+
+/// Parse `options.query`, resolving `%include` directives if present, and
+/// optimize it unless `options.no_optimize`
+pub(crate) fn compile(options: &CheckOptions) -> Result<CompiledQuery, CliError> {
+    compile_source(options, &options.query)
+}
+
+/// Compile each of `options.selects`
+fn compile_selects(options: &CheckOptions) -> Result<Vec<(String, CompiledQuery)>, CliError> {
+    options
+        .selects
+        .iter()
+        .map(|(name, query)| match compile_source(options, query) {
+            Ok(compiled) => Ok((name.clone(), compiled)),
+            Err(error) => Err(CliError::Select { name: name.clone(), error: Box::new(error) }),
+        })
+        .collect()
+}
+
+/// Run each compiled select on `input`, collecting the results by name
+fn eval_selects(
+    selects: &[(String, CompiledQuery)],
+    evaluator: &mut Evaluator,
+    input: Value,
+    meta: &HashMap<String, Value>,
+) -> Result<Value, CliError> {
+    let mut results = HashMap::with_capacity(selects.len());
+    for (name, compiled) in selects {
+        let result = match compiled.eval(evaluator, input.clone(), meta.clone()) {
+            Ok(QueryOutcome::Value(value)) => value,
+            Ok(QueryOutcome::FilteredOut) => Value::Null,
+            Err(error) => {
+                let error = Box::new(CliError::Eval(error));
+                return Err(CliError::Select { name: name.clone(), error });
+            }
         };
+        results.insert(name.clone(), result);
     }
+    Ok(Value::Object(results))
+}
 
-    let json_str = options.input.as_ref().ok_or(CliError::NoInput)?;
+/// The fingerprint of the query in `options` (see
+/// [`Query::fingerprint`](crate::Query::fingerprint)), with any `%include`d
+/// UDFs. An expression such as `$[a]` hashes like `$ | !($[a])`, which
+/// evaluates the same.
+pub fn execute_query_hash(options: &CheckOptions) -> Result<String, CliError> {
+    let query = match parse_source(options, &options.query)? {
+        CompiledQuery::Pipeline(query) => query,
+        CompiledQuery::Expression(expr) => {
+            crate::Query { udfs: vec![], statements: vec![], output: Some(expr) }
+        }
+    };
+    Ok(query.fingerprint())
+}
 
-    let json_value: serde_json::Value =
-        serde_json::from_str(json_str).map_err(CliError::Json)?;
+/// Parse `query` with the settings in `options`
+fn parse_source(options: &CheckOptions, query: &str) -> Result<CompiledQuery, CliError> {
+    if has_directives(query) {
+        return options.load_query(query).map(CompiledQuery::Pipeline);
+    }
+    let mut parser = options.parser(query)?;
+    if is_pipeline_query(query) {
+        parser.parse_query().map(CompiledQuery::Pipeline)
+    } else {
+        parser.parse().map(CompiledQuery::Expression)
+    }
+    .map_err(CliError::Parse)
+}
 
-    let input_value = json_to_clove(json_value);
+/// Compile `query` with the settings in `options`
+fn compile_source(options: &CheckOptions, query: &str) -> Result<CompiledQuery, CliError> {
+    let compiled = parse_source(options, query)?;
+    Ok(match compiled {
+        _ if options.no_optimize => compiled,
+        CompiledQuery::Pipeline(query) => CompiledQuery::Pipeline(query.optimize()),
+        CompiledQuery::Expression(expr) => CompiledQuery::Expression(optimize_expr(expr)),
+    })
+}
 
-    let mut evaluator = Evaluator::new();
-    let result = if is_query {
-        let q = parser.parse_query().map_err(CliError::Parse)?;
-        evaluator.eval_query(&q, input_value)
+/// Validate the query, reporting every syntax error in a pipeline rather
+/// than just the first
+fn check_syntax(options: &CheckOptions) -> Result<CheckResult, CliError> {
+    let query = &options.query;
+    if !options.selects.is_empty() {
+        compile_selects(options)?;
+    } else if options.jsonpath {
+        JsonPath::parse(query)?;
+    } else if is_pipeline_query(query) && !has_directives(query) {
+        let (_, mut errors) = options.parser(query)?.parse_query_recovering();
+        match errors.len() {
+            0 => {}
+            1 => return Err(CliError::Parse(errors.remove(0))),
+            _ => return Err(CliError::ParseErrors(errors)),
+        }
     } else {
-        let expr = parser.parse().map_err(CliError::Parse)?;
-        evaluator.eval_expression(&expr, input_value)
+        compile(options)?;
+    }
+    Ok(CheckResult::SyntaxValid)
+}
+
+/// The input document, pruned by `selector`, or with `options.slurp` the
+/// array of every document in the input, read whole
+fn read_document<R: Read>(
+    reader: R,
+    selector: &PathSelector,
+    options: &CheckOptions,
+) -> Result<Value, CliError> {
+    if options.null_input {
+        return Ok(Value::Null);
     }
-    .map_err(CliError::Eval)?;
+    if options.input_format != DataFormat::Json {
+        return read_binary(reader, options);
+    }
+    read_input(reader, options.max_input_size, |reader| match options.slurp {
+        true => Value::from_reader_all_with(reader, options.duplicate_keys).map(Value::Array),
+        false => Value::from_reader_selecting_with(reader, selector, options.duplicate_keys),
+    })
+}
 
-    let output = clove_to_json(result);
-    Ok(CheckResult::Success(output))
+/// A MessagePack or CBOR document, or with `options.slurp` the array of
+/// every document in the input
+fn read_binary<R: Read>(reader: R, options: &CheckOptions) -> Result<Value, CliError> {
+    let mut bytes = Vec::new();
+    match options.max_input_size {
+        None => { reader }.read_to_end(&mut bytes)?,
+        Some(limit) => {
+            let mut limited = LimitedReader::new(reader, limit);
+            let read = limited.read_to_end(&mut bytes);
+            if limited.exceeded() {
+                return Err(CliError::InputTooLarge(limit));
+            }
+            read?
+        }
+    };
+    let format = options.input_format;
+    Ok(match options.slurp {
+        true => Value::Array(format.decode_all(&bytes)?),
+        false => format.decode(&bytes)?,
+    })
+}
+
+fn read_input<R: Read>(
+    reader: R,
+    max_input_size: Option<u64>,
+    parse: impl FnOnce(&mut dyn Read) -> Result<Value, serde_json::Error>,
+) -> Result<Value, CliError> {
+    match max_input_size {
+        None => parse(&mut { reader }).map_err(CliError::Json),
+        Some(limit) => {
+            let mut limited = LimitedReader::new(reader, limit);
+            parse(&mut limited).map_err(|e| {
+                if limited.exceeded() {
+                    CliError::InputTooLarge(limit)
+                } else {
+                    CliError::Json(e)
+                }
+            })
+        }
+    }
 }
@@ -0,0 +1,167 @@
+//! Gzip and zstd compressed input, and gzip output (`check --compress`)
+//!
+//! Input is recognized by its magic bytes, or for a file by its `.gz` or
+//! `.zst` extension, and decompressed as it is read, so limits such as
+//! `--max-input-size` apply to the decompressed document. Gzip needs the
+//! `gzip` feature and zstd the `zstd` feature; compressed input without the
+//! feature is an error rather than a confusing JSON one.
+
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::path::Path;
+
+use super::CliError;
+
+/// A compression format the CLI can read
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    /// Gzip, RFC 1952 (needs the `gzip` feature)
+    Gzip,
+    /// Zstandard (needs the `zstd` feature)
+    Zstd,
+}
+
+impl Compression {
+    /// The longest magic number, in bytes
+    const MAGIC_LEN: usize = 4;
+
+    /// The compression `bytes` start with, if any
+    pub fn sniff(bytes: &[u8]) -> Option<Compression> {
+        if bytes.starts_with(&[0x1f, 0x8b]) {
+            Some(Compression::Gzip)
+        } else if bytes.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+            Some(Compression::Zstd)
+        } else {
+            None
+        }
+    }
+
+    /// The compression a file name's extension says, if any
+    pub fn from_path(path: &Path) -> Option<Compression> {
+        match path.extension()?.to_str()? {
+            "gz" | "gzip" => Some(Compression::Gzip),
+            "zst" | "zstd" => Some(Compression::Zstd),
+            _ => None,
+        }
+    }
+
+    /// The name of the feature this format needs
+    fn feature(self) -> &'static str {
+        match self {
+            Compression::Gzip => "gzip",
+            Compression::Zstd => "zstd",
+        }
+    }
+}
+
+impl fmt::Display for Compression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.feature())
+    }
+}
+
+/// `reader`, decompressed if it starts with the magic bytes of a
+/// [`Compression`] format
+pub fn decompress<'a>(mut reader: impl Read + 'a) -> Result<Box<dyn Read + 'a>, CliError> {
+    let mut magic = [0u8; Compression::MAGIC_LEN];
+    let mut len = 0;
+    // A pipe may hand over fewer bytes than asked for
+    while len < magic.len() {
+        match reader.read(&mut magic[len..]) {
+            Ok(0) => break,
+            Ok(n) => len += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(CliError::Io(e)),
+        }
+    }
+    let compression = Compression::sniff(&magic[..len]);
+    decoder(io::Cursor::new(magic).take(len as u64).chain(reader), compression)
+}
+
+/// Open the file at `path` for reading, decompressing it as its extension
+/// or its first bytes say
+pub fn open_input(path: &Path) -> Result<Box<dyn Read>, CliError> {
+    let file = File::open(path).map_err(CliError::Io)?;
+    match Compression::from_path(path) {
+        Some(compression) => decoder(file, Some(compression)),
+        None => decompress(file),
+    }
+}
+
+fn decoder<'a>(
+    input: impl Read + 'a,
+    compression: Option<Compression>,
+) -> Result<Box<dyn Read + 'a>, CliError> {
+    match compression {
+        None => Ok(Box::new(input)),
+        // Concatenated gzip members, as `cat a.gz b.gz` makes, read as one
+        #[cfg(feature = "gzip")]
+        Some(Compression::Gzip) => Ok(Box::new(flate2::read::MultiGzDecoder::new(input))),
+        #[cfg(feature = "zstd")]
+        Some(Compression::Zstd) => Ok(Box::new(zstd::Decoder::new(input).map_err(CliError::Io)?)),
+        #[allow(unreachable_patterns)]
+        Some(compression) => {
+            let _ = input;
+            Err(CliError::Compression(compression))
+        }
+    }
+}
+
+/// Where the CLI writes results: the underlying writer as is, or through a
+/// gzip encoder. Call [`finish`](Self::finish) once everything is written,
+/// to complete the gzip stream.
+pub enum OutputStream<W: Write> {
+    /// Uncompressed
+    Plain(W),
+    /// Gzip-compressed (needs the `gzip` feature)
+    #[cfg(feature = "gzip")]
+    Gzip(flate2::write::GzEncoder<W>),
+}
+
+impl<W: Write> OutputStream<W> {
+    /// Write to `writer`, gzip-compressed if `compress`
+    pub fn new(writer: W, compress: bool) -> Result<Self, CliError> {
+        match compress {
+            false => Ok(OutputStream::Plain(writer)),
+            #[cfg(feature = "gzip")]
+            true => {
+                let encoder = flate2::write::GzEncoder::new(writer, flate2::Compression::default());
+                Ok(OutputStream::Gzip(encoder))
+            }
+            #[cfg(not(feature = "gzip"))]
+            true => Err(CliError::Compression(Compression::Gzip)),
+        }
+    }
+
+    /// Write out anything buffered, and the end of the gzip stream, and
+    /// return the underlying writer
+    pub fn finish(self) -> io::Result<W> {
+        match self {
+            OutputStream::Plain(mut writer) => writer.flush().map(|()| writer),
+            #[cfg(feature = "gzip")]
+            OutputStream::Gzip(encoder) => {
+                let mut writer = encoder.finish()?;
+                writer.flush().map(|()| writer)
+            }
+        }
+    }
+}
+
+impl<W: Write> Write for OutputStream<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            OutputStream::Plain(writer) => writer.write(buf),
+            #[cfg(feature = "gzip")]
+            OutputStream::Gzip(encoder) => encoder.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            OutputStream::Plain(writer) => writer.flush(),
+            #[cfg(feature = "gzip")]
+            OutputStream::Gzip(encoder) => encoder.flush(),
+        }
+    }
+}
@@ -34,6 +34,7 @@ pub fn clove_to_json(v: Value) -> serde_json::Value {
             .map(serde_json::Value::Number)
             .unwrap_or(serde_json::Value::Null),
         Value::String(s) => serde_json::Value::String(s),
+        Value::Bytes(b) => serde_json::Value::String(crate::value::encode_base64(&b)),
         Value::Array(arr) => {
             serde_json::Value::Array(arr.into_iter().map(clove_to_json).collect())
         }
@@ -16,6 +16,46 @@ pub enum DocCategory {
 }
 
 impl DocCategory {
+    /// Every category, in the order `clove docs` lists them
+    pub const ALL: [Self; 8] = [
+        Self::Syntax,
+        Self::Operators,
+        Self::ArrayMethods,
+        Self::StringMethods,
+        Self::ObjectMethods,
+        Self::Scopes,
+        Self::Types,
+        Self::Queries,
+    ];
+
+    /// The name `clove docs` lists the category under
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Syntax => "syntax",
+            Self::Operators => "operators",
+            Self::ArrayMethods => "array-methods",
+            Self::StringMethods => "string-methods",
+            Self::ObjectMethods => "object-methods",
+            Self::Scopes => "scopes",
+            Self::Types => "types",
+            Self::Queries => "queries",
+        }
+    }
+
+    /// One-line description of the category
+    pub fn summary(self) -> &'static str {
+        match self {
+            Self::Syntax => "Root access, field access, array indexing, and basic notation",
+            Self::Operators => "Comparison, logical, arithmetic, and existence operators",
+            Self::ArrayMethods => "Filter, map, aggregate, and transform methods for arrays",
+            Self::StringMethods => "Text manipulation and inspection methods for strings",
+            Self::ObjectMethods => "Methods for working with object keys and values",
+            Self::Scopes => "Reference scopes: $ (root), @ (current), and environment vars",
+            Self::Types => "Type system, type checking, and coercion rules",
+            Self::Queries => "Pipe syntax for document-level filter and transform operations",
+        }
+    }
+
     /// Parse category name from string
     #[allow(clippy::should_implement_trait)]
     pub fn from_str(s: &str) -> Option<Self> {
@@ -81,6 +121,38 @@ pub fn get_doc_category(name: &str) -> Result<&'static str, CliError> {
     }
 }
 
+/// The entries for `.name()` in the method docs, without their indentation.
+/// A method documented for several types has one entry per type.
+pub(crate) fn method_doc(name: &str) -> Vec<String> {
+    let call = format!(".{}(", name);
+    let mut entries = Vec::new();
+    for doc in [ARRAY_METHODS_DOC, STRING_METHODS_DOC, OBJECT_METHODS_DOC] {
+        let mut lines = doc.lines().peekable();
+        while let Some(line) = lines.next() {
+            if !(line.starts_with("  .") && line.contains(&call)) {
+                continue;
+            }
+            let mut entry = vec![line.trim()];
+            while let Some(next) = lines.next_if(|next| next.is_empty() || next.starts_with("   ")) {
+                entry.push(next.get(2..).unwrap_or(""));
+            }
+            while entry.last() == Some(&"") {
+                entry.pop();
+            }
+            entries.push(entry.join("\n"));
+        }
+    }
+    entries
+}
+
+/// The line describing `symbol` in the operator docs, such as
+/// `==    Equal (strict type matching)`
+pub(crate) fn operator_doc(symbol: &str) -> Option<&'static str> {
+    OPERATORS_DOC.lines().map(str::trim).find(|line| {
+        line.strip_prefix(symbol).is_some_and(|rest| rest.starts_with("  "))
+    })
+}
+
 const SYNTAX_DOC: &str = r#"SYNTAX - Basic Access Notation
 
 ROOT ACCESS
@@ -211,6 +283,40 @@ ARITHMETIC OPERATORS
     - Modulo by zero raises an error
     - Cannot mix strings with numbers in arithmetic
 
+ERROR HANDLING
+  try expr catch fallback
+    The value of expr, or of fallback if evaluating expr fails.
+
+  Examples:
+    $[rows].map(try @[price] * @[qty] catch null)
+    (try $[total] / $[count] catch 0) + 1
+
+  Constraints:
+    - Errors in fallback are not caught
+    - Size limits and denied environment variables are not caught
+
+MATCH EXPRESSIONS
+  match value { pattern => result, ... }
+    The result of the first arm whose pattern fits the value, or
+    null if none does.
+
+  Patterns:
+    _                   Anything
+    name                Anything, bound to name (or @name) in the arm
+    "click"  42  null   A value equal to the literal
+    {"type": t}         An object with these keys (others are ignored)
+    [a, b]  [a, ...]    An array of exactly two / at least one element
+
+  Guards:
+    pattern if condition => result
+
+  Example:
+    match $[event] {
+      {"type": "click", "x": x} => x,
+      {"type": "scroll"} => 0,
+      _ => null
+    }
+
 OPERATOR PRECEDENCE (highest to lowest)
   1. !           Unary NOT
   2. * / %       Multiplicative
@@ -238,15 +344,33 @@ ELEMENT ACCESS
     Returns the number of elements.
     Example: $[items].length()  =>  3
 
+  .count(predicate?)
+    Returns the number of elements, or with a predicate the number of
+    elements it matches, in one pass.
+    Example: $[jobs].count(@[status] == "failed")  =>  2
+
 SEARCHING
   .contains(value)
     Returns true if the array contains the value.
     Example: $[tags].contains("urgent")  =>  true/false
 
+  .contains_all(list) / .contains_any(list)
+    Returns true if all / any of the list items are elements.
+    Example: $[tags].contains_all(["urgent", "bug"])  =>  true/false
+
     Constraints:
-      - Uses strict equality for comparison
+      - Uses strict equality for comparison (deep for arrays/objects)
       - Works with any value type
 
+  .find(condition) / .find_index(condition)
+    The first element where condition is true / its index, or null.
+    Stops at the first match.
+    Example: $[users].find(@[id] == 42)
+
+  .index_of(value) / .last_index_of(value)
+    Index of the first / last element equal to value, or null.
+    Example: [3, 1, 3].last_index_of(3)  =>  2
+
 FILTERING
   .filter(condition)
     Returns elements where condition is true.
@@ -275,32 +399,69 @@ TRANSFORMATION
       - Returns array of same length
       - Can return any type per element
 
+  .flat_map(expression)
+    Maps each element and concatenates the results into one array, in a
+    single pass. A result that is not an array is kept as one element.
+    Example: $[orders].flat_map(@[items])  =>  every item of every order
+
 AGGREGATION
   .sum()
     Sum of all numeric elements.
     Example: $[prices].sum()  =>  150
 
   .min()
-    Minimum value (works with numbers and strings).
+    Minimum value (any types, using the same ordering as .sort()).
     Example: $[scores].min()  =>  42
 
   .max()
     Maximum value.
     Example: $[scores].max()  =>  100
 
+  .min_by(key) / .max_by(key)
+    The element with the smallest / largest key, in one pass; ties keep the first.
+    Example: $[items].min_by(@[price])  =>  the cheapest item
+
+  .arg_min(key?) / .arg_max(key?)
+    Index of the smallest / largest element (or key), or null if empty.
+    Example: [3, 1, 2].arg_min()  =>  1
+
   .avg()
     Average of numeric elements (always returns float).
     Example: $[scores].avg()  =>  75.5
 
+  .median() / .percentile(p)
+    Median, or the p-th percentile (0-100), interpolating between ranks.
+    Example: $[latency_ms].percentile(95)  =>  412.5
+
+  .variance() / .stddev()
+    Population variance and standard deviation.
+    Example: [2, 4, 4, 4, 5, 5, 7, 9].stddev()  =>  2.0
+
+  .stats()
+    Summary object: {count, min, max, mean, median, stddev}.
+    Example: [1, 2, 3, 4].stats()
+      =>  {"count": 4, "min": 1, "max": 4, "mean": 2.5, "median": 2.5,
+           "stddev": 1.118...}
+
     Constraints:
       - Empty array returns null for min/max, 0 for sum, null for avg
-      - Non-numeric elements are skipped in sum/avg
+        and the statistics above (.stats() gives count 0 and nulls)
+      - Non-numeric elements are skipped in sum/avg and the statistics
 
 ORDERING
   .sort()
     Sort ascending.
     Example: $[numbers].sort()  =>  [1, 2, 3]
 
+  .sort(key, key, ...)
+    Sort ascending by one or more keys; later keys break ties.
+    Example: $[people].sort(@[last], @[first])
+
+  .sort_by([spec, ...])
+    Multi-key sort with per-key direction. A spec is a key expression
+    or {"key": <expr>, "desc": true}.
+    Example: $[people].sort_by([{"key": @[age], "desc": true}, @[last]])
+
   .sort_desc()
     Sort descending.
     Example: $[numbers].sort_desc()  =>  [3, 2, 1]
@@ -310,13 +471,21 @@ ORDERING
     Example: $[items].reverse()
 
     Constraints:
-      - Sorts by natural ordering (numbers, then strings)
-      - Mixed types: numbers < strings
+      - Sorting is stable (equal keys keep their order)
+      - Sorts by natural ordering within a type
+      - Mixed types: null < boolean < number < string < array < object
+      - Arrays compare element-wise, objects entry-wise in key order
+      - With --strict-ordering, mixed types are an error instead
 
 SET OPERATIONS
   .unique()
-    Remove duplicate values (preserves first occurrence).
-    Example: [1, 2, 1, 3].unique()  =>  [1, 2, 3]
+    Remove duplicate values (preserves first occurrence). Values compare
+    as with ==, so 1 and 1.0 are duplicates.
+    Example: [1, 2, 1.0, 3].unique()  =>  [1, 2, 3]
+
+  .unique_by(key)
+    Remove elements whose key was already seen (keeps first occurrence).
+    Example: $[events].unique_by(@[id])
 
   .flatten()
     Flatten nested arrays one level deep.
@@ -325,6 +494,47 @@ SET OPERATIONS
     Constraints:
       - Non-array elements are kept as-is
       - Only flattens one level
+
+SLICING AND BATCHING
+  .take(n) / .skip(n)
+    First n elements / all but the first n.
+    Example: $[items].skip(20).take(10)
+
+  .sample(n) / .shuffle()
+    n elements chosen at random (in their original order) / all elements
+    in random order. Use 'clove check --seed N' for repeatable results.
+    Example: $[events].sample(10)
+
+  .take_while(condition) / .skip_while(condition)
+    Leading elements matching the condition / everything after them.
+    Example: $[readings].take_while(@ < 100)
+
+  .chunk(n)
+    Consecutive arrays of n elements (last may be shorter).
+    Example: [1, 2, 3, 4, 5].chunk(2)  =>  [[1, 2], [3, 4], [5]]
+
+  .window(n)
+    Sliding windows of n consecutive elements.
+    Example: [1, 2, 3, 4].window(3)  =>  [[1, 2, 3], [2, 3, 4]]
+
+    Constraints:
+      - n must be a non-negative integer (at least 1 for chunk/window)
+
+TIME WINDOWS
+  .bucket_by_time(timestamp, window)
+    Groups elements by the time window their timestamp falls in, as
+    {"start", "end", "count", "items"} objects in time order.
+    Example: $[logs].bucket_by_time(@[ts], "5m").map([@[start], @[count]])
+
+  .rate_per(interval)
+    Counts an array of timestamps per interval, from the earliest to the
+    latest, as {"start", "count"} objects (including empty intervals).
+    Example: $[logs].filter(@[level] == "error").map(@[ts]).rate_per("1m")
+
+    Constraints:
+      - Timestamps are RFC 3339 strings ("2024-05-01T10:05:00Z") or Unix
+        seconds; windows start and end in UTC, aligned to the epoch
+      - Durations are a whole number and ms, s, m, h, d or w ("90s", "1h")
 "#;
 
 const STRING_METHODS_DOC: &str = r#"STRING-METHODS - Text Manipulation and Inspection
@@ -343,6 +553,10 @@ SEARCHING
     Returns true if string contains the substring.
     Example: $[name].contains("Alice")  =>  true/false
 
+  .contains_all(list) / .contains_any(list)
+    Returns true if all / any of the substrings occur.
+    Example: $[msg].contains_any(["error", "fatal"])  =>  true/false
+
     Constraints:
       - Case-sensitive
       - Empty substring always returns true
@@ -372,6 +586,24 @@ CASE CONVERSION
       - Uses Unicode case mapping
       - Non-alphabetic characters unchanged
 
+  .title()
+    Capitalize the first letter of every word, lowercase the rest.
+    Example: "hello wORLD".title()  =>  "Hello World"
+
+  .snake_case() / .kebab_case() / .camel_case()
+    Split into words and join them as some_words, some-words or someWords.
+    Example: "HTTPServer error".snake_case()  =>  "http_server_error"
+
+    Constraints:
+      - Words are runs of letters and digits; a lowercase letter or digit
+        followed by an uppercase one starts a new word (fooBar, HTTPServer)
+      - Letters outside ASCII and combining accents are kept
+
+NORMALIZATION
+  .normalize_unicode(form?)
+    Unicode normalization: "NFC" (the default), "NFD", "NFKC" or "NFKD".
+    Example: "ﬁle".normalize_unicode("NFKC")  =>  "file"
+
 WHITESPACE
   .trim()
     Remove leading and trailing whitespace.
@@ -391,6 +623,66 @@ SPLITTING
       - Delimiter not found returns [original]
       - Empty delimiter splits into characters
 
+  .lines()
+    Split into lines, at "\n" or "\r\n".
+    Example: "a\n\nb\n".lines()  =>  ["a", "", "b"]
+
+    Constraints:
+      - Empty lines in between are kept; a final newline adds no empty line
+      - Empty string returns []
+
+  .words()
+    Split at runs of whitespace, ignoring leading and trailing whitespace.
+    Example: "  error  at\tline 3 ".words()  =>  ["error", "at", "line", "3"]
+
+  .chars()
+    Split into single-character strings (Unicode code points, as counted
+    by .length()).
+    Example: "héy".chars()  =>  ["h", "é", "y"]
+
+URLS (with the stdlib feature)
+  .parse_url()
+    {scheme, username, password, host, port, path, query, fragment}, or null.
+    Example: "https://example.com:8080/a?x=1".parse_url()[port]  =>  8080
+
+  .url_encode() / .url_decode()
+    Percent-encode all but A-Z a-z 0-9 - . _ ~, or decode %XX escapes.
+    Example: "a b/c".url_encode()  =>  "a%20b%2Fc"
+
+  .parse_query_string()
+    Object of a query string's parameters (leading ? optional).
+    Example: "?tag=a&tag=b&q=red+shoes".parse_query_string()
+      =>  {"tag": ["a", "b"], "q": "red shoes"}
+
+    Constraints:
+      - + and %XX are decoded
+      - A repeated parameter is an array of its values
+
+BYTES
+  .base64_decode()
+    Decode base64 text (standard or URL-safe, padding optional) to bytes.
+    Example: "aGk=".base64_decode().hex_encode()  =>  "6869"
+
+  .base64_encode()
+    Standard, padded base64 of bytes, or of a string's UTF-8 bytes.
+    Example: "hi".base64_encode()  =>  "aGk="
+
+  .hex_decode()
+    Decode hex text (either case) to bytes.
+    Example: "CAFE".hex_decode().bytes_len()  =>  2
+
+  .hex_encode()
+    Lowercase hex of bytes, or of a string's UTF-8 bytes.
+    Example: $[sig].base64_decode().hex_encode()
+
+  .bytes_len()
+    Number of bytes; a string counts its UTF-8 bytes.
+    Example: "héllo".bytes_len()  =>  6
+
+    Constraints:
+      - Invalid base64 or hex is an error
+      - JSON output writes bytes as a base64 string
+
 CONCATENATION
   Use the + operator to concatenate strings.
     Example: $[first] + " " + $[last]  =>  "John Doe"
@@ -404,15 +696,18 @@ const OBJECT_METHODS_DOC: &str = r#"OBJECT-METHODS - Working with Keys and Value
 
 KEYS
   .keys()
-    Returns an array of the object's keys.
+    Returns an array of the object's keys, sorted.
 
     Example:
       Input:  {"name": "Alice", "age": 30}
       Query:  $.keys()
-      Output: ["name", "age"]
+      Output: ["age", "name"]
+
+  .sorted_keys()
+    Same as .keys(); spells out the sorted order for readers.
 
     Constraints:
-      - Order is not guaranteed (depends on implementation)
+      - Keys are sorted by code point, so output is stable between runs
       - Returns empty array for empty object
       - Only works on objects, not arrays
 
@@ -423,13 +718,62 @@ VALUES
     Example:
       Input:  {"name": "Alice", "age": 30}
       Query:  $.values()
-      Output: ["Alice", 30]
+      Output: [30, "Alice"]
 
     Constraints:
-      - Order matches .keys() order
+      - Order matches .keys() order (sorted by key)
       - Returns empty array for empty object
       - Only works on objects, not arrays
 
+KEY MEMBERSHIP
+  .has(key)
+    Returns true if the object has the key, even when its value is null.
+    On arrays, takes an integer index (negative counts from the end).
+    Example: $[config].has("debug")  =>  true/false
+
+  .has_path(key, ...)
+    Returns true if the nested entry exists, even when its value is null,
+    "" or []. Missing intermediates give false instead of an error.
+    Example: $.has_path("user", "address", "city")  =>  true/false
+
+  .get_path(path)
+    Returns the value at a path written as a string, e.g. one read from
+    configuration: keys joined by ., integer indices, * for every element,
+    and brackets or \ for keys containing dots. Also takes an array of keys
+    and indices, as .paths() returns. Missing paths give null.
+    Example: $.get_path("items.0.price")
+    Example: $.get_path(@field)  with  --arg field 'meta["build.id"]'
+    Example: $.get_path("items.*.price")  =>  [10, null, 7]
+
+  .contains(key)
+    Returns true if the object has the key.
+    Example: $[config].contains("debug")  =>  true/false
+
+  .contains_all(keys) / .contains_any(keys)
+    Returns true if all / any of the keys are present.
+    Example: $[user].contains_all(["name", "email"])
+
+WHOLE DOCUMENT
+  .walk(expression)
+    Rebuilds the value bottom-up: every node, from the leaves to the value
+    itself, is replaced by expression with @ bound to it, after its children
+    have been replaced. Works on any value, however deeply nested.
+    Example: $.walk(match @ { s if @s.type() == "string" => @s.trim(), x => @x })
+             trims every string in the document
+
+  .paths()
+    Returns the path to every leaf, as arrays of keys and indices that
+    .has_path() accepts. Scalars and empty arrays and objects are leaves.
+
+    Example:
+      Input:  {"a": 1, "b": {"c": [true, []]}}
+      Query:  $.paths()
+      Output: [["a"], ["b", "c", 0], ["b", "c", 1]]
+
+    Constraints:
+      - Depth-first, in key and index order
+      - The paths of a scalar are [[]]
+
 TYPE CHECK
   .type()
     Returns the type name as a string.
@@ -441,10 +785,28 @@ TYPE CHECK
 
     See 'clove doc types' for all type names.
 
+  .is_nan()
+    True for a float that is NaN, such as the result of 1e308 * 10 - 1e308 * 10.
+    Example: $[ratio].is_nan()  =>  false
+
+  .is_finite()
+    True for integers and for floats that are neither NaN nor infinite;
+    false for everything else.
+    Example: $ | ?($[score].is_finite())
+
+DEFAULTS
+  .or(default)
+    Returns the value, or default if it is null. Works on any value, and
+    default is only evaluated when it is used.
+    Example: $[user][nickname].or($[user][name])
+
+    For several fallbacks, &coalesce[a, b, ...] returns the first argument
+    that is not null, evaluating no further.
+
 COMMON PATTERNS
 
   Check if object has a key:
-    $[field]?
+    $.has("field")
 
   Get all keys matching a pattern:
     $.keys().filter(@.startswith("user_"))
@@ -459,8 +821,8 @@ COMMON PATTERNS
 const SCOPES_DOC: &str = r#"SCOPES - Reference Contexts
 
 $ (ROOT SCOPE)
-  References the original input document.
-  All queries start from root.
+  References the current document: the input, as changed by the stages
+  before. All queries start from root.
 
   Example:
     Input: {"config": {"debug": true}, "items": [1, 2, 3]}
@@ -470,6 +832,15 @@ $ (ROOT SCOPE)
   Use inside methods to reference root from nested context:
     $[items].filter(@ > $[config][threshold])
 
+$$ (ORIGINAL DOCUMENT)
+  References the input document as it was before any stage ran, so later
+  stages can still reach fields that earlier ones replaced or removed.
+
+  Example:
+    Input: {"user": {"name": "Ann"}, "tenant": "acme"}
+    Query: $ | $[user] | !({"name": $[name], "tenant": $$[tenant]})
+    Output: {"name": "Ann", "tenant": "acme"}
+
 @ (CURRENT SCOPE)
   References the current element in filter/map operations.
   Only valid inside .filter() and .map() method arguments.
@@ -489,6 +860,30 @@ $ (ROOT SCOPE)
     This compares each product's category against the root
     document's default_category field.
 
+ENCLOSING ITEMS (@@)
+  In a nested lambda, @@ is the element of the lambda around the
+  current one (@@@ the one around that, and so on).
+
+  Example:
+    $[a].filter($[b].any(@ == @@))
+
+NAMED LAMBDAS
+  name -> body names the current element, so that @name still
+  refers to it inside nested lambdas, where @ is the inner element.
+
+  Examples:
+    $[orders].map(order -> @order[items].map(@[qty] * @order[rate]))
+    $[xs].map(x -> $[ys].filter(y -> @y > @x))
+
+PARAMETERS (@name)
+  clove check --arg NAME VALUE binds a string to @NAME, and --argjson
+  NAME JSON binds a parsed JSON value; both are repeatable. The query
+  reads them like scopes it defined before its first statement.
+
+  Examples:
+    clove check '$[users].filter(@[role] == @role)' --arg role admin
+    clove check '$[items].take(@n)' --argjson n 5
+
 ENVIRONMENT VARIABLES
   $VARIABLE_NAME
     Access shell environment variables.
@@ -497,10 +892,16 @@ ENVIRONMENT VARIABLES
     $[env] == $NODE_ENV
     $[config][api_key] == $API_KEY
 
+  Typed lookup:
+    $PORT:int        Parse as integer
+    $RATIO:float     Parse as float
+    $DEBUG:bool      true/1/yes/on or false/0/no/off
+
   Constraints:
     - Variable name must be uppercase by convention
-    - Undefined variables return null
-    - Values are always strings
+    - Undefined variables raise an error
+    - Values are strings unless a :type suffix is given
+    - 'clove check --no-env' denies all environment access
 
 SCOPE RESOLUTION ORDER
   1. @ - Current element (only in filter/map)
@@ -535,12 +936,21 @@ PRIMITIVE TYPES
     Floating-point numbers (64-bit IEEE 754).
     Examples: 3.14, -0.5, 1.0, 1e10
     .type() returns: "float"
+    Arithmetic can give NaN or infinity, which JSON output writes as null
+    (with a warning); check --non-finite error fails instead. -0.0 equals
+    0.0 and is written as -0.0.
 
   string
     UTF-8 text in double quotes.
     Examples: "hello", "world", ""
     .type() returns: "string"
 
+  bytes
+    Binary data, from .base64_decode(), .hex_decode() or MessagePack/CBOR
+    input. No literal; JSON output writes it as a base64 string.
+    Example: "aGk=".base64_decode()
+    .type() returns: "bytes"
+
 COMPOSITE TYPES
 
   array
@@ -629,13 +1039,64 @@ TRANSFORM OPERATOR
 
   Array field transforms:
     Filter array: $ | ~($[items] := ?(@[active]))
+    Filter object: $ | ~($[config] := ?(@ != null))  (@key is the entry's key)
     Map array:    $ | ~($[names] := @[name])
 
+  Element transforms (@ is the indexed element):
+    $ | ~($[items][0][price] := @[price] * 2)
+
+  Computed keys and .first()/.last() in the path:
+    $ | ~($[items][$[index]][price] := 0)
+    $ | ~($[items].last()[done] := true)
+
   Constraints:
     - Path must be a valid field reference
+    - Computed keys must be strings or integers
     - Creates field if it doesn't exist
     - Expression can reference $, @, or literals
 
+DELETE OPERATOR
+  $ | -(<path>)
+
+  Removes the value at the path; does nothing if it is missing. A [*]
+  key matches every element of an array or value of an object.
+
+  Examples:
+    $ | -($[password])
+    $ | -($[users][*][password])
+    $ | -($[orders][*][items][*][cost])
+
+  With --redact <path> the CLI replaces the values in the result with
+  "[REDACTED]" instead.
+
+CONDITIONAL STAGES
+  $ | when (<condition>) { <statement> | <statement> ... }
+
+  Runs the statements in braces only if the condition is true;
+  otherwise the document passes through unchanged.
+
+  Examples:
+    $ | when ($[legacy][?]) { ~($[id] := $[legacy][id]) | -($[legacy]) }
+    $ | when ($[total] > 100) { ~($[discount] := 0.1) }
+
+  A filter inside the braces that fails ends the whole query.
+
+LOOPS
+  $ | foreach <path> as @<name> { <statement> | <statement> ... }
+
+  Runs the statements once per element of the array at the path, with
+  the element as @<name>, and writes the results back. $ is still the
+  whole document.
+
+  Examples:
+    $ | foreach $[items] as @item { ~(@item[total] := @item[qty] * $[rate]) }
+    $ | foreach $[items] as @item { ?(@item[qty] > 0) | -(@item[draft]) }
+
+  Behavior:
+    - Transforms and deletes target the element: @item[field]
+    - A failing filter drops the element from the array
+    - An expression statement replaces the element
+
 OUTPUT OPERATORS
   $ | !json
     Format output as compact JSON.
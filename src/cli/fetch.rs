@@ -0,0 +1,70 @@
+//! Input fetched over HTTP(S) (`check --input-url`)
+//!
+//! The response body feeds the same pipeline as stdin, so it may be
+//! compressed and is held to `--max-input-size`; a `Content-Length` over the
+//! limit fails before any of the body is read. Needs the `http` feature.
+
+use std::io::Read;
+use std::time::Duration;
+
+use super::CliError;
+
+/// A GET request for the input document
+#[derive(Debug, Clone, Default)]
+pub struct FetchRequest {
+    /// The URL to fetch
+    pub url: String,
+    /// Extra request headers, as name and value
+    pub headers: Vec<(String, String)>,
+    /// How long the whole request may take, body included
+    pub timeout: Option<Duration>,
+    /// Reject a response that says it is larger than this many bytes
+    pub max_size: Option<u64>,
+}
+
+/// Parse a `--header` such as `Authorization: Bearer abc`
+pub fn parse_header(s: &str) -> Result<(String, String), String> {
+    match s.split_once(':') {
+        Some((name, value)) if !name.trim().is_empty() => {
+            Ok((name.trim().to_string(), value.trim().to_string()))
+        }
+        _ => Err(format!("expected NAME: VALUE, got '{}'", s)),
+    }
+}
+
+/// Send `request`, returning a reader of the response body. A status other
+/// than 2xx is an error.
+#[cfg(feature = "http")]
+pub fn fetch(request: &FetchRequest) -> Result<Box<dyn Read + Send>, CliError> {
+    let failed = |message: String| CliError::Fetch { url: request.url.clone(), message };
+    let config = ureq::Agent::config_builder()
+        .timeout_global(request.timeout)
+        .http_status_as_error(false)
+        .build();
+    let mut call = ureq::Agent::new_with_config(config).get(&request.url);
+    for (name, value) in &request.headers {
+        call = call.header(name, value);
+    }
+    let response = call.call().map_err(|e| failed(e.to_string()))?;
+
+    let status = response.status();
+    if !status.is_success() {
+        return Err(failed(format!("HTTP {}", status)));
+    }
+    let body = response.into_body();
+    if let (Some(limit), Some(length)) = (request.max_size, body.content_length())
+        && length > limit
+    {
+        return Err(CliError::InputTooLarge(limit));
+    }
+    Ok(Box::new(body.into_reader()))
+}
+
+/// Without the `http` feature, fetching always fails
+#[cfg(not(feature = "http"))]
+pub fn fetch(request: &FetchRequest) -> Result<Box<dyn Read + Send>, CliError> {
+    Err(CliError::Fetch {
+        url: request.url.clone(),
+        message: "http support is not enabled (build with --features http)".to_string(),
+    })
+}
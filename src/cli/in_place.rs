@@ -0,0 +1,116 @@
+//! Atomic in-place rewriting of JSON files, and atomic `--output` files
+
+use std::fs;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use super::CliError;
+use crate::random::Rng;
+
+/// Replace the contents of `path` with `contents`.
+///
+/// The new contents are written to a temporary file in the same directory,
+/// flushed to disk and then renamed over the original, so readers never see
+/// a partially written file. The original file's permissions are preserved.
+/// When `backup` is set, the original is first copied to `<path>.bak`.
+pub fn write_in_place(path: &Path, contents: &str, backup: bool) -> Result<(), CliError> {
+    write_in_place_with(path, backup, |file| file.write_all(contents.as_bytes()))
+}
+
+/// Like [`write_in_place`], with the new contents streamed by `write`
+/// rather than built up front
+pub fn write_in_place_with(
+    path: &Path,
+    backup: bool,
+    write: impl FnOnce(&mut AtomicFile) -> io::Result<()>,
+) -> Result<(), CliError> {
+    // Fail before writing anything if there is no file to replace
+    fs::metadata(path)?;
+
+    if backup {
+        fs::copy(path, backup_path(path))?;
+    }
+
+    let mut file = AtomicFile::create(path)?;
+    write(&mut file)?;
+    Ok(file.commit()?)
+}
+
+/// A file that replaces `path` only once it is completely written.
+///
+/// Writes go to a new temporary file in the same directory, with the
+/// permissions of any file already at `path`, which [`commit`](Self::commit)
+/// flushes to disk and renames over `path`. Dropped without committing,
+/// for example when a query fails part way through its output, the
+/// temporary file is removed and `path` is left as it was.
+pub struct AtomicFile {
+    file: BufWriter<fs::File>,
+    path: PathBuf,
+    tmp_path: PathBuf,
+}
+
+impl AtomicFile {
+    /// Start writing the new contents of `path`
+    pub fn create(path: &Path) -> io::Result<Self> {
+        // Never open a file that is already there, which could be a link
+        // planted by someone else; pick another name instead
+        let rng = Rng::default();
+        let mut attempts = 0;
+        let (file, tmp_path) = loop {
+            let tmp_path = temp_path(path, rng.next_u64());
+            match fs::OpenOptions::new().write(true).create_new(true).open(&tmp_path) {
+                Ok(file) => break (file, tmp_path),
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists && attempts < 100 => attempts += 1,
+                Err(e) => return Err(e),
+            }
+        };
+        let atomic = AtomicFile { file: BufWriter::new(file), path: path.to_path_buf(), tmp_path };
+        if let Ok(metadata) = fs::metadata(path) {
+            atomic.file.get_ref().set_permissions(metadata.permissions())?;
+        }
+        Ok(atomic)
+    }
+
+    /// Put the written contents in place of `path`
+    pub fn commit(mut self) -> io::Result<()> {
+        self.file.flush()?;
+        self.file.get_ref().sync_all()?;
+        // Renamed, there is nothing left for `drop` to remove
+        fs::rename(&self.tmp_path, &self.path)
+    }
+}
+
+impl Write for AtomicFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.file.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+impl Drop for AtomicFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.tmp_path);
+    }
+}
+
+/// Path of the backup written by [`write_in_place`]: `config.json` → `config.json.bak`
+pub fn backup_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".bak");
+    PathBuf::from(name)
+}
+
+fn temp_path(path: &Path, suffix: u64) -> PathBuf {
+    let file_name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let tmp_name = format!(".{}.clove-{:016x}.tmp", file_name, suffix);
+    match path.parent() {
+        Some(dir) => dir.join(tmp_name),
+        None => PathBuf::from(tmp_name),
+    }
+}
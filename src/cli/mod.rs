@@ -3,15 +3,38 @@
 //! Provides programmatic access to clove CLI functionality for embedding
 //! in other tools (like checkmate).
 
+mod assert;
+mod bench;
 mod check;
+mod compression;
 mod convert;
 mod docs;
+mod fetch;
+mod in_place;
 mod onboard;
+mod redact;
+mod schema;
+mod snapshot;
 
-pub use check::{execute_check, CheckOptions, CheckResult};
+pub use assert::{
+    execute_assert, execute_assert_reader, AssertOutcome, EXIT_ERROR, EXIT_FAILED, EXIT_PASSED,
+};
+pub use bench::{execute_bench, BenchOptions, BenchReport};
+pub use check::{
+    execute_check, execute_check_ndjson, execute_check_reader, execute_query_hash, parse_byte_size,
+    parse_select, CheckOptions, CheckResult, REDACTED,
+};
+pub(crate) use check::is_pipeline_query;
+pub use compression::{decompress, open_input, Compression, OutputStream};
 pub use convert::{clove_to_json, json_to_clove};
 pub use docs::{get_doc_category, get_docs_overview, DocCategory};
+pub use fetch::{fetch, parse_header, FetchRequest};
+pub(crate) use docs::{method_doc, operator_doc};
+pub use in_place::{backup_path, write_in_place, write_in_place_with, AtomicFile};
 pub use onboard::get_onboarding_content;
+pub use redact::{execute_redact, RedactReport, RedactRule, RedactStrategy, RedactTarget};
+pub use schema::execute_schema_infer;
+pub use snapshot::{execute_test, TestCase, TestOptions, TestReport, TestStatus};
 
 use std::io;
 
@@ -20,6 +43,8 @@ use std::io;
 pub enum CliError {
     /// Parser error
     Parse(crate::ParseError),
+    /// Several parser errors, in source order (from `--syntax-only`)
+    ParseErrors(Vec<crate::ParseError>),
     /// Evaluation error
     Eval(crate::EvalError),
     /// JSON parsing error
@@ -28,18 +53,77 @@ pub enum CliError {
     Io(io::Error),
     /// No input provided
     NoInput,
+    /// Input larger than the configured limit (in bytes)
+    InputTooLarge(u64),
+    /// A check that only validated the query, where a result was needed
+    NoResult,
     /// Unknown documentation category
     UnknownCategory(String),
+    /// jq filter that could not be translated
+    Jq(crate::jq::JqError),
+    /// Invalid JSONPath selector
+    JsonPath(crate::jsonpath::JsonPathError),
+    /// Invalid or unsupported MessagePack or CBOR document
+    Format(crate::binary::FormatError),
+    /// Compressed input or `--compress` without the format's feature
+    Compression(Compression),
+    /// Fetching `--input-url` failed
+    Fetch { url: String, message: String },
+    /// A result that cannot be written as shell variables (`--output-format env`)
+    Env(crate::output::EnvError),
+    /// Failed to resolve `%include` directives
+    Load(crate::loader::LoadError),
+    /// A record of newline-delimited input failed (`line` is 1-based)
+    Record { line: usize, error: Box<CliError> },
+    /// The `--argjson` parameter `name` is not valid JSON
+    Param { name: String, error: Box<CliError> },
+    /// The `--select` query called `name` failed
+    Select { name: String, error: Box<CliError> },
+    /// The `--redact` path (or redaction rule path) `target` is not a valid
+    /// delete target
+    Redact { target: String, error: Box<CliError> },
+    /// A `hash` redaction rule without a key to hash with
+    NoHashKey,
 }
 
 impl std::fmt::Display for CliError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             CliError::Parse(e) => write!(f, "Parse error: {}", e),
+            CliError::ParseErrors(errors) => {
+                for (i, e) in errors.iter().enumerate() {
+                    if i > 0 {
+                        writeln!(f)?;
+                    }
+                    write!(f, "Parse error: {}", e)?;
+                }
+                Ok(())
+            }
             CliError::Eval(e) => write!(f, "Evaluation error: {}", e),
             CliError::Json(e) => write!(f, "Invalid JSON: {}", e),
             CliError::Io(e) => write!(f, "IO error: {}", e),
             CliError::NoInput => write!(f, "No input provided. Use --input or pipe JSON to stdin."),
+            CliError::InputTooLarge(limit) => {
+                write!(f, "Input exceeds the maximum size of {} bytes (see --max-input-size)", limit)
+            }
+            CliError::NoResult => write!(f, "The query was only validated, so there is no result"),
+            CliError::Jq(e) => write!(f, "{}", e),
+            CliError::JsonPath(e) => write!(f, "{}", e),
+            CliError::Format(e) => write!(f, "{}", e),
+            CliError::Compression(compression) => write!(
+                f,
+                "{} support is not enabled (build with --features {})",
+                compression,
+                compression
+            ),
+            CliError::Env(e) => write!(f, "{}", e),
+            CliError::Load(e) => write!(f, "{}", e),
+            CliError::Fetch { url, message } => write!(f, "Fetching {}: {}", url, message),
+            CliError::Record { line, error } => write!(f, "Line {}: {}", line, error),
+            CliError::Param { name, error } => write!(f, "Parameter '{}': {}", name, error),
+            CliError::Select { name, error } => write!(f, "Select '{}': {}", name, error),
+            CliError::Redact { target, error } => write!(f, "Redact '{}': {}", target, error),
+            CliError::NoHashKey => write!(f, "The hash strategy needs a secret key (see --hash-key-file)"),
             CliError::UnknownCategory(c) => {
                 write!(f, "Unknown category: '{}'\nRun 'clove docs' to see available categories.", c)
             }
@@ -51,9 +135,21 @@ impl std::error::Error for CliError {
     fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
         match self {
             CliError::Parse(e) => Some(e),
+            CliError::ParseErrors(errors) => errors.first().map(|e| e as _),
             CliError::Eval(e) => Some(e),
             CliError::Json(e) => Some(e),
             CliError::Io(e) => Some(e),
+            CliError::Jq(e) => Some(e),
+            CliError::JsonPath(e) => Some(e),
+            CliError::Format(e) => Some(e),
+            CliError::Env(e) => Some(e),
+            CliError::Load(e) => Some(e),
+            CliError::Record { error, .. }
+            | CliError::Param { error, .. }
+            | CliError::Select { error, .. }
+            | CliError::Redact { error, .. } => {
+                Some(error.as_ref())
+            }
             _ => None,
         }
     }
@@ -77,6 +173,36 @@ impl From<serde_json::Error> for CliError {
     }
 }
 
+impl From<crate::jq::JqError> for CliError {
+    fn from(e: crate::jq::JqError) -> Self {
+        CliError::Jq(e)
+    }
+}
+
+impl From<crate::jsonpath::JsonPathError> for CliError {
+    fn from(e: crate::jsonpath::JsonPathError) -> Self {
+        CliError::JsonPath(e)
+    }
+}
+
+impl From<crate::binary::FormatError> for CliError {
+    fn from(e: crate::binary::FormatError) -> Self {
+        CliError::Format(e)
+    }
+}
+
+impl From<crate::output::EnvError> for CliError {
+    fn from(e: crate::output::EnvError) -> Self {
+        CliError::Env(e)
+    }
+}
+
+impl From<crate::loader::LoadError> for CliError {
+    fn from(e: crate::loader::LoadError) -> Self {
+        CliError::Load(e)
+    }
+}
+
 impl From<io::Error> for CliError {
     fn from(e: io::Error) -> Self {
         CliError::Io(e)
@@ -56,6 +56,13 @@ Methods can be chained.
     --input '{"users": [{"name": "Alice", "active": true}, {"name": "Bob", "active": false}]}'
   => ["Alice"]
 
+STEP 8: ASSERTIONS
+------------------
+Use clove assert to gate scripts: exit 0 if the query is truthy, 1 if not.
+
+  clove assert '$[status] == 200' --input '{"status": 200}' && echo healthy
+  => healthy
+
 NEXT STEPS
 ----------
   clove docs              List all documentation categories
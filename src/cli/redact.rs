@@ -0,0 +1,267 @@
+//! Sanitize JSON documents with a file of redaction rules (`clove redact`)
+//!
+//! A rules file is a JSON array of rules. Each names what it matches, a
+//! `path` as written in a delete (where `[*]` matches every element or
+//! value) or a `field` name matched in objects at any depth, and what to
+//! do with the values it matches:
+//!
+//! ```json
+//! [
+//!   {"path": "$[users][*][password]", "strategy": "remove"},
+//!   {"field": "ssn", "strategy": "mask"},
+//!   {"path": "$[users][*][email]", "strategy": "hash"}
+//! ]
+//! ```
+//!
+//! - `remove` deletes the value, as `-()` does
+//! - `mask` replaces it with [`REDACTED`]
+//! - `hash` replaces it with the lowercase hex HMAC-SHA256 of the string,
+//!   or of the compact JSON of any other value, under a secret key, so
+//!   equal values can still be matched up
+//!
+//! Rules apply in order, and each counts the values it touched.
+//!
+//! Hashing needs the key because emails, phone numbers or IDs are easy to
+//! guess: anyone could hash the candidates and compare, were the digests
+//! plain SHA-256. Keep the key secret, and reuse it only where digests
+//! should match up.
+
+use std::fmt;
+use std::io::{BufReader, Read};
+
+use std::sync::Arc;
+
+use hmac::{Hmac, Mac};
+use serde::Deserialize;
+use sha2::Sha256;
+
+use super::{CliError, REDACTED, json_to_clove};
+use crate::output::to_json;
+use crate::transform::{Path, extract_path};
+use crate::{Lexer, Parser, Value};
+
+/// What a rule does with the values it matches
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RedactStrategy {
+    /// Delete the value
+    Remove,
+    /// Replace the value with [`REDACTED`]
+    Mask,
+    /// Replace the value with its HMAC-SHA256 digest
+    Hash,
+}
+
+impl fmt::Display for RedactStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(match self {
+            RedactStrategy::Remove => "remove",
+            RedactStrategy::Mask => "mask",
+            RedactStrategy::Hash => "hash",
+        })
+    }
+}
+
+/// What a rule matches
+#[derive(Debug, Clone, PartialEq)]
+pub enum RedactTarget {
+    /// The values at a path, which may contain wildcards
+    Path { source: String, path: Path },
+    /// Every object field of this name, at any depth
+    Field(String),
+}
+
+impl fmt::Display for RedactTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RedactTarget::Path { source, .. } => f.write_str(source),
+            RedactTarget::Field(name) => write!(f, "field {}", name),
+        }
+    }
+}
+
+/// One rule of a rules file
+#[derive(Debug, Clone, PartialEq)]
+pub struct RedactRule {
+    pub target: RedactTarget,
+    pub strategy: RedactStrategy,
+    /// The secret key of the `hash` strategy
+    hash_key: Option<Arc<[u8]>>,
+}
+
+/// A rule as written, before its path is parsed
+#[derive(Deserialize)]
+#[serde(try_from = "RawRule")]
+struct RuleSpec {
+    target: RawTarget,
+    strategy: RedactStrategy,
+}
+
+enum RawTarget {
+    Path(String),
+    Field(String),
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawRule {
+    path: Option<String>,
+    field: Option<String>,
+    strategy: RedactStrategy,
+}
+
+impl TryFrom<RawRule> for RuleSpec {
+    type Error = String;
+
+    fn try_from(rule: RawRule) -> Result<Self, String> {
+        let target = match (rule.path, rule.field) {
+            (Some(path), None) => RawTarget::Path(path),
+            (None, Some(field)) => RawTarget::Field(field),
+            _ => return Err("a rule needs either a \"path\" or a \"field\"".to_string()),
+        };
+        Ok(RuleSpec { target, strategy: rule.strategy })
+    }
+}
+
+impl RedactRule {
+    /// Parse the rules of a rules file (see the [module docs](self)).
+    /// `hash_key` is the secret key of `hash` rules, which fail without one.
+    pub fn parse_rules(text: &str, hash_key: Option<&[u8]>) -> Result<Vec<RedactRule>, CliError> {
+        let rules: Vec<RuleSpec> = serde_json::from_str(text).map_err(CliError::Json)?;
+        let hash_key: Option<Arc<[u8]>> = hash_key.filter(|key| !key.is_empty()).map(Arc::from);
+        rules
+            .into_iter()
+            .map(|rule| {
+                let target = match rule.target {
+                    RawTarget::Field(name) => RedactTarget::Field(name),
+                    RawTarget::Path(source) => {
+                        let path = Parser::new(Lexer::new(&source))
+                            .and_then(|mut parser| parser.parse_target())
+                            .map_err(CliError::Parse)
+                            .and_then(|expr| extract_path(&expr).map_err(CliError::Eval));
+                        match path {
+                            Ok(path) => RedactTarget::Path { source, path },
+                            Err(error) => {
+                                return Err(CliError::Redact { target: source, error: Box::new(error) });
+                            }
+                        }
+                    }
+                };
+                let hash_key = match rule.strategy {
+                    RedactStrategy::Hash => Some(hash_key.clone().ok_or(CliError::NoHashKey)?),
+                    RedactStrategy::Remove | RedactStrategy::Mask => None,
+                };
+                Ok(RedactRule { target, strategy: rule.strategy, hash_key })
+            })
+            .collect()
+    }
+
+    /// Apply the rule to `document`, returning how many values it touched
+    pub fn apply(&self, document: &mut Value) -> usize {
+        match (&self.target, self.strategy) {
+            (RedactTarget::Path { path, .. }, RedactStrategy::Remove) => document.remove_matching(path),
+            (RedactTarget::Path { path, .. }, _) => document.update_matching(path, &mut |value| self.replace(value)),
+            (RedactTarget::Field(name), _) => self.apply_to_field(document, name),
+        }
+    }
+
+    /// Replace `value` as the strategy says (other than removing it)
+    fn replace(&self, value: &mut Value) {
+        *value = match (self.strategy, &self.hash_key) {
+            (RedactStrategy::Hash, Some(key)) => match Hmac::<Sha256>::new_from_slice(key) {
+                Ok(mut mac) => {
+                    let text = match &*value {
+                        Value::String(s) => s.clone(),
+                        other => to_json(other),
+                    };
+                    mac.update(text.as_bytes());
+                    let digest = mac.finalize().into_bytes();
+                    Value::String(digest.iter().map(|byte| format!("{:02x}", byte)).collect())
+                }
+                // HMAC takes keys of any length, but never leave the value as it was
+                Err(_) => Value::String(REDACTED.to_string()),
+            },
+            _ => Value::String(REDACTED.to_string()),
+        };
+    }
+
+    fn apply_to_field(&self, value: &mut Value, name: &str) -> usize {
+        match value {
+            Value::Object(map) => {
+                let touched = match self.strategy {
+                    RedactStrategy::Remove => map.remove(name).map(|_| 1),
+                    _ => map.get_mut(name).map(|value| {
+                        self.replace(value);
+                        1
+                    }),
+                };
+                let nested: usize = map
+                    .iter_mut()
+                    .filter(|(key, _)| *key != name)
+                    .map(|(_, value)| self.apply_to_field(value, name))
+                    .sum();
+                touched.unwrap_or(0) + nested
+            }
+            Value::Array(items) => items.iter_mut().map(|item| self.apply_to_field(item, name)).sum(),
+            _ => 0,
+        }
+    }
+}
+
+/// How many values each rule touched, over every document
+#[derive(Debug, Clone, Default)]
+pub struct RedactReport {
+    /// Documents read
+    pub documents: usize,
+    /// Each rule with its count, in rule order
+    pub rules: Vec<(RedactRule, usize)>,
+}
+
+impl RedactReport {
+    /// Values touched by all the rules together
+    pub fn total(&self) -> usize {
+        self.rules.iter().map(|(_, count)| count).sum()
+    }
+}
+
+impl fmt::Display for RedactReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let width = self.rules.iter().map(|(rule, _)| rule.target.to_string().len()).max().unwrap_or(0);
+        for (rule, count) in &self.rules {
+            writeln!(f, "  {:<6} {:<width$}  {}", rule.strategy, rule.target.to_string(), count, width = width)?;
+        }
+        write!(
+            f,
+            "Redacted {} value{} in {} document{}",
+            self.total(),
+            if self.total() == 1 { "" } else { "s" },
+            self.documents,
+            if self.documents == 1 { "" } else { "s" }
+        )
+    }
+}
+
+/// Apply `rules` to every document in `input`, handing each to `emit`.
+///
+/// The input may hold any number of whitespace-separated JSON documents,
+/// so newline-delimited logs work as is.
+pub fn execute_redact<R: Read>(
+    input: R,
+    rules: &[RedactRule],
+    mut emit: impl FnMut(Value) -> Result<(), CliError>,
+) -> Result<RedactReport, CliError> {
+    let mut report = RedactReport {
+        documents: 0,
+        rules: rules.iter().map(|rule| (rule.clone(), 0)).collect(),
+    };
+    let documents = serde_json::Deserializer::from_reader(BufReader::new(input)).into_iter::<serde_json::Value>();
+    for document in documents {
+        let mut document = json_to_clove(document.map_err(CliError::Json)?);
+        for (rule, count) in &mut report.rules {
+            *count += rule.apply(&mut document);
+        }
+        report.documents += 1;
+        emit(document)?;
+    }
+    Ok(report)
+}
@@ -0,0 +1,23 @@
+//! Infer the shape of sample JSON documents
+
+use std::io::{BufReader, Read};
+
+use crate::infer::Shape;
+use super::{json_to_clove, CliError};
+
+/// Merge the shape of every document in `inputs`.
+///
+/// Each input may hold any number of whitespace-separated JSON documents,
+/// so newline-delimited JSON works as is. An input with no documents
+/// contributes nothing.
+pub fn execute_schema_infer<R: Read>(inputs: impl IntoIterator<Item = R>) -> Result<Shape, CliError> {
+    let mut shape = Shape::default();
+    for input in inputs {
+        let documents = serde_json::Deserializer::from_reader(BufReader::new(input))
+            .into_iter::<serde_json::Value>();
+        for document in documents {
+            shape.observe(&json_to_clove(document?));
+        }
+    }
+    Ok(shape)
+}
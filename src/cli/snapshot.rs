@@ -0,0 +1,269 @@
+//! Snapshot tests for queries (`clove test`)
+//!
+//! A test directory holds one case per `.clove` file, found in
+//! subdirectories too:
+//!
+//! ```text
+//! tests/queries/
+//!   discount.clove            the query
+//!   discount.input.json       its input (null if missing)
+//!   discount.expected.json    the expected output, the snapshot
+//! ```
+//!
+//! A case passes when the query's output equals the snapshot as JSON, so
+//! formatting and key order do not matter. A filter that rejects the input
+//! outputs `null`. Queries run with seed 0, so `.sample()`, `.shuffle()`
+//! and `&random[]` repeat, and relative `%include`s resolve against the
+//! query's directory. With `update`, failing and missing snapshots are
+//! rewritten from the actual output instead.
+//!
+//! The same cases can run under `cargo test`:
+//!
+//! ```no_run
+//! use clove_lang::cli::{execute_test, TestOptions};
+//!
+//! #[test]
+//! fn queries() {
+//!     execute_test("tests/queries".as_ref(), &TestOptions::default()).unwrap().assert_passed();
+//! }
+//! ```
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use super::{CheckOptions, CheckResult, CliError, clove_to_json, execute_check};
+
+/// Options for the test command
+#[derive(Debug, Clone, Default)]
+pub struct TestOptions {
+    /// Write the actual output as the snapshot of every case that does not
+    /// match (or has none yet)
+    pub update: bool,
+    /// Deny the queries access to environment variables
+    pub no_env: bool,
+}
+
+/// How one case went
+#[derive(Debug, Clone, PartialEq)]
+pub enum TestStatus {
+    Passed,
+    /// The output differs from the snapshot (both pretty-printed)
+    Failed { expected: String, actual: String },
+    /// The case has no snapshot yet
+    Missing { actual: String },
+    /// The snapshot was written from the output (with `update`)
+    Updated,
+    /// The query or its input could not be read, parsed or evaluated
+    Error(String),
+}
+
+/// One test case and its result
+#[derive(Debug, Clone)]
+pub struct TestCase {
+    /// Path of the query relative to the test directory, without `.clove`
+    pub name: String,
+    pub status: TestStatus,
+}
+
+/// Results of every case in a test directory, in path order
+#[derive(Debug, Clone, Default)]
+pub struct TestReport {
+    pub cases: Vec<TestCase>,
+}
+
+impl TestReport {
+    /// Number of cases that passed or were updated
+    pub fn passed(&self) -> usize {
+        self.cases
+            .iter()
+            .filter(|case| matches!(case.status, TestStatus::Passed | TestStatus::Updated))
+            .count()
+    }
+
+    /// Number of cases that failed, have no snapshot, or could not run
+    pub fn failed(&self) -> usize {
+        self.cases.len() - self.passed()
+    }
+
+    /// Whether every case passed (or was updated)
+    pub fn is_success(&self) -> bool {
+        self.failed() == 0
+    }
+
+    /// Panic with the report unless every case passed, for use in `#[test]`
+    /// functions
+    #[track_caller]
+    pub fn assert_passed(&self) {
+        assert!(self.is_success(), "query snapshot tests failed:\n{}", self);
+    }
+}
+
+impl std::fmt::Display for TestReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for case in &self.cases {
+            match &case.status {
+                TestStatus::Passed => writeln!(f, "ok       {}", case.name)?,
+                TestStatus::Updated => writeln!(f, "updated  {}", case.name)?,
+                TestStatus::Error(message) => {
+                    writeln!(f, "ERROR    {}", case.name)?;
+                    for line in message.lines() {
+                        writeln!(f, "         {}", line)?;
+                    }
+                }
+                TestStatus::Missing { actual } => {
+                    writeln!(f, "MISSING  {} (run with --update to write the snapshot)", case.name)?;
+                    for line in actual.lines() {
+                        writeln!(f, "         + {}", line)?;
+                    }
+                }
+                TestStatus::Failed { expected, actual } => {
+                    writeln!(f, "FAILED   {}", case.name)?;
+                    writeln!(f, "         --- expected")?;
+                    writeln!(f, "         +++ actual")?;
+                    for line in diff_lines(expected, actual) {
+                        writeln!(f, "         {}", line)?;
+                    }
+                }
+            }
+        }
+        let updated = self.cases.iter().filter(|case| case.status == TestStatus::Updated).count();
+        write!(f, "\n{} passed, {} failed", self.passed() - updated, self.failed())?;
+        if updated > 0 {
+            write!(f, ", {} updated", updated)?;
+        }
+        Ok(())
+    }
+}
+
+/// Run every case under `dir`.
+///
+/// Fails only if the directory cannot be read; a case that cannot run is
+/// reported as [`TestStatus::Error`].
+pub fn execute_test(dir: &Path, options: &TestOptions) -> Result<TestReport, CliError> {
+    let mut queries = Vec::new();
+    find_queries(dir, &mut queries).map_err(CliError::Io)?;
+    queries.sort();
+
+    let cases = queries
+        .iter()
+        .map(|query| {
+            let name = query.strip_prefix(dir).unwrap_or(query).with_extension("");
+            TestCase {
+                name: name.to_string_lossy().replace('\\', "/"),
+                status: run_case(query, options),
+            }
+        })
+        .collect();
+    Ok(TestReport { cases })
+}
+
+/// Add the `.clove` files under `dir` to `found`
+fn find_queries(dir: &Path, found: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            find_queries(&path, found)?;
+        } else if path.extension().is_some_and(|ext| ext == "clove") {
+            found.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// `query` with `.clove` replaced by `suffix`, e.g. `.input.json`
+fn sibling(query: &Path, suffix: &str) -> PathBuf {
+    let mut path = query.with_extension("").into_os_string();
+    path.push(suffix);
+    PathBuf::from(path)
+}
+
+fn run_case(query: &Path, options: &TestOptions) -> TestStatus {
+    let actual = match evaluate_case(query, options) {
+        Ok(actual) => actual,
+        Err(error) => return TestStatus::Error(error.to_string()),
+    };
+    let actual_text = pretty(&actual);
+
+    let snapshot = sibling(query, ".expected.json");
+    let status = match fs::read_to_string(&snapshot) {
+        Ok(text) => match serde_json::from_str::<serde_json::Value>(&text) {
+            Ok(expected) if expected == actual => return TestStatus::Passed,
+            Ok(expected) => TestStatus::Failed { expected: pretty(&expected), actual: actual_text.clone() },
+            Err(error) => return TestStatus::Error(format!("Invalid snapshot {}: {}", snapshot.display(), error)),
+        },
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => {
+            TestStatus::Missing { actual: actual_text.clone() }
+        }
+        Err(error) => return TestStatus::Error(error.to_string()),
+    };
+
+    if !options.update {
+        return status;
+    }
+    match fs::write(&snapshot, actual_text + "\n") {
+        Ok(()) => TestStatus::Updated,
+        Err(error) => TestStatus::Error(format!("Cannot write {}: {}", snapshot.display(), error)),
+    }
+}
+
+/// The output of the case's query on its input
+fn evaluate_case(query: &Path, options: &TestOptions) -> Result<serde_json::Value, CliError> {
+    let source = fs::read_to_string(query).map_err(CliError::Io)?;
+    let input = match fs::read_to_string(sibling(query, ".input.json")) {
+        Ok(input) => input,
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => "null".to_string(),
+        Err(error) => return Err(CliError::Io(error)),
+    };
+    let check = CheckOptions {
+        query: source.trim().to_string(),
+        input: Some(input),
+        no_env: options.no_env,
+        seed: Some(0),
+        base_dir: query.parent().map(Path::to_path_buf),
+        ..Default::default()
+    };
+    Ok(match execute_check(&check)? {
+        CheckResult::Success { value, .. } => clove_to_json(value),
+        CheckResult::FilteredOut | CheckResult::SyntaxValid => serde_json::Value::Null,
+    })
+}
+
+fn pretty(value: &serde_json::Value) -> String {
+    serde_json::to_string_pretty(value).unwrap_or_default()
+}
+
+/// A line diff of `expected` and `actual`: unchanged lines start with a
+/// space, removed ones with `-` and added ones with `+`
+fn diff_lines(expected: &str, actual: &str) -> Vec<String> {
+    let old: Vec<_> = expected.lines().collect();
+    let new: Vec<_> = actual.lines().collect();
+
+    // common[i][j]: length of the longest common subsequence of old[i..] and new[j..]
+    let mut common = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            common[i][j] = if old[i] == new[j] {
+                common[i + 1][j + 1] + 1
+            } else {
+                common[i + 1][j].max(common[i][j + 1])
+            };
+        }
+    }
+
+    let (mut i, mut j) = (0, 0);
+    let mut lines = Vec::new();
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            lines.push(format!("  {}", old[i]));
+            i += 1;
+            j += 1;
+        } else if i < old.len() && (j == new.len() || common[i + 1][j] >= common[i][j + 1]) {
+            lines.push(format!("- {}", old[i]));
+            i += 1;
+        } else {
+            lines.push(format!("+ {}", new[j]));
+            j += 1;
+        }
+    }
+    lines
+}
@@ -1,11 +1,32 @@
-use std::{collections::HashMap, env};
+use std::{
+    cell::{Cell, RefCell},
+    collections::{BTreeMap, HashMap, HashSet},
+    env,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
+use base64::{
+    Engine, alphabet,
+    engine::{DecodePaddingMode, GeneralPurpose, GeneralPurposeConfig},
+};
 use rust_decimal::{Decimal, prelude::FromPrimitive, prelude::ToPrimitive};
 
 use crate::{
-    ast::{BinOp, Expr, Query, Statement},
-    transform::{PathSegment, TransformType, determine_transform_type, extract_path},
-    value::Value,
+    ast::{BinOp, EnvVarType, Expr, MatchArm, Pattern, Query, Statement, UDF},
+    case,
+    functions::FunctionRegistry,
+    memo::{self, Memo},
+    methods::MethodRegistry,
+    output::to_json,
+    random::Rng,
+    regex_cache::{REGEX_SIZE_LIMIT, RegexCache},
+    suggest, time,
+    transform::{
+        PathSegment, TransformType, determine_transform_type, extract_path, parse_key_path, resolve_path,
+        select_path,
+    },
+    value::{Value, whole_number},
 };
 
 /// Evaluation context holding both root and lambda contexts
@@ -15,22 +36,453 @@ pub struct EvalContext {
     pub root: Value,
     /// The current lambda item (what @ refers to), if in lambda function
     pub lambda: Option<Value>,
+    /// Metadata about the record being evaluated (what `$meta` refers to)
+    pub meta: Arc<HashMap<String, Value>>,
+    /// Arguments of the UDF being evaluated (what `@1`, `@2`... refer to)
+    args: Arc<Vec<UdfArg>>,
+    /// The key of the object entry being filtered (what `@key` refers to)
+    key: Option<String>,
+    /// Items of the enclosing named lambdas (`item -> ...`), innermost last
+    bindings: Arc<Vec<(String, Value)>>,
+    /// Items of the lambdas around the current one (what `@@` refers to)
+    enclosing: Option<Arc<Enclosing>>,
+    /// The document the query started from (what `$$` refers to), when
+    /// earlier stages may have replaced `root`
+    original: Option<Arc<Value>>,
+}
+
+/// The item of an enclosing lambda, and of the ones around it
+#[derive(Debug)]
+struct Enclosing {
+    item: Value,
+    outer: Option<Arc<Enclosing>>,
 }
 
 impl EvalContext {
     pub fn new(root: Value) -> Self {
-        EvalContext { root, lambda: None }
+        EvalContext {
+            root,
+            lambda: None,
+            meta: Arc::default(),
+            args: Arc::default(),
+            key: None,
+            bindings: Arc::default(),
+            enclosing: None,
+            original: None,
+        }
     }
 
-    /// Create a new context with lambda item
+    /// Create a new context with lambda item. The current item, if any,
+    /// becomes the enclosing one (`@@`).
     pub fn with_lambda(&self, lambda: Value) -> Self {
+        let enclosing = match &self.lambda {
+            Some(item) => {
+                Some(Arc::new(Enclosing { item: item.clone(), outer: self.enclosing.clone() }))
+            }
+            None => self.enclosing.clone(),
+        };
         EvalContext {
             root: self.root.clone(),
             lambda: Some(lambda),
+            meta: Arc::clone(&self.meta),
+            args: Arc::clone(&self.args),
+            key: self.key.clone(),
+            bindings: Arc::clone(&self.bindings),
+            enclosing,
+            original: self.original.clone(),
+        }
+    }
+
+    /// Create a new context for an object entry: `@` is its value and
+    /// `@key` its key
+    fn with_entry(&self, key: &str, value: Value) -> Self {
+        EvalContext { key: Some(key.to_string()), ..self.with_lambda(value) }
+    }
+
+    /// Create a new context with different UDF arguments
+    fn with_args(&self, args: Arc<Vec<UdfArg>>) -> Self {
+        EvalContext {
+            root: self.root.clone(),
+            lambda: self.lambda.clone(),
+            meta: Arc::clone(&self.meta),
+            args,
+            key: self.key.clone(),
+            bindings: Arc::clone(&self.bindings),
+            enclosing: self.enclosing.clone(),
+            original: self.original.clone(),
+        }
+    }
+
+    /// Create a new context in which `@name` refers to `value`
+    fn with_binding(&self, name: &str, value: Value) -> Self {
+        self.with_bindings(vec![(name.to_string(), value)])
+    }
+
+    /// Create a new context with several names bound at once
+    fn with_bindings(&self, bindings: Vec<(String, Value)>) -> Self {
+        let mut context = self.with_args(Arc::clone(&self.args));
+        Arc::make_mut(&mut context.bindings).extend(bindings);
+        context
+    }
+
+    /// Attach record metadata, e.g. `line` or `file`
+    pub fn with_meta(mut self, meta: Arc<HashMap<String, Value>>) -> Self {
+        self.meta = meta;
+        self
+    }
+
+    /// Attach the document the query started from, if `root` is not it
+    fn with_original(mut self, original: Option<Arc<Value>>) -> Self {
+        self.original = original;
+        self
+    }
+
+    /// A context for a later stage, where `$` is `root` and the rest carries over
+    fn next_stage(&self, root: Value) -> Self {
+        EvalContext::new(root).with_meta(Arc::clone(&self.meta)).with_original(self.original.clone())
+    }
+
+    /// The document the query started from (`$$`)
+    fn original(&self) -> &Value {
+        self.original.as_deref().unwrap_or(&self.root)
+    }
+}
+
+/// An argument bound to a UDF parameter
+#[derive(Debug, Clone)]
+enum UdfArg {
+    /// Evaluated once, at the call site
+    Value(Value),
+    /// Evaluated wherever the body uses the parameter, so that `@` inside
+    /// it refers to the item of the body's own lambda (`.map(@2)`). `args`
+    /// are the arguments in scope at the call site.
+    Lambda { expr: Expr, args: Arc<Vec<UdfArg>> },
+}
+
+/// A UDF ready to call
+#[derive(Debug)]
+struct CompiledUdf {
+    arity: usize,
+    /// Defaults of the last `defaults.len()` parameters
+    defaults: Vec<Expr>,
+    /// Whether extra arguments are collected into `@rest`
+    variadic: bool,
+    body: Statement,
+    /// Per parameter: whether it is passed by name (see [`lambda_params`])
+    by_name: Vec<bool>,
+}
+
+/// Where evaluating a UDF body ended: with its value, or at a call of the
+/// same UDF in tail position, whose arguments are bound but whose body is
+/// not yet evaluated
+enum TailCall {
+    Done(Value),
+    Recur(Vec<UdfArg>),
+}
+
+/// Deepest chain of UDF calls before evaluation gives up, unless set with
+/// [`Evaluator::with_max_call_depth`]
+pub const MAX_CALL_DEPTH: usize = 64;
+
+/// Most calls in tail position a UDF call may loop through, so that a
+/// function that recurses forever fails rather than hangs
+const MAX_TAIL_CALLS: usize = 100_000;
+
+/// Most intervals `.rate_per()` fills in between the earliest and latest
+/// timestamps
+const MAX_RATE_INTERVALS: i64 = 1_000_000;
+
+/// Base64 engines for `.base64_decode()`, which accept text with or
+/// without padding
+const BASE64_DECODE: GeneralPurposeConfig =
+    GeneralPurposeConfig::new().with_decode_padding_mode(DecodePaddingMode::Indifferent);
+const BASE64_STANDARD: GeneralPurpose = GeneralPurpose::new(&alphabet::STANDARD, BASE64_DECODE);
+const BASE64_URL_SAFE: GeneralPurpose = GeneralPurpose::new(&alphabet::URL_SAFE, BASE64_DECODE);
+
+/// Controls what `$NAME` environment variable references can see.
+///
+/// Queries from untrusted sources should not be able to read secrets from the
+/// host's environment. Set a policy with [`Evaluator::with_env_policy`].
+///
+/// # Examples
+///
+/// ```
+/// use clove_lang::{EnvPolicy, Evaluator};
+/// use std::collections::HashMap;
+///
+/// // Only expose what the query needs, without touching the real environment
+/// let vars = HashMap::from([("THRESHOLD".to_string(), "10".to_string())]);
+/// let evaluator = Evaluator::new().with_env_policy(EnvPolicy::Map(vars));
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum EnvPolicy {
+    /// Read from the process environment (default)
+    #[default]
+    Process,
+    /// Every lookup fails with [`EvalError::EnvAccessDenied`]
+    DenyAll,
+    /// Only the listed names are read from the process environment
+    AllowList(HashSet<String>),
+    /// Lookups are served from this map; the process environment is never read
+    Map(HashMap<String, String>),
+}
+
+/// What integer arithmetic does when a result does not fit in an `i64`.
+///
+/// Applies to `+`, `-`, `*`, `/` and `.sum()`. Set a policy with
+/// [`Evaluator::with_overflow_policy`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// Continue in floating point, losing precision past 2^53 (default)
+    #[default]
+    Float,
+    /// Fail with [`EvalError::Overflow`]
+    Error,
+}
+
+/// What float arithmetic does when a result is NaN or infinite, such as
+/// `1e308 * 10`.
+///
+/// Applies to `+`, `-`, `*`, `/`, `%` and `.sum()`. Set a policy with
+/// [`Evaluator::with_non_finite_policy`]; queries can also guard with
+/// `.is_nan()` and `.is_finite()`. Negative zero is an ordinary float: it
+/// equals `0.0` and is written as `-0.0`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum NonFinitePolicy {
+    /// Keep the value; JSON output, which cannot represent it, writes it as
+    /// `null` (default)
+    #[default]
+    Null,
+    /// Fail with [`EvalError::NonFinite`]
+    Error,
+}
+
+impl std::str::FromStr for NonFinitePolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "null" => Ok(NonFinitePolicy::Null),
+            "error" => Ok(NonFinitePolicy::Error),
+            _ => Err(format!("Unknown non-finite number policy '{}' (expected null or error)", s)),
+        }
+    }
+}
+
+/// The result of running a query pipeline
+#[derive(Debug, Clone, PartialEq)]
+pub enum QueryOutcome {
+    /// The query's output
+    Value(Value),
+    /// A filter rejected the document, so the statements after it and the
+    /// output were skipped
+    FilteredOut,
+}
+
+/// The size of the document one stage of a query received, recorded when
+/// [`Evaluator::with_stage_stats`] is enabled
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StageStats {
+    /// What the stage is: `filter`, `transform`, `scope @name`, `delete`,
+    /// `access`, `exists` or `output`, or `result` for the query's result
+    pub stage: String,
+    /// Approximate bytes of the document (see [`Value::approx_size`])
+    pub bytes: usize,
+}
+
+/// What an evaluation did, recorded when [`Evaluator::with_eval_stats`] is
+/// enabled
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct EvalStats {
+    /// Expressions evaluated, counting a lambda body once per item
+    pub expressions: usize,
+    /// Method calls by method name (calls answered from the memo are not
+    /// counted)
+    pub method_calls: BTreeMap<String, usize>,
+    /// Deepest nesting of expressions being evaluated at once
+    pub max_depth: usize,
+    /// Approximate bytes (see [`Value::approx_size`]) of the values method
+    /// calls returned and the documents statements produced
+    pub allocated_bytes: usize,
+    /// Each statement, named as in [`StageStats`], in the order they started
+    /// (so the statements of a `when` block follow its own entry), then the
+    /// query's output
+    pub statements: Vec<StatementTiming>,
+    /// Approximate bytes of the query's result
+    pub result_bytes: usize,
+}
+
+/// The document one statement of a query received, and how long it took
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StatementTiming {
+    /// What the statement is, as in [`StageStats::stage`]
+    pub stage: String,
+    /// Approximate bytes of the document (see [`Value::approx_size`])
+    pub bytes: usize,
+    pub elapsed: Duration,
+}
+
+impl EvalStats {
+    /// The stats as a value, for logging or returning as JSON
+    ///
+    /// ```text
+    /// {"expressions": 12, "method_calls": {"map": 1}, "max_depth": 4, "allocated_bytes": 96,
+    ///  "statements": [{"stage": "output", "bytes": 64, "elapsed_ms": 0.02}], "result_bytes": 32}
+    /// ```
+    pub fn to_value(&self) -> Value {
+        let count = |n: usize| Value::Integer(i64::try_from(n).unwrap_or(i64::MAX));
+        Value::object([
+            ("expressions", count(self.expressions)),
+            (
+                "method_calls",
+                Value::object(self.method_calls.iter().map(|(name, n)| (name.as_str(), count(*n)))),
+            ),
+            ("max_depth", count(self.max_depth)),
+            ("allocated_bytes", count(self.allocated_bytes)),
+            (
+                "statements",
+                Value::array(self.statements.iter().map(|timing| {
+                    Value::object([
+                        ("stage", Value::from(timing.stage.as_str())),
+                        ("bytes", count(timing.bytes)),
+                        ("elapsed_ms", Value::Float(timing.elapsed.as_secs_f64() * 1000.0)),
+                    ])
+                })),
+            ),
+            ("result_bytes", count(self.result_bytes)),
+        ])
+    }
+}
+
+/// Running [`EvalStats`] of the evaluation in progress
+#[derive(Default)]
+struct StatsRecorder {
+    stats: RefCell<EvalStats>,
+    /// Expressions currently being evaluated
+    depth: Cell<usize>,
+}
+
+impl StatsRecorder {
+    fn enter(&self) {
+        let depth = self.depth.get() + 1;
+        self.depth.set(depth);
+        let mut stats = self.stats.borrow_mut();
+        stats.expressions += 1;
+        stats.max_depth = stats.max_depth.max(depth);
+    }
+
+    fn leave(&self) {
+        self.depth.set(self.depth.get() - 1);
+    }
+
+    /// Record a call of `method` that returned a value of `bytes`
+    fn method_call(&self, method: &str, bytes: usize) {
+        let mut stats = self.stats.borrow_mut();
+        *stats.method_calls.entry(method.to_string()).or_default() += 1;
+        stats.allocated_bytes += bytes;
+    }
+
+    /// Record a statement (or the output) that received a document of
+    /// `bytes`, returning its entry for [`end_statement`](Self::end_statement)
+    fn begin_statement(&self, stage: String, bytes: usize) -> usize {
+        let mut stats = self.stats.borrow_mut();
+        stats.statements.push(StatementTiming { stage, bytes, elapsed: Duration::ZERO });
+        stats.statements.len() - 1
+    }
+
+    /// Complete the entry of a statement that started at `start` and
+    /// produced `result`
+    fn end_statement(&self, entry: usize, start: Instant, result: Option<&Value>) {
+        let elapsed = start.elapsed();
+        let mut stats = self.stats.borrow_mut();
+        stats.allocated_bytes += result.map_or(0, Value::approx_size);
+        if let Some(timing) = stats.statements.get_mut(entry) {
+            timing.elapsed = elapsed;
+        }
+    }
+
+    fn result(&self, bytes: usize) {
+        self.stats.borrow_mut().result_bytes = bytes;
+    }
+
+    fn reset(&self) {
+        self.stats.take();
+        self.depth.set(0);
+    }
+}
+
+impl QueryOutcome {
+    /// The output value, with a filtered-out document as `null`
+    pub fn into_value(self) -> Value {
+        match self {
+            QueryOutcome::Value(value) => value,
+            QueryOutcome::FilteredOut => Value::Null,
+        }
+    }
+}
+
+/// One key of a multi-key sort: the per-element key expression and its direction
+struct SortKey<'a> {
+    expr: &'a Expr,
+    desc: bool,
+}
+
+/// A streaming stage in a lazily evaluated method chain
+enum LazyStage<'a> {
+    /// `.filter(pred)` - drop elements failing the predicate
+    Filter(&'a Expr),
+    /// `.map(expr)` - replace each element
+    Map(&'a Expr),
+}
+
+/// What every element reaching a lazy stage is known to be
+#[derive(Clone, Copy, PartialEq)]
+enum ItemKind {
+    Number,
+    Object,
+    Array,
+    Any,
+}
+
+impl ItemKind {
+    fn of(items: &[Value]) -> Self {
+        let kind = |item: &Value| match item {
+            Value::Integer(_) | Value::Float(_) => ItemKind::Number,
+            Value::Object(_) => ItemKind::Object,
+            Value::Array(_) => ItemKind::Array,
+            _ => ItemKind::Any,
+        };
+        match items.split_first() {
+            Some((first, rest)) if rest.iter().all(|item| kind(item) == kind(first)) => kind(first),
+            _ => ItemKind::Any,
         }
     }
 }
 
+/// How far [`Evaluator::eval_lazy_chain`] got with a method call
+enum LazyChain {
+    /// The chain was streamed to its result
+    Done(Value),
+    /// Not a chain that streams; nothing was evaluated
+    NotLazy,
+    /// The chain's source, already evaluated, for the eager path to carry
+    /// on from through its `stages` `.filter()`/`.map()` calls
+    Source { value: Value, stages: usize },
+}
+
+/// The method consuming a lazily evaluated chain
+enum LazyTerminal<'a> {
+    First,
+    Count(Option<&'a Expr>),
+    Exists,
+    Any(&'a Expr),
+    All(&'a Expr),
+    Find(&'a Expr),
+    FindIndex(&'a Expr),
+    /// Holds the count expression, evaluated once the source is
+    Take(&'a Expr),
+}
+
 /// The main query evaluator.
 ///
 /// Executes parsed queries against JSON documents, maintaining scope references
@@ -39,6 +491,44 @@ impl EvalContext {
 pub struct Evaluator {
     /// Named scope references defined during query execution (@name := ...)
     scopes: HashMap<String, Value>,
+    /// Live scopes (@name ::= ...), re-evaluated on every reference
+    live_scopes: HashMap<String, Expr>,
+    /// Errors of hoisted scopes, reported when the scope is referenced
+    deferred: HashMap<String, EvalError>,
+    /// Live scopes currently being evaluated, to report self-reference
+    resolving: RefCell<Vec<String>>,
+    /// What environment variable references may read
+    env_policy: EnvPolicy,
+    /// What integer arithmetic does on overflow
+    overflow_policy: OverflowPolicy,
+    /// What float arithmetic does with NaN and infinite results
+    non_finite_policy: NonFinitePolicy,
+    /// Whether ordering values of different types is an error
+    strict_ordering: bool,
+    /// UDFs defined by the query being evaluated
+    udfs: HashMap<String, Arc<CompiledUdf>>,
+    /// Number of UDF calls currently being evaluated
+    call_depth: Cell<usize>,
+    /// Deepest chain of UDF calls allowed ([`MAX_CALL_DEPTH`] if unset)
+    max_call_depth: Option<usize>,
+    /// Results of repeated pure subexpressions
+    memo: Memo,
+    /// Source of `.sample()`, `.shuffle()` and `&random[]`
+    rng: Rng,
+    /// Largest document, in approximate bytes, a stage may receive
+    max_document_bytes: Option<usize>,
+    /// Document sizes of the last query, when recording is enabled
+    stage_stats: Option<Vec<StageStats>>,
+    /// Counters and timings of the last query, when recording is enabled
+    eval_stats: Option<StatsRecorder>,
+    /// Methods supplied by the host
+    methods: MethodRegistry,
+    /// Functions supplied by the host, called as `ext::name(...)`
+    functions: FunctionRegistry,
+    /// Compiled `.matches()` patterns, possibly shared with other evaluators
+    regex_cache: RegexCache,
+    /// Largest compiled size of a pattern ([`REGEX_SIZE_LIMIT`] if unset)
+    regex_size_limit: Option<usize>,
 }
 
 /// Errors that can occur during query evaluation.
@@ -51,13 +541,55 @@ pub enum EvalError {
     AccessError(String),
 
     /// Reference to undefined scope (@name not defined)
-    UndefinedScope(String),
+    UndefinedScope {
+        name: String,
+        /// Defined scopes with a similar name, closest first
+        candidates: Vec<String>,
+    },
+
+    /// Call to a UDF the query does not define (&name[...])
+    UndefinedFunction {
+        name: String,
+        /// Defined functions with a similar name, closest first
+        candidates: Vec<String>,
+    },
+
+    /// Call to a method that is neither built in nor registered (.name(...))
+    UnknownMethod {
+        name: String,
+        /// Methods with a similar name, closest first
+        candidates: Vec<String>,
+    },
+
+    /// Call to a host function that is not registered (ext::name(...))
+    UndefinedHostFunction(String),
 
     /// Reference to undefined environment variable ($VARNAME)
     UndefinedEnvVar(String),
 
+    /// Environment variable blocked by the evaluator's [`EnvPolicy`]
+    EnvAccessDenied(String),
+
     /// Division by zero
     DivisionByZero,
+
+    /// Integer overflow under [`OverflowPolicy::Error`]
+    Overflow(String),
+
+    /// A NaN or infinite float result under [`NonFinitePolicy::Error`]
+    NonFinite(String),
+
+    /// A document larger than [`Evaluator::with_max_document_bytes`] allows
+    LimitExceeded(String),
+
+    /// A `.matches()` pattern that does not parse or is over the limits of
+    /// [`crate::regex_cache`]
+    InvalidRegex {
+        pattern: String,
+        message: String,
+        /// Character offset of the error in the pattern, when it has one
+        position: Option<usize>,
+    },
 }
 
 impl std::fmt::Display for EvalError {
@@ -65,427 +597,1740 @@ impl std::fmt::Display for EvalError {
         match self {
             EvalError::TypeError(msg) => write!(f, "Type error: {}", msg),
             EvalError::AccessError(msg) => write!(f, "Access error: {}", msg),
-            EvalError::UndefinedScope(name) => write!(f, "Undefined scope: @{} is not defined", name),
+            EvalError::UndefinedScope { name, candidates } => {
+                write!(f, "Undefined scope: @{} is not defined", name)?;
+                write_suggestion(f, "@", candidates, "")
+            }
+            EvalError::UndefinedFunction { name, candidates } => {
+                write!(f, "Undefined function: &{} is not defined", name)?;
+                write_suggestion(f, "&", candidates, "[]")
+            }
+            EvalError::UnknownMethod { name, candidates } => {
+                write!(f, "Unknown method: {}", name)?;
+                write_suggestion(f, ".", candidates, "()")
+            }
+            EvalError::UndefinedHostFunction(name) => {
+                write!(f, "Undefined function: ext::{} is not registered", name)
+            }
             EvalError::UndefinedEnvVar(name) => write!(f, "Undefined environment variable: ${}", name),
+            EvalError::EnvAccessDenied(name) => write!(f, "Access to environment variable ${} is not allowed", name),
             EvalError::DivisionByZero => write!(f, "Division by zero"),
+            EvalError::Overflow(msg) => write!(f, "Integer overflow: {}", msg),
+            EvalError::NonFinite(msg) => write!(f, "Non-finite number: {}", msg),
+            EvalError::LimitExceeded(msg) => write!(f, "Limit exceeded: {}", msg),
+            EvalError::InvalidRegex { pattern, message, position } => {
+                write!(f, "Invalid regex \"{}\": {}", pattern, message)?;
+                match position {
+                    Some(position) => write!(f, " (at offset {})", position),
+                    None => Ok(()),
+                }
+            }
         }
     }
 }
 
 impl std::error::Error for EvalError {}
 
+/// Append " (did you mean ...?)" naming the closest of `candidates`
+fn write_suggestion(
+    f: &mut std::fmt::Formatter<'_>,
+    prefix: &str,
+    candidates: &[String],
+    suffix: &str,
+) -> std::fmt::Result {
+    match candidates.first() {
+        Some(candidate) => write!(f, " (did you mean {}{}{}?)", prefix, candidate, suffix),
+        None => Ok(()),
+    }
+}
+
+/// Milliseconds since the epoch of a timestamp read by `.bucket_by_time()`
+/// or `.rate_per()`
+fn timestamp_arg(method: &str, ts: &Value) -> Result<i64, EvalError> {
+    time::timestamp_millis(ts).ok_or_else(|| {
+        EvalError::TypeError(format!(
+            ".{}() timestamps must be RFC 3339 strings or Unix seconds, got {}",
+            method,
+            match ts {
+                Value::String(s) => format!("\"{}\"", s),
+                other => type_name(other).to_string(),
+            }
+        ))
+    })
+}
+
+/// The bytes a bytes method works on: bytes as they are, or the UTF-8
+/// encoding of a string
+fn bytes_of<'a>(object: &'a Value, method: &str) -> Result<&'a [u8], EvalError> {
+    match object {
+        Value::Bytes(b) => Ok(b),
+        Value::String(s) => Ok(s.as_bytes()),
+        _ => Err(EvalError::TypeError(format!(
+            ".{}() requires bytes or string, got {}",
+            method,
+            type_name(object)
+        ))),
+    }
+}
+
+/// How [`StageStats`] names a statement
+pub(crate) fn stage_name(stmt: &Statement) -> String {
+    match stmt {
+        Statement::Filter(_) => "filter".to_string(),
+        Statement::When { .. } => "when".to_string(),
+        Statement::ForEach { name, .. } => format!("foreach @{}", name),
+        Statement::Transform { .. } => "transform".to_string(),
+        Statement::ScopeDefinition { name, .. }
+        | Statement::LiveScopeDefinition { name, .. }
+        | Statement::HoistedScope { name, .. } => format!("scope @{}", name),
+        Statement::Delete(_) => "delete".to_string(),
+        Statement::Access(_) => "access".to_string(),
+        Statement::ExistenceCheck(_) => "exists".to_string(),
+    }
+}
+
+/// The path below `@name` that a statement in its foreach body changes
+fn item_path(target: &Expr, name: &str) -> Result<Vec<PathSegment>, EvalError> {
+    fn rebase(expr: &Expr, name: &str) -> Option<Expr> {
+        match expr {
+            Expr::LambdaVar(var) if var == name => Some(Expr::Root),
+            Expr::Access { object, key } => {
+                Some(Expr::Access { object: Box::new(rebase(object, name)?), key: key.clone() })
+            }
+            _ => None,
+        }
+    }
+    match rebase(target, name) {
+        Some(path) => extract_path(&path),
+        None => Err(EvalError::TypeError(format!(
+            "A foreach body can only change its element: use a path such as @{}[field]",
+            name
+        ))),
+    }
+}
+
+/// A path as written in error messages, e.g. `items.0`
+fn display_path(path: &[PathSegment]) -> String {
+    path.iter().map(ToString::to_string).collect::<Vec<_>>().join(".")
+}
+
 /// Returns a human-readable type name for a Value
-fn type_name(v: &Value) -> &'static str {
+pub(crate) fn type_name(v: &Value) -> &'static str {
     match v {
         Value::Null => "null",
         Value::Boolean(_) => "boolean",
         Value::Integer(_) => "integer",
         Value::Float(_) => "float",
         Value::String(_) => "string",
+        Value::Bytes(_) => "bytes",
         Value::Array(_) => "array",
         Value::Object(_) => "object",
     }
 }
 
+/// Methods that evaluate their arguments once per element, with `@` bound to it
+pub(crate) const LAMBDA_METHODS: &[&str] = &[
+    "any", "all", "filter", "map", "sum", "unique_by", "sort", "sort_by", "take_while", "skip_while",
+    "find", "find_index", "bucket_by_time", "flat_map", "count", "min_by", "max_by", "arg_min",
+    "arg_max", "walk",
+];
+
+/// Every method name `.name(...)` can call, other than those of features
+/// (see [`method_names`])
+pub(crate) const METHOD_NAMES: &[&str] = &[
+    "any", "all", "find", "find_index", "index_of", "last_index_of", "filter", "map", "count",
+    "length", "sum", "min", "max", "min_by", "max_by", "arg_min", "arg_max", "avg", "median",
+    "percentile", "variance", "stddev", "stats", "first", "last", "exists", "unique", "unique_by",
+    "sort", "sort_by", "sort_desc", "reverse", "flatten", "take", "sample", "shuffle", "skip",
+    "take_while", "skip_while", "chunk", "window", "upper", "lower", "trim", "title", "snake_case",
+    "camel_case", "kebab_case", "normalize_unicode", "split", "lines", "words", "chars", "contains",
+    "contains_all", "contains_any", "startswith", "endswith", "matches", "keys", "sorted_keys",
+    "values", "has", "has_path", "get_path", "type", "or", "bucket_by_time", "rate_per", "flat_map",
+    "base64_decode", "base64_encode", "hex_decode", "hex_encode", "bytes_len", "is_nan",
+    "is_finite", "walk", "paths",
+];
+
+/// Every method name `.name(...)` can call with the enabled features
+pub(crate) fn method_names<'a>() -> impl Iterator<Item = &'a str> {
+    let (names, features): (&[&'a str], &[&'a str]) = (METHOD_NAMES, FEATURE_METHODS);
+    names.iter().chain(features).copied()
+}
+
+#[cfg(feature = "stdlib")]
+const FEATURE_METHODS: &[&str] = crate::stdlib::METHODS;
+#[cfg(not(feature = "stdlib"))]
+const FEATURE_METHODS: &[&str] = &[];
+
+/// Methods whose result is random, so that two calls may differ
+pub(crate) const RANDOM_METHODS: &[&str] = &["sample", "shuffle"];
+
+/// Which of a UDF's `arity` parameters are passed by name: those its body
+/// hands directly to a lambda method (`@1.map(@2)`), or to a by-name
+/// parameter of another UDF
+fn lambda_params(body: &Statement, arity: usize, udfs: &HashMap<&str, Vec<bool>>) -> Vec<bool> {
+    let mut by_name = vec![false; arity];
+    let mut exprs = Vec::new();
+    memo::statement_exprs(body, &mut exprs);
+    for expr in exprs {
+        mark_lambda_args(expr, udfs, &mut by_name);
+    }
+    by_name
+}
+
+fn mark_lambda_args(expr: &Expr, udfs: &HashMap<&str, Vec<bool>>, by_name: &mut [bool]) {
+    let mut walk = |expr: &Expr| mark_lambda_args(expr, udfs, by_name);
+    match expr {
+        Expr::MethodCall { object, method, args } => {
+            mark_lambda_args(object, udfs, by_name);
+            for arg in args {
+                if LAMBDA_METHODS.contains(&method.as_str()) {
+                    mark_by_name(arg, by_name);
+                }
+                mark_lambda_args(arg, udfs, by_name);
+            }
+        }
+        Expr::UDFCall { name, args } => {
+            let params = udfs.get(name.as_str());
+            for (i, arg) in args.iter().enumerate() {
+                if params.and_then(|p| p.get(i)) == Some(&true) {
+                    mark_by_name(arg, by_name);
+                }
+                mark_lambda_args(arg, udfs, by_name);
+            }
+        }
+        Expr::HostCall { args, .. } => args.iter().for_each(walk),
+        Expr::Access { object, key } => {
+            walk(object);
+            walk(key);
+        }
+        Expr::BinaryOp { left, right, .. } => {
+            walk(left);
+            walk(right);
+        }
+        Expr::ExistenceCheck(inner) | Expr::Filter(inner) => walk(inner),
+        Expr::Lambda { body, .. } => walk(body),
+        Expr::Try { body, fallback } => {
+            walk(body);
+            walk(fallback);
+        }
+        Expr::Match { subject, arms } => {
+            walk(subject);
+            for arm in arms {
+                arm.guard.iter().for_each(&mut walk);
+                walk(&arm.body);
+            }
+        }
+        Expr::Object(items) => items.iter().for_each(|(_, value)| walk(value)),
+        Expr::Array(items) => items.iter().for_each(walk),
+        Expr::Float(_)
+        | Expr::Integer(_)
+        | Expr::String(_)
+        | Expr::Boolean(_)
+        | Expr::Null
+        | Expr::Root
+        | Expr::OriginalRoot
+        | Expr::ScopeRef(_)
+        | Expr::LambdaParam
+        | Expr::OuterLambdaParam(_)
+        | Expr::LambdaVar(_)
+        | Expr::ArgRef(_)
+        | Expr::EnvVar(_)
+        | Expr::TypedEnvVar { .. }
+        | Expr::Meta
+        | Expr::Key(_)
+        | Expr::Wildcard => {}
+    }
+}
+
+/// Whether `query`, or a UDF it defines, refers to the original document (`$$`)
+fn reads_original(query: &Query) -> bool {
+    let mut exprs = memo::query_exprs(query);
+    for udf in &query.udfs {
+        memo::statement_exprs(&udf.body, &mut exprs);
+        exprs.extend(&udf.defaults);
+    }
+    exprs.into_iter().any(mentions_original)
+}
+
+fn mentions_original(expr: &Expr) -> bool {
+    match expr {
+        Expr::OriginalRoot => true,
+        Expr::MethodCall { object, args, .. } => mentions_original(object) || args.iter().any(mentions_original),
+        Expr::UDFCall { args, .. } | Expr::HostCall { args, .. } | Expr::Array(args) => {
+            args.iter().any(mentions_original)
+        }
+        Expr::Access { object: left, key: right }
+        | Expr::BinaryOp { left, right, .. }
+        | Expr::Try { body: left, fallback: right } => mentions_original(left) || mentions_original(right),
+        Expr::ExistenceCheck(inner) | Expr::Filter(inner) | Expr::Lambda { body: inner, .. } => {
+            mentions_original(inner)
+        }
+        Expr::Match { subject, arms } => {
+            mentions_original(subject)
+                || arms.iter().any(|arm| arm.guard.iter().chain([&arm.body]).any(mentions_original))
+        }
+        Expr::Object(items) => items.iter().any(|(_, value)| mentions_original(value)),
+        Expr::Float(_)
+        | Expr::Integer(_)
+        | Expr::String(_)
+        | Expr::Boolean(_)
+        | Expr::Null
+        | Expr::Root
+        | Expr::ScopeRef(_)
+        | Expr::LambdaParam
+        | Expr::OuterLambdaParam(_)
+        | Expr::LambdaVar(_)
+        | Expr::ArgRef(_)
+        | Expr::EnvVar(_)
+        | Expr::TypedEnvVar { .. }
+        | Expr::Meta
+        | Expr::Key(_)
+        | Expr::Wildcard => false,
+    }
+}
+
+/// Mark `arg` as passed by name if it is a bare parameter reference (`@2`)
+fn mark_by_name(arg: &Expr, by_name: &mut [bool]) {
+    if let Expr::ArgRef(n) = arg
+        && let Some(flag) = n.checked_sub(1).and_then(|i| by_name.get_mut(i))
+    {
+        *flag = true;
+    }
+}
+
+/// Result of an existence check (`[?]`): present and non-empty
+fn exists(value: &Value) -> bool {
+    match value {
+        Value::Null => false,
+        Value::Array(arr) => !arr.is_empty(),
+        Value::Object(obj) => !obj.is_empty(),
+        Value::String(s) => !s.is_empty(),
+        _ => true,
+    }
+}
+
+fn is_number(value: &Value) -> bool {
+    matches!(value, Value::Integer(_) | Value::Float(_))
+}
+
+fn is_zero(value: &Value) -> bool {
+    match value {
+        Value::Integer(n) => *n == 0,
+        Value::Float(n) => *n == 0.0,
+        _ => false,
+    }
+}
+
+/// Numeric items of an array receiver as floats (non-numeric items are
+/// skipped, as in `.avg()`)
+fn numeric_items(method: &str, object: &Value) -> Result<Vec<f64>, EvalError> {
+    match object {
+        Value::Array(arr) => Ok(arr.iter().filter_map(Value::as_float).collect()),
+        _ => Err(EvalError::TypeError(format!(
+            ".{}() requires array, got {}",
+            method,
+            type_name(object)
+        ))),
+    }
+}
+
+/// Percentile `p` (0-100) of sorted numbers, interpolating linearly between
+/// the closest ranks; `None` when empty
+fn percentile_of_sorted(sorted: &[f64], p: f64) -> Option<f64> {
+    let last = sorted.len().checked_sub(1)?;
+    let rank = p / 100.0 * last as f64;
+    let lower = rank.floor() as usize;
+    let upper = rank.ceil() as usize;
+    Some(sorted[lower] + (sorted[upper] - sorted[lower]) * (rank - lower as f64))
+}
+
+/// Count, mean and sum of squared deviations, accumulated in a single pass
+/// (Welford's algorithm)
+struct Moments {
+    count: usize,
+    mean: f64,
+    m2: f64,
+}
+
+impl Moments {
+    fn of(numbers: &[f64]) -> Self {
+        let mut moments = Moments { count: 0, mean: 0.0, m2: 0.0 };
+        for &x in numbers {
+            moments.count += 1;
+            let delta = x - moments.mean;
+            moments.mean += delta / moments.count as f64;
+            moments.m2 += delta * (x - moments.mean);
+        }
+        moments
+    }
+
+    fn mean(&self) -> Option<f64> {
+        (self.count > 0).then_some(self.mean)
+    }
+
+    /// Population variance
+    fn variance(&self) -> Option<f64> {
+        (self.count > 0).then(|| self.m2 / self.count as f64)
+    }
+}
+
+/// Convert the raw text of an environment variable for `$NAME:type`
+fn convert_env_value(name: &str, raw: &str, ty: EnvVarType) -> Result<Value, EvalError> {
+    let trimmed = raw.trim();
+    let converted = match ty {
+        EnvVarType::String => Some(Value::String(raw.to_string())),
+        EnvVarType::Int => trimmed.parse::<i64>().ok().map(Value::Integer),
+        EnvVarType::Float => trimmed.parse::<f64>().ok().filter(|f| f.is_finite()).map(Value::Float),
+        EnvVarType::Bool => match trimmed.to_lowercase().as_str() {
+            "true" | "1" | "yes" | "on" => Some(Value::Boolean(true)),
+            "false" | "0" | "no" | "off" => Some(Value::Boolean(false)),
+            _ => None,
+        },
+    };
+    converted.ok_or_else(|| {
+        EvalError::TypeError(format!(
+            "Environment variable ${} = '{}' is not a valid {}",
+            name,
+            raw,
+            ty.name()
+        ))
+    })
+}
+
 impl Evaluator {
     /// Creates a new evaluator with empty scope references.
     pub fn new() -> Self {
         Self::default()
     }
 
-    /// Evaluates a complete query against a JSON document.
-    ///
-    /// Executes the query pipeline statement by statement, threading the result
-    /// through each transformation, and returns the final output.
+    /// Sets the policy governing `$NAME` environment variable lookups.
     ///
-    /// # Arguments
-    ///
-    /// * `query` - The parsed query to execute
-    /// * `document` - The JSON document to query (becomes the root `$`)
+    /// See [`EnvPolicy`]. The default reads the process environment.
+    pub fn with_env_policy(mut self, policy: EnvPolicy) -> Self {
+        self.env_policy = policy;
+        self
+    }
+
+    /// Sets what integer arithmetic does when a result overflows `i64`.
     ///
-    /// # Returns
+    /// See [`OverflowPolicy`]. The default continues in floating point.
+    pub fn with_overflow_policy(mut self, policy: OverflowPolicy) -> Self {
+        self.overflow_policy = policy;
+        self
+    }
+
+    /// Sets what float arithmetic does when a result is NaN or infinite.
     ///
-    /// The result of the query's output expression, or the final transformed
-    /// document if no explicit output is specified.
+    /// See [`NonFinitePolicy`]. The default keeps the value, which JSON
+    /// output writes as `null`.
     ///
     /// # Examples
     ///
     /// ```
-    /// use clove_lang::Evaluator;
-    /// use clove_lang::parser::Parser;
-    /// use clove_lang::lexer::Lexer;
-    /// use clove_lang::Value;
-    /// use std::collections::HashMap;
+    /// use clove_lang::{EvalError, Evaluator, Lexer, NonFinitePolicy, Parser, Value};
     ///
-    /// let mut doc = HashMap::new();
-    /// doc.insert("price".to_string(), Value::Integer(100));
+    /// let expr = Parser::new(Lexer::new("1e308 * 10")).unwrap().parse().unwrap();
+    /// let result = Evaluator::new().eval_expression(&expr, Value::Null).unwrap();
+    /// assert_eq!(result, Value::Float(f64::INFINITY));
+    /// let result = Evaluator::new()
+    ///     .with_non_finite_policy(NonFinitePolicy::Error)
+    ///     .eval_expression(&expr, Value::Null);
+    /// assert!(matches!(result, Err(EvalError::NonFinite(_))));
+    /// ```
+    pub fn with_non_finite_policy(mut self, policy: NonFinitePolicy) -> Self {
+        self.non_finite_policy = policy;
+        self
+    }
+
+    /// Makes `.sort()`, `.sort_desc()`, `.sort_by()`, `.min()`, `.max()`,
+    /// `.min_by()`, `.max_by()`, `.arg_min()` and `.arg_max()` fail with
+    /// [`EvalError::TypeError`] when they would order values of different
+    /// types, such as a number and a string or null. Integers and floats are
+    /// both numbers.
     ///
-    /// let query_str = "$ | ?($[price] > 50)";
-    /// let lexer = Lexer::new(query_str);
-    /// let mut parser = Parser::new(lexer).unwrap();
-    /// let query = parser.parse_query().unwrap();
+    /// By default such values are ordered by type (see [`Value::total_cmp`]),
+    /// which is rarely what a query means.
+    ///
+    /// # Examples
     ///
-    /// let mut evaluator = Evaluator::new();
-    /// let result = evaluator.eval_query(&query, Value::Object(doc)).unwrap();
-    /// // Returns the document because price > 50
     /// ```
-    pub fn eval_query(&mut self, query: &Query, document: Value) -> Result<Value, EvalError> {
-        let mut current = document;
+    /// use clove_lang::{clove_value, EvalError, Evaluator, Lexer, Parser};
+    ///
+    /// let expr = Parser::new(Lexer::new("$.sort()")).unwrap().parse().unwrap();
+    /// let doc = clove_value!([3, "2", 1.5]);
+    /// assert!(Evaluator::new().eval_expression(&expr, doc.clone()).is_ok());
+    /// let result = Evaluator::new().with_strict_ordering(true).eval_expression(&expr, doc);
+    /// assert!(matches!(result, Err(EvalError::TypeError(_))));
+    /// ```
+    pub fn with_strict_ordering(mut self, strict: bool) -> Self {
+        self.strict_ordering = strict;
+        self
+    }
 
-        for stmt in &query.statements {
-            let ctx = EvalContext::new(current);
-            current = self.eval_statement(stmt, &ctx)?;
-        }
+    /// Enables or disables caching the results of repeated pure
+    /// subexpressions within an evaluation (see [`crate::memo`]).
+    ///
+    /// Enabled by default. Results are the same either way; disabling it
+    /// saves hashing the document for queries that gain nothing from it.
+    pub fn with_memoization(mut self, enabled: bool) -> Self {
+        self.memo.set_enabled(enabled);
+        self
+    }
 
-        match &query.output {
-            Some(expr) => {
-                let ctx = EvalContext::new(current);
+    /// Makes the host methods in `methods` callable from queries, ahead of
+    /// the built-in methods (see [`crate::methods`]).
+    pub fn with_methods(mut self, methods: MethodRegistry) -> Self {
+        self.methods = methods;
+        self
+    }
 
-                self.eval_expr(expr, &ctx)
-            }
-            None => Ok(current),
-        }
+    /// Makes the host functions in `functions` callable from queries as
+    /// `ext::name(...)` (see [`crate::functions`]).
+    pub fn with_functions(mut self, functions: FunctionRegistry) -> Self {
+        self.functions = functions;
+        self
     }
 
-    /// Evaluates a single expression against a JSON document.
+    /// Binds query parameters, which queries read as `@name` like a scope
+    /// defined before their first statement. A scope the query defines
+    /// with the same name replaces the parameter.
     ///
-    /// This is a convenience method for evaluating standalone expressions
-    /// without a full query pipeline.
+    /// # Examples
     ///
-    /// # Arguments
+    /// ```
+    /// use std::collections::HashMap;
+    /// use clove_lang::{clove_value, Evaluator, Lexer, Parser, Value};
     ///
-    /// * `expr` - The expression to evaluate
-    /// * `document` - The JSON document (becomes the root `$`)
+    /// let expr = Parser::new(Lexer::new("$.filter(@[level] == @level)")).unwrap().parse().unwrap();
+    /// let params = HashMap::from([("level".to_string(), Value::String("error".to_string()))]);
+    /// let doc = clove_value!([{"level": "info"}, {"level": "error"}]);
+    /// let errors = Evaluator::new().with_params(params).eval_expression(&expr, doc).unwrap();
+    /// assert_eq!(errors, clove_value!([{"level": "error"}]));
+    /// ```
+    pub fn with_params(mut self, params: HashMap<String, Value>) -> Self {
+        self.scopes.extend(params);
+        self
+    }
+
+    /// Seeds the random numbers behind `.sample()`, `.shuffle()` and
+    /// `&random[]`, so that they repeat from run to run.
+    ///
+    /// By default the seed is different for every evaluator.
     ///
     /// # Examples
     ///
     /// ```
-    /// use clove_lang::{Evaluator, Expr, Value};
+    /// use clove_lang::{clove_value, Evaluator, Lexer, Parser};
     ///
-    /// let mut evaluator = Evaluator::new();
-    /// let expr = Expr::Root;
-    /// let doc = Value::Integer(42);
+    /// let expr = Parser::new(Lexer::new("$.shuffle()")).unwrap().parse().unwrap();
+    /// let doc = clove_value!([1, 2, 3, 4, 5, 6, 7, 8]);
+    /// let first = Evaluator::new().with_seed(7).eval_expression(&expr, doc.clone()).unwrap();
+    /// let again = Evaluator::new().with_seed(7).eval_expression(&expr, doc).unwrap();
+    /// assert_eq!(first, again);
+    /// ```
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.rng = Rng::seeded(seed);
+        self
+    }
+
+    /// Fails evaluation with [`EvalError::LimitExceeded`] when a query
+    /// stage would receive a document of more than `limit` bytes.
+    ///
+    /// Every stage works on its own copy of the document, so a query over a
+    /// very large input holds several at once. The size is checked against
+    /// [`Value::approx_size`] for the input, between stages and for the
+    /// result, so a stage that grows the document is stopped before the
+    /// next one copies it. Unlimited by default.
+    ///
+    /// # Examples
     ///
-    /// let result = evaluator.eval_expression(&expr, doc).unwrap();
-    /// assert_eq!(result, Value::Integer(42));
     /// ```
-    pub fn eval_expression(&mut self, expr: &Expr, document: Value) -> Result<Value, EvalError> {
-        let context = EvalContext::new(document);
-        self.eval_expr(expr, &context)
+    /// use clove_lang::{clove_value, EvalError, Evaluator, Lexer, Parser};
+    ///
+    /// let query = Parser::new(Lexer::new("$ | ~($[copy] := $[items])")).unwrap().parse_query().unwrap();
+    /// let doc = clove_value!({"items": [1, 2, 3, 4, 5, 6, 7, 8]});
+    /// let limit = doc.approx_size();
+    ///
+    /// let result = Evaluator::new().with_max_document_bytes(limit).eval_query(&query, doc);
+    /// assert!(matches!(result, Err(EvalError::LimitExceeded(_))));
+    /// ```
+    pub fn with_max_document_bytes(mut self, limit: usize) -> Self {
+        self.max_document_bytes = Some(limit);
+        self
     }
 
-    fn eval_statement(&mut self, stmt: &Statement, ctx: &EvalContext) -> Result<Value, EvalError> {
-        match stmt {
-            Statement::Filter(condition) => {
-                let result = self.eval_expr(condition, ctx)?;
-                if result.as_bool() {
-                    Ok(ctx.root.clone())
-                } else {
-                    Ok(Value::Null)
-                }
-            }
-            Statement::Transform { target, value } => self.apply_transform(ctx, target, value),
-            Statement::ScopeDefinition { name, path } => {
-                let value = self.eval_expr(path, ctx)?;
-                self.scopes.insert(name.clone(), value);
-                Ok(ctx.root.clone())
-            }
-            Statement::Delete(path_expr) => {
-                let path = extract_path(path_expr)?;
-                if path.is_empty() {
-                    return Ok(ctx.root.clone());
-                }
-                let mut result = ctx.root.clone();
-                self.delete_field(&mut result, &path);
-                Ok(result)
-            }
-            Statement::Access(expr) => self.eval_expr(expr, ctx),
-            Statement::ExistenceCheck(_expr) => unreachable!(),
+    /// Fails evaluation with [`EvalError::LimitExceeded`] when UDF calls
+    /// nest more than `limit` deep, such as a recursive UDF walking a tree.
+    /// The default is [`MAX_CALL_DEPTH`].
+    ///
+    /// Each nested call takes stack, so a higher limit may need a larger
+    /// stack. A UDF that calls itself in tail position (as its whole body,
+    /// in an arm of a `match` that is, or on the right of a `??` that is)
+    /// does not nest: the call replaces the current one, so such loops can
+    /// run far deeper than the limit.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use clove_lang::{EvalError, Evaluator, Lexer, Parser, Value};
+    ///
+    /// let source = "&depth:1 := match @1 { {\"child\": c} => 1 + &depth[@c], _ => 0 }\n$ | !(&depth[$])";
+    /// let query = Parser::new(Lexer::new(source)).unwrap().parse_query().unwrap();
+    /// let doc = Value::from_reader(r#"{"child": {"child": {"child": {}}}}"#.as_bytes()).unwrap();
+    ///
+    /// let result = Evaluator::new().eval_query(&query, doc.clone()).unwrap();
+    /// assert_eq!(result, Value::Integer(3));
+    /// let result = Evaluator::new().with_max_call_depth(2).eval_query(&query, doc);
+    /// assert!(matches!(result, Err(EvalError::LimitExceeded(_))));
+    /// ```
+    pub fn with_max_call_depth(mut self, limit: usize) -> Self {
+        self.max_call_depth = Some(limit);
+        self
+    }
+
+    /// Fails `.matches()` with [`EvalError::InvalidRegex`] when its pattern
+    /// would compile to more than `bytes`. The default is
+    /// [`REGEX_SIZE_LIMIT`]; see [`crate::regex_cache`] for the other limits.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use clove_lang::{EvalError, Evaluator, Lexer, Parser, Value};
+    ///
+    /// let expr = Parser::new(Lexer::new(r#"$.matches("\\w{50}")"#)).unwrap().parse().unwrap();
+    /// let text = Value::String("word".into());
+    ///
+    /// assert!(Evaluator::new().eval_expression(&expr, text.clone()).is_ok());
+    /// let result = Evaluator::new().with_regex_size_limit(10_000).eval_expression(&expr, text);
+    /// assert!(matches!(result, Err(EvalError::InvalidRegex { .. })));
+    /// ```
+    pub fn with_regex_size_limit(mut self, bytes: usize) -> Self {
+        self.regex_size_limit = Some(bytes);
+        self
+    }
+
+    /// Keeps compiled `.matches()` patterns in `cache`, which clones of it
+    /// given to other evaluators share. Each evaluator otherwise has its own
+    /// cache of [`RegexCache::DEFAULT_CAPACITY`] patterns.
+    pub fn with_regex_cache(mut self, cache: RegexCache) -> Self {
+        self.regex_cache = cache;
+        self
+    }
+
+    /// Records the size of the document each stage of a query receives,
+    /// for [`stage_stats`](Self::stage_stats).
+    ///
+    /// Off by default, as measuring walks the whole document per stage.
+    pub fn with_stage_stats(mut self, enabled: bool) -> Self {
+        self.stage_stats = enabled.then(Vec::new);
+        self
+    }
+
+    /// The document sizes the last query's stages received, in order and
+    /// ending with the query's `result`; empty unless
+    /// [`with_stage_stats`](Self::with_stage_stats) is enabled.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use clove_lang::{clove_value, Evaluator, Lexer, Parser};
+    ///
+    /// let query = Parser::new(Lexer::new("$ | ?($[n] > 1) | !($[n])")).unwrap().parse_query().unwrap();
+    /// let mut evaluator = Evaluator::new().with_stage_stats(true);
+    /// evaluator.eval_query(&query, clove_value!({"n": 2})).unwrap();
+    ///
+    /// let stages: Vec<_> = evaluator.stage_stats().iter().map(|s| s.stage.as_str()).collect();
+    /// assert_eq!(stages, ["filter", "output", "result"]);
+    /// ```
+    pub fn stage_stats(&self) -> &[StageStats] {
+        self.stage_stats.as_deref().unwrap_or_default()
+    }
+
+    /// Records what evaluating a query does, for
+    /// [`eval_stats`](Self::eval_stats): expressions evaluated, method calls,
+    /// nesting depth, an estimate of the memory allocated, and the size of
+    /// the document each statement received and the time it took.
+    ///
+    /// Off by default, as the estimate measures every method's result.
+    pub fn with_eval_stats(mut self, enabled: bool) -> Self {
+        self.eval_stats = enabled.then(StatsRecorder::default);
+        self
+    }
+
+    /// What the last query or expression did; `None` unless
+    /// [`with_eval_stats`](Self::with_eval_stats) is enabled.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use clove_lang::{clove_value, Evaluator, Lexer, Parser};
+    ///
+    /// let source = "$ | ?($[n] > 1) | !($[xs].map(@ * 2))";
+    /// let query = Parser::new(Lexer::new(source)).unwrap().parse_query().unwrap();
+    /// let mut evaluator = Evaluator::new().with_eval_stats(true);
+    /// evaluator.eval_query(&query, clove_value!({"n": 2, "xs": [1, 2, 3]})).unwrap();
+    ///
+    /// let stats = evaluator.eval_stats().unwrap();
+    /// assert_eq!(stats.method_calls["map"], 1);
+    /// let stages: Vec<_> = stats.statements.iter().map(|s| s.stage.as_str()).collect();
+    /// assert_eq!(stages, ["filter", "output"]);
+    /// ```
+    pub fn eval_stats(&self) -> Option<EvalStats> {
+        self.eval_stats.as_ref().map(|recorder| recorder.stats.borrow().clone())
+    }
+
+    /// Measure `document` on its way into `stage`, when recording stats or
+    /// enforcing a size limit (0 otherwise)
+    fn check_document(&mut self, stage: impl FnOnce() -> String, document: &Value) -> Result<usize, EvalError> {
+        if self.max_document_bytes.is_none() && self.stage_stats.is_none() && self.eval_stats.is_none() {
+            return Ok(0);
         }
+        let bytes = document.approx_size();
+        let stage = stage();
+        if let Some(limit) = self.max_document_bytes
+            && bytes > limit
+        {
+            return Err(EvalError::LimitExceeded(format!(
+                "the document for the {} stage is about {} bytes, over the limit of {}",
+                stage, bytes, limit
+            )));
+        }
+        if let Some(stats) = &mut self.stage_stats {
+            stats.push(StageStats { stage, bytes });
+        }
+        Ok(bytes)
     }
 
-    fn eval_expr(&self, expr: &Expr, context: &EvalContext) -> Result<Value, EvalError> {
-        match expr {
-            Expr::Float(n) => Ok(Value::Float(*n)),
-            Expr::Integer(n) => Ok(Value::Integer(*n)),
-            Expr::String(s) => Ok(Value::String(s.clone())),
-            Expr::Boolean(b) => Ok(Value::Boolean(*b)),
-            Expr::Null => Ok(Value::Null),
-            Expr::Root => Ok(context.root.clone()),
-            Expr::EnvVar(name) => match env::var(name) {
-                Ok(val) => Ok(Value::String(val)),
-                Err(_) => Err(EvalError::UndefinedEnvVar(name.to_string())),
-            },
-            Expr::ScopeRef(name) => self
-                .scopes
-                .get(name)
-                .cloned()
-                .ok_or_else(|| EvalError::UndefinedScope(name.clone())),
-            Expr::LambdaParam => {
-                // In lambda context, `@` refers to current item.
-                // This is passed as context already.
-                match &context.lambda {
-                    Some(lambda) => Ok(lambda.clone()),
-                    None => Ok(context.root.clone()),
-                }
-            }
-            Expr::Access { object, key } => {
-                let obj_value = self.eval_expr(object, context)?;
-                let key_value = self.eval_expr(key, context)?;
-                self.apply_access(&obj_value, &key_value)
-            }
-            Expr::BinaryOp { op, left, right } => {
-                if *op == BinOp::NullCoalesce {
-                    let left_val = self.eval_expr(left, context)?;
-                    if left_val == Value::Null {
-                        self.eval_expr(right, context)
-                    } else {
-                        Ok(left_val)
-                    }
-                } else {
-                    let left_val = self.eval_expr(left, context)?;
-                    let right_val = self.eval_expr(right, context)?;
-                    self.apply_binop(*op, &left_val, &right_val)
-                }
+    /// Measure the query's `result`, like a stage's document
+    fn check_result(&mut self, result: &Value) -> Result<(), EvalError> {
+        let bytes = self.check_document(|| "result".to_string(), result)?;
+        if let Some(recorder) = &self.eval_stats {
+            recorder.result(bytes);
+        }
+        Ok(())
+    }
+
+    /// Start timing `stmt`, which received a document of `bytes`
+    fn begin_statement(&self, stmt: &Statement, bytes: usize) -> Option<usize> {
+        self.eval_stats.as_ref().map(|recorder| recorder.begin_statement(stage_name(stmt), bytes))
+    }
+
+    fn end_statement(&self, entry: Option<usize>, start: Instant, result: Option<&Value>) {
+        if let Some(recorder) = &self.eval_stats
+            && let Some(entry) = entry
+        {
+            recorder.end_statement(entry, start, result);
+        }
+    }
+
+    /// `result`, unless it is a NaN or infinite float that
+    /// [`NonFinitePolicy::Error`] rejects; `what` describes the operation
+    fn finite(&self, result: Value, what: impl FnOnce() -> String) -> Result<Value, EvalError> {
+        match result {
+            Value::Float(n) if !n.is_finite() && self.non_finite_policy == NonFinitePolicy::Error => {
+                Err(EvalError::NonFinite(format!("{} gives {}", what(), n)))
             }
-            Expr::Object(items) => {
-                let mut map = HashMap::new();
-                for (key, expr) in items {
-                    let value = self.eval_expr(expr, context)?;
-                    map.insert(key.clone(), value);
+            result => Ok(result),
+        }
+    }
+
+    /// The result of an integer operation that overflowed: `float` under
+    /// [`OverflowPolicy::Float`], an error otherwise
+    fn overflowed(&self, what: String, float: f64) -> Result<Value, EvalError> {
+        match self.overflow_policy {
+            OverflowPolicy::Float => Ok(Value::Float(float)),
+            OverflowPolicy::Error => Err(EvalError::Overflow(what)),
+        }
+    }
+
+    /// Evaluate a live scope against the current document. Lambda items are
+    /// not visible to it, just as at the point of definition.
+    fn eval_live_scope(&self, name: &str, expr: &Expr, context: &EvalContext) -> Result<Value, EvalError> {
+        if self.resolving.borrow().iter().any(|n| n == name) {
+            return Err(EvalError::TypeError(format!("Live scope @{} refers to itself", name)));
+        }
+        self.resolving.borrow_mut().push(name.to_string());
+        let ctx = context.next_stage(context.root.clone());
+        let result = self.eval_expr(expr, &ctx);
+        self.resolving.borrow_mut().pop();
+        result
+    }
+
+    /// Replace the callable UDFs with `udfs`
+    fn define_udfs(&mut self, udfs: &[UDF]) {
+        // A parameter passed on to another UDF's by-name parameter is by
+        // name too, so repeat until nothing changes
+        let mut by_name: HashMap<&str, Vec<bool>> = udfs
+            .iter()
+            .map(|udf| (udf.name.as_str(), vec![false; udf.arity]))
+            .collect();
+        loop {
+            let mut changed = false;
+            for udf in udfs {
+                let params = lambda_params(&udf.body, udf.arity, &by_name);
+                if by_name.get(udf.name.as_str()) != Some(&params) {
+                    by_name.insert(&udf.name, params);
+                    changed = true;
                 }
-                Ok(Value::Object(map))
             }
-            Expr::Array(exprs) => {
-                let mut arr = Vec::new();
-                for expr in exprs {
-                    arr.push(self.eval_expr(expr, context)?);
-                }
-                Ok(Value::Array(arr))
+            if !changed {
+                break;
             }
-            Expr::Filter(expr) => self.eval_expr(expr, context),
-            Expr::MethodCall {
-                object,
-                method,
-                args,
-            } => {
-                let obj_value = self.eval_expr(object, context)?;
-                self.eval_method_call(&obj_value, method, args, context)
+        }
+
+        self.udfs = udfs
+            .iter()
+            .map(|udf| {
+                let compiled = CompiledUdf {
+                    arity: udf.arity,
+                    defaults: udf.defaults.clone(),
+                    variadic: udf.variadic,
+                    body: udf.body.clone(),
+                    by_name: by_name[udf.name.as_str()].clone(),
+                };
+                (udf.name.clone(), Arc::new(compiled))
+            })
+            .collect();
+    }
+
+    /// The error for a call to `&name[...]`, which is not defined
+    fn undefined_function(&self, name: &str) -> EvalError {
+        let mut defined: Vec<&str> = self.udfs.keys().map(String::as_str).collect();
+        defined.extend(["random", "coalesce"]);
+        #[cfg(feature = "stdlib")]
+        defined.extend(crate::stdlib::BUILTINS.iter().map(|builtin| builtin.name));
+        EvalError::UndefinedFunction { name: name.to_string(), candidates: suggest::near_misses(name, defined) }
+    }
+
+    /// The error for a reference to `@name`, which is not defined
+    fn undefined_scope(&self, name: &str, context: &EvalContext) -> EvalError {
+        let defined = (context.bindings.iter().map(|(bound, _)| bound))
+            .chain(self.scopes.keys())
+            .chain(self.live_scopes.keys())
+            .chain(self.deferred.keys())
+            .map(String::as_str);
+        EvalError::UndefinedScope { name: name.to_string(), candidates: suggest::near_misses(name, defined) }
+    }
+
+    /// Evaluate `&name[args]`. Arguments are bound by value, except those
+    /// the body passes on as a lambda, which are bound by name. Parameters
+    /// left out take their defaults, and a variadic UDF's extra arguments
+    /// are bound as an array after the last parameter (`@rest`).
+    fn call_udf(&self, name: &str, args: &[Expr], context: &EvalContext) -> Result<Value, EvalError> {
+        let Some(udf) = self.udfs.get(name) else {
+            if name == "random" {
+                return self.call_random(args);
             }
-            Expr::UDFCall { name: _, args: _ } => {
-                // Next up
-                todo!("UDF execution - needs UDF registry")
+            if name == "coalesce" {
+                return self.call_coalesce(args, context);
             }
-            Expr::ArgRef(n) => Err(EvalError::TypeError(format!(
-                "Argument reference @{} can only be used within UDF definitions",
-                n
-            ))),
-            Expr::ExistenceCheck(expr) => {
-                let value = self.eval_expr(expr, context)?;
-                let exists = match value {
-                    Value::Null => false,
-                    Value::Array(ref arr) => !arr.is_empty(),
-                    Value::Object(ref obj) => !obj.is_empty(),
-                    Value::String(ref s) => !s.is_empty(),
-                    _ => true,
-                };
-                Ok(Value::Boolean(exists))
+            #[cfg(feature = "stdlib")]
+            if let Some(builtin) = crate::stdlib::lookup(name) {
+                return self.call_builtin(builtin, args, context);
             }
-            Expr::Key(name) => Ok(Value::String(name.clone())),
+            return Err(self.undefined_function(name));
+        };
+        let bound = self.bind_udf_args(name, udf, args, context)?;
+
+        let limit = self.max_call_depth.unwrap_or(MAX_CALL_DEPTH);
+        let depth = self.call_depth.get();
+        if depth >= limit {
+            return Err(EvalError::LimitExceeded(format!(
+                "UDF calls are nested too deeply (more than {} levels) calling &{}",
+                limit, name
+            )));
         }
+        self.call_depth.set(depth + 1);
+        let result = self.eval_udf_calls(name, udf, bound, context);
+        self.call_depth.set(depth);
+        result
     }
 
-    fn apply_access(&self, object: &Value, key: &Value) -> Result<Value, EvalError> {
-        match (object, key) {
-            (Value::Object(map), Value::Float(k)) => {
-                Ok(map.get(&k.to_string()).cloned().unwrap_or(Value::Null))
+    /// Evaluate a call of `udf` with `bound` arguments. A call of the same
+    /// UDF in tail position replaces this one rather than nesting inside
+    /// it, so it takes no stack.
+    fn eval_udf_calls(
+        &self,
+        name: &str,
+        udf: &CompiledUdf,
+        mut bound: Vec<UdfArg>,
+        context: &EvalContext,
+    ) -> Result<Value, EvalError> {
+        for _ in 0..=MAX_TAIL_CALLS {
+            match self.eval_udf_body(name, udf, &context.with_args(Arc::new(bound)))? {
+                TailCall::Done(value) => return Ok(value),
+                TailCall::Recur(args) => bound = args,
             }
-            (Value::Object(map), Value::Boolean(k)) => {
-                Ok(map.get(&k.to_string()).cloned().unwrap_or(Value::Null))
+        }
+        Err(EvalError::LimitExceeded(format!(
+            "UDF calls are nested too deeply (more than {} calls in tail position) calling &{}",
+            MAX_TAIL_CALLS, name
+        )))
+    }
+
+    /// Bind the arguments of a call of `udf` to its parameters
+    fn bind_udf_args(
+        &self,
+        name: &str,
+        udf: &CompiledUdf,
+        args: &[Expr],
+        context: &EvalContext,
+    ) -> Result<Vec<UdfArg>, EvalError> {
+        let required = udf.arity - udf.defaults.len();
+        if args.len() < required || (!udf.variadic && args.len() > udf.arity) {
+            let expected = if udf.variadic {
+                format!("at least {}", required)
+            } else if required < udf.arity {
+                format!("{} to {}", required, udf.arity)
+            } else {
+                udf.arity.to_string()
+            };
+            return Err(EvalError::TypeError(format!(
+                "&{} takes {} argument(s), got {}",
+                name,
+                expected,
+                args.len()
+            )));
+        }
+
+        let mut bound = Vec::with_capacity(udf.arity + usize::from(udf.variadic));
+        for (i, &by_name) in udf.by_name.iter().enumerate() {
+            let arg = match args.get(i) {
+                Some(arg) if by_name => {
+                    UdfArg::Lambda { expr: arg.clone(), args: Arc::clone(&context.args) }
+                }
+                Some(arg) => UdfArg::Value(self.eval_expr(arg, context)?),
+                // A default sees the parameters before it
+                None => {
+                    let default = &udf.defaults[i - required];
+                    let args = Arc::new(bound.clone());
+                    if by_name {
+                        UdfArg::Lambda { expr: default.clone(), args }
+                    } else {
+                        UdfArg::Value(self.eval_expr(default, &context.with_args(args))?)
+                    }
+                }
+            };
+            bound.push(arg);
+        }
+        if udf.variadic {
+            let rest = args[udf.arity.min(args.len())..]
+                .iter()
+                .map(|arg| self.eval_expr(arg, context))
+                .collect::<Result<Vec<_>, _>>()?;
+            bound.push(UdfArg::Value(Value::Array(rest)));
+        }
+        Ok(bound)
+    }
+
+    #[cfg(feature = "stdlib")]
+    fn call_builtin(
+        &self,
+        builtin: &crate::stdlib::Builtin,
+        args: &[Expr],
+        context: &EvalContext,
+    ) -> Result<Value, EvalError> {
+        if args.len() != builtin.arity {
+            return Err(EvalError::TypeError(format!(
+                "&{} takes {} argument(s), got {}",
+                builtin.name,
+                builtin.arity,
+                args.len()
+            )));
+        }
+        let values = args
+            .iter()
+            .map(|arg| self.eval_expr(arg, context))
+            .collect::<Result<Vec<_>, _>>()?;
+        (builtin.call)(&values)
+    }
+
+    /// `&random[]`: a float in `[0, 1)`
+    fn call_random(&self, args: &[Expr]) -> Result<Value, EvalError> {
+        if !args.is_empty() {
+            return Err(EvalError::TypeError(format!(
+                "&random takes 0 argument(s), got {}",
+                args.len()
+            )));
+        }
+        Ok(Value::Float(self.rng.next_f64()))
+    }
+
+    /// `&coalesce[a, b, ...]`: the first argument that is not null, evaluating
+    /// no further than that one
+    fn call_coalesce(&self, args: &[Expr], context: &EvalContext) -> Result<Value, EvalError> {
+        if args.is_empty() {
+            return Err(EvalError::TypeError(
+                "&coalesce takes at least 1 argument(s), got 0".to_string(),
+            ));
+        }
+        for arg in args {
+            let value = self.eval_expr(arg, context)?;
+            if value != Value::Null {
+                return Ok(value);
             }
-            (Value::Object(map), Value::Integer(k)) => {
-                Ok(map.get(&k.to_string()).cloned().unwrap_or(Value::Null))
+        }
+        Ok(Value::Null)
+    }
+
+    /// A UDF body's value: a filter yields whether it passes, and a
+    /// transform of an argument (`~(@1 := ...)`) yields the new value
+    fn eval_udf_body(&self, name: &str, udf: &CompiledUdf, ctx: &EvalContext) -> Result<TailCall, EvalError> {
+        match &udf.body {
+            Statement::Access(expr) | Statement::Transform { target: Expr::ArgRef(_), value: expr } => {
+                self.eval_tail(name, udf, expr, ctx)
             }
-            (Value::Object(map), Value::String(k)) => {
-                Ok(map.get(k).cloned().unwrap_or(Value::Null))
+            Statement::ExistenceCheck(expr) => {
+                Ok(TailCall::Done(Value::Boolean(exists(&self.eval_expr(expr, ctx)?))))
             }
-            (Value::Array(arr), Value::Integer(n)) => {
-                let index = if *n < 0 {
-                    // Negative index: count from end (-1 = last, -2 = second to last)
-                    let abs_idx = (-*n) as usize;
-                    if abs_idx > arr.len() {
-                        return Ok(Value::Null);
-                    }
-                    arr.len() - abs_idx
-                } else {
-                    *n as usize
-                };
-                Ok(arr.get(index).cloned().unwrap_or(Value::Null))
+            Statement::Filter(condition) => {
+                Ok(TailCall::Done(Value::Boolean(self.eval_expr(condition, ctx)?.as_bool())))
             }
-            (Value::Array(_), Value::String(k)) => Err(EvalError::TypeError(format!(
-                "Cannot use string key '{}' on array; use integer index instead",
-                k
-            ))),
-            (v, Value::Integer(_)) => Err(EvalError::TypeError(format!(
-                "Cannot use integer index on {}; only arrays support integer indexing",
-                type_name(v)
-            ))),
             _ => Err(EvalError::TypeError(format!(
-                "Cannot access {} with {} key",
-                type_name(object),
-                type_name(key)
+                "&{} cannot be called: its body must be an expression, a filter, or a transform of an argument",
+                name
             ))),
         }
     }
 
-    fn apply_binop(&self, op: BinOp, left: &Value, right: &Value) -> Result<Value, EvalError> {
-        match op {
-            BinOp::Add => match (left, right) {
-                (Value::Float(a), Value::Float(b)) => Ok(Value::Float(a + b)),
-                (Value::Integer(a), Value::Integer(b)) => Ok(Value::Integer(a + b)),
-                (Value::Integer(a), Value::Float(b)) => {
-                    if let Some(ad) = Decimal::from_i64(*a)
-                        && let Some(bd) = Decimal::from_f64(*b)
-                    {
-                        let rd = ad + bd;
-                        if rd.is_integer() &&
-                            let Some(r) = rd.to_i64() {
-                            return Ok(Value::Integer(r));
-                        } else if let Some(r) = rd.to_f64() {
-                            return Ok(Value::Float(r));
-                        }
-                    }
-                    let res = *a as f64 + b;
-                    Ok(Value::Float(res))
-                }
-                (Value::Float(a), Value::Integer(b)) => {
-                    if let Some(ad) = Decimal::from_f64(*a)
-                        && let Some(bd) = Decimal::from_i64(*b)
-                    {
-                        let rd = ad + bd;
-                        if rd.is_integer() &&
-                            let Some(r) = rd.to_i64() {
-                            return Ok(Value::Integer(r));
-                        } else if let Some(r) = rd.to_f64() {
-                            return Ok(Value::Float(r));
-                        }
-                    }
-                    let res = *a + *b as f64;
-                    Ok(Value::Float(res))
-                }
-                (Value::String(a), Value::String(b)) => Ok(Value::String(format!("{}{}", a, b))),
-                (a, b) => Err(EvalError::TypeError(format!(
-                    "Cannot add {} and {}",
-                    type_name(a), type_name(b)
-                ))),
+    /// Evaluate `expr`, in tail position in the body of `udf`, stopping at a
+    /// call of `udf` itself. Tail positions are the body, the arms of a
+    /// `match` in one and the right side of a `??` in one.
+    fn eval_tail(
+        &self,
+        name: &str,
+        udf: &CompiledUdf,
+        expr: &Expr,
+        ctx: &EvalContext,
+    ) -> Result<TailCall, EvalError> {
+        let Some(recorder) = &self.eval_stats else {
+            return self.eval_tail_node(name, udf, expr, ctx);
+        };
+        recorder.enter();
+        let result = self.eval_tail_node(name, udf, expr, ctx);
+        recorder.leave();
+        result
+    }
+
+    fn eval_tail_node(
+        &self,
+        name: &str,
+        udf: &CompiledUdf,
+        expr: &Expr,
+        ctx: &EvalContext,
+    ) -> Result<TailCall, EvalError> {
+        match expr {
+            Expr::UDFCall { name: callee, args } if callee == name => {
+                self.bind_udf_args(name, udf, args, ctx).map(TailCall::Recur)
+            }
+            Expr::Match { subject, arms } => match self.match_arm(subject, arms, ctx)? {
+                Some((body, ctx)) => self.eval_tail(name, udf, body, &ctx),
+                None => Ok(TailCall::Done(Value::Null)),
             },
-            BinOp::Subtract => match (left, right) {
-                (Value::Float(a), Value::Float(b)) => Ok(Value::Float(a - b)),
-                (Value::Integer(a), Value::Integer(b)) => Ok(Value::Integer(a - b)),
-                (Value::Integer(a), Value::Float(b)) => {
-                    if let Some(ad) = Decimal::from_i64(*a)
-                        && let Some(bd) = Decimal::from_f64(*b)
-                    {
-                        let rd = ad - bd;
-                        if rd.is_integer() &&
-                            let Some(r) = rd.to_i64() {
-                            return Ok(Value::Integer(r));
-                        } else if let Some(r) = rd.to_f64() {
-                            return Ok(Value::Float(r));
-                        }
-                    }
-                    let res = *a as f64 - b;
-                    Ok(Value::Float(res))
+            Expr::BinaryOp { op: BinOp::NullCoalesce, left, right } => match self.eval_expr(left, ctx)? {
+                Value::Null => self.eval_tail(name, udf, right, ctx),
+                value => Ok(TailCall::Done(value)),
+            },
+            _ => self.eval_node(expr, ctx).map(TailCall::Done),
+        }
+    }
+
+    /// Look up an environment variable under the evaluator's policy
+    fn lookup_env(&self, name: &str) -> Result<String, EvalError> {
+        let value = match &self.env_policy {
+            EnvPolicy::Process => env::var(name).ok(),
+            EnvPolicy::DenyAll => return Err(EvalError::EnvAccessDenied(name.to_string())),
+            EnvPolicy::AllowList(allowed) => {
+                if !allowed.contains(name) {
+                    return Err(EvalError::EnvAccessDenied(name.to_string()));
                 }
-                (Value::Float(a), Value::Integer(b)) => {
-                    if let Some(ad) = Decimal::from_f64(*a)
-                        && let Some(bd) = Decimal::from_i64(*b)
-                    {
-                        let rd = ad - bd;
-                        if rd.is_integer() &&
-                            let Some(r) = rd.to_i64() {
-                            return Ok(Value::Integer(r));
-                        } else if let Some(r) = rd.to_f64() {
-                            return Ok(Value::Float(r));
-                        }
+                env::var(name).ok()
+            }
+            EnvPolicy::Map(vars) => vars.get(name).cloned(),
+        };
+        value.ok_or_else(|| EvalError::UndefinedEnvVar(name.to_string()))
+    }
+
+    /// Evaluates a complete query against a JSON document.
+    ///
+    /// Executes the query pipeline statement by statement, threading the result
+    /// through each transformation, and returns the final output.
+    ///
+    /// # Arguments
+    ///
+    /// * `query` - The parsed query to execute
+    /// * `document` - The JSON document to query (becomes the root `$`)
+    ///
+    /// # Returns
+    ///
+    /// The result of the query's output expression, or the final transformed
+    /// document if no explicit output is specified.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use clove_lang::Evaluator;
+    /// use clove_lang::parser::Parser;
+    /// use clove_lang::lexer::Lexer;
+    /// use clove_lang::Value;
+    /// use std::collections::HashMap;
+    ///
+    /// let mut doc = HashMap::new();
+    /// doc.insert("price".to_string(), Value::Integer(100));
+    ///
+    /// let query_str = "$ | ?($[price] > 50)";
+    /// let lexer = Lexer::new(query_str);
+    /// let mut parser = Parser::new(lexer).unwrap();
+    /// let query = parser.parse_query().unwrap();
+    ///
+    /// let mut evaluator = Evaluator::new();
+    /// let result = evaluator.eval_query(&query, Value::Object(doc)).unwrap();
+    /// // Returns the document because price > 50
+    /// ```
+    pub fn eval_query(&mut self, query: &Query, document: Value) -> Result<Value, EvalError> {
+        self.eval_query_with_meta(query, document, HashMap::new())
+    }
+
+    /// Like [`eval_query`](Self::eval_query), with metadata about the
+    /// document that the query can read as `$meta`.
+    ///
+    /// Drivers that evaluate many records set keys such as `line` (the
+    /// 1-based record number) and `file` (the source path). Keys that were
+    /// not supplied read as `null`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use clove_lang::{Evaluator, Lexer, Parser, Value};
+    /// use std::collections::HashMap;
+    ///
+    /// let query = Parser::new(Lexer::new("$ | !($meta[line])")).unwrap().parse_query().unwrap();
+    /// let meta = HashMap::from([("line".to_string(), Value::Integer(7))]);
+    ///
+    /// let result = Evaluator::new().eval_query_with_meta(&query, Value::Null, meta).unwrap();
+    /// assert_eq!(result, Value::Integer(7));
+    /// ```
+    pub fn eval_query_with_meta(
+        &mut self,
+        query: &Query,
+        document: Value,
+        meta: HashMap<String, Value>,
+    ) -> Result<Value, EvalError> {
+        self.eval_query_outcome(query, document, meta)
+            .map(QueryOutcome::into_value)
+    }
+
+    /// Runs a query, telling a document rejected by a filter apart from one
+    /// whose output is `null`.
+    ///
+    /// A rejecting filter ends the pipeline: the statements after it and the
+    /// output expression are not evaluated.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use clove_lang::{Evaluator, Lexer, Parser, QueryOutcome, Value};
+    /// use std::collections::HashMap;
+    ///
+    /// let query = Parser::new(Lexer::new("$ | ?($ > 1) | !(null)")).unwrap().parse_query().unwrap();
+    /// let mut evaluator = Evaluator::new();
+    ///
+    /// let kept = evaluator.eval_query_outcome(&query, Value::Integer(2), HashMap::new()).unwrap();
+    /// assert_eq!(kept, QueryOutcome::Value(Value::Null));
+    /// let rejected = evaluator.eval_query_outcome(&query, Value::Integer(0), HashMap::new()).unwrap();
+    /// assert_eq!(rejected, QueryOutcome::FilteredOut);
+    /// ```
+    pub fn eval_query_outcome(
+        &mut self,
+        query: &Query,
+        document: Value,
+        meta: HashMap<String, Value>,
+    ) -> Result<QueryOutcome, EvalError> {
+        #[cfg(feature = "tracing")]
+        let phase = crate::telemetry::Phase::eval(Some(query));
+        self.define_udfs(&query.udfs);
+        self.memo.prepare(memo::query_exprs(query));
+        if let Some(stats) = &mut self.stage_stats {
+            stats.clear();
+        }
+        if let Some(recorder) = &self.eval_stats {
+            recorder.reset();
+        }
+        // Only keep a copy of the document if a stage may replace it
+        let original = reads_original(query).then(|| Arc::new(document.clone()));
+        let stage = EvalContext::new(Value::Null).with_meta(Arc::new(meta)).with_original(original);
+        let mut current = document;
+
+        for stmt in &query.statements {
+            let bytes = self.check_document(|| stage_name(stmt), &current)?;
+            self.memo.reset_root();
+            let ctx = stage.next_stage(current);
+            let entry = self.begin_statement(stmt, bytes);
+            let start = Instant::now();
+            let result = self.eval_statement(stmt, ctx)?;
+            self.end_statement(entry, start, result.as_ref());
+            #[cfg(feature = "tracing")]
+            phase.statement(stmt, start.elapsed(), result.is_some());
+            current = match result {
+                Some(value) => value,
+                None => return Ok(QueryOutcome::FilteredOut),
+            };
+        }
+
+        let result = match &query.output {
+            Some(expr) => {
+                let bytes = self.check_document(|| "output".to_string(), &current)?;
+                self.memo.reset_root();
+                let ctx = stage.next_stage(current);
+
+                self.eval_output(expr, &ctx, bytes)?
+            }
+            None => current,
+        };
+        self.check_result(&result)?;
+        Ok(QueryOutcome::Value(result))
+    }
+
+    /// Evaluates a single expression against a JSON document.
+    ///
+    /// This is a convenience method for evaluating standalone expressions
+    /// without a full query pipeline.
+    ///
+    /// # Arguments
+    ///
+    /// * `expr` - The expression to evaluate
+    /// * `document` - The JSON document (becomes the root `$`)
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use clove_lang::{Evaluator, Expr, Value};
+    ///
+    /// let mut evaluator = Evaluator::new();
+    /// let expr = Expr::Root;
+    /// let doc = Value::Integer(42);
+    ///
+    /// let result = evaluator.eval_expression(&expr, doc).unwrap();
+    /// assert_eq!(result, Value::Integer(42));
+    /// ```
+    pub fn eval_expression(&mut self, expr: &Expr, document: Value) -> Result<Value, EvalError> {
+        self.eval_expression_with_meta(expr, document, HashMap::new())
+    }
+
+    /// Like [`eval_expression`](Self::eval_expression), with metadata the
+    /// expression can read as `$meta` (see [`eval_query_with_meta`](Self::eval_query_with_meta)).
+    pub fn eval_expression_with_meta(
+        &mut self,
+        expr: &Expr,
+        document: Value,
+        meta: HashMap<String, Value>,
+    ) -> Result<Value, EvalError> {
+        #[cfg(feature = "tracing")]
+        let _phase = crate::telemetry::Phase::eval(None);
+        self.memo.prepare([expr]);
+        if let Some(stats) = &mut self.stage_stats {
+            stats.clear();
+        }
+        if let Some(recorder) = &self.eval_stats {
+            recorder.reset();
+        }
+        let bytes = self.check_document(|| "output".to_string(), &document)?;
+        let context = EvalContext::new(document).with_meta(Arc::new(meta));
+        let result = self.eval_output(expr, &context, bytes)?;
+        self.check_result(&result)?;
+        Ok(result)
+    }
+
+    /// Evaluate a query's output expression on a document of `bytes`,
+    /// timing it when recording stats
+    fn eval_output(&self, expr: &Expr, context: &EvalContext, bytes: usize) -> Result<Value, EvalError> {
+        let entry = self
+            .eval_stats
+            .as_ref()
+            .map(|recorder| recorder.begin_statement("output".to_string(), bytes));
+        let start = Instant::now();
+        let result = self.eval_expr(expr, context)?;
+        self.end_statement(entry, start, None);
+        #[cfg(feature = "tracing")]
+        crate::telemetry::output(start.elapsed());
+        Ok(result)
+    }
+
+    /// The document after `stmt`, or `None` if a filter rejected it
+    fn eval_statement(&mut self, stmt: &Statement, ctx: EvalContext) -> Result<Option<Value>, EvalError> {
+        let value = match stmt {
+            Statement::Filter(condition) => {
+                if !self.eval_expr(condition, &ctx)?.as_bool() {
+                    return Ok(None);
+                }
+                ctx.root
+            }
+            Statement::When { condition, statements } => {
+                if !self.eval_expr(condition, &ctx)?.as_bool() {
+                    return Ok(Some(ctx.root));
+                }
+                let stage = ctx.next_stage(Value::Null);
+                let mut current = ctx.root;
+                for stmt in statements {
+                    let bytes = self.check_document(|| stage_name(stmt), &current)?;
+                    self.memo.reset_root();
+                    let ctx = stage.next_stage(current);
+                    let entry = self.begin_statement(stmt, bytes);
+                    let start = Instant::now();
+                    let result = self.eval_statement(stmt, ctx)?;
+                    self.end_statement(entry, start, result.as_ref());
+                    current = match result {
+                        Some(value) => value,
+                        None => return Ok(None),
+                    };
+                }
+                current
+            }
+            Statement::ForEach { path, name, statements } => {
+                let path = self.target_path(path, &ctx)?;
+                let mut result = ctx.root.clone();
+                self.eval_foreach(&mut result, &path, name, statements, &ctx)?;
+                result
+            }
+            Statement::Transform { target, value } => self.apply_transform(&ctx, target, value)?,
+            Statement::ScopeDefinition { name, path } => {
+                let value = self.eval_expr(path, &ctx)?;
+                self.live_scopes.remove(name);
+                self.scopes.insert(name.clone(), value);
+                ctx.root
+            }
+            Statement::LiveScopeDefinition { name, path } => {
+                self.scopes.remove(name);
+                self.live_scopes.insert(name.clone(), path.clone());
+                ctx.root
+            }
+            Statement::HoistedScope { name, path } => {
+                match self.eval_expr(path, &ctx) {
+                    Ok(value) => {
+                        self.deferred.remove(name);
+                        self.scopes.insert(name.clone(), value);
+                    }
+                    Err(e) => {
+                        self.scopes.remove(name);
+                        self.deferred.insert(name.clone(), e);
                     }
-                    let res = *a - *b as f64;
-                    Ok(Value::Float(res))
                 }
-                (a, b) => Err(EvalError::TypeError(format!(
-                    "Cannot subtract {} from {}",
-                    type_name(b), type_name(a)
-                ))),
-            },
+                ctx.root
+            }
+            Statement::Delete(path_expr) => {
+                let path = self.target_path(path_expr, &ctx)?;
+                let mut result = ctx.root;
+                result.remove_matching(&path);
+                result
+            }
+            Statement::Access(expr) => self.eval_expr(expr, &ctx)?,
+            Statement::ExistenceCheck(expr) => Value::Boolean(exists(&self.eval_expr(expr, &ctx)?)),
+        };
+        Ok(Some(value))
+    }
 
-            BinOp::Multiply => match (left, right) {
-                (Value::Float(a), Value::Float(b)) => Ok(Value::Float(a * b)),
-                (Value::Integer(a), Value::Integer(b)) => Ok(Value::Integer(a * b)),
-                (Value::Integer(a), Value::Float(b)) => {
-                    if let Some(ad) = Decimal::from_i64(*a)
-                        && let Some(bd) = Decimal::from_f64(*b)
-                    {
-                        let rd = ad * bd;
-                        if rd.is_integer() &&
-                            let Some(r) = rd.to_i64() {
-                            return Ok(Value::Integer(r));
-                        } else if let Some(r) = rd.to_f64() {
-                            return Ok(Value::Float(r));
-                        }
+    /// Run a foreach body on each element of the array at `path` in
+    /// `target`, and put the elements it keeps back
+    fn eval_foreach(
+        &mut self,
+        target: &mut Value,
+        path: &[PathSegment],
+        name: &str,
+        statements: &[Statement],
+        ctx: &EvalContext,
+    ) -> Result<(), EvalError> {
+        let items = match target.get_path_mut(path) {
+            Some(Value::Array(items)) => std::mem::take(items),
+            Some(value) => {
+                return Err(EvalError::TypeError(format!(
+                    "foreach requires an array, but '{}' is {}",
+                    display_path(path),
+                    type_name(value)
+                )));
+            }
+            None => return Err(EvalError::AccessError(format!("Field '{}' not found", display_path(path)))),
+        };
+        let mut results = Vec::with_capacity(items.len());
+        for item in items {
+            if let Some(item) = self.eval_foreach_body(name, statements, item, ctx)? {
+                results.push(item);
+            }
+        }
+        target.set_path(path, Value::Array(results))?;
+        Ok(())
+    }
+
+    /// `item` after the statements of a foreach body, or `None` if a filter
+    /// dropped it
+    fn eval_foreach_body(
+        &mut self,
+        name: &str,
+        statements: &[Statement],
+        mut item: Value,
+        ctx: &EvalContext,
+    ) -> Result<Option<Value>, EvalError> {
+        for stmt in statements {
+            let item_ctx = ctx.with_binding(name, item.clone());
+            item = match stmt {
+                Statement::Filter(condition) => {
+                    if !self.eval_expr(condition, &item_ctx)?.as_bool() {
+                        return Ok(None);
                     }
-                    let res = *a as f64 * b;
-                    Ok(Value::Float(res))
+                    item
                 }
-                (Value::Float(a), Value::Integer(b)) => {
-                    if let Some(ad) = Decimal::from_f64(*a)
-                        && let Some(bd) = Decimal::from_i64(*b)
-                    {
-                        let rd = ad * bd;
-                        if rd.is_integer() &&
-                            let Some(r) = rd.to_i64() {
-                            return Ok(Value::Integer(r));
-                        } else if let Some(r) = rd.to_f64() {
-                            return Ok(Value::Float(r));
+                Statement::When { condition, statements } => {
+                    if !self.eval_expr(condition, &item_ctx)?.as_bool() {
+                        item
+                    } else {
+                        match self.eval_foreach_body(name, statements, item, ctx)? {
+                            Some(item) => item,
+                            None => return Ok(None),
                         }
                     }
-                    let res = *a * *b as f64;
-                    Ok(Value::Float(res))
                 }
-                (a, b) => Err(EvalError::TypeError(format!(
-                    "Cannot multiply {} by {}",
-                    type_name(a), type_name(b)
-                ))),
-            },
-            BinOp::Divide => match (left, right) {
-                (Value::Float(a), Value::Float(b)) => Ok(Value::Float(a / b)),
-                (Value::Integer(a), Value::Integer(b)) => {
-                    // Check if division is exact; if not, return Float
-                    if *a % *b == 0 {
-                        Ok(Value::Integer(a / b))
-                    } else {
-                        Ok(Value::Float(*a as f64 / *b as f64))
+                Statement::Transform { target, value } => {
+                    let path = item_path(target, name)?;
+                    if path.is_empty() {
+                        return Err(EvalError::TypeError(format!(
+                            "Cannot transform @{} itself. Transform a field such as @{}[price], or write the new value as a statement",
+                            name, name
+                        )));
                     }
+                    self.transform_path(item, &path, value, &item_ctx)?
                 }
-                (Value::Integer(a), Value::Float(b)) => {
-                    if let Some(ad) = Decimal::from_i64(*a)
-                        && let Some(bd) = Decimal::from_f64(*b)
-                    {
-                        let rd = ad / bd;
-                        if rd.is_integer() &&
-                            let Some(r) = rd.to_i64() {
-                            return Ok(Value::Integer(r));
-                        } else if let Some(r) = rd.to_f64() {
-                            return Ok(Value::Float(r));
-                        }
-                    }
-                    let res = *a as f64 / b;
-                    Ok(Value::Float(res))
+                Statement::Delete(target) => {
+                    item.remove_matching(&item_path(target, name)?);
+                    item
                 }
-                (Value::Float(a), Value::Integer(b)) => {
-                    if let Some(ad) = Decimal::from_f64(*a)
-                        && let Some(bd) = Decimal::from_i64(*b)
+                Statement::ForEach { path, name: inner, statements } => {
+                    let path = item_path(path, name)?;
+                    self.eval_foreach(&mut item, &path, inner, statements, &item_ctx)?;
+                    item
+                }
+                Statement::Access(expr) => self.eval_expr(expr, &item_ctx)?,
+                Statement::ExistenceCheck(expr) => Value::Boolean(exists(&self.eval_expr(expr, &item_ctx)?)),
+                Statement::ScopeDefinition { .. }
+                | Statement::LiveScopeDefinition { .. }
+                | Statement::HoistedScope { .. } => {
+                    self.eval_statement(stmt, item_ctx)?;
+                    item
+                }
+            };
+        }
+        Ok(Some(item))
+    }
+
+    fn eval_expr(&self, expr: &Expr, context: &EvalContext) -> Result<Value, EvalError> {
+        let Some(recorder) = &self.eval_stats else {
+            return self.eval_node(expr, context);
+        };
+        recorder.enter();
+        let result = self.eval_node(expr, context);
+        recorder.leave();
+        result
+    }
+
+    fn eval_node(&self, expr: &Expr, context: &EvalContext) -> Result<Value, EvalError> {
+        match expr {
+            Expr::Float(n) => Ok(Value::Float(*n)),
+            Expr::Integer(n) => Ok(Value::Integer(*n)),
+            Expr::String(s) => Ok(Value::String(s.clone())),
+            Expr::Boolean(b) => Ok(Value::Boolean(*b)),
+            Expr::Null => Ok(Value::Null),
+            Expr::Root => Ok(context.root.clone()),
+            Expr::OriginalRoot => Ok(context.original().clone()),
+            Expr::EnvVar(name) => Ok(Value::String(self.lookup_env(name)?)),
+            Expr::TypedEnvVar { name, ty } => {
+                let raw = self.lookup_env(name)?;
+                convert_env_value(name, &raw, *ty)
+            }
+            Expr::Meta => Ok(Value::Object(context.meta.as_ref().clone())),
+            Expr::ScopeRef(name) if name == "key" && context.key.is_some() => {
+                Ok(context.key.clone().map_or(Value::Null, Value::String))
+            }
+            Expr::ScopeRef(name) => match self.live_scopes.get(name) {
+                Some(live) => self.eval_live_scope(name, live, context),
+                None => match self.scopes.get(name) {
+                    Some(value) => Ok(value.clone()),
+                    None => Err(self
+                        .deferred
+                        .get(name)
+                        .cloned()
+                        .unwrap_or_else(|| self.undefined_scope(name, context))),
+                },
+            },
+            Expr::LambdaParam => {
+                // In lambda context, `@` refers to current item.
+                // This is passed as context already.
+                match &context.lambda {
+                    Some(lambda) => Ok(lambda.clone()),
+                    None => Ok(context.root.clone()),
+                }
+            }
+            Expr::Access { object, key } => {
+                let obj_value = self.eval_expr(object, context)?;
+                let key_value = self.eval_expr(key, context)?;
+                self.apply_access(&obj_value, &key_value)
+            }
+            Expr::BinaryOp { op, left, right } => {
+                if *op == BinOp::NullCoalesce {
+                    let left_val = self.eval_expr(left, context)?;
+                    if left_val == Value::Null {
+                        self.eval_expr(right, context)
+                    } else {
+                        Ok(left_val)
+                    }
+                } else {
+                    let left_val = self.eval_expr(left, context)?;
+                    let right_val = self.eval_expr(right, context)?;
+                    let result = self.apply_binop(*op, &left_val, &right_val)?;
+                    self.finite(result, || {
+                        format!("{:?} of {} and {}", op, to_json(&left_val), to_json(&right_val))
+                    })
+                }
+            }
+            Expr::Object(items) => {
+                let mut map = HashMap::new();
+                for (key, expr) in items {
+                    let value = self.eval_expr(expr, context)?;
+                    map.insert(key.clone(), value);
+                }
+                Ok(Value::Object(map))
+            }
+            Expr::Array(exprs) => {
+                let mut arr = Vec::new();
+                for expr in exprs {
+                    arr.push(self.eval_expr(expr, context)?);
+                }
+                Ok(Value::Array(arr))
+            }
+            Expr::Filter(expr) => self.eval_expr(expr, context),
+            Expr::MethodCall {
+                object,
+                method,
+                args,
+            } => {
+                let memo_id = self.memo.id(expr);
+                if let Some(id) = memo_id
+                    && let Some(cached) = self.memo.get(id, &context.root)
+                {
+                    return Ok(cached);
+                }
+                let value = match self.eval_lazy_chain(object, method, args, context)? {
+                    LazyChain::Done(result) => result,
+                    LazyChain::NotLazy => {
+                        let obj_value = self.eval_expr(object, context)?;
+                        self.eval_method_call(&obj_value, method, args, context)?
+                    }
+                    LazyChain::Source { value, stages } => {
+                        let obj_value = self.eval_stages_from(object, stages, value, context)?;
+                        self.eval_method_call(&obj_value, method, args, context)?
+                    }
+                };
+                if let Some(recorder) = &self.eval_stats {
+                    recorder.method_call(method, value.approx_size());
+                }
+                if let Some(id) = memo_id {
+                    self.memo.insert(id, &context.root, value.clone());
+                }
+                Ok(value)
+            }
+            Expr::UDFCall { name, args } => self.call_udf(name, args, context),
+            Expr::HostCall { name, args } => {
+                let function = self
+                    .functions
+                    .get(name)
+                    .ok_or_else(|| EvalError::UndefinedHostFunction(name.clone()))?;
+                let args = args.iter().map(|arg| self.eval_expr(arg, context)).collect::<Result<Vec<_>, _>>()?;
+                function(&args)
+            }
+            Expr::ArgRef(n) => match n.checked_sub(1).and_then(|i| context.args.get(i)) {
+                Some(UdfArg::Value(value)) => Ok(value.clone()),
+                Some(UdfArg::Lambda { expr, args }) => {
+                    self.eval_expr(expr, &context.with_args(Arc::clone(args)))
+                }
+                None if context.args.is_empty() => Err(EvalError::TypeError(format!(
+                    "Argument reference @{} can only be used within UDF definitions",
+                    n
+                ))),
+                None => Err(EvalError::TypeError(format!(
+                    "Argument reference @{} is out of range: the function takes {} argument(s)",
+                    n,
+                    context.args.len()
+                ))),
+            },
+            Expr::ExistenceCheck(expr) => Ok(Value::Boolean(exists(&self.eval_expr(expr, context)?))),
+            Expr::Key(name) => Ok(Value::String(name.clone())),
+            Expr::Wildcard => Err(EvalError::TypeError(
+                "[*] can only be used in a delete target, like -($[items][*][password])".to_string(),
+            )),
+            Expr::Lambda { param, body } => match &context.lambda {
+                Some(item) => self.eval_expr(body, &context.with_binding(param, item.clone())),
+                None => Err(EvalError::TypeError(format!(
+                    "Lambda '{} -> ...' can only be passed to a method that takes a lambda",
+                    param
+                ))),
+            },
+            Expr::OuterLambdaParam(level) => {
+                let mut enclosing = context.enclosing.as_deref();
+                for _ in 1..*level {
+                    enclosing = enclosing.and_then(|frame| frame.outer.as_deref());
+                }
+                enclosing.map(|frame| frame.item.clone()).ok_or_else(|| {
+                    EvalError::TypeError(format!(
+                        "{} needs {} lambda(s) around the current one",
+                        "@".repeat(level + 1),
+                        level
+                    ))
+                })
+            }
+            Expr::LambdaVar(name) => context
+                .bindings
+                .iter()
+                .rev()
+                .find(|(bound, _)| bound == name)
+                .map(|(_, value)| value.clone())
+                .ok_or_else(|| self.undefined_scope(name, context)),
+            Expr::Match { subject, arms } => match self.match_arm(subject, arms, context)? {
+                Some((body, context)) => self.eval_expr(body, &context),
+                None => Ok(Value::Null),
+            },
+            // Limits and the environment policy are not for a query to
+            // recover from
+            Expr::Try { body, fallback } => match self.eval_expr(body, context) {
+                result @ (Ok(_) | Err(EvalError::LimitExceeded(_) | EvalError::EnvAccessDenied(_))) => {
+                    result
+                }
+                Err(_) => self.eval_expr(fallback, context),
+            },
+        }
+    }
+
+    /// The body of the first arm of a `match` on `subject` that applies,
+    /// with the context that binds its pattern's names
+    fn match_arm<'a>(
+        &self,
+        subject: &Expr,
+        arms: &'a [MatchArm],
+        context: &EvalContext,
+    ) -> Result<Option<(&'a Expr, EvalContext)>, EvalError> {
+        let subject = self.eval_expr(subject, context)?;
+        for arm in arms {
+            let mut bound = Vec::new();
+            if !self.match_pattern(&arm.pattern, &subject, &mut bound, context)? {
+                continue;
+            }
+            let context = context.with_bindings(bound);
+            if let Some(guard) = &arm.guard
+                && !self.eval_expr(guard, &context)?.is_truthy()
+            {
+                continue;
+            }
+            return Ok(Some((&arm.body, context)));
+        }
+        Ok(None)
+    }
+
+    /// Whether `value` has the shape of `pattern`, adding the names it binds
+    /// to `bound`
+    fn match_pattern(
+        &self,
+        pattern: &Pattern,
+        value: &Value,
+        bound: &mut Vec<(String, Value)>,
+        context: &EvalContext,
+    ) -> Result<bool, EvalError> {
+        match (pattern, value) {
+            (Pattern::Wildcard, _) => Ok(true),
+            (Pattern::Bind(name), _) => {
+                bound.push((name.clone(), value.clone()));
+                Ok(true)
+            }
+            (Pattern::Literal(expr), _) => Ok(self.eval_expr(expr, context)?.equals(value)),
+            (Pattern::Object(entries), Value::Object(map)) => {
+                for (key, pattern) in entries {
+                    match map.get(key) {
+                        Some(value) if self.match_pattern(pattern, value, bound, context)? => {}
+                        _ => return Ok(false),
+                    }
+                }
+                Ok(true)
+            }
+            (Pattern::Array { items, rest }, Value::Array(values)) => {
+                if values.len() < items.len() || (!rest && values.len() > items.len()) {
+                    return Ok(false);
+                }
+                for (pattern, value) in items.iter().zip(values) {
+                    if !self.match_pattern(pattern, value, bound, context)? {
+                        return Ok(false);
+                    }
+                }
+                Ok(true)
+            }
+            (Pattern::Object(_) | Pattern::Array { .. }, _) => Ok(false),
+        }
+    }
+
+    fn apply_access(&self, object: &Value, key: &Value) -> Result<Value, EvalError> {
+        match (object, key) {
+            (Value::Object(map), Value::Float(k)) => {
+                Ok(map.get(&k.to_string()).cloned().unwrap_or(Value::Null))
+            }
+            (Value::Object(map), Value::Boolean(k)) => {
+                Ok(map.get(&k.to_string()).cloned().unwrap_or(Value::Null))
+            }
+            (Value::Object(map), Value::Integer(k)) => {
+                Ok(map.get(&k.to_string()).cloned().unwrap_or(Value::Null))
+            }
+            (Value::Object(map), Value::String(k)) => {
+                Ok(map.get(k).cloned().unwrap_or(Value::Null))
+            }
+            (Value::Array(arr), Value::Integer(n)) => {
+                let index = if *n < 0 {
+                    // Negative index: count from end (-1 = last, -2 = second to last)
+                    let abs_idx = n.unsigned_abs() as usize;
+                    if abs_idx > arr.len() {
+                        return Ok(Value::Null);
+                    }
+                    arr.len() - abs_idx
+                } else {
+                    *n as usize
+                };
+                Ok(arr.get(index).cloned().unwrap_or(Value::Null))
+            }
+            // A whole-number float indexes like the integer it equals
+            (Value::Array(_), Value::Float(k)) if whole_number(*k).is_some() => {
+                self.apply_access(object, &Value::Integer(*k as i64))
+            }
+            (Value::Array(_), Value::String(k)) => Err(EvalError::TypeError(format!(
+                "Cannot use string key '{}' on array; use integer index instead",
+                k
+            ))),
+            (v, Value::Integer(_)) => Err(EvalError::TypeError(format!(
+                "Cannot use integer index on {}; only arrays support integer indexing",
+                type_name(v)
+            ))),
+            _ => Err(EvalError::TypeError(format!(
+                "Cannot access {} with {} key",
+                type_name(object),
+                type_name(key)
+            ))),
+        }
+    }
+
+    fn apply_binop(&self, op: BinOp, left: &Value, right: &Value) -> Result<Value, EvalError> {
+        match op {
+            BinOp::Add => match (left, right) {
+                (Value::Float(a), Value::Float(b)) => Ok(Value::Float(a + b)),
+                (Value::Integer(a), Value::Integer(b)) => match a.checked_add(*b) {
+                    Some(n) => Ok(Value::Integer(n)),
+                    None => self.overflowed(format!("{} + {}", a, b), *a as f64 + *b as f64),
+                },
+                (Value::Integer(a), Value::Float(b)) => {
+                    if let Some(ad) = Decimal::from_i64(*a)
+                        && let Some(bd) = Decimal::from_f64(*b)
+                        && let Some(rd) = ad.checked_add(bd)
                     {
-                        let rd = ad / bd;
                         if rd.is_integer() &&
                             let Some(r) = rd.to_i64() {
                             return Ok(Value::Integer(r));
@@ -493,22 +2338,41 @@ impl Evaluator {
                             return Ok(Value::Float(r));
                         }
                     }
-                    let res = *a / *b as f64;
+                    let res = *a as f64 + b;
+                    Ok(Value::Float(res))
+                }
+                (Value::Float(a), Value::Integer(b)) => {
+                    if let Some(ad) = Decimal::from_f64(*a)
+                        && let Some(bd) = Decimal::from_i64(*b)
+                        && let Some(rd) = ad.checked_add(bd)
+                    {
+                        if rd.is_integer() &&
+                            let Some(r) = rd.to_i64() {
+                            return Ok(Value::Integer(r));
+                        } else if let Some(r) = rd.to_f64() {
+                            return Ok(Value::Float(r));
+                        }
+                    }
+                    let res = *a + *b as f64;
                     Ok(Value::Float(res))
                 }
+                (Value::String(a), Value::String(b)) => Ok(Value::String(format!("{}{}", a, b))),
                 (a, b) => Err(EvalError::TypeError(format!(
-                    "Cannot divide {} by {}",
+                    "Cannot add {} and {}",
                     type_name(a), type_name(b)
                 ))),
             },
-            BinOp::Modulo => match (left, right) {
-                (Value::Float(a), Value::Float(b)) => Ok(Value::Float(a % b)),
-                (Value::Integer(a), Value::Integer(b)) => Ok(Value::Integer(a % b)),
+            BinOp::Subtract => match (left, right) {
+                (Value::Float(a), Value::Float(b)) => Ok(Value::Float(a - b)),
+                (Value::Integer(a), Value::Integer(b)) => match a.checked_sub(*b) {
+                    Some(n) => Ok(Value::Integer(n)),
+                    None => self.overflowed(format!("{} - {}", a, b), *a as f64 - *b as f64),
+                },
                 (Value::Integer(a), Value::Float(b)) => {
                     if let Some(ad) = Decimal::from_i64(*a)
                         && let Some(bd) = Decimal::from_f64(*b)
+                        && let Some(rd) = ad.checked_sub(bd)
                     {
-                        let rd = ad % bd;
                         if rd.is_integer() &&
                             let Some(r) = rd.to_i64() {
                             return Ok(Value::Integer(r));
@@ -516,14 +2380,14 @@ impl Evaluator {
                             return Ok(Value::Float(r));
                         }
                     }
-                    let res = *a as f64 % b;
+                    let res = *a as f64 - b;
                     Ok(Value::Float(res))
                 }
                 (Value::Float(a), Value::Integer(b)) => {
                     if let Some(ad) = Decimal::from_f64(*a)
                         && let Some(bd) = Decimal::from_i64(*b)
+                        && let Some(rd) = ad.checked_sub(bd)
                     {
-                        let rd = ad % bd;
                         if rd.is_integer() &&
                             let Some(r) = rd.to_i64() {
                             return Ok(Value::Integer(r));
@@ -531,1044 +2395,2445 @@ impl Evaluator {
                             return Ok(Value::Float(r));
                         }
                     }
-                    let res = *a % *b as f64;
+                    let res = *a - *b as f64;
                     Ok(Value::Float(res))
                 }
                 (a, b) => Err(EvalError::TypeError(format!(
-                    "Cannot compute modulo of {} by {}",
-                    type_name(a), type_name(b)
-                ))),
-            },
-            BinOp::Equal => Ok(Value::Boolean(left == right)),
-            BinOp::NotEqual => Ok(Value::Boolean(left != right)),
-            BinOp::LessThan => match (left, right) {
-                (Value::Float(a), Value::Float(b)) => Ok(Value::Boolean(a < b)),
-                (Value::Integer(a), Value::Integer(b)) => Ok(Value::Boolean(a < b)),
-                (Value::Integer(a), Value::Float(b)) => Ok(Value::Boolean((*a as f64) < *b)),
-                (Value::Float(a), Value::Integer(b)) => Ok(Value::Boolean(*a < (*b as f64))),
-                (a, b) => Err(EvalError::TypeError(format!(
-                    "Cannot compare {} < {} (comparison requires numeric types)",
-                    type_name(a), type_name(b)
-                ))),
-            },
-            BinOp::GreaterThan => match (left, right) {
-                (Value::Float(a), Value::Float(b)) => Ok(Value::Boolean(a > b)),
-                (Value::Integer(a), Value::Integer(b)) => Ok(Value::Boolean(a > b)),
-                (Value::Integer(a), Value::Float(b)) => Ok(Value::Boolean(*a as f64 > *b)),
-                (Value::Float(a), Value::Integer(b)) => Ok(Value::Boolean(*a > *b as f64)),
-                (a, b) => Err(EvalError::TypeError(format!(
-                    "Cannot compare {} > {} (comparison requires numeric types)",
-                    type_name(a), type_name(b)
+                    "Cannot subtract {} from {}",
+                    type_name(b), type_name(a)
                 ))),
             },
-            BinOp::LessEqual => match (left, right) {
-                (Value::Float(a), Value::Float(b)) => Ok(Value::Boolean(a <= b)),
-                (Value::Integer(a), Value::Integer(b)) => Ok(Value::Boolean(a <= b)),
-                (Value::Integer(a), Value::Float(b)) => Ok(Value::Boolean(*a as f64 <= *b)),
-                (Value::Float(a), Value::Integer(b)) => Ok(Value::Boolean(*a <= *b as f64)),
+
+            BinOp::Multiply => match (left, right) {
+                (Value::Float(a), Value::Float(b)) => Ok(Value::Float(a * b)),
+                (Value::Integer(a), Value::Integer(b)) => match a.checked_mul(*b) {
+                    Some(n) => Ok(Value::Integer(n)),
+                    None => self.overflowed(format!("{} * {}", a, b), *a as f64 * *b as f64),
+                },
+                (Value::Integer(a), Value::Float(b)) => {
+                    if let Some(ad) = Decimal::from_i64(*a)
+                        && let Some(bd) = Decimal::from_f64(*b)
+                        && let Some(rd) = ad.checked_mul(bd)
+                    {
+                        if rd.is_integer() &&
+                            let Some(r) = rd.to_i64() {
+                            return Ok(Value::Integer(r));
+                        } else if let Some(r) = rd.to_f64() {
+                            return Ok(Value::Float(r));
+                        }
+                    }
+                    let res = *a as f64 * b;
+                    Ok(Value::Float(res))
+                }
+                (Value::Float(a), Value::Integer(b)) => {
+                    if let Some(ad) = Decimal::from_f64(*a)
+                        && let Some(bd) = Decimal::from_i64(*b)
+                        && let Some(rd) = ad.checked_mul(bd)
+                    {
+                        if rd.is_integer() &&
+                            let Some(r) = rd.to_i64() {
+                            return Ok(Value::Integer(r));
+                        } else if let Some(r) = rd.to_f64() {
+                            return Ok(Value::Float(r));
+                        }
+                    }
+                    let res = *a * *b as f64;
+                    Ok(Value::Float(res))
+                }
                 (a, b) => Err(EvalError::TypeError(format!(
-                    "Cannot compare {} <= {} (comparison requires numeric types)",
+                    "Cannot multiply {} by {}",
                     type_name(a), type_name(b)
                 ))),
             },
-            BinOp::GreaterEqual => match (left, right) {
-                (Value::Float(a), Value::Float(b)) => Ok(Value::Boolean(a >= b)),
-                (Value::Integer(a), Value::Integer(b)) => Ok(Value::Boolean(a >= b)),
-                (Value::Integer(a), Value::Float(b)) => Ok(Value::Boolean(*a as f64 >= *b)),
-                (Value::Float(a), Value::Integer(b)) => Ok(Value::Boolean(*a >= *b as f64)),
-                (a, b) => Err(EvalError::TypeError(format!(
-                    "Cannot compare {} >= {} (comparison requires numeric types)",
+            BinOp::Divide | BinOp::Modulo if is_zero(right) && is_number(left) => {
+                Err(EvalError::DivisionByZero)
+            }
+            BinOp::Divide => match (left, right) {
+                (Value::Float(a), Value::Float(b)) => Ok(Value::Float(a / b)),
+                (Value::Integer(a), Value::Integer(b)) => {
+                    // Check if division is exact; if not, return Float
+                    // (only i64::MIN / -1 overflows)
+                    match (a.checked_rem(*b), a.checked_div(*b)) {
+                        (Some(0), Some(q)) => Ok(Value::Integer(q)),
+                        (None, _) | (_, None) => {
+                            self.overflowed(format!("{} / {}", a, b), *a as f64 / *b as f64)
+                        }
+                        _ => Ok(Value::Float(*a as f64 / *b as f64)),
+                    }
+                }
+                (Value::Integer(a), Value::Float(b)) => {
+                    if let Some(ad) = Decimal::from_i64(*a)
+                        && let Some(bd) = Decimal::from_f64(*b)
+                        && let Some(rd) = ad.checked_div(bd)
+                    {
+                        if rd.is_integer() &&
+                            let Some(r) = rd.to_i64() {
+                            return Ok(Value::Integer(r));
+                        } else if let Some(r) = rd.to_f64() {
+                            return Ok(Value::Float(r));
+                        }
+                    }
+                    let res = *a as f64 / b;
+                    Ok(Value::Float(res))
+                }
+                (Value::Float(a), Value::Integer(b)) => {
+                    if let Some(ad) = Decimal::from_f64(*a)
+                        && let Some(bd) = Decimal::from_i64(*b)
+                        && let Some(rd) = ad.checked_div(bd)
+                    {
+                        if rd.is_integer() &&
+                            let Some(r) = rd.to_i64() {
+                            return Ok(Value::Integer(r));
+                        } else if let Some(r) = rd.to_f64() {
+                            return Ok(Value::Float(r));
+                        }
+                    }
+                    let res = *a / *b as f64;
+                    Ok(Value::Float(res))
+                }
+                (a, b) => Err(EvalError::TypeError(format!(
+                    "Cannot divide {} by {}",
+                    type_name(a), type_name(b)
+                ))),
+            },
+            BinOp::Modulo => match (left, right) {
+                (Value::Float(a), Value::Float(b)) => Ok(Value::Float(a % b)),
+                // Only i64::MIN % -1 overflows, and its remainder is 0
+                (Value::Integer(a), Value::Integer(b)) => Ok(Value::Integer(a.checked_rem(*b).unwrap_or(0))),
+                (Value::Integer(a), Value::Float(b)) => {
+                    if let Some(ad) = Decimal::from_i64(*a)
+                        && let Some(bd) = Decimal::from_f64(*b)
+                        && let Some(rd) = ad.checked_rem(bd)
+                    {
+                        if rd.is_integer() &&
+                            let Some(r) = rd.to_i64() {
+                            return Ok(Value::Integer(r));
+                        } else if let Some(r) = rd.to_f64() {
+                            return Ok(Value::Float(r));
+                        }
+                    }
+                    let res = *a as f64 % b;
+                    Ok(Value::Float(res))
+                }
+                (Value::Float(a), Value::Integer(b)) => {
+                    if let Some(ad) = Decimal::from_f64(*a)
+                        && let Some(bd) = Decimal::from_i64(*b)
+                        && let Some(rd) = ad.checked_rem(bd)
+                    {
+                        if rd.is_integer() &&
+                            let Some(r) = rd.to_i64() {
+                            return Ok(Value::Integer(r));
+                        } else if let Some(r) = rd.to_f64() {
+                            return Ok(Value::Float(r));
+                        }
+                    }
+                    let res = *a % *b as f64;
+                    Ok(Value::Float(res))
+                }
+                (a, b) => Err(EvalError::TypeError(format!(
+                    "Cannot compute modulo of {} by {}",
+                    type_name(a), type_name(b)
+                ))),
+            },
+            BinOp::Equal => Ok(Value::Boolean(left.equals(right))),
+            BinOp::NotEqual => Ok(Value::Boolean(!left.equals(right))),
+            BinOp::ApproxEqual => Ok(Value::Boolean(left.approx_eq(right))),
+            BinOp::LessThan => match (left, right) {
+                (Value::Float(a), Value::Float(b)) => Ok(Value::Boolean(a < b)),
+                (Value::Integer(a), Value::Integer(b)) => Ok(Value::Boolean(a < b)),
+                (Value::Integer(a), Value::Float(b)) => Ok(Value::Boolean((*a as f64) < *b)),
+                (Value::Float(a), Value::Integer(b)) => Ok(Value::Boolean(*a < (*b as f64))),
+                (a, b) => Err(EvalError::TypeError(format!(
+                    "Cannot compare {} < {} (comparison requires numeric types)",
+                    type_name(a), type_name(b)
+                ))),
+            },
+            BinOp::GreaterThan => match (left, right) {
+                (Value::Float(a), Value::Float(b)) => Ok(Value::Boolean(a > b)),
+                (Value::Integer(a), Value::Integer(b)) => Ok(Value::Boolean(a > b)),
+                (Value::Integer(a), Value::Float(b)) => Ok(Value::Boolean(*a as f64 > *b)),
+                (Value::Float(a), Value::Integer(b)) => Ok(Value::Boolean(*a > *b as f64)),
+                (a, b) => Err(EvalError::TypeError(format!(
+                    "Cannot compare {} > {} (comparison requires numeric types)",
+                    type_name(a), type_name(b)
+                ))),
+            },
+            BinOp::LessEqual => match (left, right) {
+                (Value::Float(a), Value::Float(b)) => Ok(Value::Boolean(a <= b)),
+                (Value::Integer(a), Value::Integer(b)) => Ok(Value::Boolean(a <= b)),
+                (Value::Integer(a), Value::Float(b)) => Ok(Value::Boolean(*a as f64 <= *b)),
+                (Value::Float(a), Value::Integer(b)) => Ok(Value::Boolean(*a <= *b as f64)),
+                (a, b) => Err(EvalError::TypeError(format!(
+                    "Cannot compare {} <= {} (comparison requires numeric types)",
+                    type_name(a), type_name(b)
+                ))),
+            },
+            BinOp::GreaterEqual => match (left, right) {
+                (Value::Float(a), Value::Float(b)) => Ok(Value::Boolean(a >= b)),
+                (Value::Integer(a), Value::Integer(b)) => Ok(Value::Boolean(a >= b)),
+                (Value::Integer(a), Value::Float(b)) => Ok(Value::Boolean(*a as f64 >= *b)),
+                (Value::Float(a), Value::Integer(b)) => Ok(Value::Boolean(*a >= *b as f64)),
+                (a, b) => Err(EvalError::TypeError(format!(
+                    "Cannot compare {} >= {} (comparison requires numeric types)",
                     type_name(a), type_name(b)
                 ))),
             },
             BinOp::And => Ok(Value::Boolean(left.as_bool() && right.as_bool())),
             BinOp::Or => Ok(Value::Boolean(left.as_bool() || right.as_bool())),
-            BinOp::NullCoalesce => unreachable!("NullCoalesce handled in eval_expr"),
+            // Normally short-circuited in eval_expr
+            BinOp::NullCoalesce => Ok(if *left == Value::Null { right.clone() } else { left.clone() }),
+        }
+    }
+    fn apply_transform(
+        &mut self,
+        ctx: &EvalContext,
+        target: &Expr,
+        value_expr: &Expr,
+    ) -> Result<Value, EvalError> {
+        let path = self.target_path(target, ctx)?;
+        if path.is_empty() {
+            return Err(EvalError::TypeError(
+                "Cannot transform the root document. Transform a field such as $[price], or use !(...) to output a new document".into(),
+            ));
+        }
+        self.transform_path(ctx.root.clone(), &path, value_expr, ctx)
+    }
+
+    /// The path a transform, delete or foreach target points to, with any
+    /// computed keys evaluated against `ctx`
+    fn target_path(&self, target: &Expr, ctx: &EvalContext) -> Result<Vec<PathSegment>, EvalError> {
+        resolve_path(target, &mut |key| self.eval_expr(key, ctx))
+    }
+
+    /// `document` with the entry at `path` (which is not empty) set from
+    /// `value_expr`, as in a transform
+    fn transform_path(
+        &mut self,
+        mut result: Value,
+        path: &[PathSegment],
+        value_expr: &Expr,
+        ctx: &EvalContext,
+    ) -> Result<Value, EvalError> {
+        let Some((last, parents)) = path.split_last() else {
+            return Ok(result);
+        };
+
+        let transform_type = determine_transform_type(value_expr);
+
+        // Below an index, `@` is the last array element the path passes through
+        let element = match transform_type {
+            TransformType::MapArray(_) => parents
+                .iter()
+                .rposition(|segment| matches!(segment, PathSegment::Index(_)))
+                .and_then(|i| result.get_path(&parents[..=i]))
+                .cloned(),
+            _ => None,
+        };
+
+        let parent = result.path_mut(parents, false)?;
+        self.apply_transform_to_parent(parent, last, transform_type, element, ctx)?;
+
+        Ok(result)
+    }
+
+    /// Apply `transform` to the entry `segment` of `parent`. `element` is the
+    /// array element `@` refers to when the entry is a field of one rather
+    /// than an array.
+    fn apply_transform_to_parent(
+        &mut self,
+        parent: &mut Value,
+        segment: &PathSegment,
+        transform: TransformType,
+        element: Option<Value>,
+        ctx: &EvalContext,
+    ) -> Result<(), EvalError> {
+        let slot = std::slice::from_ref(segment);
+
+        // Fail on a missing element or mismatched parent before evaluating
+        if !matches!((&*parent, segment), (Value::Object(_), PathSegment::Field(_))) {
+            parent.path_mut(slot, false)?;
+        }
+
+        let new_value = match transform {
+            TransformType::Replace(expr) => self.eval_expr(&expr, ctx)?,
+            TransformType::FilterArray(cond) => {
+                if let PathSegment::Index(_) = segment {
+                    return Err(EvalError::TypeError("Cannot filter on array index. Use a field instead (e.g., $[items] not $[items][0])".into()));
+                }
+                match parent.get_path(slot) {
+                    Some(Value::Array(items)) => Value::Array(self.filter_array(items, &cond, ctx)?),
+                    Some(Value::Object(entries)) => Value::Object(self.filter_object(entries, &cond, ctx)?),
+                    Some(value) => {
+                        return Err(EvalError::TypeError(format!(
+                            "Filter transform requires array or object, but '{}' is {}",
+                            segment, type_name(value)
+                        )))
+                    }
+                    None => return Err(EvalError::AccessError(format!("Field '{}' not found", segment))),
+                }
+            }
+            TransformType::MapArray(expr) => match (parent.get_path(slot), element) {
+                (Some(Value::Array(items)), _) => Value::Array(self.map_array(items, &expr, ctx)?),
+                // `@` is the element being replaced
+                (Some(current), _) if matches!(segment, PathSegment::Index(_)) => {
+                    self.eval_expr(&expr, &ctx.with_lambda(current.clone()))?
+                }
+                // A field of an array element: `@` is that element
+                (_, Some(element)) => self.eval_expr(&expr, &ctx.with_lambda(element))?,
+                (None, None) => return Err(EvalError::AccessError(format!("Field '{}' not found", segment))),
+                (Some(value), None) => {
+                    return Err(EvalError::TypeError(format!(
+                        "Map transform requires array, but '{}' is {}",
+                        segment, type_name(value)
+                    )))
+                }
+            },
+        };
+
+        parent.set_path(slot, new_value)?;
+        Ok(())
+    }
+
+    fn filter_array(
+        &self,
+        items: &[Value],
+        condition: &Expr,
+        ctx: &EvalContext,
+    ) -> Result<Vec<Value>, EvalError> {
+        let mut result = Vec::new();
+
+        for item in items {
+            let lambda_ctx = ctx.with_lambda(item.clone());
+
+            let keep = self.eval_expr(condition, &lambda_ctx)?;
+
+            if keep.as_bool() {
+                result.push(item.clone());
+            }
+        }
+
+        Ok(result)
+    }
+
+    /// Keep the entries for which `condition` holds, with `@` bound to the
+    /// value and `@key` to the key. Entries are visited in key order, so the
+    /// same entry always reports an error.
+    fn filter_object(
+        &self,
+        entries: &HashMap<String, Value>,
+        condition: &Expr,
+        ctx: &EvalContext,
+    ) -> Result<HashMap<String, Value>, EvalError> {
+        let mut keys: Vec<&String> = entries.keys().collect();
+        keys.sort();
+
+        let mut result = HashMap::new();
+        for key in keys {
+            let value = &entries[key];
+            if self.eval_expr(condition, &ctx.with_entry(key, value.clone()))?.as_bool() {
+                result.insert(key.clone(), value.clone());
+            }
+        }
+
+        Ok(result)
+    }
+
+    fn map_array(
+        &self,
+        items: &[Value],
+        expr: &Expr,
+        ctx: &EvalContext,
+    ) -> Result<Vec<Value>, EvalError> {
+        let mut result = Vec::new();
+
+        for item in items {
+            let lambda_ctx = ctx.with_lambda(item.clone());
+
+            let new_value = self.eval_expr(expr, &lambda_ctx)?;
+
+            result.push(new_value);
+        }
+
+        Ok(result)
+    }
+
+    // ========================================
+    // Lazy Array Pipelines
+    // ========================================
+
+    /// Evaluate `<array>.filter(..).map(..)...<terminal>()` element by element.
+    ///
+    /// Chains of `.filter()`/`.map()` feeding a terminal method are streamed:
+    /// no intermediate arrays are built, and short-circuiting terminals stop
+    /// pulling elements as soon as their answer is known. Skipping elements
+    /// is only unobservable when no stage can fail or has side effects, so
+    /// only such stages (see [`Evaluator::infallible_kind`]) are streamed.
+    ///
+    /// Leaves anything else to the eager path: [`LazyChain::NotLazy`] when
+    /// the expression isn't such a chain, or [`LazyChain::Source`] when its
+    /// source is not an array or its stages may fail, so the source isn't
+    /// evaluated twice.
+    fn eval_lazy_chain(
+        &self,
+        object: &Expr,
+        method: &str,
+        args: &[Expr],
+        ctx: &EvalContext,
+    ) -> Result<LazyChain, EvalError> {
+        let terminal = match (method, args) {
+            ("first", []) => LazyTerminal::First,
+            ("count", []) => LazyTerminal::Count(None),
+            ("count", [pred]) => LazyTerminal::Count(Some(pred)),
+            ("exists", []) => LazyTerminal::Exists,
+            ("any", [pred]) => LazyTerminal::Any(pred),
+            ("all", [pred]) => LazyTerminal::All(pred),
+            ("find", [pred]) => LazyTerminal::Find(pred),
+            ("find_index", [pred]) => LazyTerminal::FindIndex(pred),
+            ("take", [count]) => LazyTerminal::Take(count),
+            _ => return Ok(LazyChain::NotLazy),
+        };
+
+        let mut stages = Vec::new();
+        let mut source = object;
+        while let Expr::MethodCall { object, method, args } = source {
+            match (method.as_str(), args.as_slice()) {
+                ("filter", [pred]) => stages.push(LazyStage::Filter(pred)),
+                ("map", [expr]) => stages.push(LazyStage::Map(expr)),
+                _ => break,
+            }
+            source = object;
+        }
+        if stages.is_empty() {
+            return Ok(LazyChain::NotLazy);
+        }
+        stages.reverse();
+
+        let items = match self.eval_expr(source, ctx)? {
+            Value::Array(items) => items,
+            value => return Ok(LazyChain::Source { value, stages: stages.len() }),
+        };
+        let mut kind = ItemKind::of(&items);
+        for stage in &stages {
+            let checked = match stage {
+                LazyStage::Filter(pred) => self.infallible_kind(pred, kind, None).map(|_| kind),
+                LazyStage::Map(expr) => self.infallible_kind(expr, kind, None),
+            };
+            match checked {
+                Some(next) => kind = next,
+                None => return Ok(LazyChain::Source { value: Value::Array(items), stages: stages.len() }),
+            }
+        }
+        // Like the eager `.take()`, the count comes after its receiver
+        let limit = match terminal {
+            LazyTerminal::Take(count) => self.count_arg("take", std::slice::from_ref(count), ctx)?,
+            _ => 0,
+        };
+        // Streaming stages build no array of their own
+        if let Some(recorder) = &self.eval_stats {
+            for stage in &stages {
+                let method = match stage {
+                    LazyStage::Filter(_) => "filter",
+                    LazyStage::Map(_) => "map",
+                };
+                recorder.method_call(method, 0);
+            }
+        }
+
+        let mut result = match terminal {
+            LazyTerminal::First | LazyTerminal::Find(_) | LazyTerminal::FindIndex(_) => Value::Null,
+            LazyTerminal::Count(_) => Value::Integer(0),
+            LazyTerminal::Exists | LazyTerminal::Any(_) => Value::Boolean(false),
+            LazyTerminal::All(_) => Value::Boolean(true),
+            LazyTerminal::Take(_) => Value::Array(Vec::new()),
+        };
+
+        if matches!(terminal, LazyTerminal::Take(_)) && limit == 0 {
+            return Ok(LazyChain::Done(result));
+        }
+
+        // Position among the elements that reach the terminal
+        let mut index = 0;
+        'items: for item in items {
+            let mut value = item;
+            for stage in &stages {
+                match stage {
+                    LazyStage::Filter(pred) => {
+                        if !self.eval_expr(pred, &ctx.with_lambda(value.clone()))?.as_bool() {
+                            continue 'items;
+                        }
+                    }
+                    LazyStage::Map(expr) => {
+                        value = self.eval_expr(expr, &ctx.with_lambda(value))?;
+                    }
+                }
+            }
+
+            match &terminal {
+                LazyTerminal::First => {
+                    result = value;
+                    break;
+                }
+                LazyTerminal::Count(pred) => {
+                    let counted = match pred {
+                        Some(pred) => self.eval_expr(pred, &ctx.with_lambda(value))?.as_bool(),
+                        None => true,
+                    };
+                    if let Value::Integer(n) = &mut result
+                        && counted
+                    {
+                        *n += 1;
+                    }
+                }
+                LazyTerminal::Exists => {
+                    result = Value::Boolean(true);
+                    break;
+                }
+                LazyTerminal::Any(pred) => {
+                    if self.eval_expr(pred, &ctx.with_lambda(value))?.as_bool() {
+                        result = Value::Boolean(true);
+                        break;
+                    }
+                }
+                LazyTerminal::All(pred) => {
+                    if !self.eval_expr(pred, &ctx.with_lambda(value))?.as_bool() {
+                        result = Value::Boolean(false);
+                        break;
+                    }
+                }
+                LazyTerminal::Find(pred) => {
+                    if self.eval_expr(pred, &ctx.with_lambda(value.clone()))?.as_bool() {
+                        result = value;
+                        break;
+                    }
+                }
+                LazyTerminal::FindIndex(pred) => {
+                    if self.eval_expr(pred, &ctx.with_lambda(value))?.as_bool() {
+                        result = Value::Integer(index);
+                        break;
+                    }
+                }
+                LazyTerminal::Take(_) => {
+                    if let Value::Array(taken) = &mut result {
+                        taken.push(value);
+                        if taken.len() == limit {
+                            break;
+                        }
+                    }
+                }
+            }
+            index += 1;
+        }
+
+        Ok(LazyChain::Done(result))
+    }
+
+    /// What `expr` gives for every element of kind `item`, or `None` unless
+    /// it is sure to give something without error or side effect: paths with
+    /// literal keys, comparisons and arithmetic on numbers. `param` is the
+    /// name a lambda around `expr` gives the element.
+    fn infallible_kind(&self, expr: &Expr, item: ItemKind, param: Option<&str>) -> Option<ItemKind> {
+        let number = |expr: &Expr| {
+            self.infallible_kind(expr, item, param).filter(|&kind| kind == ItemKind::Number)
+        };
+        match expr {
+            Expr::Integer(_) | Expr::Float(_) => Some(ItemKind::Number),
+            Expr::String(_) | Expr::Boolean(_) | Expr::Null => Some(ItemKind::Any),
+            Expr::Root | Expr::OriginalRoot => Some(ItemKind::Any),
+            Expr::Meta => Some(ItemKind::Object),
+            Expr::LambdaParam => Some(item),
+            Expr::LambdaVar(name) if param == Some(name.as_str()) => Some(item),
+            Expr::Lambda { param: name, body } if param.is_none() => {
+                self.infallible_kind(body, item, Some(name))
+            }
+            Expr::ExistenceCheck(inner) => self.infallible_kind(inner, item, param).map(|_| ItemKind::Any),
+            Expr::Access { object, key } => {
+                let receiver = self.infallible_kind(object, item, param)?;
+                match (receiver, key.as_ref()) {
+                    (ItemKind::Object, Expr::Key(_) | Expr::String(_) | Expr::Integer(_))
+                    | (ItemKind::Array, Expr::Integer(_)) => Some(ItemKind::Any),
+                    _ => None,
+                }
+            }
+            Expr::BinaryOp { op, left, right } => match op {
+                BinOp::Equal
+                | BinOp::NotEqual
+                | BinOp::ApproxEqual
+                | BinOp::And
+                | BinOp::Or
+                | BinOp::NullCoalesce => {
+                    self.infallible_kind(left, item, param)?;
+                    self.infallible_kind(right, item, param)?;
+                    Some(ItemKind::Any)
+                }
+                BinOp::LessThan | BinOp::GreaterThan | BinOp::LessEqual | BinOp::GreaterEqual => {
+                    number(left)?;
+                    number(right)?;
+                    Some(ItemKind::Any)
+                }
+                BinOp::Add | BinOp::Subtract | BinOp::Multiply
+                    if self.overflow_policy != OverflowPolicy::Error
+                        && self.non_finite_policy != NonFinitePolicy::Error =>
+                {
+                    number(left)?;
+                    number(right)?;
+                    Some(ItemKind::Number)
+                }
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// Evaluate the last `stages` method calls of the chain `expr` eagerly,
+    /// one whole array at a time, starting from its evaluated `source`
+    fn eval_stages_from(
+        &self,
+        expr: &Expr,
+        stages: usize,
+        source: Value,
+        ctx: &EvalContext,
+    ) -> Result<Value, EvalError> {
+        let Expr::MethodCall { object, method, args } = expr else {
+            return Ok(source);
+        };
+        if stages == 0 {
+            return Ok(source);
+        }
+        let obj_value = self.eval_stages_from(object, stages - 1, source, ctx)?;
+        let value = self.eval_method_call(&obj_value, method, args, ctx)?;
+        if let Some(recorder) = &self.eval_stats {
+            recorder.method_call(method, value.approx_size());
+        }
+        Ok(value)
+    }
+
+    /// Dispatch method calls to their implementations
+    fn eval_method_call(
+        &self,
+        object: &Value,
+        method: &str,
+        args: &[Expr],
+        ctx: &EvalContext,
+    ) -> Result<Value, EvalError> {
+        if let Some(host) = self.methods.get(method) {
+            let args = args.iter().map(|arg| self.eval_expr(arg, ctx)).collect::<Result<Vec<_>, _>>()?;
+            return host(object, &args);
+        }
+        match method {
+            // Array methods
+            "any" => self.method_any(object, args, ctx),
+            "all" => self.method_all(object, args, ctx),
+            "find" => self.method_find(object, args, ctx),
+            "find_index" => self.method_find_index(object, args, ctx),
+            "index_of" => self.method_index_of("index_of", object, args, ctx),
+            "last_index_of" => self.method_index_of("last_index_of", object, args, ctx),
+            "filter" => self.method_filter(object, args, ctx),
+            "map" => self.method_map(object, args, ctx),
+            "flat_map" => self.method_flat_map(object, args, ctx),
+            "count" => self.method_count(object, args, ctx),
+            "length" => self.method_length(object),
+            "sum" => self.method_sum(object, args, ctx),
+            "min" => self.method_min(object),
+            "max" => self.method_max(object),
+            "min_by" => self.method_extreme_by("min_by", std::cmp::Ordering::Less, object, args, ctx),
+            "max_by" => self.method_extreme_by("max_by", std::cmp::Ordering::Greater, object, args, ctx),
+            "arg_min" => self.method_arg_extreme("arg_min", std::cmp::Ordering::Less, object, args, ctx),
+            "arg_max" => self.method_arg_extreme("arg_max", std::cmp::Ordering::Greater, object, args, ctx),
+            "avg" => self.method_avg(object),
+            "median" => self.method_median(object),
+            "percentile" => self.method_percentile(object, args, ctx),
+            "variance" => self.method_variance(object),
+            "stddev" => self.method_stddev(object),
+            "stats" => self.method_stats(object),
+            "first" => self.method_first(object),
+            "last" => self.method_last(object),
+            "exists" => self.method_exists(object),
+            "unique" => self.method_unique(object),
+            "unique_by" => self.method_unique_by(object, args, ctx),
+            "sort" => self.method_sort(object, args, ctx),
+            "sort_by" => self.method_sort_by(object, args, ctx),
+            "sort_desc" => self.method_sort_desc(object),
+            "reverse" => self.method_reverse(object),
+            "flatten" => self.method_flatten(object),
+            "take" => self.method_take(object, args, ctx),
+            "sample" => self.method_sample(object, args, ctx),
+            "shuffle" => self.method_shuffle(object),
+            "skip" => self.method_skip(object, args, ctx),
+            "take_while" => self.method_take_while(object, args, ctx),
+            "skip_while" => self.method_skip_while(object, args, ctx),
+            "chunk" => self.method_chunk(object, args, ctx),
+            "window" => self.method_window(object, args, ctx),
+            "bucket_by_time" => self.method_bucket_by_time(object, args, ctx),
+            "rate_per" => self.method_rate_per(object, args, ctx),
+            // String methods
+            "upper" => self.method_upper(object),
+            "lower" => self.method_lower(object),
+            "trim" => self.method_trim(object),
+            "title" => self.method_recase("title", case::title, object),
+            "snake_case" => self.method_recase("snake_case", case::snake_case, object),
+            "camel_case" => self.method_recase("camel_case", case::camel_case, object),
+            "kebab_case" => self.method_recase("kebab_case", case::kebab_case, object),
+            "normalize_unicode" => self.method_normalize_unicode(object, args, ctx),
+            #[cfg(feature = "stdlib")]
+            "parse_url" | "url_encode" | "url_decode" | "parse_query_string" => {
+                crate::stdlib::call_method(method, object)
+            }
+            "split" => self.method_split(object, args, ctx),
+            "lines" | "words" | "chars" => self.method_split_into(method, object),
+            "contains" => self.method_contains(object, args, ctx),
+            "contains_all" => self.method_contains_all(object, args, ctx),
+            "contains_any" => self.method_contains_any(object, args, ctx),
+            "startswith" => self.method_startswith(object, args, ctx),
+            "endswith" => self.method_endswith(object, args, ctx),
+            "matches" => self.method_matches(object, args, ctx),
+            // Bytes methods
+            "base64_decode" => self.method_base64_decode(object),
+            "base64_encode" => self.method_base64_encode(object),
+            "hex_decode" => self.method_hex_decode(object),
+            "hex_encode" => self.method_hex_encode(object),
+            "bytes_len" => self.method_bytes_len(object),
+            // Object methods
+            "keys" | "sorted_keys" => self.method_keys(object),
+            "values" => self.method_values(object),
+            "has" => self.method_has(object, args, ctx),
+            "has_path" => self.method_has_path(object, args, ctx),
+            "get_path" => self.method_get_path(object, args, ctx),
+            // Tree methods
+            "walk" => self.method_walk(object, args, ctx),
+            "paths" => self.method_paths(object),
+            // Type method (works on any value)
+            "type" => self.method_type(object),
+            "is_nan" => Ok(Value::Boolean(matches!(object, Value::Float(n) if n.is_nan()))),
+            "is_finite" => Ok(Value::Boolean(match object {
+                Value::Integer(_) => true,
+                Value::Float(n) => n.is_finite(),
+                _ => false,
+            })),
+            "or" => self.method_or(object, args, ctx),
+            _ => Err(EvalError::UnknownMethod {
+                name: method.to_string(),
+                candidates: suggest::near_misses(method, method_names().chain(self.methods.names())),
+            }),
+        }
+    }
+
+    /// .any(lambda) - returns true if any element matches
+    fn method_any(
+        &self,
+        object: &Value,
+        args: &[Expr],
+        ctx: &EvalContext,
+    ) -> Result<Value, EvalError> {
+        let arr = match object {
+            Value::Array(arr) => arr,
+            _ => {
+                return Err(EvalError::TypeError(format!(
+                    ".any() requires array, got {}",
+                    type_name(object)
+                )))
+            }
+        };
+
+        if args.is_empty() {
+            return Err(EvalError::TypeError(
+                ".any() requires a predicate argument".to_string(),
+            ));
+        }
+
+        let predicate = &args[0];
+
+        for item in arr {
+            let lambda_ctx = ctx.with_lambda(item.clone());
+            let result = self.eval_expr(predicate, &lambda_ctx)?;
+            if result.as_bool() {
+                return Ok(Value::Boolean(true));
+            }
+        }
+
+        Ok(Value::Boolean(false))
+    }
+
+    /// .all(lambda) - returns true if all elements match
+    fn method_all(
+        &self,
+        object: &Value,
+        args: &[Expr],
+        ctx: &EvalContext,
+    ) -> Result<Value, EvalError> {
+        let arr = match object {
+            Value::Array(arr) => arr,
+            _ => {
+                return Err(EvalError::TypeError(format!(
+                    ".all() requires array, got {}",
+                    type_name(object)
+                )))
+            }
+        };
+
+        if args.is_empty() {
+            return Err(EvalError::TypeError(
+                ".all() requires a predicate argument".to_string(),
+            ));
+        }
+
+        let predicate = &args[0];
+
+        for item in arr {
+            let lambda_ctx = ctx.with_lambda(item.clone());
+            let result = self.eval_expr(predicate, &lambda_ctx)?;
+            if !result.as_bool() {
+                return Ok(Value::Boolean(false));
+            }
+        }
+
+        Ok(Value::Boolean(true))
+    }
+
+    /// .filter(lambda) - returns array of matching elements
+    fn method_filter(
+        &self,
+        object: &Value,
+        args: &[Expr],
+        ctx: &EvalContext,
+    ) -> Result<Value, EvalError> {
+        let arr = match object {
+            Value::Array(arr) => arr,
+            _ => {
+                return Err(EvalError::TypeError(format!(
+                    ".filter() requires array, got {}",
+                    type_name(object)
+                )))
+            }
+        };
+
+        if args.is_empty() {
+            return Err(EvalError::TypeError(
+                ".filter() requires a predicate argument".to_string(),
+            ));
+        }
+
+        let predicate = &args[0];
+        let filtered = self.filter_array(arr, predicate, ctx)?;
+
+        Ok(Value::Array(filtered))
+    }
+
+    /// .map(lambda) - transforms each element
+    fn method_map(
+        &self,
+        object: &Value,
+        args: &[Expr],
+        ctx: &EvalContext,
+    ) -> Result<Value, EvalError> {
+        let arr = match object {
+            Value::Array(arr) => arr,
+            _ => {
+                return Err(EvalError::TypeError(format!(
+                    ".map() requires array, got {}",
+                    type_name(object)
+                )))
+            }
+        };
+
+        if args.is_empty() {
+            return Err(EvalError::TypeError(
+                ".map() requires a transform expression argument".to_string(),
+            ));
+        }
+
+        let transform = &args[0];
+        let mapped = self.map_array(arr, transform, ctx)?;
+
+        Ok(Value::Array(mapped))
+    }
+
+    /// .flat_map(lambda) - maps each element and concatenates the results in
+    /// one pass; a result that is not an array counts as one element
+    fn method_flat_map(
+        &self,
+        object: &Value,
+        args: &[Expr],
+        ctx: &EvalContext,
+    ) -> Result<Value, EvalError> {
+        let arr = match object {
+            Value::Array(arr) => arr,
+            _ => {
+                return Err(EvalError::TypeError(format!(
+                    ".flat_map() requires array, got {}",
+                    type_name(object)
+                )))
+            }
+        };
+
+        if args.is_empty() {
+            return Err(EvalError::TypeError(
+                ".flat_map() requires a transform expression argument".to_string(),
+            ));
+        }
+
+        let mut result = Vec::with_capacity(arr.len());
+        for item in arr {
+            match self.eval_expr(&args[0], &ctx.with_lambda(item.clone()))? {
+                Value::Array(inner) => result.extend(inner),
+                other => result.push(other),
+            }
+        }
+
+        Ok(Value::Array(result))
+    }
+
+    /// .count(lambda?) - returns number of elements, or of the elements
+    /// matching a predicate
+    fn method_count(
+        &self,
+        object: &Value,
+        args: &[Expr],
+        ctx: &EvalContext,
+    ) -> Result<Value, EvalError> {
+        let arr = match object {
+            Value::Array(arr) => arr,
+            _ => {
+                return Err(EvalError::TypeError(format!(
+                    ".count() requires array, got {}",
+                    type_name(object)
+                )))
+            }
+        };
+
+        let predicate = match args {
+            [] => return Ok(Value::Integer(arr.len() as i64)),
+            [predicate] => predicate,
+            _ => {
+                return Err(EvalError::TypeError(
+                    ".count() takes at most one predicate argument".to_string(),
+                ))
+            }
+        };
+
+        let mut count = 0;
+        for item in arr {
+            let lambda_ctx = ctx.with_lambda(item.clone());
+            if self.eval_expr(predicate, &lambda_ctx)?.as_bool() {
+                count += 1;
+            }
+        }
+        Ok(Value::Integer(count))
+    }
+
+    /// .sum(lambda?) - sums numeric values, optionally extracting with lambda
+    fn method_sum(
+        &self,
+        object: &Value,
+        args: &[Expr],
+        ctx: &EvalContext,
+    ) -> Result<Value, EvalError> {
+        let arr = match object {
+            Value::Array(arr) => arr,
+            _ => {
+                return Err(EvalError::TypeError(format!(
+                    ".sum() requires array, got {}",
+                    type_name(object)
+                )))
+            }
+        };
+
+        let mut sum_int: i64 = 0;
+        let mut sum_float: f64 = 0.0;
+        let mut has_float = false;
+
+        for item in arr {
+            let value = if args.is_empty() {
+                item.clone()
+            } else {
+                let lambda_ctx = ctx.with_lambda(item.clone());
+                self.eval_expr(&args[0], &lambda_ctx)?
+            };
+
+            match value {
+                Value::Integer(n) => {
+                    if has_float {
+                        sum_float += n as f64;
+                    } else if let Some(sum) = sum_int.checked_add(n) {
+                        sum_int = sum;
+                    } else {
+                        if self.overflow_policy == OverflowPolicy::Error {
+                            return Err(EvalError::Overflow(format!(
+                                ".sum() exceeded the integer range adding {}",
+                                n
+                            )));
+                        }
+                        sum_float = sum_int as f64 + n as f64;
+                        has_float = true;
+                    }
+                }
+                Value::Float(n) => {
+                    if !has_float {
+                        sum_float = sum_int as f64;
+                        has_float = true;
+                    }
+                    sum_float += n;
+                }
+                _ => {
+                    return Err(EvalError::TypeError(format!(
+                        ".sum() requires numeric values, got {}",
+                        type_name(&value)
+                    )))
+                }
+            }
+        }
+
+        if has_float {
+            self.finite(Value::Float(sum_float), || ".sum()".to_string())
+        } else {
+            Ok(Value::Integer(sum_int))
+        }
+    }
+
+    /// .first() - returns first element or null
+    fn method_first(&self, object: &Value) -> Result<Value, EvalError> {
+        match object {
+            Value::Array(arr) => Ok(arr.first().cloned().unwrap_or(Value::Null)),
+            _ => Err(EvalError::TypeError(format!(
+                ".first() requires array, got {}",
+                type_name(object)
+            ))),
+        }
+    }
+
+    /// .last() - returns last element or null
+    fn method_last(&self, object: &Value) -> Result<Value, EvalError> {
+        match object {
+            Value::Array(arr) => Ok(arr.last().cloned().unwrap_or(Value::Null)),
+            _ => Err(EvalError::TypeError(format!(
+                ".last() requires array, got {}",
+                type_name(object)
+            ))),
+        }
+    }
+
+    /// .exists() - returns true if array exists and is non-empty
+    fn method_exists(&self, object: &Value) -> Result<Value, EvalError> {
+        match object {
+            Value::Array(arr) => Ok(Value::Boolean(!arr.is_empty())),
+            Value::Null => Ok(Value::Boolean(false)),
+            _ => Err(EvalError::TypeError(format!(
+                ".exists() requires array, got {}",
+                type_name(object)
+            ))),
+        }
+    }
+
+    /// .unique() - returns array with duplicates removed (first occurrence kept)
+    fn method_unique(&self, object: &Value) -> Result<Value, EvalError> {
+        let arr = match object {
+            Value::Array(arr) => arr,
+            _ => {
+                return Err(EvalError::TypeError(format!(
+                    ".unique() requires array, got {}",
+                    type_name(object)
+                )))
+            }
+        };
+
+        let mut seen = HashSet::with_capacity(arr.len());
+        let result = arr
+            .iter()
+            .filter(|item| seen.insert(item.hash_key()))
+            .cloned()
+            .collect();
+
+        Ok(Value::Array(result))
+    }
+
+    /// .unique_by(lambda) - removes elements whose derived key was already seen
+    fn method_unique_by(
+        &self,
+        object: &Value,
+        args: &[Expr],
+        ctx: &EvalContext,
+    ) -> Result<Value, EvalError> {
+        let arr = match object {
+            Value::Array(arr) => arr,
+            _ => {
+                return Err(EvalError::TypeError(format!(
+                    ".unique_by() requires array, got {}",
+                    type_name(object)
+                )))
+            }
+        };
+
+        if args.is_empty() {
+            return Err(EvalError::TypeError(
+                ".unique_by() requires a key expression argument".to_string(),
+            ));
+        }
+
+        let mut seen = HashSet::with_capacity(arr.len());
+        let mut result = Vec::new();
+        for item in arr {
+            let lambda_ctx = ctx.with_lambda(item.clone());
+            let key = self.eval_expr(&args[0], &lambda_ctx)?;
+            if seen.insert(key.hash_key()) {
+                result.push(item.clone());
+            }
+        }
+
+        Ok(Value::Array(result))
+    }
+
+    /// .sort(lambda...) - returns sorted array, optionally by one or more keys
+    ///
+    /// Later keys break ties left by earlier ones; all keys sort ascending.
+    fn method_sort(
+        &self,
+        object: &Value,
+        args: &[Expr],
+        ctx: &EvalContext,
+    ) -> Result<Value, EvalError> {
+        let arr = match object {
+            Value::Array(arr) => arr,
+            _ => {
+                return Err(EvalError::TypeError(format!(
+                    ".sort() requires array, got {}",
+                    type_name(object)
+                )))
+            }
+        };
+
+        let keys: Vec<SortKey> = args.iter().map(|expr| SortKey { expr, desc: false }).collect();
+        Ok(Value::Array(self.sort_by_keys("sort", arr, &keys, ctx)?))
+    }
+
+    /// .sort_by([spec, ...]) - multi-key sort with per-key direction
+    ///
+    /// Each spec is either a key expression (ascending) or an object
+    /// `{"key": <expr>, "desc": <bool>}`. Specs must be written as an array
+    /// literal because key expressions are evaluated per element.
+    fn method_sort_by(
+        &self,
+        object: &Value,
+        args: &[Expr],
+        ctx: &EvalContext,
+    ) -> Result<Value, EvalError> {
+        let arr = match object {
+            Value::Array(arr) => arr,
+            _ => {
+                return Err(EvalError::TypeError(format!(
+                    ".sort_by() requires array, got {}",
+                    type_name(object)
+                )))
+            }
+        };
+
+        let specs = match args {
+            [Expr::Array(specs)] => specs,
+            [] => {
+                return Err(EvalError::TypeError(
+                    ".sort_by() requires an array of sort keys".to_string(),
+                ))
+            }
+            _ => {
+                return Err(EvalError::TypeError(
+                    ".sort_by() argument must be an array literal of sort keys, e.g. [{\"key\": @[age], \"desc\": true}]".to_string(),
+                ))
+            }
+        };
+
+        let mut keys = Vec::with_capacity(specs.len());
+        for spec in specs {
+            keys.push(self.parse_sort_spec(spec, ctx)?);
+        }
+
+        Ok(Value::Array(self.sort_by_keys("sort_by", arr, &keys, ctx)?))
+    }
+
+    /// Interpret one `.sort_by()` spec
+    fn parse_sort_spec<'a>(&self, spec: &'a Expr, ctx: &EvalContext) -> Result<SortKey<'a>, EvalError> {
+        let Expr::Object(fields) = spec else {
+            return Ok(SortKey { expr: spec, desc: false });
+        };
+
+        let mut key = None;
+        let mut desc = false;
+        for (name, expr) in fields {
+            match name.as_str() {
+                "key" => key = Some(expr),
+                "desc" => desc = self.eval_expr(expr, ctx)?.as_bool(),
+                other => {
+                    return Err(EvalError::TypeError(format!(
+                        ".sort_by() spec has unknown field '{}' (expected \"key\" or \"desc\")",
+                        other
+                    )))
+                }
+            }
         }
+
+        let expr = key.ok_or_else(|| {
+            EvalError::TypeError(".sort_by() spec object requires a \"key\" field".to_string())
+        })?;
+        Ok(SortKey { expr, desc })
     }
-    /// Remove a field at the given path. Silent no-op if path doesn't exist.
-    fn delete_field(&self, current: &mut Value, path: &[PathSegment]) {
-        if path.is_empty() {
-            return;
-        }
 
-        if path.len() == 1 {
-            // Remove the target key from parent
-            match (&mut *current, &path[0]) {
-                (Value::Object(map), PathSegment::Field(key)) => {
-                    map.remove(key);
+    /// Stable sort of `items` by the given keys (the element itself if `keys` is empty)
+    fn sort_by_keys(
+        &self,
+        method: &str,
+        items: &[Value],
+        keys: &[SortKey],
+        ctx: &EvalContext,
+    ) -> Result<Vec<Value>, EvalError> {
+        let mut keyed: Vec<(Vec<Value>, &Value)> = Vec::with_capacity(items.len());
+        for item in items {
+            let item_keys = if keys.is_empty() {
+                vec![item.clone()]
+            } else {
+                let lambda_ctx = ctx.with_lambda(item.clone());
+                let mut item_keys = Vec::with_capacity(keys.len());
+                for key in keys {
+                    item_keys.push(self.eval_expr(key.expr, &lambda_ctx)?);
                 }
-                (Value::Array(arr), PathSegment::Index(idx)) => {
-                    let len = arr.len();
-                    let index = if *idx < 0 {
-                        let abs = idx.unsigned_abs() as usize;
-                        if abs > len { return; }
-                        len - abs
-                    } else {
-                        *idx as usize
-                    };
-                    if index < len {
-                        arr.remove(index);
-                    }
+                item_keys
+            };
+            keyed.push((item_keys, item));
+        }
+        for i in 0..keys.len().max(1) {
+            self.check_orderable(method, keyed.iter().map(|(item_keys, _)| &item_keys[i]))?;
+        }
+
+        keyed.sort_by(|(a, _), (b, _)| {
+            for (i, (ka, kb)) in a.iter().zip(b).enumerate() {
+                let ord = self.compare_values(ka, kb);
+                let ord = if keys.get(i).is_some_and(|k| k.desc) { ord.reverse() } else { ord };
+                if ord != std::cmp::Ordering::Equal {
+                    return ord;
                 }
-                _ => {} // no-op
             }
-            return;
+            std::cmp::Ordering::Equal
+        });
+
+        Ok(keyed.into_iter().map(|(_, v)| v.clone()).collect())
+    }
+
+    /// Compare two values for sorting (see [`Value::total_cmp`])
+    fn compare_values(&self, a: &Value, b: &Value) -> std::cmp::Ordering {
+        a.total_cmp(b)
+    }
+
+    /// With strict ordering, fail unless `values` are all of one type, so
+    /// that ordering them does not fall back to the order between types
+    fn check_orderable<'a>(
+        &self,
+        method: &str,
+        values: impl IntoIterator<Item = &'a Value>,
+    ) -> Result<(), EvalError> {
+        if !self.strict_ordering {
+            return Ok(());
+        }
+        let mut values = values.into_iter();
+        let Some(first) = values.next() else {
+            return Ok(());
+        };
+        match values.find(|value| value.type_rank() != first.type_rank()) {
+            Some(other) => Err(EvalError::TypeError(format!(
+                ".{}() cannot order {} and {} values under strict ordering",
+                method,
+                type_name(first),
+                type_name(other)
+            ))),
+            None => Ok(()),
+        }
+    }
+
+    // ========================================
+    // String Methods
+    // ========================================
+
+    /// .upper() - converts string to uppercase
+    fn method_upper(&self, object: &Value) -> Result<Value, EvalError> {
+        match object {
+            Value::String(s) => Ok(Value::String(s.to_uppercase())),
+            _ => Err(EvalError::TypeError(format!(
+                ".upper() requires string, got {}",
+                type_name(object)
+            ))),
+        }
+    }
+
+    /// .lower() - converts string to lowercase
+    fn method_lower(&self, object: &Value) -> Result<Value, EvalError> {
+        match object {
+            Value::String(s) => Ok(Value::String(s.to_lowercase())),
+            _ => Err(EvalError::TypeError(format!(
+                ".lower() requires string, got {}",
+                type_name(object)
+            ))),
+        }
+    }
+
+    /// .title() / .snake_case() / .camel_case() / .kebab_case() - changes
+    /// the case of a string's words (see [`case`])
+    fn method_recase(&self, name: &str, recase: fn(&str) -> String, object: &Value) -> Result<Value, EvalError> {
+        match object {
+            Value::String(s) => Ok(Value::String(recase(s))),
+            _ => Err(EvalError::TypeError(format!(
+                ".{}() requires string, got {}",
+                name,
+                type_name(object)
+            ))),
         }
+    }
+
+    /// .normalize_unicode(form?) - the string in Unicode normalization form
+    /// "NFC" (the default), "NFD", "NFKC" or "NFKD"
+    fn method_normalize_unicode(
+        &self,
+        object: &Value,
+        args: &[Expr],
+        ctx: &EvalContext,
+    ) -> Result<Value, EvalError> {
+        use unicode_normalization::UnicodeNormalization;
 
-        // Navigate to parent, then recurse
-        match (&mut *current, &path[0]) {
-            (Value::Object(map), PathSegment::Field(key)) => {
-                if let Some(child) = map.get_mut(key) {
-                    self.delete_field(child, &path[1..]);
+        let Value::String(s) = object else {
+            return Err(EvalError::TypeError(format!(
+                ".normalize_unicode() requires string, got {}",
+                type_name(object)
+            )));
+        };
+        let form = match args {
+            [] => "NFC".to_string(),
+            [arg] => match self.eval_expr(arg, ctx)? {
+                Value::String(form) => form,
+                other => {
+                    return Err(EvalError::TypeError(format!(
+                        ".normalize_unicode() form must be a string, got {}",
+                        type_name(&other)
+                    )))
                 }
-                // missing intermediate → no-op
+            },
+            _ => {
+                return Err(EvalError::TypeError(
+                    ".normalize_unicode() takes at most one argument, the form".to_string(),
+                ))
             }
-            (Value::Array(arr), PathSegment::Index(idx)) => {
-                let len = arr.len();
-                let index = if *idx < 0 {
-                    let abs = idx.unsigned_abs() as usize;
-                    if abs > len { return; }
-                    len - abs
-                } else {
-                    *idx as usize
-                };
-                if let Some(child) = arr.get_mut(index) {
-                    self.delete_field(child, &path[1..]);
-                }
+        };
+        let normalized = match form.to_ascii_uppercase().as_str() {
+            "NFC" => s.nfc().collect(),
+            "NFD" => s.nfd().collect(),
+            "NFKC" => s.nfkc().collect(),
+            "NFKD" => s.nfkd().collect(),
+            _ => {
+                return Err(EvalError::TypeError(format!(
+                    ".normalize_unicode() form must be \"NFC\", \"NFD\", \"NFKC\" or \"NFKD\", got \"{}\"",
+                    form
+                )))
             }
-            _ => {} // no-op
+        };
+        Ok(Value::String(normalized))
+    }
+
+    /// .contains(value) - substring check for strings, element membership for
+    /// arrays (deep equality), key membership for objects
+    fn method_contains(
+        &self,
+        object: &Value,
+        args: &[Expr],
+        ctx: &EvalContext,
+    ) -> Result<Value, EvalError> {
+        if !matches!(object, Value::String(_) | Value::Array(_) | Value::Object(_)) {
+            return Err(EvalError::TypeError(format!(
+                ".contains() requires string, array, or object, got {}",
+                type_name(object)
+            )));
         }
+
+        if args.is_empty() {
+            return Err(EvalError::TypeError(
+                ".contains() requires a substring, element, or key argument".to_string(),
+            ));
+        }
+
+        let needle = self.eval_expr(&args[0], ctx)?;
+        Ok(Value::Boolean(self.value_contains("contains", object, &needle)?))
     }
 
-    fn apply_transform(
-        &mut self,
+    /// .contains_all(list) - returns true if every item of list is contained
+    fn method_contains_all(
+        &self,
+        object: &Value,
+        args: &[Expr],
         ctx: &EvalContext,
-        target: &Expr,
-        value_expr: &Expr,
     ) -> Result<Value, EvalError> {
-        let path = extract_path(target)?;
+        let needles = self.contains_list_arg("contains_all", object, args, ctx)?;
+        for needle in &needles {
+            if !self.value_contains("contains_all", object, needle)? {
+                return Ok(Value::Boolean(false));
+            }
+        }
+        Ok(Value::Boolean(true))
+    }
 
-        // if path.is_empty() {
-        //     return Err(EvalError::TypeError(
-        //         "Cannot transform root directly. Use a specific field path or use literal object for output".to_string(),
-        //     ));
-        // }
+    /// .contains_any(list) - returns true if at least one item of list is contained
+    fn method_contains_any(
+        &self,
+        object: &Value,
+        args: &[Expr],
+        ctx: &EvalContext,
+    ) -> Result<Value, EvalError> {
+        let needles = self.contains_list_arg("contains_any", object, args, ctx)?;
+        for needle in &needles {
+            if self.value_contains("contains_any", object, needle)? {
+                return Ok(Value::Boolean(true));
+            }
+        }
+        Ok(Value::Boolean(false))
+    }
 
-        let transform_type = determine_transform_type(value_expr);
+    /// Validate receiver and evaluate the list argument of .contains_all()/.contains_any()
+    fn contains_list_arg(
+        &self,
+        method: &str,
+        object: &Value,
+        args: &[Expr],
+        ctx: &EvalContext,
+    ) -> Result<Vec<Value>, EvalError> {
+        if !matches!(object, Value::String(_) | Value::Array(_) | Value::Object(_)) {
+            return Err(EvalError::TypeError(format!(
+                ".{}() requires string, array, or object, got {}",
+                method,
+                type_name(object)
+            )));
+        }
 
-        let mut result = ctx.root.clone();
+        if args.is_empty() {
+            return Err(EvalError::TypeError(format!(
+                ".{}() requires a list argument",
+                method
+            )));
+        }
 
-        self.apply_transform_at_path(&mut result, &path, transform_type, ctx)?;
+        match self.eval_expr(&args[0], ctx)? {
+            Value::Array(items) => Ok(items),
+            other => Err(EvalError::TypeError(format!(
+                ".{}() argument must be array, got {}",
+                method,
+                type_name(&other)
+            ))),
+        }
+    }
 
-        Ok(result)
+    /// Membership test shared by the .contains() family
+    fn value_contains(&self, method: &str, haystack: &Value, needle: &Value) -> Result<bool, EvalError> {
+        match (haystack, needle) {
+            (Value::String(s), Value::String(sub)) => Ok(s.contains(sub.as_str())),
+            (Value::String(_), _) => Err(EvalError::TypeError(format!(
+                ".{}() on string requires string argument, got {}",
+                method,
+                type_name(needle)
+            ))),
+            (Value::Array(arr), _) => Ok(arr.iter().any(|item| item.equals(needle))),
+            (Value::Object(obj), Value::String(key)) => Ok(obj.contains_key(key)),
+            (Value::Object(_), _) => Err(EvalError::TypeError(format!(
+                ".{}() on object requires string key, got {}",
+                method,
+                type_name(needle)
+            ))),
+            _ => Err(EvalError::TypeError(format!(
+                ".{}() requires string, array, or object, got {}",
+                method,
+                type_name(haystack)
+            ))),
+        }
     }
 
-    fn apply_transform_at_path(
-        &mut self,
-        current: &mut Value,
-        path: &[PathSegment],
-        transform: TransformType,
+    /// .startswith(prefix) - returns true if string starts with prefix
+    fn method_startswith(
+        &self,
+        object: &Value,
+        args: &[Expr],
         ctx: &EvalContext,
-    ) -> Result<(), EvalError> {
-        if path.is_empty() {
+    ) -> Result<Value, EvalError> {
+        let s = match object {
+            Value::String(s) => s,
+            _ => {
+                return Err(EvalError::TypeError(format!(
+                    ".startswith() requires string, got {}",
+                    type_name(object)
+                )))
+            }
+        };
+
+        if args.is_empty() {
             return Err(EvalError::TypeError(
-                "Internal error: empty path in apply_transform_at_path".into(),
+                ".startswith() requires a prefix argument".to_string(),
             ));
         }
 
-        if path.len() == 1 {
-            return self.apply_transform_to_parent(current, &path[0], transform, ctx);
+        let prefix = self.eval_expr(&args[0], ctx)?;
+        match prefix {
+            Value::String(p) => Ok(Value::Boolean(s.starts_with(&p))),
+            _ => Err(EvalError::TypeError(format!(
+                ".startswith() argument must be string, got {}",
+                type_name(&prefix)
+            ))),
         }
+    }
 
-        let segment = &path[0];
-
-        let rest = &path[1..];
-
-        match (current, segment) {
-            (Value::Object(map), PathSegment::Field(key)) => {
-                let child = map
-                    .get_mut(key)
-                    .ok_or_else(|| EvalError::AccessError(format!("Field '{}' not found", key)))?;
-
-                self.apply_transform_at_path(child, rest, transform, ctx)?;
-                Ok(())
-            }
-            (Value::Array(arr), PathSegment::Index(idx)) => {
-                let len = arr.len();
-                let index = if *idx >= 0 {
-                    *idx as usize
-                } else if idx.unsigned_abs() < (len as u64) {
-                    len - idx.unsigned_abs() as usize
-                } else {
-                    return Err(EvalError::AccessError(format!("Cannot access array element at {} for array with length {}", idx, len)))
-                };
-                let child = arr.get_mut(index).ok_or_else(|| {
-                    EvalError::AccessError(format!(
-                        "Array index {} out of bounds (length: {})",
-                        idx, len,
-                    ))
-                })?;
-                self.apply_transform_at_path(child, rest, transform, ctx)?;
-                Ok(())
+    /// .endswith(suffix) - returns true if string ends with suffix
+    fn method_endswith(
+        &self,
+        object: &Value,
+        args: &[Expr],
+        ctx: &EvalContext,
+    ) -> Result<Value, EvalError> {
+        let s = match object {
+            Value::String(s) => s,
+            _ => {
+                return Err(EvalError::TypeError(format!(
+                    ".endswith() requires string, got {}",
+                    type_name(object)
+                )))
             }
+        };
 
-            (Value::Array(_), PathSegment::Field(_)) => Err(EvalError::TypeError(
-                "Cannot use field name on array".into(),
-            )),
+        if args.is_empty() {
+            return Err(EvalError::TypeError(
+                ".endswith() requires a suffix argument".to_string(),
+            ));
+        }
 
-            (v, p) => Err(EvalError::TypeError(format!(
-                "Cannot navigate through {} with path segment {:?}",
-                type_name(v), p
+        let suffix = self.eval_expr(&args[0], ctx)?;
+        match suffix {
+            Value::String(suf) => Ok(Value::Boolean(s.ends_with(&suf))),
+            _ => Err(EvalError::TypeError(format!(
+                ".endswith() argument must be string, got {}",
+                type_name(&suffix)
             ))),
         }
     }
 
-    fn apply_transform_to_parent(
-        &mut self,
-        parent: &mut Value,
-        segment: &PathSegment,
-        transform: TransformType,
+    /// .matches(pattern) - returns true if string matches regex pattern
+    fn method_matches(
+        &self,
+        object: &Value,
+        args: &[Expr],
         ctx: &EvalContext,
-    ) -> Result<(), EvalError> {
-        match (parent, segment) {
-            (Value::Object(map), PathSegment::Field(key)) => match transform {
-                TransformType::Replace(expr) => {
-                    let new_value = self.eval_expr(&expr, ctx)?;
-                    map.insert(key.clone(), new_value);
-                    Ok(())
-                }
-                TransformType::FilterArray(cond) => {
-                    let arr = map.get(key).ok_or_else(|| {
-                        EvalError::AccessError(format!("Field '{}' not found", key))
-                    })?;
-
-                    match arr {
-                        Value::Array(items) => {
-                            let filtered = self.filter_array(items, &cond, ctx)?;
-                            map.insert(key.clone(), Value::Array(filtered));
-                            Ok(())
-                        }
-                        _ => Err(EvalError::TypeError(format!(
-                            "Filter transform requires array, but '{}' is {}",
-                            key, type_name(arr)
-                        ))),
-                    }
-                }
-                TransformType::MapArray(expr) => {
-                    let arr = map.get(key).ok_or_else(|| {
-                        EvalError::AccessError(format!("Field '{}' not found", key))
-                    })?;
-
-                    match arr {
-                        Value::Array(items) => {
-                            let mapped = self.map_array(items, &expr, ctx)?;
-                            map.insert(key.clone(), Value::Array(mapped));
-                            Ok(())
-                        }
-                        _ => Err(EvalError::TypeError(format!(
-                            "Map transform requires array, but '{}' is {}",
-                            key, type_name(arr)
-                        ))),
-                    }
-                }
-            },
-            (Value::Array(arr), PathSegment::Index(idx)) => match transform {
-                TransformType::Replace(expr) => {
-                    let len = arr.len();
-                    let index = if *idx >= 0 {
-                        *idx as usize
-                    } else {
-                        len - idx.unsigned_abs() as usize
-                    };
+    ) -> Result<Value, EvalError> {
+        if args.len() != 1 {
+            return Err(EvalError::TypeError(
+                ".matches() requires exactly one argument".to_string(),
+            ));
+        }
+        let pattern_val = self.eval_expr(&args[0], ctx)?;
+        let pattern_str = match &pattern_val {
+            Value::String(s) => s.as_str(),
+            _ => {
+                return Err(EvalError::TypeError(format!(
+                    ".matches() argument must be string, got {}",
+                    type_name(&pattern_val)
+                )))
+            }
+        };
+        let re = self.regex_cache.compile(pattern_str, self.regex_size_limit.unwrap_or(REGEX_SIZE_LIMIT))?;
+        match object {
+            Value::String(s) => Ok(Value::Boolean(re.is_match(s))),
+            _ => Ok(Value::Boolean(false)),
+        }
+    }
 
-                    let new_val = self.eval_expr(&expr, ctx)?;
-                    arr[index] = new_val;
-                    Ok(())
-                }
-                TransformType::FilterArray(_) | TransformType::MapArray(_) => {
-                    Err(EvalError::TypeError("Cannot filter/map on array index. Use a field instead (e.g., $[items] not $[items][0])".into()))
-                }
-            },
+    // ========================================
+    // Bytes Methods
+    // ========================================
 
-            (Value::Object(_), PathSegment::Index(_)) => {
-                Err(EvalError::TypeError(
-                        "Cannot use array index on object".into()
-                ))
+    /// .base64_decode() - the bytes of base64 text (standard or URL-safe
+    /// alphabet, padding optional)
+    fn method_base64_decode(&self, object: &Value) -> Result<Value, EvalError> {
+        let text = match object {
+            Value::String(s) => s.trim(),
+            _ => {
+                return Err(EvalError::TypeError(format!(
+                    ".base64_decode() requires string, got {}",
+                    type_name(object)
+                )))
             }
+        };
+        let engine = if text.contains(['-', '_']) { &BASE64_URL_SAFE } else { &BASE64_STANDARD };
+        engine
+            .decode(text)
+            .map(Value::Bytes)
+            .map_err(|e| EvalError::TypeError(format!(".base64_decode(): {}", e)))
+    }
 
-            (Value::Array(_), PathSegment::Field(_)) => {
-                Err(EvalError::TypeError(
-                        "Cannot use array index on object".into()
-                ))
-            }
+    /// .base64_encode() - standard, padded base64 of bytes, or of a
+    /// string's UTF-8 bytes
+    fn method_base64_encode(&self, object: &Value) -> Result<Value, EvalError> {
+        let bytes = bytes_of(object, "base64_encode")?;
+        Ok(Value::String(crate::value::encode_base64(bytes)))
+    }
 
+    /// .hex_decode() - the bytes of hex text (either case)
+    fn method_hex_decode(&self, object: &Value) -> Result<Value, EvalError> {
+        let text = match object {
+            Value::String(s) => s.trim(),
             _ => {
-                Err(EvalError::TypeError(
-                        "Invalid parent type for transform.".to_string()
-                ))
+                return Err(EvalError::TypeError(format!(
+                    ".hex_decode() requires string, got {}",
+                    type_name(object)
+                )))
             }
+        };
+        if text.len() % 2 != 0 {
+            return Err(EvalError::TypeError(format!(
+                ".hex_decode(): odd number of digits ({})",
+                text.len()
+            )));
         }
+        text.as_bytes()
+            .chunks(2)
+            .map(|pair| {
+                std::str::from_utf8(pair)
+                    .ok()
+                    .and_then(|pair| u8::from_str_radix(pair, 16).ok())
+                    .ok_or_else(|| {
+                        EvalError::TypeError(format!(
+                            ".hex_decode(): invalid hex digits '{}'",
+                            String::from_utf8_lossy(pair)
+                        ))
+                    })
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .map(Value::Bytes)
     }
 
-    fn filter_array(
-        &self,
-        items: &[Value],
-        condition: &Expr,
-        ctx: &EvalContext,
-    ) -> Result<Vec<Value>, EvalError> {
-        let mut result = Vec::new();
+    /// .hex_encode() - lowercase hex of bytes, or of a string's UTF-8 bytes
+    fn method_hex_encode(&self, object: &Value) -> Result<Value, EvalError> {
+        let bytes = bytes_of(object, "hex_encode")?;
+        Ok(Value::String(bytes.iter().map(|b| format!("{:02x}", b)).collect()))
+    }
 
-        for item in items {
-            let lambda_ctx = ctx.with_lambda(item.clone());
+    /// .bytes_len() - number of bytes, counting a string as UTF-8
+    fn method_bytes_len(&self, object: &Value) -> Result<Value, EvalError> {
+        Ok(Value::Integer(bytes_of(object, "bytes_len")?.len() as i64))
+    }
 
-            let keep = self.eval_expr(condition, &lambda_ctx)?;
+    // ========================================
+    // Type Method
+    // ========================================
 
-            if keep.as_bool() {
-                result.push(item.clone());
-            }
+    /// .type() - returns the type name as a string
+    fn method_type(&self, object: &Value) -> Result<Value, EvalError> {
+        let type_name = match object {
+            Value::Null => "null",
+            Value::Boolean(_) => "boolean",
+            Value::Integer(_) => "number",
+            Value::Float(_) => "number",
+            Value::String(_) => "string",
+            Value::Bytes(_) => "bytes",
+            Value::Array(_) => "array",
+            Value::Object(_) => "object",
+        };
+        Ok(Value::String(type_name.to_string()))
+    }
+
+    /// .or(default) - the value itself, or default if it is null. The default
+    /// is only evaluated when it is used.
+    fn method_or(&self, object: &Value, args: &[Expr], ctx: &EvalContext) -> Result<Value, EvalError> {
+        let [default] = args else {
+            return Err(EvalError::TypeError(format!(
+                ".or() takes 1 argument, got {}",
+                args.len()
+            )));
+        };
+        match object {
+            Value::Null => self.eval_expr(default, ctx),
+            _ => Ok(object.clone()),
         }
+    }
 
-        Ok(result)
+    // ========================================
+    // Additional Array Methods
+    // ========================================
+
+    /// .length() - returns length of array or string
+    fn method_length(&self, object: &Value) -> Result<Value, EvalError> {
+        match object {
+            Value::Array(arr) => Ok(Value::Integer(arr.len() as i64)),
+            Value::String(s) => Ok(Value::Integer(s.chars().count() as i64)),
+            Value::Bytes(b) => Ok(Value::Integer(b.len() as i64)),
+            _ => Err(EvalError::TypeError(format!(
+                ".length() requires array, string or bytes, got {}",
+                type_name(object)
+            ))),
+        }
     }
 
-    fn map_array(
-        &self,
-        items: &[Value],
-        expr: &Expr,
-        ctx: &EvalContext,
-    ) -> Result<Vec<Value>, EvalError> {
-        let mut result = Vec::new();
+    /// .min() - returns minimum value in array
+    fn method_min(&self, object: &Value) -> Result<Value, EvalError> {
+        let arr = match object {
+            Value::Array(arr) => arr,
+            _ => {
+                return Err(EvalError::TypeError(format!(
+                    ".min() requires array, got {}",
+                    type_name(object)
+                )))
+            }
+        };
 
-        for item in items {
-            let lambda_ctx = ctx.with_lambda(item.clone());
+        if arr.is_empty() {
+            return Ok(Value::Null);
+        }
+        self.check_orderable("min", arr)?;
 
-            let new_value = self.eval_expr(expr, &lambda_ctx)?;
+        let min = arr
+            .iter()
+            .reduce(|min, item| if self.compare_values(item, min).is_lt() { item } else { min });
 
-            result.push(new_value);
+        Ok(min.cloned().unwrap_or(Value::Null))
+    }
+
+    /// .max() - returns maximum value in array
+    fn method_max(&self, object: &Value) -> Result<Value, EvalError> {
+        let arr = match object {
+            Value::Array(arr) => arr,
+            _ => {
+                return Err(EvalError::TypeError(format!(
+                    ".max() requires array, got {}",
+                    type_name(object)
+                )))
+            }
+        };
+
+        if arr.is_empty() {
+            return Ok(Value::Null);
         }
+        self.check_orderable("max", arr)?;
 
-        Ok(result)
+        let max = arr
+            .iter()
+            .reduce(|max, item| if self.compare_values(item, max).is_gt() { item } else { max });
+
+        Ok(max.cloned().unwrap_or(Value::Null))
     }
 
-    /// Dispatch method calls to their implementations
-    fn eval_method_call(
+    /// .min_by(lambda) / .max_by(lambda) - returns the element whose key is
+    /// smallest / largest, or null for an empty array
+    fn method_extreme_by(
         &self,
+        name: &str,
+        wanted: std::cmp::Ordering,
         object: &Value,
-        method: &str,
         args: &[Expr],
         ctx: &EvalContext,
     ) -> Result<Value, EvalError> {
-        match method {
-            // Array methods
-            "any" => self.method_any(object, args, ctx),
-            "all" => self.method_all(object, args, ctx),
-            "filter" => self.method_filter(object, args, ctx),
-            "map" => self.method_map(object, args, ctx),
-            "count" => self.method_count(object),
-            "length" => self.method_length(object),
-            "sum" => self.method_sum(object, args, ctx),
-            "min" => self.method_min(object),
-            "max" => self.method_max(object),
-            "avg" => self.method_avg(object),
-            "first" => self.method_first(object),
-            "last" => self.method_last(object),
-            "exists" => self.method_exists(object),
-            "unique" => self.method_unique(object),
-            "sort" => self.method_sort(object, args, ctx),
-            "sort_desc" => self.method_sort_desc(object),
-            "reverse" => self.method_reverse(object),
-            "flatten" => self.method_flatten(object),
-            // String methods
-            "upper" => self.method_upper(object),
-            "lower" => self.method_lower(object),
-            "trim" => self.method_trim(object),
-            "split" => self.method_split(object, args, ctx),
-            "contains" => self.method_contains(object, args, ctx),
-            "startswith" => self.method_startswith(object, args, ctx),
-            "endswith" => self.method_endswith(object, args, ctx),
-            "matches" => self.method_matches(object, args, ctx),
-            // Object methods
-            "keys" => self.method_keys(object),
-            "values" => self.method_values(object),
-            // Type method (works on any value)
-            "type" => self.method_type(object),
-            _ => Err(EvalError::TypeError(format!(
-                "Unknown method: {}",
-                method
-            ))),
+        if args.is_empty() {
+            return Err(EvalError::TypeError(format!(".{}() requires a key expression argument", name)));
         }
+        Ok(match self.extreme_position(name, wanted, object, args, ctx)? {
+            Some((_, item)) => item.clone(),
+            None => Value::Null,
+        })
     }
 
-    /// .any(lambda) - returns true if any element matches
-    fn method_any(
+    /// .arg_min(lambda?) / .arg_max(lambda?) - returns the index of the
+    /// smallest / largest element (or key), or null for an empty array
+    fn method_arg_extreme(
         &self,
+        name: &str,
+        wanted: std::cmp::Ordering,
         object: &Value,
         args: &[Expr],
         ctx: &EvalContext,
     ) -> Result<Value, EvalError> {
+        Ok(match self.extreme_position(name, wanted, object, args, ctx)? {
+            Some((index, _)) => Value::Integer(index as i64),
+            None => Value::Null,
+        })
+    }
+
+    /// Find the first element whose key (the element itself without a key
+    /// expression) orders `wanted` against every other, in one pass
+    fn extreme_position<'a>(
+        &self,
+        name: &str,
+        wanted: std::cmp::Ordering,
+        object: &'a Value,
+        args: &[Expr],
+        ctx: &EvalContext,
+    ) -> Result<Option<(usize, &'a Value)>, EvalError> {
         let arr = match object {
             Value::Array(arr) => arr,
             _ => {
                 return Err(EvalError::TypeError(format!(
-                    ".any() requires array, got {}",
+                    ".{}() requires array, got {}",
+                    name,
                     type_name(object)
                 )))
             }
         };
 
-        if args.is_empty() {
-            return Err(EvalError::TypeError(
-                ".any() requires a predicate argument".to_string(),
-            ));
-        }
-
-        let predicate = &args[0];
-
-        for item in arr {
-            let lambda_ctx = ctx.with_lambda(item.clone());
-            let result = self.eval_expr(predicate, &lambda_ctx)?;
-            if result.as_bool() {
-                return Ok(Value::Boolean(true));
+        let mut best: Option<(usize, Value)> = None;
+        for (index, item) in arr.iter().enumerate() {
+            let key = match args.first() {
+                Some(expr) => self.eval_expr(expr, &ctx.with_lambda(item.clone()))?,
+                None => item.clone(),
+            };
+            match &best {
+                Some((_, best_key)) => {
+                    self.check_orderable(name, [best_key, &key])?;
+                    if self.compare_values(&key, best_key) == wanted {
+                        best = Some((index, key));
+                    }
+                }
+                None => best = Some((index, key)),
             }
         }
 
-        Ok(Value::Boolean(false))
+        Ok(best.map(|(index, _)| (index, &arr[index])))
     }
 
-    /// .all(lambda) - returns true if all elements match
-    fn method_all(
-        &self,
-        object: &Value,
-        args: &[Expr],
-        ctx: &EvalContext,
-    ) -> Result<Value, EvalError> {
+    /// .avg() - returns average of numeric values in array
+    fn method_avg(&self, object: &Value) -> Result<Value, EvalError> {
         let arr = match object {
             Value::Array(arr) => arr,
             _ => {
                 return Err(EvalError::TypeError(format!(
-                    ".all() requires array, got {}",
+                    ".avg() requires array, got {}",
                     type_name(object)
                 )))
             }
         };
 
-        if args.is_empty() {
-            return Err(EvalError::TypeError(
-                ".all() requires a predicate argument".to_string(),
-            ));
+        if arr.is_empty() {
+            return Ok(Value::Null);
         }
 
-        let predicate = &args[0];
+        // Integers are summed exactly; i128 cannot overflow for any array
+        // that fits in memory
+        let mut sum_int: i128 = 0;
+        let mut sum: f64 = 0.0;
+        let mut count: usize = 0;
 
         for item in arr {
-            let lambda_ctx = ctx.with_lambda(item.clone());
-            let result = self.eval_expr(predicate, &lambda_ctx)?;
-            if !result.as_bool() {
-                return Ok(Value::Boolean(false));
+            match item {
+                Value::Integer(n) => {
+                    sum_int += *n as i128;
+                    count += 1;
+                }
+                Value::Float(n) => {
+                    sum += n;
+                    count += 1;
+                }
+                _ => {}
             }
         }
 
-        Ok(Value::Boolean(true))
+        if count == 0 {
+            return Ok(Value::Null);
+        }
+
+        Ok(Value::Float((sum_int as f64 + sum) / count as f64))
     }
 
-    /// .filter(lambda) - returns array of matching elements
-    fn method_filter(
+    /// .median() - returns the median of numeric values in array
+    fn method_median(&self, object: &Value) -> Result<Value, EvalError> {
+        let mut numbers = numeric_items("median", object)?;
+        numbers.sort_by(f64::total_cmp);
+        Ok(percentile_of_sorted(&numbers, 50.0).map_or(Value::Null, Value::Float))
+    }
+
+    /// .percentile(p) - returns the p-th percentile (0-100) of numeric values,
+    /// interpolating linearly between the closest ranks
+    fn method_percentile(
         &self,
         object: &Value,
         args: &[Expr],
         ctx: &EvalContext,
     ) -> Result<Value, EvalError> {
+        let mut numbers = numeric_items("percentile", object)?;
+
+        if args.is_empty() {
+            return Err(EvalError::TypeError(
+                ".percentile() requires a percentile argument (0-100)".to_string(),
+            ));
+        }
+
+        let arg = self.eval_expr(&args[0], ctx)?;
+        let p = match arg.as_float() {
+            Some(p) if (0.0..=100.0).contains(&p) => p,
+            Some(p) => {
+                return Err(EvalError::TypeError(format!(
+                    ".percentile() argument must be between 0 and 100, got {}",
+                    p
+                )))
+            }
+            None => {
+                return Err(EvalError::TypeError(format!(
+                    ".percentile() argument must be number, got {}",
+                    type_name(&arg)
+                )))
+            }
+        };
+
+        numbers.sort_by(f64::total_cmp);
+        Ok(percentile_of_sorted(&numbers, p).map_or(Value::Null, Value::Float))
+    }
+
+    /// .variance() - returns the population variance of numeric values in array
+    fn method_variance(&self, object: &Value) -> Result<Value, EvalError> {
+        let moments = Moments::of(&numeric_items("variance", object)?);
+        Ok(moments.variance().map_or(Value::Null, Value::Float))
+    }
+
+    /// .stddev() - returns the population standard deviation of numeric values in array
+    fn method_stddev(&self, object: &Value) -> Result<Value, EvalError> {
+        let moments = Moments::of(&numeric_items("stddev", object)?);
+        Ok(moments.variance().map_or(Value::Null, |v| Value::Float(v.sqrt())))
+    }
+
+    /// .stats() - returns {count, min, max, mean, median, stddev} of numeric values
+    fn method_stats(&self, object: &Value) -> Result<Value, EvalError> {
+        let mut numbers = numeric_items("stats", object)?;
+        let moments = Moments::of(&numbers);
+        numbers.sort_by(f64::total_cmp);
+
+        // min/max come from the original items so integers stay integers
+        let items: &[Value] = match object {
+            Value::Array(arr) => arr,
+            _ => &[],
+        };
+        let numeric = items.iter().filter(|v| matches!(v, Value::Integer(_) | Value::Float(_)));
+        let min = numeric
+            .clone()
+            .reduce(|min, item| if self.compare_values(item, min).is_lt() { item } else { min });
+        let max = numeric
+            .reduce(|max, item| if self.compare_values(item, max).is_gt() { item } else { max });
+
+        let float_or_null = |n: Option<f64>| n.map_or(Value::Null, Value::Float);
+        let mut stats = HashMap::new();
+        stats.insert("count".to_string(), Value::Integer(moments.count as i64));
+        stats.insert("min".to_string(), min.cloned().unwrap_or(Value::Null));
+        stats.insert("max".to_string(), max.cloned().unwrap_or(Value::Null));
+        stats.insert("mean".to_string(), float_or_null(moments.mean()));
+        stats.insert("median".to_string(), float_or_null(percentile_of_sorted(&numbers, 50.0)));
+        stats.insert("stddev".to_string(), float_or_null(moments.variance().map(f64::sqrt)));
+        Ok(Value::Object(stats))
+    }
+
+    /// .sort_desc() - sorts array in descending order
+    fn method_sort_desc(&self, object: &Value) -> Result<Value, EvalError> {
         let arr = match object {
             Value::Array(arr) => arr,
             _ => {
                 return Err(EvalError::TypeError(format!(
-                    ".filter() requires array, got {}",
+                    ".sort_desc() requires array, got {}",
                     type_name(object)
                 )))
             }
         };
 
-        if args.is_empty() {
-            return Err(EvalError::TypeError(
-                ".filter() requires a predicate argument".to_string(),
-            ));
-        }
-
-        let predicate = &args[0];
-        let filtered = self.filter_array(arr, predicate, ctx)?;
+        self.check_orderable("sort_desc", arr)?;
+        let mut sorted = arr.clone();
+        sorted.sort_by(|a, b| self.compare_values(b, a));
 
-        Ok(Value::Array(filtered))
+        Ok(Value::Array(sorted))
     }
 
-    /// .map(lambda) - transforms each element
-    fn method_map(
-        &self,
-        object: &Value,
-        args: &[Expr],
-        ctx: &EvalContext,
-    ) -> Result<Value, EvalError> {
+    /// .reverse() - reverses array order
+    fn method_reverse(&self, object: &Value) -> Result<Value, EvalError> {
         let arr = match object {
             Value::Array(arr) => arr,
             _ => {
                 return Err(EvalError::TypeError(format!(
-                    ".map() requires array, got {}",
+                    ".reverse() requires array, got {}",
                     type_name(object)
                 )))
             }
         };
 
-        if args.is_empty() {
-            return Err(EvalError::TypeError(
-                ".map() requires a transform expression argument".to_string(),
-            ));
-        }
-
-        let transform = &args[0];
-        let mapped = self.map_array(arr, transform, ctx)?;
-
-        Ok(Value::Array(mapped))
-    }
-
-    /// .count() - returns number of elements
-    fn method_count(&self, object: &Value) -> Result<Value, EvalError> {
-        match object {
-            Value::Array(arr) => Ok(Value::Integer(arr.len() as i64)),
-            _ => Err(EvalError::TypeError(format!(
-                ".count() requires array, got {}",
-                type_name(object)
-            ))),
-        }
+        let mut reversed = arr.clone();
+        reversed.reverse();
+        Ok(Value::Array(reversed))
     }
 
-    /// .sum(lambda?) - sums numeric values, optionally extracting with lambda
-    fn method_sum(
-        &self,
-        object: &Value,
-        args: &[Expr],
-        ctx: &EvalContext,
-    ) -> Result<Value, EvalError> {
+    /// .flatten() - flattens nested arrays one level
+    fn method_flatten(&self, object: &Value) -> Result<Value, EvalError> {
         let arr = match object {
             Value::Array(arr) => arr,
             _ => {
                 return Err(EvalError::TypeError(format!(
-                    ".sum() requires array, got {}",
+                    ".flatten() requires array, got {}",
                     type_name(object)
                 )))
             }
         };
 
-        let mut sum_int: i64 = 0;
-        let mut sum_float: f64 = 0.0;
-        let mut has_float = false;
-
+        let mut result = Vec::new();
         for item in arr {
-            let value = if args.is_empty() {
-                item.clone()
-            } else {
-                let lambda_ctx = ctx.with_lambda(item.clone());
-                self.eval_expr(&args[0], &lambda_ctx)?
-            };
-
-            match value {
-                Value::Integer(n) => {
-                    if has_float {
-                        sum_float += n as f64;
-                    } else {
-                        sum_int += n;
-                    }
-                }
-                Value::Float(n) => {
-                    if !has_float {
-                        sum_float = sum_int as f64;
-                        has_float = true;
-                    }
-                    sum_float += n;
-                }
-                _ => {
-                    return Err(EvalError::TypeError(format!(
-                        ".sum() requires numeric values, got {}",
-                        type_name(&value)
-                    )))
-                }
+            match item {
+                Value::Array(inner) => result.extend(inner.clone()),
+                other => result.push(other.clone()),
             }
         }
 
-        if has_float {
-            Ok(Value::Float(sum_float))
-        } else {
-            Ok(Value::Integer(sum_int))
-        }
+        Ok(Value::Array(result))
     }
 
-    /// .first() - returns first element or null
-    fn method_first(&self, object: &Value) -> Result<Value, EvalError> {
-        match object {
-            Value::Array(arr) => Ok(arr.first().cloned().unwrap_or(Value::Null)),
-            _ => Err(EvalError::TypeError(format!(
-                ".first() requires array, got {}",
-                type_name(object)
-            ))),
+    /// Evaluate the count argument of .take()/.skip()/.chunk()/.window()
+    fn count_arg(
+        &self,
+        method: &str,
+        args: &[Expr],
+        ctx: &EvalContext,
+    ) -> Result<usize, EvalError> {
+        if args.len() != 1 {
+            return Err(EvalError::TypeError(format!(
+                ".{}() requires exactly one argument",
+                method
+            )));
         }
-    }
-
-    /// .last() - returns last element or null
-    fn method_last(&self, object: &Value) -> Result<Value, EvalError> {
-        match object {
-            Value::Array(arr) => Ok(arr.last().cloned().unwrap_or(Value::Null)),
-            _ => Err(EvalError::TypeError(format!(
-                ".last() requires array, got {}",
-                type_name(object)
+        match self.eval_expr(&args[0], ctx)? {
+            Value::Integer(n) if n >= 0 => Ok(n as usize),
+            Value::Integer(n) => Err(EvalError::TypeError(format!(
+                ".{}() argument must be non-negative, got {}",
+                method, n
             ))),
-        }
-    }
-
-    /// .exists() - returns true if array exists and is non-empty
-    fn method_exists(&self, object: &Value) -> Result<Value, EvalError> {
-        match object {
-            Value::Array(arr) => Ok(Value::Boolean(!arr.is_empty())),
-            Value::Null => Ok(Value::Boolean(false)),
-            _ => Err(EvalError::TypeError(format!(
-                ".exists() requires array, got {}",
-                type_name(object)
+            other => Err(EvalError::TypeError(format!(
+                ".{}() argument must be integer, got {}",
+                method,
+                type_name(&other)
             ))),
         }
     }
 
-    /// .unique() - returns array with duplicates removed
-    fn method_unique(&self, object: &Value) -> Result<Value, EvalError> {
+    /// .take(n) - returns the first n elements
+    fn method_take(
+        &self,
+        object: &Value,
+        args: &[Expr],
+        ctx: &EvalContext,
+    ) -> Result<Value, EvalError> {
         let arr = match object {
             Value::Array(arr) => arr,
             _ => {
                 return Err(EvalError::TypeError(format!(
-                    ".unique() requires array, got {}",
+                    ".take() requires array, got {}",
                     type_name(object)
                 )))
             }
         };
 
-        let mut result = Vec::new();
-        for item in arr {
-            if !result.contains(item) {
-                result.push(item.clone());
-            }
-        }
-
-        Ok(Value::Array(result))
+        let n = self.count_arg("take", args, ctx)?;
+        Ok(Value::Array(arr.iter().take(n).cloned().collect()))
     }
 
-    /// .sort(lambda?) - returns sorted array
-    fn method_sort(
+    /// .sample(n) - returns n elements chosen at random, in their original
+    /// order (all of them if there are no more than n)
+    fn method_sample(
         &self,
         object: &Value,
         args: &[Expr],
         ctx: &EvalContext,
     ) -> Result<Value, EvalError> {
         let arr = match object {
-            Value::Array(arr) => arr.clone(),
+            Value::Array(arr) => arr,
             _ => {
                 return Err(EvalError::TypeError(format!(
-                    ".sort() requires array, got {}",
+                    ".sample() requires array, got {}",
                     type_name(object)
                 )))
             }
         };
 
-        if arr.is_empty() {
-            return Ok(Value::Array(arr));
-        }
-
-        // Extract sort keys if lambda provided
-        let mut items_with_keys: Vec<(Value, Value)> = if args.is_empty() {
-            arr.iter().map(|v| (v.clone(), v.clone())).collect()
-        } else {
-            let mut result = Vec::new();
-            for item in &arr {
-                let lambda_ctx = ctx.with_lambda(item.clone());
-                let key = self.eval_expr(&args[0], &lambda_ctx)?;
-                result.push((item.clone(), key));
-            }
-            result
-        };
-
-        // Sort by keys
-        items_with_keys.sort_by(|(_, a), (_, b)| self.compare_values(a, b));
-
-        let sorted: Vec<Value> = items_with_keys.into_iter().map(|(v, _)| v).collect();
-        Ok(Value::Array(sorted))
-    }
-
-    /// Compare two values for sorting
-    fn compare_values(&self, a: &Value, b: &Value) -> std::cmp::Ordering {
-        match (a, b) {
-            (Value::Integer(a), Value::Integer(b)) => a.cmp(b),
-            (Value::Float(a), Value::Float(b)) => a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal),
-            (Value::Integer(a), Value::Float(b)) => (*a as f64).partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal),
-            (Value::Float(a), Value::Integer(b)) => a.partial_cmp(&(*b as f64)).unwrap_or(std::cmp::Ordering::Equal),
-            (Value::String(a), Value::String(b)) => a.cmp(b),
-            (Value::Boolean(a), Value::Boolean(b)) => a.cmp(b),
-            _ => std::cmp::Ordering::Equal,
-        }
-    }
-
-    // ========================================
-    // String Methods
-    // ========================================
-
-    /// .upper() - converts string to uppercase
-    fn method_upper(&self, object: &Value) -> Result<Value, EvalError> {
-        match object {
-            Value::String(s) => Ok(Value::String(s.to_uppercase())),
-            _ => Err(EvalError::TypeError(format!(
-                ".upper() requires string, got {}",
-                type_name(object)
-            ))),
-        }
+        let n = self.count_arg("sample", args, ctx)?;
+        Ok(Value::Array(self.rng.sample(arr, n).into_iter().cloned().collect()))
     }
 
-    /// .lower() - converts string to lowercase
-    fn method_lower(&self, object: &Value) -> Result<Value, EvalError> {
+    /// .shuffle() - returns the elements in random order
+    fn method_shuffle(&self, object: &Value) -> Result<Value, EvalError> {
         match object {
-            Value::String(s) => Ok(Value::String(s.to_lowercase())),
+            Value::Array(arr) => {
+                let mut shuffled = arr.clone();
+                self.rng.shuffle(&mut shuffled);
+                Ok(Value::Array(shuffled))
+            }
             _ => Err(EvalError::TypeError(format!(
-                ".lower() requires string, got {}",
+                ".shuffle() requires array, got {}",
                 type_name(object)
             ))),
         }
     }
 
-    /// .contains(substring) - returns true if string contains substring
-    fn method_contains(
+    /// .skip(n) - returns all but the first n elements
+    fn method_skip(
         &self,
         object: &Value,
         args: &[Expr],
         ctx: &EvalContext,
     ) -> Result<Value, EvalError> {
-        let s = match object {
-            Value::String(s) => s,
+        let arr = match object {
+            Value::Array(arr) => arr,
             _ => {
                 return Err(EvalError::TypeError(format!(
-                    ".contains() requires string, got {}",
+                    ".skip() requires array, got {}",
                     type_name(object)
                 )))
             }
         };
 
-        if args.is_empty() {
-            return Err(EvalError::TypeError(
-                ".contains() requires a substring argument".to_string(),
-            ));
-        }
-
-        let substr = self.eval_expr(&args[0], ctx)?;
-        match substr {
-            Value::String(sub) => Ok(Value::Boolean(s.contains(&sub))),
-            _ => Err(EvalError::TypeError(format!(
-                ".contains() argument must be string, got {}",
-                type_name(&substr)
-            ))),
-        }
+        let n = self.count_arg("skip", args, ctx)?;
+        Ok(Value::Array(arr.iter().skip(n).cloned().collect()))
     }
 
-    /// .startswith(prefix) - returns true if string starts with prefix
-    fn method_startswith(
+    /// .find(lambda) - returns the first element that matches, or null
+    fn method_find(
         &self,
         object: &Value,
         args: &[Expr],
         ctx: &EvalContext,
     ) -> Result<Value, EvalError> {
-        let s = match object {
-            Value::String(s) => s,
-            _ => {
-                return Err(EvalError::TypeError(format!(
-                    ".startswith() requires string, got {}",
-                    type_name(object)
-                )))
-            }
-        };
-
-        if args.is_empty() {
-            return Err(EvalError::TypeError(
-                ".startswith() requires a prefix argument".to_string(),
-            ));
-        }
+        Ok(match self.find_position(".find()", object, args, ctx)? {
+            Some((_, item)) => item.clone(),
+            None => Value::Null,
+        })
+    }
 
-        let prefix = self.eval_expr(&args[0], ctx)?;
-        match prefix {
-            Value::String(p) => Ok(Value::Boolean(s.starts_with(&p))),
-            _ => Err(EvalError::TypeError(format!(
-                ".startswith() argument must be string, got {}",
-                type_name(&prefix)
-            ))),
-        }
+    /// .find_index(lambda) - returns the index of the first element that
+    /// matches, or null
+    fn method_find_index(
+        &self,
+        object: &Value,
+        args: &[Expr],
+        ctx: &EvalContext,
+    ) -> Result<Value, EvalError> {
+        Ok(match self.find_position(".find_index()", object, args, ctx)? {
+            Some((index, _)) => Value::Integer(index as i64),
+            None => Value::Null,
+        })
     }
 
-    /// .endswith(suffix) - returns true if string ends with suffix
-    fn method_endswith(
+    /// .index_of(value) / .last_index_of(value) - returns the index of the
+    /// first / last element equal to value, or null
+    fn method_index_of(
         &self,
+        name: &str,
         object: &Value,
         args: &[Expr],
         ctx: &EvalContext,
     ) -> Result<Value, EvalError> {
-        let s = match object {
-            Value::String(s) => s,
+        let arr = match object {
+            Value::Array(arr) => arr,
             _ => {
                 return Err(EvalError::TypeError(format!(
-                    ".endswith() requires string, got {}",
+                    ".{}() requires array, got {}",
+                    name,
                     type_name(object)
                 )))
             }
         };
 
-        if args.is_empty() {
-            return Err(EvalError::TypeError(
-                ".endswith() requires a suffix argument".to_string(),
-            ));
-        }
+        let [arg] = args else {
+            return Err(EvalError::TypeError(format!(
+                ".{}() requires a value argument",
+                name
+            )));
+        };
 
-        let suffix = self.eval_expr(&args[0], ctx)?;
-        match suffix {
-            Value::String(suf) => Ok(Value::Boolean(s.ends_with(&suf))),
-            _ => Err(EvalError::TypeError(format!(
-                ".endswith() argument must be string, got {}",
-                type_name(&suffix)
-            ))),
-        }
+        let needle = self.eval_expr(arg, ctx)?;
+        let mut positions = arr.iter().enumerate();
+        let found = if name == "last_index_of" {
+            positions.rfind(|(_, item)| item.equals(&needle))
+        } else {
+            positions.find(|(_, item)| item.equals(&needle))
+        };
+
+        Ok(found.map_or(Value::Null, |(index, _)| Value::Integer(index as i64)))
     }
 
-    /// .matches(pattern) - returns true if string matches regex pattern
-    fn method_matches(
+    /// The first element of `object` matching the predicate in `args`, and
+    /// its index; elements after it are not evaluated
+    fn find_position<'v>(
         &self,
-        object: &Value,
-        args: &[Expr],
-        ctx: &EvalContext,
-    ) -> Result<Value, EvalError> {
-        if args.len() != 1 {
-            return Err(EvalError::TypeError(
-                ".matches() requires exactly one argument".to_string(),
-            ));
-        }
-        let pattern_val = self.eval_expr(&args[0], ctx)?;
-        let pattern_str = match &pattern_val {
-            Value::String(s) => s.as_str(),
+        name: &str,
+        object: &'v Value,
+        args: &[Expr],
+        ctx: &EvalContext,
+    ) -> Result<Option<(usize, &'v Value)>, EvalError> {
+        let arr = match object {
+            Value::Array(arr) => arr,
             _ => {
                 return Err(EvalError::TypeError(format!(
-                    ".matches() argument must be string, got {}",
-                    type_name(&pattern_val)
+                    "{} requires array, got {}",
+                    name,
+                    type_name(object)
                 )))
             }
         };
-        let re = regex::Regex::new(pattern_str)
-            .map_err(|e| EvalError::TypeError(format!("invalid regex: {e}")))?;
-        match object {
-            Value::String(s) => Ok(Value::Boolean(re.is_match(s))),
-            _ => Ok(Value::Boolean(false)),
-        }
-    }
-
-    // ========================================
-    // Type Method
-    // ========================================
 
-    /// .type() - returns the type name as a string
-    fn method_type(&self, object: &Value) -> Result<Value, EvalError> {
-        let type_name = match object {
-            Value::Null => "null",
-            Value::Boolean(_) => "boolean",
-            Value::Integer(_) => "number",
-            Value::Float(_) => "number",
-            Value::String(_) => "string",
-            Value::Array(_) => "array",
-            Value::Object(_) => "object",
+        let [predicate] = args else {
+            return Err(EvalError::TypeError(format!(
+                "{} requires a predicate argument",
+                name
+            )));
         };
-        Ok(Value::String(type_name.to_string()))
-    }
-
-    // ========================================
-    // Additional Array Methods
-    // ========================================
 
-    /// .length() - returns length of array or string
-    fn method_length(&self, object: &Value) -> Result<Value, EvalError> {
-        match object {
-            Value::Array(arr) => Ok(Value::Integer(arr.len() as i64)),
-            Value::String(s) => Ok(Value::Integer(s.chars().count() as i64)),
-            _ => Err(EvalError::TypeError(format!(
-                ".length() requires array or string, got {}",
-                type_name(object)
-            ))),
+        for (index, item) in arr.iter().enumerate() {
+            let lambda_ctx = ctx.with_lambda(item.clone());
+            if self.eval_expr(predicate, &lambda_ctx)?.as_bool() {
+                return Ok(Some((index, item)));
+            }
         }
+
+        Ok(None)
     }
 
-    /// .min() - returns minimum value in array
-    fn method_min(&self, object: &Value) -> Result<Value, EvalError> {
+    /// .take_while(lambda) - returns the leading elements that match
+    fn method_take_while(
+        &self,
+        object: &Value,
+        args: &[Expr],
+        ctx: &EvalContext,
+    ) -> Result<Value, EvalError> {
         let arr = match object {
             Value::Array(arr) => arr,
             _ => {
                 return Err(EvalError::TypeError(format!(
-                    ".min() requires array, got {}",
+                    ".take_while() requires array, got {}",
                     type_name(object)
                 )))
             }
         };
 
-        if arr.is_empty() {
-            return Ok(Value::Null);
+        if args.is_empty() {
+            return Err(EvalError::TypeError(
+                ".take_while() requires a predicate argument".to_string(),
+            ));
         }
 
-        let mut min: Option<&Value> = None;
+        let mut result = Vec::new();
         for item in arr {
-            match (min, item) {
-                (None, v) => min = Some(v),
-                (Some(Value::Integer(a)), Value::Integer(b)) if b < a => min = Some(item),
-                (Some(Value::Float(a)), Value::Float(b)) if b < a => min = Some(item),
-                (Some(Value::Integer(a)), Value::Float(b)) if *b < (*a as f64) => min = Some(item),
-                (Some(Value::Float(a)), Value::Integer(b)) if (*b as f64) < *a => min = Some(item),
-                (Some(Value::String(a)), Value::String(b)) if b < a => min = Some(item),
-                _ => {}
+            let lambda_ctx = ctx.with_lambda(item.clone());
+            if !self.eval_expr(&args[0], &lambda_ctx)?.as_bool() {
+                break;
             }
+            result.push(item.clone());
         }
 
-        Ok(min.cloned().unwrap_or(Value::Null))
+        Ok(Value::Array(result))
     }
 
-    /// .max() - returns maximum value in array
-    fn method_max(&self, object: &Value) -> Result<Value, EvalError> {
+    /// .skip_while(lambda) - drops the leading elements that match
+    fn method_skip_while(
+        &self,
+        object: &Value,
+        args: &[Expr],
+        ctx: &EvalContext,
+    ) -> Result<Value, EvalError> {
         let arr = match object {
             Value::Array(arr) => arr,
             _ => {
                 return Err(EvalError::TypeError(format!(
-                    ".max() requires array, got {}",
+                    ".skip_while() requires array, got {}",
                     type_name(object)
                 )))
             }
         };
 
-        if arr.is_empty() {
-            return Ok(Value::Null);
+        if args.is_empty() {
+            return Err(EvalError::TypeError(
+                ".skip_while() requires a predicate argument".to_string(),
+            ));
         }
 
-        let mut max: Option<&Value> = None;
-        for item in arr {
-            match (max, item) {
-                (None, v) => max = Some(v),
-                (Some(Value::Integer(a)), Value::Integer(b)) if b > a => max = Some(item),
-                (Some(Value::Float(a)), Value::Float(b)) if b > a => max = Some(item),
-                (Some(Value::Integer(a)), Value::Float(b)) if *b > (*a as f64) => max = Some(item),
-                (Some(Value::Float(a)), Value::Integer(b)) if (*b as f64) > *a => max = Some(item),
-                (Some(Value::String(a)), Value::String(b)) if b > a => max = Some(item),
-                _ => {}
+        let mut start = arr.len();
+        for (i, item) in arr.iter().enumerate() {
+            let lambda_ctx = ctx.with_lambda(item.clone());
+            if !self.eval_expr(&args[0], &lambda_ctx)?.as_bool() {
+                start = i;
+                break;
             }
         }
 
-        Ok(max.cloned().unwrap_or(Value::Null))
+        Ok(Value::Array(arr[start..].to_vec()))
     }
 
-    /// .avg() - returns average of numeric values in array
-    fn method_avg(&self, object: &Value) -> Result<Value, EvalError> {
+    /// .chunk(n) - splits array into consecutive arrays of n elements (last may be shorter)
+    fn method_chunk(
+        &self,
+        object: &Value,
+        args: &[Expr],
+        ctx: &EvalContext,
+    ) -> Result<Value, EvalError> {
         let arr = match object {
             Value::Array(arr) => arr,
             _ => {
                 return Err(EvalError::TypeError(format!(
-                    ".avg() requires array, got {}",
+                    ".chunk() requires array, got {}",
                     type_name(object)
                 )))
             }
         };
 
-        if arr.is_empty() {
-            return Ok(Value::Null);
-        }
-
-        let mut sum: f64 = 0.0;
-        let mut count: usize = 0;
-
-        for item in arr {
-            match item {
-                Value::Integer(n) => {
-                    sum += *n as f64;
-                    count += 1;
-                }
-                Value::Float(n) => {
-                    sum += n;
-                    count += 1;
-                }
-                _ => {}
-            }
-        }
-
-        if count == 0 {
-            return Ok(Value::Null);
+        let n = self.count_arg("chunk", args, ctx)?;
+        if n == 0 {
+            return Err(EvalError::TypeError(
+                ".chunk() size must be at least 1".to_string(),
+            ));
         }
 
-        Ok(Value::Float(sum / count as f64))
+        let chunks = arr.chunks(n).map(|c| Value::Array(c.to_vec())).collect();
+        Ok(Value::Array(chunks))
     }
 
-    /// .sort_desc() - sorts array in descending order
-    fn method_sort_desc(&self, object: &Value) -> Result<Value, EvalError> {
+    /// .window(n) - returns all overlapping windows of n consecutive elements
+    fn method_window(
+        &self,
+        object: &Value,
+        args: &[Expr],
+        ctx: &EvalContext,
+    ) -> Result<Value, EvalError> {
         let arr = match object {
             Value::Array(arr) => arr,
             _ => {
                 return Err(EvalError::TypeError(format!(
-                    ".sort_desc() requires array, got {}",
+                    ".window() requires array, got {}",
                     type_name(object)
                 )))
             }
         };
 
-        let mut sorted = arr.clone();
-        sorted.sort_by(|a, b| {
-            match (a, b) {
-                (Value::Integer(x), Value::Integer(y)) => y.cmp(x),
-                (Value::Float(x), Value::Float(y)) => y.partial_cmp(x).unwrap_or(std::cmp::Ordering::Equal),
-                (Value::Integer(x), Value::Float(y)) => y.partial_cmp(&(*x as f64)).unwrap_or(std::cmp::Ordering::Equal),
-                (Value::Float(x), Value::Integer(y)) => (*y as f64).partial_cmp(x).unwrap_or(std::cmp::Ordering::Equal),
-                (Value::String(x), Value::String(y)) => y.cmp(x),
-                _ => std::cmp::Ordering::Equal
-            }
-        });
+        let n = self.count_arg("window", args, ctx)?;
+        if n == 0 {
+            return Err(EvalError::TypeError(
+                ".window() size must be at least 1".to_string(),
+            ));
+        }
 
-        Ok(Value::Array(sorted))
+        let windows = arr.windows(n).map(|w| Value::Array(w.to_vec())).collect();
+        Ok(Value::Array(windows))
     }
 
-    /// .reverse() - reverses array order
-    fn method_reverse(&self, object: &Value) -> Result<Value, EvalError> {
+    /// .bucket_by_time(lambda, window) - groups elements by the time window
+    /// their timestamp falls in, as `{start, end, count, items}` in time
+    /// order. Windows are aligned to the epoch, so "1h" starts on the hour.
+    fn method_bucket_by_time(
+        &self,
+        object: &Value,
+        args: &[Expr],
+        ctx: &EvalContext,
+    ) -> Result<Value, EvalError> {
         let arr = match object {
             Value::Array(arr) => arr,
             _ => {
                 return Err(EvalError::TypeError(format!(
-                    ".reverse() requires array, got {}",
+                    ".bucket_by_time() requires array, got {}",
                     type_name(object)
                 )))
             }
         };
+        let [key, window] = args else {
+            return Err(EvalError::TypeError(
+                ".bucket_by_time() requires a timestamp expression and a window, e.g. .bucket_by_time(@[ts], \"5m\")".to_string(),
+            ));
+        };
+        let window = self.duration_arg("bucket_by_time", window, ctx)?;
 
-        let mut reversed = arr.clone();
-        reversed.reverse();
-        Ok(Value::Array(reversed))
+        let mut buckets: BTreeMap<i64, Vec<Value>> = BTreeMap::new();
+        for item in arr {
+            let ts = self.eval_expr(key, &ctx.with_lambda(item.clone()))?;
+            let millis = timestamp_arg("bucket_by_time", &ts)?;
+            buckets
+                .entry(millis.div_euclid(window) * window)
+                .or_default()
+                .push(item.clone());
+        }
+
+        let buckets = buckets
+            .into_iter()
+            .map(|(start, items)| {
+                Value::object([
+                    ("start", Value::String(time::format_millis(start))),
+                    ("end", Value::String(time::format_millis(start + window))),
+                    ("count", Value::Integer(items.len() as i64)),
+                    ("items", Value::Array(items)),
+                ])
+            })
+            .collect();
+        Ok(Value::Array(buckets))
     }
 
-    /// .flatten() - flattens nested arrays one level
-    fn method_flatten(&self, object: &Value) -> Result<Value, EvalError> {
+    /// .rate_per(interval) - counts an array of timestamps per interval, as
+    /// `{start, count}` for every interval from the earliest timestamp to the
+    /// latest, including those with none
+    fn method_rate_per(
+        &self,
+        object: &Value,
+        args: &[Expr],
+        ctx: &EvalContext,
+    ) -> Result<Value, EvalError> {
         let arr = match object {
             Value::Array(arr) => arr,
             _ => {
                 return Err(EvalError::TypeError(format!(
-                    ".flatten() requires array, got {}",
+                    ".rate_per() requires array, got {}",
                     type_name(object)
                 )))
             }
         };
+        let [interval] = args else {
+            return Err(EvalError::TypeError(
+                ".rate_per() requires exactly one argument".to_string(),
+            ));
+        };
+        let interval = self.duration_arg("rate_per", interval, ctx)?;
+
+        let starts = arr
+            .iter()
+            .map(|ts| Ok(timestamp_arg("rate_per", ts)?.div_euclid(interval)))
+            .collect::<Result<Vec<i64>, EvalError>>()?;
+        let (Some(&first), Some(&last)) = (starts.iter().min(), starts.iter().max()) else {
+            return Ok(Value::Array(Vec::new()));
+        };
+        if last - first >= MAX_RATE_INTERVALS {
+            return Err(EvalError::TypeError(format!(
+                ".rate_per() would produce {} intervals (at most {}); use a longer interval",
+                last - first + 1,
+                MAX_RATE_INTERVALS
+            )));
+        }
 
-        let mut result = Vec::new();
-        for item in arr {
-            match item {
-                Value::Array(inner) => result.extend(inner.clone()),
-                other => result.push(other.clone()),
-            }
+        let mut counts = vec![0i64; (last - first + 1) as usize];
+        for start in starts {
+            counts[(start - first) as usize] += 1;
         }
+        let rates = counts
+            .into_iter()
+            .zip(first..)
+            .map(|(count, start)| {
+                Value::object([
+                    ("start", Value::String(time::format_millis(start * interval))),
+                    ("count", Value::Integer(count)),
+                ])
+            })
+            .collect();
+        Ok(Value::Array(rates))
+    }
 
-        Ok(Value::Array(result))
+    /// The window of `.bucket_by_time()` or interval of `.rate_per()`, in
+    /// milliseconds
+    fn duration_arg(&self, method: &str, arg: &Expr, ctx: &EvalContext) -> Result<i64, EvalError> {
+        let value = self.eval_expr(arg, ctx)?;
+        if let Value::String(s) = &value
+            && let Some(millis) = time::duration_millis(s)
+        {
+            return Ok(millis);
+        }
+        let got = match &value {
+            Value::String(s) => format!("\"{}\"", s),
+            other => type_name(other).to_string(),
+        };
+        Err(EvalError::TypeError(format!(
+            ".{}() interval must be a duration such as \"30s\", \"5m\", \"1h\" or \"1d\", got {}",
+            method, got
+        )))
     }
 
     // ========================================
@@ -1626,16 +4891,34 @@ impl Evaluator {
         }
     }
 
+    /// .lines() / .words() / .chars() - splits a string into an array of
+    /// its lines, whitespace-separated words, or characters
+    fn method_split_into(&self, name: &str, object: &Value) -> Result<Value, EvalError> {
+        let Value::String(s) = object else {
+            return Err(EvalError::TypeError(format!(
+                ".{}() requires string, got {}",
+                name,
+                type_name(object)
+            )));
+        };
+        Ok(Value::Array(match name {
+            "lines" => s.lines().map(Value::from).collect(),
+            "words" => s.split_whitespace().map(Value::from).collect(),
+            _ => s.chars().map(|c| Value::String(c.into())).collect(),
+        }))
+    }
+
     // ========================================
     // Object Methods
     // ========================================
 
-    /// .keys() - returns array of object keys
+    /// .keys() / .sorted_keys() - returns array of object keys in sorted order
     fn method_keys(&self, object: &Value) -> Result<Value, EvalError> {
         match object {
             Value::Object(obj) => {
-                let keys: Vec<Value> = obj.keys().map(|k| Value::String(k.clone())).collect();
-                Ok(Value::Array(keys))
+                let mut keys: Vec<&String> = obj.keys().collect();
+                keys.sort();
+                Ok(Value::Array(keys.into_iter().map(|k| Value::String(k.clone())).collect()))
             }
             _ => Err(EvalError::TypeError(format!(
                 ".keys() requires object, got {}",
@@ -1644,12 +4927,13 @@ impl Evaluator {
         }
     }
 
-    /// .values() - returns array of object values
+    /// .values() - returns array of object values, ordered by key
     fn method_values(&self, object: &Value) -> Result<Value, EvalError> {
         match object {
             Value::Object(obj) => {
-                let values: Vec<Value> = obj.values().cloned().collect();
-                Ok(Value::Array(values))
+                let mut entries: Vec<(&String, &Value)> = obj.iter().collect();
+                entries.sort_by(|a, b| a.0.cmp(b.0));
+                Ok(Value::Array(entries.into_iter().map(|(_, v)| v.clone()).collect()))
             }
             _ => Err(EvalError::TypeError(format!(
                 ".values() requires object, got {}",
@@ -1657,4 +4941,210 @@ impl Evaluator {
             ))),
         }
     }
+
+    /// .has(key) - returns true if an object has the key or an array has the index
+    fn method_has(
+        &self,
+        object: &Value,
+        args: &[Expr],
+        ctx: &EvalContext,
+    ) -> Result<Value, EvalError> {
+        if args.is_empty() {
+            return Err(EvalError::TypeError(
+                ".has() requires a key argument".to_string(),
+            ));
+        }
+
+        let key = self.eval_expr(&args[0], ctx)?;
+        match (object, &key) {
+            (Value::Object(obj), Value::String(k)) => Ok(Value::Boolean(obj.contains_key(k))),
+            (Value::Array(arr), Value::Integer(i)) => {
+                let len = arr.len() as i64;
+                Ok(Value::Boolean((-len..len).contains(i)))
+            }
+            (Value::Object(_), _) => Err(EvalError::TypeError(format!(
+                ".has() on object requires string key, got {}",
+                type_name(&key)
+            ))),
+            (Value::Array(_), _) => Err(EvalError::TypeError(format!(
+                ".has() on array requires integer index, got {}",
+                type_name(&key)
+            ))),
+            _ => Err(EvalError::TypeError(format!(
+                ".has() requires object or array, got {}",
+                type_name(object)
+            ))),
+        }
+    }
+
+    /// .has_path(keys...) - returns true if following the keys from the
+    /// value reaches an existing entry, even when it is null
+    fn method_has_path(
+        &self,
+        object: &Value,
+        args: &[Expr],
+        ctx: &EvalContext,
+    ) -> Result<Value, EvalError> {
+        if args.is_empty() {
+            return Err(EvalError::TypeError(
+                ".has_path() requires at least one key argument".to_string(),
+            ));
+        }
+
+        let mut current = Some(object);
+        for arg in args {
+            let key = self.eval_expr(arg, ctx)?;
+            // A missing intermediate only means the path is absent, but a
+            // key that can never name an entry is a mistake in the query
+            current = match (current, &key) {
+                (Some(Value::Object(obj)), Value::String(k)) => obj.get(k),
+                (Some(Value::Array(arr)), Value::Integer(i)) => {
+                    let len = arr.len() as i64;
+                    let index = if *i < 0 { len + i } else { *i };
+                    usize::try_from(index).ok().and_then(|index| arr.get(index))
+                }
+                (_, Value::String(_) | Value::Integer(_)) => None,
+                _ => {
+                    return Err(EvalError::TypeError(format!(
+                        ".has_path() requires string keys or integer indices, got {}",
+                        type_name(&key)
+                    )))
+                }
+            };
+        }
+
+        Ok(Value::Boolean(current.is_some()))
+    }
+
+    /// .get_path(path) - the value at a path given as a string such as
+    /// "items.0.price" (see [`parse_key_path`]) or as an array of keys and
+    /// indices like .paths() returns; null when the path is missing
+    fn method_get_path(
+        &self,
+        object: &Value,
+        args: &[Expr],
+        ctx: &EvalContext,
+    ) -> Result<Value, EvalError> {
+        if args.len() != 1 {
+            return Err(EvalError::TypeError(
+                ".get_path() requires exactly one argument".to_string(),
+            ));
+        }
+
+        let path = match self.eval_expr(&args[0], ctx)? {
+            Value::String(path) => parse_key_path(&path)?,
+            Value::Array(keys) => keys
+                .iter()
+                .map(|key| match key {
+                    Value::String(k) => Ok(PathSegment::Field(k.clone())),
+                    Value::Integer(i) => Ok(PathSegment::Index(*i)),
+                    other => Err(EvalError::TypeError(format!(
+                        ".get_path() requires string keys or integer indices, got {}",
+                        type_name(other)
+                    ))),
+                })
+                .collect::<Result<_, _>>()?,
+            other => {
+                return Err(EvalError::TypeError(format!(
+                    ".get_path() requires a path string or an array of keys, got {}",
+                    type_name(&other)
+                )))
+            }
+        };
+        Ok(select_path(object, &path))
+    }
+
+    // ========================================
+    // Tree Methods
+    // ========================================
+
+    /// .walk(lambda) - rebuilds the value bottom-up, replacing every node
+    /// with the lambda applied to it once its children have been replaced.
+    /// Uses an explicit stack, so deep documents cannot overflow it.
+    fn method_walk(
+        &self,
+        object: &Value,
+        args: &[Expr],
+        ctx: &EvalContext,
+    ) -> Result<Value, EvalError> {
+        let transform = match args {
+            [transform] => transform,
+            _ => {
+                return Err(EvalError::TypeError(
+                    ".walk() requires a transform expression argument".to_string(),
+                ))
+            }
+        };
+
+        enum Step {
+            Visit(Value),
+            BuildArray(usize),
+            BuildObject(Vec<String>),
+        }
+
+        // One context for every node, so the root is not cloned per node
+        let mut item_ctx = ctx.with_lambda(Value::Null);
+        let mut steps = vec![Step::Visit(object.clone())];
+        let mut done: Vec<Value> = Vec::new();
+        while let Some(step) = steps.pop() {
+            let node = match step {
+                Step::Visit(Value::Array(items)) => {
+                    steps.push(Step::BuildArray(items.len()));
+                    steps.extend(items.into_iter().rev().map(Step::Visit));
+                    continue;
+                }
+                Step::Visit(Value::Object(obj)) => {
+                    // Children are visited in key order, as .keys() lists them
+                    let mut entries: Vec<(String, Value)> = obj.into_iter().collect();
+                    entries.sort_by(|a, b| a.0.cmp(&b.0));
+                    let (keys, values): (Vec<_>, Vec<_>) = entries.into_iter().unzip();
+                    steps.push(Step::BuildObject(keys));
+                    steps.extend(values.into_iter().rev().map(Step::Visit));
+                    continue;
+                }
+                Step::Visit(leaf) => leaf,
+                Step::BuildArray(len) => Value::Array(done.split_off(done.len() - len)),
+                Step::BuildObject(keys) => {
+                    let values = done.split_off(done.len() - keys.len());
+                    Value::Object(keys.into_iter().zip(values).collect())
+                }
+            };
+            item_ctx.lambda = Some(node);
+            done.push(self.eval_expr(transform, &item_ctx)?);
+        }
+
+        Ok(done.pop().unwrap_or(Value::Null))
+    }
+
+    /// .paths() - returns the path to every leaf, as an array of object keys
+    /// and array indices that .has_path() accepts. Scalars and empty arrays
+    /// and objects are leaves; paths are listed depth-first, in index and
+    /// key order.
+    fn method_paths(&self, object: &Value) -> Result<Value, EvalError> {
+        let mut paths = Vec::new();
+        let mut stack: Vec<(Vec<Value>, &Value)> = vec![(Vec::new(), object)];
+        while let Some((path, node)) = stack.pop() {
+            match node {
+                Value::Array(items) if !items.is_empty() => {
+                    for (i, item) in items.iter().enumerate().rev() {
+                        let mut child = path.clone();
+                        child.push(Value::Integer(i as i64));
+                        stack.push((child, item));
+                    }
+                }
+                Value::Object(obj) if !obj.is_empty() => {
+                    let mut entries: Vec<(&String, &Value)> = obj.iter().collect();
+                    entries.sort_by(|a, b| b.0.cmp(a.0));
+                    for (key, value) in entries {
+                        let mut child = path.clone();
+                        child.push(Value::String(key.clone()));
+                        stack.push((child, value));
+                    }
+                }
+                _ => paths.push(Value::Array(path)),
+            }
+        }
+
+        Ok(Value::Array(paths))
+    }
 }
@@ -0,0 +1,217 @@
+//! C ABI for embedding clove-lang in non-Rust hosts.
+//!
+//! Enabled with the `ffi` feature, and built as a C library (`libclove`) by
+//! the `clove-ffi` package. The functions here are the whole surface a
+//! binding needs: compile a query once, evaluate it against JSON text as many
+//! times as required, and free what the library handed out.
+//!
+//! # Ownership Rules
+//!
+//! - [`clove_compile`] returns an owned `CloveQuery` handle. Release it with
+//!   [`clove_query_free`] exactly once.
+//! - [`clove_eval_json`] returns an owned, NUL-terminated JSON string. Release it
+//!   with [`clove_free_result`] exactly once. Never pass it to the host's `free()`.
+//! - [`clove_last_error`] returns a pointer *borrowed* from the library. It stays
+//!   valid until the next `clove_*` call on the same thread and must not be freed.
+//! - Input strings are borrowed for the duration of the call only and must be
+//!   valid, NUL-terminated UTF-8.
+//!
+//! # Errors
+//!
+//! Failing calls return a null pointer and record a message retrievable with
+//! [`clove_last_error`]. Errors are tracked per thread; a successful call clears
+//! the previous message.
+//!
+//! # Example (C)
+//!
+//! ```text
+//! CloveQuery *q = clove_compile("$[items].filter(@ > 1)");
+//! if (!q) { fprintf(stderr, "%s\n", clove_last_error()); return 1; }
+//!
+//! char *out = clove_eval_json(q, "{\"items\": [1, 2, 3]}");
+//! if (out) { puts(out); clove_free_result(out); }
+//!
+//! clove_query_free(q);
+//! ```
+
+use std::cell::RefCell;
+use std::ffi::{CStr, CString, c_char};
+use std::ptr;
+
+use crate::ast::{Expr, Query};
+use crate::cli::{clove_to_json, is_pipeline_query, json_to_clove};
+use crate::{Evaluator, Lexer, Parser};
+
+/// A compiled Clove query, opaque to C callers.
+///
+/// Holds either a full pipeline query or a standalone expression, using the
+/// same detection rules as `clove check`.
+pub struct CloveQuery {
+    compiled: Compiled,
+}
+
+enum Compiled {
+    Query(Query),
+    Expression(Expr),
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_last_error(msg: impl Into<String>) {
+    // Interior NUL bytes cannot be represented in a C string
+    let msg = msg.into().replace('\0', "\\0");
+    let c_msg = CString::new(msg).unwrap_or_default();
+    LAST_ERROR.with(|e| *e.borrow_mut() = Some(c_msg));
+}
+
+fn clear_last_error() {
+    LAST_ERROR.with(|e| *e.borrow_mut() = None);
+}
+
+/// Borrow a C string argument as `&str`, recording an error on failure.
+///
+/// # Safety
+///
+/// `s` must be null or point to a valid NUL-terminated string.
+unsafe fn borrow_str<'a>(s: *const c_char, what: &str) -> Option<&'a str> {
+    if s.is_null() {
+        set_last_error(format!("{} is null", what));
+        return None;
+    }
+    // SAFETY: caller guarantees `s` is a valid NUL-terminated string
+    match unsafe { CStr::from_ptr(s) }.to_str() {
+        Ok(s) => Some(s),
+        Err(_) => {
+            set_last_error(format!("{} is not valid UTF-8", what));
+            None
+        }
+    }
+}
+
+fn compile(query: &str) -> Result<Compiled, String> {
+    let mut parser = Parser::new(Lexer::new(query)).map_err(|e| format!("Parse error: {}", e))?;
+    let compiled = if is_pipeline_query(query) {
+        parser.parse_query().map(Compiled::Query)
+    } else {
+        parser.parse().map(Compiled::Expression)
+    };
+    compiled.map_err(|e| format!("Parse error: {}", e))
+}
+
+fn eval(query: &CloveQuery, json: &str) -> Result<String, String> {
+    let json_value: serde_json::Value =
+        serde_json::from_str(json).map_err(|e| format!("Invalid JSON: {}", e))?;
+    let input = json_to_clove(json_value);
+
+    let mut evaluator = Evaluator::new();
+    let result = match &query.compiled {
+        Compiled::Query(q) => evaluator.eval_query(q, input),
+        Compiled::Expression(expr) => evaluator.eval_expression(expr, input),
+    }
+    .map_err(|e| format!("Evaluation error: {}", e))?;
+
+    serde_json::to_string(&clove_to_json(result)).map_err(|e| format!("Invalid JSON: {}", e))
+}
+
+/// Compile a query string into a reusable handle.
+///
+/// Returns null on failure; see [`clove_last_error`].
+///
+/// # Safety
+///
+/// `query` must be null or a valid NUL-terminated string.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn clove_compile(query: *const c_char) -> *mut CloveQuery {
+    // SAFETY: forwarded from the caller's contract
+    let Some(query) = (unsafe { borrow_str(query, "query") }) else {
+        return ptr::null_mut();
+    };
+
+    match compile(query) {
+        Ok(compiled) => {
+            clear_last_error();
+            Box::into_raw(Box::new(CloveQuery { compiled }))
+        }
+        Err(msg) => {
+            set_last_error(msg);
+            ptr::null_mut()
+        }
+    }
+}
+
+/// Evaluate a compiled query against a JSON document.
+///
+/// Returns the result as compact JSON, or null on failure; see
+/// [`clove_last_error`]. The returned string must be released with
+/// [`clove_free_result`].
+///
+/// # Safety
+///
+/// `query` must be null or a handle returned by [`clove_compile`] that has not
+/// been freed. `json` must be null or a valid NUL-terminated string.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn clove_eval_json(
+    query: *const CloveQuery,
+    json: *const c_char,
+) -> *mut c_char {
+    if query.is_null() {
+        set_last_error("query handle is null");
+        return ptr::null_mut();
+    }
+    // SAFETY: caller guarantees `query` is a live handle from `clove_compile`
+    let query = unsafe { &*query };
+    // SAFETY: forwarded from the caller's contract
+    let Some(json) = (unsafe { borrow_str(json, "json") }) else {
+        return ptr::null_mut();
+    };
+
+    match eval(query, json).and_then(|out| CString::new(out).map_err(|e| e.to_string())) {
+        Ok(out) => {
+            clear_last_error();
+            out.into_raw()
+        }
+        Err(msg) => {
+            set_last_error(msg);
+            ptr::null_mut()
+        }
+    }
+}
+
+/// Release a string returned by [`clove_eval_json`]. Null is a no-op.
+///
+/// # Safety
+///
+/// `result` must be null or a pointer returned by [`clove_eval_json`] that has
+/// not already been freed.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn clove_free_result(result: *mut c_char) {
+    if !result.is_null() {
+        // SAFETY: caller guarantees `result` came from `CString::into_raw`
+        drop(unsafe { CString::from_raw(result) });
+    }
+}
+
+/// Release a handle returned by [`clove_compile`]. Null is a no-op.
+///
+/// # Safety
+///
+/// `query` must be null or a handle returned by [`clove_compile`] that has not
+/// already been freed.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn clove_query_free(query: *mut CloveQuery) {
+    if !query.is_null() {
+        // SAFETY: caller guarantees `query` came from `Box::into_raw`
+        drop(unsafe { Box::from_raw(query) });
+    }
+}
+
+/// Message describing the most recent failure on this thread, or null.
+///
+/// The pointer is borrowed: it remains valid until the next `clove_*` call on
+/// the same thread and must not be freed.
+#[unsafe(no_mangle)]
+pub extern "C" fn clove_last_error() -> *const c_char {
+    LAST_ERROR.with(|e| e.borrow().as_ref().map_or(ptr::null(), |s| s.as_ptr()))
+}
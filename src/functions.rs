@@ -0,0 +1,147 @@
+//! Functions supplied by the host application.
+//!
+//! A [`FunctionRegistry`] holds native callbacks that queries call as
+//! `ext::name(args)`, e.g. `ext::lookup_user($[id])`. Unlike
+//! [host methods](crate::methods), they may have side effects, such as a
+//! database lookup or an HTTP request, so clove never second-guesses them:
+//!
+//! - A call runs each time evaluation reaches it, in evaluation order, and
+//!   never otherwise. Results are not cached or computed ahead of time,
+//!   and the optimizer leaves calls where they are.
+//! - Only registered functions can be called; anything else is an
+//!   [`EvalError::UndefinedHostFunction`].
+//! - An error the callback returns ends evaluation like any other, unless
+//!   the query catches it with `try ... catch`.
+//!
+//! Pass the registry to [`Evaluator::with_functions`](crate::Evaluator::with_functions).
+//!
+//! # Async functions
+//!
+//! Evaluation is synchronous. [`FunctionRegistry::register_async`] takes a
+//! function returning a future and runs it to completion with [`block_on`]
+//! on the evaluating thread. Futures that need a particular runtime, such
+//! as tokio's I/O types, should instead be registered with
+//! [`register`](FunctionRegistry::register) and handed to that runtime
+//! (for example `handle.block_on(...)` from a thread outside it).
+//!
+//! # Examples
+//!
+//! ```
+//! use clove_lang::{clove_value, Evaluator, FunctionRegistry, Lexer, Parser, Value};
+//!
+//! let mut functions = FunctionRegistry::new();
+//! functions
+//!     .register("lookup_user", |args| match args {
+//!         [Value::Integer(1)] => Ok(clove_value!({"name": "ann"})),
+//!         _ => Ok(Value::Null),
+//!     })
+//!     .unwrap();
+//!
+//! let query = Parser::new(Lexer::new("$ | ~($[user] := ext::lookup_user($[id]))")).unwrap().parse_query().unwrap();
+//! let mut evaluator = Evaluator::new().with_functions(functions);
+//! let result = evaluator.eval_query(&query, clove_value!({"id": 1, "user": null})).unwrap();
+//! assert_eq!(result, clove_value!({"id": 1, "user": {"name": "ann"}}));
+//! ```
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::pin;
+use std::sync::Arc;
+use std::task::{Context, Poll, Wake, Waker};
+use std::thread::{self, Thread};
+
+use crate::evaluator::EvalError;
+use crate::methods::RegistryError;
+use crate::value::Value;
+
+/// A host function: called with the evaluated arguments
+pub type HostFunction = dyn Fn(&[Value]) -> Result<Value, EvalError> + Send + Sync;
+
+/// Host functions by name, callable as `ext::name(...)`
+#[derive(Clone, Default)]
+pub struct FunctionRegistry {
+    functions: HashMap<String, Arc<HostFunction>>,
+}
+
+impl std::fmt::Debug for FunctionRegistry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut names: Vec<_> = self.names().collect();
+        names.sort_unstable();
+        f.debug_struct("FunctionRegistry").field("functions", &names).finish()
+    }
+}
+
+impl FunctionRegistry {
+    /// An empty registry
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register `function` as `ext::name(...)`.
+    ///
+    /// Fails if `name` is not lowercase letters, digits and underscores, or
+    /// is already registered.
+    pub fn register(
+        &mut self,
+        name: &str,
+        function: impl Fn(&[Value]) -> Result<Value, EvalError> + Send + Sync + 'static,
+    ) -> Result<(), RegistryError> {
+        let valid = name.starts_with(|c: char| c.is_ascii_lowercase() || c == '_')
+            && name.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_');
+        if !valid {
+            return Err(RegistryError::InvalidName(name.to_string()));
+        }
+        if self.functions.contains_key(name) {
+            return Err(RegistryError::Duplicate(name.to_string()));
+        }
+        self.functions.insert(name.to_string(), Arc::new(function));
+        Ok(())
+    }
+
+    /// Register an async function as `ext::name(...)`; each call blocks the
+    /// evaluating thread until the future completes (see [`block_on`])
+    pub fn register_async<F, Fut>(&mut self, name: &str, function: F) -> Result<(), RegistryError>
+    where
+        F: Fn(Vec<Value>) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<Value, EvalError>>,
+    {
+        self.register(name, move |args| block_on(function(args.to_vec())))
+    }
+
+    /// The function registered as `name`
+    pub fn get(&self, name: &str) -> Option<&HostFunction> {
+        self.functions.get(name).map(Arc::as_ref)
+    }
+
+    /// Names of the registered functions, in no particular order
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.functions.keys().map(String::as_str)
+    }
+
+    /// Whether no functions are registered
+    pub fn is_empty(&self) -> bool {
+        self.functions.is_empty()
+    }
+}
+
+/// Run `future` to completion on the current thread, parking it while the
+/// future waits
+pub fn block_on<F: Future>(future: F) -> F::Output {
+    struct Unpark(Thread);
+
+    impl Wake for Unpark {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    let waker = Waker::from(Arc::new(Unpark(thread::current())));
+    let mut context = Context::from_waker(&waker);
+    let mut future = pin!(future);
+    loop {
+        match future.as_mut().poll(&mut context) {
+            Poll::Ready(output) => return output,
+            Poll::Pending => thread::park(),
+        }
+    }
+}
@@ -0,0 +1,518 @@
+//! Editor support for queries: diagnostics, hover docs and completion.
+//!
+//! The analyses here know nothing of any editor protocol; the `clove-lsp`
+//! binary (built with the `lsp` feature) serves them over the Language
+//! Server Protocol. Positions are those of the [`Lexer`]: 1-based lines and
+//! columns counted in characters.
+//!
+//! - [`diagnostics`] reports every syntax error, using
+//!   [`Parser::parse_query_recovering`], and warns about calls to unknown
+//!   methods and functions, or with the wrong number of arguments, and
+//!   about object literals that repeat a key.
+//! - [`hover`] shows the `clove doc` entry for the method or operator under
+//!   the cursor.
+//! - [`completions`] offers method names after `.`, function names after
+//!   `&` and, given a sample document, field names after `[`.
+//!
+//! # Examples
+//!
+//! ```
+//! use clove_lang::ide::{completions, diagnostics, Severity};
+//! use clove_lang::clove_value;
+//!
+//! let query = "$ | ?($[items].lenght() > 0";
+//! let found = diagnostics(query);
+//! assert_eq!(found[0].severity, Severity::Error);
+//! assert_eq!(found[1].message, "Unknown method: lenght");
+//!
+//! let sample = clove_value!({"items": [], "owner": {"name": "ana"}});
+//! let fields: Vec<_> = completions("$ | !($[o", 1, 10, Some(&sample))
+//!     .into_iter()
+//!     .map(|completion| completion.label)
+//!     .collect();
+//! assert_eq!(fields, ["owner"]);
+//! ```
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use crate::ast::UDF;
+use crate::cli::{is_pipeline_query, method_doc, operator_doc};
+use crate::evaluator::{EvalError, method_names};
+use crate::lexer::{tokenize_with_spans, Lexer, Position};
+use crate::parser::{ParseError, Parser};
+use crate::transform::PathSegment;
+use crate::{Token, Value};
+
+/// How serious a [`Diagnostic`] is
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    /// The query does not parse
+    Error,
+    /// The query parses but fails when it reaches this point
+    Warning,
+}
+
+/// A problem found in a query, spanning `start` up to (not including) `end`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub start: Position,
+    pub end: Position,
+    pub message: String,
+}
+
+/// Documentation for the token spanning `start` to `end`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Hover {
+    pub contents: String,
+    pub start: Position,
+    pub end: Position,
+}
+
+/// What a [`Completion`] inserts
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompletionKind {
+    Method,
+    Function,
+    Field,
+}
+
+/// A name that can be typed at the cursor
+#[derive(Debug, Clone, PartialEq)]
+pub struct Completion {
+    pub label: String,
+    pub kind: CompletionKind,
+    /// A short description, such as a method's signature
+    pub detail: Option<String>,
+}
+
+/// Syntax errors and warnings for `source`, in source order within each
+/// kind. `%include` lines are skipped, and with them the warnings about
+/// functions the query does not define itself.
+pub fn diagnostics(source: &str) -> Vec<Diagnostic> {
+    let analysis = Analysis::new(source);
+    let mut found: Vec<Diagnostic> = analysis
+        .errors
+        .iter()
+        .map(|error| {
+            let start = error.position();
+            Diagnostic {
+                severity: Severity::Error,
+                start,
+                end: analysis.token_end_at(start),
+                message: error.message(),
+            }
+        })
+        .collect();
+
+    let tokens = &analysis.tokens;
+    for (i, spanned) in tokens.iter().enumerate() {
+        let (Some(name), Some(next)) = (tokens.get(i + 1), tokens.get(i + 2)) else {
+            break;
+        };
+        let Token::Identifier(ident) = &name.token else {
+            continue;
+        };
+        let message = match (&spanned.token, &next.token) {
+            (Token::Dot, Token::LParen) if !method_names().any(|name| name == ident) => {
+                format!("Unknown method: {}", ident)
+            }
+            (Token::Ampersand, Token::LBracket) => match analysis.function_arity(ident) {
+                Some(arity) => match count_args(&tokens[i + 3..]) {
+                    Some(count) if !arity.accepts(count) => {
+                        format!("&{} takes {} argument(s), got {}", ident, arity, count)
+                    }
+                    _ => continue,
+                },
+                None if !analysis.has_includes => {
+                    EvalError::UndefinedFunction { name: ident.clone(), candidates: Vec::new() }.to_string()
+                }
+                None => continue,
+            },
+            _ => continue,
+        };
+        found.push(Diagnostic {
+            severity: Severity::Warning,
+            start: name.start,
+            end: name.end,
+            message,
+        });
+    }
+    found.extend(duplicate_keys(tokens));
+    found[analysis.errors.len()..].sort_by_key(|diagnostic| diagnostic.start.offset);
+    found
+}
+
+/// Documentation for the method, function or operator at `line` and
+/// `column`, if there is any
+pub fn hover(source: &str, line: usize, column: usize) -> Option<Hover> {
+    let analysis = Analysis::new(source);
+    let offset = offset_at(&analysis.body, line, column);
+    let i = analysis
+        .tokens
+        .iter()
+        .position(|spanned| spanned.start.offset <= offset && offset < spanned.end.offset)?;
+    let spanned = &analysis.tokens[i];
+    let previous = i.checked_sub(1).map(|i| &analysis.tokens[i].token);
+
+    let contents = match (&spanned.token, previous) {
+        (Token::Identifier(name), Some(Token::Dot)) => {
+            let entries = method_doc(name);
+            if entries.is_empty() {
+                return None;
+            }
+            entries.join("\n\n")
+        }
+        (Token::Identifier(name), Some(Token::Ampersand)) => {
+            if let Some(arity) = analysis.udfs.get(name.as_str()) {
+                format!("&{}:{}\nDefined in this query", name, arity)
+            } else {
+                format!("{}\nBuilt-in function", signature(name, builtin_arity(name)?))
+            }
+        }
+        // `|` followed by `!(` or `-(` is an output or delete stage
+        (Token::Exclamation | Token::Minus, Some(Token::Pipe)) => return None,
+        (token, _) => operator_doc(operator_symbol(token)?)?.to_string(),
+    };
+    Some(Hover {
+        contents,
+        start: spanned.start,
+        end: spanned.end,
+    })
+}
+
+/// Names that can be typed at `line` and `column`, narrowed to those
+/// starting with any partial name just before the cursor. Field names come
+/// from `sample`, at the constant `$` path being indexed.
+pub fn completions(source: &str, line: usize, column: usize, sample: Option<&Value>) -> Vec<Completion> {
+    let analysis = Analysis::new(source);
+    let offset = offset_at(&analysis.body, line, column);
+    let before: Vec<&Spanned> = analysis
+        .tokens
+        .iter()
+        .take_while(|spanned| spanned.end.offset <= offset)
+        .collect();
+    let partial = match before.last() {
+        Some(Spanned { token: Token::Identifier(word), end, .. }) if end.offset == offset => Some(word.as_str()),
+        _ => None,
+    };
+    let context = &before[..before.len() - partial.map_or(0, |_| 1)];
+
+    let mut found: Vec<Completion> = match context.split_last() {
+        Some((last, _)) if last.token == Token::Dot => method_names()
+            .map(|name| Completion {
+                label: name.to_string(),
+                kind: CompletionKind::Method,
+                detail: method_doc(name).first().and_then(|doc| doc.lines().next()).map(str::to_string),
+            })
+            .collect(),
+        Some((last, _)) if last.token == Token::Ampersand => {
+            let mut functions: Vec<(String, Arity)> = analysis
+                .udfs
+                .iter()
+                .map(|(name, arity)| (name.to_string(), *arity))
+                .collect();
+            functions.push(("random".to_string(), Arity::Exactly(0)));
+            functions.push(("coalesce".to_string(), Arity::AtLeast(1)));
+            #[cfg(feature = "stdlib")]
+            functions.extend(
+                crate::stdlib::BUILTINS
+                    .iter()
+                    .map(|builtin| (builtin.name.to_string(), Arity::Exactly(builtin.arity))),
+            );
+            functions
+                .into_iter()
+                .map(|(name, arity)| Completion {
+                    detail: Some(signature(&name, arity)),
+                    label: name,
+                    kind: CompletionKind::Function,
+                })
+                .collect()
+        }
+        Some((last, rest)) if last.token == Token::LBracket => {
+            let fields = sample
+                .zip(constant_path(rest))
+                .and_then(|(sample, path)| sample.get_path(&path));
+            match fields {
+                Some(Value::Object(fields)) => fields
+                    .iter()
+                    .map(|(name, value)| Completion {
+                        label: name.clone(),
+                        kind: CompletionKind::Field,
+                        detail: Some(type_name(value).to_string()),
+                    })
+                    .collect(),
+                _ => Vec::new(),
+            }
+        }
+        _ => Vec::new(),
+    };
+    found.retain(|completion| completion.label.starts_with(partial.unwrap_or("")));
+    found.sort_by(|a, b| a.label.cmp(&b.label));
+    found.dedup_by(|a, b| a.label == b.label);
+    found
+}
+
+/// A token and where it lies in the query
+struct Spanned {
+    token: Token,
+    start: Position,
+    end: Position,
+}
+
+/// A query lexed and parsed as far as it goes
+struct Analysis {
+    /// The query with its `%` directive lines blanked
+    body: String,
+    has_includes: bool,
+    /// Every token, skipping characters that do not lex
+    tokens: Vec<Spanned>,
+    errors: Vec<ParseError>,
+    /// The arity of each UDF the query defines
+    udfs: HashMap<String, Arity>,
+}
+
+impl Analysis {
+    fn new(source: &str) -> Self {
+        let mut body = String::with_capacity(source.len());
+        let mut has_includes = false;
+        let mut in_header = true;
+        for line in source.split_inclusive('\n') {
+            if in_header && line.trim().starts_with('%') {
+                has_includes = true;
+                body.push_str(if line.ends_with('\n') { "\n" } else { "" });
+            } else {
+                in_header &= line.trim().is_empty();
+                body.push_str(line);
+            }
+        }
+
+        let tokens = tokenize_with_spans(&body)
+            .into_iter()
+            .filter(|(token, _)| !matches!(token, Token::Error(_)))
+            .map(|(token, span)| Spanned { token, start: span.start, end: span.end })
+            .collect();
+
+        let mut udfs = HashMap::new();
+        let errors = match Parser::new(Lexer::new(&body)) {
+            Err(error) => vec![error],
+            Ok(mut parser) if is_pipeline_query(&body) => {
+                let (query, errors) = parser.parse_query_recovering();
+                udfs.extend(query.udfs.iter().map(|udf| (udf.name.clone(), udf_arity(udf))));
+                errors
+            }
+            Ok(mut parser) => parser.parse().err().into_iter().collect(),
+        };
+
+        Analysis {
+            body,
+            has_includes,
+            tokens,
+            errors,
+            udfs,
+        }
+    }
+
+    /// The end of the token starting at `start`, or the next character if
+    /// none does
+    fn token_end_at(&self, start: Position) -> Position {
+        self.tokens
+            .iter()
+            .find(|spanned| spanned.start.offset == start.offset)
+            .map(|spanned| spanned.end)
+            .unwrap_or(Position::new(start.line, start.column + 1, start.offset + 1))
+    }
+
+    /// How many arguments `&name[...]` takes, if it is defined
+    fn function_arity(&self, name: &str) -> Option<Arity> {
+        match self.udfs.get(name) {
+            Some(arity) => Some(*arity),
+            None => builtin_arity(name),
+        }
+    }
+}
+
+/// How many arguments a function takes
+#[derive(Debug, Clone, Copy)]
+enum Arity {
+    Exactly(usize),
+    AtLeast(usize),
+    Between(usize, usize),
+}
+
+impl Arity {
+    fn accepts(self, count: usize) -> bool {
+        match self {
+            Arity::Exactly(n) => count == n,
+            Arity::AtLeast(n) => count >= n,
+            Arity::Between(min, max) => (min..=max).contains(&count),
+        }
+    }
+}
+
+impl fmt::Display for Arity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Arity::Exactly(n) => write!(f, "{}", n),
+            Arity::AtLeast(n) => write!(f, "at least {}", n),
+            Arity::Between(min, max) => write!(f, "{} to {}", min, max),
+        }
+    }
+}
+
+/// How many arguments a UDF defined in the query takes
+fn udf_arity(udf: &UDF) -> Arity {
+    match udf.max_arity() {
+        None => Arity::AtLeast(udf.min_arity()),
+        Some(max) if max > udf.min_arity() => Arity::Between(udf.min_arity(), max),
+        Some(max) => Arity::Exactly(max),
+    }
+}
+
+fn builtin_arity(name: &str) -> Option<Arity> {
+    match name {
+        "random" => return Some(Arity::Exactly(0)),
+        "coalesce" => return Some(Arity::AtLeast(1)),
+        _ => {}
+    }
+    #[cfg(feature = "stdlib")]
+    if let Some(builtin) = crate::stdlib::lookup(name) {
+        return Some(Arity::Exactly(builtin.arity));
+    }
+    None
+}
+
+/// How a function is shown in hovers and completions: `&name:arity`
+fn signature(name: &str, arity: Arity) -> String {
+    match arity {
+        Arity::Exactly(n) => format!("&{}:{}", name, n),
+        Arity::AtLeast(n) => format!("&{}[...] (at least {} argument(s))", name, n),
+        Arity::Between(min, max) => format!("&{}[...] ({} to {} argument(s))", name, min, max),
+    }
+}
+
+/// Warnings for the keys an object literal repeats, whose earlier values
+/// are silently dropped
+fn duplicate_keys(tokens: &[Spanned]) -> Vec<Diagnostic> {
+    // Per open `{`: the keys seen in it, and how many brackets inside it are open
+    let mut objects: Vec<(HashSet<&str>, usize)> = Vec::new();
+    let mut found = Vec::new();
+    for (i, spanned) in tokens.iter().enumerate() {
+        match &spanned.token {
+            Token::LBrace => objects.push((HashSet::new(), 0)),
+            Token::RBrace => {
+                objects.pop();
+            }
+            Token::LParen | Token::LBracket => {
+                if let Some((_, depth)) = objects.last_mut() {
+                    *depth += 1;
+                }
+            }
+            Token::RParen | Token::RBracket => {
+                if let Some((_, depth)) = objects.last_mut() {
+                    *depth = depth.saturating_sub(1);
+                }
+            }
+            Token::String(key) | Token::Identifier(key) => {
+                let is_key = i > 0
+                    && matches!(tokens[i - 1].token, Token::LBrace | Token::Comma)
+                    && tokens.get(i + 1).is_some_and(|next| next.token == Token::Colon);
+                if let Some((keys, 0)) = objects.last_mut()
+                    && is_key
+                    && !keys.insert(key.as_str())
+                {
+                    found.push(Diagnostic {
+                        severity: Severity::Warning,
+                        start: spanned.start,
+                        end: spanned.end,
+                        message: format!("Duplicate key \"{}\" in object literal; only the last value is kept", key),
+                    });
+                }
+            }
+            _ => {}
+        }
+    }
+    found
+}
+
+/// The number of arguments in `args...]`, or `None` if the `]` is missing
+fn count_args(tokens: &[Spanned]) -> Option<usize> {
+    let mut depth = 0usize;
+    let mut commas = 0;
+    for (i, spanned) in tokens.iter().enumerate() {
+        match spanned.token {
+            Token::LBracket | Token::LParen | Token::LBrace => depth += 1,
+            Token::RBracket if depth == 0 => return Some(if i == 0 { 0 } else { commas + 1 }),
+            Token::RBracket | Token::RParen | Token::RBrace => depth = depth.saturating_sub(1),
+            Token::Comma if depth == 0 => commas += 1,
+            _ => {}
+        }
+    }
+    None
+}
+
+/// The path of `$[a][0]...` ending `tokens`, if every key is a constant
+fn constant_path(tokens: &[&Spanned]) -> Option<Vec<PathSegment>> {
+    let mut path = Vec::new();
+    let mut rest = tokens;
+    loop {
+        match rest {
+            [.., last] if matches!(last.token, Token::Dollar | Token::DoubleDollar) => break,
+            [before @ .., open, key, close] if open.token == Token::LBracket && close.token == Token::RBracket => {
+                path.push(match &key.token {
+                    Token::Identifier(name) | Token::String(name) => PathSegment::Field(name.clone()),
+                    Token::Integer(index) => PathSegment::Index(*index),
+                    _ => return None,
+                });
+                rest = before;
+            }
+            _ => return None,
+        }
+    }
+    path.reverse();
+    Some(path)
+}
+
+/// How `token` is written in the operator docs
+fn operator_symbol(token: &Token) -> Option<&'static str> {
+    Some(match token {
+        Token::EqEq => "==",
+        Token::NotEq => "!=",
+        Token::Lt => "<",
+        Token::Gt => ">",
+        Token::LtEq => "<=",
+        Token::GtEq => ">=",
+        Token::And => "&&",
+        Token::Or => "||",
+        Token::Exclamation => "!",
+        Token::Plus => "+",
+        Token::Minus => "-",
+        Token::Star => "*",
+        Token::Slash => "/",
+        Token::Percent => "%",
+        _ => return None,
+    })
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Boolean(_) => "boolean",
+        Value::Integer(_) | Value::Float(_) => "number",
+        Value::String(_) => "string",
+        Value::Bytes(_) => "bytes",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+/// The character offset of 1-based `line` and `column` in `source`
+fn offset_at(source: &str, line: usize, column: usize) -> usize {
+    let before: usize = source
+        .split_inclusive('\n')
+        .take(line.saturating_sub(1))
+        .map(|line| line.chars().count())
+        .sum();
+    before + column.saturating_sub(1)
+}
@@ -0,0 +1,203 @@
+//! Inferring the shape of JSON documents from samples.
+//!
+//! A [`Shape`] summarizes every value observed at one position: which types
+//! occurred and how often, a few example values, the fields of objects (with
+//! whether each was present in every object) and the shape of array
+//! elements. Observing several documents merges them, so a field that only
+//! some documents have shows up as optional.
+//!
+//! # Examples
+//!
+//! ```
+//! use clove_lang::infer::Shape;
+//! use clove_lang::clove_value;
+//!
+//! let docs = [
+//!     clove_value!({"id": 1, "tags": ["a"], "note": "x"}),
+//!     clove_value!({"id": 2, "tags": []}),
+//! ];
+//! let shape = Shape::infer(&docs);
+//!
+//! assert!(shape.field("note").unwrap().optional);
+//! assert_eq!(shape.field("tags").unwrap().items.as_ref().unwrap().type_names(), ["string"]);
+//! print!("{}", shape.to_tree());
+//! // object
+//! //   id: integer  e.g. 1, 2
+//! //   note?: string  e.g. "x"
+//! //   tags: array
+//! //     []: string  e.g. "a"
+//! ```
+
+use std::collections::BTreeMap;
+
+use crate::output::to_json;
+use crate::value::Value;
+
+/// Distinct example values kept per position
+pub const MAX_EXAMPLES: usize = 3;
+
+/// Type names in the order they are reported
+const TYPE_NAMES: [&str; 8] =
+    ["null", "boolean", "integer", "float", "string", "bytes", "array", "object"];
+
+/// What was observed at one position in the sampled documents
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Shape {
+    /// Number of values observed
+    pub count: usize,
+    /// How many values had each type, indexed like the type names
+    type_counts: [usize; 8],
+    /// For a field, whether some observed object lacked it
+    pub optional: bool,
+    /// Fields of the observed objects
+    pub fields: BTreeMap<String, Shape>,
+    /// Elements of the observed arrays, if any had elements
+    pub items: Option<Box<Shape>>,
+    /// Up to [`MAX_EXAMPLES`] distinct scalar values, in order of appearance
+    pub examples: Vec<Value>,
+}
+
+impl Shape {
+    /// The shape of all of `values`
+    pub fn infer<'a>(values: impl IntoIterator<Item = &'a Value>) -> Shape {
+        let mut shape = Shape::default();
+        for value in values {
+            shape.observe(value);
+        }
+        shape
+    }
+
+    /// Merge one more value into the shape
+    pub fn observe(&mut self, value: &Value) {
+        self.count += 1;
+        self.type_counts[type_index(value)] += 1;
+        match value {
+            Value::Object(map) => {
+                // Fields missing from this object become optional
+                let objects = self.type_counts[type_index(value)];
+                for (name, field) in &mut self.fields {
+                    if !map.contains_key(name) {
+                        field.optional = true;
+                    }
+                }
+                for (name, field_value) in map {
+                    let field = self.fields.entry(name.clone()).or_default();
+                    if field.count == 0 && objects > 1 {
+                        field.optional = true;
+                    }
+                    field.observe(field_value);
+                }
+            }
+            Value::Array(items) => {
+                for item in items {
+                    self.items.get_or_insert_with(Box::default).observe(item);
+                }
+            }
+            scalar => {
+                if self.examples.len() < MAX_EXAMPLES && !self.examples.contains(scalar) {
+                    self.examples.push(scalar.clone());
+                }
+            }
+        }
+    }
+
+    /// The observed object field called `name`
+    pub fn field(&self, name: &str) -> Option<&Shape> {
+        self.fields.get(name)
+    }
+
+    /// Names of the observed types, e.g. `["integer", "null"]`
+    pub fn type_names(&self) -> Vec<&'static str> {
+        TYPE_NAMES
+            .iter()
+            .zip(self.type_counts)
+            .filter(|(_, count)| *count > 0)
+            .map(|(name, _)| *name)
+            .collect()
+    }
+
+    /// How many observed values had type `name` (as in [`type_names`](Self::type_names))
+    pub fn type_count(&self, name: &str) -> usize {
+        TYPE_NAMES
+            .iter()
+            .position(|t| *t == name)
+            .map_or(0, |i| self.type_counts[i])
+    }
+
+    /// The shape as a JSON-ready value:
+    /// `{"types": {...counts}, "count", "optional"?, "examples"?, "fields"?, "items"?}`
+    pub fn to_value(&self) -> Value {
+        let types = self
+            .type_names()
+            .into_iter()
+            .map(|name| (name, Value::from(self.type_count(name) as i64)));
+        let mut entries = vec![
+            ("count".to_string(), Value::from(self.count as i64)),
+            ("types".to_string(), Value::object(types)),
+        ];
+        if self.optional {
+            entries.push(("optional".to_string(), Value::Boolean(true)));
+        }
+        if !self.examples.is_empty() {
+            entries.push(("examples".to_string(), Value::array(self.examples.clone())));
+        }
+        if !self.fields.is_empty() {
+            let fields = self.fields.iter().map(|(name, field)| (name.clone(), field.to_value()));
+            entries.push(("fields".to_string(), Value::object(fields)));
+        }
+        if let Some(items) = &self.items {
+            entries.push(("items".to_string(), items.to_value()));
+        }
+        Value::object(entries)
+    }
+
+    /// The shape as an indented tree, one line per position:
+    /// `name?: type | type  e.g. example, example`
+    pub fn to_tree(&self) -> String {
+        let mut out = String::new();
+        self.write_tree(None, 0, &mut out);
+        out
+    }
+
+    fn write_tree(&self, label: Option<&str>, depth: usize, out: &mut String) {
+        out.push_str(&"  ".repeat(depth));
+        if let Some(label) = label {
+            out.push_str(label);
+            if self.optional {
+                out.push('?');
+            }
+            out.push_str(": ");
+        }
+        let types = self.type_names();
+        if types.is_empty() {
+            out.push_str("unknown");
+        }
+        out.push_str(&types.join(" | "));
+        if !self.examples.is_empty() {
+            let examples: Vec<String> = self.examples.iter().map(to_json).collect();
+            out.push_str("  e.g. ");
+            out.push_str(&examples.join(", "));
+        }
+        out.push('\n');
+
+        for (name, field) in &self.fields {
+            field.write_tree(Some(name), depth + 1, out);
+        }
+        if let Some(items) = &self.items {
+            items.write_tree(Some("[]"), depth + 1, out);
+        }
+    }
+}
+
+fn type_index(value: &Value) -> usize {
+    match value {
+        Value::Null => 0,
+        Value::Boolean(_) => 1,
+        Value::Integer(_) => 2,
+        Value::Float(_) => 3,
+        Value::String(_) => 4,
+        Value::Bytes(_) => 5,
+        Value::Array(_) => 6,
+        Value::Object(_) => 7,
+    }
+}
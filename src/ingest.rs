@@ -0,0 +1,496 @@
+//! Streaming JSON ingestion
+//!
+//! Builds [`Value`] trees straight from a byte stream, without first
+//! materializing an intermediate `serde_json::Value`. Two helpers keep large
+//! inputs manageable:
+//!
+//! - [`PathSelector`] prunes the document while parsing, so a query that only
+//!   reads constant paths (`$[a][b][0]`, `{"v": $[meta][version]}`) only
+//!   allocates the subtrees it reads.
+//! - [`LimitedReader`] refuses to read past a byte budget.
+//!
+//! JSON leaves it to the parser what to do with an object that repeats a
+//! key. By default the last value wins, as in most parsers;
+//! [`DuplicateKeys`] chooses another policy.
+//!
+//! # Examples
+//!
+//! ```
+//! use clove_lang::Value;
+//!
+//! let doc = Value::from_reader(r#"{"n": [1, 2.5, "x"]}"#.as_bytes()).unwrap();
+//! assert!(matches!(doc, Value::Object(_)));
+//! ```
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::io::{self, Read};
+
+use serde::de::{self, DeserializeSeed, Deserializer, IgnoredAny, MapAccess, SeqAccess, Visitor};
+
+use crate::{BinOp, Expr, Query, Statement};
+use crate::value::{Value, whole_number};
+
+impl Value {
+    /// Parse a JSON document from a reader.
+    pub fn from_reader<R: Read>(reader: R) -> Result<Value, serde_json::Error> {
+        Value::from_reader_selecting(reader, &PathSelector::default())
+    }
+
+    /// Parse every JSON document in a reader, such as the records of an
+    /// NDJSON stream or documents separated by whitespace.
+    ///
+    /// Blank lines are skipped; an empty stream gives no documents.
+    pub fn from_reader_all<R: Read>(reader: R) -> Result<Vec<Value>, serde_json::Error> {
+        Value::from_reader_all_with(reader, DuplicateKeys::default())
+    }
+
+    /// [`from_reader_all`](Self::from_reader_all), handling repeated keys
+    /// as `duplicates` says.
+    pub fn from_reader_all_with<R: Read>(
+        reader: R,
+        duplicates: DuplicateKeys,
+    ) -> Result<Vec<Value>, serde_json::Error> {
+        let mut de = serde_json::Deserializer::from_reader(io::BufReader::new(reader));
+        let mut documents = Vec::new();
+        // `end` succeeds once only whitespace is left
+        while de.end().is_err() {
+            documents.push(Select { paths: WHOLE, duplicates }.deserialize(&mut de)?);
+        }
+        Ok(documents)
+    }
+
+    /// Parse a JSON document from a reader, keeping only what `selector` needs.
+    ///
+    /// Objects along the selected paths keep just the selected keys; arrays
+    /// keep their length but every element that no path selects becomes
+    /// `null`. Evaluating the expression or query the selector came from
+    /// against the pruned document gives the same result as against the full
+    /// one.
+    pub fn from_reader_selecting<R: Read>(
+        reader: R,
+        selector: &PathSelector,
+    ) -> Result<Value, serde_json::Error> {
+        Value::from_reader_selecting_with(reader, selector, DuplicateKeys::default())
+    }
+
+    /// [`from_reader_selecting`](Self::from_reader_selecting), handling
+    /// repeated keys as `duplicates` says. Keys are checked in pruned
+    /// objects too, so [`DuplicateKeys::Error`] rejects the same inputs
+    /// whatever the selector.
+    pub fn from_reader_selecting_with<R: Read>(
+        reader: R,
+        selector: &PathSelector,
+        duplicates: DuplicateKeys,
+    ) -> Result<Value, serde_json::Error> {
+        let mut de = serde_json::Deserializer::from_reader(io::BufReader::new(reader));
+        let paths: Vec<&[PathKey]> = selector.paths.iter().map(Vec::as_slice).collect();
+        let value = Select { paths: &paths, duplicates }.deserialize(&mut de)?;
+        de.end()?;
+        Ok(value)
+    }
+}
+
+/// What to do with an object that repeats a key, in parsed input or in a
+/// query's object literal (see [`Parser::with_duplicate_keys`](crate::Parser::with_duplicate_keys))
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DuplicateKeys {
+    /// Fail with an error naming the key
+    Error,
+    /// Keep the first value
+    First,
+    /// Keep the last value
+    #[default]
+    Last,
+}
+
+impl std::str::FromStr for DuplicateKeys {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "error" => Ok(DuplicateKeys::Error),
+            "first" => Ok(DuplicateKeys::First),
+            "last" => Ok(DuplicateKeys::Last),
+            _ => Err(format!("Unknown duplicate key policy '{}' (expected error, first or last)", s)),
+        }
+    }
+}
+
+/// One step of a constant access path.
+#[derive(Debug, Clone, PartialEq)]
+enum PathKey {
+    /// Object field (also how non-integer keys are looked up)
+    Field(String),
+    /// Array index, or the field of the same name on objects
+    Index(i64),
+}
+
+impl PathKey {
+    fn matches_field(&self, name: &str) -> bool {
+        match self {
+            PathKey::Field(f) => f == name,
+            PathKey::Index(n) => n.to_string() == name,
+        }
+    }
+}
+
+/// The constant access paths an expression or query reads, like
+/// `$[users][0][name]`.
+///
+/// The default selector is the empty path, which keeps the whole document.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PathSelector {
+    paths: Vec<Vec<PathKey>>,
+}
+
+impl Default for PathSelector {
+    fn default() -> Self {
+        PathSelector { paths: vec![Vec::new()] }
+    }
+}
+
+impl PathSelector {
+    /// Extract the paths from an expression that reads the document only
+    /// through constant paths below the root, such as
+    /// `$[a][b] > 1 and $[c][?]` or `{"id": $[items][0][id]}`.
+    ///
+    /// Returns `None` for expressions with lambdas or UDF calls, since
+    /// pruning could change their result.
+    pub fn from_expr(expr: &Expr) -> Option<PathSelector> {
+        let mut paths = Vec::new();
+        collect_paths(expr, &mut paths)?;
+        Some(PathSelector { paths })
+    }
+
+    /// Extract the paths from a pipeline of filters and scope definitions
+    /// ending in an output, where every expression qualifies for
+    /// [`from_expr`](Self::from_expr).
+    ///
+    /// Returns `None` for pipelines with transforms, deletions, `when` or
+    /// `foreach` blocks, or that replace the document part way through.
+    pub fn from_query(query: &Query) -> Option<PathSelector> {
+        let mut paths = Vec::new();
+        for (i, stmt) in query.statements.iter().enumerate() {
+            let is_result = i + 1 == query.statements.len() && query.output.is_none();
+            match stmt {
+                Statement::Filter(expr)
+                | Statement::ScopeDefinition { path: expr, .. }
+                | Statement::LiveScopeDefinition { path: expr, .. }
+                | Statement::HoistedScope { path: expr, .. } => collect_paths(expr, &mut paths)?,
+                Statement::Access(expr) | Statement::ExistenceCheck(expr) if is_result => {
+                    return collect_paths(expr, &mut paths).map(|()| PathSelector { paths });
+                }
+                Statement::Access(_)
+                | Statement::ExistenceCheck(_)
+                | Statement::Transform { .. }
+                | Statement::When { .. }
+                | Statement::ForEach { .. }
+                | Statement::Delete(_) => return None,
+            }
+        }
+        match &query.output {
+            Some(expr) => collect_paths(expr, &mut paths)?,
+            // The document itself is the result
+            None => paths.push(Vec::new()),
+        }
+        Some(PathSelector { paths })
+    }
+
+    /// Whether this selector keeps the entire document
+    pub fn is_whole_document(&self) -> bool {
+        self.paths.iter().any(Vec::is_empty)
+    }
+
+    /// A selector keeping everything that either `self` or `other` keeps
+    pub fn union(mut self, other: PathSelector) -> PathSelector {
+        self.paths.extend(other.paths);
+        self
+    }
+}
+
+/// Add the paths `expr` reads to `paths`, or `None` if it reads the document
+/// in some other way
+fn collect_paths(expr: &Expr, paths: &mut Vec<Vec<PathKey>>) -> Option<()> {
+    match expr {
+        // Selectable queries never replace the document, so `$$` is `$`
+        Expr::Root | Expr::OriginalRoot | Expr::Access { .. } => {
+            let mut path = Vec::new();
+            let mut expr = expr;
+            loop {
+                match expr {
+                    Expr::Root | Expr::OriginalRoot => {
+                        path.reverse();
+                        paths.push(path);
+                        return Some(());
+                    }
+                    Expr::Access { object, key } => {
+                        match constant_key(key) {
+                            Some(key) => path.push(key),
+                            // A computed key needs everything below `object`
+                            None => {
+                                path.clear();
+                                collect_paths(key, paths)?;
+                            }
+                        }
+                        expr = object;
+                    }
+                    other => return collect_paths(other, paths),
+                }
+            }
+        }
+        Expr::Float(_)
+        | Expr::Integer(_)
+        | Expr::String(_)
+        | Expr::Boolean(_)
+        | Expr::Null
+        | Expr::Key(_)
+        | Expr::Wildcard
+        | Expr::ScopeRef(_)
+        | Expr::EnvVar(_)
+        | Expr::TypedEnvVar { .. }
+        | Expr::Meta => Some(()),
+        Expr::ExistenceCheck(inner) => collect_paths(inner, paths),
+        Expr::BinaryOp { left, right, .. } | Expr::Try { body: left, fallback: right } => {
+            collect_paths(left, paths)?;
+            collect_paths(right, paths)
+        }
+        Expr::MethodCall { object, args, .. } => {
+            collect_paths(object, paths)?;
+            args.iter().try_for_each(|arg| collect_paths(arg, paths))
+        }
+        Expr::Array(items) | Expr::HostCall { args: items, .. } => {
+            items.iter().try_for_each(|item| collect_paths(item, paths))
+        }
+        Expr::Object(entries) => entries
+            .iter()
+            .try_for_each(|(_, value)| collect_paths(value, paths)),
+        Expr::LambdaParam
+        | Expr::OuterLambdaParam(_)
+        | Expr::LambdaVar(_)
+        | Expr::Lambda { .. }
+        | Expr::Match { .. }
+        | Expr::ArgRef(_)
+        | Expr::Filter(_)
+        | Expr::UDFCall { .. } => None,
+    }
+}
+
+/// The path step for a literal key, or `None` if the key is computed
+fn constant_key(key: &Expr) -> Option<PathKey> {
+    match key {
+        Expr::Key(name) | Expr::String(name) => Some(PathKey::Field(name.clone())),
+        Expr::Integer(n) => Some(PathKey::Index(*n)),
+        // A whole-number float indexes like the integer it equals
+        Expr::Float(f) => Some(match whole_number(*f) {
+            Some(n) => PathKey::Index(n),
+            None => PathKey::Field(f.to_string()),
+        }),
+        Expr::Boolean(b) => Some(PathKey::Field(b.to_string())),
+        // Negative indices parse as `0 - n`
+        Expr::BinaryOp {
+            op: BinOp::Subtract,
+            left,
+            right,
+        } => match (left.as_ref(), right.as_ref()) {
+            (Expr::Integer(0), Expr::Integer(n)) => Some(PathKey::Index(n.checked_neg()?)),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Deserializes a value, pruned down to the remaining paths.
+struct Select<'a> {
+    paths: &'a [&'a [PathKey]],
+    duplicates: DuplicateKeys,
+}
+
+/// The remaining paths of a subtree that is kept whole
+const WHOLE: &[&[PathKey]] = &[&[]];
+
+impl<'a> Select<'a> {
+    fn keeps_whole(&self) -> bool {
+        self.paths.iter().any(|path| path.is_empty())
+    }
+
+    /// The seed for a child value, keeping `paths`
+    fn child(&self, paths: &'a [&'a [PathKey]]) -> Select<'a> {
+        Select { paths, duplicates: self.duplicates }
+    }
+
+    /// Whether to keep the value of `key`, given the keys of the object
+    /// read so far
+    fn admit<E: de::Error>(&self, seen: &mut HashSet<String>, key: &str) -> Result<bool, E> {
+        if self.duplicates == DuplicateKeys::Last || seen.insert(key.to_string()) {
+            return Ok(true);
+        }
+        match self.duplicates {
+            DuplicateKeys::Error => Err(E::custom(format_args!("duplicate key \"{}\"", key))),
+            _ => Ok(false),
+        }
+    }
+}
+
+impl<'de> DeserializeSeed<'de> for Select<'_> {
+    type Value = Value;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Value, D::Error> {
+        deserializer.deserialize_any(self)
+    }
+}
+
+impl<'de> Visitor<'de> for Select<'_> {
+    type Value = Value;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("a JSON value")
+    }
+
+    fn visit_unit<E: de::Error>(self) -> Result<Value, E> {
+        Ok(Value::Null)
+    }
+
+    fn visit_bool<E: de::Error>(self, b: bool) -> Result<Value, E> {
+        Ok(Value::Boolean(b))
+    }
+
+    fn visit_i64<E: de::Error>(self, n: i64) -> Result<Value, E> {
+        Ok(Value::Integer(n))
+    }
+
+    fn visit_u64<E: de::Error>(self, n: u64) -> Result<Value, E> {
+        Ok(i64::try_from(n).map_or(Value::Float(n as f64), Value::Integer))
+    }
+
+    fn visit_f64<E: de::Error>(self, n: f64) -> Result<Value, E> {
+        Ok(Value::Float(n))
+    }
+
+    fn visit_str<E: de::Error>(self, s: &str) -> Result<Value, E> {
+        Ok(Value::String(s.to_string()))
+    }
+
+    fn visit_string<E: de::Error>(self, s: String) -> Result<Value, E> {
+        Ok(Value::String(s))
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Value, A::Error> {
+        let mut items = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+        // Negative indices count from the end, so keep every element
+        let counts_from_end = self
+            .paths
+            .iter()
+            .any(|path| matches!(path.first(), Some(PathKey::Index(i64::MIN..=-1))));
+        if self.keeps_whole() || counts_from_end {
+            while let Some(item) = seq.next_element_seed(self.child(WHOLE))? {
+                items.push(item);
+            }
+        } else {
+            loop {
+                let index = items.len() as i64;
+                let rest: Vec<&[PathKey]> = self
+                    .paths
+                    .iter()
+                    .filter_map(|path| match path.split_first() {
+                        Some((PathKey::Index(n), rest)) if *n == index => Some(rest),
+                        _ => None,
+                    })
+                    .collect();
+                let item = if rest.is_empty() {
+                    seq.next_element::<IgnoredAny>()?.map(|_| Value::Null)
+                } else {
+                    seq.next_element_seed(Select { paths: &rest, duplicates: self.duplicates })?
+                };
+                match item {
+                    Some(item) => items.push(item),
+                    None => break,
+                }
+            }
+        }
+        Ok(Value::Array(items))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Value, A::Error> {
+        let mut object = HashMap::new();
+        let mut seen = HashSet::new();
+        if self.keeps_whole() {
+            while let Some(key) = map.next_key::<String>()? {
+                if !self.admit(&mut seen, &key)? {
+                    map.next_value::<IgnoredAny>()?;
+                    continue;
+                }
+                let value = map.next_value_seed(self.child(WHOLE))?;
+                object.insert(key, value);
+            }
+        } else {
+            while let Some(key) = map.next_key::<String>()? {
+                let rest: Vec<&[PathKey]> = self
+                    .paths
+                    .iter()
+                    .filter_map(|path| match path.split_first() {
+                        Some((wanted, rest)) if wanted.matches_field(&key) => Some(rest),
+                        _ => None,
+                    })
+                    .collect();
+                if !self.admit(&mut seen, &key)? || rest.is_empty() {
+                    map.next_value::<IgnoredAny>()?;
+                } else {
+                    let value = map.next_value_seed(Select { paths: &rest, duplicates: self.duplicates })?;
+                    object.insert(key, value);
+                }
+            }
+        }
+        Ok(Value::Object(object))
+    }
+}
+
+/// A reader that fails once more than `limit` bytes have been read.
+///
+/// Use [`LimitedReader::exceeded`] after a failed parse to tell a size
+/// violation apart from malformed input.
+pub struct LimitedReader<R> {
+    inner: R,
+    limit: u64,
+    read: u64,
+}
+
+impl<R: Read> LimitedReader<R> {
+    pub fn new(inner: R, limit: u64) -> Self {
+        LimitedReader {
+            inner,
+            limit,
+            read: 0,
+        }
+    }
+
+    /// The configured byte limit
+    pub fn limit(&self) -> u64 {
+        self.limit
+    }
+
+    /// Whether the input turned out to be larger than the limit
+    pub fn exceeded(&self) -> bool {
+        self.read > self.limit
+    }
+
+    fn too_large(&self) -> io::Error {
+        io::Error::other(format!("input exceeds {} bytes", self.limit))
+    }
+}
+
+impl<R: Read> Read for LimitedReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.exceeded() {
+            return Err(self.too_large());
+        }
+        // Read at most one byte past the limit, enough to detect overflow
+        let allowed = (self.limit - self.read).saturating_add(1).min(buf.len() as u64) as usize;
+        let n = self.inner.read(&mut buf[..allowed])?;
+        self.read += n as u64;
+        if self.exceeded() {
+            return Err(self.too_large());
+        }
+        Ok(n)
+    }
+}
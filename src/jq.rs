@@ -0,0 +1,72 @@
+//! Best-effort translation of jq filters into Clove.
+//!
+//! Supports the jq subset most one-liners use: paths (`.a.b`, `.[0]`,
+//! `.["key"]`, slices), `select`, `map`, `[.[] | ...]`, arithmetic,
+//! comparisons, `and`/`or`/`not`, `//`, object and array construction,
+//! top-level assignments (`=`, `|=`, `+=`, ...) and `del`, and common builtins
+//! such as `length`, `keys`, `add`, `sort_by` and `test`.
+//!
+//! Constructs without a Clove equivalent (variables, `if`, `reduce`,
+//! streams produced by `.[]` or `,`, ...) are collected and reported together
+//! in [`JqError::Unsupported`].
+//!
+//! # Examples
+//!
+//! ```
+//! use clove_lang::jq::translate;
+//!
+//! assert_eq!(translate(".users[0].name").unwrap(), "$[users][0][name]");
+//! assert_eq!(
+//!     translate("select(.age >= 18) | .name").unwrap(),
+//!     "$ | ?($[age] >= 18) | !($[name])"
+//! );
+//! assert_eq!(
+//!     translate("[.items[] | select(.price > 10) | .name]").unwrap(),
+//!     "$[items].filter(@[price] > 10).map(@[name])"
+//! );
+//! ```
+
+mod parser;
+mod translate;
+
+/// Errors from [`translate`]
+#[derive(Debug, Clone, PartialEq)]
+pub enum JqError {
+    /// The filter is not valid jq (or uses syntax the parser does not handle)
+    Parse { message: String, offset: usize },
+    /// The filter uses constructs that cannot be expressed in Clove
+    Unsupported(Vec<String>),
+}
+
+impl std::fmt::Display for JqError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            JqError::Parse { message, offset } => {
+                write!(f, "jq parse error at offset {}: {}", offset, message)
+            }
+            JqError::Unsupported(constructs) => {
+                write!(f, "Cannot translate jq filter; unsupported constructs:")?;
+                for construct in constructs {
+                    write!(f, "\n  - {}", construct)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for JqError {}
+
+/// Translate a jq filter into equivalent Clove source.
+pub fn translate(filter: &str) -> Result<String, JqError> {
+    let expr = parser::parse(filter)?;
+    let mut translator = translate::Translator::new();
+    let clove = translator.translate(&expr);
+
+    let unsupported = translator.into_unsupported();
+    if unsupported.is_empty() {
+        Ok(clove)
+    } else {
+        Err(JqError::Unsupported(unsupported))
+    }
+}
@@ -0,0 +1,723 @@
+//! Lexer and parser for the supported subset of jq syntax
+
+use super::JqError;
+
+/// A parsed jq filter
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum JqExpr {
+    /// `.`
+    Identity,
+    /// `..`
+    Recurse,
+    /// `.foo`, `."foo"`, `.["foo"]` on a target
+    Field(Box<JqExpr>, String),
+    /// `.[expr]` on a target
+    Index(Box<JqExpr>, Box<JqExpr>),
+    /// `.[from:to]` on a target
+    Slice(Box<JqExpr>, Option<Box<JqExpr>>, Option<Box<JqExpr>>),
+    /// `.[]` on a target
+    Iterate(Box<JqExpr>),
+    /// `expr?`
+    Optional(Box<JqExpr>),
+    /// Number literal, kept as written
+    Number(String),
+    String(String),
+    Boolean(bool),
+    Null,
+    /// `[expr]` or `[]`
+    Array(Option<Box<JqExpr>>),
+    /// `{key: value, shorthand}`
+    Object(Vec<(String, Option<JqExpr>)>),
+    Pipe(Box<JqExpr>, Box<JqExpr>),
+    Comma(Box<JqExpr>, Box<JqExpr>),
+    /// Binary operator, including `and`, `or`, `//` and the update-assignments
+    Binary(String, Box<JqExpr>, Box<JqExpr>),
+    Neg(Box<JqExpr>),
+    /// Builtin or user function call: `name` or `name(arg; arg)`
+    Call(String, Vec<JqExpr>),
+    /// A construct that parses but has no Clove equivalent; holds its description
+    Unsupported(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Tok {
+    Dot,
+    DotDot,
+    /// `.foo`
+    Field(String),
+    Ident(String),
+    Var(String),
+    Format(String),
+    Number(String),
+    Str(String),
+    /// String containing `\(...)` interpolation
+    InterpolatedStr,
+    Op(&'static str),
+    LBracket,
+    RBracket,
+    LBrace,
+    RBrace,
+    LParen,
+    RParen,
+    Pipe,
+    Comma,
+    Colon,
+    Semicolon,
+    Question,
+    Eof,
+}
+
+const OPERATORS: [&str; 20] = [
+    "?//", "|=", "+=", "-=", "*=", "/=", "%=", "//=", "==", "!=", "<=", ">=", "//", "<", ">", "+",
+    "-", "*", "/", "%",
+];
+
+fn tokenize(input: &str) -> Result<Vec<(Tok, usize)>, JqError> {
+    let chars: Vec<char> = input.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        let start = i;
+
+        if c.is_whitespace() {
+            i += 1;
+            continue;
+        }
+        if c == '#' {
+            while i < chars.len() && chars[i] != '\n' {
+                i += 1;
+            }
+            continue;
+        }
+
+        let is_ident_start = |c: char| c.is_ascii_alphabetic() || c == '_';
+        let is_ident_char = |c: char| c.is_ascii_alphanumeric() || c == '_';
+        let read_ident = |i: &mut usize| {
+            let begin = *i;
+            while *i < chars.len() && is_ident_char(chars[*i]) {
+                *i += 1;
+            }
+            chars[begin..*i].iter().collect::<String>()
+        };
+
+        let tok = match c {
+            '.' if chars.get(i + 1) == Some(&'.') => {
+                i += 2;
+                Tok::DotDot
+            }
+            '.' if chars.get(i + 1).is_some_and(|c| is_ident_start(*c)) => {
+                i += 1;
+                Tok::Field(read_ident(&mut i))
+            }
+            '.' if chars.get(i + 1).is_some_and(|c| c.is_ascii_digit()) => {
+                i += 1;
+                while i < chars.len() && chars[i].is_ascii_digit() {
+                    i += 1;
+                }
+                Tok::Number(format!("0{}", chars[start..i].iter().collect::<String>()))
+            }
+            '.' => {
+                i += 1;
+                Tok::Dot
+            }
+            '$' => {
+                i += 1;
+                Tok::Var(read_ident(&mut i))
+            }
+            '@' => {
+                i += 1;
+                Tok::Format(read_ident(&mut i))
+            }
+            '"' => {
+                i += 1;
+                let mut s = String::new();
+                let mut interpolated = false;
+                loop {
+                    match chars.get(i) {
+                        None => {
+                            return Err(JqError::Parse {
+                                message: "unterminated string".to_string(),
+                                offset: start,
+                            });
+                        }
+                        Some('"') => {
+                            i += 1;
+                            break;
+                        }
+                        Some('\\') => {
+                            let escaped = match chars.get(i + 1) {
+                                Some('n') => '\n',
+                                Some('t') => '\t',
+                                Some('r') => '\r',
+                                Some('b') => '\u{8}',
+                                Some('f') => '\u{c}',
+                                Some('"') => '"',
+                                Some('\\') => '\\',
+                                Some('/') => '/',
+                                Some('(') => {
+                                    // Skip the interpolated expression, tracking nesting
+                                    interpolated = true;
+                                    let mut depth = 0;
+                                    i += 1;
+                                    while let Some(c) = chars.get(i) {
+                                        match c {
+                                            '(' => depth += 1,
+                                            ')' if depth == 1 => break,
+                                            ')' => depth -= 1,
+                                            _ => {}
+                                        }
+                                        i += 1;
+                                    }
+                                    i += 1;
+                                    continue;
+                                }
+                                Some('u') => {
+                                    let hex: String = chars.iter().skip(i + 2).take(4).collect();
+                                    let code = u32::from_str_radix(&hex, 16).ok();
+                                    match code.and_then(char::from_u32) {
+                                        Some(c) => {
+                                            i += 4;
+                                            c
+                                        }
+                                        None => {
+                                            return Err(JqError::Parse {
+                                                message: format!("invalid escape \\u{}", hex),
+                                                offset: i,
+                                            });
+                                        }
+                                    }
+                                }
+                                _ => {
+                                    return Err(JqError::Parse {
+                                        message: "invalid escape sequence".to_string(),
+                                        offset: i,
+                                    });
+                                }
+                            };
+                            s.push(escaped);
+                            i += 2;
+                        }
+                        Some(c) => {
+                            s.push(*c);
+                            i += 1;
+                        }
+                    }
+                }
+                if interpolated { Tok::InterpolatedStr } else { Tok::Str(s) }
+            }
+            c if c.is_ascii_digit() => {
+                while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+                    i += 1;
+                }
+                if i < chars.len() && matches!(chars[i], 'e' | 'E') {
+                    i += 1;
+                    if i < chars.len() && matches!(chars[i], '+' | '-') {
+                        i += 1;
+                    }
+                    while i < chars.len() && chars[i].is_ascii_digit() {
+                        i += 1;
+                    }
+                }
+                Tok::Number(chars[start..i].iter().collect())
+            }
+            c if is_ident_start(c) => {
+                let mut name = read_ident(&mut i);
+                // Module-qualified names like `foo::bar`
+                while chars.get(i) == Some(&':') && chars.get(i + 1) == Some(&':') {
+                    i += 2;
+                    name.push_str("::");
+                    name.push_str(&read_ident(&mut i));
+                }
+                Tok::Ident(name)
+            }
+            '[' => {
+                i += 1;
+                Tok::LBracket
+            }
+            ']' => {
+                i += 1;
+                Tok::RBracket
+            }
+            '{' => {
+                i += 1;
+                Tok::LBrace
+            }
+            '}' => {
+                i += 1;
+                Tok::RBrace
+            }
+            '(' => {
+                i += 1;
+                Tok::LParen
+            }
+            ')' => {
+                i += 1;
+                Tok::RParen
+            }
+            ',' => {
+                i += 1;
+                Tok::Comma
+            }
+            ':' => {
+                i += 1;
+                Tok::Colon
+            }
+            ';' => {
+                i += 1;
+                Tok::Semicolon
+            }
+            _ => {
+                let rest: String = chars[i..chars.len().min(i + 3)].iter().collect();
+                if let Some(op) = OPERATORS.iter().find(|op| rest.starts_with(**op)) {
+                    i += op.chars().count();
+                    Tok::Op(op)
+                } else if c == '|' {
+                    i += 1;
+                    Tok::Pipe
+                } else if c == '?' {
+                    i += 1;
+                    Tok::Question
+                } else if c == '=' {
+                    i += 1;
+                    Tok::Op("=")
+                } else {
+                    return Err(JqError::Parse {
+                        message: format!("unexpected character '{}'", c),
+                        offset: start,
+                    });
+                }
+            }
+        };
+        tokens.push((tok, start));
+    }
+
+    tokens.push((Tok::Eof, chars.len()));
+    Ok(tokens)
+}
+
+/// Parse a jq filter
+pub(crate) fn parse(input: &str) -> Result<JqExpr, JqError> {
+    let mut parser = JqParser {
+        tokens: tokenize(input)?,
+        pos: 0,
+    };
+    let expr = parser.parse_pipe()?;
+    match parser.peek() {
+        Tok::Eof => Ok(expr),
+        _ => Err(parser.error("unexpected token")),
+    }
+}
+
+struct JqParser {
+    tokens: Vec<(Tok, usize)>,
+    pos: usize,
+}
+
+impl JqParser {
+    fn peek(&self) -> &Tok {
+        &self.tokens[self.pos].0
+    }
+
+    fn next(&mut self) -> Tok {
+        let tok = self.tokens[self.pos].0.clone();
+        if self.pos < self.tokens.len() - 1 {
+            self.pos += 1;
+        }
+        tok
+    }
+
+    fn eat(&mut self, tok: &Tok) -> bool {
+        if self.peek() == tok {
+            self.next();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn is_keyword(&self, keyword: &str) -> bool {
+        matches!(self.peek(), Tok::Ident(name) if name == keyword)
+    }
+
+    fn expect(&mut self, tok: Tok, what: &str) -> Result<(), JqError> {
+        if self.eat(&tok) {
+            Ok(())
+        } else {
+            Err(self.error(&format!("expected {}", what)))
+        }
+    }
+
+    fn expect_keyword(&mut self, keyword: &str) -> Result<(), JqError> {
+        if self.is_keyword(keyword) {
+            self.next();
+            Ok(())
+        } else {
+            Err(self.error(&format!("expected '{}'", keyword)))
+        }
+    }
+
+    fn error(&self, message: &str) -> JqError {
+        JqError::Parse {
+            message: message.to_string(),
+            offset: self.tokens[self.pos].1,
+        }
+    }
+
+    // pipe := 'def' ... | term 'as' $var '|' pipe | comma ('|' pipe)?
+    fn parse_pipe(&mut self) -> Result<JqExpr, JqError> {
+        if self.is_keyword("def") {
+            return Err(self.error("function definitions (def) are not supported"));
+        }
+
+        let lhs = self.parse_comma()?;
+
+        if self.is_keyword("as") {
+            self.next();
+            self.parse_pattern()?;
+            self.expect(Tok::Pipe, "'|' after variable binding")?;
+            self.parse_pipe()?;
+            return Ok(JqExpr::Unsupported(
+                "variable binding (`... as $x | ...`)".to_string(),
+            ));
+        }
+
+        if self.eat(&Tok::Pipe) {
+            let rhs = self.parse_pipe()?;
+            return Ok(JqExpr::Pipe(Box::new(lhs), Box::new(rhs)));
+        }
+        Ok(lhs)
+    }
+
+    fn parse_pattern(&mut self) -> Result<(), JqError> {
+        match self.next() {
+            Tok::Var(_) => Ok(()),
+            Tok::LBracket | Tok::LBrace => {
+                // Destructuring pattern: skip to the matching bracket
+                let mut depth = 1;
+                while depth > 0 {
+                    match self.next() {
+                        Tok::LBracket | Tok::LBrace => depth += 1,
+                        Tok::RBracket | Tok::RBrace => depth -= 1,
+                        Tok::Eof => return Err(self.error("unterminated pattern")),
+                        _ => {}
+                    }
+                }
+                Ok(())
+            }
+            _ => Err(self.error("expected variable after 'as'")),
+        }
+    }
+
+    fn parse_comma(&mut self) -> Result<JqExpr, JqError> {
+        let mut lhs = self.parse_alternative()?;
+        while self.eat(&Tok::Comma) {
+            let rhs = self.parse_alternative()?;
+            lhs = JqExpr::Comma(Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    // '//' is right-associative and binds looser than the assignments
+    fn parse_alternative(&mut self) -> Result<JqExpr, JqError> {
+        let lhs = self.parse_assignment()?;
+        if self.eat(&Tok::Op("//")) {
+            let rhs = self.parse_alternative()?;
+            return Ok(JqExpr::Binary("//".to_string(), Box::new(lhs), Box::new(rhs)));
+        }
+        Ok(lhs)
+    }
+
+    fn parse_assignment(&mut self) -> Result<JqExpr, JqError> {
+        let lhs = self.parse_or()?;
+        if let Tok::Op(op @ ("=" | "|=" | "+=" | "-=" | "*=" | "/=" | "%=" | "//=")) = *self.peek() {
+            self.next();
+            let rhs = self.parse_alternative()?;
+            return Ok(JqExpr::Binary(op.to_string(), Box::new(lhs), Box::new(rhs)));
+        }
+        Ok(lhs)
+    }
+
+    fn parse_or(&mut self) -> Result<JqExpr, JqError> {
+        let mut lhs = self.parse_and()?;
+        while self.is_keyword("or") {
+            self.next();
+            let rhs = self.parse_and()?;
+            lhs = JqExpr::Binary("or".to_string(), Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    fn parse_and(&mut self) -> Result<JqExpr, JqError> {
+        let mut lhs = self.parse_comparison()?;
+        while self.is_keyword("and") {
+            self.next();
+            let rhs = self.parse_comparison()?;
+            lhs = JqExpr::Binary("and".to_string(), Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    fn parse_comparison(&mut self) -> Result<JqExpr, JqError> {
+        let lhs = self.parse_additive()?;
+        if let Tok::Op(op @ ("==" | "!=" | "<" | "<=" | ">" | ">=")) = *self.peek() {
+            self.next();
+            let rhs = self.parse_additive()?;
+            return Ok(JqExpr::Binary(op.to_string(), Box::new(lhs), Box::new(rhs)));
+        }
+        Ok(lhs)
+    }
+
+    fn parse_additive(&mut self) -> Result<JqExpr, JqError> {
+        let mut lhs = self.parse_multiplicative()?;
+        while let Tok::Op(op @ ("+" | "-")) = *self.peek() {
+            self.next();
+            let rhs = self.parse_multiplicative()?;
+            lhs = JqExpr::Binary(op.to_string(), Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    fn parse_multiplicative(&mut self) -> Result<JqExpr, JqError> {
+        let mut lhs = self.parse_unary()?;
+        while let Tok::Op(op @ ("*" | "/" | "%")) = *self.peek() {
+            self.next();
+            let rhs = self.parse_unary()?;
+            lhs = JqExpr::Binary(op.to_string(), Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    fn parse_unary(&mut self) -> Result<JqExpr, JqError> {
+        if self.eat(&Tok::Op("-")) {
+            return Ok(JqExpr::Neg(Box::new(self.parse_postfix()?)));
+        }
+        self.parse_postfix()
+    }
+
+    fn parse_postfix(&mut self) -> Result<JqExpr, JqError> {
+        let mut expr = self.parse_primary()?;
+
+        loop {
+            match self.peek().clone() {
+                Tok::Field(name) => {
+                    self.next();
+                    expr = JqExpr::Field(Box::new(expr), name);
+                }
+                Tok::Dot if matches!(self.tokens[self.pos + 1].0, Tok::Str(_)) => {
+                    self.next();
+                    if let Tok::Str(name) = self.next() {
+                        expr = JqExpr::Field(Box::new(expr), name);
+                    }
+                }
+                Tok::Dot if self.tokens[self.pos + 1].0 == Tok::LBracket => {
+                    self.next();
+                }
+                Tok::LBracket => {
+                    self.next();
+                    expr = self.parse_bracket_suffix(expr)?;
+                }
+                Tok::Question => {
+                    self.next();
+                    expr = JqExpr::Optional(Box::new(expr));
+                }
+                _ => break,
+            }
+        }
+        Ok(expr)
+    }
+
+    // After '[': `]`, `expr]`, `expr:expr]`, `:expr]`, `expr:]`
+    fn parse_bracket_suffix(&mut self, target: JqExpr) -> Result<JqExpr, JqError> {
+        let target = Box::new(target);
+        if self.eat(&Tok::RBracket) {
+            return Ok(JqExpr::Iterate(target));
+        }
+
+        let from = if self.peek() == &Tok::Colon {
+            None
+        } else {
+            Some(Box::new(self.parse_pipe()?))
+        };
+
+        if self.eat(&Tok::Colon) {
+            let to = if self.peek() == &Tok::RBracket {
+                None
+            } else {
+                Some(Box::new(self.parse_pipe()?))
+            };
+            self.expect(Tok::RBracket, "']'")?;
+            return Ok(JqExpr::Slice(target, from, to));
+        }
+
+        self.expect(Tok::RBracket, "']'")?;
+        match from.map(|b| *b) {
+            Some(JqExpr::String(name)) => Ok(JqExpr::Field(target, name)),
+            Some(index) => Ok(JqExpr::Index(target, Box::new(index))),
+            None => unreachable!("slice handled above"),
+        }
+    }
+
+    fn parse_primary(&mut self) -> Result<JqExpr, JqError> {
+        let start = self.pos;
+        match self.next() {
+            Tok::Dot => {
+                if let Tok::Str(name) = self.peek().clone() {
+                    self.next();
+                    return Ok(JqExpr::Field(Box::new(JqExpr::Identity), name));
+                }
+                Ok(JqExpr::Identity)
+            }
+            Tok::DotDot => Ok(JqExpr::Recurse),
+            Tok::Field(name) => Ok(JqExpr::Field(Box::new(JqExpr::Identity), name)),
+            Tok::Number(n) => Ok(JqExpr::Number(n)),
+            Tok::Str(s) => Ok(JqExpr::String(s)),
+            Tok::InterpolatedStr => Ok(JqExpr::Unsupported(
+                "string interpolation (`\"\\(...)\"`)".to_string(),
+            )),
+            Tok::Var(name) => Ok(JqExpr::Unsupported(format!("variable ${}", name))),
+            Tok::Format(name) => Ok(JqExpr::Unsupported(format!("format string @{}", name))),
+            Tok::LParen => {
+                let expr = self.parse_pipe()?;
+                self.expect(Tok::RParen, "')'")?;
+                Ok(expr)
+            }
+            Tok::LBracket => {
+                if self.eat(&Tok::RBracket) {
+                    return Ok(JqExpr::Array(None));
+                }
+                let expr = self.parse_pipe()?;
+                self.expect(Tok::RBracket, "']'")?;
+                Ok(JqExpr::Array(Some(Box::new(expr))))
+            }
+            Tok::LBrace => self.parse_object(),
+            Tok::Ident(name) => self.parse_identifier(name),
+            _ => {
+                self.pos = start;
+                Err(self.error("expected an expression"))
+            }
+        }
+    }
+
+    fn parse_identifier(&mut self, name: String) -> Result<JqExpr, JqError> {
+        match name.as_str() {
+            "true" => return Ok(JqExpr::Boolean(true)),
+            "false" => return Ok(JqExpr::Boolean(false)),
+            "null" => return Ok(JqExpr::Null),
+            "if" => {
+                self.parse_pipe()?;
+                self.expect_keyword("then")?;
+                self.parse_pipe()?;
+                while self.is_keyword("elif") {
+                    self.next();
+                    self.parse_pipe()?;
+                    self.expect_keyword("then")?;
+                    self.parse_pipe()?;
+                }
+                if self.is_keyword("else") {
+                    self.next();
+                    self.parse_pipe()?;
+                }
+                self.expect_keyword("end")?;
+                return Ok(JqExpr::Unsupported("conditional (`if ... then ... end`)".to_string()));
+            }
+            "reduce" | "foreach" => {
+                self.parse_postfix()?;
+                self.expect_keyword("as")?;
+                self.parse_pattern()?;
+                self.expect(Tok::LParen, "'('")?;
+                self.parse_pipe()?;
+                while self.eat(&Tok::Semicolon) {
+                    self.parse_pipe()?;
+                }
+                self.expect(Tok::RParen, "')'")?;
+                return Ok(JqExpr::Unsupported(format!("{} expression", name)));
+            }
+            "try" => {
+                self.parse_postfix()?;
+                if self.is_keyword("catch") {
+                    self.next();
+                    self.parse_postfix()?;
+                }
+                return Ok(JqExpr::Unsupported("try/catch".to_string()));
+            }
+            "label" | "import" | "include" => {
+                return Err(self.error(&format!("'{}' is not supported", name)));
+            }
+            _ => {}
+        }
+
+        let mut args = Vec::new();
+        if self.eat(&Tok::LParen) {
+            loop {
+                args.push(self.parse_pipe()?);
+                if !self.eat(&Tok::Semicolon) {
+                    break;
+                }
+            }
+            self.expect(Tok::RParen, "')'")?;
+        }
+        Ok(JqExpr::Call(name, args))
+    }
+
+    fn parse_object(&mut self) -> Result<JqExpr, JqError> {
+        let mut entries = Vec::new();
+        if self.eat(&Tok::RBrace) {
+            return Ok(JqExpr::Object(entries));
+        }
+
+        loop {
+            let start = self.pos;
+            let key = match self.next() {
+                Tok::Ident(name) | Tok::Str(name) => name,
+                Tok::Var(name) => {
+                    return self.skip_object(JqExpr::Unsupported(format!(
+                        "variable ${} in object",
+                        name
+                    )));
+                }
+                Tok::LParen => {
+                    self.parse_pipe()?;
+                    self.expect(Tok::RParen, "')'")?;
+                    return self.skip_object(JqExpr::Unsupported(
+                        "computed object key (`{(expr): ...}`)".to_string(),
+                    ));
+                }
+                _ => {
+                    self.pos = start;
+                    return Err(self.error("expected object key"));
+                }
+            };
+            let value = if self.eat(&Tok::Colon) {
+                Some(self.parse_object_value()?)
+            } else {
+                None
+            };
+            entries.push((key, value));
+
+            if self.eat(&Tok::RBrace) {
+                return Ok(JqExpr::Object(entries));
+            }
+            self.expect(Tok::Comma, "',' or '}'")?;
+        }
+    }
+
+    // Object values may not contain ',' or '|' without parentheses
+    fn parse_object_value(&mut self) -> Result<JqExpr, JqError> {
+        self.parse_alternative()
+    }
+
+    fn skip_object(&mut self, result: JqExpr) -> Result<JqExpr, JqError> {
+        let mut depth = 1;
+        while depth > 0 {
+            match self.next() {
+                Tok::LBrace => depth += 1,
+                Tok::RBrace => depth -= 1,
+                Tok::Eof => return Err(self.error("unterminated object")),
+                _ => {}
+            }
+        }
+        Ok(result)
+    }
+}
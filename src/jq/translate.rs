@@ -0,0 +1,402 @@
+//! Translation of parsed jq filters into Clove source
+
+use super::parser::JqExpr;
+use crate::ast::builder::{quote_key, quote_string};
+
+// Binding strength of Clove expressions, used to decide on parentheses
+const PREC_COALESCE: u8 = 1;
+const PREC_OR: u8 = 2;
+const PREC_AND: u8 = 3;
+const PREC_COMPARE: u8 = 4;
+const PREC_ADD: u8 = 5;
+const PREC_MUL: u8 = 6;
+const PREC_UNARY: u8 = 7;
+const PREC_ATOM: u8 = 8;
+
+/// A translated Clove expression
+#[derive(Debug, Clone)]
+struct Out {
+    text: String,
+    prec: u8,
+}
+
+impl Out {
+    fn atom(text: impl Into<String>) -> Self {
+        Out {
+            text: text.into(),
+            prec: PREC_ATOM,
+        }
+    }
+
+    /// Text safe to use where binding strength `prec` is required
+    fn at(&self, prec: u8) -> String {
+        if self.prec < prec {
+            format!("({})", self.text)
+        } else {
+            self.text.clone()
+        }
+    }
+
+    fn postfix(&self, suffix: &str) -> Out {
+        Out::atom(format!("{}{}", self.at(PREC_ATOM), suffix))
+    }
+
+    fn method(&self, name: &str, args: &[String]) -> Out {
+        self.postfix(&format!(".{}({})", name, args.join(", ")))
+    }
+
+    fn is_root(&self) -> bool {
+        self.text == "$"
+    }
+
+    fn is_element(&self) -> bool {
+        self.text == "@"
+    }
+}
+
+pub(crate) struct Translator {
+    unsupported: Vec<String>,
+}
+
+impl Translator {
+    pub(crate) fn new() -> Self {
+        Translator {
+            unsupported: Vec::new(),
+        }
+    }
+
+    pub(crate) fn into_unsupported(self) -> Vec<String> {
+        self.unsupported
+    }
+
+    fn unsupported(&mut self, construct: impl Into<String>) -> Out {
+        let construct = construct.into();
+        if !self.unsupported.contains(&construct) {
+            self.unsupported.push(construct);
+        }
+        Out::atom("null")
+    }
+
+    /// Translate a whole filter into a Clove expression or pipeline query.
+    ///
+    /// Top-level `select(...)`, assignments and `del(...)` become filter,
+    /// transform and delete statements; whatever the filter finally outputs
+    /// becomes the `!(...)` output.
+    pub(crate) fn translate(&mut self, filter: &JqExpr) -> String {
+        let mut statements = Vec::new();
+        let mut current = Out::atom("$");
+
+        for stage in pipe_stages(filter) {
+            match stage {
+                JqExpr::Call(name, args) if name == "select" && args.len() == 1 => {
+                    let condition = self.expr(&args[0], &current);
+                    statements.push(format!("?({})", condition.text));
+                }
+                JqExpr::Call(name, args) if name == "del" && args.len() == 1 => {
+                    if !current.is_root() {
+                        self.unsupported("`del(...)` after the input has been narrowed");
+                    }
+                    for path in comma_items(&args[0]) {
+                        let target = self.path(path);
+                        statements.push(format!("-({})", target));
+                    }
+                }
+                JqExpr::Binary(op, lhs, rhs) if is_assignment(op) => {
+                    if !current.is_root() {
+                        self.unsupported("assignment after the input has been narrowed");
+                    }
+                    let target = self.path(lhs);
+                    let value = self.assigned_value(op, &target, rhs);
+                    statements.push(format!("~({} := {})", target, value.text));
+                }
+                _ => current = self.expr(stage, &current),
+            }
+        }
+
+        if statements.is_empty() {
+            return current.text;
+        }
+        let mut query = String::from("$");
+        for statement in statements {
+            query.push_str(" | ");
+            query.push_str(&statement);
+        }
+        if !current.is_root() {
+            query.push_str(&format!(" | !({})", current.text));
+        }
+        query
+    }
+
+    fn assigned_value(&mut self, op: &str, target: &str, rhs: &JqExpr) -> Out {
+        let root = Out::atom("$");
+        let old = Out::atom(target);
+        match op {
+            "=" => self.expr(rhs, &root),
+            "|=" => self.expr(rhs, &old),
+            _ => {
+                let value = self.expr(rhs, &root);
+                let clove_op = match op {
+                    "//=" => "??",
+                    other => other.trim_end_matches('='),
+                };
+                binary(clove_op, &old, &value)
+            }
+        }
+    }
+
+    /// Translate an assignment/deletion target, which must be a plain path
+    fn path(&mut self, expr: &JqExpr) -> String {
+        match expr {
+            JqExpr::Identity => "$".to_string(),
+            JqExpr::Field(target, name) => {
+                let base = self.path(target);
+                format!("{}{}", base, quote_key(name))
+            }
+            JqExpr::Index(target, index) => {
+                let base = self.path(target);
+                match index.as_ref() {
+                    JqExpr::Number(n) => format!("{}[{}]", base, n),
+                    JqExpr::Neg(inner) if matches!(inner.as_ref(), JqExpr::Number(_)) => {
+                        let JqExpr::Number(n) = inner.as_ref() else { unreachable!() };
+                        format!("{}[-{}]", base, n)
+                    }
+                    _ => self.unsupported("computed index in an assignment path").text,
+                }
+            }
+            JqExpr::Optional(inner) => self.path(inner),
+            _ => self.unsupported("assignment to something other than a path").text,
+        }
+    }
+
+    fn expr(&mut self, expr: &JqExpr, input: &Out) -> Out {
+        match expr {
+            JqExpr::Identity => input.clone(),
+            JqExpr::Recurse => self.unsupported("recursive descent (`..`)"),
+            JqExpr::Field(target, name) => self.expr(target, input).postfix(&quote_key(name)),
+            JqExpr::Index(target, index) => {
+                let target = self.expr(target, input);
+                match index.as_ref() {
+                    JqExpr::Number(n) => target.postfix(&format!("[{}]", n)),
+                    other => {
+                        let index = self.expr(other, input);
+                        target.postfix(&format!("[{}]", index.text))
+                    }
+                }
+            }
+            JqExpr::Slice(target, from, to) => {
+                let target = self.expr(target, input);
+                self.slice(target, from.as_deref(), to.as_deref())
+            }
+            JqExpr::Iterate(_) => {
+                self.unsupported("`.[]` outside of `[...]` or `map(...)` (Clove has no streams)")
+            }
+            JqExpr::Optional(inner) => self.expr(inner, input),
+            JqExpr::Number(n) => Out::atom(n.clone()),
+            JqExpr::String(s) => Out::atom(quote_string(s)),
+            JqExpr::Boolean(b) => Out::atom(b.to_string()),
+            JqExpr::Null => Out::atom("null"),
+            JqExpr::Array(None) => Out::atom("[]"),
+            JqExpr::Array(Some(inner)) => self.array(inner, input),
+            JqExpr::Object(entries) => {
+                let mut fields = Vec::with_capacity(entries.len());
+                for (name, value) in entries {
+                    let value = match value {
+                        Some(value) => self.expr(value, input),
+                        None => input.postfix(&quote_key(name)),
+                    };
+                    fields.push(format!("{}: {}", quote_string(name), value.text));
+                }
+                Out::atom(format!("{{{}}}", fields.join(", ")))
+            }
+            JqExpr::Pipe(lhs, rhs) => {
+                if let JqExpr::Call(name, _) = rhs.as_ref()
+                    && name == "select"
+                {
+                    return self.unsupported("`select(...)` outside the top level or `map(...)`");
+                }
+                let lhs = self.expr(lhs, input);
+                self.expr(rhs, &lhs)
+            }
+            JqExpr::Comma(_, _) => {
+                self.unsupported("`,` (multiple outputs) outside of `[...]`")
+            }
+            JqExpr::Binary(op, lhs, rhs) => {
+                if is_assignment(op) {
+                    return self.unsupported("assignment outside the top level");
+                }
+                let lhs = self.expr(lhs, input);
+                let rhs = self.expr(rhs, input);
+                let clove_op = match op.as_str() {
+                    "and" => "&&",
+                    "or" => "||",
+                    "//" => "??",
+                    other => other,
+                };
+                binary(clove_op, &lhs, &rhs)
+            }
+            JqExpr::Neg(inner) => {
+                let inner = self.expr(inner, input);
+                Out {
+                    text: format!("-{}", inner.at(PREC_UNARY)),
+                    prec: PREC_UNARY,
+                }
+            }
+            JqExpr::Call(name, args) => self.call(name, args, input),
+            JqExpr::Unsupported(construct) => self.unsupported(construct.clone()),
+        }
+    }
+
+    fn slice(&mut self, target: Out, from: Option<&JqExpr>, to: Option<&JqExpr>) -> Out {
+        let bound = |e: Option<&JqExpr>| match e {
+            None => Some(None),
+            Some(JqExpr::Number(n)) => n.parse::<usize>().ok().map(Some),
+            _ => None,
+        };
+        match (bound(from), bound(to)) {
+            (Some(None), Some(None)) => target,
+            (Some(Some(from)), Some(None)) => target.method("skip", &[from.to_string()]),
+            (Some(None), Some(Some(to))) => target.method("take", &[to.to_string()]),
+            (Some(Some(from)), Some(Some(to))) => target
+                .method("skip", &[from.to_string()])
+                .method("take", &[to.saturating_sub(from).to_string()]),
+            _ => self.unsupported("slice with negative or computed bounds"),
+        }
+    }
+
+    /// `[...]`: collect the outputs of a `.[] | ...` chain or list the items of `a, b`
+    fn array(&mut self, inner: &JqExpr, input: &Out) -> Out {
+        let stages = pipe_stages(inner);
+        if let Some(JqExpr::Iterate(target)) = stages.first() {
+            let collection = self.expr(target, input);
+            return self.each(collection, &stages[1..]);
+        }
+
+        let items: Vec<String> = comma_items(inner)
+            .into_iter()
+            .map(|item| self.expr(item, input).text)
+            .collect();
+        Out::atom(format!("[{}]", items.join(", ")))
+    }
+
+    /// Apply jq stages to every element of `collection`, as `filter`/`map` calls
+    fn each(&mut self, collection: Out, stages: &[&JqExpr]) -> Out {
+        let element = Out::atom("@");
+        let mut result = collection;
+        let mut mapped = element.clone();
+
+        for stage in stages {
+            match stage {
+                JqExpr::Call(name, args) if name == "select" && args.len() == 1 => {
+                    if !mapped.is_element() {
+                        result = result.method("map", &[mapped.text]);
+                        mapped = element.clone();
+                    }
+                    let condition = self.expr(&args[0], &element);
+                    result = result.method("filter", &[condition.text]);
+                }
+                JqExpr::Iterate(_) => {
+                    return self.unsupported("nested `.[]` inside `[...]` or `map(...)`");
+                }
+                _ => mapped = self.expr(stage, &mapped),
+            }
+        }
+
+        if !mapped.is_element() {
+            result = result.method("map", &[mapped.text]);
+        }
+        result
+    }
+
+    fn call(&mut self, name: &str, args: &[JqExpr], input: &Out) -> Out {
+        let element = Out::atom("@");
+        match (name, args) {
+            ("map", [f]) => {
+                let stages = pipe_stages(f);
+                self.each(input.clone(), &stages)
+            }
+            ("select", [_]) => {
+                self.unsupported("`select(...)` outside the top level or `map(...)`")
+            }
+            ("not", []) => {
+                // jq's `not` is true for false and null only
+                let value = binary("??", input, &Out::atom("false"));
+                binary("==", &value, &Out::atom("false"))
+            }
+            ("length", []) => input.method("length", &[]),
+            ("keys" | "keys_unsorted", []) => input.method("keys", &[]),
+            ("add", []) => input.method("sum", &[]),
+            (
+                "first" | "last" | "unique" | "sort" | "reverse" | "min" | "max" | "flatten"
+                | "trim",
+                [],
+            ) => input.method(name, &[]),
+            ("ascii_downcase", []) => input.method("lower", &[]),
+            ("ascii_upcase", []) => input.method("upper", &[]),
+            ("any" | "all", []) => input.method(name, &["@".to_string()]),
+            ("any" | "all" | "unique_by", [f]) => {
+                let f = self.expr(f, &element);
+                input.method(name, &[f.text])
+            }
+            ("sort_by", [f]) => {
+                let f = self.expr(f, &element);
+                input.method("sort_by", &[format!("[{}]", f.text)])
+            }
+            ("startswith" | "endswith" | "split" | "contains" | "has", [arg]) => {
+                let arg = self.expr(arg, input);
+                input.method(name, &[arg.text])
+            }
+            ("test", [pattern]) => {
+                let pattern = self.expr(pattern, input);
+                input.method("matches", &[pattern.text])
+            }
+            ("empty", []) => self.unsupported("`empty`"),
+            _ => self.unsupported(format!("function `{}/{}`", name, args.len())),
+        }
+    }
+}
+
+fn is_assignment(op: &str) -> bool {
+    matches!(op, "=" | "|=" | "+=" | "-=" | "*=" | "/=" | "%=" | "//=")
+}
+
+fn binary(op: &str, lhs: &Out, rhs: &Out) -> Out {
+    let prec = match op {
+        "??" => PREC_COALESCE,
+        "||" => PREC_OR,
+        "&&" => PREC_AND,
+        "==" | "!=" | "<" | "<=" | ">" | ">=" => PREC_COMPARE,
+        "+" | "-" => PREC_ADD,
+        _ => PREC_MUL,
+    };
+    // Left-associative, and comparisons do not chain
+    let left_prec = if prec == PREC_COMPARE { prec + 1 } else { prec };
+    let right_prec = prec + 1;
+    Out {
+        text: format!("{} {} {}", lhs.at(left_prec), op, rhs.at(right_prec)),
+        prec,
+    }
+}
+
+/// Split `a | b | c` into its stages
+fn pipe_stages(expr: &JqExpr) -> Vec<&JqExpr> {
+    match expr {
+        JqExpr::Pipe(lhs, rhs) => {
+            let mut stages = pipe_stages(lhs);
+            stages.extend(pipe_stages(rhs));
+            stages
+        }
+        other => vec![other],
+    }
+}
+
+/// Split `a, b, c` into its items
+fn comma_items(expr: &JqExpr) -> Vec<&JqExpr> {
+    match expr {
+        JqExpr::Comma(lhs, rhs) => {
+            let mut items = comma_items(lhs);
+            items.extend(comma_items(rhs));
+            items
+        }
+        other => vec![other],
+    }
+}
@@ -0,0 +1,856 @@
+//! JSONPath evaluation and translation to Clove.
+//!
+//! Supports the commonly used JSONPath syntax (as in RFC 9535):
+//!
+//! | Selector              | Example                          |
+//! |-----------------------|----------------------------------|
+//! | Child name            | `$.store.book`, `$['odd key']`   |
+//! | Index (negative ok)   | `$.book[0]`, `$.book[-1]`        |
+//! | Wildcard              | `$.book[*]`, `$.store.*`         |
+//! | Union                 | `$.book[0,2]`, `$['a','b']`      |
+//! | Slice                 | `$.book[1:3]`, `$.book[::2]`     |
+//! | Descendants           | `$..author`, `$..book[0]`        |
+//! | Filter                | `$.book[?(@.price < 10 && @.isbn)]` |
+//!
+//! [`JsonPath::evaluate`] returns the selected nodes in document order
+//! (object members are visited in sorted key order). [`JsonPath::to_expr`]
+//! translates paths without unions or descendant segments into a Clove
+//! [`Expr`]: a path with no wildcard, slice or filter becomes a plain access
+//! that yields the value itself, and every wildcard, slice or filter becomes
+//! a `map`/`filter` over an array (`.*` uses `values()`).
+//!
+//! The translation is meant for migrating selectors and differs in one
+//! respect: JSONPath drops missing members from its results, while a Clove
+//! `map` yields `null` for them.
+//!
+//! # Examples
+//!
+//! ```
+//! use clove_lang::jsonpath::JsonPath;
+//! use clove_lang::Value;
+//!
+//! let doc = Value::from_reader(r#"{"book": [{"a": "x"}, {"a": "y"}]}"#.as_bytes()).unwrap();
+//! let path = JsonPath::parse("$.book[*].a").unwrap();
+//!
+//! let authors = path.evaluate(&doc);
+//! assert_eq!(authors, vec![Value::String("x".into()), Value::String("y".into())]);
+//! assert_eq!(path.to_clove().unwrap(), "$[book].map(@[a])");
+//! ```
+
+use std::cmp::Ordering;
+
+use crate::ast::{BinOp, Expr};
+use crate::value::Value;
+
+/// Errors from parsing or translating a JSONPath
+#[derive(Debug, Clone, PartialEq)]
+pub enum JsonPathError {
+    /// The selector is not valid JSONPath
+    Parse { message: String, offset: usize },
+    /// The selector is valid but has no Clove equivalent
+    Untranslatable(String),
+}
+
+impl std::fmt::Display for JsonPathError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            JsonPathError::Parse { message, offset } => {
+                write!(f, "Invalid JSONPath at offset {}: {}", offset, message)
+            }
+            JsonPathError::Untranslatable(what) => {
+                write!(f, "JSONPath cannot be translated to Clove: {}", what)
+            }
+        }
+    }
+}
+
+impl std::error::Error for JsonPathError {}
+
+/// A parsed JSONPath selector
+#[derive(Debug, Clone, PartialEq)]
+pub struct JsonPath {
+    segments: Vec<Segment>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Segment {
+    selectors: Vec<Selector>,
+    descendant: bool,
+    /// Written as `.name`/`.*` rather than in brackets; the translation
+    /// treats `.*` as an object wildcard and `[*]` as an array wildcard
+    dotted: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Selector {
+    Name(String),
+    Index(i64),
+    Wildcard,
+    Slice(Option<i64>, Option<i64>, Option<i64>),
+    Filter(FilterExpr),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum FilterExpr {
+    Or(Box<FilterExpr>, Box<FilterExpr>),
+    And(Box<FilterExpr>, Box<FilterExpr>),
+    Not(Box<FilterExpr>),
+    Compare(CompareOp, Operand, Operand),
+    /// A bare path: true when it selects something
+    Exists(Operand),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum CompareOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Operand {
+    Literal(Value),
+    /// `@...` (relative) or `$...` (absolute) singular path
+    Path { relative: bool, steps: Vec<Step> },
+}
+
+/// One step of a singular path inside a filter
+#[derive(Debug, Clone, PartialEq)]
+enum Step {
+    Name(String),
+    Index(i64),
+}
+
+impl JsonPath {
+    /// Parse a JSONPath selector such as `$.store.book[*].author`
+    pub fn parse(input: &str) -> Result<JsonPath, JsonPathError> {
+        let mut parser = PathParser {
+            chars: input.chars().collect(),
+            pos: 0,
+        };
+        parser.skip_ws();
+        parser.expect('$')?;
+        let segments = parser.parse_segments()?;
+        parser.skip_ws();
+        if parser.pos < parser.chars.len() {
+            return Err(parser.error("unexpected trailing characters"));
+        }
+        Ok(JsonPath { segments })
+    }
+
+    /// Whether the path can select at most one node
+    pub fn is_singular(&self) -> bool {
+        self.segments.iter().all(|s| {
+            !s.descendant
+                && s.selectors.len() == 1
+                && matches!(s.selectors[0], Selector::Name(_) | Selector::Index(_))
+        })
+    }
+
+    /// Select nodes from `root`, in document order
+    pub fn evaluate(&self, root: &Value) -> Vec<Value> {
+        let mut nodes = vec![root];
+        for segment in &self.segments {
+            let mut next = Vec::new();
+            for node in nodes {
+                if segment.descendant {
+                    let mut all = Vec::new();
+                    descendants(node, &mut all);
+                    for n in all {
+                        select(n, &segment.selectors, root, &mut next);
+                    }
+                } else {
+                    select(node, &segment.selectors, root, &mut next);
+                }
+            }
+            nodes = next;
+        }
+        nodes.into_iter().cloned().collect()
+    }
+
+    /// Translate into an equivalent Clove expression (see the module docs)
+    pub fn to_expr(&self) -> Result<Expr, JsonPathError> {
+        Ok(translate_segments(&self.segments, Expr::Root)?.0)
+    }
+
+    /// Translate into Clove source text
+    pub fn to_clove(&self) -> Result<String, JsonPathError> {
+        self.to_expr().map(|expr| render(&expr))
+    }
+}
+
+/// Evaluate a JSONPath selector against `root`.
+pub fn evaluate(path: &str, root: &Value) -> Result<Vec<Value>, JsonPathError> {
+    Ok(JsonPath::parse(path)?.evaluate(root))
+}
+
+// ---------------------------------------------------------------------------
+// Evaluation
+// ---------------------------------------------------------------------------
+
+fn sorted_members(map: &std::collections::HashMap<String, Value>) -> Vec<&Value> {
+    let mut keys: Vec<&String> = map.keys().collect();
+    keys.sort();
+    keys.into_iter().map(|k| &map[k]).collect()
+}
+
+fn children(node: &Value) -> Vec<&Value> {
+    match node {
+        Value::Array(items) => items.iter().collect(),
+        Value::Object(map) => sorted_members(map),
+        _ => Vec::new(),
+    }
+}
+
+fn descendants<'a>(node: &'a Value, out: &mut Vec<&'a Value>) {
+    out.push(node);
+    for child in children(node) {
+        descendants(child, out);
+    }
+}
+
+fn normalize_index(index: i64, len: usize) -> Option<usize> {
+    let len = len as i64;
+    let index = if index < 0 { len + index } else { index };
+    (0..len).contains(&index).then_some(index as usize)
+}
+
+fn select<'a>(node: &'a Value, selectors: &[Selector], root: &'a Value, out: &mut Vec<&'a Value>) {
+    for selector in selectors {
+        match (selector, node) {
+            (Selector::Name(name), Value::Object(map)) => out.extend(map.get(name)),
+            (Selector::Index(i), Value::Array(items)) => {
+                out.extend(normalize_index(*i, items.len()).map(|i| &items[i]))
+            }
+            (Selector::Wildcard, _) => out.extend(children(node)),
+            (Selector::Slice(start, end, step), Value::Array(items)) => {
+                out.extend(slice_indices(items.len(), *start, *end, *step).map(|i| &items[i]))
+            }
+            (Selector::Filter(filter), _) => out.extend(
+                children(node)
+                    .into_iter()
+                    .filter(|child| filter_matches(filter, child, root)),
+            ),
+            _ => {}
+        }
+    }
+}
+
+// RFC 9535 section 2.3.4.2.2
+fn slice_indices(
+    len: usize,
+    start: Option<i64>,
+    end: Option<i64>,
+    step: Option<i64>,
+) -> Box<dyn Iterator<Item = usize>> {
+    let len = len as i64;
+    let step = step.unwrap_or(1);
+    let normalize = |i: i64| if i >= 0 { i } else { len + i };
+
+    if step > 0 {
+        let lower = normalize(start.unwrap_or(0)).clamp(0, len);
+        let upper = normalize(end.unwrap_or(len)).clamp(0, len);
+        Box::new((lower..upper).step_by(step as usize).map(|i| i as usize))
+    } else if step < 0 {
+        let upper = normalize(start.unwrap_or(len - 1)).clamp(-1, len - 1);
+        let lower = normalize(end.unwrap_or(-len - 1)).clamp(-1, len - 1);
+        let step = step.unsigned_abs() as usize;
+        Box::new(
+            ((lower + 1)..=upper)
+                .rev()
+                .step_by(step)
+                .map(|i| i as usize),
+        )
+    } else {
+        Box::new(std::iter::empty())
+    }
+}
+
+fn resolve<'a>(operand: &'a Operand, current: &'a Value, root: &'a Value) -> Option<&'a Value> {
+    match operand {
+        Operand::Literal(value) => Some(value),
+        Operand::Path { relative, steps } => {
+            let mut node = if *relative { current } else { root };
+            for step in steps {
+                node = match (step, node) {
+                    (Step::Name(name), Value::Object(map)) => map.get(name)?,
+                    (Step::Index(i), Value::Array(items)) => {
+                        &items[normalize_index(*i, items.len())?]
+                    }
+                    _ => return None,
+                };
+            }
+            Some(node)
+        }
+    }
+}
+
+fn filter_matches(filter: &FilterExpr, current: &Value, root: &Value) -> bool {
+    match filter {
+        FilterExpr::Or(a, b) => {
+            filter_matches(a, current, root) || filter_matches(b, current, root)
+        }
+        FilterExpr::And(a, b) => {
+            filter_matches(a, current, root) && filter_matches(b, current, root)
+        }
+        FilterExpr::Not(inner) => !filter_matches(inner, current, root),
+        FilterExpr::Exists(operand) => resolve(operand, current, root).is_some(),
+        FilterExpr::Compare(op, lhs, rhs) => {
+            let lhs = resolve(lhs, current, root);
+            let rhs = resolve(rhs, current, root);
+            match op {
+                CompareOp::Eq => values_equal(lhs, rhs),
+                CompareOp::Ne => !values_equal(lhs, rhs),
+                CompareOp::Lt => order(lhs, rhs) == Some(Ordering::Less),
+                CompareOp::Gt => order(lhs, rhs) == Some(Ordering::Greater),
+                CompareOp::Le => {
+                    matches!(order(lhs, rhs), Some(Ordering::Less)) || values_equal(lhs, rhs)
+                }
+                CompareOp::Ge => {
+                    matches!(order(lhs, rhs), Some(Ordering::Greater)) || values_equal(lhs, rhs)
+                }
+            }
+        }
+    }
+}
+
+fn values_equal(a: Option<&Value>, b: Option<&Value>) -> bool {
+    match (a, b) {
+        (None, None) => true,
+        (Some(a), Some(b)) => match (a.as_float(), b.as_float()) {
+            (Some(x), Some(y)) => x == y,
+            _ => a == b,
+        },
+        _ => false,
+    }
+}
+
+fn order(a: Option<&Value>, b: Option<&Value>) -> Option<Ordering> {
+    match (a?, b?) {
+        (Value::String(x), Value::String(y)) => Some(x.cmp(y)),
+        (x, y) => x.as_float()?.partial_cmp(&y.as_float()?),
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Translation
+// ---------------------------------------------------------------------------
+
+fn access(object: Expr, key: Expr) -> Expr {
+    Expr::Access {
+        object: Box::new(object),
+        key: Box::new(key),
+    }
+}
+
+fn method(object: Expr, name: &str, args: Vec<Expr>) -> Expr {
+    Expr::MethodCall {
+        object: Box::new(object),
+        method: name.to_string(),
+        args,
+    }
+}
+
+/// Returns the translated expression and whether it yields an array of results
+fn translate_segments(segments: &[Segment], mut base: Expr) -> Result<(Expr, bool), JsonPathError> {
+    for (i, segment) in segments.iter().enumerate() {
+        if segment.descendant {
+            return Err(JsonPathError::Untranslatable(
+                "descendant segments (`..`)".to_string(),
+            ));
+        }
+        let selector = match segment.selectors.as_slice() {
+            [selector] => selector,
+            _ => return Err(JsonPathError::Untranslatable("unions (`[a,b]`)".to_string())),
+        };
+
+        let collection = match selector {
+            Selector::Name(name) => {
+                base = access(base, Expr::Key(name.clone()));
+                continue;
+            }
+            Selector::Index(index) => {
+                base = access(base, Expr::Integer(*index));
+                continue;
+            }
+            Selector::Wildcard if segment.dotted => method(base, "values", vec![]),
+            Selector::Wildcard => base,
+            Selector::Slice(start, end, step) => translate_slice(base, *start, *end, *step)?,
+            Selector::Filter(filter) => method(base, "filter", vec![translate_filter(filter)?]),
+        };
+
+        let rest = &segments[i + 1..];
+        if rest.is_empty() {
+            return Ok((collection, true));
+        }
+        let (inner, inner_is_array) = translate_segments(rest, Expr::LambdaParam)?;
+        let mut result = method(collection, "map", vec![inner]);
+        if inner_is_array {
+            result = method(result, "flatten", vec![]);
+        }
+        return Ok((result, true));
+    }
+    Ok((base, false))
+}
+
+fn translate_slice(
+    base: Expr,
+    start: Option<i64>,
+    end: Option<i64>,
+    step: Option<i64>,
+) -> Result<Expr, JsonPathError> {
+    let unsupported = || {
+        JsonPathError::Untranslatable("slices with negative bounds or a step".to_string())
+    };
+    if step.is_some_and(|s| s != 1) {
+        return Err(unsupported());
+    }
+    let start = start.unwrap_or(0);
+    if start < 0 || end.is_some_and(|e| e < 0) {
+        return Err(unsupported());
+    }
+
+    let mut expr = base;
+    if start > 0 {
+        expr = method(expr, "skip", vec![Expr::Integer(start)]);
+    }
+    if let Some(end) = end {
+        expr = method(expr, "take", vec![Expr::Integer((end - start).max(0))]);
+    }
+    Ok(expr)
+}
+
+fn translate_filter(filter: &FilterExpr) -> Result<Expr, JsonPathError> {
+    let binary = |op, left: Expr, right: Expr| Expr::BinaryOp {
+        op,
+        left: Box::new(left),
+        right: Box::new(right),
+    };
+    Ok(match filter {
+        FilterExpr::Or(a, b) => binary(BinOp::Or, translate_filter(a)?, translate_filter(b)?),
+        FilterExpr::And(a, b) => binary(BinOp::And, translate_filter(a)?, translate_filter(b)?),
+        FilterExpr::Not(_) => {
+            return Err(JsonPathError::Untranslatable("negation (`!`) in filters".to_string()));
+        }
+        FilterExpr::Exists(operand) => {
+            binary(BinOp::NotEqual, translate_operand(operand), Expr::Null)
+        }
+        FilterExpr::Compare(op, lhs, rhs) => {
+            let op = match op {
+                CompareOp::Eq => BinOp::Equal,
+                CompareOp::Ne => BinOp::NotEqual,
+                CompareOp::Lt => BinOp::LessThan,
+                CompareOp::Le => BinOp::LessEqual,
+                CompareOp::Gt => BinOp::GreaterThan,
+                CompareOp::Ge => BinOp::GreaterEqual,
+            };
+            binary(op, translate_operand(lhs), translate_operand(rhs))
+        }
+    })
+}
+
+fn translate_operand(operand: &Operand) -> Expr {
+    match operand {
+        Operand::Literal(value) => match value {
+            Value::Null => Expr::Null,
+            Value::Boolean(b) => Expr::Boolean(*b),
+            Value::Integer(n) => Expr::Integer(*n),
+            Value::Float(n) => Expr::Float(*n),
+            Value::String(s) => Expr::String(s.clone()),
+            // Filter literals are always JSON scalars
+            Value::Bytes(_) | Value::Array(_) | Value::Object(_) => Expr::Null,
+        },
+        Operand::Path { relative, steps } => {
+            let base = if *relative { Expr::LambdaParam } else { Expr::Root };
+            steps.iter().fold(base, |expr, step| match step {
+                Step::Name(name) => access(expr, Expr::Key(name.clone())),
+                Step::Index(i) => access(expr, Expr::Integer(*i)),
+            })
+        }
+    }
+}
+
+/// Render the expressions produced by the translation as Clove source
+fn render(expr: &Expr) -> String {
+    match expr {
+        Expr::Root => "$".to_string(),
+        Expr::LambdaParam => "@".to_string(),
+        Expr::Null => "null".to_string(),
+        Expr::Boolean(b) => b.to_string(),
+        Expr::Integer(n) => n.to_string(),
+        Expr::Float(n) => format!("{:?}", n),
+        Expr::String(s) => serde_json::to_string(s).unwrap_or_default(),
+        Expr::Access { object, key } => {
+            let key = match key.as_ref() {
+                Expr::Key(name) if is_identifier(name) => name.clone(),
+                Expr::Key(name) => serde_json::to_string(name).unwrap_or_default(),
+                other => render(other),
+            };
+            format!("{}[{}]", render(object), key)
+        }
+        Expr::MethodCall { object, method, args } => {
+            let args: Vec<String> = args.iter().map(render).collect();
+            format!("{}.{}({})", render(object), method, args.join(", "))
+        }
+        Expr::BinaryOp { op, left, right } => {
+            let operand = |e: &Expr| match e {
+                Expr::BinaryOp { .. } => format!("({})", render(e)),
+                _ => render(e),
+            };
+            let op = match op {
+                BinOp::Equal => "==",
+                BinOp::NotEqual => "!=",
+                BinOp::ApproxEqual => "~=",
+                BinOp::LessThan => "<",
+                BinOp::LessEqual => "<=",
+                BinOp::GreaterThan => ">",
+                BinOp::GreaterEqual => ">=",
+                BinOp::And => "&&",
+                BinOp::Or => "||",
+                BinOp::Add => "+",
+                BinOp::Subtract => "-",
+                BinOp::Multiply => "*",
+                BinOp::Divide => "/",
+                BinOp::Modulo => "%",
+                BinOp::NullCoalesce => "??",
+            };
+            format!("{} {} {}", operand(left), op, operand(right))
+        }
+        other => format!("{:?}", other),
+    }
+}
+
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && !matches!(name, "true" | "false" | "null")
+}
+
+// ---------------------------------------------------------------------------
+// Parsing
+// ---------------------------------------------------------------------------
+
+struct PathParser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl PathParser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn peek_at(&self, offset: usize) -> Option<char> {
+        self.chars.get(self.pos + offset).copied()
+    }
+
+    fn error(&self, message: &str) -> JsonPathError {
+        JsonPathError::Parse {
+            message: message.to_string(),
+            offset: self.pos,
+        }
+    }
+
+    fn skip_ws(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.pos += 1;
+        }
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        if self.peek() == Some(c) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), JsonPathError> {
+        if self.eat(c) {
+            Ok(())
+        } else {
+            Err(self.error(&format!("expected '{}'", c)))
+        }
+    }
+
+    fn parse_segments(&mut self) -> Result<Vec<Segment>, JsonPathError> {
+        let mut segments = Vec::new();
+        loop {
+            match self.peek() {
+                Some('.') if self.peek_at(1) == Some('.') => {
+                    self.pos += 2;
+                    let selectors = if self.peek() == Some('[') {
+                        self.parse_bracket()?
+                    } else {
+                        vec![self.parse_dot_member()?]
+                    };
+                    segments.push(Segment {
+                        selectors,
+                        descendant: true,
+                        dotted: false,
+                    });
+                }
+                Some('.') => {
+                    self.pos += 1;
+                    segments.push(Segment {
+                        selectors: vec![self.parse_dot_member()?],
+                        descendant: false,
+                        dotted: true,
+                    });
+                }
+                Some('[') => segments.push(Segment {
+                    selectors: self.parse_bracket()?,
+                    descendant: false,
+                    dotted: false,
+                }),
+                _ => return Ok(segments),
+            }
+        }
+    }
+
+    fn parse_dot_member(&mut self) -> Result<Selector, JsonPathError> {
+        if self.eat('*') {
+            return Ok(Selector::Wildcard);
+        }
+        let name = self.parse_member_name();
+        if name.is_empty() {
+            return Err(self.error("expected member name"));
+        }
+        Ok(Selector::Name(name))
+    }
+
+    fn parse_member_name(&mut self) -> String {
+        let start = self.pos;
+        while self
+            .peek()
+            .is_some_and(|c| c.is_alphanumeric() || c == '_' || c == '-' || !c.is_ascii())
+        {
+            self.pos += 1;
+        }
+        self.chars[start..self.pos].iter().collect()
+    }
+
+    fn parse_bracket(&mut self) -> Result<Vec<Selector>, JsonPathError> {
+        self.expect('[')?;
+        let mut selectors = Vec::new();
+        loop {
+            self.skip_ws();
+            selectors.push(self.parse_selector()?);
+            self.skip_ws();
+            if self.eat(']') {
+                return Ok(selectors);
+            }
+            self.expect(',')?;
+        }
+    }
+
+    fn parse_selector(&mut self) -> Result<Selector, JsonPathError> {
+        match self.peek() {
+            Some('*') => {
+                self.pos += 1;
+                Ok(Selector::Wildcard)
+            }
+            Some('\'' | '"') => Ok(Selector::Name(self.parse_string()?)),
+            Some('?') => {
+                self.pos += 1;
+                self.skip_ws();
+                // Both `?(expr)` and `?expr` are accepted
+                let filter = self.parse_or()?;
+                Ok(Selector::Filter(filter))
+            }
+            Some(c) if c == ':' || c == '-' || c.is_ascii_digit() => {
+                let start = self.parse_optional_int()?;
+                self.skip_ws();
+                if !self.eat(':') {
+                    return start
+                        .map(Selector::Index)
+                        .ok_or_else(|| self.error("expected index"));
+                }
+                self.skip_ws();
+                let end = self.parse_optional_int()?;
+                self.skip_ws();
+                let step = if self.eat(':') {
+                    self.skip_ws();
+                    self.parse_optional_int()?
+                } else {
+                    None
+                };
+                Ok(Selector::Slice(start, end, step))
+            }
+            _ => Err(self.error("expected selector")),
+        }
+    }
+
+    fn parse_optional_int(&mut self) -> Result<Option<i64>, JsonPathError> {
+        let start = self.pos;
+        self.eat('-');
+        while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+            self.pos += 1;
+        }
+        let text: String = self.chars[start..self.pos].iter().collect();
+        if text.is_empty() {
+            return Ok(None);
+        }
+        text.parse()
+            .map(Some)
+            .map_err(|_| JsonPathError::Parse {
+                message: format!("invalid integer '{}'", text),
+                offset: start,
+            })
+    }
+
+    fn parse_string(&mut self) -> Result<String, JsonPathError> {
+        let quote = self.peek().ok_or_else(|| self.error("expected string"))?;
+        self.pos += 1;
+        let mut s = String::new();
+        loop {
+            match self.peek() {
+                None => return Err(self.error("unterminated string")),
+                Some(c) if c == quote => {
+                    self.pos += 1;
+                    return Ok(s);
+                }
+                Some('\\') => {
+                    let escaped = match self.peek_at(1) {
+                        Some('n') => '\n',
+                        Some('t') => '\t',
+                        Some('r') => '\r',
+                        Some('b') => '\u{8}',
+                        Some('f') => '\u{c}',
+                        Some(c @ ('\\' | '/' | '\'' | '"')) => c,
+                        _ => return Err(self.error("invalid escape sequence")),
+                    };
+                    s.push(escaped);
+                    self.pos += 2;
+                }
+                Some(c) => {
+                    s.push(c);
+                    self.pos += 1;
+                }
+            }
+        }
+    }
+
+    fn parse_or(&mut self) -> Result<FilterExpr, JsonPathError> {
+        let mut lhs = self.parse_and()?;
+        loop {
+            self.skip_ws();
+            if self.peek() == Some('|') && self.peek_at(1) == Some('|') {
+                self.pos += 2;
+                let rhs = self.parse_and()?;
+                lhs = FilterExpr::Or(Box::new(lhs), Box::new(rhs));
+            } else {
+                return Ok(lhs);
+            }
+        }
+    }
+
+    fn parse_and(&mut self) -> Result<FilterExpr, JsonPathError> {
+        let mut lhs = self.parse_basic()?;
+        loop {
+            self.skip_ws();
+            if self.peek() == Some('&') && self.peek_at(1) == Some('&') {
+                self.pos += 2;
+                let rhs = self.parse_basic()?;
+                lhs = FilterExpr::And(Box::new(lhs), Box::new(rhs));
+            } else {
+                return Ok(lhs);
+            }
+        }
+    }
+
+    fn parse_basic(&mut self) -> Result<FilterExpr, JsonPathError> {
+        self.skip_ws();
+        if self.peek() == Some('!') && self.peek_at(1) != Some('=') {
+            self.pos += 1;
+            return Ok(FilterExpr::Not(Box::new(self.parse_basic()?)));
+        }
+        if self.eat('(') {
+            let inner = self.parse_or()?;
+            self.skip_ws();
+            self.expect(')')?;
+            return Ok(inner);
+        }
+
+        let lhs = self.parse_operand()?;
+        self.skip_ws();
+        let op = match (self.peek(), self.peek_at(1)) {
+            (Some('='), Some('=')) => Some((CompareOp::Eq, 2)),
+            (Some('!'), Some('=')) => Some((CompareOp::Ne, 2)),
+            (Some('<'), Some('=')) => Some((CompareOp::Le, 2)),
+            (Some('>'), Some('=')) => Some((CompareOp::Ge, 2)),
+            (Some('<'), _) => Some((CompareOp::Lt, 1)),
+            (Some('>'), _) => Some((CompareOp::Gt, 1)),
+            _ => None,
+        };
+        match op {
+            Some((op, width)) => {
+                self.pos += width;
+                self.skip_ws();
+                let rhs = self.parse_operand()?;
+                Ok(FilterExpr::Compare(op, lhs, rhs))
+            }
+            None if matches!(lhs, Operand::Path { .. }) => Ok(FilterExpr::Exists(lhs)),
+            None => Err(self.error("expected comparison operator")),
+        }
+    }
+
+    fn parse_operand(&mut self) -> Result<Operand, JsonPathError> {
+        match self.peek() {
+            Some(c @ ('@' | '$')) => {
+                self.pos += 1;
+                let mut steps = Vec::new();
+                for segment in self.parse_segments()? {
+                    match (segment.descendant, segment.selectors.as_slice()) {
+                        (false, [Selector::Name(name)]) => steps.push(Step::Name(name.clone())),
+                        (false, [Selector::Index(i)]) => steps.push(Step::Index(*i)),
+                        _ => return Err(self.error("only singular paths are allowed in filters")),
+                    }
+                }
+                Ok(Operand::Path {
+                    relative: c == '@',
+                    steps,
+                })
+            }
+            Some('\'' | '"') => Ok(Operand::Literal(Value::String(self.parse_string()?))),
+            Some(c) if c == '-' || c.is_ascii_digit() => {
+                let start = self.pos;
+                while self
+                    .peek()
+                    .is_some_and(|c| c.is_ascii_digit() || matches!(c, '-' | '+' | '.' | 'e' | 'E'))
+                {
+                    self.pos += 1;
+                }
+                let text: String = self.chars[start..self.pos].iter().collect();
+                if let Ok(n) = text.parse::<i64>() {
+                    Ok(Operand::Literal(Value::Integer(n)))
+                } else if let Ok(n) = text.parse::<f64>() {
+                    Ok(Operand::Literal(Value::Float(n)))
+                } else {
+                    Err(JsonPathError::Parse {
+                        message: format!("invalid number '{}'", text),
+                        offset: start,
+                    })
+                }
+            }
+            _ => {
+                for (word, value) in [
+                    ("true", Value::Boolean(true)),
+                    ("false", Value::Boolean(false)),
+                    ("null", Value::Null),
+                ] {
+                    if self.chars[self.pos..].starts_with(&word.chars().collect::<Vec<_>>()) {
+                        self.pos += word.len();
+                        return Ok(Operand::Literal(value));
+                    }
+                }
+                Err(self.error("expected path or literal"))
+            }
+        }
+    }
+}
@@ -1,3 +1,5 @@
+use std::ops::Range;
+
 use crate::ast::Token;
 
 /// Position in source code for error reporting
@@ -33,6 +35,45 @@ pub enum LexError {
     UnexpectedEof { context: String, position: Position },
     /// Bare '=' without '=='
     BareEquals { position: Position },
+    /// Integer literal that does not fit in 64 bits
+    IntegerOverflow { text: String, position: Position },
+    /// Number literal with a missing or invalid part, like `1e` or `0xG`
+    MalformedNumber { text: String, reason: String, position: Position },
+}
+
+impl LexError {
+    /// Where in the input the error occurred
+    pub fn position(&self) -> Position {
+        match self {
+            LexError::UnexpectedChar { position, .. }
+            | LexError::UnterminatedString { position }
+            | LexError::InvalidEscape { position, .. }
+            | LexError::UnexpectedEof { position, .. }
+            | LexError::BareEquals { position }
+            | LexError::IntegerOverflow { position, .. }
+            | LexError::MalformedNumber { position, .. } => *position,
+        }
+    }
+
+    /// The error message without its position
+    pub fn message(&self) -> String {
+        match self {
+            LexError::UnexpectedChar { char, .. } => format!("Unexpected character '{}'", char),
+            LexError::UnterminatedString { .. } => "Unterminated string".to_string(),
+            LexError::InvalidEscape { char, .. } => format!("Invalid escape sequence '\\{}'", char),
+            LexError::UnexpectedEof { context, .. } => format!("Unexpected end of input {}", context),
+            LexError::BareEquals { .. } => {
+                "Unexpected '=' (did you mean '==', '!=' or ':='?)".to_string()
+            }
+            LexError::IntegerOverflow { text, .. } if text.bytes().all(|b| b.is_ascii_digit()) => {
+                format!("Integer {} is out of range (use {}.0 for a float)", text, text)
+            }
+            LexError::IntegerOverflow { text, .. } => format!("Integer {} is out of range", text),
+            LexError::MalformedNumber { text, reason, .. } => {
+                format!("Malformed number '{}': {}", text, reason)
+            }
+        }
+    }
 }
 
 impl std::fmt::Display for LexError {
@@ -53,6 +94,9 @@ impl std::fmt::Display for LexError {
             LexError::BareEquals { position } => {
                 write!(f, "Unexpected '=' at {} (did you mean '==', '!=' or ':='?)", position)
             }
+            LexError::IntegerOverflow { position, .. } | LexError::MalformedNumber { position, .. } => {
+                write!(f, "{} at {}", self.message(), position)
+            }
         }
     }
 }
@@ -64,6 +108,11 @@ pub struct Lexer {
     position: usize,
     line: usize,
     column: usize,
+    /// Where the most recently returned token starts
+    token_start: Position,
+    /// Whether the most recently returned token was `.`, after which
+    /// keywords are names (`.or(default)`)
+    after_dot: bool,
 }
 
 impl Lexer {
@@ -73,9 +122,81 @@ impl Lexer {
             position: 0,
             line: 1,
             column: 1,
+            token_start: Position::new(1, 1, 0),
+            after_dot: false,
+        }
+    }
+
+    /// Position of the first character of the most recently returned token
+    pub fn token_position(&self) -> Position {
+        self.token_start
+    }
+
+    /// Position just past the last character of the most recently returned
+    /// token
+    pub fn token_end(&self) -> Position {
+        self.current_position()
+    }
+
+    /// Text of the given 1-based source line, without its line terminator
+    pub fn line_text(&self, line: usize) -> String {
+        let text: String = self.input.iter().collect();
+        text.lines().nth(line.saturating_sub(1)).unwrap_or("").to_string()
+    }
+
+    /// Step past the character `error` was reported at, if lexing stopped
+    /// there, so the next token starts after it
+    pub(crate) fn skip_error(&mut self, error: &LexError) {
+        if self.position == error.position().offset {
+            self.advance();
         }
     }
 
+    /// Step past a bare `=` (not `==`) if it is the next token, as in a
+    /// parameter default (`&tax(rate = 0.2)`). Returns whether there was one.
+    pub(crate) fn skip_bare_equals(&mut self) -> bool {
+        self.skip_whitespace();
+        let bare = self.current_char() == Some('=') && self.peek_char(1) != Some('=');
+        if bare {
+            self.advance();
+        }
+        bare
+    }
+
+    /// Step past `->` if it is the next token, as after a named lambda's
+    /// parameter (`item -> @item[price]`). Returns whether there was one.
+    pub(crate) fn skip_arrow(&mut self) -> bool {
+        self.skip_whitespace();
+        let arrow = self.current_char() == Some('-') && self.peek_char(1) == Some('>');
+        if arrow {
+            self.advance();
+            self.advance();
+        }
+        arrow
+    }
+
+    /// Step past `::` (but not `::=`) if it is the next token, as in
+    /// `ext::name(...)`. Returns whether there was one.
+    pub(crate) fn skip_path_separator(&mut self) -> bool {
+        self.skip_whitespace();
+        let separator = self.current_char() == Some(':')
+            && self.peek_char(1) == Some(':')
+            && self.peek_char(2) != Some('=');
+        if separator {
+            self.advance();
+            self.advance();
+        }
+        separator
+    }
+
+    /// Whether `::` (but not `::=`) is the next token, without stepping past it
+    pub(crate) fn at_path_separator(&mut self) -> bool {
+        let saved = (self.position, self.line, self.column);
+        let separator = self.skip_path_separator();
+        (self.position, self.line, self.column) = saved;
+        separator
+    }
+
     fn current_char(&self) -> Option<char> {
         self.input.get(self.position).copied()
     }
@@ -169,7 +290,18 @@ impl Lexer {
         Err(LexError::UnterminatedString { position: start_pos })
     }
 
-    fn read_number(&mut self) -> Token {
+    /// A decimal integer or float (`42`, `1.5`, `1e6`, `2.5E-3`), or a hex
+    /// (`0xFF`) or octal (`0o755`) integer
+    fn read_number(&mut self) -> Result<Token, LexError> {
+        let start_pos = self.current_position();
+        if self.current_char() == Some('0') {
+            match self.peek_char(1) {
+                Some('x' | 'X') => return self.read_radix_integer(16, "hex", start_pos),
+                Some('o' | 'O') => return self.read_radix_integer(8, "octal", start_pos),
+                _ => {}
+            }
+        }
+
         let mut number = String::new();
         let mut is_float = false;
 
@@ -189,15 +321,90 @@ impl Lexer {
             }
         }
 
+        if let Some(e @ ('e' | 'E')) = self.current_char() {
+            is_float = true;
+            number.push(e);
+            self.advance();
+            if let Some(sign @ ('+' | '-')) = self.current_char() {
+                number.push(sign);
+                self.advance();
+            }
+            let mut has_digits = false;
+            while let Some(ch) = self.current_char().filter(char::is_ascii_digit) {
+                has_digits = true;
+                number.push(ch);
+                self.advance();
+            }
+            if !has_digits {
+                return Err(self.malformed_number(number, "expected digits in the exponent", start_pos));
+            }
+        }
+
         if is_float {
-            Token::Float(number.parse::<f64>().expect("Invalid float"))
+            // Digits with one interior '.' always parse (overflowing to infinity)
+            Ok(Token::Float(number.parse::<f64>().unwrap_or(f64::INFINITY)))
         } else {
-            Token::Integer(number.parse::<i64>().expect("Invalid integer"))
+            number.parse::<i64>().map(Token::Integer).map_err(|_| LexError::IntegerOverflow {
+                text: number,
+                position: start_pos,
+            })
         }
     }
 
+    /// An integer in base `radix` after its two-character prefix
+    fn read_radix_integer(
+        &mut self,
+        radix: u32,
+        name: &str,
+        start_pos: Position,
+    ) -> Result<Token, LexError> {
+        let mut text: String = self.input[self.position..self.position + 2].iter().collect();
+        self.advance();
+        self.advance();
+
+        let digits_start = text.len();
+        while let Some(ch) = self.current_char() {
+            if ch.is_digit(radix) {
+                text.push(ch);
+                self.advance();
+            } else if ch.is_ascii_alphanumeric() || ch == '_' {
+                let reason = format!("invalid {} digit '{}'", name, ch);
+                return Err(self.malformed_number(text, &reason, start_pos));
+            } else {
+                break;
+            }
+        }
+        if text.len() == digits_start {
+            let reason = format!("expected {} digits", name);
+            return Err(self.malformed_number(text, &reason, start_pos));
+        }
+
+        i64::from_str_radix(&text[digits_start..], radix)
+            .map(Token::Integer)
+            .map_err(|_| LexError::IntegerOverflow { text, position: start_pos })
+    }
+
+    /// Error for a malformed number, including the rest of the literal in its text
+    fn malformed_number(&mut self, mut text: String, reason: &str, position: Position) -> LexError {
+        while let Some(ch) = self.current_char().filter(|c| c.is_ascii_alphanumeric() || *c == '_') {
+            text.push(ch);
+            self.advance();
+        }
+        LexError::MalformedNumber { text, reason: reason.to_string(), position }
+    }
+
+    /// The next token, without consuming it
+    pub fn peek_token(&mut self) -> Result<Token, LexError> {
+        let saved = (self.position, self.line, self.column, self.token_start);
+        let token = self.next_token();
+        (self.position, self.line, self.column, self.token_start) = saved;
+        token
+    }
+
     pub fn next_token(&mut self) -> Result<Token, LexError> {
         self.skip_whitespace();
+        self.token_start = self.current_position();
+        let after_dot = std::mem::take(&mut self.after_dot);
 
         match self.current_char() {
             None => Ok(Token::Eof),
@@ -209,6 +416,10 @@ impl Lexer {
                     self.advance();
                     let name = self.read_identifier();
                     Ok(Token::EnvVar(name))
+                } else if self.peek_char(1) == Some('$') {
+                    self.advance();
+                    self.advance();
+                    Ok(Token::DoubleDollar)
                 } else {
                     self.advance();
                     Ok(Token::Dollar)
@@ -234,6 +445,7 @@ impl Lexer {
             }
             Some('.') => {
                 self.advance();
+                self.after_dot = true;
                 Ok(Token::Dot)
             }
             Some(',') => {
@@ -270,8 +482,14 @@ impl Lexer {
                 }
             }
             Some('~') => {
-                self.advance();
-                Ok(Token::Tilde)
+                if self.peek_char(1) == Some('=') {
+                    self.advance();
+                    self.advance();
+                    Ok(Token::TildeEq)
+                } else {
+                    self.advance();
+                    Ok(Token::Tilde)
+                }
             }
             Some('=') => {
                 let pos = self.current_position();
@@ -279,12 +497,21 @@ impl Lexer {
                     self.advance();
                     self.advance();
                     Ok(Token::EqEq)
+                } else if self.peek_char(1) == Some('>') {
+                    self.advance();
+                    self.advance();
+                    Ok(Token::FatArrow)
                 } else {
                     Err(LexError::BareEquals { position: pos })
                 }
             }
             Some(':') => {
-                if self.peek_char(1) == Some('=') {
+                if self.peek_char(1) == Some(':') && self.peek_char(2) == Some('=') {
+                    self.advance();
+                    self.advance();
+                    self.advance();
+                    Ok(Token::ColonColonEqual)
+                } else if self.peek_char(1) == Some('=') {
                     self.advance();
                     self.advance();
                     Ok(Token::ColonEqual)
@@ -357,6 +584,7 @@ impl Lexer {
                 let ident = self.read_identifier();
 
                 match ident.as_str() {
+                    _ if after_dot => Ok(Token::Identifier(ident)),
                     "and" => Ok(Token::And),
                     "or" => Ok(Token::Or),
                     "true" => Ok(Token::Boolean(true)),
@@ -365,7 +593,7 @@ impl Lexer {
                     _ => Ok(Token::Identifier(ident)),
                 }
             }
-            Some(ch) if ch.is_ascii_digit() => Ok(self.read_number()),
+            Some(ch) if ch.is_ascii_digit() => self.read_number(),
             Some(ch) => {
                 let pos = self.current_position();
                 Err(LexError::UnexpectedChar { char: ch, position: pos })
@@ -374,6 +602,39 @@ impl Lexer {
     }
 }
 
+/// Every token in `input` with the source it spans, for syntax
+/// highlighting. Never fails: text that does not lex becomes a
+/// [`Token::Error`] and lexing carries on after it. The end-of-input token
+/// is not included.
+///
+/// # Examples
+///
+/// ```
+/// use clove_lang::ast::{Token, TokenClass};
+/// use clove_lang::lexer::tokenize_with_spans;
+///
+/// let tokens = tokenize_with_spans("$[a] == #");
+/// let classes: Vec<_> = tokens.iter().map(|(token, _)| token.class()).collect();
+/// assert_eq!(classes[4], TokenClass::Operator);
+/// assert!(matches!(tokens[5].0, Token::Error(_)));
+/// assert_eq!(tokens[5].1.start.column, 9);
+/// ```
+pub fn tokenize_with_spans(input: &str) -> Vec<(Token, Range<Position>)> {
+    let mut lexer = Lexer::new(input);
+    let mut tokens = Vec::new();
+    loop {
+        match lexer.next_token() {
+            Ok(Token::Eof) => return tokens,
+            Ok(token) => tokens.push((token, lexer.token_position()..lexer.token_end())),
+            Err(error) => {
+                let start = lexer.token_position();
+                lexer.skip_error(&error);
+                tokens.push((Token::Error(error.message()), start..lexer.token_end()));
+            }
+        }
+    }
+}
+
 #[test]
 fn test_keywords() {
     let mut lexer = Lexer::new("and or true false null");
@@ -400,6 +661,17 @@ fn test_pipe() {
     assert_eq!(lexer.next_token().unwrap(), Token::RParen);
 }
 
+#[test]
+fn test_double_dollar() {
+    let mut lexer = Lexer::new("$$[a] $ $VAR");
+    assert_eq!(lexer.next_token().unwrap(), Token::DoubleDollar);
+    assert_eq!(lexer.next_token().unwrap(), Token::LBracket);
+    assert_eq!(lexer.next_token().unwrap(), Token::Identifier("a".to_string()));
+    assert_eq!(lexer.next_token().unwrap(), Token::RBracket);
+    assert_eq!(lexer.next_token().unwrap(), Token::Dollar);
+    assert_eq!(lexer.next_token().unwrap(), Token::EnvVar("VAR".to_string()));
+}
+
 #[test]
 fn test_double_question() {
     // ?? should produce DoubleQuestion
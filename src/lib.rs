@@ -1,17 +1,47 @@
 // pub mod tokens;
 pub mod ast;
+pub mod binary;
+mod case;
 pub mod cli;
 pub mod evaluator;
+pub mod functions;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod ide;
+pub mod infer;
+pub mod ingest;
+pub mod jq;
+pub mod jsonpath;
 pub mod lexer;
+pub mod loader;
+mod memo;
+pub mod methods;
+pub mod optimize;
 pub mod output;
 pub mod parser;
+mod random;
+pub mod regex_cache;
+#[cfg(feature = "stdlib")]
+pub mod stdlib;
+mod suggest;
+#[cfg(feature = "tracing")]
+mod telemetry;
+mod time;
 pub mod transform;
 pub mod value;
 
-pub use ast::{BinOp, Expr, Query, Statement, Token};
+pub use ast::{BinOp, Expr, Query, QueryBuilder, Statement, Token};
 pub use cli::{clove_to_json, json_to_clove};
-pub use evaluator::{EvalContext, EvalError, Evaluator};
+pub use evaluator::{
+    EnvPolicy, EvalContext, EvalError, EvalStats, Evaluator, NonFinitePolicy, OverflowPolicy, QueryOutcome,
+    StageStats, StatementTiming,
+};
+pub use ingest::{DuplicateKeys, LimitedReader, PathSelector};
 pub use lexer::{Lexer, LexError, Position};
-pub use output::{to_json, to_json_pretty};
+pub use loader::{LoadError, QueryLoader};
+pub use functions::FunctionRegistry;
+pub use methods::MethodRegistry;
+pub use output::{JsonWriter, to_json, to_json_pretty};
 pub use parser::{Parser, ParseError};
+pub use regex_cache::RegexCache;
 pub use value::Value;
@@ -0,0 +1,302 @@
+//! Loading queries that `%include` UDF libraries.
+//!
+//! A query file may start with include directives, one per line:
+//!
+//! ```text
+//! %include "lib/pricing.clv"
+//! %include "validators.clv"
+//! $ | ~($[total] := $[subtotal] * 1.2)
+//! ```
+//!
+//! An included file holds only UDF definitions (`&name:arity := ...`) and
+//! may itself include other libraries. [`QueryLoader`] resolves each path
+//! relative to the including file first, then against its search paths, and
+//! merges the definitions into [`Query::udfs`] in include order, ahead of the
+//! query's own. A library reached twice (e.g. through two other libraries) is
+//! loaded once; including a file that is already being loaded is an error.
+
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+use crate::ast::{Query, UDF};
+use crate::{DuplicateKeys, Lexer, ParseError, Parser};
+
+/// Errors from loading a query and its includes
+#[derive(Debug)]
+pub enum LoadError {
+    /// A file could not be read
+    Io { path: PathBuf, source: std::io::Error },
+    /// A query or library failed to parse (`path` is `None` for inline queries)
+    Parse { path: Option<PathBuf>, error: Box<ParseError> },
+    /// A malformed or unknown `%` directive
+    InvalidDirective { line: usize, message: String },
+    /// An included path was not found in any search location
+    NotFound { include: String, searched: Vec<PathBuf> },
+    /// A file includes itself, directly or through other libraries
+    Cycle(Vec<PathBuf>),
+    /// Two loaded files define a UDF with the same name
+    DuplicateUdf { name: String, path: Option<PathBuf> },
+}
+
+impl std::fmt::Display for LoadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LoadError::Io { path, source } => write!(f, "Cannot read {}: {}", path.display(), source),
+            LoadError::Parse { path: Some(path), error } => write!(f, "In {}: {}", path.display(), error),
+            LoadError::Parse { path: None, error } => write!(f, "{}", error),
+            LoadError::InvalidDirective { line, message } => {
+                write!(f, "Invalid directive on line {}: {}", line, message)
+            }
+            LoadError::NotFound { include, searched } => {
+                write!(f, "Included file \"{}\" not found (searched:", include)?;
+                for dir in searched {
+                    write!(f, " {}", dir.display())?;
+                }
+                write!(f, ")")
+            }
+            LoadError::Cycle(chain) => {
+                let chain: Vec<String> = chain.iter().map(|p| p.display().to_string()).collect();
+                write!(f, "Include cycle: {}", chain.join(" -> "))
+            }
+            LoadError::DuplicateUdf { name, path } => {
+                write!(f, "UDF &{} is defined more than once", name)?;
+                if let Some(path) = path {
+                    write!(f, " (again in {})", path.display())?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for LoadError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            LoadError::Io { source, .. } => Some(source),
+            LoadError::Parse { error, .. } => Some(error.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+/// Whether `source` starts with `%` directives and needs a [`QueryLoader`]
+pub fn has_directives(source: &str) -> bool {
+    source.trim_start().starts_with('%')
+}
+
+/// Loads queries, resolving `%include` directives.
+///
+/// # Examples
+///
+/// ```no_run
+/// use clove_lang::loader::QueryLoader;
+///
+/// let loader = QueryLoader::new().with_search_path("/usr/share/clove/lib");
+/// let query = loader.load_file("queries/report.clv").unwrap();
+/// println!("{} UDFs available", query.udfs.len());
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct QueryLoader {
+    search_paths: Vec<PathBuf>,
+    duplicates: DuplicateKeys,
+}
+
+/// State for one top-level load
+struct LoadState {
+    /// Files currently being loaded, outermost first
+    stack: Vec<PathBuf>,
+    /// Files already merged
+    loaded: HashSet<PathBuf>,
+    udfs: Vec<UDF>,
+}
+
+impl QueryLoader {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a directory to search for included files (searched in order
+    /// after the including file's own directory)
+    pub fn with_search_path(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.search_paths.push(dir.into());
+        self
+    }
+
+    /// Set what to do with object literals that repeat a key, in the query
+    /// and its libraries (see [`Parser::with_duplicate_keys`])
+    pub fn with_duplicate_keys(mut self, duplicates: DuplicateKeys) -> Self {
+        self.duplicates = duplicates;
+        self
+    }
+
+    fn parser(&self, body: &str) -> Result<Parser, ParseError> {
+        Ok(Parser::new(Lexer::new(body))?.with_duplicate_keys(self.duplicates))
+    }
+
+    /// Load a query file and everything it includes
+    pub fn load_file(&self, path: impl AsRef<Path>) -> Result<Query, LoadError> {
+        let path = canonical(path.as_ref())?;
+        let source = read(&path)?;
+        let mut state = LoadState {
+            stack: vec![path.clone()],
+            loaded: HashSet::new(),
+            udfs: Vec::new(),
+        };
+        self.load_query(&source, Some(&path), path.parent(), &mut state)
+    }
+
+    /// Load a query from a string; relative includes resolve against
+    /// `base_dir` (or the current directory) and then the search paths
+    pub fn load_str(&self, source: &str, base_dir: Option<&Path>) -> Result<Query, LoadError> {
+        let mut state = LoadState {
+            stack: Vec::new(),
+            loaded: HashSet::new(),
+            udfs: Vec::new(),
+        };
+        self.load_query(source, None, base_dir, &mut state)
+    }
+
+    fn load_query(
+        &self,
+        source: &str,
+        path: Option<&Path>,
+        base_dir: Option<&Path>,
+        state: &mut LoadState,
+    ) -> Result<Query, LoadError> {
+        let (includes, body) = split_directives(source)?;
+        for include in includes {
+            self.include(&include, base_dir, state)?;
+        }
+
+        let mut parser = self.parser(&body).map_err(|error| parse_error(path, error))?;
+        let mut query = parser.parse_query().map_err(|error| parse_error(path, error))?;
+        let own = std::mem::take(&mut query.udfs);
+        merge(&mut state.udfs, own, path)?;
+        query.udfs = std::mem::take(&mut state.udfs);
+        Ok(query)
+    }
+
+    fn include(&self, include: &str, base_dir: Option<&Path>, state: &mut LoadState) -> Result<(), LoadError> {
+        let path = self.resolve(include, base_dir)?;
+        if state.stack.contains(&path) {
+            let mut chain = state.stack.clone();
+            chain.push(path);
+            return Err(LoadError::Cycle(chain));
+        }
+        if !state.loaded.insert(path.clone()) {
+            return Ok(());
+        }
+
+        let source = read(&path)?;
+        let (includes, body) = split_directives(&source)?;
+        state.stack.push(path.clone());
+        for nested in includes {
+            self.include(&nested, path.parent(), state)?;
+        }
+        state.stack.pop();
+
+        let mut parser = self.parser(&body).map_err(|error| parse_error(Some(&path), error))?;
+        let udfs = parser
+            .parse_udf_library()
+            .map_err(|error| parse_error(Some(&path), error))?;
+        merge(&mut state.udfs, udfs, Some(&path))
+    }
+
+    fn resolve(&self, include: &str, base_dir: Option<&Path>) -> Result<PathBuf, LoadError> {
+        let include_path = Path::new(include);
+        if include_path.is_absolute() {
+            return canonical(include_path);
+        }
+
+        let mut searched = Vec::new();
+        let base = base_dir.map(Path::to_path_buf).unwrap_or_else(|| PathBuf::from("."));
+        for dir in std::iter::once(&base).chain(&self.search_paths) {
+            let candidate = dir.join(include_path);
+            if candidate.is_file() {
+                return canonical(&candidate);
+            }
+            searched.push(dir.clone());
+        }
+        Err(LoadError::NotFound {
+            include: include.to_string(),
+            searched,
+        })
+    }
+}
+
+/// Split leading `%include "path"` lines from the query body. Directive
+/// lines are blanked rather than removed so parse errors keep their line
+/// numbers.
+fn split_directives(source: &str) -> Result<(Vec<String>, String), LoadError> {
+    let mut includes = Vec::new();
+    let mut body = String::with_capacity(source.len());
+    let mut in_header = true;
+
+    for (index, line) in source.split_inclusive('\n').enumerate() {
+        let trimmed = line.trim();
+        if in_header && trimmed.starts_with('%') {
+            includes.push(parse_include(trimmed, index + 1)?);
+            body.push_str(if line.ends_with('\n') { "\n" } else { "" });
+            continue;
+        }
+        if !trimmed.is_empty() {
+            in_header = false;
+        }
+        body.push_str(line);
+    }
+    Ok((includes, body))
+}
+
+fn parse_include(directive: &str, line: usize) -> Result<String, LoadError> {
+    let invalid = |message: &str| LoadError::InvalidDirective {
+        line,
+        message: message.to_string(),
+    };
+    let rest = directive
+        .strip_prefix("%include")
+        .ok_or_else(|| invalid("only %include is supported"))?;
+    if !rest.starts_with(char::is_whitespace) {
+        return Err(invalid("expected %include \"path\""));
+    }
+    let path = rest
+        .trim()
+        .strip_prefix('"')
+        .and_then(|r| r.strip_suffix('"'))
+        .filter(|p| !p.is_empty() && !p.contains('"'))
+        .ok_or_else(|| invalid("expected a quoted path after %include"))?;
+    Ok(path.to_string())
+}
+
+fn merge(into: &mut Vec<UDF>, udfs: Vec<UDF>, path: Option<&Path>) -> Result<(), LoadError> {
+    for udf in udfs {
+        if into.iter().any(|existing| existing.name == udf.name) {
+            return Err(LoadError::DuplicateUdf {
+                name: udf.name,
+                path: path.map(Path::to_path_buf),
+            });
+        }
+        into.push(udf);
+    }
+    Ok(())
+}
+
+fn parse_error(path: Option<&Path>, error: ParseError) -> LoadError {
+    LoadError::Parse {
+        path: path.map(Path::to_path_buf),
+        error: Box::new(error),
+    }
+}
+
+fn read(path: &Path) -> Result<String, LoadError> {
+    std::fs::read_to_string(path).map_err(|source| LoadError::Io {
+        path: path.to_path_buf(),
+        source,
+    })
+}
+
+fn canonical(path: &Path) -> Result<PathBuf, LoadError> {
+    path.canonicalize().map_err(|source| LoadError::Io {
+        path: path.to_path_buf(),
+        source,
+    })
+}
@@ -1,6 +1,17 @@
-use clap::{Parser as ClapParser, Subcommand};
+use clap::builder::{PossibleValue, StringValueParser, TypedValueParser};
+use clap::{CommandFactory, Parser as ClapParser, Subcommand};
+use clap_complete::Shell;
 use clove_lang::cli::{self, CheckOptions, CheckResult, CliError};
-use std::io::{self, Read};
+use clove_lang::{DuplicateKeys, LimitedReader, NonFinitePolicy, Value};
+use clove_lang::binary::DataFormat;
+use clove_lang::output::{EnvPrinter, JsonWriter, NumberLexemes, OutputFormat};
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::io::{self, Read, Write};
+use std::str::FromStr;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
 
 #[derive(ClapParser)]
 #[command(name = "clove")]
@@ -11,17 +22,57 @@ struct Cli {
     command: Commands,
 }
 
+// Parsed once per run, so the size of `check`'s options does not matter
+#[allow(clippy::large_enum_variant)]
 #[derive(Subcommand)]
 enum Commands {
     /// Validate and execute a Clove query
     Check {
         /// The Clove query to execute
-        query: String,
+        #[arg(required_unless_present_any = ["query_file", "select"])]
+        query: Option<String>,
+
+        /// Read the query from FILE (may start with %include directives)
+        #[arg(short = 'f', long, value_name = "FILE", conflicts_with = "query")]
+        query_file: Option<PathBuf>,
+
+        /// Also search DIR for %include'd files (repeatable)
+        #[arg(short = 'L', long, value_name = "DIR")]
+        include_path: Vec<PathBuf>,
 
         /// JSON input (reads from stdin if not provided)
         #[arg(short, long)]
         input: Option<String>,
 
+        /// Fetch the input from URL with a GET request (needs the http
+        /// feature); a non-2xx response is an error
+        #[arg(long, value_name = "URL", conflicts_with_all = ["input", "in_place", "null_input"])]
+        input_url: Option<String>,
+
+        /// Send a header with --input-url, e.g. 'Authorization: Bearer TOKEN'
+        /// (repeatable)
+        #[arg(
+            short = 'H',
+            long,
+            value_name = "NAME: VALUE",
+            value_parser = cli::parse_header,
+            requires = "input_url"
+        )]
+        header: Vec<(String, String)>,
+
+        /// Give up on --input-url after SECONDS, body included
+        #[arg(long, value_name = "SECONDS", default_value_t = 30)]
+        timeout: u64,
+
+        /// Run the query on null without reading any input, to build a
+        /// document from literals and environment variables (like `jq -n`)
+        #[arg(
+            short = 'n',
+            long,
+            conflicts_with_all = ["input", "in_place", "ndjson", "slurp", "preserve_numbers"]
+        )]
+        null_input: bool,
+
         /// Pretty-print the output
         #[arg(short, long)]
         pretty: bool,
@@ -29,6 +80,314 @@ enum Commands {
         /// Only validate syntax, don't execute
         #[arg(long)]
         syntax_only: bool,
+
+        /// Deny the query access to environment variables
+        #[arg(long)]
+        no_env: bool,
+
+        /// Bind the string VALUE to @NAME in the query (repeatable)
+        #[arg(long, num_args = 2, value_names = ["NAME", "VALUE"])]
+        arg: Vec<String>,
+
+        /// Bind the parsed JSON value to @NAME in the query (repeatable)
+        #[arg(long, num_args = 2, value_names = ["NAME", "JSON"])]
+        argjson: Vec<String>,
+
+        /// Read JSON from FILE and write the result back to it atomically
+        #[arg(long, value_name = "FILE", conflicts_with_all = ["input", "syntax_only"])]
+        in_place: Option<PathBuf>,
+
+        /// With --in-place, keep the original file as FILE.bak
+        #[arg(long, requires = "in_place")]
+        backup: bool,
+
+        /// Reject inputs larger than SIZE (bytes, or with a K/M/G suffix)
+        #[arg(long, value_name = "SIZE", value_parser = cli::parse_byte_size)]
+        max_input_size: Option<u64>,
+
+        /// Fail when a query stage would receive a document of more than
+        /// SIZE in memory (bytes, or with a K/M/G suffix)
+        #[arg(long, value_name = "SIZE", value_parser = cli::parse_byte_size)]
+        max_document_size: Option<u64>,
+
+        /// Fail when UDF calls nest more than N deep (default 64); calls a
+        /// UDF makes to itself in tail position do not count
+        #[arg(long, value_name = "N")]
+        max_call_depth: Option<usize>,
+
+        /// Print the approximate size of the document each query stage
+        /// receives, how long it took and evaluation counters to stderr
+        #[arg(long, conflicts_with = "syntax_only")]
+        explain: bool,
+
+        /// Pretty-print JSON output with N spaces of indentation
+        #[arg(long, value_name = "N")]
+        indent: Option<usize>,
+
+        /// Escape all non-ASCII characters in JSON output as \uXXXX
+        #[arg(long)]
+        ascii: bool,
+
+        /// Canonical JSON output (RFC 8785 style) for hashing or signing
+        #[arg(long, conflicts_with_all = ["pretty", "indent", "ascii"])]
+        canonical: bool,
+
+        /// Print string results without quotes, and arrays of strings one per line
+        #[arg(short, long, conflicts_with_all = ["in_place", "canonical"])]
+        raw_output: bool,
+
+        /// Output format: json, color (plain JSON when not a terminal), table,
+        /// clove (Clove literal syntax), env (KEY=value lines from an object),
+        /// msgpack or cbor
+        #[arg(long, value_name = "FORMAT", default_value = "json", conflicts_with = "in_place")]
+        output_format: OutputKind,
+
+        /// Prefix each line of env output with `export`
+        #[arg(long)]
+        export: bool,
+
+        /// Gzip the output (needs the gzip feature); gzip and zstd input
+        /// is decompressed whether or not this is given
+        #[arg(long, conflicts_with = "in_place")]
+        compress: bool,
+
+        /// Write the output to FILE instead of stdout, replacing it only if
+        /// the check succeeds (color output is written as plain JSON)
+        #[arg(short, long, value_name = "FILE", conflicts_with = "in_place")]
+        output: Option<PathBuf>,
+
+        /// Write numbers the query left in place as the input wrote them
+        /// (`1.50`, `1e3`, integers too large for 64 bits) instead of
+        /// normalizing them
+        #[arg(long, conflicts_with = "ndjson")]
+        preserve_numbers: bool,
+
+        /// Input format: json, msgpack or cbor
+        #[arg(long, value_name = "FORMAT", default_value_t = DataFormat::Json, conflicts_with_all = ["in_place", "ndjson"])]
+        input_format: DataFormat,
+
+        /// What to do with an object that repeats a key, in the input or in
+        /// the query's object literals: error, first or last (value wins)
+        #[arg(long, value_name = "POLICY", default_value = "last")]
+        duplicate_keys: DuplicateKeys,
+
+        /// Treat QUERY as a JSONPath selector and print the matched nodes;
+        /// the Clove equivalent, when there is one, is shown on stderr
+        #[arg(long, conflicts_with = "in_place")]
+        jsonpath: bool,
+
+        /// Read newline-delimited JSON and print one result per record;
+        /// the query can read the record's line number as $meta[line]
+        #[arg(long, conflicts_with_all = ["in_place", "jsonpath"])]
+        ndjson: bool,
+
+        /// Read every JSON document in the input (e.g. NDJSON records) into
+        /// one array and run the query once on it
+        #[arg(short, long, conflicts_with = "in_place")]
+        slurp: bool,
+
+        /// Exit with status 1, printing nothing, when a filter rejects the
+        /// input (by default this prints null)
+        #[arg(long, conflicts_with_all = ["in_place", "ndjson", "syntax_only"])]
+        fail_on_null: bool,
+
+        /// Run the query exactly as written, without optimizing it
+        #[arg(long)]
+        no_optimize: bool,
+
+        /// Seed .sample(), .shuffle() and &random[] to get the same results
+        /// on every run
+        #[arg(long, value_name = "N")]
+        seed: Option<u64>,
+
+        /// Fail when .sort(), .sort_desc(), .sort_by(), .min() or .max()
+        /// would order values of different types, such as numbers and nulls
+        #[arg(long)]
+        strict_ordering: bool,
+
+        /// What arithmetic does when a result is NaN or infinite: null
+        /// (written as null, with a warning) or error
+        #[arg(long, value_name = "POLICY", default_value = "null")]
+        non_finite: NonFinitePolicy,
+
+        /// Run QUERY instead of the main query and print an object of the
+        /// results by NAME; repeat it to get several values from one parse
+        /// of the input
+        #[arg(
+            long,
+            value_name = "NAME=QUERY",
+            value_parser = cli::parse_select,
+            conflicts_with_all = ["query", "query_file", "in_place", "jsonpath", "ndjson", "explain"]
+        )]
+        select: Vec<(String, String)>,
+
+        /// Replace the values at TARGET in the result with "[REDACTED]",
+        /// where TARGET is a path as written in a delete and may contain
+        /// [*], e.g. '$[users][*][password]'; repeat it for more paths
+        #[arg(long, value_name = "TARGET")]
+        redact: Vec<String>,
+    },
+
+    /// Exit 0 if the query is truthy on the input, 1 if not (2 on errors)
+    Assert {
+        /// The Clove query to evaluate
+        #[arg(required_unless_present = "query_file")]
+        query: Option<String>,
+
+        /// Read the query from FILE (may start with %include directives)
+        #[arg(short = 'f', long, value_name = "FILE", conflicts_with = "query")]
+        query_file: Option<PathBuf>,
+
+        /// Also search DIR for %include'd files (repeatable)
+        #[arg(short = 'L', long, value_name = "DIR")]
+        include_path: Vec<PathBuf>,
+
+        /// JSON input (reads from stdin if not provided)
+        #[arg(short, long)]
+        input: Option<String>,
+
+        /// Message to print to stderr when the assertion fails
+        #[arg(short, long)]
+        message: Option<String>,
+
+        /// Deny the query access to environment variables
+        #[arg(long)]
+        no_env: bool,
+
+        /// Bind the string VALUE to @NAME in the query (repeatable)
+        #[arg(long, num_args = 2, value_names = ["NAME", "VALUE"])]
+        arg: Vec<String>,
+
+        /// Bind the parsed JSON value to @NAME in the query (repeatable)
+        #[arg(long, num_args = 2, value_names = ["NAME", "JSON"])]
+        argjson: Vec<String>,
+
+        /// Reject inputs larger than SIZE (bytes, or with a K/M/G suffix)
+        #[arg(long, value_name = "SIZE", value_parser = cli::parse_byte_size)]
+        max_input_size: Option<u64>,
+
+        /// Run the query exactly as written, without optimizing it
+        #[arg(long)]
+        no_optimize: bool,
+    },
+
+    /// Time one or more queries against the same input
+    Bench {
+        /// Clove queries to compare
+        #[arg(required = true)]
+        queries: Vec<String>,
+
+        /// JSON input (reads from stdin if not provided)
+        #[arg(short, long)]
+        input: Option<String>,
+
+        /// Measured runs per query
+        #[arg(short = 'n', long, default_value_t = 100)]
+        iterations: usize,
+
+        /// Unmeasured runs before measuring
+        #[arg(long, default_value_t = 10)]
+        warmup: usize,
+
+        /// Deny the queries access to environment variables
+        #[arg(long)]
+        no_env: bool,
+
+        /// Recompute repeated subexpressions instead of caching them
+        #[arg(long)]
+        no_memo: bool,
+
+        /// Time the queries exactly as written, without optimizing them
+        #[arg(long)]
+        no_optimize: bool,
+
+        /// Print results as JSON (one object per query, times in nanoseconds)
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Run the snapshot test cases in a directory
+    ///
+    /// Each NAME.clove query runs on NAME.input.json and its output is
+    /// compared with NAME.expected.json.
+    Test {
+        /// Directory of test cases (searched recursively)
+        dir: PathBuf,
+
+        /// Write the actual output as the snapshot of failing and new cases
+        #[arg(long)]
+        update: bool,
+
+        /// Deny the queries access to environment variables
+        #[arg(long)]
+        no_env: bool,
+    },
+
+    /// Remove, mask or hash values in JSON documents as a rules file says
+    ///
+    /// The rules file is a JSON array such as
+    /// [{"path": "$[users][*][password]", "strategy": "remove"},
+    /// {"field": "ssn", "strategy": "mask"}]. A "path" is written as in a
+    /// delete and may use [*]; a "field" matches that key at any depth.
+    /// Strategies: remove, mask (write "[REDACTED]") or hash (write the
+    /// HMAC-SHA256 hex digest under the secret key of --hash-key-file, so
+    /// guessable values such as emails can't be found by hashing guesses).
+    /// A summary of the values each rule touched goes to stderr.
+    Redact {
+        /// The rules file
+        #[arg(short, long, value_name = "FILE")]
+        rules: PathBuf,
+
+        /// JSON file to sanitize, possibly gzip or zstd compressed; it may
+        /// hold several documents, such as newline-delimited logs (reads
+        /// stdin if not given)
+        file: Option<PathBuf>,
+
+        /// JSON input instead of a file
+        #[arg(short, long, conflicts_with = "file")]
+        input: Option<String>,
+
+        /// The secret key of the hash strategy, read from FILE (without a
+        /// trailing newline); required when a rule hashes
+        #[arg(long, value_name = "FILE")]
+        hash_key_file: Option<PathBuf>,
+
+        /// Pretty-print the output
+        #[arg(short, long)]
+        pretty: bool,
+
+        /// Don't print the summary
+        #[arg(short, long)]
+        quiet: bool,
+    },
+
+    /// Work with document shapes
+    Schema {
+        #[command(subcommand)]
+        command: SchemaCommand,
+    },
+
+    /// Print a hash of the query that ignores how it is laid out, to key
+    /// caches of its results on
+    QueryHash {
+        /// The Clove query to hash
+        #[arg(required_unless_present = "query_file")]
+        query: Option<String>,
+
+        /// Read the query from FILE (may start with %include directives)
+        #[arg(short = 'f', long, value_name = "FILE", conflicts_with = "query")]
+        query_file: Option<PathBuf>,
+
+        /// Also search DIR for %include'd files (repeatable)
+        #[arg(short = 'L', long, value_name = "DIR")]
+        include_path: Vec<PathBuf>,
+    },
+
+    /// Translate a jq filter into Clove (best effort)
+    FromJq {
+        /// The jq filter to translate
+        #[arg(allow_hyphen_values = true)]
+        filter: String,
     },
 
     /// List documentation categories
@@ -37,11 +396,98 @@ enum Commands {
     /// Show documentation for a specific category
     Doc {
         /// Category name (use 'clove docs' to list categories)
+        #[arg(value_parser = DocCategoryParser, hide_possible_values = true)]
         category: String,
     },
 
     /// Interactive onboarding tutorial
     Onboard,
+
+    /// Print a shell completion script
+    ///
+    /// For example, `clove completions bash > /etc/bash_completion.d/clove`
+    /// or `clove completions zsh > "${fpath[1]}/_clove"`.
+    Completions {
+        /// The shell to complete in
+        shell: Shell,
+    },
+}
+
+/// What `--output-format` writes: text, or a binary encoding
+#[derive(Clone, Copy)]
+enum OutputKind {
+    Text(OutputFormat),
+    Binary(DataFormat),
+    /// Shell variable assignments, optionally exported
+    Env { export: bool },
+}
+
+impl FromStr for OutputKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "env" {
+            return Ok(OutputKind::Env { export: false });
+        }
+        match s.parse::<DataFormat>() {
+            Ok(DataFormat::Json) | Err(_) => s.parse().map(OutputKind::Text).map_err(|_| {
+                format!(
+                    "unknown output format '{}' (expected json, color, table, clove, env, msgpack or cbor)",
+                    s
+                )
+            }),
+            Ok(format) => Ok(OutputKind::Binary(format)),
+        }
+    }
+}
+
+/// Accepts any category name (aliases are resolved by
+/// [`cli::get_doc_category`]), offering the listed names for completion
+#[derive(Clone)]
+struct DocCategoryParser;
+
+impl TypedValueParser for DocCategoryParser {
+    type Value = String;
+
+    fn parse_ref(
+        &self,
+        cmd: &clap::Command,
+        arg: Option<&clap::Arg>,
+        value: &OsStr,
+    ) -> Result<String, clap::Error> {
+        StringValueParser::new().parse_ref(cmd, arg, value)
+    }
+
+    fn possible_values(&self) -> Option<Box<dyn Iterator<Item = PossibleValue> + '_>> {
+        Some(Box::new(
+            cli::DocCategory::ALL
+                .into_iter()
+                .map(|category| PossibleValue::new(category.name()).help(category.summary())),
+        ))
+    }
+}
+
+#[derive(Subcommand)]
+enum SchemaCommand {
+    /// Summarize field names, types, optionality and example values of
+    /// sample JSON documents
+    Infer {
+        /// JSON files to sample; each may hold several documents, such as
+        /// newline-delimited JSON (reads stdin if none are given)
+        files: Vec<PathBuf>,
+
+        /// JSON input instead of files
+        #[arg(short, long, conflicts_with = "files")]
+        input: Option<String>,
+
+        /// Print the summary as JSON instead of a tree
+        #[arg(long)]
+        json: bool,
+
+        /// Pretty-print the JSON summary
+        #[arg(short, long, requires = "json")]
+        pretty: bool,
+    },
 }
 
 fn main() {
@@ -50,10 +496,196 @@ fn main() {
     let result = match cli.command {
         Commands::Check {
             query,
+            query_file,
+            include_path,
             input,
+            input_url,
+            header,
+            timeout,
+            null_input,
             pretty,
             syntax_only,
-        } => run_check(query, input, pretty, syntax_only),
+            no_env,
+            arg,
+            argjson,
+            in_place,
+            backup,
+            max_input_size,
+            max_document_size,
+            max_call_depth,
+            explain,
+            indent,
+            ascii,
+            canonical,
+            raw_output,
+            output_format,
+            export,
+            compress,
+            output,
+            preserve_numbers,
+            input_format,
+            duplicate_keys,
+            jsonpath,
+            ndjson,
+            slurp,
+            fail_on_null,
+            no_optimize,
+            seed,
+            strict_ordering,
+            non_finite,
+            select,
+            redact,
+        } => {
+            let mut writer = match indent {
+                Some(spaces) => JsonWriter::new().indent(spaces),
+                None => JsonWriter::new().pretty(pretty),
+            }
+            .ascii_only(ascii)
+            .canonical(canonical)
+            .raw(raw_output);
+            let (query, base_dir) = match load_query_source(query, query_file) {
+                Ok(source) => source,
+                Err(e) => exit_with(e),
+            };
+            let params = match query_params(&arg, &argjson) {
+                Ok(params) => params,
+                Err(e) => exit_with(e),
+            };
+            let mut options = CheckOptions {
+                query,
+                input,
+                null_input,
+                pretty,
+                syntax_only,
+                no_env,
+                max_input_size,
+                max_document_size,
+                max_call_depth,
+                explain,
+                jsonpath,
+                include_paths: include_path,
+                base_dir,
+                meta: HashMap::new(),
+                params,
+                no_optimize,
+                seed,
+                strict_ordering,
+                non_finite,
+                slurp,
+                input_format,
+                duplicate_keys,
+                selects: select,
+                redact,
+            };
+            let output_format = match output_format {
+                OutputKind::Env { .. } => OutputKind::Env { export },
+                OutputKind::Text(OutputFormat::Color) if output.is_some() => {
+                    OutputKind::Text(OutputFormat::Json)
+                }
+                other => other,
+            };
+            let fetch = input_url.map(|url| cli::FetchRequest {
+                url,
+                headers: header,
+                timeout: Some(Duration::from_secs(timeout)),
+                max_size: max_input_size,
+            });
+            if preserve_numbers {
+                let lexemes = match scan_numbers(&mut options, in_place.as_deref(), fetch.as_ref()) {
+                    Ok(lexemes) => lexemes,
+                    Err(e) => exit_with(e),
+                };
+                writer = writer.number_lexemes(Arc::new(lexemes));
+            }
+            if jsonpath {
+                print_jsonpath_translation(&options.query);
+            }
+            match in_place {
+                Some(path) => run_check_in_place(options, &path, backup, writer),
+                None => match output_stream(output.as_deref(), compress) {
+                    // --ndjson --slurp combines the records like --slurp alone
+                    Ok(out) if ndjson && !slurp => {
+                        run_check_ndjson(options, fetch.as_ref(), output_format, writer, out)
+                    }
+                    Ok(out) => run_check(options, fetch.as_ref(), output_format, writer, fail_on_null, out),
+                    Err(e) => Err(e),
+                },
+            }
+        }
+        Commands::Assert {
+            query,
+            query_file,
+            include_path,
+            input,
+            message,
+            no_env,
+            arg,
+            argjson,
+            max_input_size,
+            no_optimize,
+        } => {
+            let source = load_query_source(query, query_file)
+                .and_then(|source| Ok((source, query_params(&arg, &argjson)?)));
+            let ((query, base_dir), params) = match source {
+                Ok(source) => source,
+                Err(e) => {
+                    eprintln!("{}", e);
+                    std::process::exit(cli::EXIT_ERROR);
+                }
+            };
+            let options = CheckOptions {
+                query,
+                input,
+                no_env,
+                params,
+                max_input_size,
+                include_paths: include_path,
+                base_dir,
+                no_optimize,
+                ..Default::default()
+            };
+            std::process::exit(run_assert(options, message));
+        }
+        Commands::Bench {
+            queries,
+            input,
+            iterations,
+            warmup,
+            no_env,
+            no_memo,
+            no_optimize,
+            json,
+        } => {
+            let options = cli::BenchOptions {
+                iterations,
+                warmup,
+                no_env,
+                memoize: !no_memo,
+                optimize: !no_optimize,
+            };
+            run_bench(&queries, input, &options, json)
+        }
+        Commands::Test { dir, update, no_env } => run_test(&dir, &cli::TestOptions { update, no_env }),
+        Commands::Redact { rules, file, input, hash_key_file, pretty, quiet } => {
+            run_redact(&rules, file, input, hash_key_file.as_deref(), pretty, quiet)
+        }
+        Commands::Schema {
+            command: SchemaCommand::Infer { files, input, json, pretty },
+        } => run_schema_infer(files, input, json, pretty),
+        Commands::QueryHash { query, query_file, include_path } => {
+            load_query_source(query, query_file).and_then(|(query, base_dir)| {
+                let options = CheckOptions { query, include_paths: include_path, base_dir, ..Default::default() };
+                println!("{}", cli::execute_query_hash(&options)?);
+                Ok(())
+            })
+        }
+        Commands::FromJq { filter } => match clove_lang::jq::translate(&filter) {
+            Ok(clove) => {
+                println!("{}", clove);
+                Ok(())
+            }
+            Err(e) => Err(CliError::Jq(e)),
+        },
         Commands::Docs => {
             print!("{}", cli::get_docs_overview());
             Ok(())
@@ -69,48 +701,434 @@ fn main() {
             print!("{}", cli::get_onboarding_content());
             Ok(())
         }
+        Commands::Completions { shell } => {
+            print_completions(shell);
+            Ok(())
+        }
     };
 
     if let Err(e) = result {
-        eprintln!("{}", e);
-        std::process::exit(1);
+        exit_with(e);
+    }
+}
+
+fn print_completions(shell: Shell) {
+    clap_complete::generate(shell, &mut Cli::command(), "clove", &mut io::stdout());
+
+    // The fish script only completes options, so add the doc categories
+    if shell == Shell::Fish {
+        for category in cli::DocCategory::ALL {
+            println!(
+                "complete -c clove -n \"__fish_clove_using_subcommand doc\" -f -a \"{}\" -d '{}'",
+                category.name(),
+                category.summary()
+            );
+        }
+    }
+}
+
+fn exit_with(e: CliError) -> ! {
+    eprintln!("{}", e);
+    std::process::exit(1);
+}
+
+/// The query parameters of `--arg` and `--argjson`, each given as NAME VALUE
+fn query_params(args: &[String], json_args: &[String]) -> Result<HashMap<String, Value>, CliError> {
+    let mut params = HashMap::new();
+    for pair in args.chunks_exact(2) {
+        params.insert(pair[0].clone(), Value::String(pair[1].clone()));
+    }
+    for pair in json_args.chunks_exact(2) {
+        let value = Value::from_reader(pair[1].as_bytes())
+            .map_err(|e| CliError::Param { name: pair[0].clone(), error: Box::new(CliError::Json(e)) })?;
+        params.insert(pair[0].clone(), value);
+    }
+    Ok(params)
+}
+
+/// The query text and the directory its relative `%include`s resolve against
+fn load_query_source(
+    query: Option<String>,
+    query_file: Option<PathBuf>,
+) -> Result<(String, Option<PathBuf>), CliError> {
+    match (query, query_file) {
+        (_, Some(path)) => {
+            let source = std::fs::read_to_string(&path).map_err(CliError::Io)?;
+            Ok((source, path.parent().map(Path::to_path_buf)))
+        }
+        (Some(query), None) => Ok((query, None)),
+        // clap requires one of the two
+        (None, None) => Ok((String::new(), None)),
     }
 }
 
 fn run_check(
-    query: String,
-    input: Option<String>,
+    options: CheckOptions,
+    fetch: Option<&cli::FetchRequest>,
+    format: OutputKind,
+    writer: JsonWriter,
+    fail_on_null: bool,
+    mut out: Output,
+) -> Result<(), CliError> {
+    let result = if options.input.is_none()
+        && !options.null_input
+        && let Some(input) = input_reader(fetch)?
+    {
+        cli::execute_check_reader(&options, input)?
+    } else {
+        cli::execute_check(&options)?
+    };
+
+    match result {
+        CheckResult::SyntaxValid => writeln!(out, "Syntax is valid")?,
+        CheckResult::Success { value, non_finite } => {
+            if non_finite {
+                warn_non_finite();
+            }
+            emit(&mut out, value, format, &writer, options.pretty)?
+        }
+        CheckResult::FilteredOut if fail_on_null => {
+            // Leave an --output file as it was
+            drop(out);
+            std::process::exit(cli::EXIT_FAILED)
+        }
+        CheckResult::FilteredOut => emit(&mut out, Value::Null, format, &writer, options.pretty)?,
+    }
+    Ok(out.finish()?.commit()?)
+}
+
+fn run_check_ndjson(
+    options: CheckOptions,
+    fetch: Option<&cli::FetchRequest>,
+    format: OutputKind,
+    writer: JsonWriter,
+    mut out: Output,
+) -> Result<(), CliError> {
+    if options.syntax_only {
+        cli::execute_check(&options)?;
+        writeln!(out, "Syntax is valid")?;
+        return Ok(out.finish()?.commit()?);
+    }
+
+    let mut warned = false;
+    let emit = |output, non_finite| {
+        if non_finite && !warned {
+            warn_non_finite();
+            warned = true;
+        }
+        emit(&mut out, output, format, &writer, options.pretty)
+    };
+    let result = match &options.input {
+        Some(text) => cli::execute_check_ndjson(&options, text.as_bytes(), emit),
+        None => match input_reader(fetch)? {
+            Some(input) => cli::execute_check_ndjson(&options, io::BufReader::new(input), emit),
+            None => Err(CliError::NoInput),
+        },
+    };
+    // Complete the gzip stream of the records written to stdout before any
+    // error; an --output file is only replaced when every record succeeded
+    let destination = out.finish()?;
+    result?;
+    Ok(destination.commit()?)
+}
+
+fn warn_non_finite() {
+    eprintln!("Warning: NaN or infinite numbers in the result were written as null");
+}
+
+/// Where a check writes its results
+type Output = cli::OutputStream<Destination>;
+
+/// The file of `--output`, or stdout, gzipped with `--compress`
+fn output_stream(path: Option<&Path>, compress: bool) -> Result<Output, CliError> {
+    let destination = match path {
+        Some(path) => Destination::File(cli::AtomicFile::create(path)?),
+        None => Destination::Stdout(io::stdout().lock()),
+    };
+    cli::OutputStream::new(destination, compress)
+}
+
+/// Stdout, or the file of `--output`, which keeps its old contents unless
+/// the check succeeds
+enum Destination {
+    Stdout(io::StdoutLock<'static>),
+    File(cli::AtomicFile),
+}
+
+impl Destination {
+    /// Put a completely written `--output` file in place
+    fn commit(self) -> io::Result<()> {
+        match self {
+            Destination::Stdout(_) => Ok(()),
+            Destination::File(file) => file.commit(),
+        }
+    }
+}
+
+impl Write for Destination {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Destination::Stdout(stdout) => stdout.write(buf),
+            Destination::File(file) => file.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Destination::Stdout(stdout) => stdout.flush(),
+            Destination::File(file) => file.flush(),
+        }
+    }
+}
+
+/// The input of a check not given with `--input`: the body of
+/// `--input-url`, or stdin unless it is a terminal, decompressed either way
+fn input_reader(fetch: Option<&cli::FetchRequest>) -> Result<Option<Box<dyn Read>>, CliError> {
+    match fetch {
+        Some(request) => cli::decompress(cli::fetch(request)?).map(Some),
+        None if !atty::is(atty::Stream::Stdin) => cli::decompress(io::stdin().lock()).map(Some),
+        None => Ok(None),
+    }
+}
+
+/// Write one result: a line of text, or the bytes of a binary encoding
+fn emit(
+    out: &mut impl Write,
+    output: Value,
+    format: OutputKind,
+    writer: &JsonWriter,
     pretty: bool,
-    syntax_only: bool,
+) -> Result<(), CliError> {
+    match format {
+        OutputKind::Text(format) => {
+            write_text(out, &output, format, writer, pretty)?;
+            writeln!(out)?;
+        }
+        OutputKind::Binary(format) => {
+            let bytes = format.encode(&output)?;
+            out.write_all(&bytes)?;
+        }
+        OutputKind::Env { export } => {
+            writeln!(out, "{}", EnvPrinter::new().export(export).print(&output)?)?
+        }
+    }
+    Ok(())
+}
+
+/// Write a result as text. JSON is streamed, so a large result is never
+/// held in memory as text as well.
+fn write_text(
+    out: &mut impl Write,
+    value: &Value,
+    format: OutputFormat,
+    writer: &JsonWriter,
+    pretty: bool,
+) -> io::Result<()> {
+    match format {
+        OutputFormat::Json => writer.write_to(out, value),
+        OutputFormat::Color => {
+            let color = atty::is(atty::Stream::Stdout) && std::env::var_os("NO_COLOR").is_none();
+            writer.clone().colored(color).write_to(out, value)
+        }
+        OutputFormat::Table | OutputFormat::Clove => {
+            out.write_all(format.formatter(pretty).format(value).as_bytes())
+        }
+    }
+}
+
+fn print_jsonpath_translation(path: &str) {
+    // Parse errors are reported by the check itself
+    if let Ok(path) = clove_lang::jsonpath::JsonPath::parse(path) {
+        match path.to_clove() {
+            Ok(clove) => eprintln!("Clove equivalent: {}", clove),
+            Err(e) => eprintln!("{}", e),
+        }
+    }
+}
+
+fn run_bench(
+    queries: &[String],
+    input: Option<String>,
+    options: &cli::BenchOptions,
+    json: bool,
 ) -> Result<(), CliError> {
     let input = match input {
-        Some(s) => Some(s),
-        None if !atty::is(atty::Stream::Stdin) => {
-            let mut buffer = String::new();
-            io::stdin().read_to_string(&mut buffer).map_err(CliError::Io)?;
-            Some(buffer)
+        Some(text) => Value::from_reader(text.as_bytes()),
+        None if !atty::is(atty::Stream::Stdin) => Value::from_reader(cli::decompress(io::stdin().lock())?),
+        None => return Err(CliError::NoInput),
+    }
+    .map_err(CliError::Json)?;
+
+    let reports = cli::execute_bench(queries, &input, options)?;
+    if json {
+        let reports: Vec<_> = reports.iter().map(cli::BenchReport::to_json).collect();
+        println!("{}", serde_json::Value::Array(reports));
+    } else {
+        for report in &reports {
+            println!("{}", report);
+        }
+    }
+    Ok(())
+}
+
+fn run_test(dir: &Path, options: &cli::TestOptions) -> Result<(), CliError> {
+    let report = cli::execute_test(dir, options)?;
+    println!("{}", report);
+    if !report.is_success() {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+fn run_schema_infer(
+    files: Vec<PathBuf>,
+    input: Option<String>,
+    json: bool,
+    pretty: bool,
+) -> Result<(), CliError> {
+    let shape = if let Some(text) = input {
+        cli::execute_schema_infer([text.as_bytes()])?
+    } else if !files.is_empty() {
+        let readers = files.iter().map(|path| cli::open_input(path)).collect::<Result<Vec<_>, _>>()?;
+        cli::execute_schema_infer(readers)?
+    } else if !atty::is(atty::Stream::Stdin) {
+        cli::execute_schema_infer([cli::decompress(io::stdin().lock())?])?
+    } else {
+        return Err(CliError::NoInput);
+    };
+
+    if shape.count == 0 {
+        return Err(CliError::NoInput);
+    }
+    if json {
+        println!("{}", JsonWriter::new().pretty(pretty).write(&shape.to_value()));
+    } else {
+        print!("{}", shape.to_tree());
+    }
+    Ok(())
+}
+
+fn run_redact(
+    rules: &Path,
+    file: Option<PathBuf>,
+    input: Option<String>,
+    hash_key_file: Option<&Path>,
+    pretty: bool,
+    quiet: bool,
+) -> Result<(), CliError> {
+    let hash_key = match hash_key_file {
+        Some(path) => {
+            let mut key = std::fs::read(path)?;
+            while key.last().is_some_and(|byte| matches!(byte, b'\n' | b'\r')) {
+                key.pop();
+            }
+            Some(key)
         }
         None => None,
     };
+    let rules = std::fs::read_to_string(rules).map_err(CliError::Io)?;
+    let rules = cli::RedactRule::parse_rules(&rules, hash_key.as_deref())?;
+    let writer = JsonWriter::new().pretty(pretty);
+    let emit = |document: Value| {
+        println!("{}", writer.write(&document));
+        Ok(())
+    };
+    let report = if let Some(text) = input {
+        cli::execute_redact(text.as_bytes(), &rules, emit)?
+    } else if let Some(path) = file {
+        cli::execute_redact(cli::open_input(&path)?, &rules, emit)?
+    } else if !atty::is(atty::Stream::Stdin) {
+        cli::execute_redact(cli::decompress(io::stdin().lock())?, &rules, emit)?
+    } else {
+        return Err(CliError::NoInput);
+    };
+
+    if report.documents == 0 {
+        return Err(CliError::NoInput);
+    }
+    if !quiet {
+        eprintln!("{}", report);
+    }
+    Ok(())
+}
 
-    let options = CheckOptions {
-        query,
-        input,
-        pretty,
-        syntax_only,
+fn run_assert(options: CheckOptions, message: Option<String>) -> i32 {
+    let result = if options.input.is_none() && !atty::is(atty::Stream::Stdin) {
+        cli::decompress(io::stdin().lock()).and_then(|input| cli::execute_assert_reader(&options, input))
+    } else {
+        cli::execute_assert(&options)
     };
 
-    match cli::execute_check(&options)? {
-        CheckResult::SyntaxValid => println!("Syntax is valid"),
-        CheckResult::Success(output) => {
-            let json = if pretty {
-                serde_json::to_string_pretty(&output)
-            } else {
-                serde_json::to_string(&output)
+    match result {
+        Ok(outcome) => {
+            if !outcome.passed
+                && let Some(message) = message
+            {
+                eprintln!("{}", message);
             }
-            .unwrap();
-            println!("{}", json);
+            outcome.exit_code()
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            cli::EXIT_ERROR
+        }
+    }
+}
+
+/// How the input of a check writes its numbers (`--preserve-numbers`).
+/// Input from stdin or `--input-url` is read up front, into `options.input`.
+fn scan_numbers(
+    options: &mut CheckOptions,
+    in_place: Option<&Path>,
+    fetch: Option<&cli::FetchRequest>,
+) -> Result<NumberLexemes, CliError> {
+    let input: Box<dyn Read> = match (in_place, &options.input) {
+        (Some(path), _) => Box::new(std::fs::File::open(path)?),
+        (None, Some(text)) => return Ok(NumberLexemes::scan(text)),
+        (None, None) => input_reader(fetch)?.ok_or(CliError::NoInput)?,
+    };
+    let text = read_text(input, options.max_input_size)?;
+    let lexemes = NumberLexemes::scan(&text);
+    if in_place.is_none() {
+        options.input = Some(text);
+    }
+    Ok(lexemes)
+}
+
+/// All of `input`, failing once it grows past `max_size` bytes
+fn read_text(input: impl Read, max_size: Option<u64>) -> Result<String, CliError> {
+    let mut text = String::new();
+    match max_size {
+        None => { input }.read_to_string(&mut text)?,
+        Some(limit) => {
+            let mut limited = LimitedReader::new(input, limit);
+            let read = limited.read_to_string(&mut text);
+            if limited.exceeded() {
+                return Err(CliError::InputTooLarge(limit));
+            }
+            read?
+        }
+    };
+    Ok(text)
+}
+
+fn run_check_in_place(
+    mut options: CheckOptions,
+    path: &Path,
+    backup: bool,
+    writer: JsonWriter,
+) -> Result<(), CliError> {
+    let file = std::fs::File::open(path).map_err(CliError::Io)?;
+    options
+        .meta
+        .insert("file".to_string(), Value::String(path.display().to_string()));
+
+    if let CheckResult::Success { value, non_finite } = cli::execute_check_reader(&options, file)? {
+        if non_finite {
+            warn_non_finite();
         }
+        let writer = writer.trailing_newline(true);
+        cli::write_in_place_with(path, backup, |file| writer.write_to(file, &value))?;
     }
     Ok(())
 }
@@ -0,0 +1,276 @@
+//! Memoization of repeated pure subexpressions within one evaluation.
+//!
+//! A pipeline such as
+//!
+//! ```text
+//! $ | ?($[items].sum(@[price]) > 100) | ~($[total] := $[items].sum(@[price]))
+//! ```
+//!
+//! computes the same aggregation twice, and `$[items].map(@[price] / $[items].sum(@[price]))`
+//! recomputes the sum for every item. Before evaluating a query the evaluator
+//! collects its *pure* method calls, whose result depends only on the
+//! document (`$`), `$meta` and environment variables: no scope or argument
+//! references, no UDF calls, no `.sample()` or `.shuffle()`, and no `@`
+//! except the one bound by their own lambda methods. Those that occur more than once, or inside a lambda, are
+//! cached under the pair (expression, hash of the document being queried),
+//! where structurally equal expressions share an entry.
+//!
+//! The cache lives for one call to `eval_query` or `eval_expression`.
+//! Disable it with [`Evaluator::with_memoization`](crate::Evaluator::with_memoization).
+
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::hash::{BuildHasher, Hash, Hasher, RandomState};
+
+use crate::ast::{Expr, Query, Statement};
+use crate::evaluator::{LAMBDA_METHODS, RANDOM_METHODS};
+use crate::value::Value;
+
+#[derive(Debug, Default)]
+pub(crate) struct Memo {
+    disabled: bool,
+    /// Cached expressions of the current query, by address, to an id shared
+    /// by structurally equal expressions
+    ids: HashMap<usize, usize>,
+    /// Hash of the document the current statement runs against, computed
+    /// on first use
+    root_hash: Cell<Option<u64>>,
+    results: RefCell<HashMap<(usize, u64), Value>>,
+    /// Keys are random per evaluator, so documents cannot be crafted to
+    /// collide
+    hasher: RandomState,
+}
+
+impl Memo {
+    pub(crate) fn set_enabled(&mut self, enabled: bool) {
+        self.disabled = !enabled;
+    }
+
+    /// Forget the previous query and find what is worth caching in `exprs`
+    pub(crate) fn prepare<'a>(&mut self, exprs: impl IntoIterator<Item = &'a Expr>) {
+        self.ids.clear();
+        self.results.get_mut().clear();
+        self.root_hash.set(None);
+        if self.disabled {
+            return;
+        }
+
+        let mut found = Vec::new();
+        for expr in exprs {
+            collect(expr, false, &mut found);
+        }
+
+        // Per structure: id, occurrences, and whether any is inside a lambda
+        let mut shapes: HashMap<String, (usize, usize, bool)> = HashMap::new();
+        let mut found_ids = Vec::with_capacity(found.len());
+        for (expr, in_lambda) in found {
+            let next_id = shapes.len();
+            let shape = shapes.entry(format!("{:?}", expr)).or_insert((next_id, 0, false));
+            shape.1 += 1;
+            shape.2 |= in_lambda;
+            found_ids.push((address(expr), shape.0));
+        }
+        let repeated: Vec<bool> = {
+            let mut by_id = vec![false; shapes.len()];
+            for (id, count, in_lambda) in shapes.into_values() {
+                by_id[id] = count > 1 || in_lambda;
+            }
+            by_id
+        };
+        self.ids = found_ids.into_iter().filter(|&(_, id)| repeated[id]).collect();
+    }
+
+    /// The document changed (a new statement started)
+    pub(crate) fn reset_root(&self) {
+        self.root_hash.set(None);
+    }
+
+    /// The cache id of `expr`, if its result is worth keeping
+    pub(crate) fn id(&self, expr: &Expr) -> Option<usize> {
+        if self.ids.is_empty() {
+            return None;
+        }
+        self.ids.get(&address(expr)).copied()
+    }
+
+    pub(crate) fn get(&self, id: usize, root: &Value) -> Option<Value> {
+        let key = (id, self.root_hash(root));
+        self.results.borrow().get(&key).cloned()
+    }
+
+    pub(crate) fn insert(&self, id: usize, root: &Value, value: Value) {
+        let key = (id, self.root_hash(root));
+        self.results.borrow_mut().insert(key, value);
+    }
+
+    fn root_hash(&self, root: &Value) -> u64 {
+        if let Some(hash) = self.root_hash.get() {
+            return hash;
+        }
+        let hash = self.hash(root);
+        self.root_hash.set(Some(hash));
+        hash
+    }
+
+    fn hash(&self, value: &Value) -> u64 {
+        let mut state = self.hasher.build_hasher();
+        self.hash_into(value, &mut state);
+        state.finish()
+    }
+
+    fn hash_into(&self, value: &Value, state: &mut impl Hasher) {
+        std::mem::discriminant(value).hash(state);
+        match value {
+            Value::Null => {}
+            Value::Boolean(b) => b.hash(state),
+            Value::Integer(n) => n.hash(state),
+            Value::Float(f) => f.to_bits().hash(state),
+            Value::String(s) => s.hash(state),
+            Value::Bytes(b) => b.hash(state),
+            Value::Array(items) => {
+                items.len().hash(state);
+                for item in items {
+                    self.hash_into(item, state);
+                }
+            }
+            Value::Object(map) => {
+                // Independent of the map's iteration order
+                let mut sum = 0u64;
+                for (key, value) in map {
+                    let mut entry = self.hasher.build_hasher();
+                    key.hash(&mut entry);
+                    self.hash_into(value, &mut entry);
+                    sum = sum.wrapping_add(entry.finish());
+                }
+                map.len().hash(state);
+                sum.hash(state);
+            }
+        }
+    }
+}
+
+/// Expressions of a query in evaluation order, for [`Memo::prepare`]
+pub(crate) fn query_exprs(query: &Query) -> Vec<&Expr> {
+    let mut exprs: Vec<&Expr> = Vec::new();
+    for statement in &query.statements {
+        statement_exprs(statement, &mut exprs);
+    }
+    exprs.extend(&query.output);
+    exprs
+}
+
+/// Add the expressions of `statement`, and of the statements inside it, to
+/// `exprs` in evaluation order
+pub(crate) fn statement_exprs<'a>(statement: &'a Statement, exprs: &mut Vec<&'a Expr>) {
+    match statement {
+        Statement::Transform { target, value } => exprs.extend([target, value]),
+        Statement::ScopeDefinition { path, .. }
+        | Statement::LiveScopeDefinition { path, .. }
+        | Statement::HoistedScope { path, .. } => exprs.push(path),
+        Statement::ExistenceCheck(expr)
+        | Statement::Filter(expr)
+        | Statement::Delete(expr)
+        | Statement::Access(expr) => exprs.push(expr),
+        Statement::When { condition: expr, statements }
+        | Statement::ForEach { path: expr, statements, .. } => {
+            exprs.push(expr);
+            for statement in statements {
+                statement_exprs(statement, exprs);
+            }
+        }
+    }
+}
+
+fn address(expr: &Expr) -> usize {
+    expr as *const Expr as usize
+}
+
+/// Collect the pure method calls in `expr`, noting whether each is inside
+/// a lambda (and so may be evaluated many times)
+fn collect<'a>(expr: &'a Expr, in_lambda: bool, found: &mut Vec<(&'a Expr, bool)>) {
+    match expr {
+        Expr::MethodCall { object, method, args } => {
+            if is_pure(expr, false) {
+                found.push((expr, in_lambda));
+            }
+            collect(object, in_lambda, found);
+            let lambda = in_lambda || LAMBDA_METHODS.contains(&method.as_str());
+            for arg in args {
+                collect(arg, lambda, found);
+            }
+        }
+        Expr::UDFCall { args, .. } | Expr::HostCall { args, .. } => {
+            args.iter().for_each(|arg| collect(arg, in_lambda, found))
+        }
+        Expr::Access { object, key } => {
+            collect(object, in_lambda, found);
+            collect(key, in_lambda, found);
+        }
+        Expr::BinaryOp { left, right, .. } | Expr::Try { body: left, fallback: right } => {
+            collect(left, in_lambda, found);
+            collect(right, in_lambda, found);
+        }
+        Expr::ExistenceCheck(inner) | Expr::Filter(inner) | Expr::Lambda { body: inner, .. } => {
+            collect(inner, in_lambda, found)
+        }
+        Expr::Match { subject, arms } => {
+            collect(subject, in_lambda, found);
+            for arm in arms {
+                arm.guard.iter().for_each(|guard| collect(guard, in_lambda, found));
+                collect(&arm.body, in_lambda, found);
+            }
+        }
+        Expr::Object(items) => items.iter().for_each(|(_, value)| collect(value, in_lambda, found)),
+        Expr::Array(items) => items.iter().for_each(|item| collect(item, in_lambda, found)),
+        _ => {}
+    }
+}
+
+/// Whether `expr` depends only on the document, `$meta` and the
+/// environment. `bound` is whether `@` refers to an item of a lambda
+/// method inside the candidate expression.
+pub(crate) fn is_pure(expr: &Expr, bound: bool) -> bool {
+    match expr {
+        Expr::Float(_)
+        | Expr::Integer(_)
+        | Expr::String(_)
+        | Expr::Boolean(_)
+        | Expr::Null
+        | Expr::Root
+        | Expr::OriginalRoot
+        | Expr::EnvVar(_)
+        | Expr::TypedEnvVar { .. }
+        | Expr::Meta
+        | Expr::Key(_)
+        | Expr::Wildcard => true,
+        Expr::LambdaParam => bound,
+        // Named and enclosing lambda items may be bound outside the candidate
+        Expr::ScopeRef(_)
+        | Expr::ArgRef(_)
+        | Expr::LambdaVar(_)
+        | Expr::OuterLambdaParam(_)
+        | Expr::UDFCall { .. } => false,
+        // Host functions may have side effects
+        Expr::HostCall { .. } => false,
+        Expr::MethodCall { object, method, args } => {
+            let lambda = bound || LAMBDA_METHODS.contains(&method.as_str());
+            !RANDOM_METHODS.contains(&method.as_str())
+                && is_pure(object, bound)
+                && args.iter().all(|arg| is_pure(arg, lambda))
+        }
+        Expr::Access { object, key } => is_pure(object, bound) && is_pure(key, bound),
+        Expr::BinaryOp { left, right, .. } | Expr::Try { body: left, fallback: right } => {
+            is_pure(left, bound) && is_pure(right, bound)
+        }
+        Expr::ExistenceCheck(inner) | Expr::Filter(inner) => is_pure(inner, bound),
+        Expr::Lambda { body, .. } => is_pure(body, bound),
+        Expr::Match { subject, arms } => {
+            is_pure(subject, bound)
+                && arms.iter().all(|arm| {
+                    arm.guard.iter().chain([&arm.body]).all(|expr| is_pure(expr, bound))
+                })
+        }
+        Expr::Object(items) => items.iter().all(|(_, value)| is_pure(value, bound)),
+        Expr::Array(items) => items.iter().all(|item| is_pure(item, bound)),
+    }
+}
@@ -0,0 +1,158 @@
+//! Methods supplied by the host application.
+//!
+//! A [`MethodRegistry`] holds native callbacks that queries call like
+//! built-in methods, `$[a].geo_distance($[b])`. Hand it to an evaluator with
+//! [`Evaluator::with_methods`](crate::Evaluator::with_methods).
+//!
+//! # Namespaces
+//!
+//! Every host method is named `namespace_name`, so that hosts and future
+//! versions of the language keep out of each other's way:
+//!
+//! - A namespace is lowercase letters and digits, starting with a letter.
+//! - It may not be the name of a built-in method, or the part of one
+//!   before an underscore (`sort` for `sort_by`, `base64`, `find`, ...).
+//! - Registering a name twice is an error.
+//!
+//! Registered methods are consulted before the built-ins, so a built-in
+//! added later under the same name does not change what a host's queries
+//! do.
+//!
+//! Arguments are evaluated before the call, with `@` as it is around the
+//! method call; host methods cannot take lambdas. Like built-ins, a call
+//! whose receiver and arguments depend only on the document may be cached
+//! within one evaluation (see [`Evaluator::with_memoization`](crate::Evaluator::with_memoization)),
+//! so a method should return the same result for the same arguments.
+//!
+//! # Examples
+//!
+//! ```
+//! use clove_lang::{clove_value, EvalError, Evaluator, Lexer, MethodRegistry, Parser, Value};
+//!
+//! let mut methods = MethodRegistry::new();
+//! methods
+//!     .register("geo", "distance", |from, args| match (from, args) {
+//!         (Value::Array(a), [Value::Array(b)]) if a.len() == 2 && b.len() == 2 => {
+//!             let coord = |v: &Value| v.as_float().unwrap_or(0.0);
+//!             let (dx, dy) = (coord(&a[0]) - coord(&b[0]), coord(&a[1]) - coord(&b[1]));
+//!             Ok(Value::Float((dx * dx + dy * dy).sqrt()))
+//!         }
+//!         _ => Err(EvalError::TypeError(".geo_distance() requires two [x, y] points".into())),
+//!     })
+//!     .unwrap();
+//!
+//! let expr = Parser::new(Lexer::new("$[a].geo_distance($[b])")).unwrap().parse().unwrap();
+//! let doc = clove_value!({"a": [0, 0], "b": [3, 4]});
+//! let distance = Evaluator::new().with_methods(methods).eval_expression(&expr, doc).unwrap();
+//! assert_eq!(distance, Value::Float(5.0));
+//! ```
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::evaluator::{EvalError, method_names};
+use crate::value::Value;
+
+/// A host method: called with the value before the dot and the evaluated
+/// arguments
+pub type HostMethod = dyn Fn(&Value, &[Value]) -> Result<Value, EvalError> + Send + Sync;
+
+/// Host methods by their full name (`namespace_name`)
+#[derive(Clone, Default)]
+pub struct MethodRegistry {
+    methods: HashMap<String, Arc<HostMethod>>,
+}
+
+/// Why a method or [host function](crate::functions) could not be registered
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RegistryError {
+    /// The name (or a method's namespace) is not made of the allowed characters
+    InvalidName(String),
+
+    /// The namespace belongs to built-in methods
+    ReservedNamespace(String),
+
+    /// A method of this full name is already registered
+    Duplicate(String),
+}
+
+impl std::fmt::Display for RegistryError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RegistryError::InvalidName(name) => write!(
+                f,
+                "Invalid name '{}': use lowercase letters, digits and underscores, starting with a letter",
+                name
+            ),
+            RegistryError::ReservedNamespace(namespace) => {
+                write!(f, "Namespace '{}' is reserved for built-in methods", namespace)
+            }
+            RegistryError::Duplicate(name) => write!(f, "Method .{}() is already registered", name),
+        }
+    }
+}
+
+impl std::error::Error for RegistryError {}
+
+impl std::fmt::Debug for MethodRegistry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut names: Vec<_> = self.names().collect();
+        names.sort_unstable();
+        f.debug_struct("MethodRegistry").field("methods", &names).finish()
+    }
+}
+
+impl MethodRegistry {
+    /// An empty registry
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register `method` as `.namespace_name(...)`.
+    ///
+    /// Fails if the namespace is not allowed (see the [module docs](self))
+    /// or the method is already registered.
+    pub fn register(
+        &mut self,
+        namespace: &str,
+        name: &str,
+        method: impl Fn(&Value, &[Value]) -> Result<Value, EvalError> + Send + Sync + 'static,
+    ) -> Result<(), RegistryError> {
+        let full_name = format!("{}_{}", namespace, name);
+        let namespace_ok = namespace.starts_with(|c: char| c.is_ascii_lowercase())
+            && namespace.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit());
+        let name_ok = !name.is_empty()
+            && name.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_');
+        if !namespace_ok || !name_ok {
+            return Err(RegistryError::InvalidName(full_name));
+        }
+        if is_reserved(namespace) {
+            return Err(RegistryError::ReservedNamespace(namespace.to_string()));
+        }
+        if self.methods.contains_key(&full_name) {
+            return Err(RegistryError::Duplicate(full_name));
+        }
+        self.methods.insert(full_name, Arc::new(method));
+        Ok(())
+    }
+
+    /// The method registered under its full name, e.g. `geo_distance`
+    pub fn get(&self, name: &str) -> Option<&HostMethod> {
+        self.methods.get(name).map(Arc::as_ref)
+    }
+
+    /// Full names of the registered methods, in no particular order
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.methods.keys().map(String::as_str)
+    }
+
+    /// Whether no methods are registered
+    pub fn is_empty(&self) -> bool {
+        self.methods.is_empty()
+    }
+}
+
+/// Whether `namespace` is a built-in method or the start of one
+fn is_reserved(namespace: &str) -> bool {
+    method_names().any(|builtin| builtin.split('_').next() == Some(namespace))
+}
@@ -0,0 +1,514 @@
+//! Rewriting parsed queries to evaluate faster.
+//!
+//! [`Query::optimize`](crate::Query::optimize) runs these passes in order:
+//!
+//! 1. **Constant folding.** Operations on literals are evaluated once:
+//!    `60 * 60 * 24` becomes `86400` and `[3, 1, 2].sort()` becomes
+//!    `[1, 2, 3]`. Operations that fail (`1 / 0`) are left to fail when the
+//!    query runs. Transform targets and deletion paths are left as written.
+//! 2. **Filter elimination.** Filter steps whose condition is always true
+//!    are removed, and `true and` / `false or` are dropped from filter
+//!    conditions.
+//! 3. **Map fusion.** Consecutive per-item transforms of the same field
+//!    become one, so the array is traversed once:
+//!    `~($[p] := @[price]) | ~($[p] := @ * 2)` becomes `~($[p] := @[price] * 2)`.
+//! 4. **Hoisting.** Parts of a lambda that do not depend on its item, such
+//!    as `$[items].sum(@[price])` in `$[items].map(@[price] / $[items].sum(@[price]))`,
+//!    are evaluated once before their statement, as a
+//!    [`Statement::HoistedScope`], instead of once per item.
+//!
+//! An optimized query gives the same results as the original. When a
+//! document would fail in several places, the error reported may differ.
+//!
+//! # Examples
+//!
+//! ```
+//! use clove_lang::{Lexer, Parser, Statement};
+//!
+//! let query = "$ | ~($[p] := @[price]) | ~($[p] := @ * 2) | ?(true)";
+//! let query = Parser::new(Lexer::new(query)).unwrap().parse_query().unwrap().optimize();
+//! assert_eq!(query.statements.len(), 1);
+//! assert!(matches!(query.statements[0], Statement::Transform { .. }));
+//! ```
+
+use crate::ast::{BinOp, Expr, Query, Statement};
+use crate::evaluator::{Evaluator, LAMBDA_METHODS, NonFinitePolicy, OverflowPolicy, RANDOM_METHODS};
+use crate::memo::is_pure;
+use crate::transform::{PathSegment, extract_path, uses_lambda_param};
+use crate::value::Value;
+
+pub(crate) fn optimize_query(query: Query) -> Query {
+    let Query { mut udfs, statements, output } = query;
+
+    for udf in &mut udfs {
+        fold_statement(&mut udf.body);
+    }
+    let statements = simplify_statements(statements);
+    let mut output = output.map(fold_constants);
+    let statements = hoist(statements, &mut output);
+    Query { udfs, statements, output }
+}
+
+/// Fold, eliminate filters and fuse maps in a sequence of statements (the
+/// pipeline, or the body of a `when`)
+fn simplify_statements(statements: Vec<Statement>) -> Vec<Statement> {
+    let mut statements: Vec<Statement> = statements
+        .into_iter()
+        .filter_map(|mut statement| {
+            fold_statement(&mut statement);
+            simplify_filter(statement)
+        })
+        .collect();
+    fuse_maps(&mut statements);
+    statements
+}
+
+/// Fold the constant subexpressions of a standalone expression (the only
+/// pass that applies outside a query)
+///
+/// ```
+/// use clove_lang::Expr;
+/// use clove_lang::optimize::optimize_expr;
+///
+/// let expr = clove_lang::Parser::new(clove_lang::Lexer::new("2 * 3 + 1")).unwrap().parse().unwrap();
+/// assert_eq!(optimize_expr(expr), Expr::Integer(7));
+/// ```
+pub fn optimize_expr(expr: Expr) -> Expr {
+    fold_constants(expr)
+}
+
+// ========================================
+// Constant Folding
+// ========================================
+
+fn fold_statement(statement: &mut Statement) {
+    match statement {
+        Statement::Transform { value, .. } => fold_in_place(value),
+        Statement::ScopeDefinition { path, .. }
+        | Statement::LiveScopeDefinition { path, .. }
+        | Statement::HoistedScope { path, .. } => fold_in_place(path),
+        Statement::ExistenceCheck(expr) | Statement::Filter(expr) | Statement::Access(expr) => {
+            fold_in_place(expr)
+        }
+        Statement::When { condition, statements } => {
+            fold_in_place(condition);
+            *statements = simplify_statements(std::mem::take(statements));
+        }
+        Statement::ForEach { statements, .. } => {
+            *statements = simplify_statements(std::mem::take(statements));
+        }
+        Statement::Delete(_) => {}
+    }
+}
+
+fn fold_in_place(expr: &mut Expr) {
+    *expr = fold_constants(std::mem::replace(expr, Expr::Null));
+}
+
+fn fold_constants(expr: Expr) -> Expr {
+    let fold = |expr: Box<Expr>| Box::new(fold_constants(*expr));
+    let expr = match expr {
+        Expr::Access { object, key } => Expr::Access { object: fold(object), key: fold(key) },
+        Expr::BinaryOp { op, left, right } => Expr::BinaryOp { op, left: fold(left), right: fold(right) },
+        Expr::MethodCall { object, method, args } => Expr::MethodCall {
+            object: fold(object),
+            method,
+            args: args.into_iter().map(fold_constants).collect(),
+        },
+        Expr::UDFCall { name, args } => Expr::UDFCall {
+            name,
+            args: args.into_iter().map(fold_constants).collect(),
+        },
+        Expr::HostCall { name, args } => Expr::HostCall {
+            name,
+            args: args.into_iter().map(fold_constants).collect(),
+        },
+        Expr::ExistenceCheck(inner) => Expr::ExistenceCheck(fold(inner)),
+        Expr::Filter(inner) => Expr::Filter(Box::new(simplify_condition(fold_constants(*inner)))),
+        Expr::Lambda { param, body } => Expr::Lambda { param, body: fold(body) },
+        Expr::Try { body, fallback } => Expr::Try { body: fold(body), fallback: fold(fallback) },
+        Expr::Object(items) => Expr::Object(
+            items.into_iter().map(|(key, value)| (key, fold_constants(value))).collect(),
+        ),
+        Expr::Array(items) => Expr::Array(items.into_iter().map(fold_constants).collect()),
+        leaf => return leaf,
+    };
+
+    let foldable = match &expr {
+        Expr::Access { object, key } => {
+            is_constant(object) && (is_constant(key) || matches!(**key, Expr::Key(_)))
+        }
+        Expr::BinaryOp { left, right, .. } => is_constant(left) && is_constant(right),
+        Expr::MethodCall { object, method, args } => {
+            !RANDOM_METHODS.contains(&method.as_str()) && is_constant(object) && args.iter().all(is_constant)
+        }
+        Expr::ExistenceCheck(inner) => is_constant(inner),
+        _ => false,
+    };
+    match foldable.then(|| evaluate(&expr)).flatten() {
+        Some(value) => Expr::from(value),
+        None => expr,
+    }
+}
+
+/// Whether `expr` is a literal value
+fn is_constant(expr: &Expr) -> bool {
+    match expr {
+        Expr::Float(_) | Expr::Integer(_) | Expr::String(_) | Expr::Boolean(_) | Expr::Null => true,
+        Expr::Array(items) => items.iter().all(is_constant),
+        Expr::Object(items) => items.iter().all(|(_, value)| is_constant(value)),
+        _ => false,
+    }
+}
+
+/// The value of a constant expression, or `None` if evaluating it fails.
+/// Integer overflow, NaN or infinite results and ordering values of
+/// different types count as failing, so the result does not depend on the
+/// evaluator's [`OverflowPolicy`], [`NonFinitePolicy`] or
+/// [strict ordering](Evaluator::with_strict_ordering).
+fn evaluate(expr: &Expr) -> Option<Value> {
+    Evaluator::new()
+        .with_overflow_policy(OverflowPolicy::Error)
+        .with_non_finite_policy(NonFinitePolicy::Error)
+        .with_strict_ordering(true)
+        .with_memoization(false)
+        .eval_expression(expr, Value::Null)
+        .ok()
+}
+
+// ========================================
+// Filter Elimination
+// ========================================
+
+/// `statement`, or `None` if it is a filter that always passes
+fn simplify_filter(statement: Statement) -> Option<Statement> {
+    match statement {
+        Statement::Filter(condition) => {
+            let condition = simplify_condition(condition);
+            let always_true = is_constant(&condition)
+                && evaluate(&condition).is_some_and(|value| value.as_bool());
+            (!always_true).then_some(Statement::Filter(condition))
+        }
+        other => Some(other),
+    }
+}
+
+/// Drop operands of `and`/`or` that cannot change a condition's truth.
+/// Both operands are always evaluated, so no errors are lost.
+fn simplify_condition(condition: Expr) -> Expr {
+    let Expr::BinaryOp { op: op @ (BinOp::And | BinOp::Or), left, right } = condition else {
+        return condition;
+    };
+    let left = simplify_condition(*left);
+    let right = simplify_condition(*right);
+    // The operand value that leaves the result to the other operand
+    let neutral = op == BinOp::And;
+    let is_neutral = |expr: &Expr| {
+        is_constant(expr) && evaluate(expr).is_some_and(|value| value.as_bool() == neutral)
+    };
+    if is_neutral(&left) {
+        right
+    } else if is_neutral(&right) {
+        left
+    } else {
+        Expr::BinaryOp { op, left: Box::new(left), right: Box::new(right) }
+    }
+}
+
+// ========================================
+// Map Fusion
+// ========================================
+
+fn fuse_maps(statements: &mut Vec<Statement>) {
+    let mut i = 0;
+    while i + 1 < statements.len() {
+        let fused = match (&statements[i], &statements[i + 1]) {
+            (
+                Statement::Transform { target, value: first },
+                Statement::Transform { target: next_target, value: second },
+            ) if target == next_target && maps_whole_array(target) => fuse(first, second)
+                .map(|value| Statement::Transform { target: target.clone(), value }),
+            _ => None,
+        };
+        match fused {
+            Some(statement) => {
+                statements[i] = statement;
+                statements.remove(i + 1);
+            }
+            None => i += 1,
+        }
+    }
+}
+
+/// Whether `@` in a per-item transform of `target` is always an item of the
+/// target array: below an array index it may instead be that element
+fn maps_whole_array(target: &Expr) -> bool {
+    extract_path(target).is_ok_and(|path| path.iter().all(|segment| matches!(segment, PathSegment::Field(_))))
+}
+
+/// One per-item transform value doing `first` then `second`, if that is
+/// equivalent
+fn fuse(first: &Expr, second: &Expr) -> Option<Expr> {
+    let per_item = |expr: &Expr| !matches!(expr, Expr::Filter(_)) && uses_lambda_param(expr);
+    if !per_item(first) || !per_item(second) || !sees_only_item(second) {
+        return None;
+    }
+    // Substituting copies `first` into each use of the item
+    match free_params(second) {
+        1 => {}
+        n if n > 1 && is_item_path(first) => {}
+        _ => return None,
+    }
+    Some(substitute(second, first))
+}
+
+/// Whether `expr` depends on nothing but its item: the document, scopes,
+/// UDF calls and host functions could observe the first transform having
+/// run, a named lambda's parameter or `@@` may be the item under another
+/// name, and a `try` would catch the first transform's errors
+fn sees_only_item(expr: &Expr) -> bool {
+    match expr {
+        Expr::Root
+        | Expr::ScopeRef(_)
+        | Expr::ArgRef(_)
+        | Expr::UDFCall { .. }
+        | Expr::HostCall { .. }
+        | Expr::Filter(_)
+        | Expr::Lambda { .. }
+        | Expr::Match { .. }
+        | Expr::Try { .. }
+        | Expr::OuterLambdaParam(_) => false,
+        Expr::Access { object, key } => sees_only_item(object) && sees_only_item(key),
+        Expr::BinaryOp { left, right, .. } => sees_only_item(left) && sees_only_item(right),
+        Expr::MethodCall { object, args, .. } => sees_only_item(object) && args.iter().all(sees_only_item),
+        Expr::ExistenceCheck(inner) => sees_only_item(inner),
+        Expr::Object(items) => items.iter().all(|(_, value)| sees_only_item(value)),
+        Expr::Array(items) => items.iter().all(sees_only_item),
+        _ => true,
+    }
+}
+
+/// Number of uses of `@` that refer to the item, rather than to the
+/// element of a lambda method inside `expr`
+fn free_params(expr: &Expr) -> usize {
+    match expr {
+        Expr::LambdaParam => 1,
+        Expr::Access { object, key } => free_params(object) + free_params(key),
+        Expr::BinaryOp { left, right, .. } => free_params(left) + free_params(right),
+        Expr::MethodCall { object, method, args } => {
+            let in_args = if LAMBDA_METHODS.contains(&method.as_str()) {
+                0
+            } else {
+                args.iter().map(free_params).sum()
+            };
+            free_params(object) + in_args
+        }
+        Expr::UDFCall { args, .. } | Expr::Array(args) => args.iter().map(free_params).sum(),
+        Expr::ExistenceCheck(inner) | Expr::Filter(inner) => free_params(inner),
+        Expr::Object(items) => items.iter().map(|(_, value)| free_params(value)).sum(),
+        _ => 0,
+    }
+}
+
+/// `@`, or a field or index of it (`@[price]`, `@[tags][0]`)
+fn is_item_path(expr: &Expr) -> bool {
+    match expr {
+        Expr::LambdaParam => true,
+        Expr::Access { object, key } => {
+            is_item_path(object)
+                && matches!(**key, Expr::Key(_) | Expr::String(_) | Expr::Integer(_))
+        }
+        _ => false,
+    }
+}
+
+/// `expr` with each use of `@` that refers to the item replaced by `item`
+fn substitute(expr: &Expr, item: &Expr) -> Expr {
+    let sub = |expr: &Expr| substitute(expr, item);
+    match expr {
+        Expr::LambdaParam => item.clone(),
+        Expr::Access { object, key } => Expr::Access {
+            object: Box::new(sub(object)),
+            key: Box::new(sub(key)),
+        },
+        Expr::BinaryOp { op, left, right } => Expr::BinaryOp {
+            op: *op,
+            left: Box::new(sub(left)),
+            right: Box::new(sub(right)),
+        },
+        Expr::MethodCall { object, method, args } => Expr::MethodCall {
+            object: Box::new(sub(object)),
+            method: method.clone(),
+            args: if LAMBDA_METHODS.contains(&method.as_str()) {
+                args.clone()
+            } else {
+                args.iter().map(sub).collect()
+            },
+        },
+        Expr::UDFCall { name, args } => Expr::UDFCall { name: name.clone(), args: args.iter().map(sub).collect() },
+        Expr::ExistenceCheck(inner) => Expr::ExistenceCheck(Box::new(sub(inner))),
+        Expr::Filter(inner) => Expr::Filter(Box::new(sub(inner))),
+        Expr::Object(items) => Expr::Object(items.iter().map(|(key, value)| (key.clone(), sub(value))).collect()),
+        Expr::Array(items) => Expr::Array(items.iter().map(sub).collect()),
+        other => other.clone(),
+    }
+}
+
+// ========================================
+// Hoisting
+// ========================================
+
+/// Hoisted expressions of one statement, with their scope names
+#[derive(Default)]
+struct Hoisted {
+    next: usize,
+    found: Vec<(Expr, String)>,
+}
+
+impl Hoisted {
+    /// Scope definitions for what was hoisted since the last call
+    fn take_definitions(&mut self) -> impl Iterator<Item = Statement> + use<> {
+        std::mem::take(&mut self.found)
+            .into_iter()
+            .map(|(path, name)| Statement::HoistedScope { name, path })
+    }
+
+    fn name_for(&mut self, expr: Expr) -> String {
+        if let Some((_, name)) = self.found.iter().find(|(found, _)| *found == expr) {
+            return name.clone();
+        }
+        let name = format!("%{}", self.next);
+        self.next += 1;
+        self.found.push((expr, name.clone()));
+        name
+    }
+
+    /// Hoist out of the lambdas inside `expr`
+    fn outside_lambda(&mut self, expr: Expr) -> Expr {
+        match expr {
+            Expr::MethodCall { object, method, args } => {
+                let lambda = LAMBDA_METHODS.contains(&method.as_str());
+                Expr::MethodCall {
+                    object: Box::new(self.outside_lambda(*object)),
+                    args: args
+                        .into_iter()
+                        .map(|arg| if lambda { self.in_lambda(arg) } else { self.outside_lambda(arg) })
+                        .collect(),
+                    method,
+                }
+            }
+            other => self.map_children(other, Self::outside_lambda),
+        }
+    }
+
+    /// Hoist the parts of lambda body `expr` that do not depend on its item
+    fn in_lambda(&mut self, expr: Expr) -> Expr {
+        if is_hoistable(&expr) {
+            return Expr::ScopeRef(self.name_for(expr));
+        }
+        match expr {
+            Expr::MethodCall { object, method, args } => Expr::MethodCall {
+                object: Box::new(self.in_lambda(*object)),
+                args: args.into_iter().map(|arg| self.in_lambda(arg)).collect(),
+                method,
+            },
+            other => self.map_children(other, Self::in_lambda),
+        }
+    }
+
+    /// A per-item transform value: hoist within it, unless that would leave
+    /// no use of `@` and so turn the transform into a plain assignment
+    fn transform_value(&mut self, value: Expr) -> Expr {
+        match value {
+            Expr::Filter(condition) => Expr::Filter(Box::new(self.in_lambda(*condition))),
+            value if uses_lambda_param(&value) => {
+                let (next, found) = (self.next, self.found.len());
+                let hoisted = self.map_children(value.clone(), Self::in_lambda);
+                if uses_lambda_param(&hoisted) {
+                    hoisted
+                } else {
+                    self.next = next;
+                    self.found.truncate(found);
+                    value
+                }
+            }
+            value => self.outside_lambda(value),
+        }
+    }
+
+    fn map_children(&mut self, expr: Expr, mut f: impl FnMut(&mut Self, Expr) -> Expr) -> Expr {
+        match expr {
+            Expr::Access { object, key } => Expr::Access {
+                object: Box::new(f(self, *object)),
+                key: Box::new(f(self, *key)),
+            },
+            Expr::BinaryOp { op, left, right } => Expr::BinaryOp {
+                op,
+                left: Box::new(f(self, *left)),
+                right: Box::new(f(self, *right)),
+            },
+            Expr::MethodCall { object, method, args } => Expr::MethodCall {
+                object: Box::new(f(self, *object)),
+                args: args.into_iter().map(|arg| f(self, arg)).collect(),
+                method,
+            },
+            Expr::UDFCall { name, args } => Expr::UDFCall {
+                name,
+                args: args.into_iter().map(|arg| f(self, arg)).collect(),
+            },
+            Expr::ExistenceCheck(inner) => Expr::ExistenceCheck(Box::new(f(self, *inner))),
+            Expr::Filter(inner) => Expr::Filter(Box::new(f(self, *inner))),
+            Expr::Object(items) => {
+                Expr::Object(items.into_iter().map(|(key, value)| (key, f(self, value))).collect())
+            }
+            Expr::Array(items) => Expr::Array(items.into_iter().map(|item| f(self, item)).collect()),
+            leaf => leaf,
+        }
+    }
+}
+
+/// Worth evaluating once instead of per item: a computation that depends
+/// only on the document, `$meta` and the environment
+fn is_hoistable(expr: &Expr) -> bool {
+    matches!(
+        expr,
+        Expr::Access { .. }
+            | Expr::BinaryOp { .. }
+            | Expr::MethodCall { .. }
+            | Expr::ExistenceCheck(_)
+            | Expr::Object(_)
+            | Expr::Array(_)
+    ) && !is_constant(expr)
+        && is_pure(expr, false)
+}
+
+fn hoist(statements: Vec<Statement>, output: &mut Option<Expr>) -> Vec<Statement> {
+    let mut hoisted = Hoisted::default();
+    let mut result = Vec::with_capacity(statements.len());
+    for statement in statements {
+        let statement = match statement {
+            Statement::Filter(expr) => Statement::Filter(hoisted.outside_lambda(expr)),
+            Statement::Access(expr) => Statement::Access(hoisted.outside_lambda(expr)),
+            Statement::ExistenceCheck(expr) => Statement::ExistenceCheck(hoisted.outside_lambda(expr)),
+            Statement::ScopeDefinition { name, path } => Statement::ScopeDefinition {
+                name,
+                path: hoisted.outside_lambda(path),
+            },
+            Statement::Transform { target, value } => Statement::Transform {
+                target,
+                value: hoisted.transform_value(value),
+            },
+            // A live scope runs against the document as it is when referenced,
+            // a `when` body only if its condition holds, and a `foreach` body
+            // once per element
+            other => other,
+        };
+        result.extend(hoisted.take_definitions());
+        result.push(statement);
+    }
+    if let Some(expr) = output.take() {
+        *output = Some(hoisted.outside_lambda(expr));
+        result.extend(hoisted.take_definitions());
+    }
+    result
+}
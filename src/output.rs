@@ -8,9 +8,36 @@
 //!
 //! - **Compact output** via [`to_json()`] - minimal whitespace for efficient transmission
 //! - **Pretty output** via [`to_json_pretty()`] - human-readable with 2-space indentation
+//! - **Configurable output** via [`JsonWriter`] - custom indent, ASCII escaping,
+//!   trailing newline and canonical (RFC 8785 style) output for hashing/signing
+//! - **Streaming** via [`JsonWriter::write_to`] - writes to any [`Write`](std::io::Write)
+//!   in chunks rather than building the whole text in memory
 //! - **String escaping** - handles special characters, control codes, and Unicode
 //! - **Type preservation** - maintains distinction between integers and floats
 //! - **Deterministic** - object keys are always sorted alphabetically
+//! - **Pluggable formats** - [`OutputFormatter`] implementations for plain JSON,
+//!   ANSI-colored JSON ([`JsonPrinter::colored`]) and aligned tables ([`TablePrinter`])
+//! - **Number preservation** via [`NumberLexemes`] - numbers a query left in
+//!   place written as the input wrote them (`1.50`, `1e3`, integers beyond `i64`)
+//! - **Shell variables** via [`EnvPrinter`] - `KEY=value` lines for `eval` or `source`
+//! - **Clove literals** via [`to_clove()`] - the query language's own syntax,
+//!   which parses and evaluates back to the same value
+//!
+//! # Validity
+//!
+//! Without [`JsonWriter::colored`] or [`JsonWriter::raw`], every writer
+//! produces valid JSON (RFC 8259) for every [`Value`]:
+//!
+//! - Object keys are escaped exactly like string values, so keys with
+//!   quotes, backslashes or control characters are safe.
+//! - Control characters (C0, DEL and C1) are always escaped; canonical
+//!   output escapes only the C0 characters JSON requires.
+//! - Text is always valid UTF-8 (invalid bytes from lossy conversions have
+//!   already become U+FFFD) and is written as is, or as `\uXXXX` escapes
+//!   with surrogate pairs when ASCII-only.
+//! - NaN and infinities, which JSON cannot represent, are written as `null`.
+//! - Bytes, which JSON has no type for, are written as base64 strings.
+//! - Floats use the shortest form that parses back to the same value.
 //!
 //! # Examples
 //!
@@ -27,130 +54,1113 @@
 //! assert_eq!(to_json_pretty(&value), "42");
 //! ```
 
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+use std::io;
+use std::str::FromStr;
+use std::sync::Arc;
+
 use crate::value::Value;
 
-pub struct JsonPrinter {
+/// A way of rendering a query result as text.
+///
+/// Implemented by [`JsonPrinter`] and [`TablePrinter`]; see [`OutputFormat`]
+/// for picking one by name.
+pub trait OutputFormatter {
+    fn format(&self, value: &Value) -> String;
+}
+
+/// Output formats selectable by name (e.g. `--output-format table`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OutputFormat {
+    /// Plain JSON
+    #[default]
+    Json,
+    /// JSON with ANSI syntax highlighting
+    Color,
+    /// Arrays of flat objects as aligned columns; anything else as JSON
+    Table,
+    /// Clove literal syntax (see [`to_clove()`])
+    Clove,
+}
+
+impl OutputFormat {
+    /// Build the formatter for this format
+    pub fn formatter(self, pretty: bool) -> Box<dyn OutputFormatter> {
+        match self {
+            OutputFormat::Json => Box::new(JsonPrinter::new(pretty)),
+            OutputFormat::Color => Box::new(JsonPrinter::new(pretty).colored()),
+            OutputFormat::Table => Box::new(TablePrinter::new(pretty)),
+            OutputFormat::Clove => Box::new(ClovePrinter::new(pretty)),
+        }
+    }
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "json" => Ok(OutputFormat::Json),
+            "color" => Ok(OutputFormat::Color),
+            "table" => Ok(OutputFormat::Table),
+            "clove" => Ok(OutputFormat::Clove),
+            other => Err(format!(
+                "unknown output format '{}' (expected json, color, table or clove)",
+                other
+            )),
+        }
+    }
+}
+
+impl fmt::Display for OutputFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            OutputFormat::Json => "json",
+            OutputFormat::Color => "color",
+            OutputFormat::Table => "table",
+            OutputFormat::Clove => "clove",
+        })
+    }
+}
+
+/// What raw output prints for a string or a non-empty array of strings
+fn raw_text(value: &Value) -> Option<String> {
+    match value {
+        Value::String(s) => Some(s.clone()),
+        Value::Array(items) if !items.is_empty() => items
+            .iter()
+            .map(|item| match item {
+                Value::String(s) => Some(s.as_str()),
+                _ => None,
+            })
+            .collect::<Option<Vec<_>>>()
+            .map(|lines| lines.join("\n")),
+        _ => None,
+    }
+}
+
+// ANSI styles used by colored output
+const STYLE_KEY: &str = "\x1b[1;34m";
+const STYLE_STRING: &str = "\x1b[32m";
+const STYLE_NUMBER: &str = "\x1b[36m";
+const STYLE_BOOLEAN: &str = "\x1b[33m";
+const STYLE_NULL: &str = "\x1b[90m";
+const STYLE_RESET: &str = "\x1b[0m";
+
+/// Configurable JSON serializer.
+///
+/// Defaults to compact output with sorted keys, matching [`to_json()`].
+///
+/// # Examples
+///
+/// ```
+/// use clove_lang::Value;
+/// use clove_lang::output::JsonWriter;
+///
+/// let value = Value::Array(vec![Value::String("café".into()), Value::Float(1.0)]);
+///
+/// assert_eq!(JsonWriter::new().write(&value), r#"["café",1.0]"#);
+/// assert_eq!(JsonWriter::new().ascii_only(true).write(&value), r#"["caf\u00e9",1.0]"#);
+/// assert_eq!(JsonWriter::new().canonical(true).write(&value), r#"["café",1]"#);
+/// assert_eq!(JsonWriter::new().indent(4).write(&value), "[\n    \"café\",\n    1.0\n]");
+/// ```
+#[derive(Debug, Clone)]
+pub struct JsonWriter {
+    indent: Option<String>,
+    ascii_only: bool,
+    sort_keys: bool,
+    trailing_newline: bool,
+    canonical: bool,
+    color: bool,
+    raw: bool,
+    numbers: Option<Arc<NumberLexemes>>,
+}
+
+impl Default for JsonWriter {
+    fn default() -> Self {
+        JsonWriter::new()
+    }
+}
+
+impl JsonWriter {
+    /// Compact output with sorted keys
+    pub fn new() -> Self {
+        JsonWriter {
+            indent: None,
+            ascii_only: false,
+            sort_keys: true,
+            trailing_newline: false,
+            canonical: false,
+            color: false,
+            raw: false,
+            numbers: None,
+        }
+    }
+
+    /// Pretty-print with 2-space indentation (or compact when `false`)
+    pub fn pretty(self, pretty: bool) -> Self {
+        if pretty { self.indent(2) } else { self.compact() }
+    }
+
+    /// Pretty-print with `spaces` spaces per level
+    pub fn indent(mut self, spaces: usize) -> Self {
+        self.indent = Some(" ".repeat(spaces));
+        self
+    }
+
+    /// Print without any insignificant whitespace
+    pub fn compact(mut self) -> Self {
+        self.indent = None;
+        self
+    }
+
+    /// Escape every non-ASCII character as `\uXXXX` (surrogate pairs above U+FFFF)
+    pub fn ascii_only(mut self, ascii_only: bool) -> Self {
+        self.ascii_only = ascii_only;
+        self
+    }
+
+    /// Sort object keys (the default); otherwise keys follow map iteration order
+    pub fn sort_keys(mut self, sort_keys: bool) -> Self {
+        self.sort_keys = sort_keys;
+        self
+    }
+
+    /// End the output with a newline
+    pub fn trailing_newline(mut self, trailing_newline: bool) -> Self {
+        self.trailing_newline = trailing_newline;
+        self
+    }
+
+    /// Canonical output in the style of RFC 8785 (JSON Canonicalization Scheme).
+    ///
+    /// Produces byte-identical output for equal values, suitable for hashing
+    /// or signing: no whitespace, keys sorted by UTF-16 code units, minimal
+    /// string escaping and ECMAScript number formatting (`1.0` prints as `1`,
+    /// `1e21` as `1e+21`). Overrides indentation, ASCII escaping, key order
+    /// and color.
+    pub fn canonical(mut self, canonical: bool) -> Self {
+        self.canonical = canonical;
+        self
+    }
+
+    /// Highlight keys, strings, numbers, booleans and null with ANSI colors
+    pub fn colored(mut self, color: bool) -> Self {
+        self.color = color;
+        self
+    }
+
+    /// Print a string result without quotes or escaping, and an array of
+    /// strings as one line per element (like jq's `--raw-output`). Other
+    /// values are written as JSON.
+    ///
+    /// ```
+    /// use clove_lang::Value;
+    /// use clove_lang::output::JsonWriter;
+    ///
+    /// let names = Value::Array(vec![Value::String("a b".into()), Value::String("c".into())]);
+    /// assert_eq!(JsonWriter::new().raw(true).write(&names), "a b\nc");
+    /// assert_eq!(JsonWriter::new().raw(true).write(&Value::Integer(1)), "1");
+    /// ```
+    pub fn raw(mut self, raw: bool) -> Self {
+        self.raw = raw;
+        self
+    }
+
+    /// Write numbers the way `numbers` says the input wrote them, e.g.
+    /// `1.50` or `1e3` rather than `1.5` or `1000.0` (ignored by canonical
+    /// output). See [`NumberLexemes`].
+    pub fn number_lexemes(mut self, numbers: Arc<NumberLexemes>) -> Self {
+        self.numbers = Some(numbers);
+        self
+    }
+
+    /// Serialize `value` with the configured options
+    pub fn write(&self, value: &Value) -> String {
+        let mut out = Chunks { buf: String::new(), sink: None };
+        // Without a sink nothing is written out, so nothing can fail
+        let _ = self.write_chunks(&mut out, value);
+        out.buf
+    }
+
+    /// Serialize `value` with the configured options straight to `sink`.
+    ///
+    /// Writes the same text as [`write`](Self::write), a chunk of about
+    /// [`CHUNK_SIZE`] bytes at a time, so a large result never has to be
+    /// held in memory as text.
+    ///
+    /// ```
+    /// use clove_lang::Value;
+    /// use clove_lang::output::JsonWriter;
+    ///
+    /// let value = Value::Array(vec![Value::Integer(1), Value::Null]);
+    /// let mut out = Vec::new();
+    /// JsonWriter::new().write_to(&mut out, &value).unwrap();
+    /// assert_eq!(out, b"[1,null]");
+    /// ```
+    pub fn write_to(&self, sink: &mut dyn io::Write, value: &Value) -> io::Result<()> {
+        let mut out = Chunks { buf: String::with_capacity(CHUNK_SIZE), sink: Some(sink) };
+        self.write_chunks(&mut out, value)?;
+        out.flush()
+    }
+
+    fn write_chunks(&self, out: &mut Chunks<'_>, value: &Value) -> io::Result<()> {
+        if self.raw
+            && let Some(text) = raw_text(value)
+        {
+            out.push_str(&text);
+        } else {
+            let numbers = match &self.numbers {
+                Some(numbers) if !self.canonical => &numbers.root,
+                _ => &PLAIN,
+            };
+            self.write_value(out, value, 0, numbers)?;
+        }
+        if self.trailing_newline {
+            out.push('\n');
+        }
+        Ok(())
+    }
+
+    fn write_value(
+        &self,
+        out: &mut Chunks<'_>,
+        value: &Value,
+        depth: usize,
+        numbers: &Lexemes,
+    ) -> io::Result<()> {
+        match value {
+            Value::Null => self.paint(out, STYLE_NULL, "null"),
+            Value::Boolean(b) => self.paint(out, STYLE_BOOLEAN, if *b { "true" } else { "false" }),
+            Value::Integer(n) => self.write_number(out, n.to_string(), numbers.number(value)),
+            Value::Float(n) => {
+                let text = if self.canonical {
+                    format_float_canonical(*n)
+                } else {
+                    format_float(*n)
+                };
+                self.write_number(out, text, numbers.number(value))
+            }
+            Value::String(s) => self.paint(out, STYLE_STRING, &self.quote(s)),
+            Value::Bytes(b) => self.paint(out, STYLE_STRING, &self.quote(&crate::value::encode_base64(b))),
+            Value::Array(arr) => return self.write_array(out, arr, depth, numbers),
+            Value::Object(obj) => return self.write_object(out, obj, depth, numbers),
+        }
+        Ok(())
+    }
+
+    /// Write a number, as the input wrote it when preserving numbers
+    fn write_number(&self, out: &mut Chunks<'_>, text: String, lexeme: Option<&str>) {
+        self.paint(out, STYLE_NUMBER, lexeme.unwrap_or(&text))
+    }
+
+    fn write_array(
+        &self,
+        out: &mut Chunks<'_>,
+        arr: &[Value],
+        depth: usize,
+        numbers: &Lexemes,
+    ) -> io::Result<()> {
+        if arr.is_empty() {
+            out.push_str("[]");
+            return Ok(());
+        }
+
+        out.push('[');
+        for (i, item) in arr.iter().enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            self.newline(out, depth + 1);
+            self.write_value(out, item, depth + 1, numbers.item(i))?;
+            out.spill()?;
+        }
+        self.newline(out, depth);
+        out.push(']');
+        Ok(())
+    }
+
+    fn write_object(
+        &self,
+        out: &mut Chunks<'_>,
+        obj: &HashMap<String, Value>,
+        depth: usize,
+        numbers: &Lexemes,
+    ) -> io::Result<()> {
+        if obj.is_empty() {
+            out.push_str("{}");
+            return Ok(());
+        }
+
+        let mut keys: Vec<&String> = obj.keys().collect();
+        if self.canonical {
+            keys.sort_by(|a, b| a.encode_utf16().cmp(b.encode_utf16()));
+        } else if self.sort_keys {
+            keys.sort();
+        }
+
+        out.push('{');
+        for (i, key) in keys.into_iter().enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            self.newline(out, depth + 1);
+            self.paint(out, STYLE_KEY, &self.quote(key));
+            out.push(':');
+            if self.indent_unit().is_some() {
+                out.push(' ');
+            }
+            self.write_value(out, &obj[key], depth + 1, numbers.field(key))?;
+            out.spill()?;
+        }
+        self.newline(out, depth);
+        out.push('}');
+        Ok(())
+    }
+
+    fn indent_unit(&self) -> Option<&str> {
+        if self.canonical { None } else { self.indent.as_deref() }
+    }
+
+    fn newline(&self, out: &mut Chunks<'_>, depth: usize) {
+        if let Some(unit) = self.indent_unit() {
+            out.push('\n');
+            for _ in 0..depth {
+                out.push_str(unit);
+            }
+        }
+    }
+
+    fn paint(&self, out: &mut Chunks<'_>, style: &str, text: &str) {
+        if self.color && !self.canonical {
+            out.push_str(style);
+            out.push_str(text);
+            out.push_str(STYLE_RESET);
+        } else {
+            out.push_str(text);
+        }
+    }
+
+    fn quote(&self, s: &str) -> String {
+        let mut out = String::with_capacity(s.len() + 2);
+        out.push('"');
+        for c in s.chars() {
+            match c {
+                '"' => out.push_str("\\\""),
+                '\\' => out.push_str("\\\\"),
+                '\n' => out.push_str("\\n"),
+                '\r' => out.push_str("\\r"),
+                '\t' => out.push_str("\\t"),
+                '\u{8}' if self.canonical => out.push_str("\\b"),
+                '\u{c}' if self.canonical => out.push_str("\\f"),
+                // Canonical form only escapes what JSON requires
+                c if self.canonical && (c as u32) < 0x20 => {
+                    out.push_str(&format!("\\u{:04x}", c as u32))
+                }
+                c if !self.canonical && (c.is_control() || (self.ascii_only && !c.is_ascii())) => {
+                    let mut units = [0u16; 2];
+                    for unit in c.encode_utf16(&mut units) {
+                        out.push_str(&format!("\\u{:04x}", unit));
+                    }
+                }
+                c => out.push(c),
+            }
+        }
+        out.push('"');
+        out
+    }
+}
+
+/// How much text [`JsonWriter::write_to`] collects before writing it out
+pub const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Text being written by a [`JsonWriter`]: all of it when there is no sink,
+/// otherwise what has not been written to the sink yet
+struct Chunks<'w> {
+    buf: String,
+    sink: Option<&'w mut dyn io::Write>,
+}
+
+impl Chunks<'_> {
+    fn push(&mut self, c: char) {
+        self.buf.push(c);
+    }
+
+    fn push_str(&mut self, s: &str) {
+        self.buf.push_str(s);
+    }
+
+    /// Write the text out if there is a chunk's worth. Called between
+    /// array elements and object entries, so a chunk only grows past
+    /// [`CHUNK_SIZE`] by one element.
+    fn spill(&mut self) -> io::Result<()> {
+        if self.buf.len() >= CHUNK_SIZE {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if let Some(sink) = &mut self.sink {
+            sink.write_all(self.buf.as_bytes())?;
+            self.buf.clear();
+        }
+        Ok(())
+    }
+}
+
+/// Shortest round-tripping form that still reads back as a float (`1.0`, `1e300`)
+fn format_float(n: f64) -> String {
+    if n.is_finite() {
+        format!("{:?}", n)
+    } else {
+        "null".to_string()
+    }
+}
+
+/// ECMAScript `Number.prototype.toString` formatting, as required by RFC 8785
+fn format_float_canonical(n: f64) -> String {
+    if !n.is_finite() {
+        return "null".to_string();
+    }
+    if n == 0.0 {
+        return "0".to_string();
+    }
+
+    // `{:e}` yields the shortest round-tripping digits, e.g. "-1.2345e-7"
+    let sci = format!("{:e}", n.abs());
+    let (mantissa, exponent) = sci.split_once('e').unwrap();
+    let digits: String = mantissa.chars().filter(|c| *c != '.').collect();
+    let exponent: i32 = exponent.parse().unwrap();
+    let k = digits.len() as i32;
+    let point = exponent + 1;
+
+    let body = if k <= point && point <= 21 {
+        format!("{}{}", digits, "0".repeat((point - k) as usize))
+    } else if 0 < point && point <= 21 {
+        format!("{}.{}", &digits[..point as usize], &digits[point as usize..])
+    } else if -6 < point && point <= 0 {
+        format!("0.{}{}", "0".repeat((-point) as usize), digits)
+    } else {
+        let sign = if point - 1 < 0 { '-' } else { '+' };
+        let mantissa = if k == 1 {
+            digits
+        } else {
+            format!("{}.{}", &digits[..1], &digits[1..])
+        };
+        format!("{}e{}{}", mantissa, sign, (point - 1).abs())
+    };
+
+    if n < 0.0 { format!("-{}", body) } else { body }
+}
+
+/// Printer for Clove literal syntax, used by [`to_clove()`] and the `clove`
+/// output format.
+///
+/// Object keys are sorted and written bare when they lex as identifiers,
+/// strings are single-quoted, and values JSON has no literal for are
+/// written as expressions that evaluate to them: bytes as
+/// `'...'.base64_decode()`, infinities as `1e999` and NaN as
+/// `(1e999 - 1e999)`.
+///
+/// ```
+/// use clove_lang::clove_value;
+/// use clove_lang::output::ClovePrinter;
+///
+/// let value = clove_value!({"name": "it's", "tags": ["a"]});
+/// assert_eq!(ClovePrinter::new(false).print(&value), r"{name: 'it\'s', tags: ['a']}");
+/// assert_eq!(ClovePrinter::new(true).print(&value), "{\n  name: 'it\\'s',\n  tags: [\n    'a'\n  ]\n}");
+/// ```
+pub struct ClovePrinter {
     pretty: bool,
 }
 
-impl JsonPrinter {
+impl ClovePrinter {
+    /// One line, or with `pretty` one element or entry per line
     pub fn new(pretty: bool) -> Self {
-        JsonPrinter { pretty }
+        ClovePrinter { pretty }
     }
 
     pub fn print(&self, value: &Value) -> String {
-        self.print_value(value, 0)
+        let mut out = String::new();
+        self.write_value(&mut out, value, 0);
+        out
     }
 
-    fn print_value(&self, value: &Value, indent: usize) -> String {
+    fn write_value(&self, out: &mut String, value: &Value, depth: usize) {
         match value {
-            Value::Null => "null".to_string(),
-            Value::Boolean(b) => b.to_string(),
-            Value::Integer(n) => n.to_string(),
-            Value::Float(n) => n.to_string(),
-            Value::String(s) => {
-                // Escape special characters
-                format!("\"{}\"", self.escape_string(s))
+            Value::Null => out.push_str("null"),
+            Value::Boolean(b) => out.push_str(if *b { "true" } else { "false" }),
+            // The literal -9223372036854775808 is out of range before negation
+            Value::Integer(i64::MIN) => out.push_str("(-9223372036854775807 - 1)"),
+            Value::Integer(n) => out.push_str(&n.to_string()),
+            Value::Float(n) if n.is_nan() => out.push_str("(1e999 - 1e999)"),
+            Value::Float(n) if n.is_infinite() => out.push_str(if *n > 0.0 { "1e999" } else { "-1e999" }),
+            Value::Float(n) => out.push_str(&format_float(*n)),
+            Value::String(s) => out.push_str(&quote_clove(s)),
+            Value::Bytes(b) => {
+                out.push_str(&quote_clove(&crate::value::encode_base64(b)));
+                out.push_str(".base64_decode()");
+            }
+            Value::Array(arr) => {
+                self.write_items(out, '[', ']', arr.iter().map(|item| (None, item)), depth)
+            }
+            Value::Object(obj) => {
+                let mut entries: Vec<(&String, &Value)> = obj.iter().collect();
+                entries.sort_by(|a, b| a.0.cmp(b.0));
+                let entries = entries.into_iter().map(|(key, value)| (Some(key.as_str()), value));
+                self.write_items(out, '{', '}', entries, depth)
             }
-            Value::Array(arr) => self.print_array(arr, indent),
-            Value::Object(obj) => self.print_object(obj, indent),
         }
     }
 
-    fn print_array(&self, arr: &[Value], indent: usize) -> String {
-        if arr.is_empty() {
-            return "[]".to_string();
+    /// Write array elements (no key) or object entries between brackets
+    fn write_items<'a>(
+        &self,
+        out: &mut String,
+        open: char,
+        close: char,
+        items: impl ExactSizeIterator<Item = (Option<&'a str>, &'a Value)>,
+        depth: usize,
+    ) {
+        if items.len() == 0 {
+            out.push(open);
+            out.push(close);
+            return;
         }
 
+        out.push(open);
+        for (i, (key, value)) in items.enumerate() {
+            if i > 0 {
+                out.push(',');
+                if !self.pretty {
+                    out.push(' ');
+                }
+            }
+            self.newline(out, depth + 1);
+            if let Some(key) = key {
+                if is_clove_identifier(key) {
+                    out.push_str(key);
+                } else {
+                    out.push_str(&quote_clove(key));
+                }
+                out.push_str(": ");
+            }
+            self.write_value(out, value, depth + 1);
+        }
+        self.newline(out, depth);
+        out.push(close);
+    }
+
+    fn newline(&self, out: &mut String, depth: usize) {
         if self.pretty {
-            let mut result = "[\n".to_string();
-            let items: Vec<String> = arr
+            out.push('\n');
+            out.push_str(&"  ".repeat(depth));
+        }
+    }
+}
+
+impl OutputFormatter for ClovePrinter {
+    fn format(&self, value: &Value) -> String {
+        self.print(value)
+    }
+}
+
+/// Whether `key` can be written as a bare object key: it lexes as an
+/// identifier rather than a keyword or literal
+fn is_clove_identifier(key: &str) -> bool {
+    let mut chars = key.chars();
+    chars.next().is_some_and(|c| c.is_alphabetic() || c == '_')
+        && chars.all(|c| c.is_alphanumeric() || c == '_')
+        && !matches!(key, "and" | "or" | "true" | "false" | "null")
+}
+
+/// A single-quoted Clove string literal. Clove strings have no `\u`
+/// escapes, so other characters are written as they are.
+fn quote_clove(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('\'');
+    for c in s.chars() {
+        match c {
+            '\'' => out.push_str("\\'"),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c => out.push(c),
+        }
+    }
+    out.push('\'');
+    out
+}
+
+/// JSON printer used by [`to_json()`], [`to_json_pretty()`] and the `json`
+/// and `color` output formats. See [`JsonWriter`] for more options.
+pub struct JsonPrinter {
+    writer: JsonWriter,
+}
+
+impl JsonPrinter {
+    pub fn new(pretty: bool) -> Self {
+        JsonPrinter {
+            writer: JsonWriter::new().pretty(pretty),
+        }
+    }
+
+    /// Highlight keys, strings, numbers, booleans and null with ANSI colors
+    pub fn colored(self) -> Self {
+        JsonPrinter {
+            writer: self.writer.colored(true),
+        }
+    }
+
+    pub fn print(&self, value: &Value) -> String {
+        self.writer.write(value)
+    }
+}
+
+impl OutputFormatter for JsonPrinter {
+    fn format(&self, value: &Value) -> String {
+        self.print(value)
+    }
+}
+
+impl OutputFormatter for JsonWriter {
+    fn format(&self, value: &Value) -> String {
+        self.write(value)
+    }
+}
+
+/// Renders arrays of flat objects as aligned text columns.
+///
+/// Columns are the union of all keys, sorted. Strings are shown without
+/// quotes, numbers are right-aligned, missing fields are left blank and
+/// nested arrays/objects are shown as compact JSON. Values that are not an
+/// array of objects fall back to JSON output.
+///
+/// ```text
+/// age  name
+/// ---  -----
+///  30  Alice
+///  25  Bob
+/// ```
+pub struct TablePrinter {
+    fallback: JsonPrinter,
+}
+
+impl TablePrinter {
+    /// `pretty` controls the JSON fallback for non-tabular values
+    pub fn new(pretty: bool) -> Self {
+        TablePrinter {
+            fallback: JsonPrinter::new(pretty),
+        }
+    }
+
+    fn cell(value: Option<&Value>) -> (String, bool) {
+        match value {
+            None => (String::new(), false),
+            Some(Value::String(s)) => (s.clone(), false),
+            Some(v @ (Value::Integer(_) | Value::Float(_))) => (to_json(v), true),
+            Some(v) => (to_json(v), false),
+        }
+    }
+}
+
+impl OutputFormatter for TablePrinter {
+    fn format(&self, value: &Value) -> String {
+        let rows = match value {
+            Value::Array(rows) if !rows.is_empty() => rows,
+            _ => return self.fallback.print(value),
+        };
+        let mut objects = Vec::with_capacity(rows.len());
+        for row in rows {
+            match row {
+                Value::Object(obj) => objects.push(obj),
+                _ => return self.fallback.print(value),
+            }
+        }
+
+        let columns: Vec<&String> = objects
+            .iter()
+            .flat_map(|obj| obj.keys())
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect();
+        let cells: Vec<Vec<(String, bool)>> = objects
+            .iter()
+            .map(|obj| columns.iter().map(|c| Self::cell(obj.get(*c))).collect())
+            .collect();
+
+        let widths: Vec<usize> = columns
+            .iter()
+            .enumerate()
+            .map(|(i, c)| {
+                cells
+                    .iter()
+                    .map(|row| row[i].0.chars().count())
+                    .chain(std::iter::once(c.chars().count()))
+                    .max()
+                    .unwrap_or(0)
+            })
+            .collect();
+
+        let render_line = |parts: Vec<String>| parts.join("  ").trim_end().to_string();
+        let mut lines = Vec::with_capacity(cells.len() + 2);
+        lines.push(render_line(
+            columns
                 .iter()
-                .map(|v| {
-                    format!(
-                        "{}{}",
-                        self.indent(indent + 1),
-                        self.print_value(v, indent + 1)
-                    )
-                })
-                .collect();
-            result.push_str(&items.join(",\n"));
-            result.push('\n');
-            result.push_str(&self.indent(indent));
-            result.push(']');
-            result
+                .zip(&widths)
+                .map(|(c, w)| format!("{:<w$}", c, w = w))
+                .collect(),
+        ));
+        lines.push(render_line(widths.iter().map(|w| "-".repeat(*w)).collect()));
+        for row in &cells {
+            lines.push(render_line(
+                row.iter()
+                    .zip(&widths)
+                    .map(|((text, numeric), w)| {
+                        if *numeric {
+                            format!("{:>w$}", text, w = w)
+                        } else {
+                            format!("{:<w$}", text, w = w)
+                        }
+                    })
+                    .collect(),
+            ));
+        }
+        lines.join("\n")
+    }
+}
+
+/// How the numbers of a JSON text were written, for writing them back the
+/// same way.
+///
+/// Parsing turns every number into an `i64` or `f64`, so `1.50`, `1e3` or
+/// an integer too large for `i64` come out of a query as `1.5`, `1000.0`
+/// or a rounded float. [`JsonWriter::number_lexemes`] writes a number as
+/// the input wrote it when the output has that same number in the same
+/// place (the same keys and indexes from the top), so fields a query did
+/// not touch keep their exact text (and large integers their digits).
+/// Numbers a query computed or moved are written normally.
+///
+/// Several documents (such as NDJSON) line up with the array `--slurp`
+/// reads them as.
+///
+/// # Examples
+///
+/// ```
+/// use std::sync::Arc;
+/// use clove_lang::Value;
+/// use clove_lang::output::{JsonWriter, NumberLexemes};
+///
+/// let input = r#"{"price": 1.50, "id": 12345678901234567890123, "tax": 1.5}"#;
+/// let doc = Value::from_reader(input.as_bytes()).unwrap();
+///
+/// assert_eq!(JsonWriter::new().write(&doc), r#"{"id":1.2345678901234568e22,"price":1.5,"tax":1.5}"#);
+/// let writer = JsonWriter::new().number_lexemes(Arc::new(NumberLexemes::scan(input)));
+/// assert_eq!(writer.write(&doc), r#"{"id":12345678901234567890123,"price":1.50,"tax":1.5}"#);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct NumberLexemes {
+    root: Lexemes,
+}
+
+/// The numbers written differently from the writer's normal text, by place
+#[derive(Debug, Clone, Default)]
+enum Lexemes {
+    /// Nothing here needs its original text
+    #[default]
+    Plain,
+    /// A number, and the text of the input for it
+    Number(Value, String),
+    Array(Vec<Lexemes>),
+    /// Only the fields holding such numbers
+    Object(HashMap<String, Lexemes>),
+}
+
+static PLAIN: Lexemes = Lexemes::Plain;
+
+impl Lexemes {
+    fn item(&self, index: usize) -> &Lexemes {
+        match self {
+            Lexemes::Array(items) => items.get(index).unwrap_or(&PLAIN),
+            _ => &PLAIN,
+        }
+    }
+
+    fn field(&self, key: &str) -> &Lexemes {
+        match self {
+            Lexemes::Object(fields) => fields.get(key).unwrap_or(&PLAIN),
+            _ => &PLAIN,
+        }
+    }
+
+    /// The input's text for `value`, if it is the number that was here
+    fn number(&self, value: &Value) -> Option<&str> {
+        match self {
+            Lexemes::Number(original, lexeme) if original == value => Some(lexeme),
+            _ => None,
+        }
+    }
+
+    fn array(items: Vec<Lexemes>) -> Lexemes {
+        if items.iter().all(|item| matches!(item, Lexemes::Plain)) {
+            Lexemes::Plain
         } else {
-            let items: Vec<String> = arr.iter().map(|v| self.print_value(v, indent)).collect();
-            format!("[{}]", items.join(","))
+            Lexemes::Array(items)
         }
     }
 
-    fn print_object(
-        &self,
-        obj: &std::collections::HashMap<String, Value>,
-        indent: usize,
-    ) -> String {
-        if obj.is_empty() {
-            return "{}".to_string();
+    fn object(fields: HashMap<String, Lexemes>) -> Lexemes {
+        if fields.is_empty() { Lexemes::Plain } else { Lexemes::Object(fields) }
+    }
+}
+
+/// An array or object being scanned
+enum Open {
+    Array(Vec<Lexemes>),
+    /// The fields so far, and the key of the next value once it is read
+    Object(HashMap<String, Lexemes>, Option<String>),
+}
+
+impl NumberLexemes {
+    /// Record where the numbers of `json` (one document, or several such as
+    /// NDJSON) were written differently from how they would be otherwise.
+    /// Malformed JSON is scanned as far as it makes sense; parsing it is
+    /// what reports the error.
+    pub fn scan(json: &str) -> NumberLexemes {
+        let mut documents = Vec::new();
+        let mut open: Vec<Open> = Vec::new();
+        let mut expect_key = false;
+        for token in json_tokens(json) {
+            let value = match token {
+                JsonToken::Open(b'[') => {
+                    open.push(Open::Array(Vec::new()));
+                    continue;
+                }
+                JsonToken::Open(_) => {
+                    open.push(Open::Object(HashMap::new(), None));
+                    expect_key = true;
+                    continue;
+                }
+                JsonToken::Comma => {
+                    expect_key = matches!(open.last(), Some(Open::Object(..)));
+                    continue;
+                }
+                JsonToken::String(text) if expect_key => {
+                    if let Some(Open::Object(_, key)) = open.last_mut() {
+                        *key = serde_json::from_str(text).ok();
+                    }
+                    expect_key = false;
+                    continue;
+                }
+                JsonToken::Close => match open.pop() {
+                    Some(Open::Array(items)) => Lexemes::array(items),
+                    Some(Open::Object(fields, _)) => Lexemes::object(fields),
+                    None => continue,
+                },
+                JsonToken::Number(lexeme) => match Value::from_reader(lexeme.as_bytes()) {
+                    Ok(value) if to_json(&value) != lexeme => Lexemes::Number(value, lexeme.to_string()),
+                    _ => Lexemes::Plain,
+                },
+                JsonToken::String(_) | JsonToken::Other => Lexemes::Plain,
+            };
+            expect_key = false;
+            match open.last_mut() {
+                None => documents.push(value),
+                Some(Open::Array(items)) => items.push(value),
+                Some(Open::Object(fields, key)) => {
+                    // A repeated key is read as its last value
+                    if let Some(key) = key.take() {
+                        match value {
+                            Lexemes::Plain => fields.remove(&key),
+                            value => fields.insert(key, value),
+                        };
+                    }
+                }
+            }
+        }
+        let root = match documents.len() {
+            1 => documents.pop().unwrap_or_default(),
+            _ => Lexemes::array(documents),
+        };
+        NumberLexemes { root }
+    }
+
+    /// Whether every number is written normally
+    pub fn is_empty(&self) -> bool {
+        matches!(self.root, Lexemes::Plain)
+    }
+}
+
+/// A token of a JSON text, as far as [`NumberLexemes::scan`] cares
+enum JsonToken<'a> {
+    /// `[` or `{`
+    Open(u8),
+    /// `]` or `}`
+    Close,
+    Comma,
+    /// A string, quotes and escapes included
+    String(&'a str),
+    Number(&'a str),
+    /// `true`, `false` or `null`
+    Other,
+}
+
+/// The tokens of a JSON text, without whitespace and colons
+fn json_tokens(json: &str) -> impl Iterator<Item = JsonToken<'_>> {
+    let bytes = json.as_bytes();
+    let mut i = 0;
+    std::iter::from_fn(move || {
+        while i < bytes.len() {
+            let start = i;
+            i += 1;
+            match bytes[start] {
+                b'[' | b'{' => return Some(JsonToken::Open(bytes[start])),
+                b']' | b'}' => return Some(JsonToken::Close),
+                b',' => return Some(JsonToken::Comma),
+                b'"' => {
+                    while i < bytes.len() && bytes[i] != b'"' {
+                        i += if bytes[i] == b'\\' { 2 } else { 1 };
+                    }
+                    i = (i + 1).min(bytes.len());
+                    return Some(JsonToken::String(&json[start..i]));
+                }
+                b'-' | b'0'..=b'9' => {
+                    while i < bytes.len() && matches!(bytes[i], b'0'..=b'9' | b'-' | b'+' | b'.' | b'e' | b'E') {
+                        i += 1;
+                    }
+                    return Some(JsonToken::Number(&json[start..i]));
+                }
+                b't' | b'f' | b'n' => {
+                    while i < bytes.len() && bytes[i].is_ascii_alphabetic() {
+                        i += 1;
+                    }
+                    return Some(JsonToken::Other);
+                }
+                _ => {}
+            }
+        }
+        None
+    })
+}
+
+/// Shell variable assignments, one `KEY=value` line per entry of an object.
+///
+/// For reading a query's results into a shell script with `eval` or
+/// `source`. Keys must be valid variable names and come out sorted. Strings
+/// are single-quoted unless they only hold characters the shell leaves
+/// alone, numbers and booleans are written as JSON, `null` as an empty
+/// value, and bytes as base64. Anything else (a result that is not an
+/// object, or a nested array or object) is an [`EnvError`].
+///
+/// # Examples
+///
+/// ```
+/// use clove_lang::clove_value;
+/// use clove_lang::output::EnvPrinter;
+///
+/// let config = clove_value!({"HOST": "db.local", "GREETING": "it's up", "PORT": 5432});
+/// assert_eq!(
+///     EnvPrinter::new().print(&config).unwrap(),
+///     "GREETING='it'\\''s up'\nHOST=db.local\nPORT=5432"
+/// );
+/// assert_eq!(EnvPrinter::new().export(true).print(&clove_value!({"A": 1})).unwrap(), "export A=1");
+/// assert!(EnvPrinter::new().print(&clove_value!({"A": [1]})).is_err());
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct EnvPrinter {
+    export: bool,
+}
+
+/// Why a value cannot be written as shell variable assignments
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EnvError {
+    /// The result is not an object (its type)
+    NotAnObject(&'static str),
+    /// A key is not a valid shell variable name
+    InvalidName(String),
+    /// The value of a key is an array or an object (its type)
+    Nested { key: String, type_name: &'static str },
+}
+
+impl fmt::Display for EnvError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EnvError::NotAnObject(type_name) => {
+                write!(f, "env output requires an object of variables, got {}", type_name)
+            }
+            EnvError::InvalidName(key) => write!(
+                f,
+                "env output: '{}' is not a valid variable name (use letters, digits and underscores, not starting with a digit)",
+                key
+            ),
+            EnvError::Nested { key, type_name } => write!(
+                f,
+                "env output: '{}' is an {}; only strings, numbers, booleans and null can be written as variables",
+                key, type_name
+            ),
         }
+    }
+}
+
+impl std::error::Error for EnvError {}
+
+impl EnvPrinter {
+    /// Plain `KEY=value` lines
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Prefix each line with `export `
+    pub fn export(mut self, export: bool) -> Self {
+        self.export = export;
+        self
+    }
 
-        // Sort keys for deterministic output
-        let mut keys: Vec<_> = obj.keys().collect();
+    /// The assignments for the entries of `value`, one per line
+    pub fn print(&self, value: &Value) -> Result<String, EnvError> {
+        let Value::Object(vars) = value else {
+            return Err(EnvError::NotAnObject(crate::evaluator::type_name(value)));
+        };
+        let mut keys: Vec<&String> = vars.keys().collect();
         keys.sort();
 
-        if self.pretty {
-            let mut result = "{\n".to_string();
-            let items: Vec<String> = keys
-                .iter()
-                .map(|k| {
-                    format!(
-                        "{}\"{}\": {}",
-                        self.indent(indent + 1),
-                        self.escape_string(k),
-                        self.print_value(obj.get(*k).unwrap(), indent + 1)
-                    )
-                })
-                .collect();
-            result.push_str(&items.join(",\n"));
-            result.push('\n');
-            result.push_str(&self.indent(indent));
-            result.push('}');
-            result
-        } else {
-            let items: Vec<String> = keys
-                .iter()
-                .map(|k| {
-                    format!(
-                        "\"{}\":{}",
-                        self.escape_string(k),
-                        self.print_value(obj.get(*k).unwrap(), indent)
-                    )
-                })
-                .collect();
-            format!("{{{}}}", items.join(","))
-        }
-    }
-
-    fn indent(&self, level: usize) -> String {
-        "  ".repeat(level)
-    }
-
-    fn escape_string(&self, s: &str) -> String {
-        s.chars()
-            .flat_map(|c| match c {
-                '"' => vec!['\\', '"'],
-                '\\' => vec!['\\', '\\'],
-                '\n' => vec!['\\', 'n'],
-                '\r' => vec!['\\', 'r'],
-                '\t' => vec!['\\', 't'],
-                c if c.is_control() => {
-                    // Unicode escape for control chars
-                    format!("\\u{:04x}", c as u32).chars().collect()
+        let mut lines = Vec::with_capacity(keys.len());
+        for key in keys {
+            let valid = key.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+                && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+            if !valid {
+                return Err(EnvError::InvalidName(key.clone()));
+            }
+            let text = match &vars[key] {
+                Value::Null => String::new(),
+                Value::String(s) => s.clone(),
+                Value::Bytes(b) => crate::value::encode_base64(b),
+                value @ (Value::Boolean(_) | Value::Integer(_) | Value::Float(_)) => to_json(value),
+                value @ (Value::Array(_) | Value::Object(_)) => {
+                    return Err(EnvError::Nested {
+                        key: key.clone(),
+                        type_name: crate::evaluator::type_name(value),
+                    });
                 }
-                c => vec![c],
-            })
-            .collect()
+            };
+            let export = if self.export { "export " } else { "" };
+            lines.push(format!("{}{}={}", export, key, shell_quote(&text)));
+        }
+        Ok(lines.join("\n"))
+    }
+}
+
+/// `text` as one shell word: as is when every character is safe unquoted,
+/// otherwise in single quotes
+fn shell_quote(text: &str) -> String {
+    let safe = |c: char| c.is_ascii_alphanumeric() || "_@%+=:,./-".contains(c);
+    if !text.is_empty() && text.chars().all(safe) {
+        return text.to_string();
     }
+    format!("'{}'", text.replace('\'', "'\\''"))
 }
 
 // Convenience functions
@@ -219,3 +1229,22 @@ pub fn to_json(value: &Value) -> String {
 pub fn to_json_pretty(value: &Value) -> String {
     JsonPrinter::new(true).print(value)
 }
+
+/// Converts a Value to Clove literal syntax, on one line.
+///
+/// Parsing the result as an expression and evaluating it gives back the
+/// same value, so documents can be written in the query language's own
+/// syntax. See [`ClovePrinter`] for the details and for pretty output.
+///
+/// # Examples
+///
+/// ```
+/// use clove_lang::clove_value;
+/// use clove_lang::output::to_clove;
+///
+/// let value = clove_value!({"name": "Alice", "first name": "A", "tags": [1, 2.5, null]});
+/// assert_eq!(to_clove(&value), "{'first name': 'A', name: 'Alice', tags: [1, 2.5, null]}");
+/// ```
+pub fn to_clove(value: &Value) -> String {
+    ClovePrinter::new(false).print(value)
+}
@@ -1,65 +1,232 @@
 use crate::{
-    ast::{BinOp, Expr, Query, Statement, Token, UDF},
-    lexer::{Lexer, LexError},
+    ast::{BinOp, EnvVarType, Expr, MatchArm, Pattern, Query, Statement, Token, UDF},
+    ingest::DuplicateKeys,
+    lexer::{Lexer, LexError, Position},
 };
 use std::mem;
 
 /// Errors that can occur during parsing
+///
+/// Every variant records where in the query it occurred and the text of that
+/// source line, so `Display` can point at the offending token:
+///
+/// ```text
+/// Expected RBracket, got Eof at line 1, column 8
+///   |
+/// 1 | $[items
+///   |        ^ missing closing ']'
+/// ```
 #[derive(Debug, Clone)]
 pub enum ParseError {
     /// Lexer error (with position)
-    LexError(LexError),
+    LexError { error: LexError, source_line: String },
     /// Unexpected token
-    UnexpectedToken { expected: String, got: Token },
+    UnexpectedToken {
+        expected: String,
+        got: Token,
+        position: Position,
+        source_line: String,
+    },
     /// Invalid syntax
-    InvalidSyntax(String),
+    InvalidSyntax {
+        message: String,
+        position: Position,
+        source_line: String,
+    },
 }
 
-impl std::fmt::Display for ParseError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+impl ParseError {
+    /// Where in the query the error occurred
+    pub fn position(&self) -> Position {
         match self {
-            ParseError::LexError(e) => write!(f, "{}", e),
-            ParseError::UnexpectedToken { expected, got } => {
-                write!(f, "Expected {}, got {:?}", expected, got)
+            ParseError::LexError { error, .. } => error.position(),
+            ParseError::UnexpectedToken { position, .. }
+            | ParseError::InvalidSyntax { position, .. } => *position,
+        }
+    }
+
+    /// The source line containing the error (empty if unknown)
+    pub fn source_line(&self) -> &str {
+        match self {
+            ParseError::LexError { source_line, .. }
+            | ParseError::UnexpectedToken { source_line, .. }
+            | ParseError::InvalidSyntax { source_line, .. } => source_line,
+        }
+    }
+
+    /// The error message without location or snippet
+    pub fn message(&self) -> String {
+        match self {
+            ParseError::LexError { error, .. } => error.message(),
+            ParseError::UnexpectedToken { expected, got, .. } => {
+                format!("Expected {}, got {:?}", expected, got)
             }
-            ParseError::InvalidSyntax(msg) => write!(f, "{}", msg),
+            ParseError::InvalidSyntax { message, .. } => message.clone(),
+        }
+    }
+
+    /// A short suggestion for fixing the error, if one applies
+    pub fn hint(&self) -> Option<&'static str> {
+        match self {
+            ParseError::UnexpectedToken { expected, got, .. } => match expected.as_str() {
+                "RBracket" => Some("missing closing ']'"),
+                "RParen" => Some("missing closing ')'"),
+                "RBrace" => Some("missing closing '}'"),
+                "Colon" => Some("object entries are written as key: value"),
+                "ColonEqual" => Some("assignments use ':='"),
+                "Eof" => Some("unexpected input after the end of the query"),
+                "Dollar" => Some("queries start with '$'"),
+                _ if *got == Token::Eof => Some("query ended unexpectedly"),
+                _ => None,
+            },
+            ParseError::LexError { error: LexError::UnterminatedString { .. }, .. } => {
+                Some("close the string with a matching quote")
+            }
+            _ => None,
         }
     }
 }
 
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let position = self.position();
+        write!(f, "{} at {}", self.message(), position)?;
+
+        let source_line = self.source_line();
+        if source_line.is_empty() {
+            return Ok(());
+        }
+
+        let gutter = " ".repeat(position.line.to_string().len());
+        let caret_pad = " ".repeat(position.column.saturating_sub(1));
+        write!(f, "\n{} |\n{} | {}\n{} | {}^", gutter, position.line, source_line, gutter, caret_pad)?;
+        if let Some(hint) = self.hint() {
+            write!(f, " {}", hint)?;
+        }
+        Ok(())
+    }
+}
+
 impl std::error::Error for ParseError {}
 
 impl From<LexError> for ParseError {
-    fn from(e: LexError) -> Self {
-        ParseError::LexError(e)
+    fn from(error: LexError) -> Self {
+        ParseError::LexError {
+            error,
+            source_line: String::new(),
+        }
     }
 }
 
+/// Default for the deepest expression nesting the parser accepts.
+///
+/// Parsing and evaluation recurse once per level, so without a bound a long
+/// enough query (`((((...`, or a chain like `1 + 1 + ...`) overflows the stack
+/// and aborts the process instead of returning an error. At this depth a
+/// release build needs well under 1 MiB of stack (debug builds need several
+/// times more); lower it with [`Parser::with_max_depth`] for smaller stacks.
+pub const MAX_NESTING_DEPTH: usize = 128;
+
 pub struct Parser {
     lexer: Lexer,
     current_token: Token,
+    /// Where `current_token` starts in the source
+    current_position: Position,
+    /// Nesting depth of the expression being parsed
+    depth: usize,
+    max_depth: usize,
+    /// Parameter names of the UDF being parsed, which `@name` refers to
+    /// instead of a scope
+    params: Vec<String>,
+    /// Parameter names of the named lambdas being parsed, innermost last
+    lambdas: Vec<String>,
+    /// Names bound by the patterns of the match arms being parsed
+    bound: Vec<String>,
+    /// What to do with an object literal that repeats a key
+    duplicates: DuplicateKeys,
+    /// Whether `[*]` may be a key: only in a delete target
+    wildcards: bool,
 }
 
 impl Parser {
     pub fn new(mut lexer: Lexer) -> Result<Self, ParseError> {
-        let current_token = lexer.next_token()?;
+        let current_token = match lexer.next_token() {
+            Ok(token) => token,
+            Err(error) => {
+                let source_line = lexer.line_text(error.position().line);
+                return Err(ParseError::LexError { error, source_line });
+            }
+        };
+        let current_position = lexer.token_position();
         Ok(Parser {
             lexer,
             current_token,
+            current_position,
+            depth: 0,
+            max_depth: MAX_NESTING_DEPTH,
+            params: Vec::new(),
+            lambdas: Vec::new(),
+            bound: Vec::new(),
+            duplicates: DuplicateKeys::default(),
+            wildcards: false,
         })
     }
 
+    /// Set the deepest expression nesting to accept (default [`MAX_NESTING_DEPTH`])
+    pub fn with_max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    /// Set what to do with an object literal that repeats a key, like
+    /// `{"a": 1, "a": 2}`: by default the last value wins. With
+    /// [`DuplicateKeys::First`] the later values are dropped unevaluated.
+    pub fn with_duplicate_keys(mut self, duplicates: DuplicateKeys) -> Self {
+        self.duplicates = duplicates;
+        self
+    }
+
     fn advance(&mut self) -> Result<(), ParseError> {
-        self.current_token = self.lexer.next_token()?;
+        self.current_token = self.lexer.next_token().map_err(|error| {
+            let source_line = self.lexer.line_text(error.position().line);
+            ParseError::LexError { error, source_line }
+        })?;
+        self.current_position = self.lexer.token_position();
         Ok(())
     }
 
+    /// Error for an unexpected `got` token at the current position
+    fn unexpected_token(&self, expected: impl Into<String>, got: Token) -> ParseError {
+        ParseError::UnexpectedToken {
+            expected: expected.into(),
+            got,
+            position: self.current_position,
+            source_line: self.lexer.line_text(self.current_position.line),
+        }
+    }
+
+    /// Error for the current token not being what was expected
+    fn unexpected(&self, expected: impl Into<String>) -> ParseError {
+        self.unexpected_token(expected, self.current_token.clone())
+    }
+
+    /// Syntax error at the current position
+    fn syntax_error(&self, message: String) -> ParseError {
+        self.syntax_error_at(self.current_position, message)
+    }
+
+    /// Syntax error at an earlier token's position
+    fn syntax_error_at(&self, position: Position, message: String) -> ParseError {
+        ParseError::InvalidSyntax {
+            message,
+            position,
+            source_line: self.lexer.line_text(position.line),
+        }
+    }
+
     fn expect(&mut self, expected: Token) -> Result<(), ParseError> {
         if std::mem::discriminant(&self.current_token) != std::mem::discriminant(&expected) {
-            return Err(ParseError::UnexpectedToken {
-                expected: format!("{:?}", expected),
-                got: self.current_token.clone(),
-            });
+            return Err(self.unexpected(format!("{:?}", expected)));
         }
         self.advance()
     }
@@ -68,6 +235,43 @@ impl Parser {
         std::mem::discriminant(&self.current_token) == std::mem::discriminant(token)
     }
 
+    /// Enter one level of nesting, failing past the maximum depth. Callers
+    /// restore the depth they started at through [`Parser::scoped`].
+    fn descend(&mut self) -> Result<(), ParseError> {
+        if self.depth >= self.max_depth {
+            return Err(self.syntax_error(format!(
+                "Expression is nested too deeply (more than {} levels)",
+                self.max_depth
+            )));
+        }
+        self.depth += 1;
+        Ok(())
+    }
+
+    /// Run `parse` and restore the current depth afterwards, on success or
+    /// error. Left-associative loops `descend` once per operator inside this,
+    /// since each operator nests the expression built so far one level deeper.
+    fn scoped<T>(
+        &mut self,
+        parse: impl FnOnce(&mut Self) -> Result<T, ParseError>,
+    ) -> Result<T, ParseError> {
+        let depth = self.depth;
+        let result = parse(self);
+        self.depth = depth;
+        result
+    }
+
+    /// Run `parse` one nesting level deeper
+    fn nested<T>(
+        &mut self,
+        parse: impl FnOnce(&mut Self) -> Result<T, ParseError>,
+    ) -> Result<T, ParseError> {
+        self.scoped(|p| {
+            p.descend()?;
+            parse(p)
+        })
+    }
+
     fn parse_primary(&mut self) -> Result<Expr, ParseError> {
         match mem::replace(&mut self.current_token, Token::Eof) {
             // Literals
@@ -97,26 +301,64 @@ impl Parser {
                 self.advance()?;
                 Ok(Expr::Root)
             }
+            Token::DoubleDollar => {
+                self.advance()?;
+                Ok(Expr::OriginalRoot)
+            }
             Token::EnvVar(name) => {
                 self.advance()?;
+
+                // `$meta` is record metadata, not an environment variable
+                if name == "meta" {
+                    return Ok(Expr::Meta);
+                }
+
+                // Typed lookup: $NAME:type
+                if self.check(&Token::Colon) {
+                    self.advance()?;
+                    let ty = match &self.current_token {
+                        Token::Identifier(t) => EnvVarType::from_name(t).ok_or_else(|| {
+                            self.syntax_error(format!(
+                                "Unknown type '{}' for ${} (expected int, float, bool, or string)",
+                                t, name
+                            ))
+                        })?,
+                        _ => {
+                            return Err(self.unexpected(format!("type name after '${}:'", name)))
+                        }
+                    };
+                    self.advance()?;
+                    return Ok(Expr::TypedEnvVar { name, ty });
+                }
+
                 Ok(Expr::EnvVar(name))
             }
             Token::At => {
                 self.advance()?;
 
-                // Disambiguate '@', '@name', '@1'
+                // Disambiguate '@', '@@', '@name', '@1'
                 match &self.current_token {
+                    // @@, @@@ -> Item of an enclosing lambda
+                    Token::At => {
+                        let mut level = 0;
+                        while self.check(&Token::At) {
+                            level += 1;
+                            self.advance()?;
+                        }
+                        Ok(Expr::OuterLambdaParam(level))
+                    }
                     // @1, @2 -> Argument reference
                     Token::Integer(n) if *n > 0 => {
                         let arg_num = *n as usize;
                         self.advance()?;
                         Ok(Expr::ArgRef(arg_num))
                     }
-                    // @identifier -> scope reference
-                    Token::Identifier(name) => {
+                    // @identifier -> parameter or scope reference; `catch`
+                    // is reserved, so `try @ catch x` ends its body at `@`
+                    Token::Identifier(name) if name != "catch" => {
                         let name = name.clone();
                         self.advance()?;
-                        Ok(Expr::ScopeRef(name))
+                        Ok(self.at_name(name))
                     }
                     // @ alone -> Lambda parameter
                     _ => Ok(Expr::LambdaParam),
@@ -125,7 +367,7 @@ impl Parser {
 
             Token::LParen => {
                 self.advance()?;
-                let expr = self.parse_expression()?;
+                let expr = self.nested(Self::parse_expression)?;
                 self.expect(Token::RParen)?;
                 Ok(expr)
             }
@@ -133,36 +375,76 @@ impl Parser {
             // Unary minus (for negative numbers/negation)
             Token::Minus => {
                 self.advance()?;
-                let operand = self.parse_primary()?;
-                Ok(Expr::BinaryOp {
-                    op: BinOp::Subtract,
-                    left: Box::new(Expr::Integer(0)),
-                    right: Box::new(operand),
-                })
+                let operand = self.nested(Self::parse_primary)?;
+                Ok(negate(operand))
+            }
+
+            // &name[args] -> UDF call
+            Token::Ampersand => {
+                self.advance()?;
+                self.nested(Self::parse_udf_call)
+            }
+
+            // name -> body: a lambda naming its item
+            Token::Identifier(name) if self.lexer.skip_arrow() => {
+                self.advance()?;
+                self.lambdas.push(name);
+                let body = self.nested(Self::parse_expression);
+                let param = self.lambdas.pop().unwrap_or_default();
+                Ok(Expr::Lambda { param, body: Box::new(body?) })
+            }
+
+            // ext::name(args) -> host function call
+            Token::Identifier(name) if name == "ext" && self.lexer.skip_path_separator() => {
+                self.advance()?;
+                self.nested(Self::parse_host_call)
+            }
+
+            // match subject { pattern => body, ... }
+            Token::Identifier(name) if name == "match" => {
+                self.advance()?;
+                self.nested(Self::parse_match)
+            }
+
+            // try body catch fallback
+            Token::Identifier(name) if name == "try" => {
+                self.advance()?;
+                self.nested(Self::parse_try)
+            }
+
+            // A name bound by a match pattern
+            Token::Identifier(name) if self.bound.contains(&name) => {
+                self.advance()?;
+                Ok(Expr::LambdaVar(name))
             }
 
             // These should never appear as primary expressions
-            Token::Identifier(name) => Err(ParseError::InvalidSyntax(format!(
-                "Unexpected identifier '{}' - identifiers must be part of access expressions (use $[{}] or @[{}])",
-                name, name, name
-            ))),
+            Token::Identifier(name) => {
+                let error = self.syntax_error(format!(
+                    "Unexpected identifier '{}' - identifiers must be part of access expressions (use $[{}] or @[{}])",
+                    name, name, name
+                ));
+                self.current_token = Token::Identifier(name);
+                Err(error)
+            }
 
             // Object literals
             Token::LBrace => {
                 self.advance()?;
-                self.parse_object_literal()
+                self.nested(Self::parse_object_literal)
             }
             // Array literals
             Token::LBracket => {
                 self.advance()?;
-                self.parse_array_literal()
+                self.nested(Self::parse_array_literal)
             }
 
-            // Others also unexpected
-            token => Err(ParseError::UnexpectedToken {
-                expected: "expression".to_string(),
-                got: token,
-            }),
+            // Others also unexpected; put the token back for error recovery
+            token => {
+                let error = self.unexpected_token("expression", token.clone());
+                self.current_token = token;
+                Err(error)
+            }
         }
     }
 
@@ -174,18 +456,24 @@ impl Parser {
                 Token::String(s) => s.clone(),
                 Token::Identifier(s) => s.clone(),
                 _ => {
-                    return Err(ParseError::UnexpectedToken {
-                        expected: "string or identifier as object key".to_string(),
-                        got: self.current_token.clone(),
-                    })
+                    return Err(self.unexpected("string or identifier as object key".to_string()))
                 }
             };
+            let key_position = self.current_position;
 
             self.advance()?;
             self.expect(Token::Colon)?;
 
             let value = self.parse_expression()?;
-            pairs.push((key, value));
+            let repeated = pairs.iter().any(|(existing, _)| *existing == key);
+            match self.duplicates {
+                DuplicateKeys::Error if repeated => {
+                    let message = format!("Duplicate key \"{}\" in object literal", key);
+                    return Err(self.syntax_error_at(key_position, message));
+                }
+                DuplicateKeys::First if repeated => {}
+                _ => pairs.push((key, value)),
+            }
 
             if !self.check(&Token::RBrace) {
                 self.expect(Token::Comma)?;
@@ -211,10 +499,192 @@ impl Parser {
         Ok(Expr::Array(elements))
     }
 
+    fn parse_host_call(&mut self) -> Result<Expr, ParseError> {
+        let name = match &self.current_token {
+            Token::Identifier(n) => n.clone(),
+            _ => return Err(self.unexpected("function name after 'ext::'")),
+        };
+        self.advance()?;
+        self.expect(Token::LParen)?;
+
+        let mut args = vec![];
+        while !self.check(&Token::RParen) {
+            args.push(self.parse_expression()?);
+
+            if !self.check(&Token::RParen) {
+                self.expect(Token::Comma)?;
+            }
+        }
+
+        self.expect(Token::RParen)?;
+        Ok(Expr::HostCall { name, args })
+    }
+
+    fn parse_try(&mut self) -> Result<Expr, ParseError> {
+        let body = self.parse_expression()?;
+        match &self.current_token {
+            Token::Identifier(name) if name == "catch" => self.advance()?,
+            _ => return Err(self.unexpected("'catch' after try expression")),
+        }
+        let fallback = self.parse_expression()?;
+        Ok(Expr::Try { body: Box::new(body), fallback: Box::new(fallback) })
+    }
+
+    fn parse_match(&mut self) -> Result<Expr, ParseError> {
+        let subject = self.parse_expression()?;
+        self.expect(Token::LBrace)?;
+
+        let mut arms = vec![];
+        while !self.check(&Token::RBrace) {
+            let mut names = vec![];
+            let pattern = self.parse_pattern(&mut names)?;
+
+            // The pattern's names are visible in its guard and body only
+            let outer = self.bound.len();
+            self.bound.extend(names);
+            let arm = self.parse_match_arm(pattern);
+            self.bound.truncate(outer);
+            arms.push(arm?);
+
+            if !self.check(&Token::RBrace) {
+                self.expect(Token::Comma)?;
+            }
+        }
+        if arms.is_empty() {
+            return Err(self.syntax_error("A match needs at least one arm".to_string()));
+        }
+
+        self.expect(Token::RBrace)?;
+        Ok(Expr::Match { subject: Box::new(subject), arms })
+    }
+
+    fn parse_match_arm(&mut self, pattern: Pattern) -> Result<MatchArm, ParseError> {
+        let guard = match &self.current_token {
+            Token::Identifier(name) if name == "if" => {
+                self.advance()?;
+                Some(self.parse_expression()?)
+            }
+            _ => None,
+        };
+        self.expect(Token::FatArrow)?;
+        let body = self.parse_expression()?;
+        Ok(MatchArm { pattern, guard, body })
+    }
+
+    /// Parse a match pattern, adding the names it binds to `names`
+    fn parse_pattern(&mut self, names: &mut Vec<String>) -> Result<Pattern, ParseError> {
+        let pattern = match mem::replace(&mut self.current_token, Token::Eof) {
+            Token::Identifier(name) if name == "_" => {
+                self.advance()?;
+                Pattern::Wildcard
+            }
+            Token::Identifier(name) => {
+                if names.contains(&name) {
+                    let error = self.syntax_error(format!(
+                        "Name '{}' is bound twice in the same pattern",
+                        name
+                    ));
+                    self.current_token = Token::Identifier(name);
+                    return Err(error);
+                }
+                self.advance()?;
+                names.push(name.clone());
+                Pattern::Bind(name)
+            }
+            Token::LBrace => {
+                self.advance()?;
+                let mut entries = vec![];
+                while !self.check(&Token::RBrace) {
+                    let key = match &self.current_token {
+                        Token::String(s) | Token::Identifier(s) => s.clone(),
+                        _ => {
+                            return Err(
+                                self.unexpected("string or identifier as object key".to_string())
+                            )
+                        }
+                    };
+                    self.advance()?;
+                    self.expect(Token::Colon)?;
+                    entries.push((key, self.nested(|p| p.parse_pattern(names))?));
+
+                    if !self.check(&Token::RBrace) {
+                        self.expect(Token::Comma)?;
+                    }
+                }
+                self.expect(Token::RBrace)?;
+                Pattern::Object(entries)
+            }
+            Token::LBracket => {
+                self.advance()?;
+                let mut items = vec![];
+                let mut rest = false;
+                while !self.check(&Token::RBracket) {
+                    // A trailing `...` allows more elements
+                    if self.check(&Token::Dot) {
+                        for _ in 0..3 {
+                            self.expect(Token::Dot)?;
+                        }
+                        rest = true;
+                        break;
+                    }
+                    items.push(self.nested(|p| p.parse_pattern(names))?);
+
+                    if !self.check(&Token::RBracket) {
+                        self.expect(Token::Comma)?;
+                    }
+                }
+                self.expect(Token::RBracket)?;
+                Pattern::Array { items, rest }
+            }
+            token @ (Token::Float(_)
+            | Token::Integer(_)
+            | Token::String(_)
+            | Token::Boolean(_)
+            | Token::Null
+            | Token::Minus) => {
+                self.current_token = token;
+                Pattern::Literal(self.parse_primary()?)
+            }
+            token => {
+                let error = self.unexpected_token("pattern", token.clone());
+                self.current_token = token;
+                return Err(error);
+            }
+        };
+        Ok(pattern)
+    }
+
+    fn parse_udf_call(&mut self) -> Result<Expr, ParseError> {
+        let name = match &self.current_token {
+            Token::Identifier(n) => n.clone(),
+            _ => return Err(self.unexpected("UDF name after '&'".to_string())),
+        };
+        self.advance()?;
+        self.expect(Token::LBracket)?;
+
+        let mut args = vec![];
+        while !self.check(&Token::RBracket) {
+            args.push(self.parse_expression()?);
+
+            if !self.check(&Token::RBracket) {
+                self.expect(Token::Comma)?;
+            }
+        }
+
+        self.expect(Token::RBracket)?;
+        Ok(Expr::UDFCall { name, args })
+    }
+
     fn parse_access(&mut self) -> Result<Expr, ParseError> {
+        self.scoped(Self::parse_access_chain)
+    }
+
+    fn parse_access_chain(&mut self) -> Result<Expr, ParseError> {
         let mut expr = self.parse_primary()?;
 
         loop {
+            self.descend()?;
+
             if self.check(&Token::LBracket) {
                 self.advance()?;
 
@@ -239,10 +709,7 @@ impl Parser {
                 let name = match &self.current_token {
                     Token::Identifier(n) => n.clone(),
                     _ => {
-                        return Err(ParseError::UnexpectedToken {
-                            expected: "identifier after '.'".to_string(),
-                            got: self.current_token.clone(),
-                        })
+                        return Err(self.unexpected("identifier after '.'".to_string()))
                     }
                 };
 
@@ -287,67 +754,78 @@ impl Parser {
     }
 
     fn parse_access_key(&mut self) -> Result<Expr, ParseError> {
-        match &self.current_token {
-            Token::Identifier(_) | Token::String(_) => {
-                match mem::replace(&mut self.current_token, Token::Eof) {
-                    Token::Identifier(name) => {
-                        self.advance()?;
-                        Ok(Expr::Key(name))
-                    }
-                    Token::String(name) => {
-                        self.advance()?;
-                        Ok(Expr::Key(name))
-                    }
-                    _ => unreachable!(),
-                }
+        match &mut self.current_token {
+            Token::Identifier(name) | Token::String(name) => {
+                let name = mem::take(name);
+                self.advance()?;
+                Ok(Expr::Key(name))
+            }
+            Token::Star if self.wildcards => {
+                self.advance()?;
+                Ok(Expr::Wildcard)
+            }
+            Token::Star if matches!(self.lexer.peek_token(), Ok(Token::RBracket)) => Err(self.syntax_error(
+                "[*] can only be used in a delete target, like -($[items][*][password])".to_string(),
+            )),
+            // A computed key is an ordinary expression, even in a delete target
+            _ => {
+                let wildcards = mem::replace(&mut self.wildcards, false);
+                let key = self.parse_expression();
+                self.wildcards = wildcards;
+                key
             }
-            _ => self.parse_expression(),
         }
     }
 
     fn parse_multiplicative(&mut self) -> Result<Expr, ParseError> {
-        let mut left = self.parse_access()?;
-
-        loop {
-            let op = match &self.current_token {
-                Token::Star => BinOp::Multiply,
-                Token::Slash => BinOp::Divide,
-                Token::Percent => BinOp::Modulo,
-                _ => break,
-            };
+        self.scoped(|p| {
+            let mut left = p.parse_access()?;
+
+            loop {
+                let op = match &p.current_token {
+                    Token::Star => BinOp::Multiply,
+                    Token::Slash => BinOp::Divide,
+                    Token::Percent => BinOp::Modulo,
+                    _ => break,
+                };
 
-            self.advance()?;
-            let right = self.parse_access()?;
+                p.descend()?;
+                p.advance()?;
+                let right = p.parse_access()?;
 
-            left = Expr::BinaryOp {
-                op,
-                left: Box::new(left),
-                right: Box::new(right),
-            };
-        }
-        Ok(left)
+                left = Expr::BinaryOp {
+                    op,
+                    left: Box::new(left),
+                    right: Box::new(right),
+                };
+            }
+            Ok(left)
+        })
     }
 
     fn parse_additive(&mut self) -> Result<Expr, ParseError> {
-        let mut left = self.parse_multiplicative()?;
-
-        loop {
-            let op = match &self.current_token {
-                Token::Plus => BinOp::Add,
-                Token::Minus => BinOp::Subtract,
-                _ => break,
-            };
+        self.scoped(|p| {
+            let mut left = p.parse_multiplicative()?;
+
+            loop {
+                let op = match &p.current_token {
+                    Token::Plus => BinOp::Add,
+                    Token::Minus => BinOp::Subtract,
+                    _ => break,
+                };
 
-            self.advance()?;
-            let right = self.parse_multiplicative()?;
+                p.descend()?;
+                p.advance()?;
+                let right = p.parse_multiplicative()?;
 
-            left = Expr::BinaryOp {
-                op,
-                left: Box::new(left),
-                right: Box::new(right),
-            };
-        }
-        Ok(left)
+                left = Expr::BinaryOp {
+                    op,
+                    left: Box::new(left),
+                    right: Box::new(right),
+                };
+            }
+            Ok(left)
+        })
     }
 
     fn parse_comparison(&mut self) -> Result<Expr, ParseError> {
@@ -356,6 +834,7 @@ impl Parser {
         if let Some(op) = match &self.current_token {
             Token::EqEq => Some(BinOp::Equal),
             Token::NotEq => Some(BinOp::NotEqual),
+            Token::TildeEq => Some(BinOp::ApproxEqual),
             Token::Lt => Some(BinOp::LessThan),
             Token::Gt => Some(BinOp::GreaterThan),
             Token::LtEq => Some(BinOp::LessEqual),
@@ -375,51 +854,60 @@ impl Parser {
     }
 
     fn parse_and(&mut self) -> Result<Expr, ParseError> {
-        let mut left = self.parse_comparison()?;
-
-        while self.check(&Token::And) {
-            self.advance()?;
-            let right = self.parse_comparison()?;
-
-            left = Expr::BinaryOp {
-                op: BinOp::And,
-                left: Box::new(left),
-                right: Box::new(right),
-            };
-        }
-        Ok(left)
+        self.scoped(|p| {
+            let mut left = p.parse_comparison()?;
+
+            while p.check(&Token::And) {
+                p.descend()?;
+                p.advance()?;
+                let right = p.parse_comparison()?;
+
+                left = Expr::BinaryOp {
+                    op: BinOp::And,
+                    left: Box::new(left),
+                    right: Box::new(right),
+                };
+            }
+            Ok(left)
+        })
     }
 
     fn parse_or(&mut self) -> Result<Expr, ParseError> {
-        let mut left = self.parse_and()?;
-
-        while self.check(&Token::Or) {
-            self.advance()?;
-            let right = self.parse_and()?;
-
-            left = Expr::BinaryOp {
-                op: BinOp::Or,
-                left: Box::new(left),
-                right: Box::new(right),
-            };
-        }
-        Ok(left)
+        self.scoped(|p| {
+            let mut left = p.parse_and()?;
+
+            while p.check(&Token::Or) {
+                p.descend()?;
+                p.advance()?;
+                let right = p.parse_and()?;
+
+                left = Expr::BinaryOp {
+                    op: BinOp::Or,
+                    left: Box::new(left),
+                    right: Box::new(right),
+                };
+            }
+            Ok(left)
+        })
     }
 
     fn parse_null_coalesce(&mut self) -> Result<Expr, ParseError> {
-        let mut left = self.parse_or()?;
-
-        while self.check(&Token::DoubleQuestion) {
-            self.advance()?;
-            let right = self.parse_or()?;
-
-            left = Expr::BinaryOp {
-                op: BinOp::NullCoalesce,
-                left: Box::new(left),
-                right: Box::new(right),
-            };
-        }
-        Ok(left)
+        self.scoped(|p| {
+            let mut left = p.parse_or()?;
+
+            while p.check(&Token::DoubleQuestion) {
+                p.descend()?;
+                p.advance()?;
+                let right = p.parse_or()?;
+
+                left = Expr::BinaryOp {
+                    op: BinOp::NullCoalesce,
+                    left: Box::new(left),
+                    right: Box::new(right),
+                };
+            }
+            Ok(left)
+        })
     }
 
     pub fn parse_expression(&mut self) -> Result<Expr, ParseError> {
@@ -431,11 +919,28 @@ impl Parser {
         self.expect(Token::Eof)?;
         Ok(expr)
     }
+
+    /// Parse a path as written in a delete, such as `$[users][*][password]`:
+    /// an access expression whose keys may include the wildcard `[*]`
+    pub fn parse_target(&mut self) -> Result<Expr, ParseError> {
+        let target = self.parse_wildcard_access()?;
+        self.expect(Token::Eof)?;
+        Ok(target)
+    }
+
+    fn parse_wildcard_access(&mut self) -> Result<Expr, ParseError> {
+        self.wildcards = true;
+        let target = self.parse_access();
+        self.wildcards = false;
+        target
+    }
 }
 
 impl Parser {
     /// Parse a complete query
     pub fn parse_query(&mut self) -> Result<Query, ParseError> {
+        #[cfg(feature = "tracing")]
+        let phase = crate::telemetry::Phase::parse();
         let mut udfs = vec![];
 
         while self.check(&Token::Ampersand) {
@@ -446,11 +951,11 @@ impl Parser {
         let mut output = None;
 
         self.expect(Token::Dollar)?;
-        while self.check(&Token::Pipe) && output.is_none() {
+        while self.check(&Token::Pipe) {
             self.advance()?;
 
             if self.check(&Token::Exclamation) {
-                output = Some(self.parse_output()?);
+                output = Some(self.parse_outputs()?);
                 break;
             } else {
                 statements.push(self.parse_statement()?);
@@ -459,17 +964,166 @@ impl Parser {
 
         self.expect(Token::Eof)?;
 
-        Ok(Query {
+        let query = Query {
             udfs,
             statements,
             output,
-        })
+        };
+        #[cfg(feature = "tracing")]
+        phase.record_query(&query);
+        Ok(query)
+    }
+
+    /// Parse a complete query, continuing past syntax errors.
+    ///
+    /// After an error the parser skips ahead to the next `|` and carries on
+    /// with the statement after it (within the UDF definitions, to the next
+    /// line starting with `&` or `$`), so a single pass finds an error in
+    /// every statement rather than just the first. Returns the query built
+    /// from everything that parsed, and the errors in source order; the query
+    /// is only complete when there are none.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use clove_lang::{Lexer, Parser};
+    ///
+    /// let mut parser = Parser::new(Lexer::new("$ | ?($[a] ==) | ~($[b] := 1) | !($[c]")).unwrap();
+    /// let (query, errors) = parser.parse_query_recovering();
+    ///
+    /// assert_eq!(query.statements.len(), 1);
+    /// assert_eq!(errors.len(), 2);
+    /// assert_eq!(errors[1].message(), "Expected RParen, got Eof");
+    /// ```
+    pub fn parse_query_recovering(&mut self) -> (Query, Vec<ParseError>) {
+        let mut errors = vec![];
+        let mut udfs = vec![];
+
+        while self.check(&Token::Ampersand) {
+            match self.parse_udf_definition() {
+                Ok(udf) => udfs.push(udf),
+                Err(error) => {
+                    let line = self.current_position.line;
+                    self.recover(error, &mut errors, |token, position| {
+                        position.line > line && matches!(token, Token::Ampersand | Token::Dollar)
+                    });
+                }
+            }
+        }
+
+        let mut statements = vec![];
+        let mut output = None;
+
+        if self.check(&Token::Dollar) {
+            if let Err(error) = self.advance() {
+                self.recover(error, &mut errors, |_, _| false);
+            }
+        } else {
+            self.recover(self.unexpected("Dollar"), &mut errors, |_, _| false);
+        }
+
+        loop {
+            match self.current_token {
+                Token::Eof => break,
+                Token::Pipe => {}
+                _ => {
+                    self.recover(self.unexpected("Eof"), &mut errors, |_, _| false);
+                    continue;
+                }
+            }
+
+            let parsed = self.advance().and_then(|()| {
+                if self.check(&Token::Exclamation) {
+                    let outputs = self.parse_outputs()?;
+                    output.get_or_insert(outputs);
+                } else {
+                    statements.push(self.parse_statement()?);
+                }
+                Ok(())
+            });
+            if let Err(error) = parsed {
+                self.recover(error, &mut errors, |_, _| false);
+            }
+        }
+
+        (Query { udfs, statements, output }, errors)
+    }
+
+    /// Record `error` and skip to the next `|`, the end of the query, or a
+    /// token `stop` accepts, recording lexer errors along the way
+    fn recover(
+        &mut self,
+        error: ParseError,
+        errors: &mut Vec<ParseError>,
+        stop: impl Fn(&Token, Position) -> bool,
+    ) {
+        // After a lexer error the current token is the one before it
+        let mut stale = self.record(error, errors);
+        while stale
+            || !(matches!(self.current_token, Token::Pipe | Token::Eof)
+                || stop(&self.current_token, self.current_position))
+        {
+            stale = match self.advance() {
+                Ok(()) => false,
+                Err(error) => self.record(error, errors),
+            };
+        }
+    }
+
+    /// Add `error` to `errors`, moving the lexer past it if it is a lexer
+    /// error. Returns whether it was.
+    fn record(&mut self, error: ParseError, errors: &mut Vec<ParseError>) -> bool {
+        let lexical = match &error {
+            ParseError::LexError { error, .. } => {
+                self.lexer.skip_error(error);
+                true
+            }
+            _ => false,
+        };
+        errors.push(error);
+        lexical
+    }
+
+    /// Parse a UDF library: a sequence of UDF definitions with no pipeline
+    ///
+    /// Used for files pulled in with `%include` (see [`crate::loader`]).
+    pub fn parse_udf_library(&mut self) -> Result<Vec<UDF>, ParseError> {
+        let mut udfs = vec![];
+        while self.check(&Token::Ampersand) {
+            udfs.push(self.parse_udf_definition()?);
+        }
+        self.expect(Token::Eof)?;
+        Ok(udfs)
     }
 
     fn parse_statement(&mut self) -> Result<Statement, ParseError> {
         match &self.current_token {
             Token::Question => self.parse_filter(),
             Token::Tilde => self.parse_transform(),
+            Token::Identifier(name)
+                if name == "when" && matches!(self.lexer.peek_token(), Ok(Token::LParen)) =>
+            {
+                self.parse_when()
+            }
+            Token::Identifier(name)
+                if name == "foreach" && matches!(self.lexer.peek_token(), Ok(Token::Dollar | Token::At)) =>
+            {
+                self.parse_foreach()
+            }
+            // A UDF body may start with an argument reference: `@1 * 2`
+            // or `@price * 2`, and a foreach body with its element
+            Token::At
+                if match self.lexer.peek_token() {
+                    Ok(Token::Integer(n)) => n > 0,
+                    Ok(Token::Identifier(name)) => {
+                        self.params.contains(&name) || self.lambdas.contains(&name)
+                    }
+                    _ => false,
+                } =>
+            {
+                let expr = self.parse_expression()?;
+                Ok(Statement::Access(expr))
+            }
             Token::At => self.parse_scope_definition_or_access(),
             Token::Minus => {
                 // Peek: if '-' followed by '(' it's a delete statement
@@ -480,11 +1134,7 @@ impl Parser {
                 } else {
                     // Put back the minus context by parsing as negation expression
                     let operand = self.parse_primary()?;
-                    let expr = Expr::BinaryOp {
-                        op: BinOp::Subtract,
-                        left: Box::new(Expr::Integer(0)),
-                        right: Box::new(operand),
-                    };
+                    let expr = negate(operand);
                     // Continue parsing the rest of the expression
                     // (access, multiplicative, additive, etc.)
                     Ok(Statement::Access(expr))
@@ -499,11 +1149,57 @@ impl Parser {
 
     fn parse_delete(&mut self) -> Result<Statement, ParseError> {
         self.expect(Token::LParen)?;
-        let path_expr = self.parse_access()?;
+        let path_expr = self.parse_wildcard_access()?;
         self.expect(Token::RParen)?;
         Ok(Statement::Delete(path_expr))
     }
 
+    /// `when (condition) { statement | statement ... }`
+    fn parse_when(&mut self) -> Result<Statement, ParseError> {
+        self.advance()?;
+        self.expect(Token::LParen)?;
+        let condition = self.parse_expression()?;
+        self.expect(Token::RParen)?;
+        self.expect(Token::LBrace)?;
+        let statements = self.parse_block_statements()?;
+        self.expect(Token::RBrace)?;
+        Ok(Statement::When { condition, statements })
+    }
+
+    /// `foreach path as @name { statement | statement ... }`
+    fn parse_foreach(&mut self) -> Result<Statement, ParseError> {
+        self.advance()?;
+        let path = self.parse_access()?;
+        match &self.current_token {
+            Token::Identifier(word) if word == "as" => self.advance()?,
+            _ => return Err(self.unexpected("'as' after the foreach path".to_string())),
+        }
+        self.expect(Token::At)?;
+        let name = match &self.current_token {
+            Token::Identifier(name) => name.clone(),
+            _ => return Err(self.unexpected("identifier after '@'".to_string())),
+        };
+        self.advance()?;
+        self.expect(Token::LBrace)?;
+
+        self.lambdas.push(name);
+        let statements = self.parse_block_statements();
+        let name = self.lambdas.pop().unwrap_or_default();
+        let statements = statements?;
+        self.expect(Token::RBrace)?;
+        Ok(Statement::ForEach { path, name, statements })
+    }
+
+    /// One or more statements separated by `|`, as in a `when` or `foreach` body
+    fn parse_block_statements(&mut self) -> Result<Vec<Statement>, ParseError> {
+        let mut statements = vec![self.parse_statement()?];
+        while self.check(&Token::Pipe) {
+            self.advance()?;
+            statements.push(self.parse_statement()?);
+        }
+        Ok(statements)
+    }
+
     fn parse_filter(&mut self) -> Result<Statement, ParseError> {
         self.advance()?;
         self.expect(Token::LParen)?;
@@ -534,58 +1230,193 @@ impl Parser {
         Ok(Statement::Transform { target, value })
     }
 
-    fn parse_output(&mut self) -> Result<Expr, ParseError> {
+    /// Parse the outputs ending a query: either one `!(expr)`, or any number
+    /// of named `!(name: expr)` outputs, which combine into an object keyed by
+    /// name. Outputs may be separated by `|` or just whitespace.
+    fn parse_outputs(&mut self) -> Result<Expr, ParseError> {
+        let mut unnamed = None;
+        let mut named: Vec<(String, Expr)> = vec![];
+
+        loop {
+            let position = self.current_position;
+            let (name, expr) = self.parse_output()?;
+            match name {
+                None if unnamed.is_none() && named.is_empty() => unnamed = Some(expr),
+                None => {
+                    return Err(self.syntax_error_at(
+                        position,
+                        "A query with several outputs must name each one, e.g. !(report: ...) !(summary: ...)".to_string(),
+                    ))
+                }
+                Some(_) if unnamed.is_some() => {
+                    return Err(self.syntax_error_at(
+                        position,
+                        "Cannot mix an unnamed output with named outputs; name the first one too".to_string(),
+                    ))
+                }
+                Some(name) if named.iter().any(|(existing, _)| *existing == name) => {
+                    return Err(self.syntax_error_at(
+                        position,
+                        format!("Duplicate output name '{}'", name),
+                    ))
+                }
+                Some(name) => named.push((name, expr)),
+            }
+
+            if self.check(&Token::Pipe) {
+                self.advance()?;
+                if !self.check(&Token::Exclamation) {
+                    return Err(self.unexpected("another output '!(...)' (outputs must come last)"));
+                }
+            } else if !self.check(&Token::Exclamation) {
+                break;
+            }
+        }
+
+        Ok(unnamed.unwrap_or(Expr::Object(named)))
+    }
+
+    /// Parse `!(expr)` or `!(name: expr)`
+    fn parse_output(&mut self) -> Result<(Option<String>, Expr), ParseError> {
         self.advance()?;
         self.expect(Token::LParen)?;
+        let name = match &mut self.current_token {
+            // `name:`, but not an expression such as `ext::name(...)` or
+            // `match ...`
+            Token::Identifier(name)
+                if matches!(self.lexer.peek_token(), Ok(Token::Colon)) && !self.lexer.at_path_separator() =>
+            {
+                let name = mem::take(name);
+                self.advance()?;
+                self.expect(Token::Colon)?;
+                Some(name)
+            }
+            _ => None,
+        };
         let expr = self.parse_expression()?;
         self.expect(Token::RParen)?;
-        Ok(expr)
+        Ok((name, expr))
     }
 
     fn parse_udf_definition(&mut self) -> Result<UDF, ParseError> {
+        // Parameter names only mean something within their own definition
+        let udf = self.parse_udf();
+        self.params.clear();
+        udf
+    }
+
+    /// Parse `&name:arity := body` or `&name(params) := body`
+    fn parse_udf(&mut self) -> Result<UDF, ParseError> {
         self.expect(Token::Ampersand)?;
 
         let name = match &self.current_token {
             Token::Identifier(n) => n.clone(),
             _ => {
-                return Err(ParseError::UnexpectedToken {
-                    expected: "UDF name".to_string(),
-                    got: self.current_token.clone(),
-                })
+                return Err(self.unexpected("UDF name".to_string()))
             }
         };
 
         self.advance()?;
-        self.expect(Token::Colon)?;
 
-        let arity = match &self.current_token {
-            Token::Integer(n) if *n >= 0 => *n as usize,
-            _ => {
-                return Err(ParseError::UnexpectedToken {
-                    expected: "non-negative integer for UDF arity".to_string(),
-                    got: self.current_token.clone(),
-                })
-            }
+        let (arity, defaults, variadic) = if self.check(&Token::LParen) {
+            self.parse_udf_params()?
+        } else {
+            self.expect(Token::Colon)?;
+            let arity = match &self.current_token {
+                Token::Integer(n) if *n >= 0 => *n as usize,
+                _ => {
+                    return Err(self.unexpected("non-negative integer for UDF arity".to_string()))
+                }
+            };
+            self.advance()?;
+            (arity, Vec::new(), false)
         };
-        self.advance()?;
 
         self.expect(Token::ColonEqual)?;
 
         let body = self.parse_statement()?;
 
-        Ok(UDF { name, arity, body })
+        Ok(UDF { name, arity, defaults, variadic, body })
+    }
+
+    /// Parse a parameter list, `(price, rate = 0.2, ...)`, into the arity,
+    /// the defaults of the trailing parameters, and whether it ends in
+    /// `...` (extra arguments collected into `@rest`). Records the names in
+    /// `params` for the body.
+    fn parse_udf_params(&mut self) -> Result<(usize, Vec<Expr>, bool), ParseError> {
+        self.expect(Token::LParen)?;
+
+        let mut defaults = Vec::new();
+        let mut variadic = false;
+        while !self.check(&Token::RParen) {
+            if self.check(&Token::Dot) {
+                for _ in 0..3 {
+                    self.expect(Token::Dot)?;
+                }
+                variadic = true;
+                break;
+            }
+
+            let name = match &self.current_token {
+                Token::Identifier(n) => n.clone(),
+                _ => return Err(self.unexpected("parameter name")),
+            };
+            if self.params.contains(&name) {
+                return Err(self.syntax_error(format!("Duplicate parameter '{}'", name)));
+            }
+            let position = self.current_position;
+            let has_default = self.lexer.skip_bare_equals();
+            self.advance()?;
+
+            // A default sees only the parameters before it
+            if has_default {
+                defaults.push(self.parse_expression()?);
+            } else if !defaults.is_empty() {
+                return Err(self.syntax_error_at(
+                    position,
+                    format!("Parameter '{}' needs a default, as it follows one with a default", name),
+                ));
+            }
+            self.params.push(name);
+
+            if !self.check(&Token::RParen) {
+                self.expect(Token::Comma)?;
+            }
+        }
+        self.expect(Token::RParen)?;
+
+        let arity = self.params.len();
+        if variadic {
+            if self.params.iter().any(|name| name == "rest") {
+                return Err(self.syntax_error(
+                    "A variadic UDF cannot name a parameter 'rest'".to_string(),
+                ));
+            }
+            self.params.push("rest".to_string());
+        }
+        Ok((arity, defaults, variadic))
+    }
+
+    /// What `@name` refers to: the item of an enclosing named lambda or a
+    /// name bound by a match pattern, a parameter of the UDF being parsed,
+    /// or else a scope
+    fn at_name(&self, name: String) -> Expr {
+        if self.lambdas.contains(&name) || self.bound.contains(&name) {
+            return Expr::LambdaVar(name);
+        }
+        match self.params.iter().position(|param| *param == name) {
+            Some(index) => Expr::ArgRef(index + 1),
+            None => Expr::ScopeRef(name),
+        }
     }
 
     fn parse_scope_definition_or_access(&mut self) -> Result<Statement, ParseError> {
         self.advance()?;
 
         let name = match &self.current_token {
-            Token::Identifier(n) => n.clone(),
+            Token::Identifier(n) if n != "catch" => n.clone(),
             _ => {
-                return Err(ParseError::UnexpectedToken {
-                    expected: "identifier after '@'".to_string(),
-                    got: self.current_token.clone(),
-                })
+                return Err(self.unexpected("identifier after '@'".to_string()))
             }
         };
 
@@ -595,6 +1426,10 @@ impl Parser {
             self.advance()?;
             let path = self.parse_expression()?;
             Ok(Statement::ScopeDefinition { name, path })
+        } else if self.check(&Token::ColonColonEqual) {
+            self.advance()?;
+            let path = self.parse_expression()?;
+            Ok(Statement::LiveScopeDefinition { name, path })
         } else {
             let mut expr = Expr::ScopeRef(name);
 
@@ -614,10 +1449,7 @@ impl Parser {
                     let field_name = match &self.current_token {
                         Token::Identifier(n) => n.clone(),
                         _ => {
-                            return Err(ParseError::UnexpectedToken {
-                                expected: "identifier after '.'".to_string(),
-                                got: self.current_token.clone(),
-                            })
+                            return Err(self.unexpected("identifier after '.'".to_string()))
                         }
                     };
                     self.advance()?;
@@ -632,3 +1464,22 @@ impl Parser {
         }
     }
 }
+
+/// Unary minus. Integer literals are folded, so `[-1]` is a literal index
+/// that access paths, transforms and deletes all accept, and so are float
+/// literals, so `-1.0` stays a float; anything else becomes `0 - operand`.
+fn negate(operand: Expr) -> Expr {
+    if let Expr::Integer(n) = operand
+        && let Some(negated) = n.checked_neg()
+    {
+        return Expr::Integer(negated);
+    }
+    if let Expr::Float(n) = operand {
+        return Expr::Float(-n);
+    }
+    Expr::BinaryOp {
+        op: BinOp::Subtract,
+        left: Box::new(Expr::Integer(0)),
+        right: Box::new(operand),
+    }
+}
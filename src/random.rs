@@ -0,0 +1,70 @@
+//! The random number generator behind `.sample()`, `.shuffle()` and
+//! `&random[]`.
+//!
+//! A SplitMix64 generator: fast, small, and good enough for picking spot
+//! checks, but not for anything security related. Each evaluator starts
+//! from a random seed unless given one with
+//! [`Evaluator::with_seed`](crate::Evaluator::with_seed), in which case the
+//! same queries on the same documents give the same results.
+
+use std::cell::Cell;
+use std::hash::{BuildHasher, RandomState};
+
+#[derive(Debug)]
+pub(crate) struct Rng {
+    state: Cell<u64>,
+}
+
+impl Default for Rng {
+    fn default() -> Self {
+        Rng::seeded(RandomState::new().hash_one(0u8))
+    }
+}
+
+impl Rng {
+    pub(crate) fn seeded(seed: u64) -> Self {
+        Rng { state: Cell::new(seed) }
+    }
+
+    pub(crate) fn next_u64(&self) -> u64 {
+        let state = self.state.get().wrapping_add(0x9e37_79b9_7f4a_7c15);
+        self.state.set(state);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// A float in `[0, 1)`
+    pub(crate) fn next_f64(&self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// An integer in `[0, bound)`; `bound` must be positive
+    pub(crate) fn below(&self, bound: usize) -> usize {
+        ((self.next_u64() as u128 * bound as u128) >> 64) as usize
+    }
+
+    /// Reorder `items` uniformly at random (Fisher-Yates)
+    pub(crate) fn shuffle<T>(&self, items: &mut [T]) {
+        for i in (1..items.len()).rev() {
+            items.swap(i, self.below(i + 1));
+        }
+    }
+
+    /// `n` of `items` chosen uniformly at random, in their original order
+    /// (Knuth's selection sampling); all of them if there are at most `n`
+    pub(crate) fn sample<'a, T>(&self, items: &'a [T], n: usize) -> Vec<&'a T> {
+        let mut chosen = Vec::with_capacity(n.min(items.len()));
+        for (seen, item) in items.iter().enumerate() {
+            let needed = n - chosen.len();
+            if needed == 0 {
+                break;
+            }
+            if self.below(items.len() - seen) < needed {
+                chosen.push(item);
+            }
+        }
+        chosen
+    }
+}
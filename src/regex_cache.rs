@@ -0,0 +1,166 @@
+//! Compiled `.matches()` patterns.
+//!
+//! Patterns come from queries, and so often from users, so they are
+//! compiled with limits: a pattern that nests groups or repetitions more
+//! than [`REGEX_NEST_LIMIT`] deep, or whose compiled form would take more
+//! than [`REGEX_SIZE_LIMIT`] bytes (see
+//! [`Evaluator::with_regex_size_limit`](crate::Evaluator::with_regex_size_limit)),
+//! fails with [`EvalError::InvalidRegex`] instead of tying up memory and
+//! time. Matching takes time linear in the input, whatever the pattern.
+//!
+//! Compiled patterns are kept in a [`RegexCache`], so a pattern is compiled
+//! once rather than for every element or record. Each evaluator has its
+//! own; hand one to several with
+//! [`Evaluator::with_regex_cache`](crate::Evaluator::with_regex_cache) to
+//! share it.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+use regex::{Regex, RegexBuilder};
+
+use crate::evaluator::EvalError;
+
+/// Largest compiled size of a pattern, in bytes, unless the evaluator says
+/// otherwise
+pub const REGEX_SIZE_LIMIT: usize = 4 << 20;
+
+/// Deepest nesting of groups and repetitions in a pattern
+pub const REGEX_NEST_LIMIT: u32 = 64;
+
+/// Memory for the lazy DFA of each pattern. Running out only makes
+/// matching slower, not fail.
+const DFA_SIZE_LIMIT: usize = 1 << 20;
+
+/// A least-recently-used cache of compiled patterns.
+///
+/// Clones share the same cache, so evaluators given clones of one cache
+/// compile each pattern once between them.
+///
+/// # Examples
+///
+/// ```
+/// use clove_lang::{Evaluator, Lexer, Parser, RegexCache, Value};
+///
+/// let cache = RegexCache::new(100);
+/// let expr = Parser::new(Lexer::new(r#"$.matches("^[a-z]+$")"#)).unwrap().parse().unwrap();
+/// for word in ["abc", "ABC"] {
+///     let mut evaluator = Evaluator::new().with_regex_cache(cache.clone());
+///     evaluator.eval_expression(&expr, Value::String(word.into())).unwrap();
+/// }
+/// assert_eq!(cache.len(), 1);
+/// ```
+#[derive(Debug, Clone)]
+pub struct RegexCache {
+    inner: Arc<Mutex<Lru>>,
+}
+
+#[derive(Debug)]
+struct Lru {
+    capacity: usize,
+    /// Incremented on every lookup, to order entries by last use
+    clock: u64,
+    /// Patterns by source and size limit, with when each was last used
+    entries: HashMap<(String, usize), (Regex, u64)>,
+}
+
+impl RegexCache {
+    /// The capacity of an evaluator's own cache
+    pub const DEFAULT_CAPACITY: usize = 256;
+
+    /// An empty cache holding up to `capacity` patterns (none when 0)
+    pub fn new(capacity: usize) -> Self {
+        let lru = Lru { capacity, clock: 0, entries: HashMap::new() };
+        RegexCache { inner: Arc::new(Mutex::new(lru)) }
+    }
+
+    /// How many patterns the cache holds at most
+    pub fn capacity(&self) -> usize {
+        self.lock().capacity
+    }
+
+    /// How many patterns the cache holds
+    pub fn len(&self) -> usize {
+        self.lock().entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Forget every pattern
+    pub fn clear(&self) {
+        self.lock().entries.clear();
+    }
+
+    /// `pattern` compiled with a limit of `size_limit` bytes, from the
+    /// cache if it is there
+    pub(crate) fn compile(&self, pattern: &str, size_limit: usize) -> Result<Regex, EvalError> {
+        let key = (pattern.to_string(), size_limit);
+        {
+            let mut lru = self.lock();
+            lru.clock += 1;
+            let now = lru.clock;
+            if let Some((regex, used)) = lru.entries.get_mut(&key) {
+                *used = now;
+                return Ok(regex.clone());
+            }
+        }
+
+        // Compile without holding the lock; another evaluator compiling the
+        // same pattern meanwhile only costs the work twice
+        let regex = compile(pattern, size_limit)?;
+        let mut lru = self.lock();
+        if lru.capacity == 0 {
+            return Ok(regex);
+        }
+        if lru.entries.len() >= lru.capacity
+            && let Some(oldest) = lru.entries.iter().min_by_key(|(_, (_, used))| *used).map(|(k, _)| k.clone())
+        {
+            lru.entries.remove(&oldest);
+        }
+        let now = lru.clock;
+        lru.entries.insert(key, (regex.clone(), now));
+        Ok(regex)
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Lru> {
+        // Every update leaves the cache consistent, so a panic while it was
+        // locked does no harm
+        self.inner.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl Default for RegexCache {
+    fn default() -> Self {
+        RegexCache::new(RegexCache::DEFAULT_CAPACITY)
+    }
+}
+
+fn compile(pattern: &str, size_limit: usize) -> Result<Regex, EvalError> {
+    RegexBuilder::new(pattern)
+        .size_limit(size_limit)
+        .dfa_size_limit(DFA_SIZE_LIMIT)
+        .nest_limit(REGEX_NEST_LIMIT)
+        .build()
+        .map_err(|error| invalid_regex(pattern, error, size_limit))
+}
+
+/// The error for a pattern `regex` rejected. Its errors only describe a
+/// syntax error as text, so the pattern is parsed again for the position.
+fn invalid_regex(pattern: &str, error: regex::Error, size_limit: usize) -> EvalError {
+    let invalid = |message: String, offset: Option<usize>| EvalError::InvalidRegex {
+        pattern: pattern.to_string(),
+        message,
+        position: offset.map(|offset| pattern[..offset].chars().count()),
+    };
+    if let regex::Error::CompiledTooBig(_) = error {
+        return invalid(format!("compiles to more than the limit of {} bytes", size_limit), None);
+    }
+    let parsed = regex_syntax::ParserBuilder::new().nest_limit(REGEX_NEST_LIMIT).build().parse(pattern);
+    match parsed {
+        Err(regex_syntax::Error::Parse(e)) => invalid(e.kind().to_string(), Some(e.span().start.offset)),
+        Err(regex_syntax::Error::Translate(e)) => invalid(e.kind().to_string(), Some(e.span().start.offset)),
+        _ => invalid(error.to_string(), None),
+    }
+}
@@ -0,0 +1,221 @@
+//! Built-in functions available to every query.
+//!
+//! Built-ins are called like UDFs, with `&name[args]`, and need no
+//! definition. A UDF defined by the query takes precedence over a built-in
+//! of the same name.
+//!
+//! | Function                 | Result                                          |
+//! |--------------------------|-------------------------------------------------|
+//! | `&is_uuid[s]`            | `s` is a hyphenated UUID (any version)          |
+//! | `&is_email[s]`           | `s` looks like an email address                 |
+//! | `&is_semver[s]`          | `s` is a semantic version (`1.2.3-rc.1`)        |
+//! | `&semver_cmp[a, b]`      | `-1`, `0` or `1` as version `a` sorts before, equal to or after `b` |
+//! | `&parse_url[s]`          | Object of `scheme`, `username`, `password`, `host`, `port`, `path`, `query`, `fragment`; `null` if `s` is not a URL |
+//! | `&base64_encode[s]`      | Standard, padded base64 of the string's bytes   |
+//! | `&base64_decode[s]`      | The decoded string                              |
+//! | `&sha256[s]`             | Lowercase hex SHA-256 digest of the string      |
+//!
+//! The `is_*` checks are `false` for non-strings; the other functions
+//! require strings and fail with a type error otherwise.
+//!
+//! The feature also adds methods for taking URLs apart, called on strings:
+//!
+//! | Method                   | Result                                          |
+//! |--------------------------|-------------------------------------------------|
+//! | `.parse_url()`           | The object `&parse_url[s]` returns              |
+//! | `.url_encode()`          | The string percent-encoded as a URL component (all but `A-Z a-z 0-9 - . _ ~`) |
+//! | `.url_decode()`          | The string with `%XX` escapes decoded           |
+//! | `.parse_query_string()`  | Object of the parameters of a query string, with a leading `?` or not; a repeated parameter is an array of its values |
+//!
+//! Built with the `stdlib` cargo feature (on by default). Without it,
+//! calling a built-in is an undefined function error, and calling one of
+//! these methods an unknown method error. `&random[]`, a float
+//! in `[0, 1)`, is not listed here: it is always available, and draws from
+//! the evaluator's generator (see [`Evaluator::with_seed`](crate::Evaluator::with_seed)).
+//!
+//! # Examples
+//!
+//! ```
+//! use clove_lang::{Evaluator, Lexer, Parser, Value};
+//!
+//! let query = Parser::new(Lexer::new("$ | !(&sha256[$])")).unwrap().parse_query().unwrap();
+//! let digest = Evaluator::new().eval_query(&query, Value::String("abc".into())).unwrap();
+//! assert_eq!(
+//!     digest,
+//!     Value::String("ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad".into())
+//! );
+//! ```
+
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::sync::LazyLock;
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use percent_encoding::{AsciiSet, NON_ALPHANUMERIC, percent_decode_str, utf8_percent_encode};
+use regex::Regex;
+use sha2::{Digest, Sha256};
+use url::form_urlencoded;
+
+use crate::evaluator::{EvalError, type_name};
+use crate::value::Value;
+
+/// A native function callable as `&name[...]`
+#[derive(Debug)]
+pub struct Builtin {
+    pub name: &'static str,
+    pub arity: usize,
+    pub call: fn(&[Value]) -> Result<Value, EvalError>,
+}
+
+/// Every built-in function
+pub const BUILTINS: &[Builtin] = &[
+    Builtin { name: "is_uuid", arity: 1, call: is_uuid },
+    Builtin { name: "is_email", arity: 1, call: is_email },
+    Builtin { name: "is_semver", arity: 1, call: is_semver },
+    Builtin { name: "semver_cmp", arity: 2, call: semver_cmp },
+    Builtin { name: "parse_url", arity: 1, call: parse_url },
+    Builtin { name: "base64_encode", arity: 1, call: base64_encode },
+    Builtin { name: "base64_decode", arity: 1, call: base64_decode },
+    Builtin { name: "sha256", arity: 1, call: sha256 },
+];
+
+/// The built-in called `name`, if there is one
+pub fn lookup(name: &str) -> Option<&'static Builtin> {
+    BUILTINS.iter().find(|builtin| builtin.name == name)
+}
+
+/// Every built-in method
+pub const METHODS: &[&str] = &["parse_url", "url_encode", "url_decode", "parse_query_string"];
+
+/// Call the built-in method `name`, one of [`METHODS`], on `object`
+pub(crate) fn call_method(name: &str, object: &Value) -> Result<Value, EvalError> {
+    let Value::String(s) = object else {
+        return Err(EvalError::TypeError(format!(".{}() requires string, got {}", name, type_name(object))));
+    };
+    match name {
+        "parse_url" => Ok(url_object(s)),
+        "url_encode" => Ok(Value::String(utf8_percent_encode(s, URL_COMPONENT).to_string())),
+        "url_decode" => percent_decode_str(s)
+            .decode_utf8()
+            .map(|decoded| Value::String(decoded.into_owned()))
+            .map_err(|_| EvalError::TypeError(".url_decode(): decoded bytes are not UTF-8".to_string())),
+        _ => Ok(query_object(s)),
+    }
+}
+
+/// What `.url_encode()` escapes: all but RFC 3986's unreserved characters
+const URL_COMPONENT: &AsciiSet = &NON_ALPHANUMERIC.remove(b'-').remove(b'.').remove(b'_').remove(b'~');
+
+static UUID: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^(?i)[0-9a-f]{8}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{12}$").unwrap()
+});
+
+// The practical subset of RFC 5322 used by HTML's `type=email` inputs
+static EMAIL: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r"^[A-Za-z0-9.!#$%&'*+/=?^_`{|}~-]+@[A-Za-z0-9](?:[A-Za-z0-9-]{0,61}[A-Za-z0-9])?(?:\.[A-Za-z0-9](?:[A-Za-z0-9-]{0,61}[A-Za-z0-9])?)+$",
+    )
+    .unwrap()
+});
+
+fn is_uuid(args: &[Value]) -> Result<Value, EvalError> {
+    Ok(Value::Boolean(matches!(&args[0], Value::String(s) if UUID.is_match(s))))
+}
+
+fn is_email(args: &[Value]) -> Result<Value, EvalError> {
+    Ok(Value::Boolean(matches!(&args[0], Value::String(s) if EMAIL.is_match(s))))
+}
+
+fn is_semver(args: &[Value]) -> Result<Value, EvalError> {
+    Ok(Value::Boolean(
+        matches!(&args[0], Value::String(s) if semver::Version::parse(s).is_ok()),
+    ))
+}
+
+fn semver_cmp(args: &[Value]) -> Result<Value, EvalError> {
+    let version = |value: &Value| {
+        let text = string_arg("semver_cmp", value)?;
+        semver::Version::parse(text).map_err(|e| {
+            EvalError::TypeError(format!("&semver_cmp: invalid version \"{}\": {}", text, e))
+        })
+    };
+    let ordering = version(&args[0])?.cmp(&version(&args[1])?);
+    Ok(Value::Integer(match ordering {
+        Ordering::Less => -1,
+        Ordering::Equal => 0,
+        Ordering::Greater => 1,
+    }))
+}
+
+fn parse_url(args: &[Value]) -> Result<Value, EvalError> {
+    Ok(url_object(string_arg("parse_url", &args[0])?))
+}
+
+/// The parts of the URL `s`, or null if it is not one
+fn url_object(s: &str) -> Value {
+    let Ok(url) = url::Url::parse(s) else {
+        return Value::Null;
+    };
+    let username = Some(url.username()).filter(|name| !name.is_empty());
+    Value::object([
+        ("scheme", Value::from(url.scheme())),
+        ("username", username.into()),
+        ("password", url.password().into()),
+        ("host", url.host_str().into()),
+        ("port", url.port_or_known_default().map(u32::from).into()),
+        ("path", url.path().into()),
+        ("query", url.query().into()),
+        ("fragment", url.fragment().into()),
+    ])
+}
+
+/// The parameters of the query string `s`, decoding `+` and `%XX`
+fn query_object(s: &str) -> Value {
+    let mut params: HashMap<String, Value> = HashMap::new();
+    for (key, value) in form_urlencoded::parse(s.strip_prefix('?').unwrap_or(s).as_bytes()) {
+        let value = Value::String(value.into_owned());
+        match params.entry(key.into_owned()) {
+            Entry::Vacant(entry) => {
+                entry.insert(value);
+            }
+            Entry::Occupied(mut entry) => match entry.get_mut() {
+                Value::Array(values) => values.push(value),
+                first => *first = Value::Array(vec![std::mem::replace(first, Value::Null), value]),
+            },
+        }
+    }
+    Value::Object(params)
+}
+
+fn base64_encode(args: &[Value]) -> Result<Value, EvalError> {
+    let text = string_arg("base64_encode", &args[0])?;
+    Ok(Value::String(STANDARD.encode(text)))
+}
+
+fn base64_decode(args: &[Value]) -> Result<Value, EvalError> {
+    let text = string_arg("base64_decode", &args[0])?;
+    let bytes = STANDARD
+        .decode(text)
+        .map_err(|e| EvalError::TypeError(format!("&base64_decode: {}", e)))?;
+    String::from_utf8(bytes)
+        .map(Value::String)
+        .map_err(|_| EvalError::TypeError("&base64_decode: decoded bytes are not UTF-8".to_string()))
+}
+
+fn sha256(args: &[Value]) -> Result<Value, EvalError> {
+    let digest = Sha256::digest(string_arg("sha256", &args[0])?.as_bytes());
+    Ok(Value::String(digest.iter().map(|byte| format!("{:02x}", byte)).collect()))
+}
+
+fn string_arg<'a>(function: &str, value: &'a Value) -> Result<&'a str, EvalError> {
+    match value {
+        Value::String(s) => Ok(s),
+        other => Err(EvalError::TypeError(format!(
+            "&{} requires a string, got {}",
+            function,
+            type_name(other)
+        ))),
+    }
+}
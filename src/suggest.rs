@@ -0,0 +1,47 @@
+//! "Did you mean" suggestions for misspelled method, scope and function
+//! names.
+//!
+//! A candidate is a near miss when its edit distance from the name
+//! is at most a third of the name's length (and at least 1), where swapping
+//! two adjacent characters is one edit. So `fliter` suggests `filter`, but
+//! `x` suggests nothing in particular.
+
+/// The `candidates` close to `name`, closest first (ties in the order given)
+pub(crate) fn near_misses<'a>(name: &str, candidates: impl IntoIterator<Item = &'a str>) -> Vec<String> {
+    let limit = (name.chars().count() / 3).max(1);
+    let mut close: Vec<(usize, &str)> = candidates
+        .into_iter()
+        .filter(|candidate| *candidate != name)
+        .map(|candidate| (edit_distance(name, candidate), candidate))
+        .filter(|(distance, _)| *distance <= limit)
+        .collect();
+    close.sort_by_key(|(distance, _)| *distance);
+    let mut names: Vec<String> = Vec::with_capacity(close.len());
+    for (_, candidate) in close {
+        if !names.iter().any(|seen| seen == candidate) {
+            names.push(candidate.to_string());
+        }
+    }
+    names
+}
+
+/// The number of single-character insertions, deletions, substitutions
+/// and swaps of adjacent characters that turn `a` into `b` (the optimal
+/// string alignment distance)
+fn edit_distance(a: &str, b: &str) -> usize {
+    let (a, b): (Vec<char>, Vec<char>) = (a.chars().collect(), b.chars().collect());
+    let mut before: Vec<usize> = Vec::new();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for i in 1..=a.len() {
+        let mut row = vec![i; b.len() + 1];
+        for j in 1..=b.len() {
+            let substitute = previous[j - 1] + usize::from(a[i - 1] != b[j - 1]);
+            row[j] = substitute.min(previous[j] + 1).min(row[j - 1] + 1);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                row[j] = row[j].min(before[j - 2] + 1);
+            }
+        }
+        before = std::mem::replace(&mut previous, row);
+    }
+    previous[b.len()]
+}
@@ -0,0 +1,95 @@
+//! `tracing` instrumentation, for embedders that want to see where the time
+//! of a query goes (feature `tracing`).
+//!
+//! Parsing a query runs in a `parse` span and evaluating one in an `eval`
+//! span, each recording the query's hash
+//! ([`Query::fingerprint`]) so the two can be matched up, and
+//! how long it took in microseconds (`elapsed_us`). Every pipeline
+//! statement emits a `statement` event with its index, stage and duration,
+//! and so does the output expression, with the stage `output`. Everything
+//! is at `DEBUG` level with the target `clove_lang`, and costs nothing
+//! beyond a check per call site when no subscriber wants it.
+
+use std::cell::Cell;
+use std::time::{Duration, Instant};
+
+use tracing::field;
+use tracing::span::EnteredSpan;
+
+use crate::ast::{Query, Statement};
+
+/// An entered span that records how long it was entered as `elapsed_us`
+/// when dropped, on whichever path the phase ends
+pub(crate) struct Phase {
+    span: EnteredSpan,
+    start: Instant,
+    /// The index of the next statement to report
+    next_statement: Cell<usize>,
+}
+
+impl Phase {
+    /// Enter the span for parsing a query
+    pub(crate) fn parse() -> Phase {
+        let span = tracing::debug_span!(
+            target: "clove_lang",
+            "parse",
+            query_hash = field::Empty,
+            statements = field::Empty,
+            elapsed_us = field::Empty,
+        );
+        Phase { span: span.entered(), start: Instant::now(), next_statement: Cell::new(0) }
+    }
+
+    /// Enter the span for evaluating `query`, or a lone expression
+    pub(crate) fn eval(query: Option<&Query>) -> Phase {
+        let span = tracing::debug_span!(
+            target: "clove_lang",
+            "eval",
+            query_hash = field::Empty,
+            statements = field::Empty,
+            elapsed_us = field::Empty,
+        );
+        let phase = Phase { span: span.entered(), start: Instant::now(), next_statement: Cell::new(0) };
+        if let Some(query) = query {
+            phase.record_query(query);
+        }
+        phase
+    }
+
+    /// Record the hash and number of statements of `query`
+    pub(crate) fn record_query(&self, query: &Query) {
+        if !self.span.is_disabled() {
+            self.span.record("query_hash", query.fingerprint().as_str());
+            self.span.record("statements", query.statements.len());
+        }
+    }
+
+    /// Emit the event for the next statement of the pipeline, which took
+    /// `elapsed` and kept the document unless a filter rejected it
+    pub(crate) fn statement(&self, stmt: &Statement, elapsed: Duration, kept: bool) {
+        let index = self.next_statement.replace(self.next_statement.get() + 1);
+        tracing::debug!(
+            target: "clove_lang",
+            index,
+            stage = crate::evaluator::stage_name(stmt),
+            elapsed_us = micros(elapsed),
+            kept,
+            "statement"
+        );
+    }
+}
+
+impl Drop for Phase {
+    fn drop(&mut self) {
+        self.span.record("elapsed_us", micros(self.start.elapsed()));
+    }
+}
+
+/// Emit the event for the output expression, which took `elapsed`
+pub(crate) fn output(elapsed: Duration) {
+    tracing::debug!(target: "clove_lang", stage = "output", elapsed_us = micros(elapsed), "statement");
+}
+
+fn micros(elapsed: Duration) -> u64 {
+    elapsed.as_micros().try_into().unwrap_or(u64::MAX)
+}
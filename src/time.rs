@@ -0,0 +1,162 @@
+//! Timestamps and durations for `.bucket_by_time()` and `.rate_per()`.
+//!
+//! Timestamps are RFC 3339 strings (`2024-05-01T10:05:00Z`,
+//! `2024-05-01 10:05:00.250+02:00`, or a bare date for midnight UTC) or
+//! numbers of Unix seconds, and are handled as milliseconds since the epoch.
+//! Durations are a whole number and a unit: `ms`, `s`, `m`, `h`, `d` or `w`.
+
+use crate::value::Value;
+
+const MS_PER_DAY: i64 = 86_400_000;
+
+/// The Unix seconds of 0000-01-01T00:00:00Z and 9999-12-31T23:59:59Z, the
+/// range RFC 3339 can write
+const MIN_SECONDS: f64 = -62_167_219_200.0;
+const MAX_SECONDS: f64 = 253_402_300_799.0;
+
+/// Milliseconds since the epoch of a timestamp value
+pub(crate) fn timestamp_millis(value: &Value) -> Option<i64> {
+    let seconds = match value {
+        Value::Integer(n) => *n as f64,
+        Value::Float(n) => *n,
+        Value::String(s) => return parse_rfc3339(s),
+        _ => return None,
+    };
+    (MIN_SECONDS..=MAX_SECONDS)
+        .contains(&seconds)
+        .then(|| (seconds * 1000.0).floor() as i64)
+}
+
+/// Milliseconds in a duration such as `"5m"`; `None` unless positive
+pub(crate) fn duration_millis(s: &str) -> Option<i64> {
+    let split = s.find(|c: char| !c.is_ascii_digit())?;
+    let (amount, unit) = s.split_at(split);
+    let unit_millis = match unit {
+        "ms" => 1,
+        "s" => 1000,
+        "m" => 60_000,
+        "h" => 3_600_000,
+        "d" => MS_PER_DAY,
+        "w" => 7 * MS_PER_DAY,
+        _ => return None,
+    };
+    let amount: i64 = amount.parse().ok()?;
+    amount.checked_mul(unit_millis).filter(|&millis| millis > 0)
+}
+
+/// A UTC RFC 3339 timestamp, with milliseconds only when there are any
+pub(crate) fn format_millis(millis: i64) -> String {
+    let (days, day_millis) = (millis.div_euclid(MS_PER_DAY), millis.rem_euclid(MS_PER_DAY));
+    let (year, month, day) = civil_from_days(days);
+    let seconds = day_millis / 1000;
+    let mut out = format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+        year,
+        month,
+        day,
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    );
+    if day_millis % 1000 != 0 {
+        out.push_str(&format!(".{:03}", day_millis % 1000));
+    }
+    out.push('Z');
+    out
+}
+
+fn parse_rfc3339(s: &str) -> Option<i64> {
+    let bytes = s.as_bytes();
+    let number = |range: std::ops::Range<usize>| -> Option<i64> {
+        let digits = s.get(range)?;
+        digits.bytes().all(|b| b.is_ascii_digit()).then(|| digits.parse().ok())?
+    };
+    let separator = |i: usize, expected: u8| bytes.get(i) == Some(&expected);
+
+    if !(separator(4, b'-') && separator(7, b'-')) {
+        return None;
+    }
+    let (year, month, day) = (number(0..4)?, number(5..7)?, number(8..10)?);
+    if !(1..=12).contains(&month) || day < 1 || day > days_in_month(year, month) {
+        return None;
+    }
+    let days = days_from_civil(year, month, day);
+    if bytes.len() == 10 {
+        return Some(days * MS_PER_DAY);
+    }
+
+    if !(matches!(bytes[10], b'T' | b't' | b' ') && separator(13, b':') && separator(16, b':')) {
+        return None;
+    }
+    let (hour, minute, second) = (number(11..13)?, number(14..16)?, number(17..19)?);
+    if hour > 23 || minute > 59 || second > 60 {
+        return None;
+    }
+    let mut millis = days * MS_PER_DAY + ((hour * 60 + minute) * 60 + second) * 1000;
+
+    let mut rest = &s[19..];
+    if let Some(fraction) = rest.strip_prefix('.') {
+        let digits = fraction.bytes().take_while(u8::is_ascii_digit).count();
+        if digits == 0 {
+            return None;
+        }
+        let padded = format!("{:0<3}", &fraction[..digits.min(3)]);
+        millis += padded.parse::<i64>().ok()?;
+        rest = &fraction[digits..];
+    }
+
+    let offset = match rest {
+        "Z" | "z" => 0,
+        _ => {
+            let sign = match rest.as_bytes().first()? {
+                b'+' => 1,
+                b'-' => -1,
+                _ => return None,
+            };
+            let (hours, minutes) = rest[1..].split_once(':')?;
+            if hours.len() != 2 || minutes.len() != 2 {
+                return None;
+            }
+            let (hours, minutes): (i64, i64) = (hours.parse().ok()?, minutes.parse().ok()?);
+            if hours > 23 || minutes > 59 {
+                return None;
+            }
+            sign * (hours * 60 + minutes) * 60_000
+        }
+    };
+    Some(millis - offset)
+}
+
+fn days_in_month(year: i64, month: i64) -> i64 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Days since 1970-01-01 of a proleptic Gregorian date (Howard Hinnant's
+/// `days_from_civil`)
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// The date `days` after 1970-01-01 (`civil_from_days`)
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
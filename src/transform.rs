@@ -1,3 +1,9 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::evaluator::type_name;
+use crate::output::to_json;
+use crate::value::{Value, whole_number};
 use crate::{ast::Expr, evaluator::EvalError};
 
 /// A segment in a navigable path used for transformations.
@@ -21,9 +27,19 @@ pub enum PathSegment {
     /// - `$[items][-1]` → `Index(-1)` (negative indices supported)
     ///
     /// # Note
-    /// Only integer literals create Index segments. Float literals always
-    /// create Field segments (converted to strings).
+    /// Integer literals and whole-number floats (`$[items][1.0]`) create
+    /// Index segments. Other floats create Field segments (converted to
+    /// strings).
     Index(i64),
+
+    /// Every element of an array or value of an object
+    ///
+    /// # Examples
+    /// - `$[users][*][password]` → `[Field("users"), Wildcard, Field("password")]`
+    ///
+    /// # Note
+    /// Only delete targets may contain it; see [`Value::remove_matching`].
+    Wildcard,
 }
 
 /// A sequence of path segments representing a navigation path through a JSON document.
@@ -43,6 +59,408 @@ pub enum PathSegment {
 /// - `PathSegment::Field("price")`
 pub type Path = Vec<PathSegment>;
 
+impl fmt::Display for PathSegment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PathSegment::Field(name) => f.write_str(name),
+            PathSegment::Index(n) => write!(f, "{}", n),
+            PathSegment::Wildcard => f.write_str("*"),
+        }
+    }
+}
+
+/// Parse a dotted path such as `items.0.price` or `items.-1`.
+///
+/// Integer segments become indices and everything else a field, so fields
+/// named like integers or containing `.` need their segments built directly,
+/// or [`parse_key_path`] with its escapes.
+/// The empty string is the empty path.
+///
+/// # Examples
+///
+/// ```
+/// use clove_lang::transform::{parse_path, PathSegment};
+///
+/// assert_eq!(
+///     parse_path("items.-1.price"),
+///     vec![
+///         PathSegment::Field("items".into()),
+///         PathSegment::Index(-1),
+///         PathSegment::Field("price".into()),
+///     ]
+/// );
+/// ```
+pub fn parse_path(path: &str) -> Path {
+    if path.is_empty() {
+        return Vec::new();
+    }
+    path.split('.')
+        .map(|segment| {
+            segment
+                .parse()
+                .map_or_else(|_| PathSegment::Field(segment.to_string()), PathSegment::Index)
+        })
+        .collect()
+}
+
+/// Parse a path the way configuration files tend to write one, such as
+/// `items.0.price`, `items[-1].price`, `items.*.price` or `meta["build.id"]`.
+///
+/// Keys are separated by `.`. A key that is an integer selects an array
+/// element (negative counts from the end) and `*` every element. `\` makes
+/// the next character part of the key, so `a\.b` is the one key `a.b` and
+/// `\1` the key `1` rather than an index. Brackets hold an index, `*`, or a
+/// key quoted with `"` or `'`, in which `\` escapes the quote or itself.
+/// The empty string is the empty path.
+///
+/// # Examples
+///
+/// ```
+/// use clove_lang::transform::{parse_key_path, PathSegment};
+///
+/// assert_eq!(
+///     parse_key_path(r#"items[-1]["unit.price"]"#).unwrap(),
+///     vec![
+///         PathSegment::Field("items".into()),
+///         PathSegment::Index(-1),
+///         PathSegment::Field("unit.price".into()),
+///     ]
+/// );
+/// assert_eq!(parse_key_path(r"a\.b.*").unwrap(), parse_key_path("['a.b'][*]").unwrap());
+/// assert!(parse_key_path("items..price").is_err());
+/// ```
+pub fn parse_key_path(path: &str) -> Result<Path, EvalError> {
+    let invalid = |message: &str, offset: usize| {
+        EvalError::TypeError(format!("Invalid path \"{}\": {} (at offset {})", path, message, offset))
+    };
+    let chars: Vec<char> = path.chars().collect();
+    let mut segments = Vec::new();
+    if chars.is_empty() {
+        return Ok(segments);
+    }
+
+    let mut i = 0;
+    let mut bracket = chars[0] == '[';
+    loop {
+        if bracket {
+            let (segment, end) =
+                bracket_segment(&chars, i + 1).map_err(|(message, at)| invalid(message, at))?;
+            segments.push(segment);
+            i = end;
+        } else {
+            let start = i;
+            let mut key = String::new();
+            let mut escaped = false;
+            while let Some(&c) = chars.get(i) {
+                match c {
+                    '.' | '[' => break,
+                    '\\' => {
+                        let &next = chars.get(i + 1).ok_or_else(|| invalid("nothing to escape", i))?;
+                        key.push(next);
+                        escaped = true;
+                        i += 2;
+                    }
+                    c => {
+                        key.push(c);
+                        i += 1;
+                    }
+                }
+            }
+            if i == start {
+                return Err(invalid("expected a key", i));
+            }
+            segments.push(match key.parse() {
+                _ if escaped => PathSegment::Field(key),
+                Ok(index) => PathSegment::Index(index),
+                Err(_) if key == "*" => PathSegment::Wildcard,
+                Err(_) => PathSegment::Field(key),
+            });
+        }
+
+        match chars.get(i) {
+            None => return Ok(segments),
+            Some('.') => bracket = false,
+            Some('[') => {
+                bracket = true;
+                continue;
+            }
+            Some(_) => return Err(invalid("expected . or [", i)),
+        }
+        i += 1;
+    }
+}
+
+/// The segment in brackets from `start`, just after the `[`, and the offset
+/// after the `]`; or what is wrong and where
+fn bracket_segment(chars: &[char], start: usize) -> Result<(PathSegment, usize), (&'static str, usize)> {
+    let close = |i: usize| match chars.get(i) {
+        Some(']') => Ok(i + 1),
+        _ => Err(("expected ]", i)),
+    };
+    if let Some(&quote @ ('"' | '\'')) = chars.get(start) {
+        let mut key = String::new();
+        let mut i = start + 1;
+        loop {
+            match chars.get(i) {
+                None => return Err(("unterminated key", start)),
+                Some(&c) if c == quote => return Ok((PathSegment::Field(key), close(i + 1)?)),
+                Some('\\') => {
+                    let &next = chars.get(i + 1).ok_or(("unterminated key", start))?;
+                    key.push(next);
+                    i += 2;
+                }
+                Some(&c) => {
+                    key.push(c);
+                    i += 1;
+                }
+            }
+        }
+    }
+
+    let end = chars[start..].iter().position(|&c| c == ']').map_or(chars.len(), |n| start + n);
+    let text: String = chars[start..end].iter().collect();
+    let segment = match text.parse() {
+        Ok(index) => PathSegment::Index(index),
+        Err(_) if text == "*" => PathSegment::Wildcard,
+        Err(_) => return Err(("expected an index, * or a quoted key", start)),
+    };
+    Ok((segment, close(end)?))
+}
+
+/// The value at `path` as `.get_path()` sees it: `null` where a step is
+/// missing or does not fit, an integer step also selecting the object key
+/// it spells (like `$[a][0]`), and a wildcard collecting the rest of the
+/// path from every array element or object value (in key order)
+pub(crate) fn select_path(value: &Value, path: &[PathSegment]) -> Value {
+    let Some((segment, rest)) = path.split_first() else {
+        return value.clone();
+    };
+    let next = match (value, segment) {
+        (Value::Object(map), PathSegment::Field(key)) => map.get(key),
+        (Value::Object(map), PathSegment::Index(index)) => map.get(&index.to_string()),
+        (Value::Array(items), PathSegment::Index(index)) => {
+            resolve_index(*index, items.len()).map(|position| &items[position])
+        }
+        (Value::Array(items), PathSegment::Wildcard) => {
+            return Value::Array(items.iter().map(|item| select_path(item, rest)).collect());
+        }
+        (Value::Object(map), PathSegment::Wildcard) => {
+            let mut keys: Vec<&String> = map.keys().collect();
+            keys.sort();
+            return Value::Array(keys.into_iter().map(|key| select_path(&map[key], rest)).collect());
+        }
+        _ => None,
+    };
+    next.map_or(Value::Null, |next| select_path(next, rest))
+}
+
+/// The position of `index` in an array of `len` elements, counting negative
+/// indices from the end
+fn resolve_index(index: i64, len: usize) -> Option<usize> {
+    let position = if index >= 0 {
+        usize::try_from(index).ok()?
+    } else {
+        len.checked_sub(usize::try_from(index.unsigned_abs()).ok()?)?
+    };
+    (position < len).then_some(position)
+}
+
+fn mismatch(value: &Value, segment: &PathSegment) -> EvalError {
+    match segment {
+        PathSegment::Field(_) => EvalError::TypeError(format!(
+            "Cannot use field name '{}' on {}",
+            segment,
+            type_name(value)
+        )),
+        PathSegment::Index(_) => EvalError::TypeError(format!(
+            "Cannot use array index {} on {}",
+            segment,
+            type_name(value)
+        )),
+        PathSegment::Wildcard => {
+            EvalError::TypeError("[*] can only be used in a delete target".to_string())
+        }
+    }
+}
+
+/// Reading and editing values by path, the way transforms and deletions do.
+///
+/// Fields select object entries and indices select array elements, negative
+/// ones counting from the end; an index never selects an object entry.
+///
+/// # Examples
+///
+/// ```
+/// use clove_lang::transform::parse_path;
+/// use clove_lang::{clove_value, Value};
+///
+/// let mut doc = clove_value!({"items": [{"price": 5}, {"price": 7}]});
+///
+/// assert_eq!(doc.get_path(&parse_path("items.-1.price")), Some(&Value::Integer(7)));
+/// doc.set_path(&parse_path("items.0.price"), Value::Integer(6)).unwrap();
+/// doc.upsert_path(&parse_path("meta.source.name"), Value::from("shop")).unwrap();
+/// assert_eq!(doc.remove_path(&parse_path("items.1")), Some(clove_value!({"price": 7})));
+///
+/// assert_eq!(doc, clove_value!({"items": [{"price": 6}], "meta": {"source": {"name": "shop"}}}));
+/// ```
+impl Value {
+    /// The value at `path`, or `None` if a step is missing or does not fit
+    /// the value it is applied to
+    pub fn get_path(&self, path: &[PathSegment]) -> Option<&Value> {
+        path.iter().try_fold(self, |current, segment| match (current, segment) {
+            (Value::Object(map), PathSegment::Field(key)) => map.get(key),
+            (Value::Array(items), PathSegment::Index(index)) => {
+                items.get(resolve_index(*index, items.len())?)
+            }
+            _ => None,
+        })
+    }
+
+    /// Like [`get_path`](Self::get_path), for changing the value in place
+    pub fn get_path_mut(&mut self, path: &[PathSegment]) -> Option<&mut Value> {
+        path.iter().try_fold(self, |current, segment| match (current, segment) {
+            (Value::Object(map), PathSegment::Field(key)) => map.get_mut(key),
+            (Value::Array(items), PathSegment::Index(index)) => {
+                let position = resolve_index(*index, items.len())?;
+                items.get_mut(position)
+            }
+            _ => None,
+        })
+    }
+
+    /// Put `value` at `path`, returning what was there before.
+    ///
+    /// The last step may add a new field to an object, but every step before
+    /// it must exist, and indices must be within the array. The empty path
+    /// replaces the whole value.
+    pub fn set_path(&mut self, path: &[PathSegment], value: Value) -> Result<Option<Value>, EvalError> {
+        self.put_path(path, value, false)
+    }
+
+    /// Like [`set_path`](Self::set_path), but missing fields along the way
+    /// are created as empty objects
+    pub fn upsert_path(&mut self, path: &[PathSegment], value: Value) -> Result<Option<Value>, EvalError> {
+        self.put_path(path, value, true)
+    }
+
+    /// Remove and return the value at `path`; removing an array element
+    /// shifts the ones after it. Returns `None`, changing nothing, if there
+    /// is no such value or `path` is empty.
+    pub fn remove_path(&mut self, path: &[PathSegment]) -> Option<Value> {
+        let (last, parents) = path.split_last()?;
+        match (self.get_path_mut(parents)?, last) {
+            (Value::Object(map), PathSegment::Field(key)) => map.remove(key),
+            (Value::Array(items), PathSegment::Index(index)) => {
+                let position = resolve_index(*index, items.len())?;
+                Some(items.remove(position))
+            }
+            _ => None,
+        }
+    }
+
+    /// Remove every value `path` matches, where [`PathSegment::Wildcard`]
+    /// steps match each element of an array or value of an object, and
+    /// return how many were removed. Without wildcards this is
+    /// [`remove_path`](Self::remove_path).
+    ///
+    /// ```
+    /// use clove_lang::transform::PathSegment;
+    /// use clove_lang::clove_value;
+    ///
+    /// let mut users = clove_value!([{"name": "ann", "password": "x"}, {"name": "bob"}]);
+    /// assert_eq!(users.remove_matching(&[PathSegment::Wildcard, PathSegment::Field("password".into())]), 1);
+    /// assert_eq!(users, clove_value!([{"name": "ann"}, {"name": "bob"}]));
+    /// ```
+    pub fn remove_matching(&mut self, path: &[PathSegment]) -> usize {
+        match path {
+            [] => 0,
+            [PathSegment::Wildcard] => match self {
+                Value::Array(items) => std::mem::take(items).len(),
+                Value::Object(map) => std::mem::take(map).len(),
+                _ => 0,
+            },
+            [PathSegment::Wildcard, rest @ ..] => match self {
+                Value::Array(items) => items.iter_mut().map(|item| item.remove_matching(rest)).sum(),
+                Value::Object(map) => map.values_mut().map(|value| value.remove_matching(rest)).sum(),
+                _ => 0,
+            },
+            [segment] => usize::from(self.remove_path(std::slice::from_ref(segment)).is_some()),
+            [segment, rest @ ..] => self
+                .get_path_mut(std::slice::from_ref(segment))
+                .map_or(0, |child| child.remove_matching(rest)),
+        }
+    }
+
+    /// Replace every value `path` matches with `value`, matching like
+    /// [`remove_matching`](Self::remove_matching), and return how many were
+    /// replaced. Nothing is added where the path leads nowhere.
+    pub fn replace_matching(&mut self, path: &[PathSegment], value: &Value) -> usize {
+        self.update_matching(path, &mut |slot| *slot = value.clone())
+    }
+
+    /// Call `update` on every value `path` matches, matching like
+    /// [`remove_matching`](Self::remove_matching), and return how many
+    /// there were
+    pub fn update_matching(&mut self, path: &[PathSegment], update: &mut dyn FnMut(&mut Value)) -> usize {
+        match path {
+            [] => {
+                update(self);
+                1
+            }
+            [PathSegment::Wildcard, rest @ ..] => match self {
+                Value::Array(items) => items.iter_mut().map(|item| item.update_matching(rest, update)).sum(),
+                Value::Object(map) => map.values_mut().map(|child| child.update_matching(rest, update)).sum(),
+                _ => 0,
+            },
+            [segment, rest @ ..] => self
+                .get_path_mut(std::slice::from_ref(segment))
+                .map_or(0, |child| child.update_matching(rest, update)),
+        }
+    }
+
+    /// The value at `path`, or an error naming the first step that fails.
+    /// With `create`, missing fields are added as empty objects.
+    pub(crate) fn path_mut(&mut self, path: &[PathSegment], create: bool) -> Result<&mut Value, EvalError> {
+        path.iter().try_fold(self, |current, segment| match (current, segment) {
+            (Value::Object(map), PathSegment::Field(key)) => {
+                if create {
+                    Ok(map.entry(key.clone()).or_insert_with(|| Value::Object(HashMap::new())))
+                } else {
+                    map.get_mut(key)
+                        .ok_or_else(|| EvalError::AccessError(format!("Field '{}' not found", key)))
+                }
+            }
+            (Value::Array(items), PathSegment::Index(index)) => {
+                let len = items.len();
+                resolve_index(*index, len)
+                    .and_then(|position| items.get_mut(position))
+                    .ok_or_else(|| {
+                        EvalError::AccessError(format!(
+                            "Array index out of bounds: {} (length: {})",
+                            index, len
+                        ))
+                    })
+            }
+            (current, segment) => Err(mismatch(current, segment)),
+        })
+    }
+
+    fn put_path(&mut self, path: &[PathSegment], value: Value, create: bool) -> Result<Option<Value>, EvalError> {
+        let Some((last, parents)) = path.split_last() else {
+            return Ok(Some(std::mem::replace(self, value)));
+        };
+        match self.path_mut(parents, create)? {
+            Value::Object(map) if matches!(last, PathSegment::Field(_)) => {
+                Ok(map.insert(last.to_string(), value))
+            }
+            parent => {
+                let slot = parent.path_mut(std::slice::from_ref(last), false)?;
+                Ok(Some(std::mem::replace(slot, value)))
+            }
+        }
+    }
+}
+
 /// Extract a navigable path from an access expression
 ///
 /// # Examples
@@ -53,11 +471,36 @@ pub type Path = Vec<PathSegment>;
 /// ```
 pub fn extract_path(expr: &Expr) -> Result<Path, EvalError> {
     let mut segments = Vec::new();
-    extract_path_recursive(expr, &mut segments)?;
+    extract_path_recursive(expr, &mut segments, &mut None)?;
+    Ok(segments)
+}
+
+/// Evaluates a computed key of a transform target
+pub type KeyEvaluator<'a> = dyn FnMut(&Expr) -> Result<Value, EvalError> + 'a;
+
+/// Extract a path like [`extract_path`], also accepting the steps a
+/// transform or delete target may take beyond literal keys:
+///
+/// - computed keys, such as `$[items][$[index]]` or `$[totals][@[name]]`,
+///   whose values `eval_key` gives: a string is a field, an integer an index
+/// - `.first()` and `.last()`, the first and last element of an array
+///
+/// ```
+/// // $[items].first()[price] → [Field("items"), Index(0), Field("price")]
+/// // $[items].last() → [Field("items"), Index(-1)]
+/// // $[prices][$[currency]] with $[currency] = "eur" → [Field("prices"), Field("eur")]
+/// ```
+pub fn resolve_path(expr: &Expr, eval_key: &mut KeyEvaluator<'_>) -> Result<Path, EvalError> {
+    let mut segments = Vec::new();
+    extract_path_recursive(expr, &mut segments, &mut Some(eval_key))?;
     Ok(segments)
 }
 
-fn extract_path_recursive(expr: &Expr, segments: &mut Path) -> Result<(), EvalError> {
+fn extract_path_recursive(
+    expr: &Expr,
+    segments: &mut Path,
+    eval_key: &mut Option<&mut KeyEvaluator<'_>>,
+) -> Result<(), EvalError> {
     match expr {
         Expr::Root => {
             // Root ($) is the starting point, adds no segment
@@ -66,7 +509,7 @@ fn extract_path_recursive(expr: &Expr, segments: &mut Path) -> Result<(), EvalEr
 
         Expr::Access { object, key } => {
             // First, extract path from the object (left to right traversal)
-            extract_path_recursive(object, segments)?;
+            extract_path_recursive(object, segments, eval_key)?;
 
             // Then add this key as a segment
             match key.as_ref() {
@@ -75,16 +518,21 @@ fn extract_path_recursive(expr: &Expr, segments: &mut Path) -> Result<(), EvalEr
                     Ok(())
                 }
 
-                Expr::Float(n) => {
-
+                Expr::Wildcard => {
+                    segments.push(PathSegment::Wildcard);
+                    Ok(())
+                }
 
-                    segments.push(PathSegment::Field(n.to_string()));
+                Expr::Float(n) => {
+                    // A whole-number float indexes like the integer it equals
+                    segments.push(match whole_number(*n) {
+                        Some(n) => PathSegment::Index(n),
+                        None => PathSegment::Field(n.to_string()),
+                    });
                     Ok(())
                 }
 
                 Expr::Integer(n) => {
-
-
                     segments.push(PathSegment::Index(*n));
                     Ok(())
                 }
@@ -95,13 +543,40 @@ fn extract_path_recursive(expr: &Expr, segments: &mut Path) -> Result<(), EvalEr
                     Ok(())
                 }
 
-                // Any other expression in key position is invalid for transforms
-                _ => Err(EvalError::TypeError(
-                    "Transform target cannot contain computed keys. Use literal field names or indices only.".to_string(),
-                )),
+                // A computed key, resolved before the write when possible
+                computed => match eval_key {
+                    Some(eval_key) => {
+                        let segment = match eval_key(computed)? {
+                            Value::String(name) => PathSegment::Field(name),
+                            Value::Integer(n) => PathSegment::Index(n),
+                            Value::Float(n) if whole_number(n).is_some() => PathSegment::Index(n as i64),
+                            other => {
+                                return Err(EvalError::TypeError(format!(
+                                    "Transform target key after ${} must be a string or integer, got {} {}",
+                                    segments.iter().map(|segment| format!("[{}]", segment)).collect::<String>(),
+                                    type_name(&other),
+                                    to_json(&other)
+                                )))
+                            }
+                        };
+                        segments.push(segment);
+                        Ok(())
+                    }
+                    None => Err(EvalError::TypeError(
+                        "Transform target cannot contain computed keys. Use literal field names or indices only.".to_string(),
+                    )),
+                },
             }
         }
 
+        Expr::MethodCall { object, method, args }
+            if eval_key.is_some() && args.is_empty() && matches!(method.as_str(), "first" | "last") =>
+        {
+            extract_path_recursive(object, segments, eval_key)?;
+            segments.push(PathSegment::Index(if method == "first" { 0 } else { -1 }));
+            Ok(())
+        }
+
         Expr::ScopeRef(name) => {
             // Scope references evaluate to values, not paths
             // We can't transform through them
@@ -113,7 +588,7 @@ fn extract_path_recursive(expr: &Expr, segments: &mut Path) -> Result<(), EvalEr
 
         // Any other expression type is invalid as a transform target
         _ => Err(EvalError::TypeError(
-            "Invalid transform target. Target must be an access path like $[field] or $[items][0][name]".to_string(),
+            "Invalid transform target. Target must be an access path like $[field], $[items][0][name], $[items].first()[name] or $[items][$[index]]".to_string(),
         )),
     }
 }
@@ -192,8 +667,8 @@ pub fn determine_transform_type(value_expr: &Expr) -> TransformType {
 /// Recursively walks the expression tree looking for `Expr::LambdaParam`
 pub fn uses_lambda_param(expr: &Expr) -> bool {
     match expr {
-        // Direct lambda parameter
-        Expr::LambdaParam => true,
+        // Direct lambda parameter, or a lambda naming it
+        Expr::LambdaParam | Expr::Lambda { .. } => true,
 
         // Access might contain @ in object or key
         Expr::Access { object, key } => uses_lambda_param(object) || uses_lambda_param(key),
@@ -220,8 +695,19 @@ pub fn uses_lambda_param(expr: &Expr) -> bool {
             uses_lambda_param(object) || args.iter().any(uses_lambda_param)
         }
 
-        // UDF calls check all arguments
-        Expr::UDFCall { args, .. } => args.iter().any(uses_lambda_param),
+        // UDF and host function calls check all arguments
+        Expr::UDFCall { args, .. } | Expr::HostCall { args, .. } => args.iter().any(uses_lambda_param),
+
+        // Try expressions check the body and fallback
+        Expr::Try { body, fallback } => uses_lambda_param(body) || uses_lambda_param(fallback),
+
+        // Match expressions check the subject, guards and bodies
+        Expr::Match { subject, arms } => {
+            uses_lambda_param(subject)
+                || arms.iter().any(|arm| {
+                    arm.guard.as_ref().is_some_and(uses_lambda_param) || uses_lambda_param(&arm.body)
+                })
+        }
 
         // These never contain lambda params
         Expr::Null
@@ -229,10 +715,16 @@ pub fn uses_lambda_param(expr: &Expr) -> bool {
         | Expr::Float(_)
         | Expr::String(_)
         | Expr::Root
+        | Expr::OriginalRoot
         | Expr::EnvVar(_)
+        | Expr::TypedEnvVar { .. }
+        | Expr::Meta
         | Expr::ScopeRef(_)
         | Expr::ArgRef(_)
+        | Expr::OuterLambdaParam(_)
+        | Expr::LambdaVar(_)
         | Expr::Integer(_)
-        | Expr::Key(_) => false,
+        | Expr::Key(_)
+        | Expr::Wildcard => false,
     }
 }
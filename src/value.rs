@@ -1,5 +1,12 @@
+use std::cmp::Ordering;
 use std::collections::HashMap;
 
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+
+/// Relative tolerance of [`Value::approx_eq`] (the `~=` operator)
+pub const APPROX_EPSILON: f64 = 1e-9;
+
 /// A JSON value used throughout the Clove query language.
 ///
 /// This type represents all valid JSON types with a distinction between
@@ -33,6 +40,7 @@ use std::collections::HashMap;
 /// let object = Value::Object(obj);
 /// ```
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub enum Value {
     /// JSON null
     Null,
@@ -49,6 +57,11 @@ pub enum Value {
     /// UTF-8 string
     String(String),
 
+    /// Binary data, from `.base64_decode()`, `.hex_decode()` or a binary
+    /// input format. JSON has no bytes type, so JSON output writes them as
+    /// a base64 string.
+    Bytes(Vec<u8>),
+
     /// Array of values (homogeneous or heterogeneous)
     Array(Vec<Value>),
 
@@ -57,6 +70,36 @@ pub enum Value {
 }
 
 impl Value {
+    /// Build an object from key/value pairs. Later duplicates of a key win.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use clove_lang::Value;
+    ///
+    /// let user = Value::object([("name", Value::from("Alice")), ("age", Value::from(30))]);
+    /// ```
+    pub fn object<K, V>(entries: impl IntoIterator<Item = (K, V)>) -> Value
+    where
+        K: Into<String>,
+        V: Into<Value>,
+    {
+        Value::Object(entries.into_iter().map(|(k, v)| (k.into(), v.into())).collect())
+    }
+
+    /// Build an array from anything convertible to values
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use clove_lang::Value;
+    ///
+    /// assert_eq!(Value::array([1, 2]), Value::Array(vec![Value::Integer(1), Value::Integer(2)]));
+    /// ```
+    pub fn array<V: Into<Value>>(items: impl IntoIterator<Item = V>) -> Value {
+        Value::Array(items.into_iter().map(Into::into).collect())
+    }
+
     /// Check if the value is truthy (for conditions)
     pub fn is_truthy(&self) -> bool {
         use Value::*;
@@ -66,6 +109,7 @@ impl Value {
             Float(n) => *n > 0.0,
             Integer(n) => *n > 0,
             String(s) => !s.is_empty(),
+            Bytes(b) => !b.is_empty(),
             Array(arr) => !arr.is_empty(),
             Object(obj) => !obj.is_empty(),
         }
@@ -105,7 +149,392 @@ impl Value {
             Value::Integer(n) => n.to_string(),
             Value::Boolean(b) => b.to_string(),
             Value::Null => "null".to_string(),
+            Value::Bytes(b) => encode_base64(b),
             _ => format!("{:?}", self),
         }
     }
+
+    /// Equality as the `==` operator sees it.
+    ///
+    /// Like `PartialEq`, except that integers and floats compare by numeric
+    /// value, also inside arrays and objects. An integer equals a float only
+    /// when the float is exactly that whole number.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use clove_lang::Value;
+    ///
+    /// assert!(Value::Integer(1).equals(&Value::Float(1.0)));
+    /// assert!(!Value::Integer(1).equals(&Value::Float(1.5)));
+    /// assert!(Value::array([1, 2]).equals(&Value::array([1.0, 2.0])));
+    /// assert_ne!(Value::Integer(1), Value::Float(1.0));
+    /// ```
+    pub fn equals(&self, other: &Value) -> bool {
+        match (self, other) {
+            (Value::Integer(a), Value::Float(b)) | (Value::Float(b), Value::Integer(a)) => {
+                int_equals_float(*a, *b)
+            }
+            (Value::Array(a), Value::Array(b)) => {
+                a.len() == b.len() && a.iter().zip(b).all(|(x, y)| x.equals(y))
+            }
+            (Value::Object(a), Value::Object(b)) => {
+                a.len() == b.len()
+                    && a.iter().all(|(key, x)| b.get(key).is_some_and(|y| x.equals(y)))
+            }
+            _ => self == other,
+        }
+    }
+
+    /// Approximate equality, as the `~=` operator sees it.
+    ///
+    /// Numbers match when they differ by at most [`APPROX_EPSILON`] relative
+    /// to the larger magnitude (absolute below 1), strings match ignoring
+    /// case, and arrays and objects match element by element. Anything
+    /// else compares as [`equals`](Self::equals).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use clove_lang::Value;
+    ///
+    /// assert!(Value::Float(0.1 + 0.2).approx_eq(&Value::Float(0.3)));
+    /// assert!(!Value::Float(0.1).approx_eq(&Value::Float(0.11)));
+    /// assert!(Value::from("Ready").approx_eq(&Value::from("READY")));
+    /// ```
+    pub fn approx_eq(&self, other: &Value) -> bool {
+        match (self, other) {
+            (Value::Integer(_) | Value::Float(_), Value::Integer(_) | Value::Float(_)) => {
+                if self.equals(other) {
+                    return true;
+                }
+                let (a, b) = (self.as_float().unwrap(), other.as_float().unwrap());
+                (a - b).abs() <= APPROX_EPSILON * a.abs().max(b.abs()).max(1.0)
+            }
+            (Value::String(a), Value::String(b)) => {
+                a == b || a.to_lowercase() == b.to_lowercase()
+            }
+            (Value::Array(a), Value::Array(b)) => {
+                a.len() == b.len() && a.iter().zip(b).all(|(x, y)| x.approx_eq(y))
+            }
+            (Value::Object(a), Value::Object(b)) => {
+                a.len() == b.len()
+                    && a.iter().all(|(key, x)| b.get(key).is_some_and(|y| x.approx_eq(y)))
+            }
+            _ => self.equals(other),
+        }
+    }
+
+    /// Position of this value's type in the cross-type ordering
+    pub(crate) fn type_rank(&self) -> u8 {
+        match self {
+            Value::Null => 0,
+            Value::Boolean(_) => 1,
+            Value::Integer(_) | Value::Float(_) => 2,
+            Value::String(_) => 3,
+            Value::Bytes(_) => 4,
+            Value::Array(_) => 5,
+            Value::Object(_) => 6,
+        }
+    }
+
+    /// Total ordering over all values, used for sorting and min/max.
+    ///
+    /// Values of different types order by type:
+    /// `null < boolean < number < string < bytes < array < object`.
+    /// Within a type:
+    /// - numbers compare numerically (integers and floats interleave; NaN sorts last)
+    /// - strings compare lexicographically by code point
+    /// - bytes compare lexicographically
+    /// - arrays compare element by element, then by length
+    /// - objects compare their entries in key order (key first, then value),
+    ///   then by number of entries
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use clove_lang::Value;
+    /// use std::cmp::Ordering;
+    ///
+    /// assert_eq!(Value::Null.total_cmp(&Value::Boolean(false)), Ordering::Less);
+    /// assert_eq!(Value::Integer(2).total_cmp(&Value::Float(1.5)), Ordering::Greater);
+    /// assert_eq!(Value::Integer(9).total_cmp(&Value::String("1".into())), Ordering::Less);
+    /// ```
+    pub fn total_cmp(&self, other: &Value) -> Ordering {
+        match (self, other) {
+            (Value::Boolean(a), Value::Boolean(b)) => a.cmp(b),
+            (Value::Integer(a), Value::Integer(b)) => a.cmp(b),
+            (Value::Float(a), Value::Float(b)) => cmp_f64(*a, *b),
+            (Value::Integer(a), Value::Float(b)) => cmp_int_f64(*a, *b),
+            (Value::Float(a), Value::Integer(b)) => cmp_int_f64(*b, *a).reverse(),
+            (Value::String(a), Value::String(b)) => a.cmp(b),
+            (Value::Bytes(a), Value::Bytes(b)) => a.cmp(b),
+            (Value::Array(a), Value::Array(b)) => a
+                .iter()
+                .zip(b)
+                .map(|(x, y)| x.total_cmp(y))
+                .find(|o| *o != Ordering::Equal)
+                .unwrap_or_else(|| a.len().cmp(&b.len())),
+            (Value::Object(a), Value::Object(b)) => {
+                let mut a_entries: Vec<_> = a.iter().collect();
+                let mut b_entries: Vec<_> = b.iter().collect();
+                a_entries.sort_by(|x, y| x.0.cmp(y.0));
+                b_entries.sort_by(|x, y| x.0.cmp(y.0));
+                a_entries
+                    .iter()
+                    .zip(&b_entries)
+                    .map(|((ka, va), (kb, vb))| ka.cmp(kb).then_with(|| va.total_cmp(vb)))
+                    .find(|o| *o != Ordering::Equal)
+                    .unwrap_or_else(|| a.len().cmp(&b.len()))
+            }
+            _ => self.type_rank().cmp(&other.type_rank()),
+        }
+    }
+
+    /// Approximate number of bytes this value occupies in memory, counting
+    /// strings, keys and nested values but not allocator overhead or spare
+    /// capacity.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use clove_lang::{clove_value, Value};
+    ///
+    /// let small = clove_value!({"a": 1});
+    /// let large = clove_value!({"a": 1, "text": "a longer string than the others"});
+    /// assert!(Value::Null.approx_size() < small.approx_size());
+    /// assert!(small.approx_size() < large.approx_size());
+    /// ```
+    pub fn approx_size(&self) -> usize {
+        let nested = match self {
+            Value::String(s) => s.len(),
+            Value::Bytes(b) => b.len(),
+            Value::Array(arr) => arr.iter().map(Value::approx_size).sum(),
+            Value::Object(obj) => obj
+                .iter()
+                .map(|(k, v)| std::mem::size_of::<String>() + k.len() + v.approx_size())
+                .sum(),
+            _ => 0,
+        };
+        std::mem::size_of::<Value>() + nested
+    }
+}
+
+/// Hashable normal form of a [`Value`].
+///
+/// Two values have equal keys exactly when they are [`equals`](Value::equals),
+/// so the key can stand in for the value in hash sets and maps (e.g. for
+/// `.unique()`). Whole-number floats are keyed as the integer they equal,
+/// other floats by their bit pattern; NaN keys compare equal to each other.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub(crate) enum ValueKey {
+    Null,
+    Boolean(bool),
+    Integer(i64),
+    Float(u64),
+    String(String),
+    Bytes(Vec<u8>),
+    Array(Vec<ValueKey>),
+    /// Entries sorted by key
+    Object(Vec<(String, ValueKey)>),
+}
+
+impl Value {
+    /// Build the hashable normal form of this value
+    pub(crate) fn hash_key(&self) -> ValueKey {
+        match self {
+            Value::Null => ValueKey::Null,
+            Value::Boolean(b) => ValueKey::Boolean(*b),
+            Value::Integer(n) => ValueKey::Integer(*n),
+            Value::Float(n) => match whole_number(*n) {
+                Some(n) => ValueKey::Integer(n),
+                None => ValueKey::Float(n.to_bits()),
+            },
+            Value::String(s) => ValueKey::String(s.clone()),
+            Value::Bytes(b) => ValueKey::Bytes(b.clone()),
+            Value::Array(arr) => ValueKey::Array(arr.iter().map(Value::hash_key).collect()),
+            Value::Object(obj) => {
+                let mut entries: Vec<_> = obj.iter().map(|(k, v)| (k.clone(), v.hash_key())).collect();
+                entries.sort_by(|a, b| a.0.cmp(&b.0));
+                ValueKey::Object(entries)
+            }
+        }
+    }
+}
+
+impl From<bool> for Value {
+    fn from(b: bool) -> Self {
+        Value::Boolean(b)
+    }
+}
+
+impl From<i32> for Value {
+    fn from(n: i32) -> Self {
+        Value::Integer(n.into())
+    }
+}
+
+impl From<u32> for Value {
+    fn from(n: u32) -> Self {
+        Value::Integer(n.into())
+    }
+}
+
+impl From<i64> for Value {
+    fn from(n: i64) -> Self {
+        Value::Integer(n)
+    }
+}
+
+impl From<f64> for Value {
+    fn from(n: f64) -> Self {
+        Value::Float(n)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(s: &str) -> Self {
+        Value::String(s.to_string())
+    }
+}
+
+impl From<String> for Value {
+    fn from(s: String) -> Self {
+        Value::String(s)
+    }
+}
+
+/// `None` becomes `null`
+impl<T: Into<Value>> From<Option<T>> for Value {
+    fn from(value: Option<T>) -> Self {
+        value.map_or(Value::Null, Into::into)
+    }
+}
+
+impl<T: Into<Value>> From<Vec<T>> for Value {
+    fn from(items: Vec<T>) -> Self {
+        Value::array(items)
+    }
+}
+
+impl From<HashMap<String, Value>> for Value {
+    fn from(map: HashMap<String, Value>) -> Self {
+        Value::Object(map)
+    }
+}
+
+/// Build a [`Value`] from JSON-like syntax.
+///
+/// `null`, arrays and objects are written as in JSON; anything else is a Rust
+/// expression converted with `Value::from`. Object keys are string literals,
+/// or any `Into<String>` expression in parentheses.
+///
+/// # Examples
+///
+/// ```
+/// use clove_lang::{clove_value, Value};
+///
+/// let threshold = 10;
+/// let doc = clove_value!({
+///     "items": [{"price": 5}, {"price": threshold * 2}],
+///     "owner": null,
+///     ("tag".to_string() + "s"): ["a", "b"],
+/// });
+/// assert_eq!(doc, Value::object([
+///     ("items", Value::array([
+///         Value::object([("price", 5)]),
+///         Value::object([("price", 20)]),
+///     ])),
+///     ("owner", Value::Null),
+///     ("tags", Value::array(["a", "b"])),
+/// ]));
+/// ```
+#[macro_export]
+macro_rules! clove_value {
+    // Array elements: collect tokens up to each top-level comma
+    (@array [$($done:expr,)*] []) => {
+        vec![$($done,)*]
+    };
+    (@array [$($done:expr,)*] [$($element:tt)+]) => {
+        vec![$($done,)* $crate::clove_value!($($element)+)]
+    };
+    (@array [$($done:expr,)*] [$($element:tt)+] , $($rest:tt)*) => {
+        $crate::clove_value!(@array [$($done,)* $crate::clove_value!($($element)+),] [] $($rest)*)
+    };
+    (@array [$($done:expr,)*] [$($element:tt)*] $next:tt $($rest:tt)*) => {
+        $crate::clove_value!(@array [$($done,)*] [$($element)* $next] $($rest)*)
+    };
+
+    // Object entries: a key, a colon, then tokens up to the next top-level comma
+    (@object $object:ident) => {};
+    (@object $object:ident $key:tt : $($rest:tt)*) => {
+        $crate::clove_value!(@entry $object [$key] [] $($rest)*)
+    };
+    (@entry $object:ident [$key:tt] [$($value:tt)+]) => {
+        $object.insert(::std::string::String::from($key), $crate::clove_value!($($value)+));
+    };
+    (@entry $object:ident [$key:tt] [$($value:tt)+] , $($rest:tt)*) => {
+        $object.insert(::std::string::String::from($key), $crate::clove_value!($($value)+));
+        $crate::clove_value!(@object $object $($rest)*);
+    };
+    (@entry $object:ident [$key:tt] [$($value:tt)*] $next:tt $($rest:tt)*) => {
+        $crate::clove_value!(@entry $object [$key] [$($value)* $next] $($rest)*)
+    };
+
+    (null) => {
+        $crate::Value::Null
+    };
+    ([ $($elements:tt)* ]) => {
+        $crate::Value::Array($crate::clove_value!(@array [] [] $($elements)*))
+    };
+    ({ $($entries:tt)* }) => {
+        $crate::Value::Object({
+            #[allow(unused_mut)]
+            let mut object = ::std::collections::HashMap::new();
+            $crate::clove_value!(@object object $($entries)*);
+            object
+        })
+    };
+    ($other:expr) => {
+        $crate::Value::from($other)
+    };
+}
+
+/// The standard, padded base64 text of `bytes`, which is how JSON output
+/// writes [`Value::Bytes`]
+pub(crate) fn encode_base64(bytes: &[u8]) -> String {
+    STANDARD.encode(bytes)
+}
+
+/// Whether `a` is exactly the whole number `b`, without rounding `a`
+fn int_equals_float(a: i64, b: f64) -> bool {
+    whole_number(b) == Some(a)
+}
+
+/// The integer equal to `n`, if it is a whole number within the range of `i64`
+pub(crate) fn whole_number(n: f64) -> Option<i64> {
+    // i64::MIN is exactly representable; i64::MAX + 1 is the first float past the range
+    (n.fract() == 0.0 && n >= i64::MIN as f64 && n < -(i64::MIN as f64)).then_some(n as i64)
+}
+
+/// Compare floats numerically, placing NaN after every other number
+fn cmp_f64(a: f64, b: f64) -> Ordering {
+    a.partial_cmp(&b)
+        .unwrap_or_else(|| a.is_nan().cmp(&b.is_nan()))
+}
+
+/// Compare an integer with a float exactly, like [`cmp_f64`] but without
+/// rounding the integer to a float, which above 2^53 would make the order
+/// intransitive
+fn cmp_int_f64(a: i64, b: f64) -> Ordering {
+    // NaN, the infinities and floats past the range of i64
+    if b.is_nan() || b >= -(i64::MIN as f64) {
+        return Ordering::Less;
+    }
+    if b < i64::MIN as f64 {
+        return Ordering::Greater;
+    }
+    // Within the range, the float's integer part converts exactly
+    a.cmp(&(b.trunc() as i64))
+        .then_with(|| 0.0.partial_cmp(&b.fract()).unwrap_or(Ordering::Equal))
 }
@@ -0,0 +1,105 @@
+#![cfg(all(feature = "msgpack", feature = "cbor"))]
+
+use clove_lang::binary::{
+    DataFormat, FormatError, value_from_cbor, value_from_msgpack, value_to_cbor, value_to_msgpack,
+};
+use clove_lang::cli::{CheckOptions, CheckResult, execute_check_reader};
+use clove_lang::{Value, clove_value};
+
+fn sample() -> Value {
+    clove_value!({
+        "id": 7,
+        "price": 2.5,
+        "name": "widget",
+        "tags": ["a", "b"],
+        "meta": {"deleted": false, "owner": null, "big": -9007199254740993i64}
+    })
+}
+
+#[test]
+fn test_round_trip() {
+    let mut doc = sample();
+    if let Value::Object(map) = &mut doc {
+        map.insert("payload".to_string(), Value::Bytes(vec![0, 159, 146, 150]));
+    }
+    assert_eq!(value_from_msgpack(&value_to_msgpack(&doc)).unwrap(), doc);
+    assert_eq!(value_from_cbor(&value_to_cbor(&doc)).unwrap(), doc);
+
+    // Keys are written in order, so equal values encode identically
+    assert_eq!(value_to_msgpack(&doc), value_to_msgpack(&doc.clone()));
+    assert_eq!(value_to_cbor(&doc), value_to_cbor(&doc.clone()));
+}
+
+#[test]
+fn test_msgpack_coercions() {
+    // {"blob": bin8 "abc", 1: uint64 max}
+    let bytes = [
+        0x82, 0xa4, b'b', b'l', b'o', b'b', 0xc4, 0x03, b'a', b'b', b'c', 0x01, 0xcf, 0xff, 0xff,
+        0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+    ];
+    assert_eq!(
+        value_from_msgpack(&bytes).unwrap(),
+        Value::object([
+            ("blob", Value::Bytes(b"abc".to_vec())),
+            ("1", Value::Float(18446744073709551615.0)),
+        ])
+    );
+
+    // Extension types and array keys have no Value form
+    assert!(value_from_msgpack(&[0xd4, 0x01, 0x00]).is_err());
+    assert!(value_from_msgpack(&[0x81, 0x90, 0xc0]).is_err());
+}
+
+#[test]
+fn test_cbor_coercions() {
+    // {"blob": h'616263', "at": 1(1700000000)}
+    let bytes = [
+        0xa2, 0x64, b'b', b'l', b'o', b'b', 0x43, b'a', b'b', b'c', 0x62, b'a', b't', 0xc1, 0x1a,
+        0x65, 0x53, 0xf1, 0x00,
+    ];
+    assert_eq!(
+        value_from_cbor(&bytes).unwrap(),
+        Value::object([("blob", Value::Bytes(b"abc".to_vec())), ("at", Value::Integer(1700000000))])
+    );
+}
+
+#[test]
+fn test_decode_errors() {
+    let bytes = value_to_msgpack(&Value::Integer(1));
+    let twice = [bytes.clone(), bytes].concat();
+    assert!(matches!(
+        value_from_msgpack(&twice),
+        Err(FormatError::Decode { format: DataFormat::Msgpack, .. })
+    ));
+    assert_eq!(DataFormat::Msgpack.decode_all(&twice).unwrap(), [Value::Integer(1), Value::Integer(1)]);
+
+    assert!(value_from_cbor(&[]).is_err());
+    assert!(value_from_msgpack(&[0x92, 0x01]).is_err());
+    assert_eq!(DataFormat::Json.decode(b"1"), Err(FormatError::NotBinary));
+}
+
+#[test]
+fn test_check_binary_input() {
+    let options = CheckOptions {
+        query: "$ | !($[tags].length())".to_string(),
+        input_format: DataFormat::Cbor,
+        ..Default::default()
+    };
+    let bytes = value_to_cbor(&sample());
+    assert!(matches!(
+        execute_check_reader(&options, bytes.as_slice()),
+        Ok(CheckResult::Success { value: v, .. }) if v == clove_value!(2)
+    ));
+
+    let options = CheckOptions {
+        query: "$.map(@[id])".to_string(),
+        input_format: DataFormat::Msgpack,
+        slurp: true,
+        ..Default::default()
+    };
+    let bytes = [value_to_msgpack(&sample()), value_to_msgpack(&clove_value!({"id": 8}))].concat();
+    assert!(matches!(
+        execute_check_reader(&options, bytes.as_slice()),
+        Ok(CheckResult::Success { value: v, .. }) if v == clove_value!([7, 8])
+    ));
+}
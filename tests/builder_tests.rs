@@ -0,0 +1,79 @@
+use clove_lang::ast::builder::{quote_key, quote_string};
+use clove_lang::{clove_value, BinOp, Evaluator, Expr, Lexer, Parser, Query, QueryBuilder, Statement, Token};
+
+fn parse_query(query: &str) -> Query {
+    Parser::new(Lexer::new(query)).unwrap().parse_query().unwrap()
+}
+
+#[test]
+fn test_builder_matches_parsed_query() {
+    let built = QueryBuilder::new()
+        .scope("limit", Expr::root().get("max").coalesce(100))
+        .filter(Expr::root().get("items").exists().and(Expr::root().get("active").equals(true)))
+        .transform(Expr::root().get("items"), Expr::filter(Expr::item().get("price").lt(Expr::scope("limit"))))
+        .transform(Expr::root().get("items").index(-1).get("tag"), Expr::item().get("name") + "!")
+        .delete(Expr::root().get("secret"))
+        .output(Expr::object([
+            ("total", Expr::root().get("items").method("sum", [Expr::item().get("price") * 2])),
+            ("first", Expr::root().get("items").index(0)),
+        ]))
+        .build();
+
+    let parsed = parse_query(
+        r#"$ | @limit := $[max] ?? 100 | ?($[items][?] and $[active] == true)
+             | ~($[items] := ?(@[price] < @limit))
+             | ~($[items][-1][tag] := @[name] + "!")
+             | -($[secret])
+             | !({"total": $[items].sum(@[price] * 2), "first": $[items][0]})"#,
+    );
+    assert_eq!(built, parsed);
+}
+
+#[test]
+fn test_builder_values_are_never_syntax() {
+    let hostile = r#"x"] | !($[secret]) | ?(true"#;
+    let query = QueryBuilder::new()
+        .output(Expr::root().get(hostile).coalesce(Expr::array([hostile.into(), Expr::from(clove_value!({"k": [1, null]}))])))
+        .build();
+
+    let doc = clove_value!({"secret": "s3cret"});
+    assert_eq!(
+        Evaluator::new().eval_query(&query, doc).unwrap(),
+        clove_value!([r#"x"] | !($[secret]) | ?(true"#, {"k": [1, null]}])
+    );
+}
+
+#[test]
+fn test_builder_udfs() {
+    let query = QueryBuilder::new()
+        .udf("double", 1, Statement::Access(Expr::arg(1) * 2))
+        .output(Expr::call("double", [Expr::root().get("n")]).binary(BinOp::Subtract, 1))
+        .build();
+    assert_eq!(query, parse_query("&double:1 := @1 * 2\n$ | !(&double[$[n]] - 1)"));
+    assert_eq!(Evaluator::new().eval_query(&query, clove_value!({"n": -3})).unwrap(), clove_value!(-7));
+}
+
+#[test]
+fn test_quote_string_round_trips() {
+    for s in ["", "plain", "say \"hi\"", "back\\slash", "it's", "line\nbreak\r\ttab", "\u{1}\u{7f}", "ünï 😀"] {
+        let quoted = quote_string(s);
+        let mut lexer = Lexer::new(&quoted);
+        assert_eq!(lexer.next_token().unwrap(), Token::String(s.to_string()), "{quoted}");
+        assert_eq!(lexer.next_token().unwrap(), Token::Eof);
+    }
+}
+
+#[test]
+fn test_quote_key_round_trips() {
+    let doc = clove_value!({"price": 1, "unit price": 2, "null": 3, "a\"]b": 4, "héllo": 5, "0": 6, "and": 7});
+    for (key, expected) in [("price", 1), ("unit price", 2), ("null", 3), ("a\"]b", 4), ("héllo", 5), ("0", 6), ("and", 7)] {
+        let query = format!("$ | !(${})", quote_key(key));
+        assert_eq!(
+            Evaluator::new().eval_query(&parse_query(&query), doc.clone()).unwrap(),
+            clove_value!(expected),
+            "{query}"
+        );
+    }
+    assert_eq!(quote_key("price"), "[price]");
+    assert_eq!(quote_key("unit price"), "[\"unit price\"]");
+}
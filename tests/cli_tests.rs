@@ -0,0 +1,802 @@
+use clove_lang::cli::{
+    backup_path, execute_assert, execute_bench, BenchOptions, execute_check, execute_check_ndjson, execute_check_reader, execute_schema_infer, parse_byte_size, parse_select,
+    get_doc_category, write_in_place, AtomicFile, execute_test, CheckOptions, CheckResult, CliError, DocCategory, EXIT_FAILED,
+    EXIT_PASSED, TestOptions, TestStatus, execute_redact, RedactRule, RedactStrategy, decompress, open_input,
+    Compression, OutputStream, fetch, parse_header, FetchRequest, execute_query_hash,
+};
+use clove_lang::{clove_value, DuplicateKeys};
+use std::fs;
+use std::io::Read;
+use std::path::PathBuf;
+
+fn scratch_file(name: &str, contents: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("clove-cli-tests-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join(name);
+    fs::write(&path, contents).unwrap();
+    path
+}
+
+#[test]
+fn test_write_in_place_replaces_contents() {
+    let path = scratch_file("replace.json", r#"{"version": "1.0"}"#);
+
+    write_in_place(&path, "{\"version\":\"2.0\"}\n", false).unwrap();
+
+    assert_eq!(fs::read_to_string(&path).unwrap(), "{\"version\":\"2.0\"}\n");
+    assert!(!backup_path(&path).exists());
+    let leftovers: Vec<_> = fs::read_dir(path.parent().unwrap())
+        .unwrap()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_name().to_string_lossy().ends_with(".tmp"))
+        .collect();
+    assert!(leftovers.is_empty());
+}
+
+#[test]
+fn test_write_in_place_with_backup() {
+    let path = scratch_file("backup.json", r#"{"version": "1.0"}"#);
+
+    write_in_place(&path, "{}\n", true).unwrap();
+
+    assert_eq!(fs::read_to_string(&path).unwrap(), "{}\n");
+    assert_eq!(backup_path(&path).file_name().unwrap(), "backup.json.bak");
+    assert_eq!(
+        fs::read_to_string(backup_path(&path)).unwrap(),
+        r#"{"version": "1.0"}"#
+    );
+}
+
+#[test]
+fn test_write_in_place_missing_file() {
+    let path = std::env::temp_dir().join("clove-cli-tests-does-not-exist.json");
+    assert!(write_in_place(&path, "{}", false).is_err());
+    assert!(!path.exists());
+}
+
+/// A directory of its own, so no other test sees its temporary files
+fn output_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("clove-cli-{}-{}", name, std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn test_atomic_file() {
+    use std::io::Write;
+
+    let path = output_dir("atomic-file").join("out.json");
+    fs::write(&path, "old").unwrap();
+
+    // Dropped without committing, the file keeps its contents
+    let mut file = AtomicFile::create(&path).unwrap();
+    file.write_all(b"partial").unwrap();
+    drop(file);
+    assert_eq!(fs::read_to_string(&path).unwrap(), "old");
+    assert_eq!(fs::read_dir(path.parent().unwrap()).unwrap().count(), 1);
+
+    // Each writer gets a temporary file of its own
+    let mut file = AtomicFile::create(&path).unwrap();
+    let mut other = AtomicFile::create(&path).unwrap();
+    file.write_all(b"new").unwrap();
+    other.write_all(b"other").unwrap();
+    assert_eq!(fs::read_dir(path.parent().unwrap()).unwrap().count(), 3);
+    drop(other);
+    file.commit().unwrap();
+    assert_eq!(fs::read_to_string(&path).unwrap(), "new");
+    assert_eq!(fs::read_dir(path.parent().unwrap()).unwrap().count(), 1);
+}
+
+#[cfg(unix)]
+#[test]
+fn test_atomic_file_keeps_permissions() {
+    use std::io::Write;
+    use std::os::unix::fs::PermissionsExt;
+
+    let path = output_dir("atomic-file-mode").join("out.json");
+    fs::write(&path, "old").unwrap();
+    fs::set_permissions(&path, fs::Permissions::from_mode(0o600)).unwrap();
+
+    let mut file = AtomicFile::create(&path).unwrap();
+    file.write_all(b"new").unwrap();
+    file.commit().unwrap();
+    assert_eq!(fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+}
+
+#[cfg(feature = "cli")]
+#[test]
+fn test_check_output_file_survives_errors() {
+    let path = output_dir("check-output").join("out.json");
+    let check = |args: &[&str]| {
+        std::process::Command::new(env!("CARGO_BIN_EXE_clove"))
+            .args(["check", "-i", r#"{"a": 1}"#, "-o"])
+            .arg(&path)
+            .args(args)
+            .output()
+            .unwrap()
+    };
+
+    fs::write(&path, "old\n").unwrap();
+    for args in [&["$ | !("][..], &["$[a] / 0"], &["$ | ?($[a] > 1)", "--fail-on-null"]] {
+        assert!(!check(args).status.success(), "{:?}", args);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "old\n", "{:?}", args);
+    }
+    assert_eq!(fs::read_dir(path.parent().unwrap()).unwrap().count(), 1);
+
+    let output = check(&["$[a] + 1"]);
+    assert!(output.status.success() && output.stdout.is_empty());
+    assert_eq!(fs::read_to_string(&path).unwrap(), "2\n");
+}
+
+#[cfg(feature = "cli")]
+#[test]
+fn test_preserved_numbers_respect_max_input_size() {
+    use std::io::Write;
+    use std::process::{Command, Stdio};
+
+    let input = r#"{"n": 1.50, "pad": "xxxxxxxxxxxxxxxxxxxxxxxx"}"#;
+    let stdin = |max_size: &str| {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_clove"))
+            .args(["check", "$", "--preserve-numbers", "--max-input-size", max_size])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+        child.stdin.take().unwrap().write_all(input.as_bytes()).unwrap();
+        child.wait_with_output().unwrap()
+    };
+    let output = stdin("10");
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("maximum size of 10 bytes"));
+    let output = stdin("1K");
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout).contains("1.50"));
+
+    let path = output_dir("preserve-limit").join("in.json");
+    fs::write(&path, input).unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_clove"))
+        .args(["check", "$ | ~($[m] := 2)", "--preserve-numbers", "--max-input-size", "10", "--in-place"])
+        .arg(&path)
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert_eq!(fs::read_to_string(&path).unwrap(), input);
+}
+
+#[test]
+fn test_parse_byte_size() {
+    assert_eq!(parse_byte_size("1024"), Ok(1024));
+    assert_eq!(parse_byte_size("512K"), Ok(512 * 1024));
+    assert_eq!(parse_byte_size("10m"), Ok(10 * 1024 * 1024));
+    assert_eq!(parse_byte_size("2G"), Ok(2 * 1024 * 1024 * 1024));
+    assert!(parse_byte_size("5X").is_err());
+    assert!(parse_byte_size("").is_err());
+}
+
+#[test]
+fn test_check_max_input_size() {
+    let options = CheckOptions {
+        query: "$[a]".to_string(),
+        input: Some(r#"{"a": 1, "padding": "xxxxxxxxxxxxxxxx"}"#.to_string()),
+        max_input_size: Some(16),
+        ..Default::default()
+    };
+    assert!(matches!(execute_check(&options), Err(CliError::InputTooLarge(16))));
+
+    let options = CheckOptions {
+        max_input_size: Some(1024),
+        ..options
+    };
+    assert!(matches!(
+        execute_check(&options),
+        Ok(CheckResult::Success { value: v, .. }) if v == clove_value!(1)
+    ));
+}
+
+#[test]
+fn test_check_max_document_size() {
+    let options = CheckOptions {
+        query: "$ | ~($[copy] := $[items]) | ~($[again] := $[copy])".to_string(),
+        input: Some(r#"{"items": [1, 2, 3, 4, 5, 6, 7, 8]}"#.to_string()),
+        max_document_size: Some(1024),
+        ..Default::default()
+    };
+    let err = execute_check(&options).unwrap_err();
+    assert!(err.to_string().contains("Limit exceeded"), "{}", err);
+
+    let options = CheckOptions { max_document_size: Some(1 << 20), explain: true, ..options };
+    assert!(matches!(execute_check(&options), Ok(CheckResult::Success { .. })));
+}
+
+#[test]
+fn test_check_selects() {
+    let options = CheckOptions {
+        input: Some(r#"{"meta": {"version": "1.2"}, "items": [1, 2, 3], "other": {"x": 1}}"#.to_string()),
+        selects: vec![
+            parse_select("version=$[meta][version]").unwrap(),
+            parse_select("count=$[items].count()").unwrap(),
+            parse_select("big=$ | ?($[items].count() > 5)").unwrap(),
+        ],
+        ..Default::default()
+    };
+    assert!(matches!(
+        execute_check(&options),
+        Ok(CheckResult::Success { value: v, .. }) if v == clove_value!({"version": "1.2", "count": 3, "big": null})
+    ));
+
+    let options = CheckOptions { selects: vec![parse_select("n=$[items].nope()").unwrap()], ..options };
+    let err = execute_check(&options).unwrap_err();
+    assert!(matches!(&err, CliError::Select { name, .. } if name == "n"), "{}", err);
+
+    assert!(parse_select("version").is_err());
+    assert!(parse_select("a b=$").is_err());
+    assert_eq!(parse_select("eq=$[a] == 1").unwrap(), ("eq".to_string(), "$[a] == 1".to_string()));
+}
+
+#[test]
+fn test_check_no_optimize_gives_same_result() {
+    let options = CheckOptions {
+        query: "$ | ?(true) | ~($[p] := @[price]) | ~($[p] := @ * 60 * 60) | !($[p])".to_string(),
+        input: Some(r#"{"p": [{"price": 1}, {"price": 2}]}"#.to_string()),
+        ..Default::default()
+    };
+    for no_optimize in [false, true] {
+        let options = CheckOptions { no_optimize, ..options.clone() };
+        assert!(matches!(
+            execute_check(&options),
+            Ok(CheckResult::Success { value: v, .. }) if v == clove_value!([3600, 7200])
+        ));
+    }
+}
+
+#[test]
+fn test_check_reader_streams_input() {
+    let options = CheckOptions {
+        query: "$[users][1][name]".to_string(),
+        ..Default::default()
+    };
+    let input = r#"{"users": [{"name": "Alice"}, {"name": "Bob"}], "other": [1, 2, 3]}"#;
+    assert!(matches!(
+        execute_check_reader(&options, input.as_bytes()),
+        Ok(CheckResult::Success { value: v, .. }) if v == clove_value!("Bob")
+    ));
+
+    let options = CheckOptions {
+        query: r#"$ | ?($[other].count() > 2) | !({"first": $[users][0][name], "last": $[other][-1]})"#.to_string(),
+        ..Default::default()
+    };
+    assert!(matches!(
+        execute_check_reader(&options, input.as_bytes()),
+        Ok(CheckResult::Success { value: v, .. }) if v == clove_value!({"first": "Alice", "last": 3})
+    ));
+}
+
+#[test]
+fn test_check_slurp_combines_documents() {
+    let options = CheckOptions {
+        query: "$ | !([$.count(), $.sum(@[amount]), $[-1][amount]])".to_string(),
+        slurp: true,
+        ..Default::default()
+    };
+    let input = "{\"amount\": 2}\n{\"amount\": 5}\n\n{\"amount\": 1}\n";
+    assert!(matches!(
+        execute_check_reader(&options, input.as_bytes()),
+        Ok(CheckResult::Success { value: v, .. }) if v == clove_value!([3, 8, 1])
+    ));
+
+    let options = CheckOptions { query: "$".to_string(), ..options };
+    assert!(matches!(
+        execute_check_reader(&options, "".as_bytes()),
+        Ok(CheckResult::Success { value: v, .. }) if v == clove_value!([])
+    ));
+    assert!(execute_check_reader(&options, "{} {".as_bytes()).is_err());
+}
+
+#[test]
+fn test_check_duplicate_keys() {
+    let check = |query: &str, duplicate_keys| {
+        execute_check(&CheckOptions {
+            query: query.to_string(),
+            input: Some(r#"{"a": 1, "a": 2}"#.to_string()),
+            duplicate_keys,
+            ..Default::default()
+        })
+    };
+
+    let result = check("$[a]", DuplicateKeys::Last);
+    assert!(matches!(result, Ok(CheckResult::Success { value: v, .. }) if v == clove_value!(2)));
+    let result = check("$ | !({x: $[a], x: 3})", DuplicateKeys::First);
+    assert!(matches!(result, Ok(CheckResult::Success { value: v, .. }) if v == clove_value!({"x": 1})));
+    assert!(matches!(check("$[b]", DuplicateKeys::Error), Err(CliError::Json(_))));
+    let result = execute_check(&CheckOptions {
+        query: "$ | !({x: 1, x: 3})".to_string(),
+        input: Some("{}".to_string()),
+        duplicate_keys: DuplicateKeys::Error,
+        ..Default::default()
+    });
+    assert!(matches!(result, Err(CliError::Parse(_))));
+}
+
+#[test]
+fn test_check_params() {
+    let options = CheckOptions {
+        query: "[@name, @n + 1]".to_string(),
+        params: [("name".to_string(), "Ann".into()), ("n".to_string(), 41i64.into())].into(),
+        null_input: true,
+        ..Default::default()
+    };
+    assert!(matches!(
+        execute_check(&options),
+        Ok(CheckResult::Success { value: v, .. }) if v == clove_value!(["Ann", 42])
+    ));
+}
+
+#[test]
+fn test_check_null_input() {
+    let options = CheckOptions {
+        query: "$ | !({\"root\": $, \"n\": [1, 2].sum()})".to_string(),
+        null_input: true,
+        ..Default::default()
+    };
+    let result = execute_check(&options);
+    assert!(matches!(result, Ok(CheckResult::Success { value: v, .. }) if v == clove_value!({"root": null, "n": 3})));
+    // The reader is not read
+    let result = execute_check_reader(&options, "not json".as_bytes());
+    assert!(matches!(result, Ok(CheckResult::Success { .. })));
+    let result = execute_check(&CheckOptions { null_input: false, ..options });
+    assert!(matches!(result, Err(CliError::NoInput)));
+}
+
+#[test]
+fn test_check_redact() {
+    let options = CheckOptions {
+        query: "$ | -($[users][*][ssn])".to_string(),
+        input: Some(r#"{"users": [{"name": "ann", "password": "x", "ssn": "1"}, {"name": "bob"}], "token": "t"}"#.to_string()),
+        redact: vec!["$[users][*][password]".to_string(), "$[token]".to_string()],
+        ..Default::default()
+    };
+    let result = execute_check(&options);
+    assert!(matches!(
+        result,
+        Ok(CheckResult::Success { value: v, .. }) if v == clove_value!({
+            "users": [{"name": "ann", "password": "[REDACTED]"}, {"name": "bob"}],
+            "token": "[REDACTED]"
+        })
+    ));
+
+    for target in ["users", "$[users][$[n]]"] {
+        let options = CheckOptions { redact: vec![target.to_string()], ..options.clone() };
+        let err = execute_check(&options).unwrap_err();
+        assert!(matches!(&err, CliError::Redact { target: t, .. } if t == target), "{}", err);
+    }
+}
+
+#[test]
+fn test_redact_rules() {
+    let text = r#"[
+        {"path": "$[users][*][password]", "strategy": "remove"},
+        {"field": "ssn", "strategy": "mask"},
+        {"path": "$[users][*][email]", "strategy": "hash"}
+    ]"#;
+    let rules = RedactRule::parse_rules(text, Some(b"secret")).unwrap();
+    assert_eq!(rules[1].strategy, RedactStrategy::Mask);
+
+    let input = r#"{"users": [{"email": "a@b.c", "password": "x", "ssn": "1", "kids": [{"ssn": "2"}]}]}
+        {"users": [], "ssn": 3}"#;
+    let mut output = Vec::new();
+    let report = execute_redact(input.as_bytes(), &rules, |doc| {
+        output.push(clove_lang::cli::clove_to_json(doc));
+        Ok(())
+    })
+    .unwrap();
+    assert_eq!(output, [
+        serde_json::json!({"users": [{
+            "email": "0ce3629b4ac1ef1367b15f9d7659135a1c8663659b98cfd72c175d86612f7879",
+            "ssn": "[REDACTED]",
+            "kids": [{"ssn": "[REDACTED]"}]
+        }]}),
+        serde_json::json!({"users": [], "ssn": "[REDACTED]"}),
+    ]);
+    assert_eq!(report.documents, 2);
+    assert_eq!(report.rules.iter().map(|(_, count)| *count).collect::<Vec<_>>(), [1, 3, 1]);
+    assert!(report.to_string().ends_with("Redacted 5 values in 2 documents"), "{}", report);
+
+    for bad in [
+        r#"[{"strategy": "mask"}]"#,
+        r#"[{"path": "$[a]", "field": "a", "strategy": "mask"}]"#,
+        r#"[{"path": "$[a]", "strategy": "shred"}]"#,
+    ] {
+        assert!(matches!(RedactRule::parse_rules(bad, None), Err(CliError::Json(_))), "{}", bad);
+    }
+    let err = RedactRule::parse_rules(r#"[{"path": "users", "strategy": "mask"}]"#, None).unwrap_err();
+    assert!(matches!(&err, CliError::Redact { target, .. } if target == "users"), "{}", err);
+
+    // Hashing takes a key, and a different key gives different digests
+    for key in [None, Some(&b""[..])] {
+        assert!(matches!(RedactRule::parse_rules(text, key), Err(CliError::NoHashKey)));
+    }
+    assert!(RedactRule::parse_rules(&text.replace("hash", "mask"), None).is_ok());
+    let rules = RedactRule::parse_rules(text, Some(b"other")).unwrap();
+    let mut doc = clove_lang::clove_value!({"users": [{"email": "a@b.c"}]});
+    rules[2].apply(&mut doc);
+    assert_eq!(
+        doc,
+        clove_lang::clove_value!({"users": [{"email": "1a66d141a42db74621abd9a70275e023c7fa29fe07604154ac8ef7d7a36f4761"}]})
+    );
+}
+
+#[test]
+fn test_check_syntax_only_reports_every_error() {
+    let check = |query: &str| {
+        execute_check(&CheckOptions {
+            query: query.to_string(),
+            syntax_only: true,
+            ..Default::default()
+        })
+    };
+
+    assert!(matches!(check("$ | ?($[a] == 1) | !($[b])"), Ok(CheckResult::SyntaxValid)));
+    assert!(matches!(check("$ | ?($[a] ==) | !($[b])"), Err(CliError::Parse(_))));
+    match check("$ | ?($[a] ==) | ~($[b] 1) | !($[c])") {
+        Err(CliError::ParseErrors(errors)) => {
+            let columns: Vec<_> = errors.iter().map(|e| e.position().column).collect();
+            assert_eq!(columns, vec![14, 25]);
+        }
+        other => panic!("expected several errors, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_assert_truthiness() {
+    let assert = |query: &str, input: &str| {
+        execute_assert(&CheckOptions {
+            query: query.to_string(),
+            input: Some(input.to_string()),
+            ..Default::default()
+        })
+    };
+
+    let passed = assert("$[status] == 200", r#"{"status": 200}"#).unwrap();
+    assert!(passed.passed);
+    assert_eq!(passed.exit_code(), EXIT_PASSED);
+
+    for query in ["$[status] == 500", "$[missing]", "$[empty]", "$[zero]"] {
+        let outcome = assert(query, r#"{"status": 200, "empty": [], "zero": 0}"#).unwrap();
+        assert!(!outcome.passed, "{}", query);
+        assert_eq!(outcome.exit_code(), EXIT_FAILED);
+    }
+
+    let filtered = assert(r#"$ | ?($[status] == 200)"#, r#"{"status": 200}"#).unwrap();
+    assert!(filtered.passed);
+    let rejected = assert(r#"$ | ?($[status] == 500)"#, r#"{"status": 200}"#).unwrap();
+    assert_eq!(rejected.exit_code(), EXIT_FAILED);
+
+    assert!(matches!(assert("$[status] ==", "{}"), Err(CliError::Parse(_))));
+    assert!(matches!(assert("$[status]", "{"), Err(CliError::Json(_))));
+}
+
+#[test]
+fn test_check_filtered_out_is_distinct_from_null() {
+    let check = |query: &str| {
+        execute_check(&CheckOptions {
+            query: query.to_string(),
+            input: Some(r#"{"value": null}"#.to_string()),
+            ..Default::default()
+        })
+        .unwrap()
+    };
+
+    assert!(matches!(check("$ | ?($[value] != null)"), CheckResult::FilteredOut));
+    assert!(matches!(
+        check("$ | ?($[value] == null) | !($[value])"),
+        CheckResult::Success { value: clove_lang::Value::Null, .. }
+    ));
+}
+
+#[test]
+fn test_check_reports_non_finite_numbers() {
+    let check = |query: &str| {
+        execute_check(&CheckOptions {
+            query: query.to_string(),
+            input: Some(r#"{"big": 1e308}"#.to_string()),
+            ..Default::default()
+        })
+        .unwrap()
+    };
+
+    assert!(matches!(
+        check("[$[big] * 10, 1]"),
+        CheckResult::Success { value, non_finite: true } if value == clove_value!([null, 1])
+    ));
+    assert!(matches!(check("$[big] / 10"), CheckResult::Success { non_finite: false, .. }));
+}
+
+#[test]
+fn test_check_with_includes() {
+    let lib = scratch_file("include-lib.clv", "&big:1 := ?(@1[price] > 100)");
+    let options = CheckOptions {
+        query: "%include \"include-lib.clv\"\n$ | ~($[checked] := true)".to_string(),
+        input: Some("{}".to_string()),
+        base_dir: lib.parent().map(|p| p.to_path_buf()),
+        ..Default::default()
+    };
+    match execute_check(&options) {
+        Ok(CheckResult::Success { value, .. }) => assert_eq!(value, clove_value!({"checked": true})),
+        other => panic!("unexpected result: {:?}", other),
+    }
+
+    let missing = CheckOptions {
+        query: "%include \"nope.clv\"\n$".to_string(),
+        input: Some("{}".to_string()),
+        base_dir: lib.parent().map(|p| p.to_path_buf()),
+        ..Default::default()
+    };
+    assert!(matches!(execute_check(&missing), Err(CliError::Load(_))));
+}
+
+#[test]
+fn test_query_hash() {
+    let hash = |query: &str| {
+        let lib = scratch_file("hash-lib.clv", "&big:1 := ?(@1[price] > 100)");
+        let options = CheckOptions {
+            query: query.to_string(),
+            base_dir: lib.parent().map(|p| p.to_path_buf()),
+            ..Default::default()
+        };
+        execute_query_hash(&options)
+    };
+
+    let expected = hash("$ | ?($[a] > 1) | !($[b])").unwrap();
+    assert_eq!(hash("$|?($.a>1)\n  |  !($['b'])").unwrap(), expected);
+    assert_ne!(hash("$ | ?($[a] >= 1) | !($[b])").unwrap(), expected);
+    // An expression hashes like the pipeline that outputs it
+    assert_eq!(hash("$[b].len()").unwrap(), hash("$ | !($[b].len())").unwrap());
+    // Included UDFs are part of the query
+    assert_eq!(
+        hash("%include \"hash-lib.clv\"\n$ | !(&big[$])").unwrap(),
+        hash("&big:1 := ?(@1[price] > 100)\n$ | !(&big[$])").unwrap()
+    );
+    assert!(matches!(hash("$ | !("), Err(CliError::Parse(_))));
+}
+
+#[test]
+fn test_check_ndjson_records() {
+    let options = CheckOptions {
+        query: r#"$ | !({"line": $meta[line], "id": $[id], "source": $meta[source]})"#.to_string(),
+        meta: [("source".to_string(), clove_lang::Value::String("events".into()))].into(),
+        ..Default::default()
+    };
+    let mut results = Vec::new();
+    execute_check_ndjson(&options, "{\"id\": 1}\n\n{\"id\": 2}\n".as_bytes(), |value, _| {
+        results.push(value);
+        Ok(())
+    })
+    .unwrap();
+    assert_eq!(results, vec![
+        clove_value!({"line": 1, "id": 1, "source": "events"}),
+        clove_value!({"line": 3, "id": 2, "source": "events"}),
+    ]);
+
+    let err = execute_check_ndjson(&options, "{}\n{oops}\n".as_bytes(), |_, _| Ok(())).unwrap_err();
+    assert!(matches!(err, CliError::Record { line: 2, .. }), "{:?}", err);
+    assert!(err.to_string().starts_with("Line 2: Invalid JSON"), "{}", err);
+}
+
+#[test]
+fn test_bench_reports_each_query() {
+    let input = clove_lang::json_to_clove(serde_json::json!({"items": [1, 2, 3]}));
+    let queries = vec!["$[items].sum()".to_string(), "$ | ?($[items].length() > 1)".to_string()];
+    let options = BenchOptions {
+        iterations: 20,
+        warmup: 2,
+        ..Default::default()
+    };
+
+    let reports = execute_bench(&queries, &input, &options).unwrap();
+    assert_eq!(reports.len(), 2);
+    for (report, query) in reports.iter().zip(&queries) {
+        assert_eq!(&report.query, query);
+        assert_eq!(report.iterations, 20);
+        assert!(report.min <= report.mean && report.mean <= report.max);
+        assert!(report.min <= report.p95 && report.p95 <= report.max);
+        let json = report.to_json();
+        assert_eq!(json["iterations"], 20);
+        assert!(json["p95_ns"].is_u64());
+    }
+
+    let bad = vec!["$[items].nope()".to_string()];
+    assert!(matches!(execute_bench(&bad, &input, &options), Err(CliError::Eval(_))));
+}
+
+#[test]
+fn test_schema_infer_reads_every_document() {
+    let ndjson = "{\"id\": 1}\n{\"id\": 2, \"extra\": true}\n";
+    let shape = execute_schema_infer([ndjson.as_bytes(), "[]".as_bytes(), "".as_bytes()]).unwrap();
+    assert_eq!(shape.count, 3);
+    assert_eq!(shape.type_names(), ["array", "object"]);
+    assert!(shape.field("extra").unwrap().optional);
+
+    assert!(matches!(
+        execute_schema_infer(["{oops}".as_bytes()]),
+        Err(CliError::Json(_))
+    ));
+}
+
+#[test]
+fn test_decompress_detects_compression() {
+    assert_eq!(Compression::sniff(&[0x1f, 0x8b, 0x08]), Some(Compression::Gzip));
+    assert_eq!(Compression::sniff(&[0x28, 0xb5, 0x2f, 0xfd]), Some(Compression::Zstd));
+    assert_eq!(Compression::sniff(b"{}"), None);
+    assert_eq!(Compression::from_path("logs.json.gz".as_ref()), Some(Compression::Gzip));
+    assert_eq!(Compression::from_path("logs.json.zst".as_ref()), Some(Compression::Zstd));
+    assert_eq!(Compression::from_path("logs.json".as_ref()), None);
+
+    // Uncompressed input passes through whole, however short
+    for text in ["", "1", "{\"a\": [1, 2]}"] {
+        let mut read = String::new();
+        decompress(text.as_bytes()).unwrap().read_to_string(&mut read).unwrap();
+        assert_eq!(read, text);
+    }
+    let mut read = String::new();
+    open_input(&scratch_file("plain.json", "[1]")).unwrap().read_to_string(&mut read).unwrap();
+    assert_eq!(read, "[1]");
+
+    #[cfg(not(feature = "zstd"))]
+    assert!(matches!(
+        decompress(&[0x28, 0xb5, 0x2f, 0xfd, 0x00][..]),
+        Err(CliError::Compression(Compression::Zstd))
+    ));
+    #[cfg(not(feature = "gzip"))]
+    assert!(matches!(
+        OutputStream::new(Vec::new(), true),
+        Err(CliError::Compression(Compression::Gzip))
+    ));
+}
+
+#[cfg(feature = "gzip")]
+#[test]
+fn test_gzip_input_and_output() {
+    use std::io::Write;
+
+    let mut out = OutputStream::new(Vec::new(), true).unwrap();
+    out.write_all(b"{\"items\": [1, 2, 3]}\n").unwrap();
+    let OutputStream::Gzip(encoder) = out else { panic!("expected a gzip stream") };
+    let compressed = encoder.finish().unwrap();
+    assert_eq!(Compression::sniff(&compressed), Some(Compression::Gzip));
+
+    let options = CheckOptions { query: "$[items].sum()".to_string(), ..Default::default() };
+    let result = execute_check_reader(&options, decompress(&compressed[..]).unwrap()).unwrap();
+    assert!(matches!(result, CheckResult::Success { value: v, .. } if v == clove_value!(6)));
+
+    // Concatenated members, as appending to a .gz log makes, read as one stream
+    let mut records = compressed.clone();
+    records.extend_from_slice(&compressed);
+    let mut text = String::new();
+    decompress(&records[..]).unwrap().read_to_string(&mut text).unwrap();
+    assert_eq!(text.lines().count(), 2);
+
+    let path = scratch_file("logs.json.gz", "");
+    fs::write(&path, &compressed).unwrap();
+    let shape = execute_schema_infer([open_input(&path).unwrap()]).unwrap();
+    assert_eq!(shape.count, 1);
+}
+
+#[cfg(feature = "zstd")]
+#[test]
+fn test_zstd_input() {
+    let compressed = zstd::encode_all(&b"{\"n\": 7}"[..], 0).unwrap();
+    let options = CheckOptions { query: "$[n] * 6".to_string(), ..Default::default() };
+    let result = execute_check_reader(&options, decompress(&compressed[..]).unwrap()).unwrap();
+    assert!(matches!(result, CheckResult::Success { value: v, .. } if v == clove_value!(42)));
+}
+
+#[test]
+fn test_parse_header() {
+    assert_eq!(
+        parse_header("Authorization: Bearer a:b").unwrap(),
+        ("Authorization".to_string(), "Bearer a:b".to_string())
+    );
+    assert_eq!(parse_header("X-Empty:").unwrap(), ("X-Empty".to_string(), String::new()));
+    assert!(parse_header("no colon").is_err());
+    assert!(parse_header(": value").is_err());
+}
+
+#[cfg(not(feature = "http"))]
+#[test]
+fn test_fetch_needs_http_feature() {
+    let request = FetchRequest { url: "http://localhost/".to_string(), ..Default::default() };
+    assert!(matches!(fetch(&request), Err(CliError::Fetch { message, .. }) if message.contains("--features http")));
+}
+
+/// Serve one HTTP response on a local port, returning its URL and the
+/// request as received
+#[cfg(feature = "http")]
+fn serve_once(response: &'static str) -> (String, std::thread::JoinHandle<String>) {
+    use std::io::{BufRead, BufReader, Write};
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/items.json", listener.local_addr().unwrap());
+    let server = std::thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut request = String::new();
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        while reader.read_line(&mut request).unwrap() > 2 {}
+        stream.write_all(response.as_bytes()).unwrap();
+        request
+    });
+    (url, server)
+}
+
+#[cfg(feature = "http")]
+#[test]
+fn test_fetch_input_url() {
+    let (url, server) =
+        serve_once("HTTP/1.1 200 OK\r\nContent-Length: 20\r\nConnection: close\r\n\r\n{\"items\": [1, 2, 3]}");
+    let request = FetchRequest {
+        url,
+        headers: vec![("X-Token".to_string(), "abc".to_string())],
+        timeout: Some(std::time::Duration::from_secs(10)),
+        max_size: None,
+    };
+    let options = CheckOptions { query: "$[items].count()".to_string(), ..Default::default() };
+    let result = execute_check_reader(&options, fetch(&request).unwrap()).unwrap();
+    assert!(matches!(result, CheckResult::Success { value: v, .. } if v == clove_value!(3)));
+    assert!(server.join().unwrap().to_lowercase().contains("x-token: abc"));
+
+    let (url, server) = serve_once("HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n");
+    let error = fetch(&FetchRequest { url, ..Default::default() }).err().unwrap();
+    assert!(error.to_string().ends_with("HTTP 404 Not Found"), "{}", error);
+    server.join().unwrap();
+
+    let (url, server) =
+        serve_once("HTTP/1.1 200 OK\r\nContent-Length: 20\r\nConnection: close\r\n\r\n{\"items\": [1, 2, 3]}");
+    let request = FetchRequest { url, max_size: Some(10), ..Default::default() };
+    assert!(matches!(fetch(&request), Err(CliError::InputTooLarge(10))));
+    server.join().unwrap();
+}
+
+#[test]
+fn test_doc_category_names_resolve() {
+    for category in DocCategory::ALL {
+        assert_eq!(DocCategory::from_str(category.name()), Some(category));
+        assert!(get_doc_category(category.name()).is_ok());
+        assert!(clove_lang::cli::get_docs_overview().contains(category.summary()), "{:?}", category);
+    }
+}
+
+#[test]
+fn test_snapshot_tests() {
+    let dir = scratch_file("snapshots.json", "").with_extension("");
+    fs::create_dir_all(dir.join("nested")).unwrap();
+    fs::write(dir.join("total.clove"), "$ | !($[items].sum())").unwrap();
+    fs::write(dir.join("total.input.json"), r#"{"items": [1, 2]}"#).unwrap();
+    fs::write(dir.join("total.expected.json"), "3").unwrap();
+    fs::write(dir.join("nested/shape.clove"), r#"$ | !({"n": $[items].count()})"#).unwrap();
+    fs::write(dir.join("nested/shape.input.json"), r#"{"items": [1, 2]}"#).unwrap();
+    fs::write(dir.join("broken.clove"), "$ | !(").unwrap();
+
+    let report = execute_test(&dir, &TestOptions::default()).unwrap();
+    let names: Vec<_> = report.cases.iter().map(|case| case.name.as_str()).collect();
+    assert_eq!(names, ["broken", "nested/shape", "total"]);
+    assert!(matches!(report.cases[0].status, TestStatus::Error(_)));
+    assert!(matches!(report.cases[1].status, TestStatus::Missing { .. }), "{}", report);
+    assert_eq!(report.cases[2].status, TestStatus::Passed);
+    assert!(report.to_string().ends_with("1 passed, 2 failed"), "{}", report);
+
+    // --update writes the missing snapshot, which then passes
+    fs::remove_file(dir.join("broken.clove")).unwrap();
+    let report = execute_test(&dir, &TestOptions { update: true, ..Default::default() }).unwrap();
+    assert!(report.is_success());
+    assert_eq!(fs::read_to_string(dir.join("nested/shape.expected.json")).unwrap(), "{\n  \"n\": 2\n}\n");
+    execute_test(&dir, &TestOptions::default()).unwrap().assert_passed();
+
+    // A changed output fails with a diff of the snapshot
+    fs::write(dir.join("nested/shape.input.json"), r#"{"items": []}"#).unwrap();
+    let report = execute_test(&dir, &TestOptions::default()).unwrap();
+    assert!(!report.is_success());
+    assert!(report.to_string().contains("FAILED   nested/shape"), "{}", report);
+    assert!(report.to_string().contains("-   \"n\": 2\n         +   \"n\": 0"), "{}", report);
+
+    assert!(matches!(execute_test(&dir.join("missing"), &TestOptions::default()), Err(CliError::Io(_))));
+}
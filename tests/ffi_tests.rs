@@ -0,0 +1,94 @@
+#![cfg(feature = "ffi")]
+
+use clove_lang::ffi::*;
+use std::ffi::{CStr, CString};
+
+fn last_error() -> Option<String> {
+    let err = clove_last_error();
+    if err.is_null() {
+        None
+    } else {
+        Some(unsafe { CStr::from_ptr(err) }.to_string_lossy().into_owned())
+    }
+}
+
+fn eval(query: &str, json: &str) -> Result<String, String> {
+    let query = CString::new(query).unwrap();
+    let json = CString::new(json).unwrap();
+    unsafe {
+        let q = clove_compile(query.as_ptr());
+        if q.is_null() {
+            return Err(last_error().unwrap());
+        }
+        let out = clove_eval_json(q, json.as_ptr());
+        clove_query_free(q);
+        if out.is_null() {
+            return Err(last_error().unwrap());
+        }
+        let s = CStr::from_ptr(out).to_str().unwrap().to_string();
+        clove_free_result(out);
+        Ok(s)
+    }
+}
+
+#[test]
+fn test_ffi_eval_expression() {
+    let out = eval("$[items].filter(@ > 1)", r#"{"items": [1, 2, 3]}"#).unwrap();
+    assert_eq!(out, "[2,3]");
+    assert_eq!(last_error(), None);
+}
+
+#[test]
+fn test_ffi_eval_pipeline_query() {
+    let out = eval("$ | ~($[n] := $[n] * 2) | !($[n])", r#"{"n": 21}"#).unwrap();
+    assert_eq!(out, "42");
+}
+
+#[test]
+fn test_ffi_compile_once_eval_many() {
+    let query = CString::new("$[x] + 1").unwrap();
+    unsafe {
+        let q = clove_compile(query.as_ptr());
+        assert!(!q.is_null());
+        for (input, expected) in [(r#"{"x": 1}"#, "2"), (r#"{"x": 41}"#, "42")] {
+            let json = CString::new(input).unwrap();
+            let out = clove_eval_json(q, json.as_ptr());
+            assert_eq!(CStr::from_ptr(out).to_str().unwrap(), expected);
+            clove_free_result(out);
+        }
+        clove_query_free(q);
+    }
+}
+
+#[test]
+fn test_ffi_parse_error() {
+    let err = eval("$[", "{}").unwrap_err();
+    assert!(err.starts_with("Parse error"), "got: {}", err);
+}
+
+#[test]
+fn test_ffi_invalid_json() {
+    let err = eval("$", "{not json").unwrap_err();
+    assert!(err.starts_with("Invalid JSON"), "got: {}", err);
+}
+
+#[test]
+fn test_ffi_eval_error() {
+    let err = eval("$[x].upper()", r#"{"x": 1}"#).unwrap_err();
+    assert!(err.starts_with("Evaluation error"), "got: {}", err);
+}
+
+#[test]
+fn test_ffi_null_arguments() {
+    unsafe {
+        assert!(clove_compile(std::ptr::null()).is_null());
+        assert_eq!(last_error().as_deref(), Some("query is null"));
+
+        assert!(clove_eval_json(std::ptr::null(), std::ptr::null()).is_null());
+        assert_eq!(last_error().as_deref(), Some("query handle is null"));
+
+        // Freeing null is a no-op
+        clove_free_result(std::ptr::null_mut());
+        clove_query_free(std::ptr::null_mut());
+    }
+}
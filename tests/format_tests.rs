@@ -0,0 +1,199 @@
+use clove_lang::ast::{BinOp, Expr, Statement};
+use clove_lang::{Lexer, Parser, Query};
+
+fn parse(source: &str) -> Query {
+    Parser::new(Lexer::new(source)).unwrap().parse_query().unwrap()
+}
+
+/// Print `query`, parse it back and check nothing changed
+fn assert_round_trips(query: &Query) {
+    let text = query.to_string();
+    let reparsed = Parser::new(Lexer::new(&text))
+        .and_then(|mut parser| parser.parse_query())
+        .unwrap_or_else(|e| panic!("{} does not parse: {}\n{:?}", text, e, query));
+    assert_eq!(&reparsed, query, "printed as {}", text);
+}
+
+fn binary(op: BinOp, left: Expr, right: Expr) -> Expr {
+    Expr::BinaryOp { op, left: Box::new(left), right: Box::new(right) }
+}
+
+fn access(object: Expr, key: Expr) -> Expr {
+    Expr::Access { object: Box::new(object), key: Box::new(key) }
+}
+
+#[test]
+fn test_format_canonical_spelling() {
+    let cases = [
+        ("$", "$"),
+        ("$|!($.a.b)", "$ | !($[a][b])"),
+        ("$ | ?($['unit price'] >= -1.5)", r#"$ | ?($["unit price"] >= -1.5)"#),
+        ("$ | !(a: 1) !(b: [2, 'x'])", "$ | !({a: 1, b: [2, \"x\"]})"),
+        (
+            "$ | ~($[items] := ?(@[n] > 0)) | -($[users][*][token])",
+            "$ | ~($[items] := ?(@[n] > 0)) | -($[users][*][token])",
+        ),
+        ("$ | @s ::= $[a]\n  | !(@s ?? $HOME)", "$ | @s ::= $[a] | !(@s ?? $HOME)"),
+        ("$ | !($PORT:int + 0x10)", "$ | !($PORT:int + 16)"),
+        ("$ | !($[a]?)", "$ | !($[a][?])"),
+        (
+            "$ | foreach $[items] as @item { ~(@item[n] := @item[n] * 2) } | when ($[x]) { ?(true) }",
+            "$ | foreach $[items] as @item { ~(@item[n] := @item[n] * 2) } | when ($[x]) { ?(true) }",
+        ),
+        (
+            "$ | !(match $[v] { {\"t\": t, 'n': [1, _, ...]} if @t > 0 => @t, _ => null })",
+            "$ | !(match $[v] { {t: t, n: [1, _, ...]} if @t > 0 => @t, _ => null })",
+        ),
+        ("$ | !($[xs].map(x -> @x.map(@ * @@)))", "$ | !($[xs].map(x -> @x.map(@ * @@)))"),
+        ("$ | !(try ext::lookup($[id]) catch &f[])", "$ | !(try ext::lookup($[id]) catch &f[])"),
+        ("&double:1 := @1 * 2\n$ | !(&double[$[n]])", "&double:1 := @1 * 2\n$ | !(&double[$[n]])"),
+    ];
+    for (source, expected) in cases {
+        let query = parse(source);
+        assert_eq!(query.to_string(), expected, "formatting {}", source);
+        assert_round_trips(&query);
+    }
+}
+
+#[test]
+fn test_format_parenthesizes_only_where_needed() {
+    let one = || Expr::Integer(1);
+    let root = |key: &str| Expr::root().get(key);
+    let cases = [
+        (binary(BinOp::Multiply, binary(BinOp::Add, one(), one()), one()), "(1 + 1) * 1"),
+        (binary(BinOp::Add, one(), binary(BinOp::Multiply, one(), one())), "1 + 1 * 1"),
+        (binary(BinOp::Subtract, one(), binary(BinOp::Subtract, one(), one())), "1 - (1 - 1)"),
+        (binary(BinOp::Subtract, binary(BinOp::Subtract, one(), one()), one()), "1 - 1 - 1"),
+        (binary(BinOp::Equal, binary(BinOp::LessThan, one(), one()), Expr::Boolean(true)), "(1 < 1) == true"),
+        (binary(BinOp::And, binary(BinOp::Or, one(), one()), one()), "(1 or 1) and 1"),
+        (binary(BinOp::Or, one(), binary(BinOp::And, one(), one())), "1 or 1 and 1"),
+        (binary(BinOp::NullCoalesce, binary(BinOp::Or, one(), one()), one()), "1 or 1 ?? 1"),
+        (binary(BinOp::Subtract, Expr::Integer(0), root("a")), "0 - $[a]"),
+        (binary(BinOp::Add, root("a"), one()).get("b"), "($[a] + 1)[b]"),
+        (Expr::Integer(-1).method("abs", []), "(-1).abs()"),
+        (Expr::Float(1.0).method("abs", []), "1.0.abs()"),
+        (Expr::ExistenceCheck(Box::new(root("a"))).get("b"), "($[a][?])[b]"),
+        (Expr::root().index(-1), "$[-1]"),
+        (access(Expr::Root, Expr::String("a".into())), "$[(\"a\")]"),
+        (access(Expr::Root, Expr::Boolean(true)), "$[true]"),
+        (
+            Expr::Try { body: Box::new(Expr::LambdaParam), fallback: Box::new(one()) },
+            "try @ catch 1",
+        ),
+        (
+            binary(BinOp::Add, Expr::Try { body: Box::new(one()), fallback: Box::new(one()) }, one()),
+            "(try 1 catch 1) + 1",
+        ),
+        (Expr::Float(f64::INFINITY), "1e999"),
+        (Expr::Float(1e300), "1e300"),
+        (Expr::String("say \"hi\"\n".into()), r#""say \"hi\"\n""#),
+    ];
+    for (expr, expected) in cases {
+        assert_eq!(expr.to_string(), expected);
+        let reparsed = Parser::new(Lexer::new(expected)).unwrap().parse().unwrap();
+        assert_eq!(reparsed, expr, "reparsing {}", expected);
+    }
+}
+
+#[test]
+fn test_format_statements_that_could_misparse() {
+    // A statement starting with `@name` or `-` reads differently
+    let query = Query {
+        udfs: vec![],
+        statements: vec![
+            Statement::Access(binary(BinOp::Add, Expr::item().get("a"), Expr::Integer(1))),
+            Statement::Access(binary(BinOp::Add, Expr::Integer(-1), Expr::Integer(2))),
+            Statement::Access(Expr::ScopeRef("s".into()).method("len", [])),
+        ],
+        output: None,
+    };
+    assert_eq!(query.to_string(), "$ | (@[a] + 1) | (-1 + 2) | (@s.len())");
+    assert_round_trips(&query);
+}
+
+#[test]
+fn test_format_udf_parameters() {
+    let source = "&tax(price, rate = 0.2) := @price * @rate\n&sum(first, ...) := @first + @rest.sum()\n$";
+    let query = parse(source);
+    let expected = "&tax(p1, p2 = 0.2) := @1 * @2\n&sum(p1, ...) := @1 + @2.sum()\n$";
+    assert_eq!(query.to_string(), expected);
+    assert_round_trips(&query);
+
+    // Names the body uses for scopes are left alone
+    let query = parse("&f(a = @p1) := @a + @p1 + @p1x\n$");
+    assert_eq!(query.to_string(), "&f(p1_ = @p1) := @1 + @p1 + @p1x\n$");
+    assert_round_trips(&query);
+}
+
+#[test]
+fn test_format_round_trips_examples() {
+    let sources = [
+        "$ | @user := $[user] | ?(@user[age] >= 18) | !(@user)",
+        "$ | ~($[prices] := @[price] * 1.1) | !($)",
+        "$ | !($[items][-1])",
+        "$ | !($[a] - -1 * -2.5 % 3 / 4)",
+        "$ | !([] + [[]] + {} + {\"\": {}})",
+        "$ | !($$[a] == $meta[line])",
+        "$ | !($[rows].map(try @[price] * 2 catch null))",
+        "$ | !($[orders].map(order -> @order[items].map(@[qty] * @order[rate])))",
+        "$ | !(1.5.abs() + 1.abs() + $[a].or($[b]) + $[a].and())",
+        "$ | !({\"and\": 1, \"true\": 2, \"x y\": 3, \"\": 4})",
+        "$ | !($[\"and\"][\"\"][0][1.5][$[k]][(x -> @x)])",
+        "$ | @s := 1 | foreach @s[a] as @x { @x[b] | -(@x[c][*]) | ?(@x ~= \"A\") }",
+        "&f:0 := ?($[a])\n&g(a, b = [1], ...) := &f[] ?? @rest\n$ | ~($[x] := &g[1, 2, 3])",
+    ];
+    for source in sources {
+        assert_round_trips(&parse(source));
+    }
+}
+
+#[cfg(feature = "arbitrary")]
+mod arbitrary_queries {
+    use super::*;
+    use arbitrary::{Arbitrary, Unstructured};
+
+    /// Deterministic bytes for `Unstructured`, so a failure reproduces
+    fn inputs(count: usize) -> impl Iterator<Item = Vec<u8>> {
+        let mut state = 0x2545_f491_4f6c_dd1d_u64;
+        let mut next = move || {
+            // xorshift64*
+            state ^= state >> 12;
+            state ^= state << 25;
+            state ^= state >> 27;
+            state.wrapping_mul(0x2545_f491_4f6c_dd1d)
+        };
+        (0..count).map(move |_| {
+            let len = (next() % 1024) as usize;
+            (0..len).map(|_| next() as u8).collect()
+        })
+    }
+
+    #[test]
+    fn test_arbitrary_queries_round_trip() {
+        for bytes in inputs(2000) {
+            let query = Query::arbitrary(&mut Unstructured::new(&bytes)).unwrap();
+            assert_round_trips(&query);
+        }
+    }
+
+    #[test]
+    fn test_arbitrary_expressions_round_trip() {
+        for bytes in inputs(2000) {
+            let expr = Expr::arbitrary(&mut Unstructured::new(&bytes)).unwrap();
+            let text = expr.to_string();
+            let reparsed = Parser::new(Lexer::new(&text))
+                .and_then(|mut parser| parser.parse())
+                .unwrap_or_else(|e| panic!("{} does not parse: {}\n{:?}", text, e, expr));
+            assert_eq!(reparsed, expr, "printed as {}", text);
+        }
+    }
+
+    #[test]
+    fn test_arbitrary_statements_print_as_one_statement() {
+        for bytes in inputs(500) {
+            let stmt = Statement::arbitrary(&mut Unstructured::new(&bytes)).unwrap();
+            let query = Query { udfs: vec![], statements: vec![stmt], output: None };
+            assert_round_trips(&query);
+        }
+    }
+}
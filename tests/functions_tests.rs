@@ -0,0 +1,124 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::task::{Context, Poll};
+
+use clove_lang::functions::block_on;
+use clove_lang::{EvalError, Evaluator, FunctionRegistry, Lexer, Parser, Value, clove_value};
+
+fn eval(functions: FunctionRegistry, query: &str, doc: Value) -> Result<Value, EvalError> {
+    let query = Parser::new(Lexer::new(query)).unwrap().parse_query().unwrap().optimize();
+    Evaluator::new().with_functions(functions).eval_query(&query, doc)
+}
+
+/// Ready on the second poll, after waking its task
+struct YieldOnce(bool);
+
+impl Future for YieldOnce {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if self.0 {
+            return Poll::Ready(());
+        }
+        self.0 = true;
+        cx.waker().wake_by_ref();
+        Poll::Pending
+    }
+}
+
+#[test]
+fn test_host_functions() {
+    let calls = Arc::new(AtomicUsize::new(0));
+    let mut functions = FunctionRegistry::new();
+    let counter = Arc::clone(&calls);
+    functions
+        .register("lookup_user", move |args| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            match args {
+                [Value::Integer(id)] => Ok(clove_value!({"id": *id, "name": format!("user{}", id)})),
+                _ => Err(EvalError::TypeError("ext::lookup_user() takes an id".to_string())),
+            }
+        })
+        .unwrap();
+    functions
+        .register_async("double", |args| async move {
+            YieldOnce(false).await;
+            match args.as_slice() {
+                [Value::Integer(n)] => Ok(Value::Integer(n * 2)),
+                _ => Ok(Value::Null),
+            }
+        })
+        .unwrap();
+
+    let doc = clove_value!({"ids": [1, 2, 2]});
+    let result = eval(functions.clone(), "$ | !($[ids].map(ext::lookup_user(@)[name]))", doc.clone());
+    assert_eq!(result.unwrap(), Value::array(["user1", "user2", "user2"]));
+    let result = eval(functions.clone(), "$ | !($[ids].map(ext::double(@)))", doc.clone());
+    assert_eq!(result.unwrap(), Value::array([2, 4, 4]));
+
+    // Calls are neither cached nor hoisted out of the lambda
+    calls.store(0, Ordering::SeqCst);
+    let result = eval(functions.clone(), "$ | !($[ids].map(ext::lookup_user(7)[id]))", doc.clone());
+    assert_eq!(result.unwrap(), Value::array([7, 7, 7]));
+    assert_eq!(calls.load(Ordering::SeqCst), 3);
+
+    // Errors from the host can be caught; unregistered names cannot be called
+    let err = eval(functions.clone(), "$ | !(ext::lookup_user())", doc.clone()).unwrap_err();
+    assert!(err.to_string().contains("takes an id"), "{}", err);
+    let result = eval(functions.clone(), "$ | !(try ext::lookup_user() catch null)", doc.clone());
+    assert_eq!(result.unwrap(), Value::Null);
+    let err = eval(functions, "$ | !(ext::delete_user(1))", doc).unwrap_err();
+    assert!(matches!(err, EvalError::UndefinedHostFunction(name) if name == "delete_user"));
+}
+
+#[test]
+fn test_register_and_block_on() {
+    let mut functions = FunctionRegistry::new();
+    let noop = |_: &[Value]| Ok(Value::Null);
+    functions.register("noop", noop).unwrap();
+    assert!(functions.register("noop", noop).is_err());
+    assert!(functions.register("Bad-Name", noop).is_err());
+    assert!(functions.register("", noop).is_err());
+    assert_eq!(functions.names().collect::<Vec<_>>(), ["noop"]);
+
+    assert_eq!(block_on(async { YieldOnce(false).await; 5 }), 5);
+}
+
+#[test]
+fn test_host_functions_run_once_in_method_chains() {
+    let log = Arc::new(Mutex::new(Vec::new()));
+    let mut functions = FunctionRegistry::new();
+    let sources = [("list", clove_value!([1, 2, 3])), ("obj", clove_value!({"a": 1})), ("num", clove_value!(2))];
+    for (name, value) in sources {
+        let log = Arc::clone(&log);
+        functions
+            .register(name, move |_| {
+                log.lock().unwrap().push(name);
+                Ok(value.clone())
+            })
+            .unwrap();
+    }
+    let run = |query: &str| {
+        log.lock().unwrap().clear();
+        let result = eval(functions.clone(), query, Value::Null);
+        (result, log.lock().unwrap().clone())
+    };
+
+    // A source that isn't an array reports its error without running again
+    let (result, calls) = run("$ | !(ext::num().filter(@ > 1).first())");
+    assert!(result.unwrap_err().to_string().contains(".filter() requires array"));
+    assert_eq!(calls, ["num"]);
+    let (result, calls) = run("$ | !(ext::obj().map(@).any(@ > 1))");
+    assert!(result.unwrap_err().to_string().contains(".map() requires array"));
+    assert_eq!(calls, ["obj"]);
+
+    // The count is evaluated once, after the receiver
+    let (result, calls) = run("$ | !(ext::list().take(ext::num()))");
+    assert_eq!(result.unwrap(), Value::array([1, 2]));
+    assert_eq!(calls, ["list", "num"]);
+    let (result, calls) = run("$ | !(ext::list().map(@ * 10).take(ext::num()))");
+    assert_eq!(result.unwrap(), Value::array([10, 20]));
+    assert_eq!(calls, ["list", "num"]);
+}
@@ -0,0 +1,112 @@
+use clove_lang::clove_value;
+use clove_lang::ide::{completions, diagnostics, hover, CompletionKind, Severity};
+
+fn labels(source: &str, column: usize, sample: Option<&clove_lang::Value>) -> Vec<String> {
+    completions(source, 1, column, sample)
+        .into_iter()
+        .map(|completion| completion.label)
+        .collect()
+}
+
+#[test]
+fn test_diagnostics_report_every_syntax_error() {
+    let found = diagnostics("$ | ?($[a] > ) | !($[b]");
+    let errors: Vec<_> = found.iter().map(|d| (d.severity, d.start.column)).collect();
+    assert_eq!(errors, [(Severity::Error, 14), (Severity::Error, 24)]);
+
+    assert!(diagnostics("$ | ?($[a] > 1) | !($[b])").is_empty());
+    assert!(diagnostics("$[items].filter(@ > 1).length()").is_empty());
+    assert_eq!(diagnostics("$[items].filter(@ >)")[0].severity, Severity::Error);
+}
+
+#[test]
+fn test_diagnostics_warn_about_unknown_calls() {
+    let query = "&double:1 := @1 * 2\n$ | !([$[a].lenght(), &double[1, 2], &tripple[3], &random[]])";
+    let found: Vec<_> = diagnostics(query)
+        .into_iter()
+        .map(|d| (d.severity, d.start.line, d.start.column, d.end.column, d.message))
+        .collect();
+    assert_eq!(
+        found,
+        [
+            (Severity::Warning, 2, 13, 19, "Unknown method: lenght".to_string()),
+            (Severity::Warning, 2, 24, 30, "&double takes 1 argument(s), got 2".to_string()),
+            (Severity::Warning, 2, 39, 46, "Undefined function: &tripple is not defined".to_string()),
+        ]
+    );
+
+    // Included files may define any function
+    let found = diagnostics("%include \"lib.clove\"\n$ | !(&tripple[3].lenght())");
+    assert_eq!(found.len(), 1);
+    assert_eq!((found[0].start.line, found[0].message.as_str()), (2, "Unknown method: lenght"));
+
+    // Defaults and `...` widen the accepted argument counts
+    let found = diagnostics("&tax(price, rate = 0.2) := @price * @rate\n$ | !([&tax[1], &tax[1, 2, 3]])");
+    assert_eq!(found.len(), 1);
+    assert_eq!(found[0].message, "&tax takes 1 to 2 argument(s), got 3");
+    assert!(diagnostics("&sum(...) := @rest.sum()\n$ | !([&sum[], &sum[1, 2, 3]])").is_empty());
+}
+
+#[test]
+fn test_diagnostics_warn_about_duplicate_keys() {
+    let query = r#"$ | !({"a": 1, "b": {"a": [{"b": 2}], a: 3}, "a": $[x].lenght(), "b": 4})"#;
+    let found: Vec<_> = diagnostics(query)
+        .into_iter()
+        .map(|d| (d.severity, d.start.column, d.message))
+        .collect();
+    assert_eq!(
+        found,
+        [
+            (Severity::Warning, 39, "Duplicate key \"a\" in object literal; only the last value is kept".to_string()),
+            (Severity::Warning, 46, "Duplicate key \"a\" in object literal; only the last value is kept".to_string()),
+            (Severity::Warning, 56, "Unknown method: lenght".to_string()),
+            (Severity::Warning, 66, "Duplicate key \"b\" in object literal; only the last value is kept".to_string()),
+        ]
+    );
+    // Keys of different objects, or of a block's statements, are unrelated
+    assert!(diagnostics(r#"$ | when ($[a]) { ~($[a] := {"x": 1}) | ~($[b] := {"x": [{"x": 2}]}) }"#).is_empty());
+}
+
+#[test]
+fn test_hover_shows_docs() {
+    let query = "$ | ?($[tags].contains(\"x\") && $[n] >= 2) | !($[n])";
+    let method = hover(query, 1, 16).unwrap();
+    assert!(method.contents.starts_with(".contains(value)"), "{}", method.contents);
+    assert!(method.contents.contains(".contains(substring)"));
+    assert_eq!((method.start.column, method.end.column), (15, 23));
+
+    assert!(hover(query, 1, 30).unwrap().contents.starts_with("&&"));
+    assert!(hover(query, 1, 38).unwrap().contents.starts_with(">="));
+
+    // Neither fields nor the output stage have docs
+    assert_eq!(hover(query, 1, 9), None);
+    assert_eq!(hover(query, 1, 46), None);
+}
+
+#[test]
+fn test_completions() {
+    assert_eq!(labels("$[items].so", 12, None), ["sort", "sort_by", "sort_desc", "sorted_keys"]);
+    assert!(labels("$[items].", 10, None).contains(&"filter".to_string()));
+
+    let query = "&twice:1 := @1 * 2\n$ | !(&tw";
+    let found = completions(query, 2, 10, None);
+    assert_eq!(found.len(), 1);
+    assert_eq!((found[0].label.as_str(), found[0].kind), ("twice", CompletionKind::Function));
+    assert_eq!(found[0].detail.as_deref(), Some("&twice:1"));
+}
+
+#[test]
+fn test_field_completions_come_from_the_sample() {
+    let sample = clove_value!({"name": "shop", "items": [{"price": 2, "tags": []}], "note": null});
+    assert_eq!(labels("$ | !($[", 9, Some(&sample)), ["items", "name", "note"]);
+    assert_eq!(labels("$ | !($[n", 10, Some(&sample)), ["name", "note"]);
+    assert_eq!(labels("$ | !($[items][0][", 19, Some(&sample)), ["price", "tags"]);
+
+    let found = completions("$[items][0][p", 1, 14, Some(&sample));
+    assert_eq!((found[0].kind, found[0].detail.as_deref()), (CompletionKind::Field, Some("number")));
+
+    // Paths that are not constant, or not in the sample, have no fields
+    assert!(labels("$ | !(@[", 9, Some(&sample)).is_empty());
+    assert!(labels("$ | !($[missing][", 18, Some(&sample)).is_empty());
+    assert!(labels("$ | !($[", 9, None).is_empty());
+}
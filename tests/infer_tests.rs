@@ -0,0 +1,72 @@
+use clove_lang::clove_value;
+use clove_lang::infer::{Shape, MAX_EXAMPLES};
+use clove_lang::Value;
+
+#[test]
+fn test_infer_merges_documents() {
+    let docs = [
+        clove_value!({"id": 1, "name": "Ann", "address": {"city": "Oslo"}}),
+        clove_value!({"id": 2, "name": null}),
+        clove_value!({"id": 3, "name": "Bo", "score": 1.5}),
+    ];
+    let shape = Shape::infer(&docs);
+
+    assert_eq!(shape.count, 3);
+    assert_eq!(shape.type_names(), ["object"]);
+    assert!(!shape.field("id").unwrap().optional);
+    assert!(shape.field("address").unwrap().optional);
+    assert!(shape.field("score").unwrap().optional);
+
+    let name = shape.field("name").unwrap();
+    assert_eq!(name.type_names(), ["null", "string"]);
+    assert_eq!(name.type_count("string"), 2);
+    assert_eq!(name.examples, [Value::from("Ann"), Value::Null, Value::from("Bo")]);
+}
+
+#[test]
+fn test_infer_array_items() {
+    let shape = Shape::infer([&clove_value!([{"a": 1}, {"a": 2, "b": true}, "x"])]);
+
+    let items = shape.items.as_deref().unwrap();
+    assert_eq!(items.count, 3);
+    assert_eq!(items.type_names(), ["string", "object"]);
+    assert!(!items.field("a").unwrap().optional);
+    assert!(items.field("b").unwrap().optional);
+
+    assert!(Shape::infer([&clove_value!([])]).items.is_none());
+}
+
+#[test]
+fn test_infer_keeps_few_distinct_examples() {
+    let docs: Vec<Value> = [1, 1, 2, 3, 4].into_iter().map(Value::from).collect();
+    let shape = Shape::infer(&docs);
+    assert_eq!(shape.examples.len(), MAX_EXAMPLES);
+    assert_eq!(shape.examples, [Value::from(1), Value::from(2), Value::from(3)]);
+}
+
+#[test]
+fn test_infer_output() {
+    let docs = [clove_value!({"id": 1, "tags": ["a"]}), clove_value!({"id": 2})];
+    let shape = Shape::infer(&docs);
+
+    assert_eq!(
+        shape.to_tree(),
+        "object\n  id: integer  e.g. 1, 2\n  tags?: array\n    []: string  e.g. \"a\"\n"
+    );
+    assert_eq!(
+        shape.to_value(),
+        clove_value!({
+            "count": 2,
+            "types": {"object": 2},
+            "fields": {
+                "id": {"count": 2, "types": {"integer": 2}, "examples": [1, 2]},
+                "tags": {
+                    "count": 1,
+                    "types": {"array": 1},
+                    "optional": true,
+                    "items": {"count": 1, "types": {"string": 1}, "examples": ["a"]}
+                }
+            }
+        })
+    );
+}
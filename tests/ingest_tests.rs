@@ -0,0 +1,203 @@
+use clove_lang::{DuplicateKeys, Evaluator, Lexer, LimitedReader, Parser, PathSelector, Query, Value, json_to_clove};
+use serde_json::json;
+use std::io::Read;
+
+fn parse_expr(input: &str) -> clove_lang::Expr {
+    Parser::new(Lexer::new(input)).unwrap().parse().unwrap()
+}
+
+fn parse_query(input: &str) -> Query {
+    Parser::new(Lexer::new(input)).unwrap().parse_query().unwrap()
+}
+
+fn sample() -> serde_json::Value {
+    json!({
+        "users": [
+            {"name": "Alice", "tags": ["admin"]},
+            {"name": "Bob", "tags": []}
+        ],
+        "metrics": {"0": 10, "1.5": 20, "true": 30},
+        "big": 18446744073709551615u64,
+        "ratio": 0.25,
+        "empty": null
+    })
+}
+
+#[test]
+fn test_from_reader_matches_json_conversion() {
+    let text = sample().to_string();
+    let streamed = Value::from_reader(text.as_bytes()).unwrap();
+    assert_eq!(streamed, json_to_clove(sample()));
+}
+
+#[test]
+fn test_from_reader_rejects_trailing_data() {
+    assert!(Value::from_reader(r#"{"a": 1} {"b": 2}"#.as_bytes()).is_err());
+    assert!(Value::from_reader(r#"{"a": "#.as_bytes()).is_err());
+}
+
+#[test]
+fn test_from_reader_all_reads_every_document() {
+    let documents = Value::from_reader_all("{\"a\": 1}\n\n[2] 3\n\"x\"\n".as_bytes()).unwrap();
+    assert_eq!(documents, vec![
+        json_to_clove(json!({"a": 1})),
+        json_to_clove(json!([2])),
+        Value::Integer(3),
+        Value::String("x".into()),
+    ]);
+    assert_eq!(Value::from_reader_all(" \n".as_bytes()).unwrap(), vec![]);
+    assert!(Value::from_reader_all("{\"a\": 1}\n{\"a\": ".as_bytes()).is_err());
+    assert!(Value::from_reader_all("1 ]".as_bytes()).is_err());
+}
+
+#[test]
+fn test_duplicate_keys() {
+    let text = r#"{"a": 1, "b": {"x": 1, "x": 2}, "a": 3}"#;
+    let whole = PathSelector::default();
+    let read = |selector: &PathSelector, duplicates| Value::from_reader_selecting_with(text.as_bytes(), selector, duplicates);
+
+    assert_eq!(Value::from_reader(text.as_bytes()).unwrap(), json_to_clove(json!({"a": 3, "b": {"x": 2}})));
+    assert_eq!(read(&whole, DuplicateKeys::First).unwrap(), json_to_clove(json!({"a": 1, "b": {"x": 1}})));
+    let err = read(&whole, DuplicateKeys::Error).unwrap_err();
+    assert!(err.to_string().contains("duplicate key \"x\""), "{}", err);
+
+    // Keys outside the selected paths are checked too
+    let selector = PathSelector::from_expr(&parse_expr("$[b][x]")).unwrap();
+    assert_eq!(read(&selector, DuplicateKeys::First).unwrap(), json_to_clove(json!({"b": {"x": 1}})));
+    let selector = PathSelector::from_expr(&parse_expr("$[c]")).unwrap();
+    assert!(read(&selector, DuplicateKeys::Error).is_err());
+
+    let documents = Value::from_reader_all_with(r#"{"a": 1} {"a": 1, "a": 2}"#.as_bytes(), DuplicateKeys::First);
+    assert_eq!(documents.unwrap()[1], json_to_clove(json!({"a": 1})));
+    assert_eq!("error".parse(), Ok(DuplicateKeys::Error));
+    assert!("newest".parse::<DuplicateKeys>().is_err());
+}
+
+#[test]
+fn test_path_selector_only_for_pure_paths() {
+    assert!(PathSelector::from_expr(&parse_expr("$[users][0][name]")).is_some());
+    assert!(PathSelector::from_expr(&parse_expr("$[users][0][name][?]")).is_some());
+    assert!(PathSelector::from_expr(&parse_expr("$")).unwrap().is_whole_document());
+    assert!(PathSelector::from_expr(&parse_expr("$[users].count()")).is_some());
+    assert!(PathSelector::from_expr(&parse_expr("$[a] == 1")).is_some());
+    assert!(PathSelector::from_expr(&parse_expr("$[users][$[i]]")).is_some());
+    assert!(PathSelector::from_expr(&parse_expr("$.keys()")).unwrap().is_whole_document());
+    assert!(PathSelector::from_expr(&parse_expr("$[users].map(@[name])")).is_none());
+    assert!(PathSelector::from_expr(&parse_expr("&twice[$[a]]")).is_none());
+
+    assert!(PathSelector::from_query(&parse_query("$ | ?($[a]) | !($[b][c])")).is_some());
+    assert!(PathSelector::from_query(&parse_query("$ | @n := $[a] | $[b][@n]")).is_some());
+    assert!(PathSelector::from_query(&parse_query("$ | ?($[a])")).unwrap().is_whole_document());
+    assert!(PathSelector::from_query(&parse_query("$ | ~($[a] := 1) | !($[a])")).is_none());
+    assert!(PathSelector::from_query(&parse_query("$ | -($[a]) | !($[b])")).is_none());
+    assert!(PathSelector::from_query(&parse_query("$ | $[a] | !($[b])")).is_none());
+}
+
+#[test]
+fn test_pruned_document_gives_same_results() {
+    let text = sample().to_string();
+    let full = json_to_clove(sample());
+    let paths = [
+        "$[users][0][name]",
+        "$[users][1][tags]",
+        "$[users][-1][name]",
+        "$[users][1.0][name]",
+        "$[users][-1.0][tags]",
+        "$[users][5][name]",
+        "$[users][0][tags][?]",
+        "$[metrics][0]",
+        "$[metrics][0.0]",
+        "$[metrics][1.5]",
+        "$[metrics][true]",
+        "$[missing][deeper]",
+        "$[big]",
+        "$[ratio]",
+        "$[empty]",
+    ];
+    for path in paths {
+        let expr = parse_expr(path);
+        let selector = PathSelector::from_expr(&expr).unwrap();
+        let pruned = Value::from_reader_selecting(text.as_bytes(), &selector).unwrap();
+
+        let expected = Evaluator::new().eval_expression(&expr, full.clone());
+        let actual = Evaluator::new().eval_expression(&expr, pruned);
+        assert_eq!(actual.ok(), expected.ok(), "{}", path);
+    }
+}
+
+#[test]
+fn test_pruned_document_drops_unselected_data() {
+    let expr = parse_expr("$[users][1][name]");
+    let selector = PathSelector::from_expr(&expr).unwrap();
+    let pruned = Value::from_reader_selecting(sample().to_string().as_bytes(), &selector).unwrap();
+
+    let expected = json_to_clove(json!({"users": [null, {"name": "Bob"}]}));
+    assert_eq!(pruned, expected);
+}
+
+#[test]
+fn test_pruned_document_gives_same_query_results() {
+    let text = sample().to_string();
+    let full = json_to_clove(sample());
+    let queries = [
+        "$ | !({\"first\": $[users][0][name], \"last\": $[users][-1][tags]})",
+        "$ | ?($[ratio] < 1) | !($[users][1][name] + \"!\")",
+        "$ | ?($[ratio] > 1) | !($[users][1][name])",
+        "$ | @m := $[metrics] | !([@m[0], $[users].count(), $[users][0][tags][?]])",
+        "$ | @i ::= $[metrics][0] | !($[users][@i - 9][name])",
+        "$ | ?($[users][0][tags].contains(\"admin\")) | $[big]",
+        "$ | !($[users][0] == $[users][1][name] ?? $[empty])",
+        "$ | !($meta ?? $[users][0][name].upper())",
+        "$ | !($[users][0.5 + 0.5][name])",
+    ];
+    for query in queries {
+        // Optimized, as the CLI runs it, so constant keys are folded
+        let query_ast = parse_query(query).optimize();
+        let selector = PathSelector::from_query(&query_ast).unwrap();
+        assert!(!selector.is_whole_document(), "{}", query);
+        let pruned = Value::from_reader_selecting(text.as_bytes(), &selector).unwrap();
+
+        let expected = Evaluator::new().eval_query_outcome(&query_ast, full.clone(), Default::default());
+        let actual = Evaluator::new().eval_query_outcome(&query_ast, pruned, Default::default());
+        assert_eq!(actual.ok(), expected.ok(), "{}", query);
+    }
+}
+
+#[test]
+fn test_pruned_document_keeps_every_selected_path() {
+    let query = parse_query("$ | ?($[ratio] < 1) | !([$[users][1][name], $[users][0][tags], $[metrics][1.5]])");
+    let selector = PathSelector::from_query(&query).unwrap();
+    let pruned = Value::from_reader_selecting(sample().to_string().as_bytes(), &selector).unwrap();
+
+    let expected = json_to_clove(json!({
+        "ratio": 0.25,
+        "users": [{"tags": ["admin"]}, {"name": "Bob"}],
+        "metrics": {"1.5": 20}
+    }));
+    assert_eq!(pruned, expected);
+}
+
+#[test]
+fn test_pruned_document_keeps_type_errors() {
+    let text = sample().to_string();
+    for path in ["$[users][name]", "$[ratio][0]"] {
+        let expr = parse_expr(path);
+        let selector = PathSelector::from_expr(&expr).unwrap();
+        let pruned = Value::from_reader_selecting(text.as_bytes(), &selector).unwrap();
+        assert!(Evaluator::new().eval_expression(&expr, pruned).is_err(), "{}", path);
+    }
+}
+
+#[test]
+fn test_limited_reader() {
+    let mut within = LimitedReader::new("12345".as_bytes(), 5);
+    let mut buf = String::new();
+    within.read_to_string(&mut buf).unwrap();
+    assert_eq!(buf, "12345");
+    assert!(!within.exceeded());
+
+    let mut over = LimitedReader::new("123456".as_bytes(), 5);
+    assert!(over.read_to_string(&mut String::new()).is_err());
+    assert!(over.exceeded());
+    assert_eq!(over.limit(), 5);
+}
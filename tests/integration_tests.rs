@@ -1,4 +1,5 @@
-use clove_lang::{evaluator::Evaluator, lexer::Lexer, output::to_json_pretty, parser::Parser, value::Value};
+use clove_lang::{EnvPolicy, EvalError, NonFinitePolicy, OverflowPolicy, QueryOutcome, evaluator::Evaluator, lexer::Lexer, output::{EnvError, EnvPrinter, JsonWriter, NumberLexemes, OutputFormat, to_clove, to_json, to_json_pretty}, parser::Parser, RegexCache, value::Value};
+use std::cmp::Ordering;
 use std::collections::HashMap;
 
 
@@ -23,11 +24,7 @@ fn eval_query(query_str: &str, doc: Value) -> Result<Value, String> {
 }
 
 fn json_object(pairs: Vec<(&str, Value)>) -> Value {
-    let mut map = HashMap::new();
-    for (k, v) in pairs {
-        map.insert(k.to_string(), v);
-    }
-    Value::Object(map)
+    Value::object(pairs)
 }
 
 fn json_array(values: Vec<Value>) -> Value {
@@ -103,6 +100,54 @@ fn test_comparison() {
     assert_eq!(result, Value::Boolean(true));
 }
 
+#[test]
+fn test_numeric_equality_across_types() {
+    let doc = json_object(vec![
+        ("price", Value::Float(1.0)),
+        ("sizes", json_array(vec![Value::Float(1.0), Value::Float(2.5)])),
+    ]);
+
+    assert_eq!(eval_expr("$[price] == 1", doc.clone()).unwrap(), Value::Boolean(true));
+    assert_eq!(eval_expr("$[price] != 1", doc.clone()).unwrap(), Value::Boolean(false));
+    assert_eq!(eval_expr("$[sizes] == [1, 2.5]", doc.clone()).unwrap(), Value::Boolean(true));
+    assert_eq!(eval_expr("$[sizes].filter(@ == 1)", doc.clone()).unwrap(), json_array(vec![Value::Float(1.0)]));
+    assert_eq!(eval_expr("$[sizes].contains(1)", doc.clone()).unwrap(), Value::Boolean(true));
+    assert_eq!(eval_expr("$[price] == 1.000001", doc.clone()).unwrap(), Value::Boolean(false));
+    assert_eq!(eval_expr(r#"$[price] == "1""#, doc).unwrap(), Value::Boolean(false));
+
+    // No rounding through f64: 2^53 + 1 is not the float 2^53
+    assert_eq!(eval_expr("9007199254740993 == 9007199254740992.0", Value::Null).unwrap(), Value::Boolean(false));
+}
+
+#[test]
+fn test_number_literal_forms() {
+    assert_eq!(eval_expr("1.5e-3 * 1e3", Value::Null).unwrap(), Value::Float(1.5));
+    assert_eq!(eval_expr("0xFF + 0o10", Value::Null).unwrap(), Value::Integer(263));
+    assert_eq!(eval_expr("$[n] == 1e6", json_object(vec![("n", Value::Integer(1_000_000))])).unwrap(), Value::Boolean(true));
+    assert!(Parser::new(Lexer::new("1e")).and_then(|mut p| p.parse()).is_err());
+}
+
+#[test]
+fn test_approximate_equality() {
+    let doc = json_object(vec![
+        ("total", Value::Float(0.1 + 0.2)),
+        ("status", Value::String("Ready".into())),
+        ("tags", json_array(vec![Value::String("A".into()), Value::Float(1.0000000001)])),
+    ]);
+
+    assert_eq!(eval_expr("$[total] == 0.3", doc.clone()).unwrap(), Value::Boolean(false));
+    assert_eq!(eval_expr("$[total] ~= 0.3", doc.clone()).unwrap(), Value::Boolean(true));
+    assert_eq!(eval_expr("$[total] ~= 0.31", doc.clone()).unwrap(), Value::Boolean(false));
+    assert_eq!(eval_expr(r#"$[status] ~= "READY""#, doc.clone()).unwrap(), Value::Boolean(true));
+    assert_eq!(eval_expr(r#"$[status] ~= "ready!""#, doc.clone()).unwrap(), Value::Boolean(false));
+    assert_eq!(eval_expr(r#"$[tags] ~= ["a", 1]"#, doc.clone()).unwrap(), Value::Boolean(true));
+    assert_eq!(eval_expr(r#"$[status] ~= 1"#, doc.clone()).unwrap(), Value::Boolean(false));
+
+    // `~=` does not start a transform
+    let result = eval_query(r#"$ | ?($[status] ~= "ready") | ~($[ok] := true)"#, doc).unwrap();
+    assert!(matches!(result, Value::Object(map) if map["ok"] == Value::Boolean(true)));
+}
+
 #[test]
 fn test_logical_and() {
     let doc = json_object(vec![
@@ -135,6 +180,29 @@ fn test_filter_rejects_record() {
     assert_eq!(result, Value::Null); // Filtered out
 }
 
+#[test]
+fn test_filter_rejection_ends_pipeline() {
+    let doc = json_object(vec![("status", Value::String("inactive".into()))]);
+    let query = Parser::new(Lexer::new(r#"$ | ?($[status] == "active") | ~($[seen] := true) | !($[seen])"#))
+        .unwrap()
+        .parse_query()
+        .unwrap();
+    let mut evaluator = Evaluator::new();
+
+    // The transform and output would fail on null; they are skipped instead
+    assert_eq!(
+        evaluator.eval_query_outcome(&query, doc.clone(), HashMap::new()).unwrap(),
+        QueryOutcome::FilteredOut
+    );
+    assert_eq!(evaluator.eval_query(&query, doc).unwrap(), Value::Null);
+
+    let active = json_object(vec![("status", Value::String("active".into()))]);
+    assert_eq!(
+        evaluator.eval_query_outcome(&query, active, HashMap::new()).unwrap(),
+        QueryOutcome::Value(Value::Boolean(true))
+    );
+}
+
 #[test]
 fn test_scope_reference() {
     let doc = json_object(vec![
@@ -147,6 +215,35 @@ fn test_scope_reference() {
     assert_eq!(result, Value::Integer(100));
 }
 
+#[test]
+fn test_live_scope_sees_later_transforms() {
+    let doc = json_object(vec![
+        ("items", json_array(vec![Value::Integer(1), Value::Integer(2)])),
+    ]);
+
+    let result = eval_query(
+        r#"$ | @snap := $[items] | @live ::= $[items] | ~($[items] := @ * 10) | !({"snap": @snap, "live": @live.sum()})"#,
+        doc,
+    )
+    .unwrap();
+    assert_eq!(result, json_object(vec![
+        ("snap", json_array(vec![Value::Integer(1), Value::Integer(2)])),
+        ("live", Value::Integer(30)),
+    ]));
+}
+
+#[test]
+fn test_live_scope_rebinding_and_cycles() {
+    let doc = json_object(vec![("x", Value::Integer(1))]);
+
+    // Re-binding with := replaces a live scope with a snapshot
+    let result = eval_query("$ | @a ::= $[x] | @a := $[x] + 1 | ~($[x] := 9) | !(@a)", doc.clone()).unwrap();
+    assert_eq!(result, Value::Integer(2));
+
+    let err = eval_query("$ | @a ::= @b | @b ::= @a | !(@a)", doc).unwrap_err();
+    assert!(err.contains("Live scope @a refers to itself"), "{}", err);
+}
+
 #[test]
 fn test_existence_check_true() {
     let doc = json_object(vec![
@@ -196,6 +293,92 @@ fn test_output_object_literal() {
     }
 }
 
+#[test]
+fn test_named_outputs() {
+    let doc = json_object(vec![
+        ("items", json_array(vec![Value::Integer(1), Value::Integer(2)])),
+        ("owner", Value::String("Bob".into())),
+    ]);
+
+    let result = eval_query("$ | ~($[items] := @ * 10) | !(items: $[items]) !(total: $[items].sum())", doc).unwrap();
+    assert_eq!(result, json_object(vec![
+        ("items", json_array(vec![Value::Integer(10), Value::Integer(20)])),
+        ("total", Value::Integer(30)),
+    ]));
+}
+
+#[test]
+fn test_meta_access() {
+    let query = Parser::new(Lexer::new("$ | ~($[source] := $meta[file]) | !({\"doc\": $, \"missing\": $meta[line]})"))
+        .unwrap()
+        .parse_query()
+        .unwrap();
+    let meta = HashMap::from([("file".to_string(), Value::String("a.json".into()))]);
+
+    let result = Evaluator::new().eval_query_with_meta(&query, json_object(vec![]), meta).unwrap();
+    assert_eq!(result, json_object(vec![
+        ("doc", json_object(vec![("source", Value::String("a.json".into()))])),
+        ("missing", Value::Null),
+    ]));
+
+    // Without metadata, $meta is an empty object
+    assert_eq!(eval_expr("$meta", Value::Null).unwrap(), json_object(vec![]));
+}
+
+#[test]
+fn test_original_document() {
+    let doc = clove_lang::clove_value!({"user": {"name": "Ann"}, "tenant": "acme", "items": [1, 2], "rate": 10});
+
+    assert_eq!(
+        eval_query("$ | $[user] | !({\"name\": $[name], \"tenant\": $$[tenant]})", doc.clone()).unwrap(),
+        clove_lang::clove_value!({"name": "Ann", "tenant": "acme"})
+    );
+    assert_eq!(
+        eval_query("$ | -($[rate]) | $[items] | !($.map(@ * $$[rate]))", doc.clone()).unwrap(),
+        clove_lang::clove_value!([10, 20])
+    );
+
+    // Inside when blocks, foreach bodies, UDFs and live scopes too
+    let query = "&rate:0 := $$[rate]\n$ | @rate ::= $$[rate] | ~($[rate] := 0) \
+        | when ($$[rate] > 1) { ~($[tenant] := $$[tenant] + \"!\") } \
+        | foreach $[items] as @item { @item * $$[rate] } | !([$[tenant], $[items], &rate[], @rate])";
+    assert_eq!(eval_query(query, doc.clone()).unwrap(), clove_lang::clove_value!(["acme!", [10, 20], 10, 10]));
+
+    // Without stages, $$ is $
+    assert_eq!(eval_expr("$$[tenant]", doc.clone()).unwrap(), Value::String("acme".into()));
+    assert_eq!(eval_query("$ | !($$)", doc.clone()).unwrap(), doc);
+}
+
+#[test]
+fn test_query_params() {
+    let params = HashMap::from([
+        ("role".to_string(), Value::String("admin".into())),
+        ("limit".to_string(), Value::Integer(1)),
+    ]);
+    let doc = clove_lang::clove_value!({"users": [
+        {"name": "Ann", "role": "admin"}, {"name": "Bo", "role": "user"}, {"name": "Cy", "role": "admin"}
+    ]});
+    let query = Parser::new(Lexer::new(
+        "&admin:1 := @1[role] == @role\n\
+         $ | ?(@limit > 0) | !($[users].filter(&admin[@]).take(@limit).map(@[name]))",
+    ))
+    .unwrap()
+    .parse_query()
+    .unwrap();
+    let mut evaluator = Evaluator::new().with_params(params.clone());
+    assert_eq!(evaluator.eval_query(&query, doc.clone()).unwrap(), clove_lang::clove_value!(["Ann"]));
+
+    // A scope the query defines replaces the parameter
+    let query = Parser::new(Lexer::new("$ | @limit := 2 | !(@limit)")).unwrap().parse_query().unwrap();
+    let mut evaluator = Evaluator::new().with_params(params);
+    assert_eq!(evaluator.eval_query(&query, doc).unwrap(), Value::Integer(2));
+
+    let expr = Parser::new(Lexer::new("@rol")).unwrap().parse().unwrap();
+    let params = HashMap::from([("role".to_string(), Value::Null)]);
+    let error = Evaluator::new().with_params(params).eval_expression(&expr, Value::Null).unwrap_err();
+    assert!(error.to_string().contains("did you mean @role?"), "{}", error);
+}
+
 #[test]
 fn test_env_var() {
     unsafe {
@@ -208,6 +391,80 @@ fn test_env_var() {
     assert_eq!(result, Value::String("test_value".into()));
 }
 
+fn eval_with_env(expr_str: &str, policy: EnvPolicy) -> Result<Value, String> {
+    let lexer = Lexer::new(expr_str);
+    let mut parser = Parser::new(lexer).unwrap();
+    let expr = parser.parse().unwrap();
+
+    let mut evaluator = Evaluator::new().with_env_policy(policy);
+    evaluator.eval_expression(&expr, Value::Null)
+        .map_err(|e| e.to_string())
+}
+
+fn env_map(pairs: &[(&str, &str)]) -> EnvPolicy {
+    EnvPolicy::Map(pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect())
+}
+
+#[test]
+fn test_env_keys_in_transform_targets() {
+    let query = Parser::new(Lexer::new("$ | ~($[$FIELD] := 1) | -($[limits][$LIMIT])")).unwrap().parse_query().unwrap();
+    let doc = clove_lang::clove_value!({"a": 0, "b": 0, "limits": {"max": 5, "min": 1}});
+    let mut evaluator = Evaluator::new().with_env_policy(env_map(&[("FIELD", "b"), ("LIMIT", "max")]));
+    let result = evaluator.eval_query(&query, doc.clone()).unwrap();
+    assert_eq!(result, clove_lang::clove_value!({"a": 0, "b": 1, "limits": {"min": 1}}));
+
+    let mut evaluator = Evaluator::new().with_env_policy(env_map(&[("FIELD", "b")]));
+    let err = evaluator.eval_query(&query, doc).unwrap_err().to_string();
+    assert!(err.contains("LIMIT"), "{}", err);
+}
+
+#[test]
+fn test_env_policy_deny_all() {
+    unsafe {
+        std::env::set_var("TEST_DENIED_VAR", "secret");
+    }
+
+    let err = eval_with_env("$TEST_DENIED_VAR", EnvPolicy::DenyAll).unwrap_err();
+    assert!(err.contains("not allowed"), "got: {}", err);
+}
+
+#[test]
+fn test_env_policy_allow_list() {
+    unsafe {
+        std::env::set_var("TEST_ALLOWED_VAR", "ok");
+        std::env::set_var("TEST_UNLISTED_VAR", "secret");
+    }
+
+    let policy = EnvPolicy::AllowList(["TEST_ALLOWED_VAR".to_string()].into_iter().collect());
+    assert_eq!(eval_with_env("$TEST_ALLOWED_VAR", policy.clone()).unwrap(), Value::String("ok".into()));
+    assert!(eval_with_env("$TEST_UNLISTED_VAR", policy).unwrap_err().contains("not allowed"));
+}
+
+#[test]
+fn test_env_policy_map_replaces_process_env() {
+    unsafe {
+        std::env::set_var("TEST_SHADOWED_VAR", "process");
+    }
+
+    let policy = env_map(&[("TEST_SHADOWED_VAR", "map")]);
+    assert_eq!(eval_with_env("$TEST_SHADOWED_VAR", policy.clone()).unwrap(), Value::String("map".into()));
+    assert!(eval_with_env("$PATH", policy).unwrap_err().contains("Undefined environment variable"));
+}
+
+#[test]
+fn test_typed_env_var() {
+    let policy = env_map(&[("PORT", "8080"), ("RATIO", "0.5"), ("DEBUG", "Yes"), ("NAME", " x ")]);
+
+    assert_eq!(eval_with_env("$PORT:int", policy.clone()).unwrap(), Value::Integer(8080));
+    assert_eq!(eval_with_env("$PORT:int + 1", policy.clone()).unwrap(), Value::Integer(8081));
+    assert_eq!(eval_with_env("$RATIO:float", policy.clone()).unwrap(), Value::Float(0.5));
+    assert_eq!(eval_with_env("$DEBUG:bool", policy.clone()).unwrap(), Value::Boolean(true));
+    assert_eq!(eval_with_env("$NAME:string", policy.clone()).unwrap(), Value::String(" x ".into()));
+
+    let err = eval_with_env("$RATIO:int", policy).unwrap_err();
+    assert!(err.contains("is not a valid int"), "got: {}", err);
+}
+
 #[test]
 fn test_complex_real_query() {
     let doc = json_object(vec![
@@ -259,6 +516,286 @@ fn test_json_output() {
     // }
 }
 
+#[test]
+fn test_json_output_escapes_keys() {
+    let doc = json_object(vec![("a\"b\\c\u{0}\u{7f}\n", Value::Float(f64::NAN))]);
+
+    assert_eq!(to_json(&doc), r#"{"a\"b\\c\u0000\u007f\n":null}"#);
+    assert_eq!(JsonWriter::new().canonical(true).write(&doc), "{\"a\\\"b\\\\c\\u0000\u{7f}\\n\":null}");
+}
+
+#[test]
+fn test_raw_output() {
+    let raw = JsonWriter::new().raw(true);
+
+    assert_eq!(raw.write(&Value::String("tab\there \"quoted\"".into())), "tab\there \"quoted\"");
+    assert_eq!(
+        raw.clone().trailing_newline(true).write(&json_array(vec![Value::String("a".into()), Value::String("b".into())])),
+        "a\nb\n"
+    );
+    // Anything else stays JSON
+    assert_eq!(raw.write(&json_array(vec![Value::String("a".into()), Value::Integer(1)])), r#"["a",1]"#);
+    assert_eq!(raw.write(&json_array(vec![])), "[]");
+    assert_eq!(raw.write(&json_object(vec![("k", Value::String("v".into()))])), r#"{"k":"v"}"#);
+}
+
+#[test]
+fn test_colored_json_output() {
+    let doc = json_object(vec![
+        ("name", Value::String("Test".into())),
+        ("ok", Value::Boolean(true)),
+        ("n", Value::Integer(1)),
+        ("none", Value::Null),
+    ]);
+
+    let colored = OutputFormat::Color.formatter(false).format(&doc);
+    assert!(colored.contains("\x1b[1;34m\"name\"\x1b[0m"));
+    assert!(colored.contains("\x1b[32m\"Test\"\x1b[0m"));
+    assert!(colored.contains("\x1b[33mtrue\x1b[0m"));
+
+    // Stripping the escapes gives the plain JSON
+    let plain = regex::Regex::new("\x1b\\[[0-9;]*m").unwrap().replace_all(&colored, "");
+    assert_eq!(plain, OutputFormat::Json.formatter(false).format(&doc));
+}
+
+#[test]
+fn test_table_output() {
+    let rows = json_array(vec![
+        json_object(vec![
+            ("name", Value::String("Alice".into())),
+            ("age", Value::Integer(30)),
+        ]),
+        json_object(vec![
+            ("name", Value::String("Bob".into())),
+            ("age", Value::Integer(5)),
+            ("tags", json_array(vec![Value::String("x".into())])),
+        ]),
+    ]);
+
+    let table = OutputFormat::Table.formatter(false).format(&rows);
+    let expected = [
+        "age  name   tags",
+        "---  -----  -----",
+        " 30  Alice",
+        "  5  Bob    [\"x\"]",
+    ];
+    assert_eq!(table, expected.join("\n"));
+}
+
+#[test]
+fn test_table_output_falls_back_to_json() {
+    let formatter = OutputFormat::Table.formatter(false);
+    let scalars = json_array(vec![Value::Integer(1), Value::Integer(2)]);
+    assert_eq!(formatter.format(&scalars), "[1,2]");
+    assert_eq!(formatter.format(&Value::Integer(7)), "7");
+    assert_eq!(formatter.format(&json_array(vec![])), "[]");
+}
+
+#[test]
+fn test_env_output() {
+    let vars = clove_lang::clove_value!({
+        "HOST": "db.local", "PORT": 5432, "RATE": 0.5, "DEBUG": false, "UNSET": null,
+        "MOTD": "it's $HOME\nbye", "URL": "https://x.test/a?b=1"
+    });
+    let expected = [
+        "DEBUG=false",
+        "HOST=db.local",
+        "MOTD='it'\\''s $HOME\nbye'",
+        "PORT=5432",
+        "RATE=0.5",
+        "UNSET=''",
+        "URL='https://x.test/a?b=1'",
+    ];
+    assert_eq!(EnvPrinter::new().print(&vars).unwrap(), expected.join("\n"));
+    let exported = EnvPrinter::new().export(true).print(&clove_lang::clove_value!({"_A1": "x"}));
+    assert_eq!(exported.unwrap(), "export _A1=x");
+
+    let printer = EnvPrinter::new();
+    assert_eq!(printer.print(&ints(&[1])), Err(EnvError::NotAnObject("array")));
+    assert_eq!(
+        printer.print(&clove_lang::clove_value!({"1X": 1})),
+        Err(EnvError::InvalidName("1X".to_string()))
+    );
+    let nested = printer.print(&clove_lang::clove_value!({"DB": {"host": "x"}})).unwrap_err();
+    assert_eq!(nested, EnvError::Nested { key: "DB".to_string(), type_name: "object" });
+    assert!(nested.to_string().contains("'DB' is an object"), "{}", nested);
+}
+
+#[test]
+fn test_preserved_numbers() {
+    let input = r#"{"price": 1.50, "big": 123456789012345678901, "exp": 2E3, "n": 3, "note": "1.50", "same": [1.0, 1.00]}"#;
+    let numbers = std::sync::Arc::new(NumberLexemes::scan(input));
+    let writer = JsonWriter::new().number_lexemes(numbers);
+    let run = |query: &str| {
+        let query = Parser::new(Lexer::new(query)).unwrap().parse_query().unwrap();
+        writer.write(&Evaluator::new().eval_query(&query, Value::from_reader(input.as_bytes()).unwrap()).unwrap())
+    };
+
+    // Untouched numbers keep their text; computed or moved ones don't
+    assert_eq!(
+        run("$ | ~($[n] := $[n] * 2) | ~($[copy] := $[price])"),
+        r#"{"big":123456789012345678901,"copy":1.5,"exp":2E3,"n":6,"note":"1.50","price":1.50,"same":[1.0,1.00]}"#
+    );
+    assert_eq!(
+        run("$ | ~($[exp] := 1.5) | ~($[same][0] := 2000.0)"),
+        r#"{"big":123456789012345678901,"exp":1.5,"n":3,"note":"1.50","price":1.50,"same":[2000.0,1.00]}"#
+    );
+    assert_eq!(run("$ | !($[price])"), "1.5");
+
+    // Equal numbers elsewhere in the input don't borrow each other's text
+    let input = r#"{"a": 1.50, "b": 1.5, "c": [10000000000000000001, 10000000000000000002]}"#;
+    let writer = JsonWriter::new().number_lexemes(std::sync::Arc::new(NumberLexemes::scan(input)));
+    let doc = Value::from_reader(input.as_bytes()).unwrap();
+    assert_eq!(writer.write(&doc), r#"{"a":1.50,"b":1.5,"c":[10000000000000000001,10000000000000000002]}"#);
+
+    // Several documents line up with the array --slurp reads
+    let numbers = NumberLexemes::scan("{\"a\": 1.0}\n{\"a\": 1e2}\n");
+    let writer = JsonWriter::new().number_lexemes(std::sync::Arc::new(numbers));
+    assert_eq!(writer.write(&clove_lang::clove_value!([{"a": 1.0}, {"a": 100.0}])), r#"[{"a":1.0},{"a":1e2}]"#);
+
+    // Canonical output stays canonical
+    let docs = clove_lang::clove_value!([{"a": 1.0}, {"a": 100.0}]);
+    assert_eq!(writer.canonical(true).write(&docs), r#"[{"a":1},{"a":100}]"#);
+    assert!(NumberLexemes::scan(r#"{"a": [1, -2, 3.5], "b": "0.10", "c": {"1.50": true}}"#).is_empty());
+    assert!(NumberLexemes::scan(r#"{"a": [1.50"#).is_empty());
+}
+
+#[test]
+fn test_json_writer_options() {
+    let doc = json_object(vec![
+        ("name", Value::String("café ☕ 😀".into())),
+        ("ratio", Value::Float(2.0)),
+    ]);
+
+    assert_eq!(JsonWriter::new().write(&doc), r#"{"name":"café ☕ 😀","ratio":2.0}"#);
+    assert_eq!(
+        JsonWriter::new().ascii_only(true).write(&doc),
+        r#"{"name":"caf\u00e9 \u2615 \ud83d\ude00","ratio":2.0}"#
+    );
+    assert_eq!(
+        JsonWriter::new().indent(3).trailing_newline(true).write(&doc),
+        "{\n   \"name\": \"café ☕ 😀\",\n   \"ratio\": 2.0\n}\n"
+    );
+    assert_eq!(JsonWriter::new().pretty(true).write(&doc), to_json_pretty(&doc));
+}
+
+#[test]
+fn test_json_writer_streams_in_chunks() {
+    /// Records the size of every write
+    struct Writes(Vec<usize>, Vec<u8>);
+    impl std::io::Write for Writes {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.push(buf.len());
+            self.1.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    let rows = (0..20_000)
+        .map(|i| {
+            let tags = json_array(vec![Value::String("é\n".into())]);
+            json_object(vec![("id", Value::Integer(i)), ("tags", tags)])
+        })
+        .collect();
+    let doc = json_object(vec![("rows", json_array(rows)), ("empty", json_array(vec![]))]);
+    let writers = [
+        JsonWriter::new(),
+        JsonWriter::new().pretty(true).trailing_newline(true),
+        JsonWriter::new().ascii_only(true).colored(true),
+        JsonWriter::new().canonical(true),
+    ];
+    for writer in writers {
+        let mut out = Writes(vec![], vec![]);
+        writer.write_to(&mut out, &doc).unwrap();
+        assert_eq!(String::from_utf8(out.1).unwrap(), writer.write(&doc));
+        assert!(out.0.len() > 1, "written at once");
+        assert!(out.0.iter().all(|&len| len < 2 * clove_lang::output::CHUNK_SIZE), "{:?}", out.0);
+    }
+
+    // Raw output of strings is written whole
+    let mut out = Vec::new();
+    JsonWriter::new().raw(true).write_to(&mut out, &Value::String("a\tb".into())).unwrap();
+    assert_eq!(out, b"a\tb");
+}
+
+#[test]
+fn test_json_writer_canonical() {
+    let canonical = |value: &Value| JsonWriter::new().canonical(true).pretty(true).write(value);
+
+    let numbers = [
+        (Value::Float(1.0), "1"),
+        (Value::Float(-0.0), "0"),
+        (Value::Float(0.000001), "0.000001"),
+        (Value::Float(1e-7), "1e-7"),
+        (Value::Float(123.456), "123.456"),
+        (Value::Float(1e20), "100000000000000000000"),
+        (Value::Float(1e21), "1e+21"),
+        (Value::Float(-1.5e300), "-1.5e+300"),
+        (Value::Integer(-42), "-42"),
+    ];
+    for (value, expected) in numbers {
+        assert_eq!(canonical(&value), expected, "{:?}", value);
+    }
+
+    // Keys sort by UTF-16 code units, so U+10000 (a surrogate pair) comes before U+FFFF
+    let doc = json_object(vec![
+        ("\u{ffff}", Value::Integer(1)),
+        ("\u{10000}", Value::Integer(2)),
+        ("b", Value::String("tab\there\u{1}é".into())),
+        ("a", json_array(vec![Value::Null, Value::Boolean(true)])),
+    ]);
+    assert_eq!(
+        canonical(&doc),
+        "{\"a\":[null,true],\"b\":\"tab\\there\\u0001é\",\"\u{10000}\":2,\"\u{ffff}\":1}"
+    );
+}
+
+#[test]
+fn test_to_clove_round_trips() {
+    let doc = clove_lang::clove_value!({
+        "name": "it's \"quoted\" \\ ok",
+        "lines": "a\nb\tc\r\u{1}é",
+        "first name": "A",
+        "_id": 7,
+        "true": true,
+        "and": null,
+        "2nd": [],
+        "": {},
+        "numbers": [0, -3, 1.0, -1.0, -2.5, 1e300, -1e-300, -0.0, i64::MAX, i64::MIN],
+        "nested": {"deep": [{"x": [[]]}]},
+    });
+    let mut doc = doc;
+    if let Value::Object(obj) = &mut doc {
+        obj.insert("bytes".into(), Value::Bytes(vec![0, 159, 255]));
+        obj.insert("inf".into(), Value::Array(vec![Value::Float(f64::INFINITY), Value::Float(f64::NEG_INFINITY)]));
+    }
+
+    for text in [to_clove(&doc), OutputFormat::Clove.formatter(true).format(&doc)] {
+        assert_eq!(eval_expr(&text, Value::Null).unwrap(), doc, "{}", text);
+    }
+    let nan = eval_expr(&to_clove(&Value::Float(f64::NAN)), Value::Null).unwrap();
+    assert!(matches!(nan, Value::Float(n) if n.is_nan()));
+
+    // Integers and floats stay distinct
+    assert_eq!(to_clove(&clove_lang::clove_value!([1, 1.0, -0.0])), "[1, 1.0, -0.0]");
+    assert_eq!(
+        to_clove(&clove_lang::clove_value!({"a b": "x", "or": 1, "ok_1": "y'z"})),
+        r"{'a b': 'x', ok_1: 'y\'z', 'or': 1}"
+    );
+    assert_eq!(to_clove(&Value::Bytes(b"hi".to_vec())), "'aGk='.base64_decode()");
+}
+
+#[test]
+fn test_output_format_names() {
+    for format in [OutputFormat::Json, OutputFormat::Color, OutputFormat::Table, OutputFormat::Clove] {
+        assert_eq!(format.to_string().parse::<OutputFormat>(), Ok(format));
+    }
+    assert!("xml".parse::<OutputFormat>().is_err());
+}
+
 // ============================================================================
 // Transform Tests
 // ============================================================================
@@ -588,6 +1125,49 @@ fn test_transform_deeply_nested() {
 //     eval_query("$ | ~($[nonexistent] := 100)", doc).unwrap();
 // }
 
+#[test]
+fn test_transform_negative_index() {
+    let doc = json_object(vec![
+        ("items", json_array(vec![Value::Integer(1), Value::Integer(2), Value::Integer(3)])),
+    ]);
+
+    let result = eval_query("$ | ~($[items][-1] := 30) | ~($[items][-3] := 10)", doc).unwrap();
+    assert_eq!(
+        result,
+        json_object(vec![
+            ("items", json_array(vec![Value::Integer(10), Value::Integer(2), Value::Integer(30)])),
+        ])
+    );
+}
+
+#[test]
+fn test_transform_binds_element_below_index() {
+    let item = |name: &str, price: i64| {
+        json_object(vec![("name", Value::String(name.into())), ("price", Value::Integer(price))])
+    };
+    let doc = json_object(vec![("items", json_array(vec![item("a", 10), item("b", 20)]))]);
+
+    // `@` is the element the path passes through, so fields update relative to it
+    let result = eval_query("$ | ~($[items][0][price] := @[price] * 2)", doc.clone()).unwrap();
+    assert_eq!(result, json_object(vec![("items", json_array(vec![item("a", 20), item("b", 20)]))]));
+
+    let result = eval_query(r#"$ | ~($[items][-1][label] := @[name] + "!")"#, doc.clone()).unwrap();
+    let mut last = item("b", 20);
+    if let Value::Object(map) = &mut last {
+        map.insert("label".into(), Value::String("b!".into()));
+    }
+    assert_eq!(result, json_object(vec![("items", json_array(vec![item("a", 10), last]))]));
+
+    // Replacing the element itself binds `@` to it
+    let result = eval_query("$ | ~($[items][1] := @[price])", doc.clone()).unwrap();
+    assert_eq!(result, json_object(vec![("items", json_array(vec![item("a", 10), Value::Integer(20)]))]));
+
+    // Arrays below an index are still mapped item by item
+    let doc = json_object(vec![("rows", json_array(vec![ints(&[1, 2])]))]);
+    let result = eval_query("$ | ~($[rows][0] := @ * 10)", doc).unwrap();
+    assert_eq!(result, json_object(vec![("rows", json_array(vec![ints(&[10, 20])]))]));
+}
+
 #[test]
 #[should_panic(expected = "index out of bounds")]
 fn test_transform_array_out_of_bounds() {
@@ -598,6 +1178,12 @@ fn test_transform_array_out_of_bounds() {
     eval_query("$ | ~($[items][10] := 100)", doc).unwrap();
 }
 
+#[test]
+#[should_panic(expected = "Cannot transform the root document")]
+fn test_transform_root_is_an_error() {
+    eval_query("$ | ~($ := 1)", json_object(vec![])).unwrap();
+}
+
 #[test]
 #[should_panic(expected = "requires array")]
 fn test_transform_filter_on_non_array() {
@@ -608,6 +1194,26 @@ fn test_transform_filter_on_non_array() {
     eval_query("$ | ~($[value] := ?(@[x] > 5))", doc).unwrap();
 }
 
+#[test]
+fn test_transform_filter_object_entries() {
+    let doc = clove_lang::clove_value!({"config": {"host": "h", "port": null, "debug": false, "tmp_dir": "/tmp"}, "key": "k"});
+
+    let result = eval_query("$ | ~($[config] := ?(@ != null)) | !($[config])", doc.clone()).unwrap();
+    assert_eq!(result, clove_lang::clove_value!({"host": "h", "debug": false, "tmp_dir": "/tmp"}));
+
+    // `@key` is the entry's key, even inside a nested lambda
+    let result = eval_query(
+        r#"$ | ~($[config] := ?(@key.startswith("tmp_") == false and [1].all(@key != "host"))) | !($[config])"#,
+        doc.clone(),
+    )
+    .unwrap();
+    assert_eq!(result, clove_lang::clove_value!({"port": null, "debug": false}));
+
+    // Outside an object filter `@key` is an ordinary scope
+    let result = eval_query("$ | @key := $[key] | !(@key)", doc).unwrap();
+    assert_eq!(result, Value::String("k".into()));
+}
+
 #[test]
 fn test_debug_arithmetic_expression() {
     let doc = json_object(vec![
@@ -791,6 +1397,75 @@ fn test_method_map_transform() {
     ]));
 }
 
+#[test]
+fn test_method_flat_map() {
+    let doc = clove_lang::clove_value!({"orders": [
+        {"id": 1, "items": ["a", "b"]},
+        {"id": 2, "items": []},
+        {"id": 3, "items": "c"},
+        {"id": 4, "items": [["d"]]}
+    ]});
+
+    // Results are concatenated one level deep; non-arrays are single elements
+    let result = eval_expr("$[orders].flat_map(@[items])", doc.clone()).unwrap();
+    assert_eq!(result, clove_lang::clove_value!(["a", "b", "c", ["d"]]));
+    assert_eq!(
+        result,
+        eval_expr("$[orders].map(@[items]).flatten()", doc.clone()).unwrap()
+    );
+
+    let result = eval_expr("$[orders].flat_map([@[id], @[id] * 10])", doc.clone()).unwrap();
+    assert_eq!(result, clove_lang::clove_value!([1, 10, 2, 20, 3, 30, 4, 40]));
+
+    assert!(eval_expr("$[orders][0].flat_map(@)", doc.clone()).unwrap_err().contains(".flat_map() requires array"));
+    assert!(eval_expr("$[orders].flat_map()", doc).is_err());
+}
+
+#[test]
+fn test_bytes_methods() {
+    let doc = json_object(vec![
+        ("payload", Value::String("3q2+7w==".to_string())),
+        ("digest", Value::String("DEADBEEF".to_string())),
+    ]);
+    let bytes = Value::Bytes(vec![0xde, 0xad, 0xbe, 0xef]);
+
+    assert_eq!(eval_expr("$[payload].base64_decode()", doc.clone()).unwrap(), bytes);
+    assert_eq!(eval_expr("$[digest].hex_decode()", doc.clone()).unwrap(), bytes);
+    assert_eq!(
+        eval_expr("$[payload].base64_decode() == $[digest].hex_decode()", doc.clone()).unwrap(),
+        Value::Boolean(true)
+    );
+    assert_eq!(
+        eval_expr("$[payload].base64_decode().hex_encode()", doc.clone()).unwrap(),
+        Value::String("deadbeef".to_string())
+    );
+    assert_eq!(
+        eval_expr("$[digest].hex_decode().base64_encode()", doc.clone()).unwrap(),
+        Value::String("3q2+7w==".to_string())
+    );
+    assert_eq!(eval_expr("$[digest].hex_decode().bytes_len()", doc.clone()).unwrap(), Value::Integer(4));
+    assert_eq!(eval_expr("$[digest].hex_decode().length()", doc.clone()).unwrap(), Value::Integer(4));
+    assert_eq!(
+        eval_expr("$[digest].hex_decode().type()", doc.clone()).unwrap(),
+        Value::String("bytes".to_string())
+    );
+
+    // Strings encode as their UTF-8 bytes
+    assert_eq!(eval_expr("\"héllo\".bytes_len()", Value::Null).unwrap(), Value::Integer(6));
+    assert_eq!(eval_expr("\"hi\".hex_encode()", Value::Null).unwrap(), Value::String("6869".to_string()));
+
+    // Padding is optional and the URL-safe alphabet is accepted
+    assert_eq!(eval_expr("\"3q2-7w\".base64_decode()", Value::Null).unwrap(), bytes);
+
+    for expr in ["\"3q2\".hex_decode()", "\"zz\".hex_decode()", "\"a!b\".base64_decode()", "1.base64_encode()"] {
+        assert!(eval_expr(expr, Value::Null).is_err(), "{}", expr);
+    }
+
+    // JSON output writes bytes as base64
+    let query = "$ | ~($[payload] := $[payload].base64_decode())";
+    assert_eq!(to_json(&eval_query(query, doc.clone()).unwrap()), to_json(&doc));
+}
+
 #[test]
 fn test_method_count() {
     let doc = json_object(vec![
@@ -806,6 +1481,26 @@ fn test_method_count() {
     assert_eq!(result, Value::Integer(4));
 }
 
+#[test]
+fn test_method_count_with_predicate() {
+    let doc = clove_lang::clove_value!({"jobs": [
+        {"status": "failed"}, {"status": "ok"}, {"status": "failed"}, {}
+    ]});
+
+    assert_eq!(eval_expr("$[jobs].count(@[status] == \"failed\")", doc.clone()).unwrap(), Value::Integer(2));
+    assert_eq!(eval_expr("$[jobs].count(@[status] == \"late\")", doc.clone()).unwrap(), Value::Integer(0));
+    // Streamed after filter and map, and the predicate sees the enclosing item
+    assert_eq!(
+        eval_expr("$[jobs].map(@[status]).filter(@ != null).count(@ == \"ok\")", doc.clone()).unwrap(),
+        Value::Integer(1)
+    );
+    assert_eq!(
+        eval_expr("$[jobs].map($[jobs].count(@[status] == @@[status]))", doc.clone()).unwrap(),
+        Value::array([2, 1, 2, 1])
+    );
+    assert!(eval_expr("$[jobs].count(true, false)", doc).unwrap_err().contains("at most one"));
+}
+
 #[test]
 fn test_method_count_empty() {
     let doc = json_object(vec![
@@ -966,6 +1661,48 @@ fn test_method_unique_integers() {
     ]));
 }
 
+#[test]
+fn test_method_unique_containers() {
+    let result = eval_expr(
+        r#"[{"a": 1, "b": [2]}, {"b": [2], "a": 1}, [1, 2], [2, 1], [1, 2]].unique()"#,
+        Value::Null,
+    ).unwrap();
+    assert_eq!(result, json_array(vec![
+        json_object(vec![("a", Value::Integer(1)), ("b", json_array(vec![Value::Integer(2)]))]),
+        json_array(vec![Value::Integer(1), Value::Integer(2)]),
+        json_array(vec![Value::Integer(2), Value::Integer(1)]),
+    ]));
+}
+
+#[test]
+fn test_method_unique_large_array() {
+    let items: Vec<Value> = (0..100_000).map(|n| Value::Integer(n % 1000)).collect();
+    let doc = json_object(vec![("items", json_array(items))]);
+
+    let result = eval_expr("$[items].unique().count()", doc).unwrap();
+    assert_eq!(result, Value::Integer(1000));
+}
+
+#[test]
+fn test_method_unique_by_keeps_first_occurrence() {
+    let doc = json_object(vec![
+        ("items", json_array(vec![
+            json_object(vec![("id", Value::Integer(1)), ("v", Value::String("a".into()))]),
+            json_object(vec![("id", Value::Integer(2)), ("v", Value::String("b".into()))]),
+            json_object(vec![("id", Value::Integer(1)), ("v", Value::String("c".into()))]),
+        ])),
+    ]);
+
+    let result = eval_expr("$[items].unique_by(@[id]).map(@[v])", doc).unwrap();
+    assert_eq!(result, json_array(vec![Value::String("a".into()), Value::String("b".into())]));
+}
+
+#[test]
+fn test_error_unique_by_missing_argument() {
+    let result = eval_expr("[1, 2].unique_by()", Value::Null);
+    assert!(result.unwrap_err().contains("requires a key expression"));
+}
+
 #[test]
 fn test_method_sort_integers() {
     let doc = json_object(vec![
@@ -1033,71 +1770,868 @@ fn test_method_sort_by_field() {
     }
 }
 
-#[test]
-fn test_method_chaining() {
-    let doc = json_object(vec![
-        ("items", json_array(vec![
-            json_object(vec![("price", Value::Integer(50)), ("active", Value::Boolean(true))]),
-            json_object(vec![("price", Value::Integer(150)), ("active", Value::Boolean(false))]),
-            json_object(vec![("price", Value::Integer(200)), ("active", Value::Boolean(true))]),
-        ])),
+fn people() -> Value {
+    let person = |last: &str, first: &str, age: i64| json_object(vec![
+        ("last", Value::String(last.into())),
+        ("first", Value::String(first.into())),
+        ("age", Value::Integer(age)),
     ]);
-
-    // Filter active items, then sum their prices
-    let result = eval_expr("$[items].filter(@[active] == true).sum(@[price])", doc).unwrap();
-    assert_eq!(result, Value::Integer(250));
+    json_object(vec![
+        ("people", json_array(vec![
+            person("Smith", "John", 40),
+            person("Doe", "Jane", 30),
+            person("Smith", "Anna", 25),
+            person("Doe", "Adam", 30),
+        ])),
+    ])
 }
 
 #[test]
-fn test_method_in_query_pipeline() {
-    let doc = json_object(vec![
-        ("items", json_array(vec![
-            json_object(vec![("price", Value::Integer(50))]),
-            json_object(vec![("price", Value::Integer(150))]),
-        ])),
-    ]);
-
-    let result = eval_query("$ | ?($[items].any(@[price] > 100))", doc).unwrap();
-    // Should pass the filter since there's an item with price > 100
-    match result {
-        Value::Object(_) => {} // passed
-        Value::Null => panic!("Should have passed filter"),
-        _ => panic!("Expected object or null"),
-    }
+fn test_method_sort_multiple_keys() {
+    let result = eval_expr("$[people].sort(@[last], @[first]).map(@[first])", people()).unwrap();
+    assert_eq!(result, json_array(vec![
+        Value::String("Adam".into()),
+        Value::String("Jane".into()),
+        Value::String("Anna".into()),
+        Value::String("John".into()),
+    ]));
 }
 
-// ============================================
-// String Method Tests
-// ============================================
-
 #[test]
-fn test_method_upper() {
-    let doc = json_object(vec![
-        ("name", Value::String("hello world".into())),
-    ]);
+fn test_method_sort_by_with_direction() {
+    let result = eval_expr(
+        r#"$[people].sort_by([{"key": @[age], "desc": true}, @[first]]).map(@[first])"#,
+        people(),
+    ).unwrap();
+    assert_eq!(result, json_array(vec![
+        Value::String("John".into()),
+        Value::String("Adam".into()),
+        Value::String("Jane".into()),
+        Value::String("Anna".into()),
+    ]));
+}
 
-    let result = eval_expr("$[name].upper()", doc).unwrap();
-    assert_eq!(result, Value::String("HELLO WORLD".into()));
+#[test]
+fn test_method_sort_by_is_stable() {
+    // Equal keys keep their original relative order
+    let result = eval_expr("$[people].sort_by([{key: @[age]}]).map(@[first])", people()).unwrap();
+    assert_eq!(result, json_array(vec![
+        Value::String("Anna".into()),
+        Value::String("Jane".into()),
+        Value::String("Adam".into()),
+        Value::String("John".into()),
+    ]));
 }
 
 #[test]
-fn test_method_lower() {
-    let doc = json_object(vec![
-        ("name", Value::String("HELLO WORLD".into())),
-    ]);
+fn test_error_sort_by_requires_array_literal() {
+    let result = eval_expr("$[people].sort_by(@[age])", people());
+    assert!(result.unwrap_err().contains("array literal"));
 
-    let result = eval_expr("$[name].lower()", doc).unwrap();
-    assert_eq!(result, Value::String("hello world".into()));
+    let result = eval_expr(r#"$[people].sort_by([{"by": @[age]}])"#, people());
+    assert!(result.unwrap_err().contains("unknown field"));
 }
 
 #[test]
-fn test_method_upper_mixed_case() {
-    let doc = json_object(vec![
-        ("text", Value::String("HeLLo WoRLd".into())),
-    ]);
-
-    let result = eval_expr("$[text].upper()", doc).unwrap();
-    assert_eq!(result, Value::String("HELLO WORLD".into()));
+fn test_method_sort_mixed_types_total_order() {
+    let result = eval_expr(
+        r#"[{"a": 1}, "b", [1], 2.5, null, true, 1, [0, 5], false, "a"].sort()"#,
+        Value::Null,
+    ).unwrap();
+    assert_eq!(result, json_array(vec![
+        Value::Null,
+        Value::Boolean(false),
+        Value::Boolean(true),
+        Value::Integer(1),
+        Value::Float(2.5),
+        Value::String("a".into()),
+        Value::String("b".into()),
+        json_array(vec![Value::Integer(0), Value::Integer(5)]),
+        json_array(vec![Value::Integer(1)]),
+        json_object(vec![("a", Value::Integer(1))]),
+    ]));
+}
+
+#[test]
+fn test_total_order_of_large_integers_and_floats() {
+    // 2^53 + 1 has no float of its own; rounded, it would equal 2^53 as a float
+    let big = 9007199254740993i64;
+    let float = Value::Float(9007199254740992.0);
+    assert_eq!(Value::Integer(big).total_cmp(&float), Ordering::Greater);
+    assert_eq!(float.total_cmp(&Value::Integer(big)), Ordering::Less);
+    assert_eq!(Value::Integer(big - 1).total_cmp(&float), Ordering::Equal);
+    assert_eq!(Value::Integer(-big).total_cmp(&Value::Float(-9007199254740992.0)), Ordering::Less);
+    assert_eq!(Value::Integer(i64::MAX).total_cmp(&Value::Float(9223372036854775807.0)), Ordering::Less);
+    assert_eq!(Value::Integer(i64::MIN).total_cmp(&Value::Float(-9223372036854775808.0)), Ordering::Equal);
+    assert_eq!(Value::Integer(2).total_cmp(&Value::Float(2.5)), Ordering::Less);
+    assert_eq!(Value::Integer(-2).total_cmp(&Value::Float(-2.5)), Ordering::Greater);
+    assert_eq!(Value::Integer(0).total_cmp(&Value::Float(-0.0)), Ordering::Equal);
+    assert_eq!(Value::Integer(i64::MAX).total_cmp(&Value::Float(f64::INFINITY)), Ordering::Less);
+    assert_eq!(Value::Integer(i64::MIN).total_cmp(&Value::Float(f64::NEG_INFINITY)), Ordering::Greater);
+    assert_eq!(Value::Integer(i64::MAX).total_cmp(&Value::Float(f64::NAN)), Ordering::Less);
+
+    let query = "[9007199254740993, 9007199254740992.0, 9007199254740992].sort()";
+    let sorted = eval_expr(query, Value::Null).unwrap();
+    assert_eq!(sorted, json_array(vec![
+        Value::Float(9007199254740992.0),
+        Value::Integer(9007199254740992),
+        Value::Integer(9007199254740993),
+    ]));
+}
+
+#[test]
+fn test_method_sort_desc_mixed_types() {
+    let result = eval_expr(r#"[1, "x", null, 2].sort_desc()"#, Value::Null).unwrap();
+    assert_eq!(result, json_array(vec![
+        Value::String("x".into()),
+        Value::Integer(2),
+        Value::Integer(1),
+        Value::Null,
+    ]));
+}
+
+#[test]
+fn test_method_min_max_mixed_types() {
+    let doc = json_object(vec![
+        ("values", json_array(vec![
+            Value::String("z".into()),
+            Value::Integer(3),
+            Value::Null,
+            json_array(vec![]),
+        ])),
+    ]);
+    assert_eq!(eval_expr("$[values].min()", doc.clone()).unwrap(), Value::Null);
+    assert_eq!(eval_expr("$[values].max()", doc).unwrap(), json_array(vec![]));
+}
+
+#[test]
+fn test_method_median_and_percentile() {
+    let doc = json_object(vec![
+        ("odd", json_array(vec![Value::Integer(5), Value::Integer(1), Value::Integer(3)])),
+        ("even", json_array(vec![Value::Integer(4), Value::Integer(1), Value::Float(3.0), Value::Integer(2)])),
+        ("mixed", json_array(vec![Value::Integer(10), Value::String("x".into()), Value::Null, Value::Integer(20)])),
+        ("empty", json_array(vec![])),
+    ]);
+
+    assert_eq!(eval_expr("$[odd].median()", doc.clone()).unwrap(), Value::Float(3.0));
+    assert_eq!(eval_expr("$[even].median()", doc.clone()).unwrap(), Value::Float(2.5));
+    assert_eq!(eval_expr("$[mixed].median()", doc.clone()).unwrap(), Value::Float(15.0));
+    assert_eq!(eval_expr("$[empty].median()", doc.clone()).unwrap(), Value::Null);
+
+    assert_eq!(eval_expr("$[even].percentile(0)", doc.clone()).unwrap(), Value::Float(1.0));
+    assert_eq!(eval_expr("$[even].percentile(100)", doc.clone()).unwrap(), Value::Float(4.0));
+    assert_eq!(eval_expr("$[even].percentile(25)", doc.clone()).unwrap(), Value::Float(1.75));
+    assert_eq!(eval_expr("$[odd].percentile(95)", doc.clone()).unwrap(), Value::Float(4.8));
+    assert_eq!(eval_expr("$[empty].percentile(50)", doc.clone()).unwrap(), Value::Null);
+
+    assert!(eval_expr("$[odd].percentile(101)", doc.clone()).unwrap_err().contains("between 0 and 100"));
+    assert!(eval_expr(r#"$[odd].percentile("p95")"#, doc.clone()).unwrap_err().contains("must be number"));
+    assert!(eval_expr("$[odd].percentile()", doc.clone()).unwrap_err().contains("requires a percentile"));
+    assert!(eval_expr("$[odd][0].median()", doc).unwrap_err().contains("requires array"));
+}
+
+#[test]
+fn test_method_variance_and_stddev() {
+    let doc = json_object(vec![
+        ("values", json_array(
+            [2, 4, 4, 4, 5, 5, 7, 9].into_iter().map(Value::Integer).collect(),
+        )),
+        ("empty", json_array(vec![])),
+    ]);
+
+    assert_eq!(eval_expr("$[values].variance()", doc.clone()).unwrap(), Value::Float(4.0));
+    assert_eq!(eval_expr("$[values].stddev()", doc.clone()).unwrap(), Value::Float(2.0));
+    assert_eq!(eval_expr("[7].stddev()", doc.clone()).unwrap(), Value::Float(0.0));
+    assert_eq!(eval_expr("$[empty].variance()", doc.clone()).unwrap(), Value::Null);
+    assert_eq!(eval_expr("$[empty].stddev()", doc).unwrap(), Value::Null);
+}
+
+#[test]
+fn test_method_stats() {
+    let result = eval_expr("[4, 1, 2.5, 2.5].stats()", Value::Null).unwrap();
+    assert_eq!(result, json_object(vec![
+        ("count", Value::Integer(4)),
+        ("min", Value::Integer(1)),
+        ("max", Value::Integer(4)),
+        ("mean", Value::Float(2.5)),
+        ("median", Value::Float(2.5)),
+        ("stddev", Value::Float(1.125f64.sqrt())),
+    ]));
+
+    let empty = eval_expr(r#"["a", null].stats()"#, Value::Null).unwrap();
+    assert_eq!(empty, json_object(vec![
+        ("count", Value::Integer(0)),
+        ("min", Value::Null),
+        ("max", Value::Null),
+        ("mean", Value::Null),
+        ("median", Value::Null),
+        ("stddev", Value::Null),
+    ]));
+}
+
+fn eval_with_overflow(expr_str: &str, doc: Value, policy: OverflowPolicy) -> Result<Value, String> {
+    let lexer = Lexer::new(expr_str);
+    let mut parser = Parser::new(lexer).unwrap();
+    let expr = parser.parse().unwrap();
+
+    let mut evaluator = Evaluator::new().with_overflow_policy(policy);
+    evaluator.eval_expression(&expr, doc)
+        .map_err(|e| e.to_string())
+}
+
+#[test]
+fn test_integer_overflow_promotes_to_float_by_default() {
+    let max = i64::MAX;
+    let doc = json_object(vec![
+        ("big", json_array(vec![Value::Integer(max), Value::Integer(max), Value::Integer(1)])),
+    ]);
+
+    assert_eq!(eval_expr("$[big].sum()", doc.clone()).unwrap(), Value::Float(max as f64 * 2.0 + 1.0));
+    assert_eq!(eval_expr("$[big][0] + 1", doc.clone()).unwrap(), Value::Float(max as f64 + 1.0));
+    assert_eq!(eval_expr("$[big][0] * 2", doc.clone()).unwrap(), Value::Float(max as f64 * 2.0));
+    // Averaging accumulates integers exactly before dividing
+    assert_eq!(eval_expr("$[big].avg()", doc).unwrap(), Value::Float((max as f64 * 2.0 + 1.0) / 3.0));
+}
+
+#[test]
+fn test_integer_overflow_policy_error() {
+    let doc = json_object(vec![
+        ("big", json_array(vec![Value::Integer(i64::MAX), Value::Integer(1)])),
+    ]);
+
+    for expr in ["$[big].sum()", "$[big][0] + 1", "0 - $[big][0] - 2", "$[big][0] * 2"] {
+        let err = eval_with_overflow(expr, doc.clone(), OverflowPolicy::Error).unwrap_err();
+        assert!(err.starts_with("Integer overflow"), "{}: {}", expr, err);
+    }
+
+    // In-range arithmetic is unaffected
+    assert_eq!(
+        eval_with_overflow("$[big][1] + 1", doc, OverflowPolicy::Error).unwrap(),
+        Value::Integer(2)
+    );
+}
+
+#[test]
+fn test_transform_resolved_targets() {
+    let doc = clove_lang::clove_value!({
+        "i": 1,
+        "currency": "eur",
+        "prices": {"eur": 10, "usd": 11},
+        "items": [{"price": 1}, {"price": 2}, {"price": 3}]
+    });
+    let items = |query: &str| eval_query(query, doc.clone()).map(|result| result.get_path(&clove_lang::transform::parse_path("items")).cloned());
+
+    assert_eq!(
+        items("$ | ~($[items].first()[price] := 0)").unwrap(),
+        Some(clove_lang::clove_value!([{"price": 0}, {"price": 2}, {"price": 3}]))
+    );
+    assert_eq!(
+        items("$ | ~($[items][$[i]][price] := @[price] * 10) | -($[items].last())").unwrap(),
+        Some(clove_lang::clove_value!([{"price": 1}, {"price": 20}]))
+    );
+    // Keys are resolved against the document before the write
+    let result = eval_query("$ | ~($[prices][$[currency]] := $[prices][$[currency]] + 1)", doc.clone()).unwrap();
+    assert_eq!(result.get_path(&clove_lang::transform::parse_path("prices.eur")), Some(&Value::Integer(11)));
+
+    let err = eval_query("$ | ~($[items][$[prices]] := 0)", doc.clone()).unwrap_err();
+    assert!(err.contains("string or integer"), "{}", err);
+    let err = eval_query("$ | ~($[items][$[i] + 0.5][price] := 0)", doc.clone()).unwrap_err();
+    assert!(err.contains("key after $[items] must be a string or integer, got float 1.5"), "{}", err);
+    let err = eval_query("$ | ~($[items].sort()[0] := 0)", doc).unwrap_err();
+    assert!(err.contains("Invalid transform target"), "{}", err);
+}
+
+#[test]
+fn test_numbers_compare_by_value_everywhere() {
+    let doc = clove_lang::clove_value!({"n": 1.0, "counts": {"1": "one"}, "items": [10, 20, 30]});
+    let eval = |query: &str| eval_query(query, doc.clone()).unwrap();
+
+    assert_eq!(eval("$ | !([1, 1.0, 2.0, 2, 0.5].unique())"), clove_lang::clove_value!([1, 2.0, 0.5]));
+    assert_eq!(eval("$ | !([[1, 2.0], [1.0, 2]].unique().length())"), Value::Integer(1));
+    assert_eq!(eval("$ | !([{\"id\": 3}, {\"id\": 3.0}].unique_by(@[id]).length())"), Value::Integer(1));
+    assert_eq!(eval("$ | !([1e300, 1e300, 1.5, 1.5].unique())"), clove_lang::clove_value!([1e300, 1.5]));
+    assert_eq!(eval("$ | !([$[items].contains(20.0), $[items].index_of(30.0)])"), clove_lang::clove_value!([true, 2]));
+
+    // Whole-number floats index arrays and name the integer's object key
+    assert_eq!(eval("$ | !($[items][$[n]])"), Value::Integer(20));
+    assert_eq!(eval("$ | !($[counts][$[n]])"), Value::from("one"));
+    assert_eq!(
+        eval("$ | ~($[items][$[n]] := 0) | !($[items])"),
+        Value::array([10, 0, 30])
+    );
+    assert!(eval_query("$ | !($[items][1.5])", doc).is_err());
+}
+
+#[test]
+fn test_whole_number_float_literal_keys() {
+    let doc = clove_lang::clove_value!({"items": [10, 20, 30], "counts": {"1": "one"}});
+    let eval = |query: &str| eval_query(query, doc.clone()).unwrap();
+
+    assert_eq!(
+        eval("$ | !([$[items][1.0], $[items][-1.0], $[counts][1.0]])"),
+        clove_lang::clove_value!([20, 30, "one"])
+    );
+    assert_eq!(eval("$ | ~($[items][1.0] := 5) | !($[items])"), Value::array([10, 5, 30]));
+    assert_eq!(eval("$ | ~($[items][-1.0] := @ + 1) | !($[items])"), Value::array([10, 20, 31]));
+    assert_eq!(eval("$ | -($[items][1.0]) | !($[items])"), Value::array([10, 30]));
+    assert_eq!(eval("$ | -($[items][-1.0]) | -($[items][0.0]) | !($[items])"), Value::array([20]));
+}
+
+#[test]
+fn test_non_finite_numbers() {
+    let doc = clove_lang::clove_value!({"big": 1e308, "values": [1e308, 1e308, 2.5]});
+    let eval = |expr: &str, policy| {
+        let expr = Parser::new(Lexer::new(expr)).unwrap().parse().unwrap();
+        Evaluator::new().with_non_finite_policy(policy).eval_expression(&expr, doc.clone()).map_err(|e| e.to_string())
+    };
+
+    // Kept by default, so queries can guard, and written as null
+    let inf = eval("$[big] * 10", NonFinitePolicy::Null).unwrap();
+    assert_eq!(inf, Value::Float(f64::INFINITY));
+    assert_eq!(to_json(&inf), "null");
+    assert!(matches!(eval("$[big] * 10 - $[big] * 10", NonFinitePolicy::Null).unwrap(), Value::Float(n) if n.is_nan()));
+    assert_eq!(eval("[$[big] * 10, 1.5, 2, \"x\"].map(@.is_finite())", NonFinitePolicy::Null).unwrap(), Value::array([false, true, true, false]));
+    assert_eq!(eval("[$[big] * 10 - $[big] * 10, 1.5].map(@.is_nan())", NonFinitePolicy::Null).unwrap(), Value::array([true, false]));
+
+    for expr in ["$[big] * 10", "$[big] + $[big]", "0 - $[big] - $[big]", "$[values].sum()", "1e308 * 10"] {
+        let err = eval(expr, NonFinitePolicy::Error).unwrap_err();
+        assert!(err.starts_with("Non-finite number"), "{}: {}", expr, err);
+    }
+    assert_eq!(eval("$[big] / 10", NonFinitePolicy::Error).unwrap(), Value::Float(1e307));
+    // Negative zero is an ordinary number
+    let zero = eval("0.0 * (0.0 - 1.0)", NonFinitePolicy::Error).unwrap();
+    assert_eq!(zero, Value::Float(0.0));
+    assert_eq!(to_json(&zero), "-0.0");
+}
+
+#[test]
+fn test_strict_ordering() {
+    let doc = clove_lang::clove_value!({
+        "mixed": [3, "2", null, 1.5],
+        "numbers": [3, 1.5, 2],
+        "people": [{"age": 30}, {"name": "x"}]
+    });
+    let strict = |expr: &str| {
+        let expr = Parser::new(Lexer::new(expr)).unwrap().parse().unwrap();
+        Evaluator::new().with_strict_ordering(true).eval_expression(&expr, doc.clone()).map_err(|e| e.to_string())
+    };
+
+    for expr in ["$[mixed].sort()", "$[mixed].sort_desc()", "$[mixed].min()", "$[mixed].max()", "$[people].sort(@[age])", "$[mixed].arg_max()", "$[people].min_by(@[age])"] {
+        let err = strict(expr).unwrap_err();
+        assert!(err.contains("cannot order"), "{}: {}", expr, err);
+        assert!(eval_expr(expr, doc.clone()).is_ok(), "{}", expr);
+    }
+    let err = strict("$[people].sort_by([{\"key\": @[age], \"desc\": true}])").unwrap_err();
+    assert!(err.contains(".sort_by() cannot order integer and null values"), "{}", err);
+
+    // Integers and floats are all numbers
+    assert_eq!(strict("$[numbers].sort()").unwrap(), Value::Array(vec![Value::Float(1.5), Value::Integer(2), Value::Integer(3)]));
+    assert_eq!(strict("$[numbers].max()").unwrap(), Value::Integer(3));
+    assert_eq!(strict("$[people].sort(@.keys()[0])").unwrap(), eval_expr("$[people].sort(@.keys()[0])", doc).unwrap());
+}
+
+#[test]
+fn test_max_document_bytes() {
+    let query = Parser::new(Lexer::new("$ | ~($[copy] := $[items]) | !($[copy].length())"))
+        .unwrap()
+        .parse_query()
+        .unwrap();
+    let doc = clove_lang::clove_value!({"items": [1, 2, 3, 4, 5, 6, 7, 8]});
+
+    // The transform doubles the document, so the output stage is over the limit
+    let limit = doc.approx_size();
+    let err = Evaluator::new()
+        .with_max_document_bytes(limit)
+        .eval_query(&query, doc.clone())
+        .unwrap_err();
+    assert!(matches!(err, clove_lang::EvalError::LimitExceeded(_)), "{:?}", err);
+    assert!(err.to_string().contains("output stage"), "{}", err);
+
+    let result = Evaluator::new().with_max_document_bytes(limit * 2).eval_query(&query, doc.clone());
+    assert_eq!(result.unwrap(), Value::Integer(8));
+
+    // The input itself is checked too
+    let err = Evaluator::new().with_max_document_bytes(16).eval_query(&query, doc).unwrap_err();
+    assert!(err.to_string().contains("transform stage"), "{}", err);
+}
+
+#[test]
+fn test_stage_stats() {
+    let query = Parser::new(Lexer::new("$ | @n := $[n] | ?(@n > 1) | -($[big]) | !(@n)"))
+        .unwrap()
+        .parse_query()
+        .unwrap();
+    let doc = clove_lang::clove_value!({"n": 2, "big": "a string of some length"});
+    let mut evaluator = Evaluator::new().with_stage_stats(true);
+    evaluator.eval_query(&query, doc.clone()).unwrap();
+
+    let stats = evaluator.stage_stats();
+    let stages: Vec<_> = stats.iter().map(|s| s.stage.as_str()).collect();
+    assert_eq!(stages, ["scope @n", "filter", "delete", "output", "result"]);
+    assert_eq!(stats[0].bytes, doc.approx_size());
+    assert!(stats[3].bytes < stats[2].bytes);
+    assert_eq!(stats[4].bytes, Value::Integer(2).approx_size());
+
+    // Each query replaces the last one's stats; none are kept by default
+    evaluator.eval_query(&query, clove_lang::clove_value!({"n": 0})).unwrap();
+    assert_eq!(evaluator.stage_stats().len(), 2);
+    let mut evaluator = Evaluator::new();
+    evaluator.eval_query(&query, doc).unwrap();
+    assert!(evaluator.stage_stats().is_empty());
+}
+
+#[test]
+fn test_eval_stats() {
+    let query = Parser::new(Lexer::new("$ | ?($[n] > 1) | !($[xs].map(@ * 2).sum())"))
+        .unwrap()
+        .parse_query()
+        .unwrap();
+    let doc = clove_lang::clove_value!({"n": 2, "xs": [1, 2, 3]});
+    let mut evaluator = Evaluator::new().with_eval_stats(true);
+    assert_eq!(evaluator.eval_query(&query, doc.clone()).unwrap(), Value::Integer(12));
+
+    let stats = evaluator.eval_stats().unwrap();
+    let calls: Vec<_> = stats.method_calls.iter().map(|(name, n)| (name.as_str(), *n)).collect();
+    assert_eq!(calls, [("map", 1), ("sum", 1)]);
+    let stages: Vec<_> = stats.statements.iter().map(|s| s.stage.as_str()).collect();
+    assert_eq!(stages, ["filter", "output"]);
+    // `$[xs].map(@ * 2).sum()` nests the root four expressions deep, and the
+    // lambda body runs once per element
+    assert_eq!(stats.max_depth, 4);
+    assert!(stats.expressions > 3 * 3, "{:?}", stats);
+    assert!(stats.allocated_bytes >= clove_lang::clove_value!([2, 4, 6]).approx_size());
+
+    let value = stats.to_value();
+    let Value::Object(map) = &value else {
+        panic!("Expected object, got {:?}", value);
+    };
+    assert_eq!(map.get("max_depth"), Some(&Value::Integer(4)));
+    assert_eq!(map.get("method_calls"), Some(&clove_lang::clove_value!({"map": 1, "sum": 1})));
+
+    // Each query replaces the last one's stats; none are kept by default
+    evaluator.eval_query(&query, clove_lang::clove_value!({"n": 0})).unwrap();
+    assert!(evaluator.eval_stats().unwrap().method_calls.is_empty());
+    assert!(Evaluator::new().eval_stats().is_none());
+}
+
+#[test]
+fn test_eval_stats_stage_sizes() {
+    let query = Parser::new(Lexer::new("$ | when($[n] > 1) { -($[big]) | @n := $[n] } | !(@n)"))
+        .unwrap()
+        .parse_query()
+        .unwrap();
+    let doc = clove_lang::clove_value!({"n": 2, "big": "a string of some length"});
+    let mut evaluator = Evaluator::new().with_eval_stats(true);
+    evaluator.eval_query(&query, doc.clone()).unwrap();
+
+    // Statements in a block get entries of their own, each with the size of
+    // the document it received
+    let stats = evaluator.eval_stats().unwrap();
+    let stages: Vec<_> = stats.statements.iter().map(|s| (s.stage.as_str(), s.bytes)).collect();
+    let smaller = clove_lang::clove_value!({"n": 2}).approx_size();
+    assert_eq!(stages, [
+        ("when", doc.approx_size()),
+        ("delete", doc.approx_size()),
+        ("scope @n", smaller),
+        ("output", smaller),
+    ]);
+    assert_eq!(stats.result_bytes, Value::Integer(2).approx_size());
+}
+
+#[test]
+fn test_method_sort_objects_by_entries() {
+    let result = eval_expr(r#"[{"b": 1}, {"a": 2}, {"a": 1, "c": 0}, {"a": 1}].sort()"#, Value::Null).unwrap();
+    assert_eq!(result, json_array(vec![
+        json_object(vec![("a", Value::Integer(1))]),
+        json_object(vec![("a", Value::Integer(1)), ("c", Value::Integer(0))]),
+        json_object(vec![("a", Value::Integer(2))]),
+        json_object(vec![("b", Value::Integer(1))]),
+    ]));
+}
+
+#[test]
+fn test_method_chaining() {
+    let doc = json_object(vec![
+        ("items", json_array(vec![
+            json_object(vec![("price", Value::Integer(50)), ("active", Value::Boolean(true))]),
+            json_object(vec![("price", Value::Integer(150)), ("active", Value::Boolean(false))]),
+            json_object(vec![("price", Value::Integer(200)), ("active", Value::Boolean(true))]),
+        ])),
+    ]);
+
+    // Filter active items, then sum their prices
+    let result = eval_expr("$[items].filter(@[active] == true).sum(@[price])", doc).unwrap();
+    assert_eq!(result, Value::Integer(250));
+}
+
+#[test]
+fn test_lazy_chain_matches_eager_results() {
+    let doc = json_object(vec![
+        ("nums", json_array((1..=10).map(Value::Integer).collect())),
+    ]);
+
+    let cases = [
+        ("$[nums].filter(@ > 3).map(@ * 10).first()", Value::Integer(40)),
+        ("$[nums].filter(@ > 30).first()", Value::Null),
+        ("$[nums].filter(@ % 2 == 0).count()", Value::Integer(5)),
+        ("$[nums].map(@ * 2).any(@ == 14)", Value::Boolean(true)),
+        ("$[nums].map(@ * 2).all(@ < 20)", Value::Boolean(false)),
+        ("$[nums].filter(@ > 100).all(@ < 0)", Value::Boolean(true)),
+        ("$[nums].filter(@ > 9).exists()", Value::Boolean(true)),
+        ("$[nums].filter(@ > 10).exists()", Value::Boolean(false)),
+        ("$[nums].map(@ * 3).find(@ > 10)", Value::Integer(12)),
+        ("$[nums].filter(@ % 2 == 0).find_index(@ > 5)", Value::Integer(2)),
+        ("$[nums].filter(@ > 3).find_index(@ > 30)", Value::Null),
+    ];
+    for (query, expected) in cases {
+        assert_eq!(eval_expr(query, doc.clone()).unwrap(), expected, "{}", query);
+    }
+}
+
+#[test]
+fn test_lazy_chain_short_circuits() {
+    // The second element fails to map, even where .first() doesn't need it
+    let doc = json_object(vec![
+        ("items", json_array(vec![Value::Integer(1), Value::String("x".into())])),
+    ]);
+
+    assert!(eval_expr("$[items].map(@ + 1).first()", doc.clone()).is_err());
+    assert!(eval_expr("$[items].map(@ + 1).any(@ == 2)", doc.clone()).is_err());
+    assert!(eval_expr("$[items].map(@ + 1).take(1)", doc.clone()).is_err());
+    assert!(eval_expr("$[items].filter(@ + 1 > 0).find_index(@ == 1)", doc.clone()).is_err());
+    assert!(eval_expr("$[items].map(@ + 1).count()", doc.clone()).is_err());
+    assert!(eval_expr("$[items].map(@ + 1).last()", doc).is_err());
+    assert!(eval_expr("[1, \"x\"].map(@ * 2).first()", Value::Null).is_err());
+    // Stages that cannot fail stream as before
+    assert_eq!(eval_expr("[1, 2].map(@ * 2).first()", Value::Null).unwrap(), Value::Integer(2));
+    assert_eq!(eval_expr("[1, \"x\"].map(@ == 1).first()", Value::Null).unwrap(), Value::Boolean(true));
+    let doc = json_object(vec![("items", ints(&[1, 2, 3]))]);
+    assert_eq!(eval_expr("$[items].filter(@ > 1).map(n -> @n * 10).take(1)", doc).unwrap(), ints(&[20]));
+}
+
+#[test]
+fn test_lazy_chain_non_array_error() {
+    let doc = json_object(vec![("items", Value::Integer(1))]);
+    let result = eval_expr("$[items].filter(@ > 0).first()", doc);
+    assert!(result.unwrap_err().contains(".filter() requires array"));
+}
+
+fn ints(values: &[i64]) -> Value {
+    json_array(values.iter().map(|n| Value::Integer(*n)).collect())
+}
+
+#[test]
+fn test_method_take_and_skip() {
+    let doc = json_object(vec![("nums", ints(&[1, 2, 3, 4, 5]))]);
+
+    assert_eq!(eval_expr("$[nums].take(2)", doc.clone()).unwrap(), ints(&[1, 2]));
+    assert_eq!(eval_expr("$[nums].take(10)", doc.clone()).unwrap(), ints(&[1, 2, 3, 4, 5]));
+    assert_eq!(eval_expr("$[nums].skip(3)", doc.clone()).unwrap(), ints(&[4, 5]));
+    assert_eq!(eval_expr("$[nums].skip(10)", doc.clone()).unwrap(), ints(&[]));
+    assert_eq!(eval_expr("$[nums].skip(1).take(2)", doc).unwrap(), ints(&[2, 3]));
+}
+
+#[test]
+fn test_method_take_lazy_chain() {
+    let doc = json_object(vec![
+        ("items", json_array(vec![Value::Integer(1), Value::Integer(2), Value::String("x".into())])),
+    ]);
+
+    assert_eq!(eval_expr("$[items].take(2).map(@ * 10)", doc.clone()).unwrap(), ints(&[10, 20]));
+    // Every element is mapped first, as without the lazy chain
+    assert!(eval_expr("$[items].map(@ * 10).take(2)", doc.clone()).is_err());
+    assert!(eval_expr("$[items].map(@ * 10).take(0)", doc).is_err());
+}
+
+#[test]
+fn test_method_take_while_and_skip_while() {
+    let doc = json_object(vec![("nums", ints(&[1, 2, 5, 1, 2]))]);
+
+    assert_eq!(eval_expr("$[nums].take_while(@ < 3)", doc.clone()).unwrap(), ints(&[1, 2]));
+    assert_eq!(eval_expr("$[nums].skip_while(@ < 3)", doc.clone()).unwrap(), ints(&[5, 1, 2]));
+    assert_eq!(eval_expr("$[nums].skip_while(@ > 0)", doc).unwrap(), ints(&[]));
+}
+
+#[test]
+fn test_method_find_and_find_index() {
+    let doc = json_object(vec![
+        ("nums", ints(&[1, 4, 6, 9])),
+        ("items", json_array(vec![Value::Integer(1), Value::String("x".into())])),
+    ]);
+
+    assert_eq!(eval_expr("$[nums].find(@ % 2 == 0)", doc.clone()).unwrap(), Value::Integer(4));
+    assert_eq!(eval_expr("$[nums].find_index(@ > 5)", doc.clone()).unwrap(), Value::Integer(2));
+    assert_eq!(eval_expr("$[nums].find(@ > 10)", doc.clone()).unwrap(), Value::Null);
+    assert_eq!(eval_expr("$[nums].find_index(@ > 10)", doc.clone()).unwrap(), Value::Null);
+
+    // Elements after the first match are never evaluated
+    assert_eq!(eval_expr("$[items].find(@ + 1 == 2)", doc.clone()).unwrap(), Value::Integer(1));
+    assert_eq!(eval_expr("$[items].find_index(@ + 1 == 2)", doc.clone()).unwrap(), Value::Integer(0));
+    assert!(eval_expr("$[items].find(@ + 1 == 3)", doc.clone()).is_err());
+
+    let err = eval_expr("$[nums][0].find(@ > 1)", doc.clone()).unwrap_err();
+    assert!(err.contains(".find() requires array"), "{}", err);
+    let err = eval_expr("$[nums].find_index()", doc).unwrap_err();
+    assert!(err.contains(".find_index() requires a predicate"), "{}", err);
+}
+
+#[test]
+fn test_method_min_by_max_by_and_arg_extremes() {
+    let doc = clove_lang::clove_value!({
+        "items": [{"name": "a", "price": 3}, {"name": "b", "price": 1.0}, {"name": "c", "price": 1}, {"name": "d", "price": 7}],
+        "scores": [4, 9, 2, 9],
+        "empty": []
+    });
+
+    // Ties keep the first element
+    assert_eq!(eval_expr("$[items].min_by(@[price])[name]", doc.clone()).unwrap(), Value::String("b".into()));
+    assert_eq!(eval_expr("$[items].max_by(@[price])[name]", doc.clone()).unwrap(), Value::String("d".into()));
+    assert_eq!(eval_expr("$[scores].arg_max()", doc.clone()).unwrap(), Value::Integer(1));
+    assert_eq!(eval_expr("$[scores].arg_min()", doc.clone()).unwrap(), Value::Integer(2));
+    assert_eq!(eval_expr("$[items].arg_min(@[price])", doc.clone()).unwrap(), Value::Integer(1));
+    assert_eq!(eval_expr("$[items].arg_max(0 - @[price])", doc.clone()).unwrap(), Value::Integer(1));
+    for expr in ["$[empty].min_by(@)", "$[empty].max_by(@)", "$[empty].arg_min()", "$[empty].arg_max(@)"] {
+        assert_eq!(eval_expr(expr, doc.clone()).unwrap(), Value::Null, "{}", expr);
+    }
+
+    let err = eval_expr("$[items].min_by()", doc.clone()).unwrap_err();
+    assert!(err.contains(".min_by() requires a key expression"), "{}", err);
+    let err = eval_expr("$[scores][0].arg_max()", doc).unwrap_err();
+    assert!(err.contains(".arg_max() requires array"), "{}", err);
+}
+
+#[test]
+fn test_method_index_of_and_last_index_of() {
+    let doc = json_object(vec![
+        ("nums", ints(&[3, 1, 3, 2])),
+        ("pairs", json_array(vec![ints(&[1, 2]), ints(&[3]), ints(&[1, 2])])),
+    ]);
+
+    assert_eq!(eval_expr("$[nums].index_of(3)", doc.clone()).unwrap(), Value::Integer(0));
+    assert_eq!(eval_expr("$[nums].last_index_of(3)", doc.clone()).unwrap(), Value::Integer(2));
+    assert_eq!(eval_expr("$[nums].index_of(2.0)", doc.clone()).unwrap(), Value::Integer(3));
+    assert_eq!(eval_expr("$[nums].index_of(7)", doc.clone()).unwrap(), Value::Null);
+    assert_eq!(eval_expr("$[nums].last_index_of(\"3\")", doc.clone()).unwrap(), Value::Null);
+    assert_eq!(eval_expr("$[pairs].index_of([1, 2])", doc.clone()).unwrap(), Value::Integer(0));
+    assert_eq!(eval_expr("$[pairs].last_index_of([1, 2])", doc.clone()).unwrap(), Value::Integer(2));
+
+    let err = eval_expr("\"abc\".index_of(\"b\")", doc).unwrap_err();
+    assert!(err.contains(".index_of() requires array"), "{}", err);
+}
+
+fn eval_seeded(expr_str: &str, doc: Value, seed: u64) -> Value {
+    let expr = Parser::new(Lexer::new(expr_str)).unwrap().parse().unwrap();
+    Evaluator::new().with_seed(seed).eval_expression(&expr, doc).unwrap()
+}
+
+fn int_items(value: &Value) -> Vec<i64> {
+    match value {
+        Value::Array(items) => items
+            .iter()
+            .map(|item| match item {
+                Value::Integer(n) => *n,
+                other => panic!("expected integer, got {:?}", other),
+            })
+            .collect(),
+        other => panic!("expected array, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_method_sample() {
+    let doc = json_object(vec![("nums", ints(&(0..20).collect::<Vec<_>>()))]);
+
+    for seed in 0..20 {
+        let sample = int_items(&eval_seeded("$[nums].sample(5)", doc.clone(), seed));
+        assert_eq!(sample.len(), 5);
+        // Distinct elements of the array, in their original order
+        assert!(sample.windows(2).all(|pair| pair[0] < pair[1]), "{:?}", sample);
+    }
+    assert_eq!(eval_seeded("$[nums].sample(50)", doc.clone(), 1), ints(&(0..20).collect::<Vec<_>>()));
+    assert_eq!(eval_seeded("$[nums].sample(0)", doc.clone(), 1), ints(&[]));
+
+    // Every element is about equally likely
+    let mut counts = [0; 4];
+    for seed in 0..4000 {
+        let picked = int_items(&eval_seeded("[0, 1, 2, 3].sample(1)", Value::Null, seed));
+        counts[picked[0] as usize] += 1;
+    }
+    assert!(counts.iter().all(|&n| (850..1150).contains(&n)), "{:?}", counts);
+
+    assert!(eval_expr("$[nums].sample(0 - 1)", doc.clone()).unwrap_err().contains("non-negative"));
+    assert!(eval_expr("$[nums][0].sample(1)", doc).unwrap_err().contains(".sample() requires array"));
+}
+
+#[test]
+fn test_method_shuffle() {
+    let doc = json_object(vec![("nums", ints(&(0..20).collect::<Vec<_>>()))]);
+
+    let shuffled = eval_seeded("$[nums].shuffle()", doc.clone(), 42);
+    let mut sorted = int_items(&shuffled);
+    assert_ne!(sorted, (0..20).collect::<Vec<_>>());
+    sorted.sort();
+    assert_eq!(sorted, (0..20).collect::<Vec<_>>());
+
+    // The same seed gives the same order, and each call draws anew
+    assert_eq!(eval_seeded("$[nums].shuffle()", doc.clone(), 42), shuffled);
+    match eval_seeded("[$[nums].shuffle(), $[nums].shuffle()]", doc.clone(), 42) {
+        Value::Array(both) => assert_ne!(both[0], both[1]),
+        other => panic!("expected array, got {:?}", other),
+    }
+
+    assert!(eval_expr(r#""abc".shuffle()"#, doc).unwrap_err().contains(".shuffle() requires array"));
+}
+
+#[test]
+fn test_random_builtin() {
+    let values = eval_seeded("[&random[], &random[], &random[]]", Value::Null, 9);
+    let Value::Array(values) = values else { panic!("expected array") };
+    for value in &values {
+        let Value::Float(f) = value else { panic!("expected float, got {:?}", value) };
+        assert!((0.0..1.0).contains(f));
+    }
+    assert_ne!(values[0], values[1]);
+    assert_eq!(eval_seeded("[&random[], &random[], &random[]]", Value::Null, 9), Value::Array(values));
+
+    assert!(eval_expr("&random[1]", Value::Null).unwrap_err().contains("takes 0 argument"));
+}
+
+#[test]
+fn test_method_chunk() {
+    let doc = json_object(vec![("nums", ints(&[1, 2, 3, 4, 5]))]);
+
+    assert_eq!(
+        eval_expr("$[nums].chunk(2)", doc).unwrap(),
+        json_array(vec![ints(&[1, 2]), ints(&[3, 4]), ints(&[5])])
+    );
+}
+
+#[test]
+fn test_method_window() {
+    let doc = json_object(vec![("nums", ints(&[1, 2, 3, 4]))]);
+
+    assert_eq!(
+        eval_expr("$[nums].window(3)", doc.clone()).unwrap(),
+        json_array(vec![ints(&[1, 2, 3]), ints(&[2, 3, 4])])
+    );
+    assert_eq!(eval_expr("$[nums].window(5)", doc).unwrap(), json_array(vec![]));
+}
+
+#[test]
+fn test_error_slicing_arguments() {
+    let doc = json_object(vec![("nums", ints(&[1, 2])), ("text", Value::String("ab".into()))]);
+
+    assert!(eval_expr("$[nums].take(-1)", doc.clone()).unwrap_err().contains("non-negative"));
+    assert!(eval_expr(r#"$[nums].skip("1")"#, doc.clone()).unwrap_err().contains("must be integer"));
+    assert!(eval_expr("$[nums].chunk(0)", doc.clone()).unwrap_err().contains("at least 1"));
+    assert!(eval_expr("$[nums].window()", doc.clone()).unwrap_err().contains("exactly one argument"));
+    assert!(eval_expr("$[text].take(1)", doc.clone()).unwrap_err().contains("requires array"));
+    assert!(eval_expr("$[nums].map(@).take(-1)", doc).unwrap_err().contains("non-negative"));
+}
+
+#[test]
+fn test_method_in_query_pipeline() {
+    let doc = json_object(vec![
+        ("items", json_array(vec![
+            json_object(vec![("price", Value::Integer(50))]),
+            json_object(vec![("price", Value::Integer(150))]),
+        ])),
+    ]);
+
+    let result = eval_query("$ | ?($[items].any(@[price] > 100))", doc).unwrap();
+    // Should pass the filter since there's an item with price > 100
+    match result {
+        Value::Object(_) => {} // passed
+        Value::Null => panic!("Should have passed filter"),
+        _ => panic!("Expected object or null"),
+    }
+}
+
+// ============================================
+// String Method Tests
+// ============================================
+
+#[test]
+fn test_method_upper() {
+    let doc = json_object(vec![
+        ("name", Value::String("hello world".into())),
+    ]);
+
+    let result = eval_expr("$[name].upper()", doc).unwrap();
+    assert_eq!(result, Value::String("HELLO WORLD".into()));
+}
+
+#[test]
+fn test_method_lower() {
+    let doc = json_object(vec![
+        ("name", Value::String("HELLO WORLD".into())),
+    ]);
+
+    let result = eval_expr("$[name].lower()", doc).unwrap();
+    assert_eq!(result, Value::String("hello world".into()));
+}
+
+#[test]
+fn test_method_case_conversions() {
+    let cases = [
+        ("\"hello wORLD, o'neil\".title()", "Hello World, O'neil"),
+        ("\"ΟΔΟΣ straße ǆungla\".title()", "Οδος Straße ǅungla"),
+        ("\"ß\".title()", "Ss"),
+        ("\"HTTPServer error, version2Name\".snake_case()", "http_server_error_version2_name"),
+        ("\"userId\".kebab_case()", "user-id"),
+        ("\"  first name  \".camel_case()", "firstName"),
+        ("\"Crème Brûlée\".snake_case()", "crème_brûlée"),
+        // A decomposed accent stays in its word
+        ("\"Cre\u{301}me bru\u{302}le\u{301}e\".camel_case()", "cre\u{301}meBru\u{302}le\u{301}e"),
+        ("\"---\".snake_case()", ""),
+    ];
+    for (expr, expected) in cases {
+        assert_eq!(eval_expr(expr, Value::Null).unwrap(), Value::String(expected.into()), "{}", expr);
+    }
+
+    let err = eval_expr("1.snake_case()", Value::Null).unwrap_err();
+    assert!(err.contains(".snake_case() requires string, got integer"), "{}", err);
+}
+
+#[test]
+fn test_method_lines_words_chars() {
+    let doc = clove_lang::clove_value!({"text": "Error: boom\r\n\n  at main\n", "blank": ""});
+
+    assert_eq!(eval_expr("$[text].lines()", doc.clone()).unwrap(), Value::array(["Error: boom", "", "  at main"]));
+    assert_eq!(eval_expr("$[text].words()", doc.clone()).unwrap(), Value::array(["Error:", "boom", "at", "main"]));
+    assert_eq!(eval_expr("\"né\".chars()", doc.clone()).unwrap(), Value::array(["n", "é"]));
+    assert_eq!(eval_expr("\"né\".chars().length() == \"né\".length()", doc.clone()).unwrap(), Value::Boolean(true));
+    for method in ["lines", "words", "chars"] {
+        let expr = format!("$[blank].{}()", method);
+        assert_eq!(eval_expr(&expr, doc.clone()).unwrap(), Value::Array(vec![]), "{}", expr);
+    }
+
+    let err = eval_expr("[1].lines()", doc).unwrap_err();
+    assert!(err.contains(".lines() requires string, got array"), "{}", err);
+}
+
+#[test]
+fn test_method_normalize_unicode() {
+    let doc = clove_lang::clove_value!({"composed": "\u{e9}", "decomposed": "e\u{301}", "ligature": "\u{fb01}le"});
+
+    assert_eq!(eval_expr("$[decomposed].normalize_unicode() == $[composed]", doc.clone()).unwrap(), Value::Boolean(true));
+    assert_eq!(eval_expr("$[composed].normalize_unicode(\"NFD\") == $[decomposed]", doc.clone()).unwrap(), Value::Boolean(true));
+    assert_eq!(eval_expr("$[ligature].normalize_unicode()", doc.clone()).unwrap(), Value::String("\u{fb01}le".into()));
+    assert_eq!(eval_expr("$[ligature].normalize_unicode(\"nfkc\")", doc.clone()).unwrap(), Value::String("file".into()));
+    assert_eq!(eval_expr("$[ligature].normalize_unicode(\"NFKD\")", doc.clone()).unwrap(), Value::String("file".into()));
+
+    let err = eval_expr("$[composed].normalize_unicode(\"NFX\")", doc).unwrap_err();
+    assert!(err.contains("got \\\"NFX\\\""), "{}", err);
+}
+
+#[test]
+fn test_method_upper_mixed_case() {
+    let doc = json_object(vec![
+        ("text", Value::String("HeLLo WoRLd".into())),
+    ]);
+
+    let result = eval_expr("$[text].upper()", doc).unwrap();
+    assert_eq!(result, Value::String("HELLO WORLD".into()));
 }
 
 #[test]
@@ -1160,6 +2694,239 @@ fn test_method_endswith_false() {
     assert_eq!(result, Value::Boolean(false));
 }
 
+#[test]
+fn test_method_contains_array_element() {
+    let doc = json_object(vec![
+        ("tags", json_array(vec![Value::String("urgent".into()), Value::String("bug".into())])),
+    ]);
+
+    assert_eq!(eval_expr(r#"$[tags].contains("urgent")"#, doc.clone()).unwrap(), Value::Boolean(true));
+    assert_eq!(eval_expr(r#"$[tags].contains("feature")"#, doc).unwrap(), Value::Boolean(false));
+}
+
+#[test]
+fn test_method_contains_array_deep_equality() {
+    let doc = json_object(vec![
+        ("items", json_array(vec![
+            json_object(vec![("id", Value::Integer(1))]),
+            json_array(vec![Value::Integer(1), Value::Integer(2)]),
+        ])),
+    ]);
+
+    assert_eq!(eval_expr(r#"$[items].contains({"id": 1})"#, doc.clone()).unwrap(), Value::Boolean(true));
+    assert_eq!(eval_expr("$[items].contains([1, 2])", doc.clone()).unwrap(), Value::Boolean(true));
+    assert_eq!(eval_expr("$[items].contains([2, 1])", doc).unwrap(), Value::Boolean(false));
+}
+
+#[test]
+fn test_method_contains_object_key() {
+    let doc = json_object(vec![
+        ("config", json_object(vec![("debug", Value::Boolean(false))])),
+    ]);
+
+    assert_eq!(eval_expr(r#"$[config].contains("debug")"#, doc.clone()).unwrap(), Value::Boolean(true));
+    assert_eq!(eval_expr(r#"$[config].contains("verbose")"#, doc.clone()).unwrap(), Value::Boolean(false));
+    assert!(eval_expr("$[config].contains(1)", doc).unwrap_err().contains("requires string key"));
+}
+
+#[test]
+fn test_method_contains_all() {
+    let doc = json_object(vec![
+        ("tags", json_array(vec![Value::String("a".into()), Value::String("b".into())])),
+        ("text", Value::String("hello world".into())),
+    ]);
+
+    assert_eq!(eval_expr(r#"$[tags].contains_all(["a", "b"])"#, doc.clone()).unwrap(), Value::Boolean(true));
+    assert_eq!(eval_expr(r#"$[tags].contains_all(["a", "c"])"#, doc.clone()).unwrap(), Value::Boolean(false));
+    assert_eq!(eval_expr("$[tags].contains_all([])", doc.clone()).unwrap(), Value::Boolean(true));
+    assert_eq!(eval_expr(r#"$[text].contains_all(["hello", "world"])"#, doc).unwrap(), Value::Boolean(true));
+}
+
+#[test]
+fn test_method_contains_any() {
+    let doc = json_object(vec![
+        ("tags", json_array(vec![Value::String("a".into()), Value::String("b".into())])),
+        ("user", json_object(vec![("email", Value::Null)])),
+    ]);
+
+    assert_eq!(eval_expr(r#"$[tags].contains_any(["x", "b"])"#, doc.clone()).unwrap(), Value::Boolean(true));
+    assert_eq!(eval_expr(r#"$[tags].contains_any(["x", "y"])"#, doc.clone()).unwrap(), Value::Boolean(false));
+    assert_eq!(eval_expr("$[tags].contains_any([])", doc.clone()).unwrap(), Value::Boolean(false));
+    assert_eq!(eval_expr(r#"$[user].contains_any(["phone", "email"])"#, doc).unwrap(), Value::Boolean(true));
+}
+
+#[test]
+fn test_method_keys_and_values_sorted() {
+    let doc = json_object(vec![
+        ("zeta", Value::Integer(1)),
+        ("alpha", Value::Integer(2)),
+        ("mid", Value::Integer(3)),
+    ]);
+    let sorted = json_array(vec![
+        Value::String("alpha".into()),
+        Value::String("mid".into()),
+        Value::String("zeta".into()),
+    ]);
+
+    assert_eq!(eval_expr("$.keys()", doc.clone()).unwrap(), sorted);
+    assert_eq!(eval_expr("$.sorted_keys()", doc.clone()).unwrap(), sorted);
+    assert_eq!(
+        eval_expr("$.values()", doc).unwrap(),
+        json_array(vec![Value::Integer(2), Value::Integer(3), Value::Integer(1)])
+    );
+}
+
+#[test]
+fn test_method_has() {
+    let doc = json_object(vec![
+        ("user", json_object(vec![("email", Value::Null)])),
+        ("items", json_array(vec![Value::Integer(1), Value::Integer(2)])),
+    ]);
+
+    assert_eq!(eval_expr(r#"$[user].has("email")"#, doc.clone()).unwrap(), Value::Boolean(true));
+    assert_eq!(eval_expr(r#"$[user].has("phone")"#, doc.clone()).unwrap(), Value::Boolean(false));
+    assert_eq!(eval_expr("$[items].has(1)", doc.clone()).unwrap(), Value::Boolean(true));
+    assert_eq!(eval_expr("$[items].has(0 - 2)", doc.clone()).unwrap(), Value::Boolean(true));
+    assert_eq!(eval_expr("$[items].has(2)", doc.clone()).unwrap(), Value::Boolean(false));
+    assert!(eval_expr("$[user].has(1)", doc.clone()).unwrap_err().contains("requires string key"));
+    assert!(eval_expr(r#"$[items].has("a")"#, doc).unwrap_err().contains("requires integer index"));
+}
+
+#[test]
+fn test_method_has_path() {
+    let doc = json_object(vec![
+        ("a", json_object(vec![
+            ("b", Value::String(String::new())),
+            ("n", Value::Null),
+            ("list", json_array(vec![json_object(vec![("id", Value::Integer(1))])])),
+        ])),
+    ]);
+
+    // Present but falsy values exist, unlike with [?]
+    assert_eq!(eval_expr(r#"$.has_path("a", "b")"#, doc.clone()).unwrap(), Value::Boolean(true));
+    assert_eq!(eval_expr("$[a][b][?]", doc.clone()).unwrap(), Value::Boolean(false));
+    assert_eq!(eval_expr(r#"$[a].has_path("n")"#, doc.clone()).unwrap(), Value::Boolean(true));
+    assert_eq!(eval_expr(r#"$.has_path("a", "list", -1, "id")"#, doc.clone()).unwrap(), Value::Boolean(true));
+
+    // Missing keys and intermediates are simply absent
+    assert_eq!(eval_expr(r#"$.has_path("a", "c")"#, doc.clone()).unwrap(), Value::Boolean(false));
+    assert_eq!(eval_expr(r#"$.has_path("x", "y", "z")"#, doc.clone()).unwrap(), Value::Boolean(false));
+    assert_eq!(eval_expr(r#"$.has_path("a", "b", "c")"#, doc.clone()).unwrap(), Value::Boolean(false));
+    assert_eq!(eval_expr(r#"$.has_path("a", "list", 1)"#, doc.clone()).unwrap(), Value::Boolean(false));
+    assert_eq!(eval_expr(r#"$.has_path("a", "list", "id")"#, doc.clone()).unwrap(), Value::Boolean(false));
+
+    assert!(eval_expr(r#"$.has_path("a", true)"#, doc.clone()).unwrap_err().contains("string keys or integer indices"));
+    assert!(eval_expr("$.has_path()", doc).unwrap_err().contains("at least one key"));
+}
+
+#[test]
+fn test_method_get_path() {
+    let doc = clove_lang::clove_value!({
+        "items": [{"price": 10, "tags": ["a"]}, {"tags": []}, {"price": 7, "tags": ["b", "c"]}],
+        "meta": {"build.id": 42, "0": "zero", "a\\b": 1},
+    });
+    let get = |path: &str| eval_expr(&format!("$.get_path({})", path), doc.clone());
+
+    assert_eq!(get(r#""items.0.price""#).unwrap(), Value::Integer(10));
+    assert_eq!(get(r#""items[-1].tags[0]""#).unwrap(), Value::String("b".into()));
+    assert_eq!(get(r#""items.*.price""#).unwrap(), clove_lang::clove_value!([10, null, 7]));
+    assert_eq!(get(r#""items[*].tags.*""#).unwrap(), clove_lang::clove_value!([["a"], [], ["b", "c"]]));
+    assert_eq!(get(r#""meta.*""#).unwrap(), clove_lang::clove_value!(["zero", 1, 42]));
+
+    // Keys containing dots, and integers naming object keys
+    for path in [r#""meta[\"build.id\"]""#, r#""meta['build.id']""#, r#""meta.build\\.id""#] {
+        assert_eq!(get(path).unwrap(), Value::Integer(42), "{}", path);
+    }
+    assert_eq!(get(r#""meta.0""#).unwrap(), Value::String("zero".into()));
+    assert_eq!(get(r#""meta['a\\\\b']""#).unwrap(), Value::Integer(1));
+    assert_eq!(get(r#""""#).unwrap(), doc);
+
+    // The arrays .paths() returns
+    assert_eq!(get(r#"["items", 2, "tags", -1]"#).unwrap(), Value::String("c".into()));
+    assert_eq!(
+        eval_expr("$.paths().map(p -> $.get_path(@p))", doc.clone()).unwrap(),
+        clove_lang::clove_value!([10, "a", [], 7, "b", "c", "zero", 1, 42])
+    );
+
+    // Missing or mismatched steps are null
+    for path in [r#""items.5.price""#, r#""nope.x""#, r#""items.price""#, r#""items.0.price.x""#] {
+        assert_eq!(get(path).unwrap(), Value::Null, "{}", path);
+    }
+
+    let err = get(r#""items..price""#).unwrap_err();
+    assert!(err.contains("items..price") && err.contains("expected a key (at offset 6)"), "{}", err);
+    assert!(get(r#""items[0""#).unwrap_err().contains("expected ]"));
+    assert!(get(r#""items[x]""#).unwrap_err().contains("expected an index, * or a quoted key"));
+    assert!(get(r#"["items", true]"#).unwrap_err().contains("string keys or integer indices"));
+    assert!(get("1").unwrap_err().contains("a path string or an array of keys"));
+    assert!(eval_expr("$.get_path()", doc).unwrap_err().contains("exactly one argument"));
+}
+
+#[test]
+fn test_method_walk() {
+    let doc = clove_lang::clove_value!({
+        "name": " Ada ",
+        "tags": [" x ", 1, {"note": " y "}],
+        "empty": [],
+    });
+
+    let trimmed = eval_expr(
+        r#"$.walk(match @ { s if @s.type() == "string" => @s.trim(), x => @x })"#,
+        doc.clone(),
+    );
+    assert_eq!(
+        trimmed.unwrap(),
+        clove_lang::clove_value!({"name": "Ada", "tags": ["x", 1, {"note": "y"}], "empty": []})
+    );
+
+    // Bottom-up: each node sees its children already replaced
+    let nested = clove_lang::clove_value!([[1, 2], [3, [4]]]);
+    assert_eq!(
+        eval_expr(r#"$.walk(match @ { a if @a.type() == "array" => @a.sum(), x => @x })"#, nested).unwrap(),
+        Value::Integer(10)
+    );
+    assert_eq!(eval_expr("$.walk(@.type())", doc.clone()).unwrap(), Value::String("object".into()));
+    assert_eq!(eval_expr("$[name].walk(@.length())", doc.clone()).unwrap(), Value::Integer(5));
+
+    // Deep documents do not use the call stack
+    let mut deep = Value::Integer(1);
+    for _ in 0..2_000 {
+        deep = json_array(vec![deep]);
+    }
+    assert_eq!(eval_expr("$.walk(@.type())", deep.clone()).unwrap(), Value::String("array".into()));
+    let paths = eval_expr("$.paths()", deep).unwrap();
+    assert_eq!(eval_expr("$[0].length()", paths).unwrap(), Value::Integer(2_000));
+
+    assert!(eval_expr("$.walk()", doc).unwrap_err().contains("requires a transform expression"));
+}
+
+#[test]
+fn test_method_paths() {
+    let doc = clove_lang::clove_value!({
+        "b": {"y": null, "x": [1, []]},
+        "a": 1,
+        "c": {},
+    });
+
+    // Depth-first in key and index order; empty containers are leaves
+    assert_eq!(
+        eval_expr("$.paths()", doc.clone()).unwrap(),
+        clove_lang::clove_value!([["a"], ["b", "x", 0], ["b", "x", 1], ["b", "y"], ["c"]])
+    );
+    assert_eq!(eval_expr("$.paths().all(@.length() > 0)", doc.clone()).unwrap(), Value::Boolean(true));
+    assert_eq!(eval_expr("$[a].paths()", doc).unwrap(), clove_lang::clove_value!([[]]));
+}
+
+#[test]
+fn test_error_contains_all_non_array_argument() {
+    let doc = json_object(vec![
+        ("tags", json_array(vec![])),
+    ]);
+
+    let result = eval_expr(r#"$[tags].contains_all("a")"#, doc);
+    assert!(result.unwrap_err().contains("argument must be array"));
+}
+
 // ============================================
 // Type Method Tests
 // ============================================
@@ -1187,7 +2954,7 @@ fn test_method_type_integer() {
 #[test]
 fn test_method_type_float() {
     let doc = json_object(vec![
-        ("value", Value::Float(3.14)),
+        ("value", Value::Float(2.5)),
     ]);
 
     let result = eval_expr("$[value].type()", doc).unwrap();
@@ -1366,7 +3133,7 @@ fn test_error_upper_on_non_string() {
 #[test]
 fn test_error_contains_on_non_string() {
     let doc = json_object(vec![
-        ("value", Value::Array(vec![])),
+        ("value", Value::Integer(42)),
     ]);
 
     let result = eval_expr(r#"$[value].contains("x")"#, doc);
@@ -1404,7 +3171,43 @@ fn test_error_unknown_method() {
 
     let result = eval_expr("$[value].foobar()", doc);
     assert!(result.is_err());
-    assert!(result.unwrap_err().contains("Unknown method"));
+    assert!(result.unwrap_err().contains("UnknownMethod"));
+}
+
+#[test]
+fn test_error_suggestions() {
+    let error = |query: &str| {
+        let query = Parser::new(Lexer::new(query)).unwrap().parse_query().unwrap();
+        Evaluator::new().eval_query(&query, Value::Null).unwrap_err()
+    };
+
+    let err = error("$ | !([1].fliter(@ > 0))");
+    assert_eq!(err.to_string(), "Unknown method: fliter (did you mean .filter()?)");
+    assert!(matches!(&err, clove_lang::EvalError::UnknownMethod { name, .. } if name == "fliter"), "{:?}", err);
+    let err = error("$ | !([1].lenght())");
+    assert_eq!(err.to_string(), "Unknown method: lenght (did you mean .length()?)");
+    assert_eq!(error("$ | !([1].foobar())").to_string(), "Unknown method: foobar");
+
+    // Candidates are every near miss, closest first
+    match error("$ | !([1].arg_mn())") {
+        clove_lang::EvalError::UnknownMethod { candidates, .. } => {
+            assert_eq!(candidates, ["arg_min", "arg_max"]);
+        }
+        other => panic!("Expected unknown method, got {:?}", other),
+    }
+
+    let err = error("$ | @total := 1 | !(@totl)");
+    assert_eq!(err.to_string(), "Undefined scope: @totl is not defined (did you mean @total?)");
+    let err = error("$ | !([1].map(item -> @itme))");
+    assert_eq!(err.to_string(), "Undefined scope: @itme is not defined (did you mean @item?)");
+    let err = error("&sum_all:1 := @1.sum()\n$ | !(&sumall[[1]])");
+    assert_eq!(err.to_string(), "Undefined function: &sumall is not defined (did you mean &sum_all[]?)");
+    match error("$ | !(&nothing_like_it[1])") {
+        clove_lang::EvalError::UndefinedFunction { name, candidates } => {
+            assert_eq!((name.as_str(), candidates), ("nothing_like_it", Vec::<String>::new()));
+        }
+        other => panic!("Expected undefined function, got {:?}", other),
+    }
 }
 
 // ============================================
@@ -1528,6 +3331,44 @@ fn test_null_coalesce_in_query_transform_fallback() {
     }
 }
 
+#[test]
+fn test_or_method_defaults_null() {
+    let doc = json_object(vec![
+        ("nick", Value::Null),
+        ("name", Value::String("ann".into())),
+        ("count", Value::Integer(0)),
+    ]);
+    assert_eq!(eval_expr("$[nick].or($[name])", doc.clone()).unwrap(), Value::String("ann".into()));
+    assert_eq!(eval_expr("$[missing].or(1)", doc.clone()).unwrap(), Value::Integer(1));
+    assert_eq!(eval_expr("$[count].or(1)", doc.clone()).unwrap(), Value::Integer(0));
+
+    // The default is only evaluated when it is used
+    assert_eq!(eval_expr("$[name].or(1 / 0)", doc.clone()).unwrap(), Value::String("ann".into()));
+    assert!(eval_expr("$[nick].or(1 / 0)", doc.clone()).is_err());
+
+    // Keywords after `.` are names, so `or` still works as an operator
+    assert_eq!(eval_expr("$[nick].or(false) or true", doc.clone()).unwrap(), Value::Boolean(true));
+    assert!(eval_expr("$[name].or()", doc).unwrap_err().contains(".or() takes 1 argument, got 0"));
+}
+
+#[test]
+fn test_coalesce_builtin() {
+    let doc = json_object(vec![
+        ("level", Value::String("warn".into())),
+    ]);
+    assert_eq!(
+        eval_expr("&coalesce[$[severity], $[priority], $[level], \"info\"]", doc.clone()).unwrap(),
+        Value::String("warn".into())
+    );
+    assert_eq!(eval_expr("&coalesce[null, $[missing]]", doc.clone()).unwrap(), Value::Null);
+    assert_eq!(eval_expr("&coalesce[$[level], 1 / 0]", doc.clone()).unwrap(), Value::String("warn".into()));
+    assert!(eval_expr("&coalesce[]", doc.clone()).unwrap_err().contains("at least 1 argument"));
+
+    // A UDF of the same name takes precedence
+    let result = eval_query("&coalesce:1 := @1 + 1\n$ | !(&coalesce[1])", doc).unwrap();
+    assert_eq!(result, Value::Integer(2));
+}
+
 // ============================================
 // .matches() Regex Method Tests
 // ============================================
@@ -1604,7 +3445,77 @@ fn test_matches_invalid_regex_error() {
     let doc = json_object(vec![]);
     let result = eval_expr(r#""test".matches("[invalid")"#, doc);
     assert!(result.is_err());
-    assert!(result.unwrap_err().contains("invalid regex"));
+    assert!(result.unwrap_err().contains("InvalidRegex"));
+}
+
+#[test]
+fn test_matches_invalid_regex_details() {
+    let eval = |query: &str, evaluator: Evaluator| {
+        let expr = Parser::new(Lexer::new(query)).unwrap().parse().unwrap();
+        let mut evaluator = evaluator;
+        evaluator.eval_expression(&expr, Value::String("text".into()))
+    };
+
+    // Syntax errors carry the pattern and the character offset of the error
+    match eval(r#"$.matches("é(b")"#, Evaluator::new()) {
+        Err(EvalError::InvalidRegex { pattern, message, position }) => {
+            assert_eq!(pattern, "é(b");
+            assert_eq!(message, "unclosed group");
+            assert_eq!(position, Some(1));
+        }
+        other => panic!("expected InvalidRegex, got {:?}", other),
+    }
+    let error = eval(r#"$.matches("[z-a]")"#, Evaluator::new()).unwrap_err();
+    assert!(error.to_string().starts_with("Invalid regex \"[z-a]\": invalid character class range"));
+
+    // Patterns too large or too deeply nested to compile are rejected
+    let error = eval(r#"$.matches("x{500000}")"#, Evaluator::new()).unwrap_err();
+    assert!(matches!(error, EvalError::InvalidRegex { position: None, .. }));
+    assert!(error.to_string().contains("more than the limit of 4194304 bytes"));
+    let nested = format!(r#"$.matches("{}a{}")"#, "(".repeat(100), ")".repeat(100));
+    assert!(matches!(eval(&nested, Evaluator::new()), Err(EvalError::InvalidRegex { .. })));
+    assert_eq!(eval(r#"$.matches("\\w{20}")"#, Evaluator::new()).unwrap(), Value::Boolean(false));
+    let limited = Evaluator::new().with_regex_size_limit(10_000);
+    assert!(matches!(eval(r#"$.matches("\\w{20}")"#, limited), Err(EvalError::InvalidRegex { .. })));
+}
+
+#[test]
+fn test_regex_cache() {
+    let cache = RegexCache::new(2);
+    assert_eq!(cache.capacity(), 2);
+    let words = clove_lang::clove_value!(["apple", "banana", "cherry"]);
+    let expr = Parser::new(Lexer::new(r#"$.filter(@.matches("an")).length()"#)).unwrap().parse().unwrap();
+
+    // One pattern, compiled once for every element and evaluator
+    for _ in 0..2 {
+        let mut evaluator = Evaluator::new().with_regex_cache(cache.clone());
+        assert_eq!(evaluator.eval_expression(&expr, words.clone()).unwrap(), Value::Integer(1));
+    }
+    assert_eq!(cache.len(), 1);
+
+    // The least recently used pattern makes way for a new one
+    let expr = Parser::new(Lexer::new(r#"[$.matches("a"), $.matches("an"), $.matches("b")]"#))
+        .unwrap()
+        .parse()
+        .unwrap();
+    let mut evaluator = Evaluator::new().with_regex_cache(cache.clone());
+    let result = evaluator.eval_expression(&expr, Value::String("banana".into())).unwrap();
+    assert_eq!(result, clove_lang::clove_value!([true, true, true]));
+    assert_eq!(cache.len(), 2);
+
+    // Invalid patterns are not cached
+    let expr = Parser::new(Lexer::new(r#"$.matches("(")"#)).unwrap().parse().unwrap();
+    assert!(evaluator.eval_expression(&expr, Value::Null).is_err());
+    assert_eq!(cache.len(), 2);
+    cache.clear();
+    assert!(cache.is_empty());
+
+    // A cache of no patterns still compiles them
+    let none = RegexCache::new(0);
+    let expr = Parser::new(Lexer::new(r#"$.matches("an")"#)).unwrap().parse().unwrap();
+    let mut evaluator = Evaluator::new().with_regex_cache(none.clone());
+    assert_eq!(evaluator.eval_expression(&expr, Value::String("banana".into())).unwrap(), Value::Boolean(true));
+    assert!(none.is_empty());
 }
 
 #[test]
@@ -1688,6 +3599,18 @@ fn test_delete_missing_field_noop() {
     assert_eq!(result, doc);
 }
 
+#[test]
+fn test_delete_negative_index() {
+    let doc = json_object(vec![
+        ("items", json_array(vec![Value::Integer(1), Value::Integer(2), Value::Integer(3)])),
+    ]);
+    let result = eval_query("$ | -($[items][-2])", doc).unwrap();
+    assert_eq!(
+        result,
+        json_object(vec![("items", json_array(vec![Value::Integer(1), Value::Integer(3)]))])
+    );
+}
+
 #[test]
 fn test_delete_chain_multiple() {
     let doc = json_object(vec![
@@ -1740,6 +3663,30 @@ fn test_delete_then_filter() {
     }
 }
 
+#[test]
+fn test_delete_wildcard() {
+    let doc = clove_lang::clove_value!({
+        "users": [
+            {"name": "ann", "password": "x", "cards": [{"last4": "1234", "cvv": "1"}]},
+            {"name": "bob", "ssn": "000", "cards": []}
+        ],
+        "teams": {"a": {"token": "t"}, "b": {}}
+    });
+    let result = eval_query(
+        "$ | -($[users][*][password]) | -($[users][*][ssn]) | -($[users][*][cards][*][cvv]) | -($[teams][*][token])",
+        doc.clone(),
+    );
+    assert_eq!(result.unwrap(), clove_lang::clove_value!({
+        "users": [{"name": "ann", "cards": [{"last4": "1234"}]}, {"name": "bob", "cards": []}],
+        "teams": {"a": {}, "b": {}}
+    }));
+
+    let result = eval_query("$ | foreach $[users] as @u { -(@u[cards][*]) } | !($[users].map(@[cards]))", doc.clone());
+    assert_eq!(result.unwrap(), clove_lang::clove_value!([[], []]));
+    let result = eval_query("$ | -($[users][0][*]) | -($[missing][*][x]) | !($[users])", doc);
+    assert_eq!(result.unwrap(), clove_lang::clove_value!([{}, {"name": "bob", "ssn": "000", "cards": []}]));
+}
+
 #[test]
 fn test_delete_deep_nested_missing_intermediate_noop() {
     let doc = json_object(vec![
@@ -1748,3 +3695,369 @@ fn test_delete_deep_nested_missing_intermediate_noop() {
     let result = eval_query("$ | -($[a][b][c])", doc.clone()).unwrap();
     assert_eq!(result, doc);
 }
+
+// ============================================
+// Memoization Tests
+// ============================================
+
+#[test]
+fn test_memoized_results_follow_the_document() {
+    let doc = json_object(vec![("nums", ints(&[1, 2, 3]))]);
+    let query = "$ | ?($[nums].sum() > 1) | ~($[total] := $[nums].sum()) \
+                 | ~($[nums] := @ * 2) | !([$[total], $[nums].sum(), $[nums].map(@ * 12 / $[nums].sum())])";
+    let expected = Value::array(vec![Value::Integer(6), Value::Integer(12), ints(&[2, 4, 6])]);
+
+    let parsed = Parser::new(Lexer::new(query)).unwrap().parse_query().unwrap();
+    for memoize in [true, false] {
+        let mut evaluator = Evaluator::new().with_memoization(memoize);
+        assert_eq!(evaluator.eval_query(&parsed, doc.clone()).unwrap(), expected);
+        // A second document must not see the first one's results
+        let other = json_object(vec![("nums", ints(&[5]))]);
+        let result = evaluator.eval_query(&parsed, other).unwrap();
+        assert_eq!(result, Value::array(vec![Value::Integer(5), Value::Integer(10), ints(&[12])]));
+    }
+}
+
+#[test]
+fn test_outer_lambda_param() {
+    let doc = clove_lang::clove_value!({"a": [1, 2, 3], "b": [2, 3]});
+
+    let result = eval_expr("$[a].filter($[b].any(@ == @@))", doc.clone());
+    assert_eq!(result.unwrap(), ints(&[2, 3]));
+    let result = eval_expr("$[a].take(1).map($[b].map($[b].map(@@@ * 100 + @@ * 10 + @)))", doc.clone());
+    assert_eq!(result.unwrap(), clove_lang::clove_value!([[[122, 123], [132, 133]]]));
+
+    // A per-item transform's item encloses the lambdas in its value
+    let result = eval_query("$ | ~($[a] := $[b].filter(@ > @@).count()) | !($[a])", doc.clone());
+    assert_eq!(result.unwrap(), ints(&[2, 1, 0]));
+
+    let err = eval_expr("$[a].map(@@)", doc).unwrap_err();
+    assert!(err.contains("@@ needs 1 lambda(s) around the current one"), "{}", err);
+}
+
+#[test]
+fn test_named_lambdas() {
+    let doc = clove_lang::clove_value!({
+        "orders": [
+            {"rate": 2, "items": [{"qty": 1}, {"qty": 3}]},
+            {"rate": 10, "items": [{"qty": 5}]}
+        ],
+        "xs": [1, 2, 3],
+        "ys": [2, 3]
+    });
+
+    let result = eval_expr("$[orders].map(order -> @order[items].map(@[qty] * @order[rate]))", doc.clone());
+    assert_eq!(result.unwrap(), clove_lang::clove_value!([[2, 6], [50]]));
+    let result = eval_expr("$[xs].map(x -> $[ys].filter(y -> @y > @x).count())", doc.clone());
+    assert_eq!(result.unwrap(), ints(&[2, 1, 0]));
+
+    // The inner name hides the outer one
+    let result = eval_expr("$[xs].map(x -> $[ys].map(x -> @x).sum())", doc.clone());
+    assert_eq!(result.unwrap(), ints(&[5, 5, 5]));
+
+    // As a per-item transform value, and passed to a UDF's lambda parameter
+    let result = eval_query("$ | ~($[xs] := x -> @x * 10) | !($[xs])", doc.clone());
+    assert_eq!(result.unwrap(), ints(&[10, 20, 30]));
+    let query = "&apply_all:2 := @1.map(@2)\n$ | !($[ys].map(y -> &apply_all[$[xs], x -> @x * @y]))";
+    assert_eq!(
+        eval_query(query, doc.clone()).unwrap(),
+        Value::array(vec![ints(&[2, 4, 6]), ints(&[3, 6, 9])])
+    );
+
+    let err = eval_expr("[x -> 1]", doc).unwrap_err();
+    assert!(err.contains("can only be passed to a method that takes a lambda"), "{}", err);
+}
+
+// ============================================
+// UDF Call Tests
+// ============================================
+
+#[test]
+fn test_udf_calls() {
+    let doc = json_object(vec![
+        ("price", Value::Integer(200)),
+        ("items", Value::array(vec![
+            json_object(vec![("price", Value::Integer(50))]),
+            json_object(vec![("price", Value::Integer(150))]),
+        ])),
+    ]);
+
+    let query = "&expensive:1 := ?(@1[price] > 100)\n$ | !($[items].filter(&expensive[@]))";
+    assert_eq!(
+        eval_query(query, doc.clone()).unwrap(),
+        Value::array(vec![json_object(vec![("price", Value::Integer(150))])])
+    );
+
+    let query = "&discount:2 := ~(@1 := @1 - @1 * @2)\n$ | ~($[price] := &discount[$[price], 0.25])";
+    match eval_query(query, doc.clone()).unwrap() {
+        Value::Object(map) => assert_eq!(map.get("price"), Some(&Value::Integer(150))),
+        other => panic!("Expected object, got {:?}", other),
+    }
+
+    let err = eval_query("$ | !(&missing[1])", doc.clone()).unwrap_err();
+    assert!(err.contains("UndefinedFunction"), "{}", err);
+    let err = eval_query("&one:1 := @1\n$ | !(&one[1, 2])", doc).unwrap_err();
+    assert!(err.contains("takes 1 argument(s), got 2"), "{}", err);
+}
+
+#[test]
+fn test_udf_lambda_arguments() {
+    let doc = json_object(vec![("nums", ints(&[1, 2, 3]))]);
+
+    // @2 is used as the lambda of .map(), so `@ * 10` is evaluated per element
+    let query = "&apply_all:2 := @1.map(@2)\n$ | !(&apply_all[$[nums], @ * 10])";
+    assert_eq!(eval_query(query, doc.clone()).unwrap(), ints(&[10, 20, 30]));
+
+    // Passed on to another UDF's lambda parameter, it stays a lambda
+    let query = "&apply_all:2 := @1.map(@2)\n\
+                 &twice:2 := &apply_all[&apply_all[@1, @2], @2]\n\
+                 $ | !(&twice[$[nums], @ + 1])";
+    assert_eq!(eval_query(query, doc.clone()).unwrap(), ints(&[3, 4, 5]));
+
+    // Other parameters are evaluated at the call site, where @ is the
+    // caller's element, not the one the body is iterating over
+    let query = "&above:2 := @1.filter(@ > @2)\n$ | !($[nums].map(&above[$[nums], @].count()))";
+    assert_eq!(eval_query(query, doc).unwrap(), ints(&[2, 1, 0]));
+}
+
+#[test]
+fn test_udf_defaults_and_rest() {
+    let doc = json_object(vec![("price", Value::Integer(100))]);
+
+    let query = "&tax(price, rate = 0.2) := @price * @rate\n$ | !([&tax[$[price]], &tax[$[price], 0.5]])";
+    assert_eq!(eval_query(query, doc.clone()).unwrap(), ints(&[20, 50]));
+
+    // Defaults may use the parameters before them, and by-name parameters
+    // default to a lambda
+    let query = "&clamp(x, lo = 0, hi = @lo + 10) := [@x, @lo, @hi]\n$ | !(&clamp[5])";
+    assert_eq!(eval_query(query, doc.clone()).unwrap(), ints(&[5, 0, 10]));
+    let query = "&scale(xs, f = @ * 2) := @xs.map(@f)\n$ | !([&scale[[1, 2]], &scale[[1, 2], @ + 1]])";
+    assert_eq!(eval_query(query, doc.clone()).unwrap(), Value::array(vec![ints(&[2, 4]), ints(&[2, 3])]));
+
+    let query = "&total(first, ...) := @first + @rest.sum()\n$ | !([&total[1], &total[1, 2, 3]])";
+    assert_eq!(eval_query(query, doc.clone()).unwrap(), ints(&[1, 6]));
+
+    let err = eval_query("&tax(price, rate = 0.2) := @price * @rate\n$ | !(&tax[])", doc.clone()).unwrap_err();
+    assert!(err.contains("takes 1 to 2 argument(s), got 0"), "{}", err);
+    let err = eval_query("&total(first, ...) := @first\n$ | !(&total[])", doc).unwrap_err();
+    assert!(err.contains("takes at least 1 argument(s), got 0"), "{}", err);
+}
+
+#[test]
+fn test_udf_recursion_is_bounded() {
+    let err = eval_query("&forever:1 := &forever[@1]\n$ | !(&forever[1])", Value::Null).unwrap_err();
+    assert!(err.starts_with("LimitExceeded") && err.contains("nested too deeply"), "{}", err);
+    assert!(err.contains("in tail position"), "{}", err);
+
+    let err = eval_query("&deeper:1 := 1 + &deeper[@1]\n$ | !(&deeper[1])", Value::Null).unwrap_err();
+    assert!(err.starts_with("LimitExceeded") && err.contains("more than 64 levels"), "{}", err);
+}
+
+#[test]
+fn test_recursive_udfs() {
+    // Recursion over a tree, nesting one call per level
+    let tree = clove_lang::clove_value!({"name": "a", "children": [
+        {"name": "b", "children": [{"name": "c", "children": []}]},
+        {"name": "d", "children": []}
+    ]});
+    let query = "&names:1 := [[@1[name]], @1[children].flat_map(&names[@])].flatten()\n$ | !(&names[$])";
+    assert_eq!(eval_query(query, tree).unwrap(), clove_lang::clove_value!(["a", "b", "c", "d"]));
+
+    // Calls in tail position loop instead of nesting, so they can go far
+    // deeper than the call depth limit
+    let query = "&count:2 := match @1 { 0 => @2, n => &count[@n - 1, @2 + 1] }\n$ | !(&count[$, 0])";
+    assert_eq!(eval_query(query, Value::Integer(10_000)).unwrap(), Value::Integer(10_000));
+    let query = "&sum(xs, total = 0) := match @xs { [] => @total, [x, ...] => &sum[@xs.skip(1), @total + @x] }\n\
+                 $ | !(&sum[$])";
+    let numbers = Value::Array((1..=1000).map(Value::Integer).collect());
+    assert_eq!(eval_query(query, numbers).unwrap(), Value::Integer(500_500));
+    let list = (0..200).fold(clove_lang::clove_value!({"id": "end"}), |next, _| {
+        clove_lang::clove_value!({"id": "node", "next": next})
+    });
+    let query = "&last:1 := match @1[next] { null => @1, _ => null } ?? &last[@1[next]]\n$ | !(&last[$][id])";
+    assert_eq!(eval_query(query, list).unwrap(), Value::String("end".into()));
+
+    // A call that is not the last thing the body does still nests
+    let query = Parser::new(Lexer::new("&depth:1 := match @1 { 0 => 0, n => 1 + &depth[@n - 1] }\n$ | !(&depth[$])"))
+        .unwrap()
+        .parse_query()
+        .unwrap();
+    let result = Evaluator::new().with_max_call_depth(10).eval_query(&query, Value::Integer(9));
+    assert_eq!(result.unwrap(), Value::Integer(9));
+    let result = Evaluator::new().with_max_call_depth(10).eval_query(&query, Value::Integer(10));
+    assert!(matches!(result, Err(clove_lang::EvalError::LimitExceeded(_))), "{:?}", result);
+}
+
+// ============================================
+// Time Window Tests
+// ============================================
+
+#[test]
+fn test_bucket_by_time() {
+    let doc = clove_lang::clove_value!({"logs": [
+        {"ts": "2024-05-01T10:04:59Z", "level": "error"},
+        {"ts": "2024-05-01T10:00:00Z", "level": "info"},
+        {"ts": "2024-05-01T12:07:30+02:00", "level": "error"},
+        {"ts": 1714558200, "level": "error"}
+    ]});
+
+    let result = eval_expr(r#"$[logs].bucket_by_time(@[ts], "5m").map([@[start], @[end], @[count]])"#, doc.clone()).unwrap();
+    assert_eq!(
+        result,
+        clove_lang::clove_value!([
+            ["2024-05-01T10:00:00Z", "2024-05-01T10:05:00Z", 2],
+            ["2024-05-01T10:05:00Z", "2024-05-01T10:10:00Z", 1],
+            ["2024-05-01T10:10:00Z", "2024-05-01T10:15:00Z", 1]
+        ])
+    );
+
+    // Items keep their input order within a window
+    let result = eval_expr(r#"$[logs].bucket_by_time(@[ts], "1h").first()[items].map(@[level])"#, doc.clone()).unwrap();
+    assert_eq!(result, clove_lang::clove_value!(["error", "info", "error", "error"]));
+
+    assert!(eval_expr(r#"$[logs].bucket_by_time(@[level], "5m")"#, doc.clone())
+        .unwrap_err()
+        .contains(r#"timestamps must be RFC 3339 strings or Unix seconds, got \"error\""#));
+    assert!(eval_expr(r#"$[logs].bucket_by_time(@[ts], "5 minutes")"#, doc.clone())
+        .unwrap_err()
+        .contains("interval must be a duration"));
+    assert!(eval_expr(r#"$[logs].bucket_by_time(@[ts])"#, doc).is_err());
+}
+
+#[test]
+fn test_rate_per() {
+    let doc = clove_lang::clove_value!({"logs": [
+        {"ts": "2024-05-01T10:00:10Z", "level": "error"},
+        {"ts": "2024-05-01T10:00:50.5Z", "level": "error"},
+        {"ts": "2024-05-01T10:01:00Z", "level": "info"},
+        {"ts": "2024-05-01T10:03:59.999Z", "level": "error"}
+    ]});
+
+    let result = eval_expr(
+        r#"$[logs].filter(@[level] == "error").map(@[ts]).rate_per("1m").map(@[count])"#,
+        doc.clone(),
+    )
+    .unwrap();
+    assert_eq!(result, clove_lang::clove_value!([2, 0, 0, 1]));
+
+    let result = eval_expr(r#"$[logs].map(@[ts]).rate_per("2m").map(@[start])"#, doc.clone()).unwrap();
+    assert_eq!(result, clove_lang::clove_value!(["2024-05-01T10:00:00Z", "2024-05-01T10:02:00Z"]));
+
+    // Fractions of a second and Unix seconds
+    let result = eval_expr(r#"[1.25, 1.75, 0].rate_per("500ms")"#, doc.clone()).unwrap();
+    assert_eq!(
+        result,
+        clove_lang::clove_value!([
+            {"start": "1970-01-01T00:00:00Z", "count": 1},
+            {"start": "1970-01-01T00:00:00.500Z", "count": 0},
+            {"start": "1970-01-01T00:00:01Z", "count": 1},
+            {"start": "1970-01-01T00:00:01.500Z", "count": 1}
+        ])
+    );
+
+    assert_eq!(eval_expr(r#"[].rate_per("1m")"#, doc.clone()).unwrap(), Value::Array(vec![]));
+    assert!(eval_expr(r#"["2000-01-01", "2024-01-01"].rate_per("1s")"#, doc.clone())
+        .unwrap_err()
+        .contains("use a longer interval"));
+    assert!(eval_expr(r#"["2024-02-30"].rate_per("1d")"#, doc).is_err());
+}
+
+#[test]
+fn test_match() {
+    let doc = clove_lang::clove_value!({
+        "events": [
+            {"type": "click", "x": 4, "y": 1},
+            {"type": "scroll", "by": 10},
+            {"type": "key"},
+            [1, 2, 3],
+            [1],
+            "done"
+        ]
+    });
+
+    let query = r#"$[events].map(match @ {
+        {"type": "click", "x": x} => x,
+        {"type": "scroll"} => 0,
+        [first, _, ...] => first * 100,
+        [only] => @only,
+        "done" => true,
+        _ => null,
+    })"#;
+    assert_eq!(
+        eval_expr(query, doc.clone()).unwrap(),
+        clove_lang::clove_value!([4, 0, null, 100, 1, true])
+    );
+
+    // Guards, nested patterns, and no matching arm
+    let query = r#"$[events].map(e -> match @e { {"x": x, "y": y} if x > y => x - y, {"by": b} if b > 99 => b })"#;
+    assert_eq!(
+        eval_expr(query, doc.clone()).unwrap(),
+        clove_lang::clove_value!([3, null, null, null, null, null])
+    );
+    let result = eval_expr(r#"match {"a": {"b": [-1, 2]}} { {"a": {"b": [-1, n]}} => n }"#, doc);
+    assert_eq!(result.unwrap(), Value::Integer(2));
+}
+
+#[test]
+fn test_try_catch() {
+    let doc = clove_lang::clove_value!({"rows": [{"price": 2}, {"price": "n/a"}, {"price": 5}]});
+
+    // One bad row no longer fails the whole map
+    let result = eval_expr("$[rows].map(try @[price] * 10 catch null)", doc.clone());
+    assert_eq!(result.unwrap(), clove_lang::clove_value!([20, null, 50]));
+    let result = eval_expr("$[rows].map(try @[price] * 10 catch -1).sum()", doc.clone());
+    assert_eq!(result.unwrap(), Value::Integer(69));
+    assert_eq!(eval_expr("try 1 / 0 catch \"inf\"", doc.clone()).unwrap(), Value::String("inf".to_string()));
+
+    // Errors in the fallback are not caught
+    assert!(eval_expr("try 1 / 0 catch 2 / 0", doc.clone()).is_err());
+    assert!(eval_expr("$[rows].map(@[price] * 10)", doc).is_err());
+}
+
+#[test]
+fn test_when() {
+    let query = "$ | when ($[legacy][?]) { ~($[id] := $[legacy][id]) | -($[legacy]) } | ~($[seen] := true)";
+    let result = eval_query(query, clove_lang::clove_value!({"legacy": {"id": 4}}));
+    assert_eq!(result.unwrap(), clove_lang::clove_value!({"id": 4, "seen": true}));
+    let result = eval_query(query, clove_lang::clove_value!({"id": 1}));
+    assert_eq!(result.unwrap(), clove_lang::clove_value!({"id": 1, "seen": true}));
+
+    // Stages in the body see the document as the earlier ones left it, and
+    // a filter in the body ends the pipeline
+    let query = "$ | when ($[n] > 0) { ~($[n] := $[n] * 10) | when ($[n] > 50) { ?(false) } } | !($[n])";
+    assert_eq!(eval_query(query, clove_lang::clove_value!({"n": 2})).unwrap(), Value::Integer(20));
+    assert_eq!(eval_query(query, clove_lang::clove_value!({"n": -2})).unwrap(), Value::Integer(-2));
+    assert_eq!(eval_query(query, clove_lang::clove_value!({"n": 6})).unwrap(), Value::Null);
+
+    // Optimizing the body gives the same results
+    let parsed = Parser::new(Lexer::new(query)).unwrap().parse_query().unwrap().optimize();
+    let result = Evaluator::new().eval_query(&parsed, clove_lang::clove_value!({"n": 2}));
+    assert_eq!(result.unwrap(), Value::Integer(20));
+}
+
+#[test]
+fn test_foreach() {
+    let doc = clove_lang::clove_value!({
+        "rate": 2,
+        "items": [{"qty": 1, "draft": true}, {"qty": 0}, {"qty": 3, "tags": ["a"]}]
+    });
+    let query = "$ | foreach $[items] as @item {
+        ?(@item[qty] > 0) | ~(@item[total] := @item[qty] * $[rate]) | -(@item[draft])
+        | when (@item[tags][?]) { ~(@item[tags] := @.upper()) }
+    } | !($[items])";
+    let expected = clove_lang::clove_value!([{"qty": 1, "total": 2}, {"qty": 3, "tags": ["A"], "total": 6}]);
+    assert_eq!(eval_query(query, doc.clone()).unwrap(), expected);
+    let optimized = Parser::new(Lexer::new(query)).unwrap().parse_query().unwrap().optimize();
+    assert_eq!(Evaluator::new().eval_query(&optimized, doc.clone()).unwrap(), expected);
+
+    // An expression replaces the element; loops nest
+    let result = eval_query("$ | foreach $[items] as @item { @item[qty] * $[rate] } | !($[items])", doc.clone());
+    assert_eq!(result.unwrap(), ints(&[2, 0, 6]));
+    let query = "$ | foreach $[groups] as @g { foreach @g[rows] as @row { ~(@row[group] := @g[name]) } }";
+    let result = eval_query(query, clove_lang::clove_value!({"groups": [{"name": "x", "rows": [{}, {}]}]}));
+    assert_eq!(result.unwrap(), clove_lang::clove_value!({"groups": [{"name": "x", "rows": [{"group": "x"}, {"group": "x"}]}]}));
+
+    // Only the element can change, and the path must hold an array
+    assert!(eval_query("$ | foreach $[items] as @item { ~($[rate] := 1) }", doc.clone()).is_err());
+    assert!(eval_query("$ | foreach $[rate] as @item { @item }", doc).is_err());
+}
@@ -0,0 +1,170 @@
+use clove_lang::cli::{CheckOptions, CheckResult, clove_to_json, execute_check};
+use clove_lang::jq::{JqError, translate};
+use serde_json::json;
+
+fn run(clove: &str, input: &serde_json::Value) -> serde_json::Value {
+    let options = CheckOptions {
+        query: clove.to_string(),
+        input: Some(input.to_string()),
+        ..Default::default()
+    };
+    match execute_check(&options) {
+        Ok(CheckResult::Success { value, .. }) => clove_to_json(value),
+        other => panic!("{} failed: {:?}", clove, other),
+    }
+}
+
+#[test]
+fn test_translate_paths() {
+    let cases = [
+        (".", "$"),
+        (".a", "$[a]"),
+        (".a.b[0]", "$[a][b][0]"),
+        (".a[-1]", "$[a][-1]"),
+        (".[\"x y\"]", "$[\"x y\"]"),
+        (".\"x y\".z", "$[\"x y\"][z]"),
+        (".a?.b", "$[a][b]"),
+        (".[2:5]", "$.skip(2).take(3)"),
+        (".items[:2]", "$[items].take(2)"),
+    ];
+    for (jq, clove) in cases {
+        assert_eq!(translate(jq).unwrap(), clove, "{}", jq);
+    }
+}
+
+#[test]
+fn test_translate_operators() {
+    let cases = [
+        (".a + .b * 2", "$[a] + $[b] * 2"),
+        ("(.a + .b) * 2", "($[a] + $[b]) * 2"),
+        (".a - (.b - .c)", "$[a] - ($[b] - $[c])"),
+        ("-.a + 1", "-$[a] + 1"),
+        (".a > 1 and .b == \"x\" or .c", "$[a] > 1 && $[b] == \"x\" || $[c]"),
+        (".a and (.b or .c)", "$[a] && ($[b] || $[c])"),
+        (".a // \"default\"", "$[a] ?? \"default\""),
+        (".ok | not", "($[ok] ?? false) == false"),
+    ];
+    for (jq, clove) in cases {
+        assert_eq!(translate(jq).unwrap(), clove, "{}", jq);
+    }
+}
+
+#[test]
+fn test_translate_select_map_and_collect() {
+    let cases = [
+        ("select(.age >= 18)", "$ | ?($[age] >= 18)"),
+        ("select(.age >= 18) | .name", "$ | ?($[age] >= 18) | !($[name])"),
+        ("map(.name)", "$.map(@[name])"),
+        (".items | map(.price * .qty) | add", "$[items].map(@[price] * @[qty]).sum()"),
+        ("map(select(.a > 1) | .b)", "$.filter(@[a] > 1).map(@[b])"),
+        ("[.items[] | select(.ok) | .id]", "$[items].filter(@[ok]).map(@[id])"),
+        ("[.[] | .a | select(. > 1)]", "$.map(@[a]).filter(@ > 1)"),
+        ("[.a, .b]", "[$[a], $[b]]"),
+        ("{name: .user.name, id}", "{\"name\": $[user][name], \"id\": $[id]}"),
+        ("[.xs[] | {v: .}]", "$[xs].map({\"v\": @})"),
+    ];
+    for (jq, clove) in cases {
+        assert_eq!(translate(jq).unwrap(), clove, "{}", jq);
+    }
+}
+
+#[test]
+fn test_translate_builtins() {
+    let cases = [
+        (".xs | length", "$[xs].length()"),
+        ("keys", "$.keys()"),
+        (".xs | sort | reverse | first", "$[xs].sort().reverse().first()"),
+        ("sort_by(.n)", "$.sort_by([@[n]])"),
+        ("unique_by(.id)", "$.unique_by(@[id])"),
+        (".xs | any(. > 2)", "$[xs].any(@ > 2)"),
+        (".name | ascii_downcase | startswith(\"a\")", "$[name].lower().startswith(\"a\")"),
+        (".config | has(\"debug\")", "$[config].has(\"debug\")"),
+        (".s | test(\"^a+$\")", "$[s].matches(\"^a+$\")"),
+    ];
+    for (jq, clove) in cases {
+        assert_eq!(translate(jq).unwrap(), clove, "{}", jq);
+    }
+}
+
+#[test]
+fn test_translate_assignments() {
+    let cases = [
+        (".version = \"2.0\"", "$ | ~($[version] := \"2.0\")"),
+        (".tags |= sort", "$ | ~($[tags] := $[tags].sort())"),
+        (".count += 1", "$ | ~($[count] := $[count] + 1)"),
+        (".name //= \"anon\"", "$ | ~($[name] := $[name] ?? \"anon\")"),
+        ("del(.tmp, .a.b)", "$ | -($[tmp]) | -($[a][b])"),
+        (
+            ".n = 1 | select(.ok) | .n",
+            "$ | ~($[n] := 1) | ?($[ok]) | !($[n])",
+        ),
+    ];
+    for (jq, clove) in cases {
+        assert_eq!(translate(jq).unwrap(), clove, "{}", jq);
+    }
+}
+
+#[test]
+fn test_translations_run_like_jq() {
+    let doc = json!({
+        "users": [
+            {"name": "Alice", "age": 30, "tags": ["b", "a"]},
+            {"name": "bob", "age": 15, "tags": []},
+            {"name": "Carol", "age": 42, "tags": ["c"]}
+        ],
+        "count": 2,
+        "tmp": true
+    });
+
+    // Expected values are what jq produces for the same filter and input
+    let cases = [
+        (".users[0].name", json!("Alice")),
+        ("[.users[] | select(.age >= 18) | .name]", json!(["Alice", "Carol"])),
+        (".users | map(.age) | add", json!(87)),
+        (".users | map(select(.tags | length > 0)) | length", json!(2)),
+        (".users[1].nickname // .users[1].name", json!("bob")),
+        (".users | sort_by(.age) | first | .name", json!("bob")),
+        ("{first: .users[0].name, total: (.users | length)}", json!({"first": "Alice", "total": 3})),
+        (".users[-1].tags | sort", json!(["c"])),
+        (".count += 1 | del(.tmp, .users) ", json!({"count": 3})),
+        (".users[0].tags |= sort | .users[0].tags", json!(["a", "b"])),
+        (".users | map(.name | ascii_downcase | startswith(\"a\") | not)", json!([false, true, true])),
+    ];
+    for (jq, expected) in cases {
+        let clove = translate(jq).unwrap();
+        assert_eq!(run(&clove, &doc), expected, "{} => {}", jq, clove);
+    }
+}
+
+#[test]
+fn test_reports_unsupported_constructs() {
+    match translate("if .a then $x else .[] end | reduce .[] as $i (0; . + $i)") {
+        Err(JqError::Unsupported(constructs)) => {
+            assert_eq!(constructs.len(), 2);
+            assert!(constructs[0].contains("conditional"));
+            assert!(constructs[1].contains("reduce"));
+        }
+        other => panic!("expected unsupported constructs, got {:?}", other),
+    }
+
+    for jq in [".[] | .a", ".a, .b", "..", "\"\\(.a)\"", ".a as $x | $x", "tostream", "@base64"] {
+        assert!(
+            matches!(translate(jq), Err(JqError::Unsupported(_))),
+            "{} should be unsupported",
+            jq
+        );
+    }
+}
+
+#[test]
+fn test_parse_errors() {
+    for jq in [".a |", "map(", ".[1", "{a: }", "\"open", "def f: .; f", ".a ^ 2"] {
+        assert!(
+            matches!(translate(jq), Err(JqError::Parse { .. })),
+            "{} should fail to parse",
+            jq
+        );
+    }
+    let message = translate(".a |").unwrap_err().to_string();
+    assert!(message.contains("offset 4"), "{}", message);
+}
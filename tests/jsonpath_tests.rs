@@ -0,0 +1,176 @@
+use clove_lang::cli::{CheckOptions, CheckResult, execute_check, json_to_clove};
+use clove_lang::jsonpath::{JsonPath, JsonPathError};
+use clove_lang::{Evaluator, Value};
+use serde_json::json;
+
+fn store() -> serde_json::Value {
+    json!({
+        "store": {
+            "book": [
+                {"category": "reference", "author": "Nigel Rees", "title": "Sayings of the Century", "price": 8.95},
+                {"category": "fiction", "author": "Evelyn Waugh", "title": "Sword of Honour", "price": 12.99},
+                {"category": "fiction", "author": "Herman Melville", "title": "Moby Dick", "isbn": "0-553-21311-3", "price": 8.99},
+                {"category": "fiction", "author": "J. R. R. Tolkien", "title": "The Lord of the Rings", "isbn": "0-395-19395-8", "price": 22.99}
+            ],
+            "bicycle": {"color": "red", "price": 19.95}
+        },
+        "expensive": 10
+    })
+}
+
+fn select(path: &str) -> serde_json::Value {
+    let nodes = JsonPath::parse(path)
+        .unwrap_or_else(|e| panic!("{}: {}", path, e))
+        .evaluate(&json_to_clove(store()));
+    clove_lang::cli::clove_to_json(Value::Array(nodes))
+}
+
+#[test]
+fn test_evaluate_children_and_indices() {
+    assert_eq!(select("$.expensive"), json!([10]));
+    assert_eq!(select("$['store']['bicycle'].color"), json!(["red"]));
+    assert_eq!(select("$.store.book[0].author"), json!(["Nigel Rees"]));
+    assert_eq!(select("$.store.book[-1].title"), json!(["The Lord of the Rings"]));
+    assert_eq!(select("$.store.book[9]"), json!([]));
+    assert_eq!(select("$.missing.deeper"), json!([]));
+    assert_eq!(select("$"), json!([store()]));
+}
+
+#[test]
+fn test_evaluate_wildcards_unions_and_slices() {
+    assert_eq!(
+        select("$.store.book[*].author"),
+        json!(["Nigel Rees", "Evelyn Waugh", "Herman Melville", "J. R. R. Tolkien"])
+    );
+    assert_eq!(select("$.store.bicycle.*"), json!(["red", 19.95]));
+    assert_eq!(select("$.store.book[0,2].price"), json!([8.95, 8.99]));
+    assert_eq!(select("$.store.bicycle['price','color']"), json!([19.95, "red"]));
+    assert_eq!(select("$.store.book[1:3].price"), json!([12.99, 8.99]));
+    assert_eq!(select("$.store.book[-2:].price"), json!([8.99, 22.99]));
+    assert_eq!(select("$.store.book[::2].price"), json!([8.95, 8.99]));
+    assert_eq!(select("$.store.book[::-1].price"), json!([22.99, 8.99, 12.99, 8.95]));
+    assert_eq!(select("$.store.book[1:1]"), json!([]));
+}
+
+#[test]
+fn test_evaluate_descendants() {
+    assert_eq!(
+        select("$..author"),
+        json!(["Nigel Rees", "Evelyn Waugh", "Herman Melville", "J. R. R. Tolkien"])
+    );
+    assert_eq!(
+        select("$.store..price"),
+        json!([19.95, 8.95, 12.99, 8.99, 22.99])
+    );
+    assert_eq!(select("$..book[2].title"), json!(["Moby Dick"]));
+}
+
+#[test]
+fn test_evaluate_filters() {
+    assert_eq!(
+        select("$.store.book[?(@.price < 10)].title"),
+        json!(["Sayings of the Century", "Moby Dick"])
+    );
+    assert_eq!(
+        select("$.store.book[?(@.isbn)].title"),
+        json!(["Moby Dick", "The Lord of the Rings"])
+    );
+    assert_eq!(
+        select("$.store.book[?(!@.isbn)].title"),
+        json!(["Sayings of the Century", "Sword of Honour"])
+    );
+    assert_eq!(
+        select("$.store.book[?(@.category == 'fiction' && @.price > $.expensive)].author"),
+        json!(["Evelyn Waugh", "J. R. R. Tolkien"])
+    );
+    assert_eq!(
+        select("$.store.book[?@.price <= 8.95 || @.author == \"Herman Melville\"].price"),
+        json!([8.95, 8.99])
+    );
+    assert_eq!(select("$.store.book[?(@.price == 'cheap')]"), json!([]));
+}
+
+#[test]
+fn test_parse_errors() {
+    for path in ["", "store.book", "$.", "$[", "$.book[?(@.a ==)]", "$['open", "$.a b"] {
+        match JsonPath::parse(path) {
+            Err(JsonPathError::Parse { .. }) => {}
+            other => panic!("{:?} should fail to parse, got {:?}", path, other),
+        }
+    }
+}
+
+#[test]
+fn test_translate_to_clove() {
+    let cases = [
+        ("$", "$"),
+        ("$.store.bicycle.color", "$[store][bicycle][color]"),
+        ("$['odd key'][0]", "$[\"odd key\"][0]"),
+        ("$.store.book[*].author", "$[store][book].map(@[author])"),
+        ("$.store.bicycle.*", "$[store][bicycle].values()"),
+        ("$.store.book[1:3]", "$[store][book].skip(1).take(2)"),
+        (
+            "$.store.book[?(@.price < 10)].title",
+            "$[store][book].filter(@[price] < 10).map(@[title])",
+        ),
+        (
+            "$.store.book[?(@.isbn && @.price > $.expensive)]",
+            "$[store][book].filter((@[isbn] != null) && (@[price] > $[expensive]))",
+        ),
+    ];
+    for (path, clove) in cases {
+        let parsed = JsonPath::parse(path).unwrap();
+        assert_eq!(parsed.to_clove().unwrap(), clove, "translating {}", path);
+    }
+}
+
+#[test]
+fn test_translation_agrees_with_evaluation() {
+    for path in [
+        "$.store.book[*].author",
+        "$.store.book[?(@.price < 10)].title",
+        "$.store.book[0:2].price",
+    ] {
+        let parsed = JsonPath::parse(path).unwrap();
+        let doc = json_to_clove(store());
+        let expected = Value::Array(parsed.evaluate(&doc));
+        let actual = Evaluator::new()
+            .eval_expression(&parsed.to_expr().unwrap(), doc)
+            .unwrap();
+        assert_eq!(actual, expected, "{}", path);
+    }
+}
+
+#[test]
+fn test_untranslatable_paths() {
+    for path in ["$..author", "$.book[0,1]", "$.book[::2]", "$.book[-2:]", "$.book[?(!@.isbn)]"] {
+        match JsonPath::parse(path).unwrap().to_expr() {
+            Err(JsonPathError::Untranslatable(_)) => {}
+            other => panic!("{} should be untranslatable, got {:?}", path, other),
+        }
+    }
+}
+
+#[test]
+fn test_check_with_jsonpath() {
+    let options = CheckOptions {
+        query: "$.store.book[?(@.isbn)].author".to_string(),
+        input: Some(store().to_string()),
+        jsonpath: true,
+        ..Default::default()
+    };
+    match execute_check(&options) {
+        Ok(CheckResult::Success { value, .. }) => {
+            assert_eq!(value, json_to_clove(json!(["Herman Melville", "J. R. R. Tolkien"])))
+        }
+        other => panic!("unexpected result: {:?}", other),
+    }
+
+    let invalid = CheckOptions {
+        query: "$.store[".to_string(),
+        syntax_only: true,
+        jsonpath: true,
+        ..Default::default()
+    };
+    assert!(execute_check(&invalid).is_err());
+}
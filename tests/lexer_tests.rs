@@ -1,7 +1,7 @@
 // tests/lexer_tests.rs
 
-use clove_lang::ast::Token;
-use clove_lang::lexer::Lexer;
+use clove_lang::ast::{Token, TokenClass};
+use clove_lang::lexer::{tokenize_with_spans, Lexer};
 
 // ============================================================================
 // Single Character Tokens
@@ -52,6 +52,7 @@ fn test_two_char_tokens() {
     let test_cases = vec![
         ("==", Token::EqEq),
         ("!=", Token::NotEq),
+        ("~=", Token::TildeEq),
         ("<=", Token::LtEq),
         (">=", Token::GtEq),
         (":=", Token::ColonEqual),
@@ -141,6 +142,23 @@ fn test_keywords_vs_identifiers() {
     }
 }
 
+#[test]
+fn test_keywords_after_dot_are_names() {
+    let mut lexer = Lexer::new("$[a].or(null) or $.and");
+    for _ in 0..4 {
+        lexer.next_token().unwrap();
+    }
+    assert_eq!(lexer.next_token().unwrap(), Token::Dot);
+    assert_eq!(lexer.next_token().unwrap(), Token::Identifier("or".to_string()));
+    assert_eq!(lexer.next_token().unwrap(), Token::LParen);
+    assert_eq!(lexer.next_token().unwrap(), Token::Null);
+    assert_eq!(lexer.next_token().unwrap(), Token::RParen);
+    assert_eq!(lexer.next_token().unwrap(), Token::Or);
+    assert_eq!(lexer.next_token().unwrap(), Token::Dollar);
+    assert_eq!(lexer.next_token().unwrap(), Token::Dot);
+    assert_eq!(lexer.next_token().unwrap(), Token::Identifier("and".to_string()));
+}
+
 // ============================================================================
 // Identifiers
 // ============================================================================
@@ -199,7 +217,7 @@ fn test_ints() {
         ("15", 15),
         ("315", 315),
         ("123456", 123456),
-        ("01", 01),
+        ("01", 1),
     ];
 
     for (input, expected) in test_cases {
@@ -246,6 +264,65 @@ fn test_floats() {
     }
 }
 
+#[test]
+fn test_scientific_notation() {
+    let test_cases = vec![
+        ("1e6", 1e6),
+        ("1E6", 1e6),
+        ("1.5e-3", 1.5e-3),
+        ("2.5E+2", 250.0),
+        ("0e0", 0.0),
+        ("1e400", f64::INFINITY),
+    ];
+
+    for (input, expected) in test_cases {
+        let mut lexer = Lexer::new(input);
+        assert_eq!(lexer.next_token().unwrap(), Token::Float(expected), "Failed for input: {}", input);
+        assert_eq!(lexer.next_token().unwrap(), Token::Eof);
+    }
+}
+
+#[test]
+fn test_hex_and_octal_integers() {
+    let test_cases = vec![
+        ("0xFF", 255),
+        ("0Xff", 255),
+        ("0x0", 0),
+        ("0x7fffffffffffffff", i64::MAX),
+        ("0o755", 0o755),
+        ("0O17", 15),
+    ];
+
+    for (input, expected) in test_cases {
+        let mut lexer = Lexer::new(input);
+        assert_eq!(lexer.next_token().unwrap(), Token::Integer(expected), "Failed for input: {}", input);
+        assert_eq!(lexer.next_token().unwrap(), Token::Eof);
+    }
+
+    let mut lexer = Lexer::new("0xFF)");
+    assert_eq!(lexer.next_token().unwrap(), Token::Integer(255));
+    assert_eq!(lexer.next_token().unwrap(), Token::RParen);
+}
+
+#[test]
+fn test_malformed_numbers() {
+    let test_cases = vec![
+        ("1e", "Malformed number '1e': expected digits in the exponent"),
+        ("2.5e+)", "Malformed number '2.5e+': expected digits in the exponent"),
+        ("1ex", "Malformed number '1ex': expected digits in the exponent"),
+        ("0x", "Malformed number '0x': expected hex digits"),
+        ("0xFG1", "Malformed number '0xFG1': invalid hex digit 'G'"),
+        ("0o78", "Malformed number '0o78': invalid octal digit '8'"),
+        ("0x8000000000000000", "Integer 0x8000000000000000 is out of range"),
+    ];
+
+    for (input, expected) in test_cases {
+        let err = Lexer::new(input).next_token().unwrap_err();
+        assert_eq!(err.message(), expected, "Failed for input: {}", input);
+        assert_eq!(err.position().column, 1);
+    }
+}
+
 #[test]
 fn test_negative_numbers() {
     let test_cases = vec![("-1", 1), ("-42", 42), ("-315", 315)];
@@ -464,6 +541,16 @@ fn test_transform_syntax() {
     assert_eq!(lexer.next_token().unwrap(), Token::Eof);
 }
 
+#[test]
+fn test_live_scope_operator() {
+    let mut lexer = Lexer::new("@items ::= $[items]");
+
+    assert_eq!(lexer.next_token().unwrap(), Token::At);
+    assert!(matches!(lexer.next_token().unwrap(), Token::Identifier(s) if s == "items"));
+    assert_eq!(lexer.next_token().unwrap(), Token::ColonColonEqual);
+    assert_eq!(lexer.next_token().unwrap(), Token::Dollar);
+}
+
 #[test]
 fn test_scope_reference() {
     let mut lexer = Lexer::new("@items := $[items]");
@@ -672,3 +759,49 @@ fn test_lowercase_env_var() {
     let mut lexer = Lexer::new("$api_key");
     assert!(matches!(lexer.next_token().unwrap(), Token::EnvVar(s) if s == "api_key"));
 }
+
+// ============================================================================
+// Highlighting
+// ============================================================================
+
+#[test]
+fn test_tokenize_with_spans() {
+    let source = "$ | ?(@[name] == \"ü\" and\n  $[n] >= 2.5)";
+    let spans: Vec<_> = tokenize_with_spans(source)
+        .into_iter()
+        .map(|(token, span)| {
+            let text: String = source.chars().skip(span.start.offset).take(span.end.offset - span.start.offset).collect();
+            (token.class(), text, span.start.line, span.start.column)
+        })
+        .collect();
+    assert_eq!(spans[8], (TokenClass::Operator, "==".to_string(), 1, 15));
+    assert_eq!(spans[9], (TokenClass::Literal, "\"ü\"".to_string(), 1, 18));
+    assert_eq!(spans[10], (TokenClass::Keyword, "and".to_string(), 1, 22));
+    assert_eq!(spans[11], (TokenClass::Reference, "$".to_string(), 2, 3));
+    assert_eq!(spans[16], (TokenClass::Literal, "2.5".to_string(), 2, 11));
+    assert_eq!(spans.last().unwrap().0, TokenClass::Punctuation);
+    assert_eq!(spans.len(), 18);
+}
+
+#[test]
+fn test_tokenize_with_spans_never_fails() {
+    let tokens = tokenize_with_spans("$[a] # 1 = \"open");
+    let classes: Vec<_> = tokens.iter().map(|(token, _)| token.class()).collect();
+    assert_eq!(
+        classes,
+        [
+            TokenClass::Reference,
+            TokenClass::Punctuation,
+            TokenClass::Reference,
+            TokenClass::Punctuation,
+            TokenClass::Error,
+            TokenClass::Literal,
+            TokenClass::Error,
+            TokenClass::Error,
+        ]
+    );
+    assert_eq!(tokens[4].0, Token::Error("Unexpected character '#'".to_string()));
+    assert_eq!((tokens[7].1.start.column, tokens[7].1.end.column), (12, 17));
+
+    assert!(tokenize_with_spans("").is_empty());
+}
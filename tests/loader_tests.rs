@@ -0,0 +1,149 @@
+use clove_lang::loader::{LoadError, QueryLoader};
+use std::path::{Path, PathBuf};
+
+/// A fresh directory per test, populated with `files`
+fn scratch_dir(test: &str, files: &[(&str, &str)]) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("clove-loader-tests-{}-{}", std::process::id(), test));
+    let _ = std::fs::remove_dir_all(&dir);
+    for (name, contents) in files {
+        let path = dir.join(name);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, contents).unwrap();
+    }
+    dir
+}
+
+fn udf_names(query: &clove_lang::Query) -> Vec<&str> {
+    query.udfs.iter().map(|u| u.name.as_str()).collect()
+}
+
+#[test]
+fn test_include_merges_udfs_in_order() {
+    let dir = scratch_dir(
+        "merge",
+        &[
+            ("lib/pricing.clv", "&net:1 := 0.8 * @1\n&gross:1 := 1.2 * @1\n"),
+            ("lib/validators.clv", "&positive:1 := ?(@1 > 0)"),
+            (
+                "report.clv",
+                "%include \"lib/pricing.clv\"\n%include \"lib/validators.clv\"\n&local:0 := $[x]\n$ | ?($[total] > 0)",
+            ),
+        ],
+    );
+
+    let query = QueryLoader::new().load_file(dir.join("report.clv")).unwrap();
+    assert_eq!(udf_names(&query), ["net", "gross", "positive", "local"]);
+    assert_eq!(query.statements.len(), 1);
+}
+
+#[test]
+fn test_nested_includes_resolve_relative_to_including_file() {
+    let dir = scratch_dir(
+        "nested",
+        &[
+            ("lib/all.clv", "%include \"inner/base.clv\"\n&twice:1 := 2 * @1"),
+            ("lib/inner/base.clv", "&one:0 := 1"),
+        ],
+    );
+
+    let query = QueryLoader::new()
+        .load_str("%include \"lib/all.clv\"\n$", Some(&dir))
+        .unwrap();
+    assert_eq!(udf_names(&query), ["one", "twice"]);
+}
+
+#[test]
+fn test_search_paths() {
+    let dir = scratch_dir("search", &[("shared/money.clv", "&cents:1 := 100 * @1")]);
+
+    let missing = QueryLoader::new().load_str("%include \"money.clv\"\n$", Some(&dir));
+    assert!(matches!(missing, Err(LoadError::NotFound { .. })), "{:?}", missing);
+
+    let query = QueryLoader::new()
+        .with_search_path(dir.join("shared"))
+        .load_str("%include \"money.clv\"\n$", Some(&dir))
+        .unwrap();
+    assert_eq!(udf_names(&query), ["cents"]);
+}
+
+#[test]
+fn test_shared_library_is_loaded_once() {
+    let dir = scratch_dir(
+        "diamond",
+        &[
+            ("common.clv", "&base:0 := 1"),
+            ("a.clv", "%include \"common.clv\"\n&a:0 := 2"),
+            ("b.clv", "%include \"common.clv\"\n&b:0 := 3"),
+        ],
+    );
+
+    let query = QueryLoader::new()
+        .load_str("%include \"a.clv\"\n%include \"b.clv\"\n$", Some(&dir))
+        .unwrap();
+    assert_eq!(udf_names(&query), ["base", "a", "b"]);
+}
+
+#[test]
+fn test_include_cycle_is_reported() {
+    let dir = scratch_dir(
+        "cycle",
+        &[
+            ("a.clv", "%include \"b.clv\"\n&a:0 := 1"),
+            ("b.clv", "%include \"a.clv\"\n&b:0 := 2"),
+        ],
+    );
+
+    match QueryLoader::new().load_str("%include \"a.clv\"\n$", Some(&dir)) {
+        Err(LoadError::Cycle(chain)) => {
+            let names: Vec<_> = chain.iter().map(|p| p.file_name().unwrap().to_str().unwrap()).collect();
+            assert_eq!(names, ["a.clv", "b.clv", "a.clv"]);
+        }
+        other => panic!("expected a cycle, got {:?}", other),
+    }
+
+    let dir = scratch_dir("self", &[("main.clv", "%include \"main.clv\"\n$")]);
+    let result = QueryLoader::new().load_file(dir.join("main.clv"));
+    assert!(matches!(result, Err(LoadError::Cycle(_))), "{:?}", result);
+}
+
+#[test]
+fn test_duplicate_udf_is_an_error() {
+    let dir = scratch_dir("duplicate", &[("lib.clv", "&f:0 := 1")]);
+    let result = QueryLoader::new().load_str("%include \"lib.clv\"\n&f:0 := 2\n$", Some(&dir));
+    match result {
+        Err(LoadError::DuplicateUdf { name, .. }) => assert_eq!(name, "f"),
+        other => panic!("expected duplicate UDF error, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_errors_name_the_failing_file() {
+    let dir = scratch_dir("badlib", &[("bad.clv", "&f:0 := 1\n$ | ?(true)")]);
+    let err = QueryLoader::new()
+        .load_str("%include \"bad.clv\"\n$", Some(&dir))
+        .unwrap_err();
+    assert!(matches!(err, LoadError::Parse { path: Some(_), .. }));
+    assert!(err.to_string().contains("bad.clv"), "{}", err);
+}
+
+#[test]
+fn test_invalid_directives() {
+    for source in ["%import \"x.clv\"\n$", "%include x.clv\n$", "%include\n$", "%includex \"a\"\n$"] {
+        let result = QueryLoader::new().load_str(source, Some(Path::new(".")));
+        assert!(
+            matches!(result, Err(LoadError::InvalidDirective { line: 1, .. })),
+            "{:?}: {:?}",
+            source,
+            result
+        );
+    }
+}
+
+#[test]
+fn test_parse_errors_keep_line_numbers() {
+    let dir = scratch_dir("lines", &[("lib.clv", "&f:0 := 1")]);
+    let err = QueryLoader::new()
+        .load_str("%include \"lib.clv\"\n\n$ | ?(", Some(&dir))
+        .unwrap_err();
+    assert!(err.to_string().contains("line 3"), "{}", err);
+}
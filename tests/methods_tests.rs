@@ -0,0 +1,66 @@
+use clove_lang::methods::RegistryError;
+use clove_lang::{EvalError, Evaluator, Lexer, MethodRegistry, Parser, Value, clove_value};
+
+fn registry() -> MethodRegistry {
+    let mut methods = MethodRegistry::new();
+    methods
+        .register("text", "shout", |value, args| match (value, args) {
+            (Value::String(s), []) => Ok(Value::String(format!("{}!", s.to_uppercase()))),
+            (Value::String(s), [Value::Integer(n)]) => {
+                Ok(Value::String(format!("{}{}", s.to_uppercase(), "!".repeat(*n as usize))))
+            }
+            _ => Err(EvalError::TypeError(".text_shout() requires a string".to_string())),
+        })
+        .unwrap();
+    methods
+}
+
+fn eval(methods: MethodRegistry, query: &str, doc: Value) -> Result<Value, String> {
+    let query = Parser::new(Lexer::new(query)).unwrap().parse_query().unwrap();
+    Evaluator::new()
+        .with_methods(methods)
+        .eval_query(&query, doc)
+        .map_err(|e| e.to_string())
+}
+
+#[test]
+fn test_host_methods() {
+    let doc = clove_value!({"names": ["ann", "bob"], "n": 2});
+
+    let result = eval(registry(), "$ | !($[names].map(@.text_shout($[n])))", doc.clone());
+    assert_eq!(result.unwrap(), Value::array(["ANN!!", "BOB!!"]));
+    let result = eval(registry(), "$ | !($[names][0].text_shout())", doc.clone());
+    assert_eq!(result.unwrap(), Value::String("ANN!".to_string()));
+
+    // Errors come back as they are, and other evaluators don't see the method
+    let err = eval(registry(), "$ | !($[n].text_shout())", doc.clone()).unwrap_err();
+    assert!(err.contains("requires a string"), "{}", err);
+    let err = eval(registry(), "$ | !($[names][0].text_shuot())", doc.clone()).unwrap_err();
+    assert!(err.contains("did you mean .text_shout()?"), "{}", err);
+    let err = eval(MethodRegistry::new(), "$ | !($[names][0].text_shout())", doc).unwrap_err();
+    assert!(err.contains("Unknown method"), "{}", err);
+}
+
+#[test]
+fn test_namespaces() {
+    let mut methods = registry();
+    let noop = |value: &Value, _: &[Value]| Ok(value.clone());
+
+    assert_eq!(methods.register("text", "shout", noop), Err(RegistryError::Duplicate("text_shout".to_string())));
+    // Built-in names and their prefixes are reserved
+    for namespace in ["map", "sort", "base64", "find"] {
+        assert_eq!(
+            methods.register(namespace, "extra", noop),
+            Err(RegistryError::ReservedNamespace(namespace.to_string()))
+        );
+    }
+    for (namespace, name) in [("", "x"), ("Geo", "x"), ("geo_x", "y"), ("1geo", "x"), ("geo", ""), ("geo", "a-b")] {
+        assert!(matches!(methods.register(namespace, name, noop), Err(RegistryError::InvalidName(_))));
+    }
+
+    methods.register("geo2", "id_of", noop).unwrap();
+    let mut names: Vec<_> = methods.names().collect();
+    names.sort();
+    assert_eq!(names, ["geo2_id_of", "text_shout"]);
+    assert!(methods.get("geo2_id_of").is_some());
+}
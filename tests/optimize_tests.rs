@@ -0,0 +1,142 @@
+use clove_lang::optimize::optimize_expr;
+use clove_lang::{clove_value, Evaluator, Expr, Lexer, Parser, Query, Statement, Value};
+
+fn parse_query(query: &str) -> Query {
+    Parser::new(Lexer::new(query)).unwrap().parse_query().unwrap()
+}
+
+fn parse_expr(expr: &str) -> Expr {
+    Parser::new(Lexer::new(expr)).unwrap().parse().unwrap()
+}
+
+fn run(query: &Query, doc: &Value) -> Result<Value, String> {
+    Evaluator::new()
+        .eval_query(query, doc.clone())
+        .map_err(|e| e.to_string())
+}
+
+/// Run `query` as written and optimized, assert both agree, and return the
+/// optimized query
+fn assert_same_result(query: &str, doc: &Value) -> Query {
+    let original = parse_query(query);
+    let optimized = original.clone().optimize();
+    assert_eq!(run(&original, doc), run(&optimized, doc), "{query}");
+    optimized
+}
+
+#[test]
+fn test_constant_folding() {
+    assert_eq!(optimize_expr(parse_expr("60 * 60 * 24")), Expr::Integer(86400));
+    assert_eq!(
+        optimize_expr(parse_expr("[3, 1, 2].sort()")),
+        Expr::Array(vec![Expr::Integer(1), Expr::Integer(2), Expr::Integer(3)])
+    );
+    assert_eq!(
+        optimize_expr(parse_expr("\"a\" + \"b\" == \"ab\"")),
+        Expr::Boolean(true)
+    );
+
+    // Only the constant operand of a non-constant expression is folded
+    assert_eq!(
+        optimize_expr(parse_expr("$[n] * (2 + 3)")),
+        parse_expr("$[n] * 5")
+    );
+}
+
+#[test]
+fn test_failing_constants_are_not_folded() {
+    for expr in ["1 / 0", "9223372036854775807 + 1", "[1, 2].nope()", "[1, 2, 3].shuffle()"] {
+        let folded = optimize_expr(parse_expr(expr));
+        assert_eq!(folded, parse_expr(expr), "{expr}");
+    }
+
+    // The error still surfaces when the query runs
+    let query = parse_query("$ | !(1 / 0)").optimize();
+    assert!(run(&query, &Value::Null).is_err());
+}
+
+#[test]
+fn test_always_true_filters_are_removed() {
+    let doc = clove_value!({"price": 80});
+
+    let query = assert_same_result("$ | ?(1 < 2) | ?(true and $[price] > 50)", &doc);
+    assert_eq!(query.statements, vec![parse_query("$ | ?($[price] > 50)").statements[0].clone()]);
+
+    // Filters that reject everything stay
+    let query = assert_same_result("$ | ?(false)", &doc);
+    assert_eq!(query.statements.len(), 1);
+}
+
+#[test]
+fn test_consecutive_maps_are_fused() {
+    let doc = clove_value!({"p": [{"price": 2}, {"price": 5}]});
+
+    let query = assert_same_result("$ | ~($[p] := @[price]) | ~($[p] := @ * 2)", &doc);
+    assert_eq!(query.statements.len(), 1);
+
+    // A second map that reads the document sees the first map's output
+    let query = assert_same_result("$ | ~($[p] := @[price]) | ~($[p] := $[p].sum())", &doc);
+    assert_eq!(query.statements.len(), 2);
+
+    // Below an index, `@` is the element rather than the previous value
+    let query = assert_same_result(
+        "$ | ~($[p][0][price] := @[price] * 2) | ~($[p][0][price] := @[price] + 1)",
+        &doc,
+    );
+    assert_eq!(query.statements.len(), 2);
+}
+
+#[test]
+fn test_invariant_subexpressions_are_hoisted() {
+    let doc = clove_value!({"items": [{"price": 1}, {"price": 3}]});
+
+    let query = assert_same_result(
+        "$ | !($[items].map(@[price] / $[items].sum(@[price])))",
+        &doc,
+    );
+    assert!(matches!(query.statements[0], Statement::HoistedScope { .. }));
+    assert_eq!(
+        run(&query, &doc).unwrap(),
+        Value::Array(vec![Value::Float(0.25), Value::Float(0.75)])
+    );
+}
+
+#[test]
+fn test_hoisted_errors_are_raised_only_when_used() {
+    let query = "$ | !($[items].map(@ * $[k].abs()))";
+
+    let empty = clove_value!({"items": [], "k": "x"});
+    assert_eq!(
+        run(&parse_query(query).optimize(), &empty).unwrap(),
+        Value::Array(vec![])
+    );
+
+    let filled = clove_value!({"items": [1], "k": "x"});
+    assert_same_result(query, &filled);
+    assert!(run(&parse_query(query).optimize(), &filled).is_err());
+}
+
+#[test]
+fn test_optimized_queries_agree() {
+    let doc = clove_value!({
+        "name": "shop",
+        "rate": 2,
+        "items": [
+            {"name": "a", "price": 10, "tags": ["x"]},
+            {"name": "b", "price": 25, "tags": []},
+            {"name": "c", "price": 40, "tags": ["x", "y"]}
+        ]
+    });
+
+    for query in [
+        "$ | ?(true) | !($[items].filter(@[price] > 10 + 5).map(@[name]))",
+        "$ | ~($[items] := @[price]) | ~($[items] := @ * $[rate])",
+        "$ | ~($[items] := @[price]) | ~($[items] := @ + 1) | !($[items].sum())",
+        "$ | !($[items].map(@[price] * $[rate] + [1, 2, 3].sum()))",
+        "$ | !($[items].filter(@[tags].length() > 0 and true).length())",
+        "$ | -($[name]) | !($[items].map(@[price] > $[items].map(@[price]).max() / 2))",
+        "$ | ?($[missing] ?? true) | !($[items].any(@[price] == 5 * 5))",
+    ] {
+        assert_same_result(query, &doc);
+    }
+}
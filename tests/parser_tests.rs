@@ -2,7 +2,8 @@
 
 use clove_lang::lexer::Lexer;
 use clove_lang::parser::Parser;
-use clove_lang::ast::{BinOp, Expr, Statement};
+use clove_lang::ast::{BinOp, EnvVarType, Expr, MatchArm, Pattern, Statement};
+use clove_lang::{DuplicateKeys, QueryBuilder};
 
 // ============================================================================
 // Simple tests
@@ -381,6 +382,22 @@ fn test_parse_env_var() {
     assert!(matches!(expr, Expr::EnvVar(name) if name == "HOME"));
 }
 
+#[test]
+fn test_parse_typed_env_var() {
+    let lexer = Lexer::new("$PORT:int");
+    let mut parser = Parser::new(lexer).unwrap();
+    let expr = parser.parse().unwrap();
+    assert!(matches!(expr, Expr::TypedEnvVar { name, ty: EnvVarType::Int } if name == "PORT"));
+}
+
+#[test]
+fn test_parse_typed_env_var_unknown_type() {
+    let lexer = Lexer::new("$PORT:integer");
+    let mut parser = Parser::new(lexer).unwrap();
+    let err = parser.parse().unwrap_err().to_string();
+    assert!(err.contains("Unknown type 'integer'"), "got: {}", err);
+}
+
 #[test]
 fn test_parse_lambda_param() {
     let lexer = Lexer::new("@");
@@ -1073,13 +1090,29 @@ fn test_parse_unary_minus() {
     let mut parser = Parser::new(lexer).unwrap();
     let expr = parser.parse().unwrap();
     
-    // Represented as 0 - 5
+    // Integer literals are folded
+    assert_eq!(expr, Expr::Integer(-5));
+}
+
+#[test]
+fn test_parse_negative_float() {
+    let lexer = Lexer::new("-1.0");
+    let mut parser = Parser::new(lexer).unwrap();
+    let expr = parser.parse().unwrap();
+
+    // Float literals are folded too, so they stay floats
+    assert_eq!(expr, Expr::Float(-1.0));
+}
+
+#[test]
+fn test_parse_negative_index() {
+    let lexer = Lexer::new("$[items][-1]");
+    let mut parser = Parser::new(lexer).unwrap();
+    let expr = parser.parse().unwrap();
+
     match expr {
-        Expr::BinaryOp { op: BinOp::Subtract, left, right } => {
-            assert!(matches!(*left, Expr::Integer (n) if n == 0));
-            assert!(matches!(*right, Expr::Integer (n) if n == 5));
-        }
-        _ => panic!("Expected Subtract for unary minus"),
+        Expr::Access { key, .. } => assert_eq!(*key, Expr::Integer(-1)),
+        _ => panic!("Expected Access"),
     }
 }
 
@@ -1315,6 +1348,44 @@ fn test_parse_object_missing_value() {
     assert!(result.is_err());
 }
 
+#[test]
+fn test_parse_object_duplicate_keys() {
+    let source = r##"{"a": 1, "b": 2, a: 3}"##;
+    let parse = |duplicates| Parser::new(Lexer::new(source)).unwrap().with_duplicate_keys(duplicates).parse();
+
+    let pairs = |expr| match expr {
+        Expr::Object(pairs) => pairs,
+        other => panic!("Expected object, got {:?}", other),
+    };
+    // The last value wins at evaluation, as before
+    assert_eq!(pairs(parse(DuplicateKeys::Last).unwrap()).len(), 3);
+    assert_eq!(
+        pairs(parse(DuplicateKeys::First).unwrap()),
+        [("a".to_string(), Expr::Integer(1)), ("b".to_string(), Expr::Integer(2))]
+    );
+    let err = parse(DuplicateKeys::Error).unwrap_err();
+    assert_eq!(err.message(), "Duplicate key \"a\" in object literal");
+    assert_eq!(err.position().column, 18);
+}
+
+#[test]
+fn test_parse_wildcard_only_in_delete_targets() {
+    let query = Parser::new(Lexer::new("$ | -($[users][*][password])")).unwrap().parse_query().unwrap();
+    let Statement::Delete(Expr::Access { object, .. }) = &query.statements[0] else {
+        panic!("Expected delete, got {:?}", query.statements[0]);
+    };
+    assert!(matches!(object.as_ref(), Expr::Access { key, .. } if **key == Expr::Wildcard));
+
+    let target = Parser::new(Lexer::new("$[*][a]")).unwrap().parse_target().unwrap();
+    assert!(matches!(target, Expr::Access { .. }));
+    let err = Parser::new(Lexer::new("$[items][*]")).unwrap().parse().unwrap_err();
+    assert!(err.message().starts_with("[*] can only be used in a delete target"), "{}", err);
+    for source in ["$ | ~($[items][*][a] := 1)", "$ | -($[items][$[*]])"] {
+        let err = Parser::new(Lexer::new(source)).unwrap().parse_query().unwrap_err();
+        assert!(err.message().starts_with("[*] can only be used in a delete target"), "{}: {}", source, err);
+    }
+}
+
 #[test]
 fn test_parse_object_unclosed() {
     let lexer = Lexer::new(r##"{"name": "value""##);
@@ -1441,6 +1512,7 @@ fn test_parse_all_comparison_operators() {
     let operators = vec![
         ("==", BinOp::Equal),
         ("!=", BinOp::NotEqual),
+        ("~=", BinOp::ApproxEqual),
         ("<", BinOp::LessThan),
         (">", BinOp::GreaterThan),
         ("<=", BinOp::LessEqual),
@@ -1574,6 +1646,21 @@ fn test_parse_scope_definition() {
     }
 }
 
+#[test]
+fn test_parse_live_scope_definition() {
+    let lexer = Lexer::new("$ | @items ::= $[items]");
+    let mut parser = Parser::new(lexer).unwrap();
+    let query = parser.parse_query().unwrap();
+
+    match &query.statements[0] {
+        Statement::LiveScopeDefinition { name, path } => {
+            assert_eq!(name, "items");
+            assert!(matches!(path, Expr::Access { .. }));
+        }
+        other => panic!("Expected LiveScopeDefinition statement, got {:?}", other),
+    }
+}
+
 #[test]
 fn test_parse_scope_usage() {
     let lexer = Lexer::new("$ | @items := $[items] | @items[0]");
@@ -1640,6 +1727,34 @@ fn test_parse_output_object() {
     assert!(matches!(query.output.unwrap(), Expr::Object(_)));
 }
 
+#[test]
+fn test_parse_named_outputs() {
+    let lexer = Lexer::new("$ | !(report: $[items]) !(summary: $[totals]) | !(count: 1)");
+    let mut parser = Parser::new(lexer).unwrap();
+    let query = parser.parse_query().unwrap();
+
+    match query.output.unwrap() {
+        Expr::Object(pairs) => {
+            let names: Vec<&str> = pairs.iter().map(|(name, _)| name.as_str()).collect();
+            assert_eq!(names, ["report", "summary", "count"]);
+        }
+        other => panic!("Expected object of outputs, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_parse_multiple_outputs_errors() {
+    for (query, message) in [
+        ("$ | !($[a]) | !($[b])", "must name each one"),
+        ("$ | !($[a]) !(b: $[b])", "Cannot mix"),
+        ("$ | !(a: 1) !(a: 2)", "Duplicate output name 'a'"),
+        ("$ | !(a: 1) | ?($[x])", "outputs must come last"),
+    ] {
+        let err = Parser::new(Lexer::new(query)).unwrap().parse_query().unwrap_err();
+        assert!(err.to_string().contains(message), "{}: {}", query, err);
+    }
+}
+
 #[test]
 fn test_parse_no_output() {
     let lexer = Lexer::new("$ | ?($[x] > 5)");
@@ -1707,6 +1822,264 @@ fn test_parse_multiple_udfs() {
     assert_eq!(query.udfs[1].name, "cheap");
 }
 
+#[test]
+fn test_parse_udf_body_starting_with_arg_ref() {
+    let lexer = Lexer::new("&apply_all:2 := @1.map(@2)\n$");
+    let mut parser = Parser::new(lexer).unwrap();
+    let query = parser.parse_query().unwrap();
+
+    match &query.udfs[0].body {
+        Statement::Access(Expr::MethodCall { object, method, args }) => {
+            assert!(matches!(**object, Expr::ArgRef(1)));
+            assert_eq!(method, "map");
+            assert!(matches!(args[..], [Expr::ArgRef(2)]));
+        }
+        other => panic!("Expected method call body, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_parse_udf_named_params() {
+    let lexer = Lexer::new("&tax(price, rate = 0.2, ...) := @price * @rate + @rest.length()\n$ | !(@price)");
+    let mut parser = Parser::new(lexer).unwrap();
+    let query = parser.parse_query().unwrap();
+
+    let udf = &query.udfs[0];
+    assert_eq!((udf.arity, udf.variadic), (2, true));
+    assert!(matches!(udf.defaults[..], [Expr::Float(rate)] if rate == 0.2));
+    match &udf.body {
+        Statement::Access(Expr::BinaryOp { left, right, .. }) => {
+            assert!(matches!(
+                &**left,
+                Expr::BinaryOp { left, right, .. }
+                    if matches!(**left, Expr::ArgRef(1)) && matches!(**right, Expr::ArgRef(2))
+            ));
+            assert!(matches!(&**right, Expr::MethodCall { object, .. } if matches!(**object, Expr::ArgRef(3))));
+        }
+        other => panic!("Expected arithmetic body, got {:?}", other),
+    }
+
+    // Names are parameters only inside their own definition
+    assert!(matches!(query.output, Some(Expr::ScopeRef(ref name)) if name == "price"));
+
+    for bad in [
+        "&f(a = 1, b) := @a\n$",
+        "&f(a, a) := @a\n$",
+        "&f(rest, ...) := @rest\n$",
+        "&f(..., a) := @a\n$",
+        "&f(a == 1) := @a\n$",
+    ] {
+        assert!(Parser::new(Lexer::new(bad)).unwrap().parse_query().is_err(), "{}", bad);
+    }
+}
+
+#[test]
+fn test_parse_named_lambda() {
+    let lexer = Lexer::new("$[xs].map(x -> $[ys].filter(y -> @y > @x)).map(@x)");
+    let expr = Parser::new(lexer).unwrap().parse().unwrap();
+
+    let Expr::MethodCall { object, args: outer_args, .. } = expr else {
+        panic!("Expected method call");
+    };
+    // Outside its lambda, @x is a scope again
+    assert!(matches!(&outer_args[..], [Expr::ScopeRef(name)] if name == "x"));
+    let Expr::MethodCall { args, .. } = *object else {
+        panic!("Expected method call");
+    };
+    match &args[..] {
+        [Expr::Lambda { param, body }] => {
+            assert_eq!(param, "x");
+            let Expr::MethodCall { args, .. } = &**body else {
+                panic!("Expected method call body, got {:?}", body);
+            };
+            assert!(matches!(
+                &args[..],
+                [Expr::Lambda { param, body }] if param == "y" && matches!(
+                    &**body,
+                    Expr::BinaryOp { left, right, .. }
+                        if **left == Expr::LambdaVar("y".to_string())
+                            && **right == Expr::LambdaVar("x".to_string())
+                )
+            ));
+        }
+        other => panic!("Expected named lambda, got {:?}", other),
+    }
+
+    assert!(Parser::new(Lexer::new("$[xs].map(x - > 1)")).unwrap().parse().is_err());
+}
+
+#[test]
+fn test_parse_outer_lambda_param() {
+    let lexer = Lexer::new("$[a].filter($[b].any(@ == @@@[x]))");
+    let expr = Parser::new(lexer).unwrap().parse().unwrap();
+
+    let Expr::MethodCall { args, .. } = expr else {
+        panic!("Expected method call");
+    };
+    let [Expr::MethodCall { args, .. }] = &args[..] else {
+        panic!("Expected nested method call, got {:?}", args);
+    };
+    assert!(matches!(
+        &args[..],
+        [Expr::BinaryOp { left, right, .. }]
+            if **left == Expr::LambdaParam
+                && matches!(&**right, Expr::Access { object, .. } if **object == Expr::OuterLambdaParam(2))
+    ));
+}
+
+#[test]
+fn test_parse_match() {
+    let lexer = Lexer::new(r#"match $[event] { {"type": "click", "x": x} if x > 0 => x, [-1, ...] => 0, _ => null }"#);
+    let expr = Parser::new(lexer).unwrap().parse().unwrap();
+
+    let Expr::Match { subject, arms } = expr else {
+        panic!("Expected match, got {:?}", expr);
+    };
+    assert_eq!(*subject, Expr::root().get("event"));
+    let [first, second, last] = &arms[..] else {
+        panic!("Expected three arms, got {:?}", arms);
+    };
+    assert_eq!(
+        first.pattern,
+        Pattern::Object(vec![
+            ("type".to_string(), Pattern::Literal(Expr::String("click".to_string()))),
+            ("x".to_string(), Pattern::Bind("x".to_string())),
+        ])
+    );
+    // Bound names are usable bare or as @x in the guard and body
+    assert!(matches!(
+        &first.guard,
+        Some(Expr::BinaryOp { left, .. }) if **left == Expr::LambdaVar("x".to_string())
+    ));
+    assert_eq!(first.body, Expr::LambdaVar("x".to_string()));
+    assert!(matches!(
+        &second.pattern,
+        Pattern::Array { items, rest: true } if matches!(&items[..], [Pattern::Literal(_)])
+    ));
+    assert_eq!(*last, MatchArm { pattern: Pattern::Wildcard, guard: None, body: Expr::Null });
+
+    // Names don't leak out of their arm
+    assert!(Parser::new(Lexer::new("match 1 { x => 1, _ => x }")).unwrap().parse().is_err());
+    assert!(Parser::new(Lexer::new("match 1 { [a, a] => a }")).unwrap().parse().is_err());
+    assert!(Parser::new(Lexer::new("match 1 { }")).unwrap().parse().is_err());
+}
+
+#[test]
+fn test_parse_try() {
+    let lexer = Lexer::new("$[rows].map(try @[price] * 2 catch 0)");
+    let expr = Parser::new(lexer).unwrap().parse().unwrap();
+
+    let Expr::MethodCall { args, .. } = expr else {
+        panic!("Expected method call");
+    };
+    assert_eq!(
+        args,
+        vec![Expr::try_or(Expr::item().get("price") * Expr::Integer(2), Expr::Integer(0))]
+    );
+    assert!(Parser::new(Lexer::new("try $[a]")).unwrap().parse().is_err());
+}
+
+#[test]
+fn test_parse_try_body_ending_in_item() {
+    // `catch` is reserved, so it ends a body that ends in `@` or `@@`
+    let parse = |source| Parser::new(Lexer::new(source)).unwrap().parse().unwrap();
+    assert_eq!(parse("try @ catch 1"), Expr::try_or(Expr::LambdaParam, Expr::Integer(1)));
+    assert_eq!(
+        parse("try 1 > @ catch 0"),
+        Expr::try_or(Expr::Integer(1).binary(BinOp::GreaterThan, Expr::LambdaParam), Expr::Integer(0))
+    );
+    assert_eq!(parse("try @@ catch @"), Expr::try_or(Expr::OuterLambdaParam(1), Expr::LambdaParam));
+    assert_eq!(
+        parse("$[rows].map(try @ catch 1)"),
+        Expr::root().get("rows").method("map", [Expr::try_or(Expr::LambdaParam, Expr::Integer(1))])
+    );
+
+    assert!(Parser::new(Lexer::new("$ | @catch := 1")).unwrap().parse_query().is_err());
+}
+
+#[test]
+fn test_parse_when() {
+    let lexer = Lexer::new("$ | when ($[legacy][?]) { ~($[id] := $[legacy][id]) | -($[legacy]) } | !($[id])");
+    let query = Parser::new(lexer).unwrap().parse_query().unwrap();
+    let legacy = Expr::root().get("legacy");
+    let expected = QueryBuilder::new()
+        .when(
+            legacy.clone().exists(),
+            QueryBuilder::new()
+                .transform(Expr::root().get("id"), legacy.clone().get("id"))
+                .delete(legacy),
+        )
+        .output(Expr::root().get("id"))
+        .build();
+    assert_eq!(query, expected);
+
+    // The body needs at least one stage, and `when` alone is not a statement
+    assert!(Parser::new(Lexer::new("$ | when (true) { }")).unwrap().parse_query().is_err());
+    assert!(Parser::new(Lexer::new("$ | when (true) ~($[a] := 1)")).unwrap().parse_query().is_err());
+}
+
+#[test]
+fn test_parse_foreach() {
+    let source = "$ | foreach $[items] as @item { ?(@item[qty] > 0) | ~(@item[total] := @item[qty] * $[rate]) }";
+    let query = Parser::new(Lexer::new(source)).unwrap().parse_query().unwrap();
+    let item = Expr::var("item");
+    let expected = QueryBuilder::new()
+        .foreach(
+            Expr::root().get("items"),
+            "item",
+            QueryBuilder::new()
+                .filter(item.clone().get("qty").gt(0))
+                .transform(item.clone().get("total"), item.get("qty") * Expr::root().get("rate")),
+        )
+        .build();
+    assert_eq!(query, expected);
+
+    // The element is only named inside the body
+    let query = Parser::new(Lexer::new("$ | foreach $[a] as @x { @x[b] } | @x[b]")).unwrap().parse_query().unwrap();
+    assert!(matches!(&query.statements[0], Statement::ForEach { statements, .. }
+        if statements == &[Statement::Access(Expr::var("x").get("b"))]));
+    assert_eq!(query.statements[1], Statement::Access(Expr::ScopeRef("x".to_string()).get("b")));
+    assert!(Parser::new(Lexer::new("$ | foreach $[a] @x { @x }")).unwrap().parse_query().is_err());
+}
+
+#[test]
+fn test_parse_host_call() {
+    let lexer = Lexer::new("ext::lookup_user($[id], 2)[name]");
+    let expr = Parser::new(lexer).unwrap().parse().unwrap();
+    assert_eq!(
+        expr,
+        Expr::host_call("lookup_user", [Expr::root().get("id"), Expr::Integer(2)]).get("name")
+    );
+
+    // Not an output name
+    let query = Parser::new(Lexer::new("$ | !(ext::now())")).unwrap().parse_query().unwrap();
+    assert_eq!(query.output, Some(Expr::host_call("now", [])));
+    let query = Parser::new(Lexer::new("$ | !(try $[a] catch 0)")).unwrap().parse_query().unwrap();
+    assert!(matches!(query.output, Some(Expr::Try { .. })));
+}
+
+#[test]
+fn test_parse_udf_call() {
+    let lexer = Lexer::new("&discount[$[price], 0.1][total]");
+    let mut parser = Parser::new(lexer).unwrap();
+    let expr = parser.parse().unwrap();
+
+    match expr {
+        Expr::Access { object, .. } => match *object {
+            Expr::UDFCall { name, args } => {
+                assert_eq!(name, "discount");
+                assert_eq!(args.len(), 2);
+            }
+            other => panic!("Expected UDF call, got {:?}", other),
+        },
+        other => panic!("Expected access, got {:?}", other),
+    }
+
+    let lexer = Lexer::new("&now[]");
+    let expr = Parser::new(lexer).unwrap().parse().unwrap();
+    assert!(matches!(expr, Expr::UDFCall { args, .. } if args.is_empty()));
+}
+
 // ============================================================================
 // Error Cases (Query)
 // ============================================================================
@@ -1751,3 +2124,119 @@ fn test_parse_scope_no_name() {
     assert!(err.contains("identifier"), "Expected identifier error, got: {}", err);
 }
 
+
+// ============================================================================
+// Error Positions and Rendering
+// ============================================================================
+
+#[test]
+fn test_parse_error_position() {
+    let lexer = Lexer::new("$[items");
+    let mut parser = Parser::new(lexer).unwrap();
+    let err = parser.parse().unwrap_err();
+
+    assert_eq!(err.position().line, 1);
+    assert_eq!(err.position().column, 8);
+    assert_eq!(err.source_line(), "$[items");
+    assert_eq!(err.hint(), Some("missing closing ']'"));
+}
+
+#[test]
+fn test_parse_error_display_snippet() {
+    let lexer = Lexer::new("$ | ?($[a] == 1");
+    let mut parser = Parser::new(lexer).unwrap();
+    let err = parser.parse_query().unwrap_err().to_string();
+
+    assert_eq!(
+        err,
+        "Expected RParen, got Eof at line 1, column 16\n  |\n1 | $ | ?($[a] == 1\n  |                ^ missing closing ')'"
+    );
+}
+
+#[test]
+fn test_parse_error_multiline_query() {
+    let lexer = Lexer::new("$\n| ?($[a] > 1)\n| !($[b] $[c])");
+    let mut parser = Parser::new(lexer).unwrap();
+    let err = parser.parse_query().unwrap_err();
+
+    assert_eq!(err.position().line, 3);
+    assert_eq!(err.position().column, 10);
+    assert_eq!(err.source_line(), "| !($[b] $[c])");
+}
+
+#[test]
+fn test_parse_error_from_lexer_has_snippet() {
+    let lexer = Lexer::new("$[a] = 1");
+    let mut parser = Parser::new(lexer).unwrap();
+    let err = parser.parse().unwrap_err();
+
+    assert_eq!(err.position().column, 6);
+    assert!(err.to_string().contains("1 | $[a] = 1\n  |      ^"), "got: {}", err);
+}
+
+// ============================================================================
+// Error Recovery
+// ============================================================================
+
+fn parse_recovering(query: &str) -> (clove_lang::Query, Vec<(usize, usize, String)>) {
+    let mut parser = Parser::new(Lexer::new(query)).unwrap();
+    let (query, errors) = parser.parse_query_recovering();
+    let errors = errors
+        .iter()
+        .map(|e| (e.position().line, e.position().column, e.message()))
+        .collect();
+    (query, errors)
+}
+
+#[test]
+fn test_recovering_parse_of_valid_query_matches_parse_query() {
+    let source = "&double:1 := @1 * 2\n$ | ?($[a] > 1) | ~($[b] := &double[@]) | -($[c]) | !($[b])";
+    let (query, errors) = parse_recovering(source);
+    assert!(errors.is_empty());
+    assert_eq!(query, Parser::new(Lexer::new(source)).unwrap().parse_query().unwrap());
+}
+
+#[test]
+fn test_recovering_parse_reports_every_statement() {
+    let (query, errors) = parse_recovering("$ | ?($[a] ==) | @n := $[n] | ~($[b] 1) | -($[c]) | !($[d] $[e])");
+    assert_eq!(errors, vec![
+        (1, 14, "Expected expression, got RParen".to_string()),
+        (1, 38, "Expected ColonEqual, got Integer(1)".to_string()),
+        (1, 60, "Expected RParen, got Dollar".to_string()),
+    ]);
+    assert_eq!(query.statements.len(), 2);
+    assert!(matches!(query.statements[0], Statement::ScopeDefinition { .. }));
+    assert!(matches!(query.statements[1], Statement::Delete(_)));
+    assert_eq!(query.output, None);
+}
+
+#[test]
+fn test_recovering_parse_skips_lexer_errors() {
+    let (query, errors) = parse_recovering("$ | ?($[a] = 1) | ~($[b] := 2 # 3) | = | !($[c])");
+    assert_eq!(errors.len(), 3, "{:?}", errors);
+    assert_eq!((errors[0].0, errors[0].1), (1, 12));
+    assert_eq!((errors[1].0, errors[1].1), (1, 31));
+    assert_eq!((errors[2].0, errors[2].1), (1, 38));
+    assert!(query.statements.is_empty());
+    assert!(query.output.is_some());
+
+    let (_, errors) = parse_recovering("$ | !(\"unterminated)");
+    assert_eq!(errors.len(), 1, "{:?}", errors);
+}
+
+#[test]
+fn test_recovering_parse_of_udfs_and_pipeline_start() {
+    let (query, errors) = parse_recovering("&bad:x := 1\n&good:0 := 2\n$ | !(&good[])");
+    assert_eq!(errors.len(), 1, "{:?}", errors);
+    assert_eq!(errors[0].0, 1);
+    assert_eq!(query.udfs.len(), 1);
+    assert_eq!(query.udfs[0].name, "good");
+    assert!(query.output.is_some());
+
+    let (query, errors) = parse_recovering("?($[a]) | !($[b]) extra");
+    assert_eq!(errors.iter().map(|e| e.2.as_str()).collect::<Vec<_>>(), vec![
+        "Expected Dollar, got Question",
+        "Expected Eof, got Identifier(\"extra\")",
+    ]);
+    assert!(query.output.is_some());
+}
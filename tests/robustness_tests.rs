@@ -0,0 +1,271 @@
+//! Arbitrary input must never panic the lexer, parser or evaluator, and
+//! arbitrary values must always serialize to valid JSON.
+//!
+//! These run a fixed number of pseudo-random cases so failures reproduce;
+//! the cargo-fuzz targets in `fuzz/` explore the same properties open-ended.
+//! Set `CLOVE_ROBUSTNESS_CASES` to run more cases locally.
+
+use clove_lang::output::{JsonWriter, to_json};
+use clove_lang::{Evaluator, Lexer, Parser, Value};
+use std::collections::HashMap;
+use std::panic;
+
+/// Small deterministic xorshift generator, so the cases are reproducible
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+
+    fn pick<'a>(&mut self, items: &[&'a str]) -> &'a str {
+        items[self.below(items.len())]
+    }
+}
+
+fn cases() -> usize {
+    std::env::var("CLOVE_ROBUSTNESS_CASES")
+        .ok()
+        .and_then(|n| n.parse().ok())
+        .unwrap_or(3000)
+}
+
+const FRAGMENTS: &[&str] = &[
+    "$", "@", "@1", "@x", "&f", "[", "]", "(", ")", "{", "}", ",", ":", ":=", "|", "?", "~", "-",
+    "!", "+", "*", "/", "%", "==", "!=", "<", "<=", ">", ">=", "&&", "||", "??", ".", "0", "1",
+    "-1", "2.5", "9223372036854775807", "99999999999999999999", "1e400", "\"s\"", "\"\\u{0}\"",
+    "\"unterminated", "true", "false", "null", "a", "items", "$HOME", "$PORT:int", " ", "\n",
+    ".map(", ".flat_map(", ".filter(", ".sum()", ".take(", ".skip(", ".chunk(", ".window(", ".sort_by(",
+    ".percentile(", ".split(", ".matches(", ".keys()", ".has(", ".flatten()", ".first()",
+    ".base64_decode()", ".hex_encode()",
+    "[0]", "[-1]", "[a]", "[\"k\"]", "?(", "~(", "-(", "!(", "\\", "#", "é", "\u{0}",
+];
+
+fn random_query(rng: &mut Rng) -> String {
+    let len = 1 + rng.below(14);
+    let mut query = String::new();
+    if rng.below(2) == 0 {
+        query.push_str("$ | ");
+    }
+    for _ in 0..len {
+        query.push_str(rng.pick(FRAGMENTS));
+    }
+    query
+}
+
+fn random_value(rng: &mut Rng, depth: usize) -> Value {
+    match rng.below(if depth > 3 { 5 } else { 7 }) {
+        0 => Value::Null,
+        1 => Value::Boolean(rng.below(2) == 0),
+        2 => Value::Integer(match rng.below(4) {
+            0 => i64::MAX,
+            1 => i64::MIN,
+            2 => -1,
+            _ => rng.below(10) as i64,
+        }),
+        3 => Value::Float([0.0, -0.5, 1e308, f64::MIN_POSITIVE][rng.below(4)]),
+        4 => Value::String(rng.pick(&["", "a", "items", "é", "1"]).to_string()),
+        5 => Value::Array((0..rng.below(4)).map(|_| random_value(rng, depth + 1)).collect()),
+        _ => Value::Object(
+            (0..rng.below(4))
+                .map(|_| {
+                    let key = rng.pick(&["a", "items", "0", "k"]).to_string();
+                    (key, random_value(rng, depth + 1))
+                })
+                .collect::<HashMap<_, _>>(),
+        ),
+    }
+}
+
+/// Lex, parse (both entry points) and evaluate; report the query on panic
+fn exercise(query: &str, doc: &Value) {
+    let result = panic::catch_unwind(|| {
+        let mut lexer = Lexer::new(query);
+        while let Ok(token) = lexer.next_token() {
+            if token == clove_lang::Token::Eof {
+                break;
+            }
+        }
+
+        if let Ok(mut parser) = Parser::new(Lexer::new(query))
+            && let Ok(expr) = parser.parse()
+        {
+            let _ = Evaluator::new().eval_expression(&expr, doc.clone());
+        }
+        if let Ok(mut parser) = Parser::new(Lexer::new(query))
+            && let Ok(q) = parser.parse_query()
+        {
+            let _ = Evaluator::new().eval_query(&q, doc.clone());
+        }
+    });
+    if result.is_err() {
+        panic!("panicked on query {:?} with input {:?}", query, doc);
+    }
+}
+
+#[test]
+fn test_random_queries_do_not_panic() {
+    let mut rng = Rng(0x9e37_79b9_7f4a_7c15);
+    for _ in 0..cases() {
+        let query = random_query(&mut rng);
+        let doc = random_value(&mut rng, 0);
+        exercise(&query, &doc);
+    }
+}
+
+fn parse_expr(query: &str) -> Result<clove_lang::Expr, clove_lang::ParseError> {
+    Parser::new(Lexer::new(query))?.parse()
+}
+
+/// Run `test` with a stack big enough for maximum nesting in a debug build
+/// (the default test thread stack is only 2 MiB)
+fn with_large_stack(test: impl FnOnce() + Send + 'static) {
+    std::thread::Builder::new()
+        .stack_size(64 << 20)
+        .spawn(test)
+        .unwrap()
+        .join()
+        .unwrap();
+}
+
+#[test]
+fn test_deep_nesting_is_an_error_not_a_stack_overflow() {
+    with_large_stack(deep_nesting_is_an_error);
+}
+
+fn deep_nesting_is_an_error() {
+    let n = 100_000;
+    let cases = [
+        format!("{}1{}", "(".repeat(n), ")".repeat(n)),
+        format!("{}1", "-".repeat(n)),
+        format!("1{}", " + 1".repeat(n)),
+        format!("$[a]{}", " ?? $[a]".repeat(n)),
+        format!("${}", "[a]".repeat(n)),
+        format!("${}", ".first()".repeat(n)),
+        "[".repeat(n),
+        "{\"a\": ".repeat(n),
+        format!("$[{}a{}", "$[".repeat(n), "]".repeat(n)),
+    ];
+    for query in &cases {
+        let err = parse_expr(query).unwrap_err();
+        assert!(err.to_string().contains("nested too deeply"), "{}", err);
+    }
+}
+
+#[test]
+fn test_nesting_up_to_the_limit_parses_and_evaluates() {
+    with_large_stack(nesting_up_to_the_limit_parses_and_evaluates);
+}
+
+fn nesting_up_to_the_limit_parses_and_evaluates() {
+    let depth = clove_lang::parser::MAX_NESTING_DEPTH - 1;
+    let cases = [
+        (format!("{}1{}", "(".repeat(depth), ")".repeat(depth)), Value::Integer(1)),
+        (format!("1{}", " + 1".repeat(depth - 1)), Value::Integer(depth as i64)),
+        (format!("{}1{}", "[".repeat(depth), "]".repeat(depth)), {
+            (0..depth).fold(Value::Integer(1), |v, _| Value::Array(vec![v]))
+        }),
+    ];
+    for (query, expected) in cases {
+        let expr = parse_expr(&query).unwrap_or_else(|e| panic!("{}", e));
+        assert_eq!(Evaluator::new().eval_expression(&expr, Value::Null).unwrap(), expected);
+    }
+
+    // Long flat expressions are fine; only nesting is limited
+    let sum = vec!["1"; 1000].join(", ");
+    let expr = parse_expr(&format!("[{}].sum()", sum)).unwrap();
+    assert_eq!(Evaluator::new().eval_expression(&expr, Value::Null).unwrap(), Value::Integer(1000));
+}
+
+#[test]
+fn test_max_depth_is_configurable() {
+    let query = format!("{}1{}", "(".repeat(10), ")".repeat(10));
+    assert!(Parser::new(Lexer::new(&query)).unwrap().with_max_depth(10).parse().is_err());
+    assert!(Parser::new(Lexer::new(&query)).unwrap().with_max_depth(11).parse().is_ok());
+}
+
+/// Characters that need care when written as JSON
+const EXOTIC_CHARS: &[char] = &[
+    '"', '\\', '/', '\0', '\u{8}', '\u{c}', '\n', '\r', '\t', '\u{1f}', '\u{7f}', '\u{85}',
+    '\u{9f}', '\u{ad}', '\u{2028}', '\u{2029}', '\u{feff}', '\u{fffd}', '\u{ffff}', 'é', '中',
+    '😀', '\u{10ffff}', 'a', ' ',
+];
+
+fn random_text(rng: &mut Rng) -> String {
+    (0..rng.below(6))
+        .map(|_| match rng.below(4) {
+            0 => char::from_u32(rng.below(0x11_0000) as u32).unwrap_or('?'),
+            _ => EXOTIC_CHARS[rng.below(EXOTIC_CHARS.len())],
+        })
+        .collect()
+}
+
+/// Like `random_value`, but with any float, integer and string
+fn random_json_value(rng: &mut Rng, depth: usize) -> Value {
+    match rng.below(if depth > 4 { 5 } else { 7 }) {
+        0 => Value::Null,
+        1 => Value::Boolean(rng.below(2) == 0),
+        2 => Value::Integer(rng.next() as i64),
+        3 => Value::Float(match rng.below(3) {
+            0 => f64::from_bits(rng.next()),
+            1 => [f64::NAN, f64::INFINITY, -0.0, 5e-324, f64::MAX, 1e21, 1e-7][rng.below(7)],
+            _ => rng.next() as i64 as f64 / 1e6,
+        }),
+        4 => Value::String(random_text(rng)),
+        5 => Value::Array((0..rng.below(4)).map(|_| random_json_value(rng, depth + 1)).collect()),
+        _ => Value::Object(
+            (0..rng.below(4))
+                .map(|_| (random_text(rng), random_json_value(rng, depth + 1)))
+                .collect::<HashMap<_, _>>(),
+        ),
+    }
+}
+
+/// Whether `parsed` is what `value` should read back as: non-finite floats
+/// are written as null, and canonical output may write whole floats as integers
+fn reads_back_as(value: &Value, parsed: &serde_json::Value) -> bool {
+    match (value, parsed) {
+        (Value::Float(f), serde_json::Value::Null) => !f.is_finite(),
+        (Value::Float(f), serde_json::Value::Number(n)) => n.as_f64() == Some(*f),
+        (Value::Integer(i), serde_json::Value::Number(n)) => n.as_i64() == Some(*i),
+        (Value::Array(items), serde_json::Value::Array(parsed)) => {
+            items.len() == parsed.len() && items.iter().zip(parsed).all(|(v, p)| reads_back_as(v, p))
+        }
+        (Value::Object(map), serde_json::Value::Object(parsed)) => {
+            map.len() == parsed.len()
+                && map.iter().all(|(k, v)| parsed.get(k).is_some_and(|p| reads_back_as(v, p)))
+        }
+        (Value::Null, serde_json::Value::Null) => true,
+        (Value::Boolean(b), serde_json::Value::Bool(p)) => b == p,
+        (Value::String(s), serde_json::Value::String(p)) => s == p,
+        _ => false,
+    }
+}
+
+#[test]
+fn test_json_output_round_trips() {
+    let writers = [
+        JsonWriter::new().pretty(true),
+        JsonWriter::new().indent(3).sort_keys(false),
+        JsonWriter::new().ascii_only(true),
+        JsonWriter::new().canonical(true),
+    ];
+    let mut rng = Rng(0x2545_f491_4f6c_dd1d);
+    for _ in 0..cases() {
+        let value = random_json_value(&mut rng, 0);
+        let outputs = std::iter::once(to_json(&value)).chain(writers.iter().map(|w| w.write(&value)));
+        for json in outputs {
+            match serde_json::from_str::<serde_json::Value>(&json) {
+                Ok(parsed) => assert!(reads_back_as(&value, &parsed), "{:?} read back as {}", value, parsed),
+                Err(e) => panic!("invalid JSON for {:?}: {}\n{}", value, e, json),
+            }
+        }
+    }
+}
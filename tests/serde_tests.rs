@@ -0,0 +1,72 @@
+#![cfg(feature = "serde")]
+
+use clove_lang::ast::{AST_FORMAT_VERSION, AstDecodeError};
+use clove_lang::{Evaluator, Lexer, Parser, Query, json_to_clove};
+use serde_json::json;
+
+fn parse(input: &str) -> Query {
+    Parser::new(Lexer::new(input)).unwrap().parse_query().unwrap()
+}
+
+#[test]
+fn test_round_trip_preserves_ast() {
+    let queries = [
+        "$",
+        "$ | ?($[age] >= 18) | ~($[total] := $[items].map(@[price] * @[qty]).sum())",
+        "$ | -($[tmp]) | ~($[status] := $[status] ?? \"done\") | !($[status])",
+        r#"$ | ~($[people] := $[people].sort_by([{"key": @[age], "desc": true}, @[last]]))"#,
+        "&expensive:1 := ?(@1[price] > 100)\n$ | ?($[items][0][price] > 50)",
+        "$ | @first := $[items][-1] | !(@first)",
+        "$ | ~($[port] := $PORT:int)",
+    ];
+    for source in queries {
+        let query = parse(source);
+        let json = query.to_json().unwrap();
+        assert_eq!(Query::from_json(&json).unwrap(), query, "{}", source);
+    }
+}
+
+#[test]
+fn test_deserialized_query_evaluates() {
+    let query = parse("$ | ?($[n] > 1) | ~($[m] := $[n] * 10)");
+    let loaded = Query::from_json(&query.to_json().unwrap()).unwrap();
+    let doc = json_to_clove(json!({"n": 2}));
+
+    let expected = Evaluator::new().eval_query(&query, doc.clone()).unwrap();
+    let actual = Evaluator::new().eval_query(&loaded, doc).unwrap();
+    assert_eq!(actual, expected);
+}
+
+#[test]
+fn test_envelope_carries_version() {
+    let json: serde_json::Value = serde_json::from_str(&parse("$").to_json().unwrap()).unwrap();
+    assert_eq!(json["version"], json!(AST_FORMAT_VERSION));
+    assert!(json.get("query").is_some());
+}
+
+#[test]
+fn test_rejects_other_versions() {
+    let mut json: serde_json::Value = serde_json::from_str(&parse("$").to_json().unwrap()).unwrap();
+    json["version"] = json!(0);
+
+    match Query::from_json(&json.to_string()) {
+        Err(AstDecodeError::UnsupportedVersion { found, expected }) => {
+            assert_eq!(found, 0);
+            assert_eq!(expected, AST_FORMAT_VERSION);
+        }
+        other => panic!("expected version error, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_rejects_untagged_or_malformed_input() {
+    assert!(matches!(
+        Query::from_json(r#"{"query": {}}"#),
+        Err(AstDecodeError::MissingVersion)
+    ));
+    assert!(matches!(
+        Query::from_json(r#"{"version": 2, "query": {"nonsense": true}}"#),
+        Err(AstDecodeError::Json(_))
+    ));
+    assert!(matches!(Query::from_json("not json"), Err(AstDecodeError::Json(_))));
+}
@@ -4,7 +4,6 @@
 // Each test references the relevant section of the spec.
 
 use clove_lang::{evaluator::Evaluator, lexer::Lexer, parser::Parser, value::Value};
-use std::collections::HashMap;
 
 fn eval_expr(expr_str: &str, doc: Value) -> Result<Value, String> {
     let lexer = Lexer::new(expr_str);
@@ -27,11 +26,7 @@ fn eval_query(query_str: &str, doc: Value) -> Result<Value, String> {
 }
 
 fn json_object(pairs: Vec<(&str, Value)>) -> Value {
-    let mut map = HashMap::new();
-    for (k, v) in pairs {
-        map.insert(k.to_string(), v);
-    }
-    Value::Object(map)
+    Value::object(pairs)
 }
 
 // ============================================================================
@@ -77,7 +72,6 @@ fn spec_root_access_array_index() {
 // ============================================================================
 
 #[test]
-#[ignore] // SPEC MISMATCH: negative indices not implemented
 fn spec_negative_array_index_last() {
     // $[items][-1] - Last element
     let arr = Value::Array(vec![Value::Integer(10), Value::Integer(20), Value::Integer(30)]);
@@ -87,7 +81,6 @@ fn spec_negative_array_index_last() {
 }
 
 #[test]
-#[ignore] // SPEC MISMATCH: negative indices not implemented
 fn spec_negative_array_index_second_to_last() {
     // $[items][-2] - Second-to-last element
     let arr = Value::Array(vec![Value::Integer(10), Value::Integer(20), Value::Integer(30)]);
@@ -636,7 +629,7 @@ fn spec_method_type_number_int() {
 
 #[test]
 fn spec_method_type_number_float() {
-    let doc = json_object(vec![("x", Value::Float(3.14))]);
+    let doc = json_object(vec![("x", Value::Float(2.5))]);
     let result = eval_expr("$[x].type()", doc).unwrap();
     assert_eq!(result, Value::String("number".into()));
 }
@@ -0,0 +1,170 @@
+#![cfg(feature = "tracing")]
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use clove_lang::{Evaluator, Lexer, Parser, Value, clove_value};
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Metadata, Subscriber};
+
+/// A span opened, a field recorded on one later, or an event
+#[derive(Debug, Clone, PartialEq)]
+enum Seen {
+    Span { id: u64, name: String },
+    Recorded { id: u64, field: String, value: String },
+    Event { fields: Vec<(String, String)> },
+}
+
+/// Remembers everything it is sent
+#[derive(Default)]
+struct Recorder {
+    seen: Arc<Mutex<Vec<Seen>>>,
+    next_id: AtomicU64,
+}
+
+#[derive(Default)]
+struct Fields(Vec<(String, String)>);
+
+impl Visit for Fields {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.push((field.name().to_string(), value.to_string()));
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        self.0.push((field.name().to_string(), format!("{:?}", value)));
+    }
+}
+
+impl Subscriber for Recorder {
+    fn enabled(&self, _: &Metadata<'_>) -> bool {
+        true
+    }
+
+    fn new_span(&self, span: &Attributes<'_>) -> Id {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed) + 1;
+        let name = span.metadata().name().to_string();
+        self.seen.lock().unwrap().push(Seen::Span { id, name });
+        Id::from_u64(id)
+    }
+
+    fn record(&self, span: &Id, values: &Record<'_>) {
+        let mut fields = Fields::default();
+        values.record(&mut fields);
+        let mut seen = self.seen.lock().unwrap();
+        for (field, value) in fields.0 {
+            seen.push(Seen::Recorded { id: span.into_u64(), field, value });
+        }
+    }
+
+    fn record_follows_from(&self, _: &Id, _: &Id) {}
+
+    fn event(&self, event: &Event<'_>) {
+        let mut fields = Fields::default();
+        event.record(&mut fields);
+        self.seen.lock().unwrap().push(Seen::Event { fields: fields.0 });
+    }
+
+    fn enter(&self, _: &Id) {}
+
+    fn exit(&self, _: &Id) {}
+}
+
+fn record(run: impl FnOnce()) -> Vec<Seen> {
+    let recorder = Recorder::default();
+    let seen = Arc::clone(&recorder.seen);
+    tracing::subscriber::with_default(recorder, run);
+    seen.lock().unwrap().clone()
+}
+
+/// The value recorded as `field` on the span with `id`
+fn recorded(seen: &[Seen], id: u64, field: &str) -> Option<String> {
+    seen.iter().find_map(|s| match s {
+        Seen::Recorded { id: i, field: f, value } if *i == id && f == field => Some(value.clone()),
+        _ => None,
+    })
+}
+
+/// The fields of every event
+fn events(seen: &[Seen]) -> Vec<Vec<(String, String)>> {
+    seen.iter()
+        .filter_map(|s| match s {
+            Seen::Event { fields } => Some(fields.clone()),
+            _ => None,
+        })
+        .collect()
+}
+
+fn field<'a>(fields: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    fields.iter().find(|(n, _)| n == name).map(|(_, value)| value.as_str())
+}
+
+#[test]
+fn test_parse_and_eval_spans() {
+    let source = "$ | ?($[n] > 1) | ~($[n] := $[n] * 2) | !($[n])";
+    let seen = record(|| {
+        let query = Parser::new(Lexer::new(source)).unwrap().parse_query().unwrap();
+        let result = Evaluator::new().eval_query(&query, clove_value!({"n": 2})).unwrap();
+        assert_eq!(result, Value::Integer(4));
+    });
+
+    let spans: Vec<(u64, &str)> = seen
+        .iter()
+        .filter_map(|s| match s {
+            Seen::Span { id, name } => Some((*id, name.as_str())),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(spans.iter().map(|(_, name)| *name).collect::<Vec<_>>(), ["parse", "eval"]);
+    let (parse, eval) = (spans[0].0, spans[1].0);
+
+    // Both spans carry the same hash, so an eval can be traced to its query
+    let hash = recorded(&seen, parse, "query_hash").unwrap();
+    assert_eq!(hash.len(), 16);
+    assert!(hash.chars().all(|c| c.is_ascii_hexdigit()));
+    assert_eq!(recorded(&seen, eval, "query_hash"), Some(hash));
+    for span in [parse, eval] {
+        assert_eq!(recorded(&seen, span, "statements").as_deref(), Some("2"));
+        assert!(recorded(&seen, span, "elapsed_us").is_some());
+    }
+
+    let events = events(&seen);
+    assert_eq!(events.len(), 3);
+    assert_eq!(field(&events[0], "index"), Some("0"));
+    assert_eq!(field(&events[0], "stage"), Some("filter"));
+    assert_eq!(field(&events[0], "kept"), Some("true"));
+    assert_eq!(field(&events[1], "index"), Some("1"));
+    assert_eq!(field(&events[1], "stage"), Some("transform"));
+    assert_eq!(field(&events[2], "stage"), Some("output"));
+    assert!(events.iter().all(|fields| field(fields, "elapsed_us").is_some()));
+}
+
+#[test]
+fn test_filtered_out_and_failed_queries() {
+    let query = Parser::new(Lexer::new("$ | ?($[n] > 1) | !($[n])")).unwrap().parse_query().unwrap();
+
+    // A rejected document ends the pipeline, so the output never runs
+    let seen = record(|| {
+        Evaluator::new().eval_query(&query, clove_value!({"n": 0})).unwrap();
+    });
+    let rejected = events(&seen);
+    assert_eq!(rejected.len(), 1);
+    assert_eq!(field(&rejected[0], "kept"), Some("false"));
+
+    // A failing query still closes its span with a duration
+    let seen = record(|| {
+        assert!(Evaluator::new().eval_query(&query, clove_value!({"n": "x"})).is_err());
+    });
+    assert!(events(&seen).is_empty());
+    assert!(recorded(&seen, 1, "elapsed_us").is_some());
+
+    // The hash depends on the query, not on how it was written
+    let hash = |source: &str| {
+        let seen = record(|| {
+            Parser::new(Lexer::new(source)).unwrap().parse_query().unwrap();
+        });
+        recorded(&seen, 1, "query_hash").unwrap()
+    };
+    assert_eq!(hash("$ | ?($[n] > 1)"), hash("$|?( $[n]>1 )"));
+    assert_ne!(hash("$ | ?($[n] > 1)"), hash("$ | ?($[n] > 2)"));
+}
@@ -3,7 +3,7 @@ mod tests {
     use clove_lang::*;
     use clove_lang::ast::BinOp;
     use clove_lang::evaluator::EvalError;
-    use clove_lang::transform::{PathSegment, extract_path, TransformType, determine_transform_type, uses_lambda_param};
+    use clove_lang::transform::{PathSegment, extract_path, resolve_path, parse_path, parse_key_path, TransformType, determine_transform_type, uses_lambda_param};
 
     // Helper functions to build AST for testing
     fn field(name: &str) -> Expr {
@@ -256,6 +256,40 @@ mod tests {
         assert!(result.is_err());
     }
 
+    #[test]
+    fn test_resolve_computed_keys_and_first_last() {
+        // $[items][$[index]].last()[1 + 2]
+        let target = access(
+            Expr::MethodCall {
+                object: Box::new(access(access(Expr::Root, field("items")), access(Expr::Root, field("index")))),
+                method: "last".to_string(),
+                args: vec![],
+            },
+            Expr::BinaryOp { op: BinOp::Add, left: Box::new(number(1)), right: Box::new(number(2)) },
+        );
+        let mut keys = vec![Value::Integer(3), Value::String("rows".to_string())];
+        let path = resolve_path(&target, &mut |_| Ok(keys.pop().unwrap())).unwrap();
+        assert_eq!(path, vec![
+            PathSegment::Field("items".to_string()),
+            PathSegment::Field("rows".to_string()),
+            PathSegment::Index(-1),
+            PathSegment::Index(3),
+        ]);
+
+        let first = Expr::MethodCall { object: Box::new(access(Expr::Root, field("items"))), method: "first".to_string(), args: vec![] };
+        assert_eq!(resolve_path(&first, &mut |_| unreachable!()).unwrap(), vec![PathSegment::Field("items".to_string()), PathSegment::Index(0)]);
+        // Without resolution, only literal paths are targets
+        assert!(extract_path(&first).is_err());
+
+        let computed = access(Expr::Root, access(Expr::Root, field("key")));
+        match resolve_path(&computed, &mut |_| Ok(Value::Boolean(true))) {
+            Err(EvalError::TypeError(msg)) => assert!(msg.contains("string or integer, got boolean"), "{}", msg),
+            other => panic!("Expected TypeError, got {:?}", other),
+        }
+        let sorted = Expr::MethodCall { object: Box::new(access(Expr::Root, field("items"))), method: "sort".to_string(), args: vec![] };
+        assert!(resolve_path(&sorted, &mut |_| unreachable!()).is_err());
+    }
+
     #[test]
     fn test_reject_literal_number() {
         // 42 - not a path at all
@@ -370,8 +404,154 @@ mod tests {
         assert_ne!(PathSegment::Index(5), PathSegment::Index(6));
         assert_ne!(PathSegment::Field("5".into()), PathSegment::Index(5));
     }
-    
-    
+
+    // ========================================================================
+    // Value Path API Tests
+    // ========================================================================
+
+    #[test]
+    fn test_parse_path() {
+        assert_eq!(parse_path(""), vec![]);
+        assert_eq!(
+            parse_path("users.-1.name"),
+            vec![PathSegment::Field("users".into()), PathSegment::Index(-1), PathSegment::Field("name".into())]
+        );
+        assert_eq!(parse_path("a..1.5"), vec![
+            PathSegment::Field("a".into()),
+            PathSegment::Field("".into()),
+            PathSegment::Index(1),
+            PathSegment::Index(5),
+        ]);
+    }
+
+    #[test]
+    fn test_parse_key_path() {
+        use PathSegment::{Field, Index, Wildcard};
+        let field = |name: &str| Field(name.into());
+
+        assert_eq!(parse_key_path("").unwrap(), vec![]);
+        assert_eq!(parse_key_path("items.-1.price").unwrap(), parse_path("items.-1.price"));
+        assert_eq!(
+            parse_key_path("items[0][*].x").unwrap(),
+            vec![field("items"), Index(0), Wildcard, field("x")]
+        );
+        assert_eq!(parse_key_path("[2].*").unwrap(), vec![Index(2), Wildcard]);
+        assert_eq!(
+            parse_key_path(r#"a\.b.\1.\*["c]\"d"]['e\\']"#).unwrap(),
+            vec![field("a.b"), field("1"), field("*"), field("c]\"d"), field("e\\")]
+        );
+        assert_eq!(parse_key_path("é.ü[1]").unwrap(), vec![field("é"), field("ü"), Index(1)]);
+
+        let cases = [
+            ("a..b", "expected a key (at offset 2)"),
+            (".a", "expected a key (at offset 0)"),
+            ("a.", "expected a key (at offset 2)"),
+            ("a.[0]", "expected a key (at offset 2)"),
+            ("a[0", "expected ] (at offset 3)"),
+            ("a[x]", "expected an index, * or a quoted key (at offset 2)"),
+            ("a[]", "expected an index, * or a quoted key (at offset 2)"),
+            ("a['b]", "unterminated key (at offset 2)"),
+            ("a['b'c]", "expected ] (at offset 5)"),
+            ("a[0]b", "expected . or [ (at offset 4)"),
+            ("a\\", "nothing to escape (at offset 1)"),
+        ];
+        for (path, message) in cases {
+            let err = parse_key_path(path).unwrap_err().to_string();
+            assert!(err.ends_with(message), "{}: {}", path, err);
+        }
+    }
+
+    #[test]
+    fn test_value_get_path() {
+        let doc = clove_value!({"users": [{"name": "Ann"}, {"name": "Bob"}], "0": "zero"});
+        assert_eq!(doc.get_path(&parse_path("users.1.name")), Some(&Value::from("Bob")));
+        assert_eq!(doc.get_path(&parse_path("users.-2.name")), Some(&Value::from("Ann")));
+        assert_eq!(doc.get_path(&[]), Some(&doc));
+        for missing in ["users.2", "users.-3", "users.name", "nope", "0", "users.0.name.x"] {
+            assert_eq!(doc.get_path(&parse_path(missing)), None, "{}", missing);
+        }
+        assert_eq!(doc.get_path(&[PathSegment::Field("0".into())]), Some(&Value::from("zero")));
+    }
+
+    #[test]
+    fn test_value_set_and_upsert_path() {
+        let mut doc = clove_value!({"users": [{"name": "Ann"}]});
+        assert_eq!(doc.set_path(&parse_path("users.0.name"), "Amy".into()).unwrap(), Some("Ann".into()));
+        assert_eq!(doc.set_path(&parse_path("users.-1.age"), 30.into()).unwrap(), None);
+        assert_eq!(doc, clove_value!({"users": [{"name": "Amy", "age": 30}]}));
+
+        for (path, error) in [
+            ("users.1.name", "Array index out of bounds: 1 (length: 1)"),
+            ("users.1", "Array index out of bounds: 1 (length: 1)"),
+            ("meta.source", "Field 'meta' not found"),
+            ("users.name", "Cannot use field name 'name' on array"),
+            ("users.0.name.first", "Cannot use field name 'first' on string"),
+            ("users.0.0", "Cannot use array index 0 on object"),
+        ] {
+            let err = doc.set_path(&parse_path(path), Value::Null).unwrap_err();
+            assert!(err.to_string().contains(error), "{}: {}", path, err);
+        }
+
+        assert_eq!(doc.upsert_path(&parse_path("meta.source.name"), "app".into()).unwrap(), None);
+        assert!(doc.upsert_path(&parse_path("users.3.name"), Value::Null).is_err());
+        assert_eq!(
+            doc,
+            clove_value!({"users": [{"name": "Amy", "age": 30}], "meta": {"source": {"name": "app"}}})
+        );
+
+        let before = doc.clone();
+        assert_eq!(doc.set_path(&[], Value::Null).unwrap(), Some(before));
+        assert_eq!(doc, Value::Null);
+    }
+
+    #[test]
+    fn test_value_remove_path() {
+        let mut doc = clove_value!({"items": [1, 2, 3], "meta": {"a": 1}});
+        assert_eq!(doc.remove_path(&parse_path("items.-1")), Some(3.into()));
+        assert_eq!(doc.remove_path(&parse_path("items.0")), Some(1.into()));
+        assert_eq!(doc.remove_path(&parse_path("meta.a")), Some(1.into()));
+        for missing in ["items.5", "items.-2", "meta.a", "meta.0", "nope.deeper", ""] {
+            assert_eq!(doc.remove_path(&parse_path(missing)), None, "{}", missing);
+        }
+        assert_eq!(doc, clove_value!({"items": [2], "meta": {}}));
+    }
+
+    #[test]
+    fn test_value_remove_and_replace_matching() {
+        let users = clove_value!({"users": [
+            {"name": "ann", "password": "x", "keys": {"a": {"secret": 1}, "b": {"secret": 2, "id": 3}}},
+            {"name": "bob"},
+            "not an object"
+        ]});
+        let path = |fields: &[&str]| {
+            fields
+                .iter()
+                .map(|f| if *f == "*" { PathSegment::Wildcard } else { PathSegment::Field(f.to_string()) })
+                .collect::<Vec<_>>()
+        };
+
+        let mut doc = users.clone();
+        assert_eq!(doc.remove_matching(&path(&["users", "*", "password"])), 1);
+        assert_eq!(doc.remove_matching(&path(&["users", "*", "keys", "*", "secret"])), 2);
+        assert_eq!(doc.remove_matching(&path(&["users", "*", "nope", "*"])), 0);
+        assert_eq!(
+            doc,
+            clove_value!({"users": [{"name": "ann", "keys": {"a": {}, "b": {"id": 3}}}, {"name": "bob"}, "not an object"]})
+        );
+        assert_eq!(doc.remove_matching(&path(&["users", "*"])), 3);
+        assert_eq!(doc, clove_value!({"users": []}));
+        // Without wildcards it is remove_path
+        assert_eq!(doc.remove_matching(&path(&["users"])), 1);
+        assert_eq!(doc.remove_matching(&[]), 0);
+
+        let mut doc = users;
+        let redacted = Value::from("-");
+        assert_eq!(doc.replace_matching(&path(&["users", "*", "name"]), &redacted), 2);
+        assert_eq!(doc.replace_matching(&path(&["users", "*", "missing"]), &redacted), 0);
+        assert_eq!(doc.get_path(&parse_path("users.1")), Some(&clove_value!({"name": "-"})));
+    }
+
+
     // ========================================================================
     // Transform Type Detection Tests
     // ========================================================================
@@ -0,0 +1,78 @@
+use clove_lang::{clove_value, Value};
+use std::collections::HashMap;
+
+#[test]
+fn test_value_constructors() {
+    let mut map = HashMap::new();
+    map.insert("a".to_string(), Value::Integer(1));
+    map.insert("b".to_string(), Value::Null);
+    assert_eq!(Value::object([("a", Value::from(1)), ("b", Value::from(None::<i64>))]), Value::Object(map));
+
+    assert_eq!(
+        Value::array(["x", "y"]),
+        Value::Array(vec![Value::String("x".into()), Value::String("y".into())])
+    );
+    assert_eq!(Value::array(Vec::<Value>::new()), Value::Array(vec![]));
+}
+
+#[test]
+fn test_value_from_scalars() {
+    assert_eq!(Value::from(true), Value::Boolean(true));
+    assert_eq!(Value::from(7u32), Value::Integer(7));
+    assert_eq!(Value::from(i64::MIN), Value::Integer(i64::MIN));
+    assert_eq!(Value::from(1.5), Value::Float(1.5));
+    assert_eq!(Value::from(String::from("s")), Value::String("s".into()));
+    assert_eq!(Value::from(Some("s")), Value::String("s".into()));
+    assert_eq!(Value::from(vec![1, 2]), Value::array([1, 2]));
+}
+
+#[test]
+fn test_clove_value_macro_scalars() {
+    assert_eq!(clove_value!(null), Value::Null);
+    assert_eq!(clove_value!(true), Value::Boolean(true));
+    assert_eq!(clove_value!(-3), Value::Integer(-3));
+    assert_eq!(clove_value!(2.5), Value::Float(2.5));
+    assert_eq!(clove_value!("hi"), Value::String("hi".into()));
+}
+
+#[test]
+fn test_clove_value_macro_nested() {
+    let name = "Alice";
+    let doc = clove_value!({
+        "user": {"name": name, "age": 20 + 10, "tags": []},
+        "scores": [1, -2, 3.5, null, [true, false],],
+        "empty": {},
+    });
+
+    assert_eq!(
+        doc,
+        Value::object([
+            (
+                "user",
+                Value::object([
+                    ("name", Value::from("Alice")),
+                    ("age", Value::from(30)),
+                    ("tags", Value::Array(vec![])),
+                ]),
+            ),
+            (
+                "scores",
+                Value::Array(vec![
+                    Value::Integer(1),
+                    Value::Integer(-2),
+                    Value::Float(3.5),
+                    Value::Null,
+                    Value::array([true, false]),
+                ]),
+            ),
+            ("empty", Value::Object(HashMap::new())),
+        ])
+    );
+}
+
+#[test]
+fn test_clove_value_macro_computed_keys() {
+    let prefix = "field";
+    let doc = clove_value!({ (format!("{}_1", prefix)): 1, (prefix): 2 });
+    assert_eq!(doc, Value::object([("field_1", 1), ("field", 2)]));
+}